@@ -0,0 +1,8 @@
+#![no_main]
+
+use ddc_bright::capabilities::Capabilities;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Capabilities::parse(data, |_| Some(100));
+});
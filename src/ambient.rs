@@ -0,0 +1,519 @@
+#[cfg(any(feature = "tui", feature = "daemon"))]
+use std::collections::HashMap;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(any(feature = "tui", feature = "daemon"))]
+use ddc_bright::display::{DisplayManager, MyDisplay};
+use ddc_bright::{
+    display::{Control, WrappedDisplay},
+    units::Level,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(any(feature = "tui", feature = "daemon"))]
+use crate::automation::{Automation, AutomationConfig};
+use crate::config::Config;
+use crate::history::{Log, Origin};
+
+/// Readings averaged into one sample, and the pause between them
+const SAMPLES: u32 = 5;
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Lighting the calibration asks for, from dark to bright
+const STAGES: [&str; 3] = ["dark room", "normal", "bright daylight"];
+
+/// How long `monitor-sensor` gets to report the first reading
+const PROXY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Weight of a new reading in the smoothed light level of adaptive brightness
+#[cfg(any(feature = "tui", feature = "daemon"))]
+const SMOOTHING: f32 = 0.3;
+
+/// Ambient light sensor settings
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AmbientConfig {
+    /// `in_illuminance_input` or `in_illuminance_raw` file of an iio device, the first one
+    /// under /sys/bus/iio/devices if unset, then iio-sensor-proxy if there is none
+    pub sensor: Option<PathBuf>,
+    /// Brightness follows the light sensor while the TUI or the daemon runs
+    pub adaptive: bool,
+    /// Seconds between readings while adapting
+    pub interval: u64,
+    /// Percent the brightness the curve wants has to move away from the one set last before it
+    /// is set again
+    pub hysteresis: f32,
+    /// `[lux, percent]` points for displays `calibrate` has no curve of, joined on a log scale
+    pub curve: Vec<[f32; 2]>,
+    /// Displays adapted by model name or id, every display if empty
+    pub displays: Vec<String>,
+}
+
+impl Default for AmbientConfig {
+    fn default() -> Self {
+        Self {
+            sensor: None,
+            adaptive: false,
+            interval: 2,
+            hysteresis: 5.0,
+            curve: vec![
+                [0.0, 10.0],
+                [10.0, 30.0],
+                [100.0, 50.0],
+                [1000.0, 80.0],
+                [10000.0, 100.0],
+            ],
+            displays: vec![],
+        }
+    }
+}
+
+/// A light sensor
+pub enum Sensor {
+    /// An iio device, read directly
+    Iio {
+        path: PathBuf,
+        /// Applied to raw readings, `(raw + offset) * scale` is lux
+        scale: f32,
+        offset: f32,
+    },
+    /// iio-sensor-proxy, through `monitor-sensor` holding a claim on the sensor while it runs
+    Proxy(Proxy),
+}
+
+impl Sensor {
+    pub fn open(config: &AmbientConfig) -> Result<Self, Box<dyn Error>> {
+        let path = match (&config.sensor, find()) {
+            (Some(path), _) => path.clone(),
+            (None, Some(path)) => path,
+            (None, None) => {
+                return Proxy::start().map(Self::Proxy).map_err(|err| {
+                    format!("no light sensor found under /sys/bus/iio/devices, {err}").into()
+                })
+            }
+        };
+        let raw = path.to_string_lossy().ends_with("_raw");
+        let attribute = |name: &str, default: f32| -> f32 {
+            let path = path.with_file_name(format!("in_illuminance_{name}"));
+            fs::read_to_string(path)
+                .ok()
+                .and_then(|text| text.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        let (scale, offset) = match raw {
+            true => (attribute("scale", 1.0), attribute("offset", 0.0)),
+            false => (1.0, 0.0),
+        };
+        let sensor = Self::Iio {
+            path,
+            scale,
+            offset,
+        };
+        sensor.read()?;
+        Ok(sensor)
+    }
+
+    /// The current illuminance in lux.
+    pub fn read(&self) -> Result<f32, Box<dyn Error>> {
+        match self {
+            Self::Iio {
+                path,
+                scale,
+                offset,
+            } => {
+                let text =
+                    fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+                let value: f32 = text
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{}: not a number: {text:?}", path.display()))?;
+                Ok(((value + offset) * scale).max(0.0))
+            }
+            Self::Proxy(proxy) => proxy.read(),
+        }
+    }
+
+    /// Averages a few readings, evening out flicker of artificial light.
+    pub fn sample(&self) -> Result<f32, Box<dyn Error>> {
+        let mut sum = 0.0;
+        for i in 0..SAMPLES {
+            if i > 0 {
+                thread::sleep(SAMPLE_INTERVAL);
+            }
+            sum += self.read()?;
+        }
+        Ok(sum / SAMPLES as f32)
+    }
+}
+
+/// `monitor-sensor --light` of iio-sensor-proxy, for sensors only the proxy can read, e.g. while
+/// it holds them or on machines exposing them through HID
+pub struct Proxy {
+    child: Child,
+    /// The light level reported last, `None` while there is no sensor
+    level: Arc<Mutex<Option<f32>>>,
+}
+
+impl Proxy {
+    fn start() -> Result<Self, Box<dyn Error>> {
+        let mut child = Command::new("monitor-sensor")
+            .arg("--light")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("monitor-sensor of iio-sensor-proxy: {err}"))?;
+        let level = Arc::new(Mutex::new(None));
+        let reported = level.clone();
+        let stdout = child.stdout.take().unwrap();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if line.contains("vanished") || line.contains("No ambient light sensor") {
+                    *reported.lock().unwrap() = None;
+                } else if let Some(lux) = parse_level(&line) {
+                    *reported.lock().unwrap() = Some(lux);
+                }
+            }
+        });
+        let proxy = Self { child, level };
+        let started = Instant::now();
+        while proxy.level.lock().unwrap().is_none() {
+            if started.elapsed() > PROXY_TIMEOUT {
+                return Err("iio-sensor-proxy reported no light level".into());
+            }
+            thread::sleep(SAMPLE_INTERVAL);
+        }
+        Ok(proxy)
+    }
+
+    fn read(&self) -> Result<f32, Box<dyn Error>> {
+        self.level
+            .lock()
+            .unwrap()
+            .ok_or_else(|| "iio-sensor-proxy lost the light sensor".into())
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The level of a `monitor-sensor` line like `    Light changed: 123.000000 (lux)` or
+/// `=== Has ambient light sensor (light level: 123.000000, lux)`.
+fn parse_level(line: &str) -> Option<f32> {
+    let (_, rest) = line
+        .split_once("Light changed: ")
+        .or_else(|| line.split_once("light level: "))?;
+    let number = rest.split([' ', ',', '(']).next()?;
+    number.parse::<f32>().ok().map(|lux| lux.max(0.0))
+}
+
+/// The first iio device measuring illuminance.
+fn find() -> Option<PathBuf> {
+    let mut devices: Vec<PathBuf> = fs::read_dir("/sys/bus/iio/devices")
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    devices.sort();
+    devices.iter().find_map(|device| {
+        ["in_illuminance_input", "in_illuminance_raw"]
+            .into_iter()
+            .map(|name| device.join(name))
+            .find(|path| Path::exists(path))
+    })
+}
+
+/// Preferred brightness in percent by lux, `offset + slope * ln(1 + lux)`. Perceived brightness
+/// follows the logarithm of illuminance, so a line fits there.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Curve {
+    pub offset: f32,
+    pub slope: f32,
+}
+
+impl Curve {
+    /// Least squares fit through `(lux, percent)` samples, `None` unless they were taken at
+    /// different light levels.
+    pub fn fit(samples: &[(f32, f32)]) -> Option<Self> {
+        let count = samples.len() as f32;
+        let points: Vec<(f32, f32)> = samples
+            .iter()
+            .map(|&(lux, percent)| (lux.max(0.0).ln_1p(), percent))
+            .collect();
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f32>() / count;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f32>() / count;
+        let variance: f32 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        if variance < f32::EPSILON {
+            return None;
+        }
+        let covariance: f32 = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum();
+        let slope = covariance / variance;
+        Some(Self {
+            offset: mean_y - slope * mean_x,
+            slope,
+        })
+    }
+
+    /// Brightness in percent at an illuminance.
+    pub fn percent(&self, lux: f32) -> f32 {
+        (self.offset + self.slope * lux.max(0.0).ln_1p()).clamp(0.0, 100.0)
+    }
+}
+
+/// Brightness in percent at an illuminance by `[lux, percent]` points, joined by lines on the
+/// same log scale as [`Curve`] and flat past the first and last one, `None` without points.
+#[cfg(any(feature = "tui", feature = "daemon"))]
+fn interpolate(points: &[[f32; 2]], lux: f32) -> Option<f32> {
+    let mut points: Vec<(f32, f32)> = points
+        .iter()
+        .map(|[lux, percent]| (lux.max(0.0).ln_1p(), percent.clamp(0.0, 100.0)))
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let x = lux.max(0.0).ln_1p();
+    let (first, last) = (*points.first()?, *points.last()?);
+    if x <= first.0 {
+        return Some(first.1);
+    }
+    let percent = points
+        .windows(2)
+        .find(|pair| x <= pair[1].0)
+        .map(|pair| {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+        })
+        .unwrap_or(last.1);
+    Some(percent)
+}
+
+/// Fitted curves by display id, kept in `ambient.toml` and written by `calibrate`
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Calibration {
+    pub displays: BTreeMap<String, Curve>,
+}
+
+impl Calibration {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("ambient.toml"))
+    }
+
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|err| format!("{}: {err}", path.display()).into())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn store(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let path = Self::path().ok_or("no config directory")?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, toml::to_string(self)?)?;
+        Ok(path)
+    }
+}
+
+/// Adaptive brightness, run now and then by the TUI or the daemon
+#[cfg(any(feature = "tui", feature = "daemon"))]
+pub struct Adaptive {
+    sensor: Sensor,
+    calibration: Calibration,
+    /// The smoothed light level and when the sensor was read last
+    lux: Option<(f32, Instant)>,
+    /// Brightness in percent set last by display id
+    set: HashMap<String, f32>,
+    /// Whether the last reading failed, so a broken sensor is reported once
+    failing: bool,
+}
+
+#[cfg(any(feature = "tui", feature = "daemon"))]
+impl Adaptive {
+    pub fn new(config: &AmbientConfig) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            sensor: Sensor::open(config)?,
+            calibration: Calibration::load()?,
+            lux: None,
+            set: HashMap::new(),
+            failing: false,
+        })
+    }
+
+    /// Reads the sensor once `interval` passed and queues the brightness the curve wants on the
+    /// displays it moved `hysteresis` away from. A failing sensor is reported once, until it
+    /// reads again.
+    pub fn run(
+        &mut self,
+        config: &AmbientConfig,
+        automation: &AutomationConfig,
+        manager: &DisplayManager,
+        history: &mut Log,
+    ) -> Result<(), Box<dyn Error>> {
+        let interval = Duration::from_secs(config.interval.max(1));
+        if self.lux.is_some_and(|(_, read)| read.elapsed() < interval) {
+            return Ok(());
+        }
+        let reading = match self.sensor.read() {
+            Ok(reading) => reading,
+            Err(err) => {
+                self.lux = self.lux.map(|(lux, _)| (lux, Instant::now()));
+                return match std::mem::replace(&mut self.failing, true) {
+                    true => Ok(()),
+                    false => Err(err),
+                };
+            }
+        };
+        self.failing = false;
+        // A passing shadow or a flickering lamp moves the smoothed level only a little
+        let lux = match self.lux {
+            Some((lux, _)) => lux + SMOOTHING * (reading - lux),
+            None => reading,
+        };
+        self.lux = Some((lux, Instant::now()));
+        if !automation.is_active(Automation::Ambient) {
+            return Ok(());
+        }
+        for display in manager.displays.iter().filter(|d| includes(config, d)) {
+            let Some(controller) = display.controls.get(&Control::Brightness) else {
+                continue;
+            };
+            let target = match self.calibration.displays.get(&display.id) {
+                Some(curve) => curve.percent(lux),
+                None => match interpolate(&config.curve, lux) {
+                    Some(percent) => percent,
+                    None => continue,
+                },
+            };
+            let (current, maximum) = {
+                let controller = controller.read().unwrap();
+                (display.percent(&controller) as f32, controller.maximum)
+            };
+            let last = self.set.get(&display.id).copied().unwrap_or(current);
+            if (target - last).abs() < config.hysteresis.max(1.0) {
+                continue;
+            }
+            let value = display.resolve(Control::Brightness, Level::Percent(target), maximum);
+            manager.queue_set(display.clone(), controller.clone(), value);
+            history.record(Origin::Ambient, display, Control::Brightness as u8, value);
+            self.set.insert(display.id.clone(), target);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "tui", feature = "daemon"))]
+fn includes(config: &AmbientConfig, display: &MyDisplay) -> bool {
+    config.displays.is_empty() || config.displays.iter().any(|query| display.matches(query))
+}
+
+/// Walks through the lighting stages, sampling the sensor and asking for the preferred
+/// brightness of each display at each, then fits and stores a curve per display.
+pub fn calibrate(
+    displays: &[WrappedDisplay],
+    config: &AmbientConfig,
+) -> Result<(), Box<dyn Error>> {
+    let sensor = Sensor::open(config)?;
+    let displays: Vec<&WrappedDisplay> = displays
+        .iter()
+        .filter(|display| display.controls.contains_key(&Control::Brightness))
+        .collect();
+    if displays.is_empty() {
+        return Err("no selected display has a brightness control".into());
+    }
+    let mut stdin = io::stdin().lock();
+    let mut samples: Vec<Vec<(f32, f32)>> = vec![vec![]; displays.len()];
+    for (stage, lighting) in STAGES.iter().enumerate() {
+        println!("{}/{}: {lighting}", stage + 1, STAGES.len());
+        prompt(&mut stdin, "Set up the lighting and press Enter")?;
+        let lux = sensor.sample()?;
+        println!("  {lux:.0} lux");
+        for (display, samples) in displays.iter().zip(&mut samples) {
+            samples.push((lux, preferred(&mut stdin, display)?));
+        }
+    }
+
+    let mut calibration = Calibration::load()?;
+    for (display, samples) in displays.iter().zip(&samples) {
+        let Some(curve) = Curve::fit(samples) else {
+            return Err("every stage measured the same light, nothing to fit".into());
+        };
+        println!(
+            "{}: {:.0}% in the dark, {:.0}% at 100 lux, {:.0}% at 10000 lux",
+            display.label(),
+            curve.percent(0.0),
+            curve.percent(100.0),
+            curve.percent(10000.0)
+        );
+        calibration.displays.insert(display.id.clone(), curve);
+    }
+    println!("Saved to {}", calibration.store()?.display());
+    Ok(())
+}
+
+/// Asks for a display's brightness in percent, writing each value typed so it can be judged
+/// until an empty line keeps the current one.
+fn preferred(input: &mut impl BufRead, display: &WrappedDisplay) -> Result<f32, Box<dyn Error>> {
+    let controller = display.controls.get(&Control::Brightness).unwrap();
+    loop {
+        let percent = {
+            let mut controller = controller.write().unwrap();
+            if let Ok(value) = display.get(Control::Brightness) {
+                controller.value = value;
+            }
+            display.percent(&controller)
+        };
+        let line = prompt(
+            input,
+            &format!(
+                "  {} brightness in % (Enter keeps {percent}%)",
+                display.label()
+            ),
+        )?;
+        if line.is_empty() {
+            return Ok(percent as f32);
+        }
+        let Ok(wanted) = line.parse::<f32>() else {
+            println!("  {line:?} is not a number");
+            continue;
+        };
+        let maximum = controller.read().unwrap().maximum;
+        let level = Level::Percent(wanted.clamp(0.0, 100.0));
+        let value = display.resolve(Control::Brightness, level, maximum);
+        match display.set(Control::Brightness, value) {
+            Ok(()) => {
+                Log::default().record(Origin::Ambient, display, Control::Brightness as u8, value)
+            }
+            Err(err) => println!("  {}: {err}", display.label()),
+        }
+    }
+}
+
+fn prompt(input: &mut impl BufRead, text: &str) -> Result<String, Box<dyn Error>> {
+    print!("{text}: ");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err("input ended before the calibration was done".into());
+    }
+    Ok(line.trim().to_string())
+}
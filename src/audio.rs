@@ -0,0 +1,90 @@
+use std::{error::Error, process};
+
+use ddc_bright::display::MyDisplay;
+use serde::{Deserialize, Serialize};
+
+/// Default audio output switches following the displays, for speakers in or behind a display
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Sets the default output, `{sink}` is replaced by the sink of the rule
+    pub command: String,
+    pub rules: Vec<AudioRule>,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        let command = if cfg!(windows) {
+            // Needs the AudioDeviceCmdlets module, core audio has no tool of its own
+            "powershell -NoProfile -Command Set-AudioDevice -ID {sink}"
+        } else {
+            // PipeWire answers pactl through pipewire-pulse
+            "pactl set-default-sink {sink}"
+        };
+        Self {
+            command: command.to_string(),
+            rules: vec![],
+        }
+    }
+}
+
+/// Switches the default audio output when a display changes input or powers off
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AudioRule {
+    /// Model name or id
+    pub display: String,
+    /// Raw input select value the display switched to
+    pub input: Option<u16>,
+    /// Applies when the display goes to standby or off instead
+    #[serde(default)]
+    pub off: bool,
+    /// Sink name or device id passed to the command
+    pub sink: String,
+}
+
+/// What happened to a display
+#[derive(Clone, Copy)]
+pub enum Trigger {
+    Input(u16),
+    Off,
+}
+
+impl AudioConfig {
+    /// Whether some rule watches the display powering off.
+    pub fn watches_power(&self, display: &MyDisplay) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.off && display.matches(&rule.display))
+    }
+
+    /// Runs the command for the first rule matching, returning the sink switched to.
+    pub fn switch(
+        &self,
+        display: &MyDisplay,
+        trigger: Trigger,
+    ) -> Result<Option<&str>, Box<dyn Error>> {
+        let Some(rule) = self.rules.iter().find(|rule| {
+            display.matches(&rule.display)
+                && match trigger {
+                    Trigger::Input(input) => rule.input == Some(input & 0xFF),
+                    Trigger::Off => rule.off,
+                }
+        }) else {
+            return Ok(None);
+        };
+        let mut words = self
+            .command
+            .split_whitespace()
+            .map(|word| word.replace("{sink}", &rule.sink));
+        let program = words.next().ok_or("audio.command is empty")?;
+        // Captured so nothing is printed over the TUI
+        let output = process::Command::new(&program).args(words).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(
+                format!("{program} failed with {}: {}", output.status, stderr.trim()).into(),
+            );
+        }
+        Ok(Some(&rule.sink))
+    }
+}
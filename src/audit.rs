@@ -0,0 +1,396 @@
+//! Audit trail of the values written, for shared workstations: who changed what from which value
+//! to which, appended to `audit.log` next to the config.
+//!
+//! Each line carries an HMAC-SHA256 over itself and the MAC of the line before, under a key
+//! that is replaced by its own SHA-256 after every line. Only the key for the next line is kept,
+//! in `audit.key`, so whoever gets hold of the machine can add lines but can't rewrite those
+//! before without the first key, which `audit --init` prints once for keeping elsewhere. Lines
+//! cut off the end leave the log behind the key and show up too. The oldest kept logs being
+//! removed can't be told apart from rotation.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    digest::{self, hex, hmac, sha256, unhex},
+    history::{self, Entry, Origin},
+};
+
+static CONFIG: OnceLock<AuditConfig> = OnceLock::new();
+
+/// Starts every file after the first, with the MAC of the last line of the one before and the
+/// number of lines before it
+const CHAINED: &str = "# chained from ";
+
+/// Where the first key is kept when auditing starts without `audit --init`, for moving off the
+/// machine
+const SEED: &str = "audit.seed";
+
+/// The key the next line is signed with, and how many lines were signed before it
+struct Key {
+    index: u64,
+    key: [u8; 32],
+}
+
+impl Key {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("audit.key"))
+    }
+
+    fn load() -> io::Result<Option<Self>> {
+        let path = Self::path().ok_or(io::ErrorKind::NotFound)?;
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let parsed = (text.split_once(' ')).and_then(|(index, key)| {
+            Some(Self {
+                index: index.parse().ok()?,
+                key: unhex(key)?,
+            })
+        });
+        parsed
+            .map(Some)
+            .ok_or_else(|| io::Error::other("audit.key can't be read"))
+    }
+
+    fn store(&self) -> io::Result<()> {
+        let path = Self::path().ok_or(io::ErrorKind::NotFound)?;
+        private(&path, &format!("{} {}\n", self.index, hex(&self.key)))
+    }
+
+    /// Signs a line chained to `previous`, moving on to the next key.
+    fn sign(&mut self, previous: &str, fields: &str) -> String {
+        let mac = hex(&hmac(&self.key, format!("{previous}\n{fields}").as_bytes()));
+        self.key = sha256(&self.key);
+        self.index += 1;
+        mac
+    }
+}
+
+/// Writes a file only the user can read.
+fn private(path: &std::path::Path, text: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(text.as_bytes())
+}
+
+/// Starts the chain with a new key, before anything was audited, and returns the first key in
+/// hex. It isn't stored, `audit --verify --key` needs it back.
+pub fn begin() -> Result<String, Box<dyn Error>> {
+    if Key::load()?.is_some() || path(0).is_some_and(|path| path.exists()) {
+        return Err(
+            "the audit log was started already; to start over, move audit.log, \
+            the older logs and audit.key aside"
+                .into(),
+        );
+    }
+    fs::create_dir_all(Config::dir().ok_or("no config directory")?)?;
+    let key = digest::random()?;
+    Key { index: 0, key }.store()?;
+    Ok(hex(&key))
+}
+
+/// Key of a chain started by the first write, with the first key left in [`SEED`].
+fn start() -> Result<Key, Box<dyn Error>> {
+    let key = digest::random()?;
+    let dir = Config::dir().ok_or("no config directory")?;
+    private(&dir.join(SEED), &format!("{}\n", hex(&key)))?;
+    Ok(Key { index: 0, key })
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Appends every write to `audit.log`
+    pub enabled: bool,
+    /// KiB the log grows to before it is moved to `audit.log.1`, older ones moving up
+    pub max_kib: u64,
+    /// Moved logs kept, the oldest beyond that is removed
+    pub keep: u32,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_kib: 1024,
+            keep: 5,
+        }
+    }
+}
+
+/// Turns auditing on or off for the writes of this process, before any are made.
+pub fn init(config: &AuditConfig) {
+    let _ = CONFIG.set(config.clone());
+}
+
+/// One audited write
+pub struct Record {
+    pub time: DateTime<Local>,
+    /// The user the write was made by
+    pub user: String,
+    pub origin: Origin,
+    pub id: String,
+    pub display: String,
+    pub code: u8,
+    /// The value the write replaced, `None` if the display never told
+    pub old: Option<u16>,
+    pub value: u16,
+}
+
+impl Record {
+    pub fn control(&self) -> String {
+        history::control_name(self.code)
+    }
+
+    /// The line without its hash.
+    fn fields(&self) -> String {
+        let old = self.old.map_or("-".to_string(), |old| old.to_string());
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{:02x}\t{old}\t{}",
+            self.time.to_rfc3339(),
+            self.user,
+            self.origin,
+            self.id,
+            self.display,
+            self.code,
+            self.value
+        )
+    }
+
+    fn from_fields(fields: &str) -> Option<Self> {
+        let mut fields = fields.split('\t');
+        Some(Self {
+            time: DateTime::parse_from_rfc3339(fields.next()?)
+                .ok()?
+                .with_timezone(&Local),
+            user: fields.next()?.to_string(),
+            origin: fields.next()?.parse().ok()?,
+            id: fields.next()?.to_string(),
+            display: fields.next()?.to_string(),
+            code: u8::from_str_radix(fields.next()?, 16).ok()?,
+            old: match fields.next()? {
+                "-" => None,
+                old => Some(old.parse().ok()?),
+            },
+            value: fields.next()?.parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let old = self.old.map_or("?".to_string(), |old| old.to_string());
+        write!(
+            f,
+            "{}  {:<10}  {:<8}  {}  {} {old}→{}",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.user,
+            self.origin.to_string(),
+            self.display,
+            self.control(),
+            self.value
+        )
+    }
+}
+
+fn path(index: u32) -> Option<PathBuf> {
+    let name = match index {
+        0 => "audit.log".to_string(),
+        index => format!("audit.log.{index}"),
+    };
+    Config::dir().map(|dir| dir.join(name))
+}
+
+/// The user running ddc_bright.
+fn user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Appends the changes to the audit log if it is enabled, moving it aside once it grew too big.
+pub fn append(entries: &[Entry]) -> Result<(), Box<dyn Error>> {
+    let Some(config) = CONFIG.get().filter(|config| config.enabled) else {
+        return Ok(());
+    };
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let path = path(0).ok_or("no config directory")?;
+    fs::create_dir_all(path.parent().ok_or("no config directory")?)?;
+    let mut key = match Key::load()? {
+        Some(key) => key,
+        None => start()?,
+    };
+    let mut hash = last_hash(&fs::read_to_string(&path).unwrap_or_default());
+    let user = user();
+    let mut text = String::new();
+    for entry in entries {
+        let record = Record {
+            time: entry.time,
+            user: user.clone(),
+            origin: entry.origin,
+            id: entry.id.clone(),
+            display: entry.display.clone(),
+            code: entry.code,
+            old: entry.old,
+            value: entry.value,
+        };
+        let fields = record.fields();
+        hash = key.sign(&hash, &fields);
+        text += &format!("{fields}\t{hash}\n");
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    file.write_all(text.as_bytes())?;
+    // The key signing the lines is gone once the next one is stored
+    key.store()?;
+    if file.metadata()?.len() > config.max_kib * 1024 {
+        drop(file);
+        rotate(config.keep, &hash, key.index)?;
+    }
+    Ok(())
+}
+
+/// Moves every log up by one, starting a new one chained to the last line, the `index`th.
+fn rotate(keep: u32, hash: &str, index: u64) -> io::Result<()> {
+    let path = |index| path(index).ok_or(io::ErrorKind::NotFound);
+    if keep == 0 {
+        fs::remove_file(path(0)?)?;
+    }
+    for index in (0..keep).rev() {
+        match fs::rename(path(index)?, path(index + 1)?) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    fs::write(path(0)?, format!("{CHAINED}{hash} at {index}\n"))
+}
+
+/// The MAC the next line chains to: that of the last line, or the one the file starts from.
+fn last_hash(text: &str) -> String {
+    let Some(line) = text.lines().last() else {
+        return String::new();
+    };
+    match line.strip_prefix(CHAINED) {
+        Some(chained) => chained.split(' ').next().unwrap_or_default().to_string(),
+        None => line.rsplit('\t').next().unwrap_or_default().to_string(),
+    }
+}
+
+/// The audited writes of every kept log, oldest first. Lines that can't be read are skipped.
+pub fn read() -> Result<Vec<Record>, Box<dyn Error>> {
+    let mut records = vec![];
+    for (_, text) in files()? {
+        for line in text.lines().filter(|line| !line.starts_with(CHAINED)) {
+            let fields = line.rsplit_once('\t').map_or(line, |(fields, _)| fields);
+            records.extend(Record::from_fields(fields));
+        }
+    }
+    Ok(records)
+}
+
+/// The kept logs with their text, oldest first.
+fn files() -> Result<Vec<(PathBuf, String)>, Box<dyn Error>> {
+    let mut files = vec![];
+    for index in 0.. {
+        let Some(path) = path(index) else {
+            break;
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => files.push((path, text)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    files.reverse();
+    Ok(files)
+}
+
+/// How the log was checked
+pub struct Verified {
+    /// Lines checked
+    pub checked: u64,
+    /// Whether the first key came from [`SEED`] next to the log instead of `--key`, which
+    /// whoever rewrites the log can rewrite too
+    pub local: bool,
+}
+
+/// Checks the MACs of every kept log from the first key, given in hex or else read from
+/// [`SEED`], and that the log ends where the key in `audit.key` was moved on to. Returns how
+/// many lines were checked or where the chain breaks first.
+pub fn verify(seed: Option<&str>) -> Result<Verified, Box<dyn Error>> {
+    let local = seed.is_none();
+    let seed = match seed {
+        Some(seed) => seed.to_string(),
+        None => Config::dir()
+            .and_then(|dir| fs::read_to_string(dir.join(SEED)).ok())
+            .ok_or("no first key: pass the one audit --init printed with --key")?,
+    };
+    let seed = unhex(&seed).ok_or("the first key must be 64 hex digits")?;
+    let mut key = Key {
+        index: 0,
+        key: seed,
+    };
+    let mut hash: Option<String> = None;
+    let mut checked = 0;
+    for (path, text) in files()? {
+        for (number, line) in text.lines().enumerate() {
+            let broken = || {
+                format!(
+                    "{} line {}: the chain breaks here",
+                    path.display(),
+                    number + 1
+                )
+            };
+            if let Some(chained) = line.strip_prefix(CHAINED) {
+                let (start, index) = chained.split_once(" at ").ok_or_else(broken)?;
+                let index: u64 = index.parse().map_err(|_| broken())?;
+                // The oldest kept file starts from a line that was removed with its file
+                match &hash {
+                    Some(hash) if hash != start || index != key.index => {
+                        return Err(broken().into())
+                    }
+                    Some(_) => {}
+                    None if index < key.index => return Err(broken().into()),
+                    None => (key.index..index).for_each(|_| key.key = sha256(&key.key)),
+                }
+                key.index = index;
+                hash = Some(start.to_string());
+                continue;
+            }
+            let (fields, stored) = line.rsplit_once('\t').ok_or_else(broken)?;
+            let expected = key.sign(hash.as_deref().unwrap_or_default(), fields);
+            if !digest::equal(stored, &expected) {
+                return Err(broken().into());
+            }
+            hash = Some(expected);
+            checked += 1;
+        }
+    }
+    // A key that moved on further means lines were cut off the end
+    let stored = Key::load()?.ok_or("audit.key is gone")?;
+    if stored.index != key.index || stored.key != key.key {
+        return Err(format!(
+            "the log ends after line {} but audit.key was moved on to {}: lines were cut off \
+            the end or the key replaced",
+            key.index, stored.index
+        )
+        .into());
+    }
+    Ok(Verified { checked, local })
+}
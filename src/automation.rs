@@ -0,0 +1,124 @@
+//! Which automations run, following the preset applied last. A preset lists the automations it
+//! turns on or off while it is applied, the ones it leaves out run as `[automation]` in the config
+//! says. The preset applied last is kept in `automation.toml` next to the config, so the daemon
+//! and the TUI follow presets applied from the command line too.
+
+use std::{collections::BTreeMap, error::Error, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::presets::Preset;
+
+/// Something that changes the displays on its own
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Automation {
+    /// Schedule rules
+    Schedule,
+    /// Corrections of the watchdog
+    Watchdog,
+    /// Input switching of the software KVM
+    Kvm,
+    /// Input switching of wake rules
+    Wake,
+    /// Following the laptop backlight
+    Backlight,
+    /// Adaptive brightness following the light sensor
+    Ambient,
+}
+
+impl Automation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Schedule => "schedule",
+            Self::Watchdog => "watchdog",
+            Self::Kvm => "kvm",
+            Self::Wake => "wake",
+            Self::Backlight => "backlight",
+            Self::Ambient => "ambient",
+        }
+    }
+}
+
+/// Automations paused until a preset turns them on
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AutomationConfig {
+    pub paused: Vec<Automation>,
+}
+
+impl AutomationConfig {
+    /// Whether the automation runs under the preset applied last. Asked right before it changes
+    /// anything, so presets applied by other processes count from then on.
+    pub fn is_active(&self, automation: Automation) -> bool {
+        Policy::load()
+            .automations
+            .get(&automation)
+            .copied()
+            .unwrap_or(!self.paused.contains(&automation))
+    }
+}
+
+/// The automations switched by the preset applied last, kept in `automation.toml`
+#[derive(Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct Policy {
+    pub preset: Option<String>,
+    pub automations: BTreeMap<Automation, bool>,
+}
+
+impl Policy {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("automation.toml"))
+    }
+
+    /// The stored policy, running everything as configured if there is none or it can't be read.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| toml::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Makes the automations of the preset the ones in effect, replacing those of the preset
+    /// applied before.
+    pub fn switch(preset: &Preset) -> Result<(), Box<dyn Error>> {
+        let policy = Self {
+            preset: Some(preset.name.clone()),
+            automations: preset.automations.clone(),
+        };
+        let previous = Self::load();
+        // Presets without automations don't rewrite the file every time the schedule applies one
+        if policy.automations.is_empty() && previous.automations.is_empty() {
+            return Ok(());
+        }
+        if policy == previous {
+            return Ok(());
+        }
+        let path = Self::path().ok_or("no config directory")?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, toml::to_string(&policy)?)?;
+        Ok(())
+    }
+}
+
+/// What applying the preset does to the automations, like `pausing schedule, kvm`, `None` if it
+/// switches none.
+pub fn describe(preset: &Preset) -> Option<String> {
+    let names = |on: bool| {
+        preset
+            .automations
+            .iter()
+            .filter(|(_, active)| **active == on)
+            .map(|(automation, _)| automation.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let parts: Vec<String> = [("pausing", names(false)), ("running", names(true))]
+        .into_iter()
+        .filter(|(_, names)| !names.is_empty())
+        .map(|(verb, names)| format!("{verb} {names}"))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("; "))
+}
@@ -0,0 +1,255 @@
+//! How displays are reached, kept apart so backends can be left out of a build
+//!
+//! Each output is taken by the first backend that reaches it: DDC/CI, directly or through the
+//! root helper, then the backlight of an internal panel, through sysfs or WMI, then software
+//! gamma.
+
+#[cfg(all(unix, feature = "helper"))]
+use crate::helper;
+#[cfg(feature = "trace")]
+use crate::trace::Exchange;
+use crate::Error;
+use ddc_hi::{Ddc as _, Display, DisplayInfo, Handle};
+use std::mem::ManuallyDrop;
+use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "trace")]
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// A way of reaching a display, one per kind of connection
+pub(crate) trait Backend: Send + Sync {
+    /// Reads the current and maximum value of a VCP code.
+    fn read_vcp(&self, code: u8) -> Result<(u16, u16), Error>;
+
+    fn write_vcp(&self, code: u8, value: u16) -> Result<(), Error>;
+
+    fn capabilities_string(&self) -> Result<Vec<u8>, Error>;
+
+    /// Locks the connection for a series of writes, so they don't each wait for the lock.
+    /// Backends without a lock of their own write one at a time.
+    fn session(&self) -> Box<dyn Session + '_> {
+        Box::new(Unlocked(self))
+    }
+
+    /// Sends a DDC/CI command as bytes and reads `reply` bytes of answer after `delay`, for
+    /// tracing. `None` where the backend only offers whole commands.
+    #[cfg(feature = "trace")]
+    fn exchange(&self, _data: &[u8], _reply: usize, _delay: Duration) -> Option<Exchange> {
+        None
+    }
+}
+
+/// A connection locked for a series of writes
+pub(crate) trait Session {
+    fn get_vcp(&mut self, code: u8) -> Result<u16, Error>;
+
+    fn set_vcp(&mut self, code: u8, value: u16) -> Result<(), Error>;
+}
+
+/// Held while an NVAPI handle is used or dropped, see [`SendHandle`]
+#[cfg(all(windows, feature = "nvapi"))]
+static NVAPI: Mutex<()> = Mutex::new(());
+
+/// A ddc-hi handle that may move to other threads.
+///
+/// The Windows handles hold raw pointers to physical monitors, which any thread of the process
+/// may use, and the `Mutex` of [`Ddc`] keeps it to one thread at a time. The NVAPI handles also
+/// share an `Rc` of their GPU with the other displays on it, so they are only used and dropped
+/// while holding `NVAPI`, and no two threads ever touch the count of that `Rc` at once.
+pub(crate) struct SendHandle(ManuallyDrop<Handle>);
+
+// SAFETY: see above, nothing else of a handle is tied to the thread that created it
+unsafe impl Send for SendHandle {}
+
+impl SendHandle {
+    pub fn new(handle: Handle) -> Self {
+        Self(ManuallyDrop::new(handle))
+    }
+
+    /// Runs `use_handle` on the handle, holding `NVAPI` for an NVAPI one.
+    pub fn with<T>(&mut self, use_handle: impl FnOnce(&mut Handle) -> T) -> T {
+        let _shared = self.shared();
+        use_handle(&mut self.0)
+    }
+
+    #[cfg(all(windows, feature = "nvapi"))]
+    fn shared(&self) -> Option<MutexGuard<'static, ()>> {
+        match *self.0 {
+            Handle::Nvapi(_) => Some(NVAPI.lock().unwrap_or_else(|err| err.into_inner())),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(all(windows, feature = "nvapi")))]
+    fn shared(&self) -> Option<MutexGuard<'static, ()>> {
+        None
+    }
+}
+
+impl Drop for SendHandle {
+    fn drop(&mut self) {
+        let _shared = self.shared();
+        // SAFETY: dropped once, here, and never used again
+        unsafe { ManuallyDrop::drop(&mut self.0) };
+    }
+}
+
+/// A display ddc-hi found, its handle wrapped to reach the thread loading it
+pub(crate) struct Found {
+    pub handle: SendHandle,
+    pub info: DisplayInfo,
+}
+
+impl From<Display> for Found {
+    fn from(display: Display) -> Self {
+        Self {
+            handle: SendHandle::new(display.handle),
+            info: display.info,
+        }
+    }
+}
+
+/// Through whichever ddc-hi backend enumerated the display
+pub(crate) struct Ddc(pub Mutex<SendHandle>);
+
+impl Backend for Ddc {
+    fn read_vcp(&self, code: u8) -> Result<(u16, u16), Error> {
+        let value = self
+            .0
+            .lock()
+            .unwrap()
+            .with(|handle| handle.get_vcp_feature(code))?;
+        Ok((value.value(), value.maximum()))
+    }
+
+    fn write_vcp(&self, code: u8, value: u16) -> Result<(), Error> {
+        let mut handle = self.0.lock().unwrap();
+        Ok(handle.with(|handle| handle.set_vcp_feature(code, value))?)
+    }
+
+    fn capabilities_string(&self) -> Result<Vec<u8>, Error> {
+        let mut handle = self.0.lock().unwrap();
+        Ok(handle.with(|handle| handle.capabilities_string())?)
+    }
+
+    fn session(&self) -> Box<dyn Session + '_> {
+        Box::new(DdcSession(self.0.lock().unwrap()))
+    }
+
+    #[cfg(all(target_os = "linux", feature = "i2c", feature = "trace"))]
+    fn exchange(&self, data: &[u8], reply: usize, delay: Duration) -> Option<Exchange> {
+        // The i2c device is the only handle on Linux
+        let mut handle = self.0.lock().unwrap();
+        let Handle::I2cDevice(ddc) = &mut *handle.0;
+        Some(crate::trace::exchange_i2c(ddc, data, reply, delay))
+    }
+}
+
+struct DdcSession<'a>(MutexGuard<'a, SendHandle>);
+
+impl Session for DdcSession<'_> {
+    fn get_vcp(&mut self, code: u8) -> Result<u16, Error> {
+        Ok(self.0.with(|handle| handle.get_vcp_feature(code))?.value())
+    }
+
+    fn set_vcp(&mut self, code: u8, value: u16) -> Result<(), Error> {
+        Ok(self.0.with(|handle| handle.set_vcp_feature(code, value))?)
+    }
+}
+
+/// Through the root helper, for users without access to the i2c devices
+#[cfg(all(unix, feature = "helper"))]
+pub(crate) struct Helper {
+    pub client: helper::Client,
+    /// Id of the display on the helper's side
+    pub id: String,
+}
+
+#[cfg(all(unix, feature = "helper"))]
+impl Backend for Helper {
+    fn read_vcp(&self, code: u8) -> Result<(u16, u16), Error> {
+        self.client.get(&self.id, code).map_err(Error::Helper)
+    }
+
+    fn write_vcp(&self, code: u8, value: u16) -> Result<(), Error> {
+        self.client
+            .set(&self.id, code, value)
+            .map_err(Error::Helper)
+    }
+
+    fn capabilities_string(&self) -> Result<Vec<u8>, Error> {
+        self.client.capabilities(&self.id).map_err(Error::Helper)
+    }
+}
+
+/// Writes through the backend itself, one call per write
+struct Unlocked<'a, B: ?Sized>(&'a B);
+
+impl<B: Backend + ?Sized> Session for Unlocked<'_, B> {
+    fn get_vcp(&mut self, code: u8) -> Result<u16, Error> {
+        Ok(self.0.read_vcp(code)?.0)
+    }
+
+    fn set_vcp(&mut self, code: u8, value: u16) -> Result<(), Error> {
+        self.0.write_vcp(code, value)
+    }
+}
+
+/// Logs every read and write of the session it wraps
+pub(crate) struct Logged<'a> {
+    pub session: Box<dyn Session + 'a>,
+    /// Id of the display the session is of
+    pub display: &'a str,
+}
+
+impl Session for Logged<'_> {
+    fn get_vcp(&mut self, code: u8) -> Result<u16, Error> {
+        let result = self.session.get_vcp(code);
+        match &result {
+            Ok(value) => debug!(display = self.display, "read 0x{code:02X}: {value}"),
+            Err(err) => warn!(display = self.display, "reading 0x{code:02X} failed: {err}"),
+        }
+        result
+    }
+
+    fn set_vcp(&mut self, code: u8, value: u16) -> Result<(), Error> {
+        let result = self.session.set_vcp(code, value);
+        match &result {
+            Ok(()) => debug!(display = self.display, "wrote 0x{code:02X}: {value}"),
+            Err(err) => warn!(
+                display = self.display,
+                "writing 0x{code:02X} = {value} failed: {err}"
+            ),
+        }
+        result
+    }
+}
+
+/// Of a display that was unplugged, failing every write without touching the bus
+pub(crate) struct Gone;
+
+impl Session for Gone {
+    fn get_vcp(&mut self, _: u8) -> Result<u16, Error> {
+        Err(Error::Gone)
+    }
+
+    fn set_vcp(&mut self, _: u8, _: u16) -> Result<(), Error> {
+        Err(Error::Gone)
+    }
+}
+
+/// Brightness, the only VCP code of backends dimming the whole picture like backlights and
+/// gamma
+#[cfg(any(
+    all(target_os = "linux", any(feature = "sysfs", feature = "gamma")),
+    all(windows, feature = "wmi")
+))]
+pub(crate) fn brightness_only(code: u8, what: &str) -> Result<(), std::io::Error> {
+    match code == crate::display::Control::Brightness as u8 {
+        true => Ok(()),
+        false => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("{what} only has brightness"),
+        )),
+    }
+}
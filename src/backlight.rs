@@ -0,0 +1,262 @@
+//! Ties the external displays to the internal backlight, so the desktop's brightness keys move
+//! them too
+
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use ddc_bright::{
+    display::{Control, DisplayManager, MyDisplay, WrappedDisplay},
+    supervisor::Supervisor,
+    units::Level,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::automation::{Automation, AutomationConfig};
+use crate::history::{Log, Origin};
+use crate::logging;
+use crate::notify::{NotificationsConfig, Source};
+
+const SYSFS: &str = "/sys/class/backlight";
+
+/// How often the internal backlight is read while following it
+const INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BacklightConfig {
+    /// Name under /sys/class/backlight, e.g. "intel_backlight", the first one if unset
+    pub device: Option<String>,
+    /// External displays take the brightness of the internal one whenever it changes, e.g.
+    /// through KDE PowerDevil or the GNOME settings daemon handling the brightness keys
+    pub follow: bool,
+    /// `brightness-key` moves the internal backlight along with the external displays
+    pub together: bool,
+    /// Percent one `brightness-key` press moves
+    pub step: u16,
+    /// External displays affected by model name or id, every display if empty
+    pub displays: Vec<String>,
+}
+
+impl Default for BacklightConfig {
+    fn default() -> Self {
+        Self {
+            device: None,
+            follow: false,
+            together: true,
+            step: 5,
+            displays: vec![],
+        }
+    }
+}
+
+impl BacklightConfig {
+    fn includes(&self, display: &MyDisplay) -> bool {
+        // The internal panel is listed as a display too, but it is the one being followed
+        if display.backend == "sysfs" {
+            return false;
+        }
+        self.displays.is_empty() || self.displays.iter().any(|query| display.matches(query))
+    }
+}
+
+/// Which way a brightness key goes
+#[derive(Clone, Copy)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_lowercase().as_str() {
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            _ => Err(format!("{text:?} is not up or down")),
+        }
+    }
+}
+
+/// The internal panel's backlight
+struct Backlight {
+    name: String,
+    dir: PathBuf,
+    maximum: u32,
+}
+
+impl Backlight {
+    fn open(config: &BacklightConfig) -> Result<Self, Box<dyn Error>> {
+        let name = match &config.device {
+            Some(name) => name.clone(),
+            None => {
+                let mut names: Vec<String> = fs::read_dir(SYSFS)
+                    .map_err(|err| format!("{SYSFS}: {err}"))?
+                    .filter_map(|entry| Some(entry.ok()?.file_name().to_string_lossy().into()))
+                    .collect();
+                names.sort();
+                names
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| format!("no backlight under {SYSFS}"))?
+            }
+        };
+        let dir = Path::new(SYSFS).join(&name);
+        let maximum = read_number(&dir.join("max_brightness"))?.max(1);
+        Ok(Self { name, dir, maximum })
+    }
+
+    /// The brightness in percent.
+    fn percent(&self) -> Result<u16, Box<dyn Error>> {
+        let raw = read_number(&self.dir.join("brightness"))?;
+        Ok((raw as u64 * 100 / self.maximum as u64).min(100) as u16)
+    }
+
+    /// Writes the sysfs file if allowed, otherwise asks logind, which lets the session user
+    /// change its own backlight.
+    fn set_percent(&self, percent: u16) -> Result<(), Box<dyn Error>> {
+        let raw = (self.maximum as u64 * percent as u64 / 100) as u32;
+        if fs::write(self.dir.join("brightness"), raw.to_string()).is_ok() {
+            return Ok(());
+        }
+        let status = process::Command::new("busctl")
+            .args([
+                "call",
+                "org.freedesktop.login1",
+                "/org/freedesktop/login1/session/auto",
+                "org.freedesktop.login1.Session",
+                "SetBrightness",
+                "ssu",
+                "backlight",
+                &self.name,
+                &raw.to_string(),
+            ])
+            .status()?;
+        if !status.success() {
+            return Err(format!("busctl failed with {status}").into());
+        }
+        Ok(())
+    }
+}
+
+fn read_number(path: &Path) -> Result<u32, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{}: not a number: {text:?}", path.display()).into())
+}
+
+/// Sets the brightness of a display in percent of its range, logged as `origin`.
+fn set_percent(display: &MyDisplay, percent: u16, origin: Origin) -> Result<(), Box<dyn Error>> {
+    let Some(controller) = display.controls.get(&Control::Brightness) else {
+        return Ok(());
+    };
+    let maximum = controller.read().unwrap().maximum;
+    let value = display.resolve(Control::Brightness, Level::Percent(percent as f32), maximum);
+    display.set(Control::Brightness, value)?;
+    controller.write().unwrap().value = value;
+    Log::default().record(origin, display, Control::Brightness as u8, value);
+    Ok(())
+}
+
+/// Follows the internal backlight while the returned supervisor lives, if configured.
+pub fn start(
+    manager: &DisplayManager,
+    config: &BacklightConfig,
+    automation: &AutomationConfig,
+) -> Option<Supervisor> {
+    if !config.follow {
+        return None;
+    }
+    let backlight = match Backlight::open(config) {
+        Ok(backlight) => backlight,
+        Err(err) => {
+            eprintln!("backlight: {err}");
+            return None;
+        }
+    };
+    let displays = manager.shared_displays();
+    let config = config.clone();
+    let automation = automation.clone();
+
+    let mut supervisor = Supervisor::default();
+    supervisor.spawn("backlight", move |token| {
+        // Only changes from now on are followed
+        let mut last = backlight.percent().ok();
+        while !token.is_cancelled() {
+            thread::sleep(INTERVAL);
+            let Ok(percent) = backlight.percent() else {
+                continue;
+            };
+            if last == Some(percent) {
+                continue;
+            }
+            last = Some(percent);
+            if !automation.is_active(Automation::Backlight) {
+                continue;
+            }
+            let displays: Vec<WrappedDisplay> = displays.lock().unwrap().clone();
+            for display in displays.iter().filter(|display| config.includes(display)) {
+                if let Err(err) = set_percent(display, percent, Origin::Backlight) {
+                    let label = display.label();
+                    logging::report(format!("{label}: following the backlight failed: {err}"));
+                }
+            }
+        }
+    });
+    Some(supervisor)
+}
+
+/// Moves the external displays, and the internal backlight if `together`, by one step. Meant to
+/// be bound to the brightness keys in place of the desktop's own handling.
+pub fn key(
+    manager: &DisplayManager,
+    config: &BacklightConfig,
+    direction: Direction,
+    notifications: &NotificationsConfig,
+) -> Result<(), Box<dyn Error>> {
+    let step = match direction {
+        Direction::Up => config.step as i32,
+        Direction::Down => -(config.step as i32),
+    };
+    let moved = |percent: u16| (percent as i32 + step).clamp(0, 100) as u16;
+    if config.together {
+        let backlight = Backlight::open(config)?;
+        backlight.set_percent(moved(backlight.percent()?))?;
+    }
+    let mut failed = false;
+    let mut changed = vec![];
+    for display in manager
+        .displays
+        .iter()
+        .filter(|display| config.includes(display))
+    {
+        let Some(controller) = display.controls.get(&Control::Brightness) else {
+            continue;
+        };
+        let percent = moved(display.percent(&controller.read().unwrap()));
+        match set_percent(display, percent, Origin::Cli) {
+            Ok(()) => changed.push(format!("{}: brightness {percent}%", display.label())),
+            Err(err) => {
+                logging::report(format!("{}: {err}", display.label()));
+                failed |= !display.is_lenient();
+            }
+        }
+    }
+    if !changed.is_empty() {
+        if let Err(err) = notifications.notify(Source::Keys, &changed.join("\n")) {
+            logging::report(err.to_string());
+        }
+    }
+    if failed {
+        return Err("not every display took the change".into());
+    }
+    Ok(())
+}
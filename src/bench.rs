@@ -0,0 +1,149 @@
+use std::{
+    error::Error,
+    thread,
+    time::{Duration, Instant},
+};
+
+use ddc_bright::display::{Control, MyDisplay, Tuning, WrappedDisplay};
+
+use crate::quirks::Cache;
+
+/// Writes per measured strategy, even so brightness ends where it started
+const STEPS: u32 = 10;
+
+/// Pauses after each write that are tried
+const DELAYS_MS: [u64; 4] = [0, 20, 50, 100];
+
+struct Outcome {
+    tuning: Tuning,
+    writes_per_second: f32,
+    latency: Duration,
+    /// Writes that failed or read back differently
+    failures: u32,
+}
+
+/// Measures write strategies on the brightness of each display and recommends the fastest
+/// reliable one, storing it in the quirk cache if `save` is set.
+pub fn run(displays: &[WrappedDisplay], save: bool) -> Result<(), Box<dyn Error>> {
+    let mut cache = Cache::load()?;
+    for display in displays {
+        println!("{}", display.label());
+        let base = match display.get(Control::Brightness) {
+            Ok(base) => base,
+            Err(err) => {
+                println!("  skipped, brightness can't be read: {err}");
+                continue;
+            }
+        };
+        let maximum = display
+            .controls
+            .get(&Control::Brightness)
+            .map_or(100, |controller| controller.read().unwrap().maximum);
+        let other = if base >= 5 {
+            base - 5
+        } else {
+            (base + 5).min(maximum)
+        };
+
+        println!("  delay  verify  writes/s  latency  failures");
+        let mut outcomes = vec![];
+        for delay_ms in DELAYS_MS {
+            for verify in [false, true] {
+                let outcome = measure(
+                    display,
+                    Tuning {
+                        delay_ms,
+                        verify,
+                        ..Tuning::default()
+                    },
+                    base,
+                    other,
+                );
+                println!(
+                    "  {:>3} ms  {:<6}  {:>8.1}  {:>4} ms  {:>8}",
+                    delay_ms,
+                    if verify { "on" } else { "off" },
+                    outcome.writes_per_second,
+                    outcome.latency.as_millis(),
+                    outcome.failures
+                );
+                outcomes.push(outcome);
+            }
+        }
+
+        let best = outcomes
+            .iter()
+            .filter(|outcome| outcome.failures == 0)
+            .max_by(|a, b| a.writes_per_second.total_cmp(&b.writes_per_second));
+        let Some(best) = best else {
+            println!("  no strategy was reliable, check the cable or try another port");
+            display.set(Control::Brightness, base)?;
+            continue;
+        };
+        let one_by_one = burst(display, best.tuning, base, other, false);
+        let coalesced = burst(display, best.tuning, base, other, true);
+        println!(
+            "  burst of {STEPS}: {} ms written one by one, {} ms coalesced like the change queue does",
+            one_by_one.as_millis(),
+            coalesced.as_millis()
+        );
+        println!(
+            "  recommended: delay_ms = {}, verify = {}",
+            best.tuning.delay_ms, best.tuning.verify
+        );
+        cache.displays.insert(display.id.clone(), best.tuning);
+    }
+    if save {
+        let path = cache.store()?;
+        println!("Saved to {}", path.display());
+    }
+    Ok(())
+}
+
+fn write(display: &MyDisplay, tuning: Tuning, value: u16) -> bool {
+    let written = display.set(Control::Brightness, value).is_ok()
+        && (!tuning.verify
+            || display
+                .get(Control::Brightness)
+                .is_ok_and(|read| read == value));
+    thread::sleep(Duration::from_millis(tuning.delay_ms));
+    written
+}
+
+/// Alternates brightness between `other` and `base`, ending on `base`.
+fn measure(display: &MyDisplay, tuning: Tuning, base: u16, other: u16) -> Outcome {
+    let start = Instant::now();
+    let mut latency = Duration::ZERO;
+    let mut failures = 0;
+    for step in 0..STEPS {
+        let value = if step % 2 == 0 { other } else { base };
+        let write_start = Instant::now();
+        if !write(display, tuning, value) {
+            failures += 1;
+        }
+        latency += write_start.elapsed();
+    }
+    let elapsed = start.elapsed();
+    // Without verifying, a dropped write only shows in the final value
+    if !tuning.verify && display.get(Control::Brightness).ok() != Some(base) {
+        failures += 1;
+    }
+    Outcome {
+        tuning,
+        writes_per_second: STEPS as f32 / elapsed.as_secs_f32(),
+        latency: latency / STEPS,
+        failures,
+    }
+}
+
+/// Time until the last of a burst of changes is written, with or without skipping the others.
+fn burst(display: &MyDisplay, tuning: Tuning, base: u16, other: u16, coalesce: bool) -> Duration {
+    let start = Instant::now();
+    if !coalesce {
+        for step in 0..STEPS - 1 {
+            write(display, tuning, if step % 2 == 0 { other } else { base });
+        }
+    }
+    write(display, tuning, base);
+    start.elapsed()
+}
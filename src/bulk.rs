@@ -0,0 +1,175 @@
+//! Reading every control of many displays again, a few displays at a time: one after another
+//! takes long on a video wall, all at once crowds the buses and the helper. Writing many values
+//! at once, like a preset for every display, spaced out so displays don't drop any.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use crate::display::{Control, Controller, WrappedDisplay};
+use crate::Error;
+
+/// Least time between commands to a display, as DDC/CI asks for
+pub const WRITE_GAP: Duration = Duration::from_millis(50);
+
+/// Times a value that failed or didn't take is written again, after the other values of the
+/// display, on top of the retries of each write
+const ROUNDS: usize = 2;
+
+/// What reading one display again found
+pub struct Reread {
+    pub display: WrappedDisplay,
+    /// Controls read and updated
+    pub read: usize,
+    /// Controls that didn't answer, their values left as they were
+    pub failed: Vec<(Control, Error)>,
+}
+
+/// Reads every control of the displays again, at most `parallel` displays at a time, and updates
+/// their values. `progress` is called on this thread as each display is done, in the order they
+/// finish, which is also the order returned.
+pub fn reread(
+    displays: &[WrappedDisplay],
+    parallel: usize,
+    progress: impl FnMut(&Reread),
+) -> Vec<Reread> {
+    each(displays, parallel, read, progress)
+}
+
+/// Runs `work` on the items, at most `parallel` at a time, calling `progress` on this thread with
+/// each result as it comes in.
+fn each<T: Sync, R: Send>(
+    items: &[T],
+    parallel: usize,
+    work: impl Fn(&T) -> R + Sync,
+    mut progress: impl FnMut(&R),
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..parallel.clamp(1, items.len().max(1)) {
+            let (sender, next, work) = (sender.clone(), &next, &work);
+            scope.spawn(move || {
+                while let Some(item) = items.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    if sender.send(work(item)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        receiver.iter().inspect(|result| progress(result)).collect()
+    })
+}
+
+fn read(display: &WrappedDisplay) -> Reread {
+    let mut reread = Reread {
+        display: display.clone(),
+        read: 0,
+        failed: vec![],
+    };
+    // Values waiting to be written or previewed stay as they are
+    let held = |controller: &Controller| controller.pending || controller.previewed;
+    for (control, controller) in &display.controls {
+        if held(&controller.read().unwrap()) {
+            continue;
+        }
+        match display.get(*control) {
+            Ok(value) => {
+                let mut controller = controller.write().unwrap();
+                if !held(&controller) {
+                    controller.value = value;
+                    controller.failed = false;
+                }
+                reread.read += 1;
+            }
+            Err(err) => {
+                controller.write().unwrap().failed = true;
+                reread.failed.push((*control, err));
+            }
+        }
+    }
+    reread
+}
+
+/// What writing the values of one display did
+pub struct Applied {
+    pub display: WrappedDisplay,
+    /// Controls written with their values, in the order they were
+    pub written: Vec<(Control, u16)>,
+    /// Controls that still failed or didn't take the value in the last round
+    pub failed: Vec<(Control, Error)>,
+}
+
+/// Writes the values of each display one after another, at least [`WRITE_GAP`] or the delay the
+/// display is tuned with apart, checking continuous controls took the value. What fails is
+/// written again after the rest. Displays are written in parallel, `progress` is called on this
+/// thread as each one is done, in the order returned.
+pub fn apply(
+    values: &[(WrappedDisplay, Vec<(Control, u16)>)],
+    progress: impl FnMut(&Applied),
+) -> Vec<Applied> {
+    each(
+        values,
+        values.len(),
+        |(display, values)| write(display, values),
+        progress,
+    )
+}
+
+fn write(display: &WrappedDisplay, values: &[(Control, u16)]) -> Applied {
+    let mut applied = Applied {
+        display: display.clone(),
+        written: vec![],
+        failed: vec![],
+    };
+    let mut pending = values.to_vec();
+    for round in 1..=ROUNDS {
+        let mut failed = vec![];
+        for (control, value) in pending {
+            match write_checked(display, control, value) {
+                Ok(()) => applied.written.push((control, value)),
+                Err(err) => failed.push((control, value, err)),
+            }
+        }
+        // A disconnected display won't take them in another round either
+        let gone = failed.iter().any(|(_, _, err)| matches!(err, Error::Gone));
+        if failed.is_empty() || gone || round == ROUNDS {
+            applied.failed = failed
+                .into_iter()
+                .map(|(control, _, err)| (control, err))
+                .collect();
+            break;
+        }
+        pending = failed
+            .into_iter()
+            .map(|(control, value, _)| (control, value))
+            .collect();
+    }
+    for (control, value) in &applied.written {
+        if let Some(controller) = display.controls.get(control) {
+            let mut controller = controller.write().unwrap();
+            controller.value = *value;
+            controller.failed = false;
+        }
+    }
+    applied
+}
+
+/// Writes the value and waits the gap the display needs before the next command, then reads
+/// continuous controls back. Discrete ones like the input source may not answer right after
+/// switching.
+fn write_checked(display: &WrappedDisplay, control: Control, value: u16) -> Result<(), Error> {
+    display.set(control, value)?;
+    thread::sleep(WRITE_GAP.max(Duration::from_millis(display.tuning.delay_ms)));
+    if !control.is_continuous() {
+        return Ok(());
+    }
+    match display.get(control)? {
+        read if read == value => Ok(()),
+        read => Err(Error::Ddc(anyhow!("wrote {value} but read back {read}"))),
+    }
+}
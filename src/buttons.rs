@@ -0,0 +1,56 @@
+//! The row of buttons at the bottom of the TUI, pressed with F1 to F12 or a click. A button does
+//! whatever it lists in the order of the fields: preset, set, toggle and then run.
+
+use std::collections::BTreeMap;
+
+use ddc_bright::display::Control;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Target, scene::Amount};
+
+/// Function keys there are, later buttons are only clicked
+pub const KEYS: usize = 12;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Button {
+    pub label: String,
+    /// Displays `set` and `toggle` go to
+    #[serde(default = "focused")]
+    pub target: Target,
+    /// Name of a preset to apply
+    pub preset: Option<String>,
+    /// Values to write, raw (`40`), percent (`"40%"`), dB (`"-20dB"`) or a value name
+    #[serde(default)]
+    pub set: BTreeMap<Control, Amount>,
+    /// Values each control goes through, the one after the current value each press
+    #[serde(default)]
+    pub toggle: BTreeMap<Control, Vec<Amount>>,
+    /// Command to start, split at whitespace without a shell
+    pub run: Option<String>,
+}
+
+fn focused() -> Target {
+    Target::Focused
+}
+
+/// Checks the values of every button.
+pub fn check(buttons: &[Button]) -> Result<(), String> {
+    for button in buttons {
+        let error = |err: String| format!("button {}: {err}", button.label);
+        for (control, amount) in &button.set {
+            amount.check(*control).map_err(error)?;
+        }
+        for (control, amounts) in &button.toggle {
+            if amounts.is_empty() {
+                return Err(error(format!(
+                    "no values to toggle {} through",
+                    control.key()
+                )));
+            }
+            for amount in amounts {
+                amount.check(*control).map_err(error)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use std::{fmt, io};
+
+use mccs_db::{Access, Database, ValueType};
+use serde::Serialize;
+
+/// What a display reports to support in its MCCS capabilities string
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The capabilities string as sent by the display
+    pub raw: String,
+    /// MCCS version as `(major, minor)`
+    pub mccs_version: Option<(u8, u8)>,
+    pub model: Option<String>,
+    /// What kind of display it says it is, like `lcd`
+    pub display_type: Option<String>,
+    pub features: Vec<Feature>,
+}
+
+/// A VCP feature code the display advertises
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct Feature {
+    pub code: u8,
+    /// Name from the capabilities string or the MCCS spec
+    pub name: Option<String>,
+    pub readable: bool,
+    pub writable: bool,
+    pub kind: FeatureKind,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum FeatureKind {
+    /// Any value up to `maximum`, if the display reported one
+    Continuous {
+        maximum: Option<u16>,
+    },
+    /// One of a fixed set of values, with their labels where known
+    Discrete {
+        values: Vec<(u8, Option<String>)>,
+    },
+    /// A byte array
+    Table,
+    Unknown,
+}
+
+impl Capabilities {
+    /// Parses a capabilities string, `maximum` looks up the range of continuous features.
+    pub fn parse(raw: &[u8], mut maximum: impl FnMut(u8) -> Option<u16>) -> io::Result<Self> {
+        let raw = normalize(raw);
+        // The parser's own error dumps the whole input
+        let mut caps = mccs_caps::parse_capabilities(&raw).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "malformed capabilities string")
+        })?;
+        if caps.mccs_version.is_none() {
+            caps.mccs_version = packed_version(&caps);
+        }
+        // Same as ddc-hi, without a version only the capabilities themselves are known.
+        let mut database = match &caps.mccs_version {
+            Some(version) => Database::from_version(version),
+            None => Database::default(),
+        };
+        database.apply_capabilities(&caps);
+
+        let features = caps
+            .vcp_features
+            .keys()
+            .filter_map(|&code| database.get(code))
+            .map(|descriptor| {
+                let readable = descriptor.access != Access::WriteOnly;
+                let kind = match &descriptor.ty {
+                    ValueType::Continuous { .. } => FeatureKind::Continuous {
+                        maximum: if readable {
+                            maximum(descriptor.code)
+                        } else {
+                            None
+                        },
+                    },
+                    ValueType::NonContinuous { values, .. } => FeatureKind::Discrete {
+                        values: values.iter().map(|(&v, name)| (v, name.clone())).collect(),
+                    },
+                    ValueType::Table { .. } => FeatureKind::Table,
+                    ValueType::Unknown => FeatureKind::Unknown,
+                };
+                // Names from vcpname() aren't carried over by the database
+                let name = caps.vcp_features[&descriptor.code].name.clone();
+                Feature {
+                    code: descriptor.code,
+                    name: name.or_else(|| descriptor.name.clone()),
+                    readable,
+                    writable: descriptor.access != Access::ReadOnly,
+                    kind,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            raw: String::from_utf8_lossy(&raw).into_owned(),
+            mccs_version: caps
+                .mccs_version
+                .map(|version| (version.major, version.minor)),
+            model: caps.model,
+            display_type: caps.ty.map(|ty| ty.to_string()),
+            features,
+        })
+    }
+
+    pub fn feature(&self, code: u8) -> Option<&Feature> {
+        self.features.iter().find(|feature| feature.code == code)
+    }
+}
+
+impl Feature {
+    /// Whether the code can be read and written, as `rw`, `r ` or ` w`.
+    pub fn access(&self) -> &'static str {
+        match (self.readable, self.writable) {
+            (true, true) => "rw",
+            (true, false) => "r ",
+            (false, true) => " w",
+            (false, false) => "  ",
+        }
+    }
+}
+
+/// The range or the allowed values, e.g. `0..100` or `0x01 VGA-1, 0x0f DisplayPort-1`.
+impl fmt::Display for FeatureKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FeatureKind::Continuous {
+                maximum: Some(maximum),
+            } => write!(f, "0..{maximum}"),
+            FeatureKind::Continuous { maximum: None } => write!(f, "continuous"),
+            FeatureKind::Discrete { values } => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|(value, name)| match name {
+                        Some(name) => format!("0x{value:02x} {name}"),
+                        None => format!("0x{value:02x}"),
+                    })
+                    .collect();
+                write!(f, "{}", values.join(", "))
+            }
+            FeatureKind::Table => write!(f, "table"),
+            FeatureKind::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The values the MCCS spec names for a non-continuous code, for displays that don't say.
+pub(crate) fn standard_values(code: u8) -> Vec<(u8, Option<String>)> {
+    let database = Database::from_version(&mccs::Version::new(2, 2));
+    match database.get(code).map(|descriptor| &descriptor.ty) {
+        Some(ValueType::NonContinuous { values, .. }) => {
+            values.iter().map(|(&v, name)| (v, name.clone())).collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// Works around vendor quirks the parser chokes on: trailing NULs, a doubled outer pair of
+/// parentheses and value lists nested inside the values of `vcp()`.
+fn normalize(raw: &[u8]) -> Vec<u8> {
+    let mut raw = raw;
+    while let [rest @ .., b'\0' | b' ' | b'\r' | b'\n'] = raw {
+        raw = rest;
+    }
+    while raw.starts_with(b"((") && raw.ends_with(b"))") {
+        raw = &raw[1..raw.len() - 1];
+    }
+
+    let lower = raw.to_ascii_lowercase();
+    let Some(start) = lower
+        .windows(4)
+        .position(|window| window == b"vcp(")
+        .map(|index| index + 4)
+    else {
+        return raw.to_vec();
+    };
+    let mut normalized = raw[..start].to_vec();
+    let mut depth = 1;
+    for (index, &byte) in raw.iter().enumerate().skip(start) {
+        let outer = depth;
+        match byte {
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            _ => (),
+        }
+        if depth == 0 {
+            normalized.extend_from_slice(&raw[index..]);
+            return normalized;
+        }
+        // Only keep one level of values per feature code, and "DC (00)" becomes "DC(00)"
+        let spaced = byte == b' ' && raw.get(index + 1) == Some(&b'(');
+        if outer <= 2 && depth <= 2 && !spaced {
+            normalized.push(byte);
+        }
+    }
+    raw.to_vec()
+}
+
+/// Reads versions written without a dot, like `mccs_ver(0201)`.
+fn packed_version(caps: &mccs::Capabilities) -> Option<mccs::Version> {
+    let tag = caps
+        .unknown_tags
+        .iter()
+        .find(|tag| tag.name == "mccs_ver")?;
+    let mccs::UnknownData::String(value) = &tag.data else {
+        return None;
+    };
+    if value.len() != 4 {
+        return None;
+    }
+    Some(mccs::Version::new(
+        value.get(..2)?.parse().ok()?,
+        value.get(2..)?.parse().ok()?,
+    ))
+}
@@ -0,0 +1,1504 @@
+#[cfg(feature = "daemon")]
+use std::sync::Arc;
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone};
+
+use clap::{Parser, Subcommand};
+use ddc_bright::{
+    bulk,
+    display::{Control, DisplayManager, MyDisplay, WrappedController, WrappedDisplay, POWER_MODE},
+    doctor,
+    edid::Edid,
+    orientation::Orientation,
+    power::PowerMode,
+    temperature,
+    units::{self, Adjust, Level},
+};
+
+#[cfg(feature = "daemon")]
+use crate::control;
+#[cfg(all(windows, feature = "service"))]
+use crate::service;
+use crate::{
+    ambient,
+    audio::{AudioConfig, Trigger},
+    audit,
+    backlight::{self, Direction},
+    bench,
+    config::Config,
+    events,
+    history::{Entry, Log, Origin},
+    probe, rotate,
+    scene::Scene,
+    schedule, state, statusline,
+};
+
+/// TUI app for changing brightness of displays
+#[derive(Parser)]
+#[command(version)]
+pub struct Args {
+    /// Seconds each backend may spend enumerating before remaining buses are skipped
+    #[arg(long, value_name = "SECS")]
+    pub enumeration_timeout: Option<u64>,
+
+    /// Run headless, keeping displays at the values in the [watchdog] config and applying the
+    /// [[schedule]]
+    #[cfg(feature = "daemon")]
+    #[arg(long, visible_alias = "daemon")]
+    pub watchdog: bool,
+
+    /// Run list, get or set on the daemon of another machine, through SSH
+    #[cfg(feature = "daemon")]
+    #[arg(long, value_name = "HOST")]
+    pub remote: Option<String>,
+
+    /// Pass requests on stdin to the running daemon, the remote end of --remote
+    #[cfg(feature = "daemon")]
+    #[arg(long, hide = true)]
+    pub relay: bool,
+
+    /// Run as the Windows service, started by the service control manager
+    #[cfg(all(windows, feature = "service"))]
+    #[arg(long, hide = true)]
+    pub service: bool,
+
+    /// Run as the root helper serving DDC access to unprivileged users on its socket, by default
+    /// /run/ddc_bright.sock. The config isn't read
+    #[cfg(all(unix, feature = "helper"))]
+    #[arg(
+        long,
+        value_name = "SOCKET",
+        num_args = 0..=1,
+        default_missing_value = ddc_bright::helper::DEFAULT_SOCKET
+    )]
+    pub helper: Option<PathBuf>,
+
+    /// Set the volume of every display and exit, same as `set --volume`
+    #[arg(long, value_name = "LEVEL", allow_hyphen_values = true)]
+    pub volume: Option<Adjust>,
+
+    /// Don't look for a newer release this time, even if update_check is set
+    #[arg(long)]
+    pub no_update_check: bool,
+
+    /// Log every DDC/CI transaction with the bytes sent and read back and how long it took
+    #[cfg(feature = "trace")]
+    #[arg(long)]
+    pub trace_ddc: bool,
+
+    /// Apply a preset saved from the TUI and exit
+    #[arg(long, value_name = "NAME", visible_alias = "profile")]
+    pub preset: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start the TUI, which also runs without a subcommand
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Run headless like --watchdog, keeping displays at the values in the [watchdog] config
+    /// and applying the [[schedule]]
+    #[cfg(feature = "daemon")]
+    Daemon,
+    /// Start the stub of a windowed interface: a native window of sliders on Windows, sliders
+    /// in the browser served from localhost elsewhere
+    #[cfg(feature = "gui")]
+    Gui {
+        /// Serve the sliders to the browser on Windows too
+        #[arg(long)]
+        browser: bool,
+    },
+    /// Read subcommands line by line from stdin, like `set --display 1 --brightness 40`, and
+    /// run them against displays loaded once
+    #[cfg(feature = "cli")]
+    Cli,
+    /// List the displays with their numbers
+    List {
+        /// Print every display with its identifiers and current values as JSON instead
+        #[cfg(feature = "json")]
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the current values of a display's controls
+    Get {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// Only print this control's raw value
+        control: Option<Control>,
+    },
+    /// Read every control of displays again, a few displays at a time, e.g. for a video wall
+    Refresh {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// Displays read at the same time
+        #[arg(long, default_value_t = 4)]
+        parallel: usize,
+    },
+    /// Set controls, as raw value, percent (40%) or dB (-20dB)
+    Set {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// Raw (`40`), percent (`40%`) or a step from the current value (`+10`, `-5%`)
+        #[arg(long, value_name = "LEVEL", allow_hyphen_values = true)]
+        brightness: Option<Adjust>,
+        #[arg(long, value_name = "LEVEL", allow_hyphen_values = true)]
+        contrast: Option<Adjust>,
+        /// Also in decibels (`-20dB`)
+        #[arg(long, value_name = "LEVEL", allow_hyphen_values = true)]
+        volume: Option<Adjust>,
+        /// Raw input select value, as listed by `capabilities`
+        #[arg(long, value_name = "VALUE")]
+        input: Option<u16>,
+        /// Colour temperature in Kelvin, from 2000 up to the neutral 6500, set through the
+        /// red, green and blue gains
+        #[arg(long, value_name = "KELVIN")]
+        temp: Option<u16>,
+        /// Controls and levels in pairs, like `brightness +10 contrast 75%`
+        #[arg(value_name = "CONTROL LEVEL", allow_hyphen_values = true)]
+        pairs: Vec<String>,
+    },
+    /// Switch displays to an input like a KVM switch, or list the inputs they advertise
+    Input {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// Name like dp1, "HDMI 2" or usb-c1, or the raw value
+        input: Option<String>,
+    },
+    /// Turn displays on, to standby or off, or show whether they are on
+    Power {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// on, standby or off
+        mode: Option<PowerMode>,
+    },
+    /// Measure how fast and reliably displays take writes by changing their brightness, and
+    /// recommend a delay and whether to verify writes
+    Bench {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// Store the recommendations in the quirk cache, used from then on
+        #[arg(long)]
+        save: bool,
+    },
+    /// Check why displays may not turn up: the i2c-dev module, access to the buses and whether
+    /// displays answer DDC/CI
+    Doctor,
+    /// Try the known controls displays don't advertise, many leave out some they have, and list
+    /// those that work
+    Probe {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// Store the controls found in the quirk cache, kept by refreshes from then on
+        #[arg(long)]
+        save: bool,
+    },
+    /// Print every readable setting of displays as TOML, to restore with `import`
+    Export {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+    },
+    /// Write the settings from an `export` back to the displays with the same serial number
+    Import {
+        /// TOML file written by `export`
+        file: PathBuf,
+        /// Restore on these displays instead, e.g. another one of the same model
+        #[arg(long)]
+        display: Option<String>,
+    },
+    /// Print the MCCS capabilities string of displays and what it advertises
+    Capabilities {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// Print the parsed capabilities as JSON instead
+        #[cfg(feature = "json")]
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the EDID of displays in hex and what it says: chromaticities, timings and serial
+    Edid {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// Write only the bytes as read, e.g. for `edid-decode`
+        #[arg(long, conflicts_with = "decoded")]
+        raw: bool,
+        /// Print only what it says
+        #[arg(long)]
+        decoded: bool,
+    },
+    /// Sample the light sensor in a dark, normal and bright room, asking for the preferred
+    /// brightness of each display each time, and store the fitted curves
+    Calibrate {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+    },
+    /// Print the latest value changes and what made them: tui, cli, hotkey, schedule, ambient,
+    /// watchdog, wake, backlight, scene or kvm
+    History {
+        /// Model name or id
+        #[arg(long)]
+        display: Option<String>,
+        /// Only changes made by this
+        #[arg(long)]
+        origin: Option<Origin>,
+        /// Number of changes printed, newest last
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print the audited writes, with who made them and the value each replaced
+    Audit {
+        /// Model name or id
+        #[arg(long)]
+        display: Option<String>,
+        /// Only writes since then: today, yesterday, a date like 2024-05-01 or an age like 24h
+        #[arg(long, value_name = "WHEN", value_parser = parse_since)]
+        since: Option<DateTime<Local>>,
+        /// Check that the chain is intact instead, from the first key
+        #[arg(long, conflicts_with_all = ["display", "since", "init"])]
+        verify: bool,
+        /// First key of the chain, as `--init` printed it, instead of the one in audit.seed
+        #[arg(long, value_name = "HEX", requires = "verify")]
+        key: Option<String>,
+        /// Start the chain with a new key and print the first key for keeping elsewhere
+        #[arg(long, conflicts_with_all = ["display", "since"])]
+        init: bool,
+    },
+    /// Print what happened: value changes, errors, hot-plugging and schedule triggers
+    Events {
+        #[command(subcommand)]
+        action: EventsAction,
+    },
+    /// Move the external displays one [backlight] step, and the internal backlight along if
+    /// together is set. Bind it to the brightness keys
+    BrightnessKey {
+        /// up or down
+        direction: Direction,
+    },
+    /// Print the values of displays on one line for status bars like waybar, i3blocks or
+    /// polybar, stepping the brightness first for a scroll
+    Statusline {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// Line per display, with {name}, {number}, {id} and control names like {brightness}
+        #[arg(long, default_value = statusline::DEFAULT_FORMAT)]
+        format: String,
+        /// Between the lines of the displays
+        #[arg(long, default_value = "  ")]
+        separator: String,
+        /// Print waybar's JSON with a tooltip and the mean brightness instead
+        #[cfg(feature = "json")]
+        #[arg(long)]
+        json: bool,
+        /// Keep running, reading the values again this often, e.g. 5s, and printing a new line
+        /// when they change
+        #[arg(long, value_name = "INTERVAL", value_parser = parse_age)]
+        watch: Option<Duration>,
+        /// Step the brightness up or down first, also taken from i3blocks' BLOCK_BUTTON
+        #[arg(long)]
+        scroll: Option<Direction>,
+        /// Percent the brightness moves per scroll
+        #[arg(long, default_value_t = 5)]
+        step: u16,
+        /// Print a waybar module running this with scroll handlers instead
+        #[cfg(feature = "json")]
+        #[arg(long)]
+        module: bool,
+    },
+    /// Run the steps of a scene file: presets, values to set or fade to, commands and waits
+    Run {
+        /// TOML file with [[step]] tables
+        scene: PathBuf,
+    },
+    /// Install, remove or query the Windows service running the watchdog
+    #[cfg(all(windows, feature = "service"))]
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Inspect the preset schedule
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleAction,
+    },
+    /// Read or write any VCP code, including ones the display doesn't advertise, printing the
+    /// current and maximum value it answers with
+    Vcp {
+        /// Number from `list`, model name or id, every display if unset
+        #[arg(long)]
+        display: Option<String>,
+        /// VCP code in hex, like 10 or 0x10
+        #[arg(value_parser = units::parse_code)]
+        code: u8,
+        /// Value to write, in decimal or hex like 0x11, only read if unset
+        #[arg(value_parser = units::parse_raw, requires = "display")]
+        value: Option<u16>,
+    },
+    /// Show which way displays are turned, or turn one, rotating the desktop along if
+    /// [orientation] sync_desktop is set
+    Orientation {
+        /// Model name or id, every display if unset
+        display: Option<String>,
+        /// landscape, portrait, landscape-flipped or portrait-flipped
+        #[arg(requires = "display")]
+        orientation: Option<Orientation>,
+    },
+}
+
+#[cfg(all(windows, feature = "service"))]
+#[derive(Subcommand)]
+pub enum ServiceAction {
+    /// Register the service to start with Windows, needs an administrator
+    Install,
+    /// Stop and remove the service, needs an administrator
+    Uninstall,
+    /// Ask the running service how it is doing
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum ScheduleAction {
+    /// List the [[schedule]] rules with the next time each triggers
+    List,
+    /// Print what the rules would change on each display over a period, without writing
+    Simulate {
+        /// Start as HH:MM today, now if unset
+        #[arg(long, value_parser = parse_clock)]
+        from: Option<NaiveTime>,
+        /// End as HH:MM, the next day if it is before the start
+        #[arg(long, value_parser = parse_clock)]
+        to: NaiveTime,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum EventsAction {
+    /// Print every logged event, oldest first
+    Export {
+        /// Only events newer than this, e.g. 30m, 24h or 7d
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        since: Option<Duration>,
+        /// Print the events as JSON instead
+        #[cfg(feature = "json")]
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// The time `age` ago.
+fn cutoff(age: Option<Duration>) -> Result<Option<DateTime<Local>>, Box<dyn Error>> {
+    match age {
+        Some(age) => Ok(Some(Local::now() - chrono::Duration::from_std(age)?)),
+        None => Ok(None),
+    }
+}
+
+/// Parses an age like `90s`, `30m`, `24h` or `7d`.
+/// The start of today or yesterday, of a date, or the time an age ago.
+fn parse_since(text: &str) -> Result<DateTime<Local>, String> {
+    let today = Local::now().date_naive();
+    let date = match text {
+        "today" => Some(today),
+        "yesterday" => today.pred_opt(),
+        text => NaiveDate::parse_from_str(text, "%Y-%m-%d").ok(),
+    };
+    match date {
+        Some(date) => Local
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            .ok_or_else(|| format!("{text:?} starts in a DST gap")),
+        None => {
+            let age = parse_age(text)
+                .map_err(|_| format!("{text:?} is not today, yesterday, a date or an age"))?;
+            Ok(Local::now() - age)
+        }
+    }
+}
+
+fn parse_clock(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text, "%H:%M")
+        .map_err(|_| format!("{text:?} is not a time like 18:00"))
+}
+
+fn parse_age(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("{text:?} is not an age like 30m, 24h or 7d");
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(Duration::from_secs(number * seconds))
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print the effective configuration with defaults and command line overrides applied
+    Print,
+}
+
+/// Runs the subcommands read from stdin one per line until it ends or one says `exit`, against
+/// the displays of `manager`. Blank lines and those starting with `#` are skipped, words may be
+/// quoted. A failing line only stops the script from exiting successfully.
+#[cfg(feature = "cli")]
+pub fn shell(config: &Config, manager: &mut DisplayManager) -> Result<(), Box<dyn Error>> {
+    use std::io::{BufRead, IsTerminal};
+
+    let prompt = io::stdin().is_terminal();
+    let (mut ran, mut failed) = (0, 0);
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if prompt {
+            print!("> ");
+            io::stdout().flush()?;
+        }
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "exit" || line == "quit" {
+            break;
+        }
+        ran += 1;
+        let done = shell_words(line).and_then(|words| {
+            let args = Args::try_parse_from(["ddc_bright".to_string()].into_iter().chain(words))
+                .map_err(|err| err.render().to_string().trim_end().to_string())?;
+            let command = args.command.ok_or("a subcommand, like list")?;
+            run(command, config, || Ok(&mut *manager)).map_err(|err| err.to_string())
+        });
+        if let Err(err) = done {
+            failed += 1;
+            eprintln!("{line}: {err}");
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(format!("{failed} of {ran} commands failed").into()),
+    }
+}
+
+/// The words of a line, split at whitespace outside of double or single quotes.
+#[cfg(feature = "cli")]
+fn shell_words(line: &str) -> Result<Vec<String>, String> {
+    let (mut words, mut word, mut quote) = (vec![], None::<String>, None);
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("a quote isn't closed".to_string());
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Runs a subcommand, `load` enumerates the displays for the commands that need them.
+pub fn run<'a>(
+    command: Command,
+    config: &Config,
+    load: impl FnOnce() -> Result<&'a mut DisplayManager, Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    // A running daemon answers without enumerating the displays again
+    #[cfg(feature = "daemon")]
+    if let Some(words) = request(&command)? {
+        let endpoint = control::endpoint(config.control_socket.as_ref());
+        if let Some(result) = control::forward(&endpoint, &words) {
+            return result;
+        }
+    }
+    match command {
+        // Started as the frontend before any command runs
+        #[cfg(feature = "tui")]
+        Command::Tui => Err("the TUI can't be started from here".into()),
+        #[cfg(feature = "daemon")]
+        Command::Daemon => Err("the daemon can't be started from here".into()),
+        #[cfg(feature = "gui")]
+        Command::Gui { .. } => Err("the GUI can't be started from here".into()),
+        #[cfg(feature = "cli")]
+        Command::Cli => Err("the command line can't be started from here".into()),
+        Command::Config {
+            action: ConfigAction::Print,
+        } => {
+            print!("{}", config.print()?);
+            Ok(())
+        }
+        #[cfg(all(windows, feature = "service"))]
+        Command::Service { action } => match action {
+            ServiceAction::Install => service::install(),
+            ServiceAction::Uninstall => service::uninstall(),
+            ServiceAction::Status => service::status(),
+        },
+        Command::Schedule {
+            action: ScheduleAction::List,
+        } => {
+            schedule::list(config);
+            Ok(())
+        }
+        Command::Schedule {
+            action: ScheduleAction::Simulate { from, to },
+        } => schedule::simulate(config, &*load()?, from, to, &mut io::stdout()),
+        Command::Orientation {
+            display,
+            orientation,
+        } => rotate::run(
+            &*load()?,
+            &config.orientation,
+            display.as_deref(),
+            orientation,
+        ),
+        #[cfg(feature = "json")]
+        Command::List { json: true } => {
+            let snapshots: Vec<_> = load()?
+                .displays
+                .iter()
+                .map(|display| display.snapshot())
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&snapshots)?);
+            Ok(())
+        }
+        Command::List { .. } => {
+            list(&load()?.displays, &mut io::stdout())?;
+            Ok(())
+        }
+        Command::Bench { display, save } => {
+            let manager = load()?;
+            bench::run(&select(&manager.displays, display.as_deref())?, save)
+        }
+        Command::Doctor => {
+            let manager = load()?;
+            doctor(&manager.displays, &mut io::stdout())
+        }
+        Command::Probe { display, save } => {
+            let manager = load()?;
+            probe::run(&select(&manager.displays, display.as_deref())?, save)
+        }
+        Command::Export { display } => {
+            let manager = load()?;
+            state::export(
+                &select(&manager.displays, display.as_deref())?,
+                &mut io::stdout(),
+            )
+        }
+        Command::Import { file, display } => {
+            let manager = load()?;
+            let targets = display
+                .map(|query| select(&manager.displays, Some(&query)))
+                .transpose()?;
+            state::import(&manager.displays, &file, targets.as_deref())
+        }
+        #[cfg(feature = "json")]
+        Command::Capabilities {
+            display,
+            json: true,
+        } => capabilities_json(&select(&load()?.displays, display.as_deref())?),
+        Command::Capabilities { display, .. } => {
+            capabilities(&select(&load()?.displays, display.as_deref())?)
+        }
+        Command::Edid {
+            display,
+            raw,
+            decoded,
+        } => edid(
+            &select(&load()?.displays, display.as_deref())?,
+            raw,
+            decoded,
+        ),
+        Command::Calibrate { display } => ambient::calibrate(
+            &select(&load()?.displays, display.as_deref())?,
+            &config.ambient,
+        ),
+        Command::History {
+            display,
+            origin,
+            limit,
+        } => history(display.as_deref(), origin, limit),
+        Command::Audit { init: true, .. } => {
+            let key = audit::begin()?;
+            println!("{key}");
+            eprintln!(
+                "The first key of the audit chain. Keep it off this machine, \
+                audit --verify --key checks the log with it"
+            );
+            Ok(())
+        }
+        Command::Audit {
+            verify: true, key, ..
+        } => {
+            let verified = audit::verify(key.as_deref())?;
+            println!("{} audited writes, the chain is intact", verified.checked);
+            if verified.local {
+                eprintln!(
+                    "Checked with the first key in audit.seed, which anyone who can rewrite the \
+                    log can rewrite too. Move it off the machine and pass it with --key"
+                );
+            }
+            Ok(())
+        }
+        Command::Audit { display, since, .. } => {
+            for record in audit::read()?
+                .into_iter()
+                .filter(|record| since.is_none_or(|since| record.time >= since))
+                .filter(|record| {
+                    (display.as_deref())
+                        .is_none_or(|query| record.id == query || record.display == query)
+                })
+            {
+                println!("{record}");
+            }
+            Ok(())
+        }
+        #[cfg(feature = "json")]
+        Command::Events {
+            action: EventsAction::Export { since, json: true },
+        } => {
+            let events = events::export(cutoff(since)?)?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&events::to_json(&events))?
+            );
+            Ok(())
+        }
+        Command::Events {
+            action: EventsAction::Export { since, .. },
+        } => {
+            let events = events::export(cutoff(since)?)?;
+            if events.is_empty() {
+                println!("No events logged");
+            }
+            for event in &events {
+                println!("{event}");
+            }
+            Ok(())
+        }
+        Command::BrightnessKey { direction } => backlight::key(
+            &*load()?,
+            &config.backlight,
+            direction,
+            &config.notifications,
+        ),
+        #[cfg(feature = "json")]
+        Command::Statusline {
+            display,
+            format,
+            module: true,
+            ..
+        } => statusline::module(display.as_deref(), &format),
+        Command::Statusline {
+            display,
+            format,
+            separator,
+            #[cfg(feature = "json")]
+            json,
+            watch,
+            scroll,
+            step,
+            ..
+        } => {
+            let manager = load()?;
+            let displays = select(&manager.displays, display.as_deref())?;
+            if let Some(direction) = scroll.or_else(statusline::clicked) {
+                statusline::scroll(&displays, direction, step)?;
+            }
+            let options = statusline::Options {
+                format: &format,
+                separator: &separator,
+                #[cfg(feature = "json")]
+                json,
+                watch: watch.map(|interval| interval.max(Duration::from_secs(1))),
+            };
+            statusline::run(&manager.displays, &displays, &options)
+        }
+        Command::Run { scene } => {
+            let scene = Scene::load(&scene)?;
+            scene.run(config, load()?)
+        }
+        Command::Vcp {
+            display,
+            code,
+            value,
+        } => vcp(&select(&load()?.displays, display.as_deref())?, code, value),
+        Command::Get { display, control } => {
+            let displays = select(&load()?.displays, display.as_deref())?;
+            get(&displays, control, &mut io::stdout(), &mut io::stderr())
+        }
+        Command::Refresh { display, parallel } => {
+            let displays = select(&load()?.displays, display.as_deref())?;
+            refresh(&displays, parallel, &mut io::stdout(), &mut io::stderr())
+        }
+        Command::Input { display, input } => match input {
+            Some(name) => switch_input(&*load()?, display.as_deref(), &name, &config.audio),
+            None => inputs(&*load()?, display.as_deref()),
+        },
+        Command::Power { display, mode } => match mode {
+            Some(mode) => set_power(&*load()?, display.as_deref(), mode, &config.audio),
+            None => power(&*load()?, display.as_deref()),
+        },
+        Command::Set {
+            display,
+            brightness,
+            contrast,
+            volume,
+            input,
+            temp,
+            pairs,
+        } => {
+            let levels = levels(brightness, contrast, volume, input, temp, &pairs)?;
+            let displays = select(&load()?.displays, display.as_deref())?;
+            set(&displays, &levels, &config.audio, &mut io::stderr())
+        }
+    }
+}
+
+/// The levels `set` writes for its options and pairs.
+fn levels(
+    brightness: Option<Adjust>,
+    contrast: Option<Adjust>,
+    volume: Option<Adjust>,
+    input: Option<u16>,
+    temp: Option<u16>,
+    pairs: &[String],
+) -> Result<Vec<(Control, Adjust)>, Box<dyn Error>> {
+    let mut levels: Vec<(Control, Adjust)> = [
+        (Control::Brightness, brightness),
+        (Control::Contrast, contrast),
+        (Control::Volume, volume),
+        (
+            Control::InputSource,
+            input.map(|input| Level::Raw(input).into()),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(control, level)| Some((control, level?)))
+    .collect();
+    if let Some(kelvin) = temp {
+        if !(temperature::MIN..=temperature::NEUTRAL).contains(&kelvin) {
+            return Err(format!(
+                "--temp must be from {} to {} K",
+                temperature::MIN,
+                temperature::NEUTRAL
+            )
+            .into());
+        }
+        let gains = temperature::levels(kelvin).into_iter();
+        levels.extend(gains.map(|(control, level)| (control, level.into())));
+    }
+    if !pairs.len().is_multiple_of(2) {
+        return Err("set takes pairs of a control and a level, like brightness +10".into());
+    }
+    for pair in pairs.chunks(2) {
+        levels.push((pair[0].parse()?, pair[1].parse()?));
+    }
+    if levels.is_empty() {
+        return Err("nothing to set, pass e.g. --brightness 40".into());
+    }
+    Ok(levels)
+}
+
+/// The control socket request doing the same as `command`, `None` for commands the daemon
+/// doesn't take.
+#[cfg(feature = "daemon")]
+fn request(command: &Command) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+    let display =
+        |display: &Option<String>| display.as_ref().map(|query| format!("display={query}"));
+    let words = match command {
+        #[cfg(feature = "json")]
+        Command::List { json: true } => return Ok(None),
+        Command::List { .. } => vec!["list".to_string()],
+        Command::Get {
+            display: query,
+            control,
+        } => {
+            let mut words = vec!["get".to_string()];
+            words.extend(display(query));
+            words.extend(control.map(|control| control.key()));
+            words
+        }
+        Command::Refresh {
+            display: query,
+            parallel,
+        } => {
+            let mut words = vec!["refresh".to_string()];
+            words.extend(display(query));
+            words.push(parallel.to_string());
+            words
+        }
+        Command::Set {
+            display: query,
+            brightness,
+            contrast,
+            volume,
+            input,
+            temp,
+            pairs,
+        } => {
+            let mut words = vec!["set".to_string()];
+            words.extend(display(query));
+            let levels = levels(*brightness, *contrast, *volume, *input, *temp, pairs)?;
+            for (control, level) in levels {
+                words.extend([control.key(), level.to_string()]);
+            }
+            words
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(words))
+}
+
+/// Runs a command on the daemon of another machine, over SSH.
+#[cfg(feature = "daemon")]
+pub fn remote(host: &str, command: Command) -> Result<(), Box<dyn Error>> {
+    let words = request(&command)?.ok_or("only list, get, refresh and set work with --remote")?;
+    control::remote(host, &words)
+}
+
+fn history(
+    display: Option<&str>,
+    origin: Option<Origin>,
+    limit: usize,
+) -> Result<(), Box<dyn Error>> {
+    let entries: Vec<Entry> = Log::read()?
+        .into_iter()
+        .filter(|entry| origin.is_none_or(|origin| entry.origin == origin))
+        .filter(|entry| display.is_none_or(|query| entry.id == query || entry.display == query))
+        .collect();
+    if entries.is_empty() {
+        println!("No changes logged");
+    }
+    for entry in &entries[entries.len().saturating_sub(limit)..] {
+        println!("{entry}");
+    }
+    Ok(())
+}
+
+/// Switches the default audio output following a display, reporting what happened.
+/// Prints the inputs of displays, marking the current one.
+fn inputs(manager: &DisplayManager, display: Option<&str>) -> Result<(), Box<dyn Error>> {
+    for display in select(&manager.displays, display)? {
+        let current = display.get(Control::InputSource).ok();
+        println!("{}", display.label());
+        let allowed = display.allowed_values(Control::InputSource);
+        if allowed.is_empty() {
+            println!("  no inputs advertised, use raw values or MCCS names like dp1");
+        }
+        for (value, _) in allowed {
+            let marker = if current == Some(value) { '*' } else { ' ' };
+            println!(
+                "{marker} 0x{value:02x} {}",
+                display.describe(Control::InputSource, value)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Switches displays to the input named, each by its own value for the name.
+fn switch_input(
+    manager: &DisplayManager,
+    display: Option<&str>,
+    name: &str,
+    audio: &AudioConfig,
+) -> Result<(), Box<dyn Error>> {
+    let mut failed = false;
+    let mut history = Log::default();
+    for display in select(&manager.displays, display)? {
+        let Some(controller) = display.controls.get(&Control::InputSource) else {
+            eprintln!("{}: no input source control", display.label());
+            failed = true;
+            continue;
+        };
+        let Some(value) = display.value_named(Control::InputSource, name) else {
+            eprintln!(
+                "{}: no input named {name}, see `ddc_bright input`",
+                display.label()
+            );
+            failed = true;
+            continue;
+        };
+        match display.set(Control::InputSource, value) {
+            Ok(()) => {
+                controller.write().unwrap().value = value;
+                history.record(Origin::Cli, &display, Control::InputSource as u8, value);
+                println!(
+                    "{}: {}",
+                    display.label(),
+                    display.describe(Control::InputSource, value)
+                );
+                switch_audio(audio, &display, Trigger::Input(value));
+            }
+            Err(err) => {
+                eprintln!("{}: {err}", display.label());
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return Err(format!("not every display switched to {name}").into());
+    }
+    Ok(())
+}
+
+fn power(manager: &DisplayManager, display: Option<&str>) -> Result<(), Box<dyn Error>> {
+    for display in select(&manager.displays, display)? {
+        match display.power() {
+            Ok(Some(mode)) => println!("{}: {mode}", display.label()),
+            Ok(None) => println!("{}: unknown", display.label()),
+            // Displays that are off often don't answer at all
+            Err(err) => println!("{}: not answering, likely off ({err})", display.label()),
+        }
+    }
+    Ok(())
+}
+
+/// Reads or writes a VCP code of displays, printing what each answers with.
+fn vcp(displays: &[WrappedDisplay], code: u8, value: Option<u16>) -> Result<(), Box<dyn Error>> {
+    let mut failed = false;
+    let mut history = Log::default();
+    for display in displays {
+        let result = match value {
+            Some(value) => display.set_raw(code, value),
+            None => display.get_raw(code),
+        };
+        match result {
+            Ok((current, maximum)) => {
+                if value.is_some() {
+                    history.record(Origin::Cli, display, code, current);
+                }
+                println!(
+                    "{}: 0x{code:02x} = {current} (0x{current:02x}), maximum {maximum}",
+                    display.label()
+                );
+            }
+            Err(err) => {
+                eprintln!("{}: 0x{code:02x}: {err}", display.label());
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return Err("not every display answered".into());
+    }
+    Ok(())
+}
+
+/// Turns displays on or off. Turning one off switches the audio output like the display
+/// powering off by itself would.
+fn set_power(
+    manager: &DisplayManager,
+    display: Option<&str>,
+    mode: PowerMode,
+    audio: &AudioConfig,
+) -> Result<(), Box<dyn Error>> {
+    let mut failed = false;
+    let mut history = Log::default();
+    for display in select(&manager.displays, display)? {
+        if let Err(err) = display.set_power(mode) {
+            eprintln!("{}: {err}", display.label());
+            failed |= !display.is_lenient();
+            continue;
+        }
+        history.record(Origin::Cli, &display, POWER_MODE, mode.to_vcp());
+        println!("{}: {mode}", display.label());
+        if mode != PowerMode::On {
+            switch_audio(audio, &display, Trigger::Off);
+        }
+    }
+    if failed {
+        return Err(format!("not every display turned {mode}").into());
+    }
+    Ok(())
+}
+
+pub fn switch_audio(audio: &AudioConfig, display: &MyDisplay, trigger: Trigger) {
+    match audio.switch(display, trigger) {
+        Ok(Some(sink)) => println!("{}: audio output switched to {sink}", display.label()),
+        Ok(None) => {}
+        Err(err) => eprintln!("{}: switching audio output failed: {err}", display.label()),
+    }
+}
+
+/// Displays picked by their number in `list`, model name or id, or every display.
+pub fn select(
+    displays: &[WrappedDisplay],
+    query: Option<&str>,
+) -> Result<Vec<WrappedDisplay>, String> {
+    let Some(query) = query else {
+        return Ok(displays.to_vec());
+    };
+    let selected: Vec<WrappedDisplay> = match query.parse::<usize>() {
+        Ok(number) => displays
+            .get(number.wrapping_sub(1))
+            .cloned()
+            .into_iter()
+            .collect(),
+        Err(_) => displays
+            .iter()
+            .filter(|display| display.matches(query))
+            .cloned()
+            .collect(),
+    };
+    if selected.is_empty() {
+        return Err(format!("no display matches {query}"));
+    }
+    Ok(selected)
+}
+
+/// Prints each check, with what to do about those that failed.
+fn doctor(displays: &[WrappedDisplay], out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+    for check in doctor::run(displays) {
+        match check.result {
+            Ok(found) => writeln!(out, "{}: ok, {found}", check.name)?,
+            Err(problem) => {
+                failed += 1;
+                writeln!(out, "{}: {}", check.name, problem.cause)?;
+                writeln!(out, "  {}", problem.fix)?;
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err("a check failed".into()),
+    }
+}
+
+fn capabilities(displays: &[WrappedDisplay]) -> Result<(), Box<dyn Error>> {
+    let mut failed = false;
+    for display in displays {
+        println!("{} ({})", display.label(), display.id);
+        let capabilities = match display.capabilities() {
+            Ok(capabilities) => capabilities,
+            Err(err) => {
+                eprintln!("{}: {err}", display.label());
+                failed = true;
+                continue;
+            }
+        };
+        println!("raw: {}", capabilities.raw);
+        if let Some((major, minor)) = capabilities.mccs_version {
+            println!("MCCS version: {major}.{minor}");
+        }
+        if let Some(model) = &capabilities.model {
+            println!("model: {model}");
+        }
+        if let Some(display_type) = &capabilities.display_type {
+            println!("type: {display_type}");
+        }
+        for feature in &capabilities.features {
+            println!(
+                "  0x{:02x} {} {:<28} {}",
+                feature.code,
+                feature.access(),
+                feature.name.as_deref().unwrap_or(""),
+                feature.kind
+            );
+        }
+        println!();
+    }
+    if failed {
+        return Err("not every display sent its capabilities".into());
+    }
+    Ok(())
+}
+
+/// Writes the EDID of each display: the bytes as they are with `raw`, otherwise in hex unless
+/// only `decoded` is asked for, followed by what it says.
+fn edid(displays: &[WrappedDisplay], raw: bool, decoded: bool) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    let mut failed = false;
+    for display in displays {
+        let Some(bytes) = &display.edid else {
+            eprintln!(
+                "{}: the {} backend has no EDID",
+                display.label(),
+                display.backend
+            );
+            failed = true;
+            continue;
+        };
+        if raw {
+            out.write_all(bytes)?;
+            continue;
+        }
+        writeln!(out, "{} ({})", display.label(), display.id)?;
+        if !decoded {
+            for line in bytes.chunks(16) {
+                let hex: Vec<String> = line.iter().map(|byte| format!("{byte:02x}")).collect();
+                writeln!(out, "  {}", hex.join(" "))?;
+            }
+        }
+        match Edid::decode(bytes) {
+            Ok(edid) => describe_edid(&mut out, &edid)?,
+            Err(err) => {
+                eprintln!("{}: {err}", display.label());
+                failed = true;
+            }
+        }
+        writeln!(out)?;
+    }
+    if failed {
+        return Err("not every display has an EDID that could be read".into());
+    }
+    Ok(())
+}
+
+fn describe_edid(out: &mut impl Write, edid: &Edid) -> io::Result<()> {
+    if let Some(name) = &edid.name {
+        writeln!(out, "name: {name}")?;
+    }
+    write!(
+        out,
+        "manufacturer: {}, product 0x{:04X}",
+        edid.manufacturer, edid.product
+    )?;
+    match (&edid.serial_text, edid.serial) {
+        (Some(serial), _) => writeln!(out, ", serial {serial}")?,
+        (None, Some(serial)) => writeln!(out, ", serial {serial}")?,
+        (None, None) => writeln!(out)?,
+    }
+    match edid.week {
+        Some(week) => writeln!(out, "made: week {week} of {}", edid.year)?,
+        None => writeln!(out, "model year: {}", edid.year)?,
+    }
+    let (major, minor) = edid.version;
+    let input = if edid.digital { "digital" } else { "analog" };
+    write!(out, "version: {major}.{minor}, {input}")?;
+    match edid.size {
+        Some((width, height)) => writeln!(out, ", {width} x {height} cm")?,
+        None => writeln!(out)?,
+    }
+    let points: Vec<String> = ["red", "green", "blue", "white"]
+        .iter()
+        .zip(edid.chromaticity)
+        .map(|(point, (x, y))| format!("{point} {x:.3}, {y:.3}"))
+        .collect();
+    writeln!(out, "chromaticity: {}", points.join("; "))?;
+    for (index, timing) in edid.detailed.iter().enumerate() {
+        let kind = if index == 0 { "preferred" } else { "detailed" };
+        writeln!(
+            out,
+            "{kind}: {}x{} @ {:.2} Hz, {:.2} MHz",
+            timing.width,
+            timing.height,
+            timing.refresh,
+            timing.clock as f32 / 1000.0
+        )?;
+    }
+    let standard: Vec<String> = (edid.standard.iter())
+        .map(|(width, height, refresh)| format!("{width}x{height}@{refresh}"))
+        .collect();
+    if !standard.is_empty() {
+        writeln!(out, "standard: {}", standard.join(", "))?;
+    }
+    if !edid.established.is_empty() {
+        writeln!(out, "established: {}", edid.established.join(", "))?;
+    }
+    if edid.extensions > 0 {
+        writeln!(out, "extension blocks: {}", edid.extensions)?;
+    }
+    if !edid.checksum {
+        writeln!(out, "checksum: wrong")?;
+    }
+    Ok(())
+}
+
+/// Prints one object per display, with `capabilities` or the `error` reading them.
+#[cfg(feature = "json")]
+fn capabilities_json(displays: &[WrappedDisplay]) -> Result<(), Box<dyn Error>> {
+    use serde_json::json;
+
+    let mut failed = false;
+    let entries: Vec<_> = displays
+        .iter()
+        .map(|display| match display.capabilities() {
+            Ok(capabilities) => json!({
+                "name": display.name,
+                "id": display.id,
+                "capabilities": capabilities,
+            }),
+            Err(err) => {
+                failed = true;
+                json!({ "name": display.name, "id": display.id, "error": err.to_string() })
+            }
+        })
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&entries)?);
+    if failed {
+        return Err("not every display sent its capabilities".into());
+    }
+    Ok(())
+}
+
+/// Prints the values to `out` and every failed read to `warn`.
+/// Prints the number, name and id of each display, with its connector and mode where known.
+pub fn list(displays: &[WrappedDisplay], out: &mut impl Write) -> io::Result<()> {
+    for (i, display) in displays.iter().enumerate() {
+        let name = match display.alias() {
+            Some(alias) => format!("{alias} [{}]", display.name),
+            None => display.name.clone(),
+        };
+        let output = display
+            .output
+            .as_ref()
+            .map(|output| format!(" {output}"))
+            .unwrap_or_default();
+        writeln!(out, "{}: {name} ({}){output}", i + 1, display.id)?;
+    }
+    Ok(())
+}
+
+/// Reads every control of the displays again, `parallel` displays at a time, printing each one
+/// as it is done.
+pub fn refresh(
+    displays: &[WrappedDisplay],
+    parallel: usize,
+    out: &mut impl Write,
+    warn: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let mut done = 0;
+    let mut failed = false;
+    let mut report = |reread: &bulk::Reread| -> io::Result<()> {
+        done += 1;
+        let (display, read) = (&reread.display, reread.read);
+        let total = read + reread.failed.len();
+        let count = match reread.failed.is_empty() {
+            true => total.to_string(),
+            false => format!("{read} of {total}"),
+        };
+        let label = display.label();
+        writeln!(
+            out,
+            "[{done}/{}] {label}: {count} values read",
+            displays.len()
+        )?;
+        for (control, err) in &reread.failed {
+            writeln!(warn, "{label}: {}: {err}", control.get_name())?;
+        }
+        failed |= !reread.failed.is_empty() && !display.is_lenient();
+        Ok(())
+    };
+    let mut written = Ok(());
+    bulk::reread(displays, parallel, |reread| {
+        if written.is_ok() {
+            written = report(reread);
+        }
+    });
+    written?;
+    writeln!(
+        out,
+        "Read {} displays in {:.1}s",
+        displays.len(),
+        started.elapsed().as_secs_f32()
+    )?;
+    if failed {
+        return Err("not every value could be read".into());
+    }
+    Ok(())
+}
+
+pub fn get(
+    displays: &[WrappedDisplay],
+    control: Option<Control>,
+    out: &mut impl Write,
+    warn: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let mut failed = false;
+    for display in displays {
+        if let Some(control) = control {
+            match display.get(control) {
+                // A single value stays bare, so scripts can use the output as is
+                Ok(value) if displays.len() == 1 => writeln!(out, "{value}")?,
+                Ok(value) => writeln!(out, "{}: {value}", display.label())?,
+                Err(err) => {
+                    writeln!(warn, "{}: {err}", display.label())?;
+                    failed = true;
+                }
+            }
+            continue;
+        }
+        let mut values = vec![];
+        for (control, controller) in &display.controls {
+            let maximum = controller.read().unwrap().maximum;
+            match display.get(*control) {
+                Ok(value) if control.is_continuous() => {
+                    values.push(format!("{}={value}/{maximum}", control.key()))
+                }
+                Ok(value) => values.push(format!(
+                    "{}={}",
+                    control.key(),
+                    display.describe(*control, value).replace(' ', "_")
+                )),
+                Err(err) => {
+                    writeln!(warn, "{}: {}: {err}", display.label(), control.get_name())?;
+                    failed = true;
+                }
+            }
+        }
+        values.sort();
+        writeln!(out, "{}: {}", display.label(), values.join(" "))?;
+    }
+    if failed {
+        return Err("not every value could be read".into());
+    }
+    Ok(())
+}
+
+/// Writes the levels to the displays right away, reporting every failed write to `warn`.
+pub fn set(
+    displays: &[WrappedDisplay],
+    levels: &[(Control, Adjust)],
+    audio: &AudioConfig,
+    warn: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let mut set = 0;
+    let mut failed = false;
+    let mut history = Log::default();
+    check_steps(levels)?;
+    for display in displays {
+        for (control, level) in levels {
+            let Some(controller) = display.controls.get(control) else {
+                continue;
+            };
+            set += 1;
+            let value = target(display, controller, *level);
+            match display.set(*control, value) {
+                Ok(()) => {
+                    controller.write().unwrap().value = value;
+                    history.record(Origin::Cli, display, *control as u8, value);
+                    if *control == Control::InputSource {
+                        switch_audio(audio, display, Trigger::Input(value));
+                    }
+                }
+                Err(err) => {
+                    writeln!(warn, "{}: {}: {err}", display.label(), control.get_name())?;
+                    failed |= !display.is_lenient();
+                }
+            }
+        }
+    }
+    if failed {
+        return Err("not every value could be written".into());
+    }
+    if set == 0 {
+        return Err("no selected display has any of the controls".into());
+    }
+    Ok(())
+}
+
+/// Queues the levels on the manager like [`set`] writes them, for the daemon, and waits until
+/// they are written. Changes left waiting for a display to answer count as failed.
+#[cfg(feature = "daemon")]
+pub fn queue(
+    manager: &DisplayManager,
+    displays: &[WrappedDisplay],
+    levels: &[(Control, Adjust)],
+    audio: &AudioConfig,
+    origin: Origin,
+    warn: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    check_steps(levels)?;
+    let mut queued = vec![];
+    for display in displays {
+        for (control, level) in levels {
+            let Some(controller) = display.controls.get(control) else {
+                continue;
+            };
+            let value = target(display, controller, *level);
+            manager.queue_set(display.clone(), controller.clone(), value);
+            queued.push((display, *control, value));
+        }
+    }
+    if queued.is_empty() {
+        return Err("no selected display has any of the controls".into());
+    }
+    manager.flush();
+    let parked = manager.queued();
+    let mut failed = false;
+    let mut history = Log::default();
+    for (display, control, value) in queued {
+        let waiting = parked
+            .iter()
+            .any(|change| Arc::ptr_eq(&change.display, display) && change.control == control);
+        match (waiting, display.write_error()) {
+            (false, None) => {
+                history.record(origin, display, control as u8, value);
+                if control == Control::InputSource {
+                    switch_audio(audio, display, Trigger::Input(value));
+                }
+            }
+            (_, error) => {
+                let error = error.unwrap_or_else(|| "waiting for the display".to_string());
+                writeln!(warn, "{}: {}: {error}", display.label(), control.get_name())?;
+                failed |= !display.is_lenient();
+            }
+        }
+    }
+    match failed {
+        true => Err("not every value could be written".into()),
+        false => Ok(()),
+    }
+}
+
+fn check_steps(levels: &[(Control, Adjust)]) -> Result<(), Box<dyn Error>> {
+    match levels
+        .iter()
+        .find(|(control, level)| !control.is_continuous() && level.is_relative())
+    {
+        Some((control, _)) => {
+            Err(format!("{} takes no steps like +1, only values", control.key()).into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// The value a level writes to the control of the display.
+fn target(display: &MyDisplay, controller: &WrappedController, level: Adjust) -> u16 {
+    let controller = controller.read().unwrap();
+    display.adjust(
+        controller.kind,
+        level,
+        controller.value,
+        controller.write_bound(),
+    )
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use crate::display::{Control, MyDisplay};
+
+/// A virtual 0-100 control driving several real controls at once
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Composite {
+    pub name: String,
+    pub parts: Vec<Part>,
+}
+
+/// Maps the composite range linearly onto `from..=to` of a real control
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Part {
+    pub control: Control,
+    pub from: u16,
+    pub to: u16,
+}
+
+impl Part {
+    fn span(&self) -> i32 {
+        self.to as i32 - self.from as i32
+    }
+
+    fn map(&self, value: u16) -> u16 {
+        let scaled = self.from as i32 * 100 + self.span() * value.min(100) as i32;
+        (scaled + 50).div_euclid(100) as u16
+    }
+
+    fn unmap(&self, value: u16) -> Option<u16> {
+        if self.span() == 0 {
+            return None;
+        }
+        let scaled = (value as i32 - self.from as i32) * 100;
+        Some(((scaled + self.span() / 2).div_euclid(self.span())).clamp(0, 100) as u16)
+    }
+}
+
+impl Composite {
+    /// Values of the real controls for a composite value.
+    pub fn targets(&self, value: u16) -> impl Iterator<Item = (Control, u16)> + '_ {
+        self.parts
+            .iter()
+            .map(move |part| (part.control, part.map(value)))
+    }
+
+    /// Guesses the composite value from the current value of the widest part.
+    pub fn estimate(&self, display: &MyDisplay) -> u16 {
+        self.parts
+            .iter()
+            .filter_map(|part| Some((part, display.controls.get(&part.control)?)))
+            .max_by_key(|(part, _)| part.span().abs())
+            .and_then(|(part, controller)| part.unmap(controller.read().unwrap().value))
+            .unwrap_or(0)
+    }
+}
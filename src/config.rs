@@ -0,0 +1,571 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    error::Error,
+    fs, io,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+
+#[cfg(feature = "tui")]
+use ddc_bright::display::MyDisplay;
+use ddc_bright::{
+    composite::Composite, coupling::Coupling, display::Control, mapping::Mapping,
+    policy::ErrorRule, temperature, units::VolumeUnit, values::ValueNames,
+};
+use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::{
+    ambient::AmbientConfig,
+    audio::AudioConfig,
+    audit::AuditConfig,
+    automation::AutomationConfig,
+    backlight::BacklightConfig,
+    buttons::{self, Button},
+    events::EventsConfig,
+    group::Group,
+    keys::KeysConfig,
+    kvm::KvmConfig,
+    logging::LogConfig,
+    notify::NotificationsConfig,
+    presets::{self, Preset},
+    quirks::Readout,
+    rotate::OrientationConfig,
+    schedule::Rule,
+    setup::Setup,
+    sun::Location,
+    theme::ThemeConfig,
+    wake::WakeConfig,
+    watchdog::WatchdogConfig,
+};
+
+/// Version of the config format, older files are migrated when loaded
+pub const VERSION: i64 = 3;
+
+/// Turns reduced motion on when set to anything but `0`, `false` or `no` and the config doesn't
+/// say
+const REDUCED_MOTION_ENV: &str = "DDC_BRIGHT_REDUCED_MOTION";
+
+/// Upgrades a document by one version, from the version it is indexed by plus one
+const MIGRATIONS: [fn(&mut DocumentMut, &mut Vec<String>); 2] = [move_alert_webhook, drop_lock];
+
+/// User configuration, read from `config.toml` in the config directory
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Named sets of displays, matched by model name or id
+    pub groups: HashMap<String, Group>,
+    /// Names shown instead of the model name, by serial number or the id of displays without one
+    pub aliases: BTreeMap<String, String>,
+    pub hotkeys: Vec<Hotkey>,
+    /// Row of buttons at the bottom of the TUI, pressed with F1 to F12 or a click
+    pub buttons: Vec<Button>,
+    /// Layout and profile of the navigation keys
+    pub keys: KeysConfig,
+    /// Built-in theme of the TUI and colours replacing parts of it
+    pub theme: ThemeConfig,
+    /// Virtual controls shown after the real ones of every display
+    pub composites: Vec<Composite>,
+    /// Controls raised as others get low, e.g. contrast at very low brightness
+    pub coupling: Vec<Coupling>,
+    /// Curves making the percent shown look like that share of the brightness
+    pub mapping: Vec<Mapping>,
+    /// Displays whose failed writes are only warned about, or make commands fail
+    pub errors: Vec<ErrorRule>,
+    /// Names for input and colour preset values, like `inputs = { desktop = 15 }`
+    pub values: Vec<ValueNames>,
+    /// Displays the colour temperature control is left out or kept for
+    pub temperature: Vec<temperature::Setting>,
+    /// Named sets of control values, saved and applied from the presets popup
+    pub presets: Vec<Preset>,
+    /// Presets and values applied at times of day while the TUI or `--daemon` runs
+    pub schedule: Vec<Rule>,
+    /// Presets applied when the connected displays are the ones a rule lists
+    pub setup: Vec<Setup>,
+    /// Where the displays are, for schedule rules at sunrise and sunset
+    pub location: Option<Location>,
+    /// Shows volume as approximate dB instead of the raw value when set to "db"
+    pub volume_unit: VolumeUnit,
+    /// Extra vendor specific readouts for the details panel
+    pub readouts: Vec<Readout>,
+    /// Seconds each backend (e.g. "i2c-dev") may spend enumerating displays
+    pub enumeration_timeout: HashMap<String, u64>,
+    pub watchdog: WatchdogConfig,
+    pub events: EventsConfig,
+    pub polling: PollingConfig,
+    pub fade: FadeConfig,
+    pub retry: RetryConfig,
+    pub worker: WorkerConfig,
+    /// Reads, writes, retries and errors logged to `ddc_bright.log` and the log pane
+    pub log: LogConfig,
+    /// Every write with who made it and the value it replaced, chained by hashes in `audit.log`
+    pub audit: AuditConfig,
+    pub dbus: DbusConfig,
+    pub mqtt: MqttConfig,
+    /// Input of shared displays following the machine in use
+    pub kvm: KvmConfig,
+    pub orientation: OrientationConfig,
+    /// Input switches when a display wakes up from standby
+    pub wake: WakeConfig,
+    /// Light sensor used by `calibrate`
+    pub ambient: AmbientConfig,
+    /// Automations that only run under presets turning them on
+    pub automation: AutomationConfig,
+    /// Desktop notifications of changes made by the schedule, the control socket and the keys
+    pub notifications: NotificationsConfig,
+    /// Default audio output switches on input changes and power off
+    pub audio: AudioConfig,
+    /// Ties the external displays to the internal backlight and its keys
+    pub backlight: BacklightConfig,
+    /// Socket of the root helper, see `--helper`
+    pub helper_socket: Option<PathBuf>,
+    /// Socket, or named pipe on Windows, the daemon takes commands on
+    pub control_socket: Option<PathBuf>,
+    /// Looks for a newer release on GitHub when the TUI starts
+    pub update_check: bool,
+    /// Offers a synced group for two displays the TUI keeps being set the same distance apart in
+    /// brightness
+    pub learn_offsets: bool,
+    /// Lowest brightness in percent the TUI turns the only connected display down to. At 0
+    /// it asks first, as a black screen leaves nothing to turn it up again on.
+    pub brightness_floor: u16,
+    /// Writes values right away instead of fading, keeps the TUI still and dims displays once
+    /// instead of blinking them to identify them. Unset follows `DDC_BRIGHT_REDUCED_MOTION` and
+    /// the desktop's animation setting
+    pub reduced_motion: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Hotkey {
+    /// Key pressed in the TUI
+    #[serde(default)]
+    pub key: Option<char>,
+    /// Key combination the daemon takes system-wide, e.g. `Super+Alt+Up`
+    #[serde(default)]
+    pub global: Option<String>,
+    pub target: Target,
+    #[serde(default = "default_control")]
+    pub control: Control,
+    /// Amount added by one press, negative to decrease
+    pub step: i16,
+}
+
+/// Displays affected by a hotkey
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Focused,
+    All,
+    Group(String),
+}
+
+/// Background reading of values changed on the display itself, e.g. through its OSD
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PollingConfig {
+    pub enabled: bool,
+    /// Seconds between reads while the TUI is in use
+    pub interval: u64,
+    /// Seconds between reads while the user is idle or the terminal isn't focused
+    pub idle_interval: u64,
+    /// Seconds between reads of only the display the TUI shows while polling is off, 0 to leave
+    /// it alone
+    pub watch: u64,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 5,
+            idle_interval: 60,
+            watch: 10,
+        }
+    }
+}
+
+/// Gradual transitions for large changes of continuous controls
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct FadeConfig {
+    /// Milliseconds a fade takes, 0 to jump to the new value
+    pub duration_ms: u64,
+    /// Smallest change faded, in percent of the control's maximum
+    pub threshold: u16,
+}
+
+impl Default for FadeConfig {
+    fn default() -> Self {
+        Self {
+            duration_ms: 0,
+            threshold: 10,
+        }
+    }
+}
+
+/// Reads and writes tried again when a display doesn't answer, e.g. behind a flaky dock
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Tries after the first one, 0 to give up right away
+    pub attempts: u32,
+    /// Milliseconds waited before the first retry, doubled for each one after it
+    pub backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 2,
+            backoff_ms: 40,
+        }
+    }
+}
+
+/// Scheduling of the thread writing changes, so keypresses still reach the displays quickly
+/// while a compile or game keeps the CPUs busy
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct WorkerConfig {
+    /// Niceness from -20 to 19, lower is scheduled first. Below 0 needs root or `CAP_SYS_NICE`
+    /// on Linux, Windows maps it to its thread priorities
+    pub nice: Option<i32>,
+    /// Numbers of the CPUs the thread may run on, any if empty
+    pub cpus: Vec<usize>,
+}
+
+/// The D-Bus service of the daemon
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct DbusConfig {
+    /// Serves every user on the system bus instead of the session bus, polkit deciding who may
+    /// change values. Needs the files in `contrib/` installed
+    pub system: bool,
+}
+
+/// The MQTT client of the daemon, publishing the displays as Home Assistant lights
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// `host:port` of the broker, the client is off if unset
+    pub broker: Option<String>,
+    /// Password needs the username set
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Prefix Home Assistant looks for discovery messages under
+    pub discovery_prefix: String,
+    /// Prefix of the state and command topics and the client id, apart for each machine
+    pub topic: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: None,
+            username: None,
+            password: None,
+            discovery_prefix: "homeassistant".to_string(),
+            topic: "ddc_bright".to_string(),
+        }
+    }
+}
+
+/// `DDC_BRIGHT_REDUCED_MOTION` if set, otherwise whether GNOME and the desktops sharing its
+/// settings have animations turned off.
+fn detect_reduced_motion() -> bool {
+    if let Some(value) = env::var_os(REDUCED_MOTION_ENV) {
+        return !matches!(value.to_str(), Some("" | "0" | "false" | "no"));
+    }
+    if !cfg!(all(unix, not(target_os = "macos"))) {
+        return false;
+    }
+    Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "enable-animations"])
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|output| output.status.success() && output.stdout.trim_ascii() == b"false")
+}
+
+fn default_control() -> Control {
+    Control::Brightness
+}
+
+impl Config {
+    pub fn dir() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os("XDG_CONFIG_HOME").or_else(|| env::var_os("APPDATA")) {
+            return Some(PathBuf::from(dir).join("ddc_bright"));
+        }
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("ddc_bright"))
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Self::dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Whether fades, animations and blinking are left out.
+    pub fn reduced_motion(&self) -> bool {
+        static DETECTED: OnceLock<bool> = OnceLock::new();
+        self.reduced_motion
+            .unwrap_or_else(|| *DETECTED.get_or_init(detect_reduced_motion))
+    }
+
+    /// Loads the config file, falling back to defaults if there is none, with the built-in
+    /// presets it doesn't replace.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let mut config = Self::read()?;
+        presets::with_builtin(&mut config.presets)?;
+        buttons::check(&config.buttons)?;
+        Ok(config)
+    }
+
+    fn read() -> Result<Self, Box<dyn Error>> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let error = |err: &dyn std::fmt::Display| format!("{}: {err}", path.display());
+        let mut document: DocumentMut = text.parse().map_err(|err| error(&err))?;
+        let changes = migrate(&mut document).map_err(|err| error(&err))?;
+        if !changes.is_empty() {
+            // Keep the old file around in case the migration got something wrong
+            fs::write(path.with_extension("toml.bak"), &text)?;
+            fs::write(&path, document.to_string())?;
+            eprintln!(
+                "{} upgraded to version {VERSION}, the old one is kept as config.toml.bak:",
+                path.display()
+            );
+            for change in &changes {
+                eprintln!("  {change}");
+            }
+        }
+        toml::from_str(&document.to_string()).map_err(|err| error(&err).into())
+    }
+
+    /// The effective configuration as TOML, including defaults.
+    pub fn print(&self) -> Result<String, Box<dyn Error>> {
+        Ok(format!("version = {VERSION}\n{}", toml::to_string(self)?))
+    }
+
+    /// Sets the alias of a display, or removes it if `None`, in the config and in the config file.
+    /// The rest of the file is kept as it is.
+    #[cfg(feature = "tui")]
+    pub fn store_alias(&mut self, key: &str, alias: Option<&str>) -> Result<(), Box<dyn Error>> {
+        Self::edit_table("aliases", |aliases| {
+            match alias {
+                Some(alias) => aliases.insert(key, toml_edit::value(alias)),
+                None => aliases.remove(key),
+            };
+        })?;
+        match alias {
+            Some(alias) => self.aliases.insert(key.to_string(), alias.to_string()),
+            None => self.aliases.remove(key),
+        };
+        Ok(())
+    }
+
+    /// Adds a synced group of the displays by their serial numbers, or ids without one,
+    /// replacing a group of the same name, in the config and in the config file.
+    #[cfg(feature = "tui")]
+    pub fn store_group(&mut self, name: &str, displays: &[String]) -> Result<(), Box<dyn Error>> {
+        let group = Group {
+            displays: displays.to_vec(),
+            sync: true,
+            ..Group::default()
+        };
+        Self::edit_table("groups", |groups| {
+            let mut table = toml_edit::InlineTable::new();
+            table.insert(
+                "displays",
+                displays.iter().collect::<toml_edit::Array>().into(),
+            );
+            table.insert("sync", true.into());
+            groups.insert(name, toml_edit::value(table));
+        })?;
+        self.groups.insert(name.to_string(), group);
+        Ok(())
+    }
+
+    /// Keeps a member of a group `offset` percent of brightness above the others, in the config
+    /// and in the config file.
+    #[cfg(feature = "tui")]
+    pub fn store_offset(
+        &mut self,
+        group: &str,
+        member: &str,
+        offset: i16,
+    ) -> Result<(), Box<dyn Error>> {
+        let known = self
+            .groups
+            .get_mut(group)
+            .ok_or(format!("no group {group}"))?;
+        let mut written = false;
+        Self::edit_table("groups", |groups| {
+            let Some(table) = groups.get_mut(group).and_then(Item::as_table_like_mut) else {
+                return;
+            };
+            if !table.contains_key("offsets") {
+                table.insert("offsets", toml_edit::value(toml_edit::InlineTable::new()));
+            }
+            if let Some(offsets) = table.get_mut("offsets").and_then(Item::as_table_like_mut) {
+                offsets.insert(member, toml_edit::value(offset as i64));
+                written = true;
+            }
+        })?;
+        if !written {
+            return Err(format!("group {group} is not a table in the config file").into());
+        }
+        known.offsets.insert(member.to_string(), offset);
+        Ok(())
+    }
+
+    /// Changes a table of the config file, keeping the rest of the file as it is.
+    #[cfg(feature = "tui")]
+    fn edit_table(
+        key: &str,
+        change: impl FnOnce(&mut dyn toml_edit::TableLike),
+    ) -> Result<(), Box<dyn Error>> {
+        let path = Self::path().ok_or("no config directory")?;
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => format!("version = {VERSION}\n"),
+            Err(err) => return Err(err.into()),
+        };
+        let mut document: DocumentMut = text
+            .parse()
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        let table = document
+            .entry(key)
+            .or_insert_with(|| Item::Table(Table::new()))
+            .as_table_like_mut()
+            .ok_or(format!("{key} is not a table"))?;
+        change(table);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, document.to_string())?;
+        Ok(())
+    }
+
+    #[cfg(feature = "tui")]
+    pub fn hotkey(&self, key: char) -> Option<&Hotkey> {
+        self.hotkeys.iter().find(|hotkey| hotkey.key == Some(key))
+    }
+
+    /// Checks whether a display belongs to the named group.
+    #[cfg(feature = "tui")]
+    pub fn in_group(&self, group: &str, display: &MyDisplay) -> bool {
+        self.groups
+            .get(group)
+            .is_some_and(|group| group.contains(display))
+    }
+}
+
+/// Runs the migrations a document is missing, returns a description of every changed key.
+fn migrate(document: &mut DocumentMut) -> Result<Vec<String>, String> {
+    let version = match document.get("version") {
+        Some(item) => item.as_integer().ok_or("version must be a number")?,
+        None => 1,
+    };
+    if version > VERSION {
+        return Err(format!(
+            "written for config version {version}, this build only knows {VERSION}"
+        ));
+    }
+
+    let mut changes = vec![];
+    for migration in MIGRATIONS.iter().skip(version.max(1) as usize - 1) {
+        migration(document, &mut changes);
+    }
+    if version < VERSION {
+        document.insert("version", toml_edit::value(VERSION));
+        changes.push(format!("version = {VERSION} added"));
+    }
+    Ok(changes)
+}
+
+/// Version 2 keeps every webhook in `[events]`.
+fn move_alert_webhook(document: &mut DocumentMut, changes: &mut Vec<String>) {
+    let Some(webhook) = document
+        .get_mut("watchdog")
+        .and_then(Item::as_table_like_mut)
+        .and_then(|watchdog| watchdog.remove("webhook"))
+    else {
+        return;
+    };
+    let events = document
+        .entry("events")
+        .or_insert_with(|| Item::Table(Table::new()));
+    if let Some(events) = events.as_table_like_mut() {
+        events.insert("alert_webhook", webhook);
+        changes.push("watchdog.webhook moved to events.alert_webhook".to_string());
+    }
+}
+
+/// Version 3 reads the lock from a file its users can't change, see [`ddc_bright::lock::path`].
+fn drop_lock(document: &mut DocumentMut, changes: &mut Vec<String>) {
+    if document.remove("lock").is_some() {
+        let path = ddc_bright::lock::path();
+        changes.push(format!(
+            "lock removed, it only applies from {}",
+            path.display()
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrated(text: &str) -> (String, Vec<String>) {
+        let mut document: DocumentMut = text.parse().unwrap();
+        let changes = migrate(&mut document).unwrap();
+        (document.to_string(), changes)
+    }
+
+    #[test]
+    fn unversioned_configs_run_every_migration() {
+        let (text, changes) = migrated(
+            "lock = true\n\n[watchdog]\nwebhook = \"http://localhost/hook\"\ninterval = 30\n",
+        );
+        let document: DocumentMut = text.parse().unwrap();
+        assert_eq!(document["version"].as_integer(), Some(VERSION));
+        assert!(document.get("lock").is_none());
+        assert!(document["watchdog"].get("webhook").is_none());
+        assert_eq!(document["watchdog"]["interval"].as_integer(), Some(30));
+        assert_eq!(
+            document["events"]["alert_webhook"].as_str(),
+            Some("http://localhost/hook")
+        );
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0], "watchdog.webhook moved to events.alert_webhook");
+        assert!(changes[1].starts_with("lock removed"));
+        assert_eq!(changes[2], format!("version = {VERSION} added"));
+    }
+
+    #[test]
+    fn migrations_already_applied_are_skipped() {
+        // A lock at version 2 was written after the webhook moved, only the lock goes
+        let (text, changes) = migrated("version = 2\nlock = true\n\n[events]\nwebhook = \"a\"\n");
+        assert!(!text.contains("lock"));
+        assert!(text.contains("webhook = \"a\""));
+        assert_eq!(changes.len(), 2);
+    }
+
+    #[test]
+    fn current_configs_stay_as_they_are() {
+        let text = format!("version = {VERSION} # kept\n\n[watchdog]\ninterval = 30\n");
+        assert_eq!(migrated(&text), (text.clone(), vec![]));
+    }
+
+    #[test]
+    fn newer_configs_are_refused() {
+        let mut document: DocumentMut = format!("version = {}\n", VERSION + 1).parse().unwrap();
+        assert!(migrate(&mut document).is_err());
+        let mut document: DocumentMut = "version = \"3\"\n".parse().unwrap();
+        assert!(migrate(&mut document).is_err());
+    }
+}
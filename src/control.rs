@@ -0,0 +1,411 @@
+//! Control socket of the daemon, so subcommands can use its open displays instead of
+//! enumerating them again.
+//!
+//! Every request is one line, its words separated by whitespace, or by tabs if it has any so
+//! display names can contain spaces. `display=<query>` picks displays like `--display`.
+//! The requests are `status`, `list`, `get [display=<query>] [control]`,
+//! `readouts [display=<query>]` and `set [display=<query>] <control> <level>...`, e.g.
+//! `set display=U2720Q brightness 50%`.
+//! Answers are lines of output starting with `out ` and warnings starting with `warn `, the
+//! last line is `ok` or `err <message>`.
+
+use std::{
+    env,
+    error::Error,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use ddc_bright::{
+    display::{Control, DisplayManager, WrappedDisplay},
+    units::Adjust,
+};
+
+use ddc_bright::supervisor::Supervisor;
+use std::thread;
+#[cfg(unix)]
+use std::{
+    os::unix::net::{UnixListener, UnixStream},
+    time::Duration,
+};
+
+use crate::{
+    audio::AudioConfig,
+    cli,
+    history::Origin,
+    notify::{NotificationsConfig, Source},
+    quirks::{self, Readout},
+    watchdog::Jobs,
+};
+
+/// How often the listener looks for clients and cancellation
+#[cfg(unix)]
+const TICK: Duration = Duration::from_millis(100);
+
+/// How long a client may take to send a request before it is dropped
+#[cfg(unix)]
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What answering requests takes from the config
+#[derive(Clone)]
+pub struct Settings {
+    pub audio: AudioConfig,
+    pub notifications: NotificationsConfig,
+    pub readouts: Vec<Readout>,
+}
+
+/// Where the daemon listens: `control_socket` from the config, otherwise a socket in the
+/// runtime directory on Unix and a named pipe on Windows.
+pub fn endpoint(configured: Option<&PathBuf>) -> PathBuf {
+    if let Some(path) = configured {
+        return path.clone();
+    }
+    if cfg!(windows) {
+        return PathBuf::from(r"\\.\pipe\ddc_bright-control");
+    }
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("ddc_bright.sock"),
+        None => {
+            let user = env::var("USER").unwrap_or_default();
+            env::temp_dir().join(format!("ddc_bright-{user}.sock"))
+        }
+    }
+}
+
+/// Answers requests on the socket while the returned supervisor lives, each client on a thread
+/// of its own. Only the user running the daemon may connect.
+#[cfg(unix)]
+pub fn start(
+    manager: &DisplayManager,
+    jobs: Jobs,
+    endpoint: PathBuf,
+    settings: Settings,
+) -> io::Result<Supervisor> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // A socket left behind by a daemon that didn't exit cleanly is replaced
+    if UnixStream::connect(&endpoint).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "another daemon is already running",
+        ));
+    }
+    let _ = std::fs::remove_file(&endpoint);
+    let listener = UnixListener::bind(&endpoint)?;
+    std::fs::set_permissions(&endpoint, std::fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+    let displays = manager.shared_displays();
+
+    let mut supervisor = Supervisor::default();
+    supervisor.spawn("control socket", move |token| {
+        while !token.is_cancelled() {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let displays = displays.lock().unwrap().clone();
+                    let (jobs, settings) = (jobs.clone(), settings.clone());
+                    thread::spawn(move || {
+                        let _ = (stream.set_nonblocking(false))
+                            .and_then(|()| stream.set_read_timeout(Some(READ_TIMEOUT)))
+                            .and_then(|()| serve(&stream, &stream, &displays, &jobs, &settings));
+                    });
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(TICK),
+                Err(err) => {
+                    eprintln!("{}: {err}", endpoint.display());
+                    return;
+                }
+            }
+        }
+        let _ = std::fs::remove_file(&endpoint);
+    });
+    Ok(supervisor)
+}
+
+/// Answers requests on the named pipe, each client on a thread of its own. The thread waiting
+/// for clients isn't supervised, it can't be woken up, so the returned supervisor is empty.
+#[cfg(windows)]
+pub fn start(
+    manager: &DisplayManager,
+    jobs: Jobs,
+    endpoint: PathBuf,
+    settings: Settings,
+) -> io::Result<Supervisor> {
+    let displays = manager.shared_displays();
+    let name = endpoint.to_string_lossy().into_owned();
+    thread::spawn(move || loop {
+        let pipe = match accept_pipe(&name) {
+            Ok(pipe) => pipe,
+            Err(err) => {
+                eprintln!("{name}: {err}");
+                return;
+            }
+        };
+        let displays = displays.lock().unwrap().clone();
+        let (jobs, settings) = (jobs.clone(), settings.clone());
+        thread::spawn(move || serve(&pipe, &pipe, &displays, &jobs, &settings));
+    });
+    Ok(Supervisor::default())
+}
+
+/// Creates an instance of a named pipe and waits for a client to connect to it.
+#[cfg(windows)]
+pub fn accept_pipe(name: &str) -> io::Result<std::fs::File> {
+    use std::{os::windows::io::FromRawHandle, ptr};
+    use windows_sys::Win32::{
+        Foundation::{GetLastError, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::PIPE_ACCESS_DUPLEX,
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE,
+            PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+    };
+
+    let wide: Vec<u16> = name.encode_utf16().chain([0]).collect();
+    loop {
+        // SAFETY: the name is NUL terminated and outlives the call, no security attributes
+        // picks the default ones.
+        let handle = unsafe {
+            CreateNamedPipeW(
+                wide.as_ptr(),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                ptr::null(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle is a valid pipe, owned by the file from here on so it is closed
+        // on every path.
+        let pipe = unsafe { std::fs::File::from_raw_handle(handle) };
+        // A client connecting between creating and waiting shows up as ERROR_PIPE_CONNECTED
+        let connected = unsafe { ConnectNamedPipe(handle, ptr::null_mut()) } != 0
+            || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED;
+        if connected {
+            return Ok(pipe);
+        }
+    }
+}
+
+/// Answers the requests of one client until it hangs up.
+fn serve(
+    reader: impl Read,
+    mut writer: impl Write,
+    displays: &[WrappedDisplay],
+    jobs: &Jobs,
+    settings: &Settings,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        let (mut out, mut warn) = (vec![], vec![]);
+        let result = answer(
+            line.trim_end_matches(['\r', '\n']),
+            displays,
+            jobs,
+            settings,
+            &mut out,
+            &mut warn,
+        );
+        for (kind, text) in [("out", out), ("warn", warn)] {
+            for line in String::from_utf8_lossy(&text).lines() {
+                writeln!(writer, "{kind} {line}")?;
+            }
+        }
+        match result {
+            Ok(()) => writeln!(writer, "ok")?,
+            Err(err) => writeln!(writer, "err {}", err.to_string().replace('\n', " "))?,
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+fn answer(
+    line: &str,
+    displays: &[WrappedDisplay],
+    jobs: &Jobs,
+    settings: &Settings,
+    out: &mut Vec<u8>,
+    warn: &mut Vec<u8>,
+) -> Result<(), Box<dyn Error>> {
+    let words: Vec<&str> = match line.contains('\t') {
+        true => line.split('\t').collect(),
+        false => line.split_whitespace().collect(),
+    };
+    let mut query = None;
+    let mut rest = vec![];
+    for word in words.iter().skip(1) {
+        match word.strip_prefix("display=") {
+            Some(display) => query = Some(display),
+            None => rest.push(*word),
+        }
+    }
+    let selected = || cli::select(displays, query);
+
+    match words.first().copied() {
+        Some("status") => writeln!(out, "running, {} displays", displays.len())?,
+        Some("list") => cli::list(displays, out)?,
+        Some("get") => {
+            let control = rest.first().map(|control| control.parse()).transpose()?;
+            cli::get(&selected()?, control, out, warn)?;
+        }
+        Some("readouts") => {
+            for display in selected()? {
+                for (readout, value) in quirks::read(&settings.readouts, &display) {
+                    let value = match value {
+                        Some(value) => format!("{value}{}", readout.unit),
+                        None => "unsupported".to_string(),
+                    };
+                    writeln!(out, "{}: {} {value}", display.label(), readout.name)?;
+                }
+            }
+        }
+        Some("refresh") => {
+            let parallel = match rest.first() {
+                Some(parallel) => parallel.parse()?,
+                None => 4,
+            };
+            cli::refresh(&selected()?, parallel, out, warn)?;
+        }
+        Some("set") => {
+            if rest.is_empty() || rest.len() % 2 != 0 {
+                return Err("set takes pairs of a control and a level".into());
+            }
+            let mut levels = vec![];
+            for pair in rest.chunks(2) {
+                levels.push((pair[0].parse::<Control>()?, pair[1].parse::<Adjust>()?));
+            }
+            let selected = selected()?;
+            let (targets, queued) = (selected.clone(), levels.clone());
+            let audio = settings.audio.clone();
+            let (result, warnings) = jobs
+                .run(move |manager| {
+                    let mut warnings = vec![];
+                    let result = cli::queue(
+                        manager,
+                        &targets,
+                        &queued,
+                        &audio,
+                        Origin::Socket,
+                        &mut warnings,
+                    );
+                    (result.map_err(|err| err.to_string()), warnings)
+                })
+                .ok_or("the daemon is stopping")?;
+            warn.write_all(&warnings)?;
+            result?;
+            let mut changed = vec![];
+            for display in &selected {
+                for (control, _) in &levels {
+                    if let Some(controller) = display.controls.get(control) {
+                        let value = display.describe(*control, controller.read().unwrap().value);
+                        changed.push(format!("{}: {} {value}", display.label(), control.key()));
+                    }
+                }
+            }
+            if let Err(err) = settings
+                .notifications
+                .notify(Source::Socket, &changed.join("\n"))
+            {
+                writeln!(warn, "{err}")?;
+            }
+        }
+        Some(command) => return Err(format!("unknown command {command:?}").into()),
+        None => return Err("empty request".into()),
+    }
+    Ok(())
+}
+
+/// Sends a request to the running daemon and prints its output. `None` if no daemon listens,
+/// so the caller can do the work itself.
+pub fn forward(endpoint: &PathBuf, words: &[String]) -> Option<Result<(), Box<dyn Error>>> {
+    let stream = connect(endpoint).ok()?;
+    Some(match stream.try_clone() {
+        Ok(reader) => request(reader, stream, words),
+        Err(err) => Err(err.into()),
+    })
+}
+
+/// Runs a request on the daemon of another machine through `ssh host ddc_bright --relay`.
+/// SSH asks for passwords and host keys on the terminal as usual.
+pub fn remote(host: &str, words: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut ssh = Command::new("ssh")
+        .args(["-T", host, "ddc_bright", "--relay"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("running ssh: {err}"))?;
+    let (Some(writer), Some(reader)) = (ssh.stdin.take(), ssh.stdout.take()) else {
+        return Err("ssh has no pipes".into());
+    };
+    // Closing the pipe to ssh once answered ends the relay
+    let result = request(reader, writer, words);
+    let status = ssh.wait()?;
+    match result {
+        Err(_) if !status.success() => Err(format!("ssh {host} failed with {status}").into()),
+        result => result,
+    }
+}
+
+/// Passes requests on stdin to the running daemon and its answers to stdout, the remote end
+/// of `--remote`. Answers `err` when there is no daemon.
+pub fn relay(endpoint: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let Ok(mut stream) = connect(endpoint) else {
+        println!("err no daemon is running on {}", endpoint.display());
+        return Ok(());
+    };
+    let mut answers = BufReader::new(stream.try_clone()?).lines();
+    for line in io::stdin().lines() {
+        writeln!(stream, "{}", line?)?;
+        for answer in answers.by_ref() {
+            let answer = answer?;
+            println!("{answer}");
+            if answer == "ok" || answer.starts_with("err ") {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn connect(endpoint: &PathBuf) -> io::Result<UnixStream> {
+    UnixStream::connect(endpoint)
+}
+
+#[cfg(windows)]
+fn connect(endpoint: &PathBuf) -> io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(endpoint)
+}
+
+fn request(
+    reader: impl Read,
+    mut writer: impl Write,
+    words: &[String],
+) -> Result<(), Box<dyn Error>> {
+    writeln!(writer, "{}", words.join("\t"))?;
+    drop(writer);
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if let Some(output) = line.strip_prefix("out ") {
+            println!("{output}");
+        } else if let Some(warning) = line.strip_prefix("warn ") {
+            eprintln!("{warning}");
+        } else if line == "ok" {
+            return Ok(());
+        } else {
+            let message = line.strip_prefix("err ").unwrap_or(&line);
+            return Err(message.to_string().into());
+        }
+    }
+    Err("the daemon hung up without answering".into())
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::display::{Control, MyDisplay};
+
+/// Raises one control as another approaches 0, e.g. contrast as brightness gets very low so
+/// text stays readable, and takes it back on the way up
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Coupling {
+    /// Model name, id or serial of the display, every display if unset
+    pub display: Option<String>,
+    /// Control whose changes are followed
+    pub control: Control,
+    /// Control raised
+    pub target: Control,
+    /// Percent of `control` below which `target` is raised
+    pub below: u16,
+    /// Percent of its maximum `target` is raised by when `control` is at 0
+    pub boost: u16,
+}
+
+impl Default for Coupling {
+    fn default() -> Self {
+        Self {
+            display: None,
+            control: Control::Brightness,
+            target: Control::Contrast,
+            below: 20,
+            boost: 10,
+        }
+    }
+}
+
+impl Coupling {
+    pub fn applies(&self, display: &MyDisplay) -> bool {
+        self.display
+            .as_deref()
+            .is_none_or(|query| display.matches(query))
+    }
+
+    /// Raise of the target, in percent of its maximum, with `control` at `percent`. Grows
+    /// linearly from nothing at `below` to `boost` at 0.
+    pub fn boost(&self, percent: u16) -> u16 {
+        if percent >= self.below {
+            return 0;
+        }
+        let missing = (self.below - percent) as u32;
+        ((self.boost as u32 * missing + self.below as u32 / 2) / self.below as u32) as u16
+    }
+}
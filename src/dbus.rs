@@ -0,0 +1,309 @@
+//! D-Bus service of the daemon, so applets and scripts on the desktop can list displays and
+//! get and set their controls on the session bus, or on the system bus for every user sharing
+//! the monitors.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use ddc_bright::{
+    display::{Control, DisplayManager, WrappedDisplay},
+    supervisor::Supervisor,
+    units::Adjust,
+};
+use zbus::{
+    blocking::connection, fdo, interface, message::Header, names::BusName,
+    object_server::SignalContext, zvariant::Value, Connection,
+};
+
+use crate::{
+    audio::AudioConfig,
+    automation::Policy,
+    cli,
+    config::DbusConfig,
+    history::{Log, Origin},
+    presets::{Preset, Saved},
+    watchdog::Jobs,
+};
+
+/// Well-known name of the service
+pub const NAME: &str = "org.ddc_bright.Manager";
+const PATH: &str = "/org/ddc_bright/Manager";
+
+/// How often the cached values are compared for `ValueChanged`
+const TICK: Duration = Duration::from_millis(250);
+
+/// Polkit action callers on the system bus need to change values
+const ACTION: &str = "org.ddc_bright.change";
+
+/// Presets of each user when serving the system bus, by uid below this
+const USERS: &str = "/var/lib/ddc_bright/users";
+
+struct Manager {
+    displays: Arc<Mutex<Vec<WrappedDisplay>>>,
+    jobs: Jobs,
+    audio: AudioConfig,
+    /// Serving the system bus, where polkit decides who may change values
+    system: bool,
+}
+
+impl Manager {
+    fn select(&self, display: &str) -> fdo::Result<Vec<WrappedDisplay>> {
+        let query = (!display.is_empty()).then_some(display);
+        cli::select(&self.displays.lock().unwrap(), query).map_err(fdo::Error::InvalidArgs)
+    }
+
+    /// Runs `job` with the manager of the daemon on a thread of its own, so the bus is served
+    /// while the displays are written.
+    async fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&DisplayManager) -> T + Send + 'static,
+    ) -> fdo::Result<T> {
+        let jobs = self.jobs.clone();
+        blocking::unblock(move || jobs.run(job))
+            .await
+            .ok_or_else(|| fdo::Error::Failed("the daemon is stopping".into()))
+    }
+
+    /// Asks polkit whether the caller may change values, always allowed on the session bus.
+    async fn authorize(&self, bus: &Connection, header: &Header<'_>) -> fdo::Result<()> {
+        if !self.system {
+            return Ok(());
+        }
+        let sender = header
+            .sender()
+            .ok_or_else(|| fdo::Error::AccessDenied("no sender".into()))?;
+        let subject = (
+            "system-bus-name",
+            HashMap::from([("name", Value::from(sender.as_str()))]),
+        );
+        let details: HashMap<&str, &str> = HashMap::new();
+        // Flag 1 lets polkit ask the user for a password
+        let body = (subject, ACTION, details, 1u32, "");
+        let reply = bus
+            .call_method(
+                Some("org.freedesktop.PolicyKit1"),
+                "/org/freedesktop/PolicyKit1/Authority",
+                Some("org.freedesktop.PolicyKit1.Authority"),
+                "CheckAuthorization",
+                &body,
+            )
+            .await?;
+        let (authorized, _, _): (bool, bool, HashMap<String, String>) =
+            reply.body().deserialize()?;
+        match authorized {
+            true => Ok(()),
+            false => Err(fdo::Error::AccessDenied(format!("{ACTION} not allowed"))),
+        }
+    }
+
+    /// Presets of the caller, kept apart for each user on the system bus.
+    async fn presets(&self, bus: &Connection, header: &Header<'_>) -> fdo::Result<Saved> {
+        let saved = match self.system {
+            true => {
+                let sender = header
+                    .sender()
+                    .ok_or_else(|| fdo::Error::AccessDenied("no sender".into()))?;
+                let uid = fdo::DBusProxy::new(bus)
+                    .await?
+                    .get_connection_unix_user(BusName::from(sender.to_owned()))
+                    .await?;
+                Saved::load_at(
+                    PathBuf::from(USERS)
+                        .join(uid.to_string())
+                        .join("presets.toml"),
+                )
+            }
+            false => Saved::load(),
+        };
+        saved.map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+fn preset(name: &str) -> fdo::Result<Preset> {
+    if name.is_empty() {
+        return Err(fdo::Error::InvalidArgs("empty preset name".into()));
+    }
+    Ok(Preset {
+        name: name.to_string(),
+        ..Preset::default()
+    })
+}
+
+fn control(name: &str) -> fdo::Result<Control> {
+    name.parse().map_err(fdo::Error::InvalidArgs)
+}
+
+#[interface(name = "org.ddc_bright.Manager")]
+impl Manager {
+    /// Number, id and model name of every display
+    fn list(&self) -> Vec<(u32, String, String)> {
+        let displays = self.displays.lock().unwrap();
+        (1..)
+            .zip(displays.iter())
+            .map(|(number, display)| (number, display.id.clone(), display.label()))
+            .collect()
+    }
+
+    /// Current value of a control, read from the display. `display` is a number from `List`,
+    /// model name or id and has to match one display.
+    async fn get(&self, display: &str, control: &str) -> fdo::Result<u16> {
+        let control = self::control(control)?;
+        let [display] = &self.select(display)?[..] else {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "{display:?} matches several displays"
+            )));
+        };
+        let display = display.clone();
+        blocking::unblock(move || {
+            display
+                .get(control)
+                .map_err(|err| fdo::Error::Failed(format!("{}: {err}", display.label())))
+        })
+        .await
+    }
+
+    /// Writes a level like `40`, `40%`, `-20dB` or a step like `+10` to the matching displays,
+    /// every display if `display` is empty.
+    async fn set(
+        &self,
+        #[zbus(connection)] bus: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        display: &str,
+        control: &str,
+        level: &str,
+    ) -> fdo::Result<()> {
+        self.authorize(bus, &header).await?;
+        let control = self::control(control)?;
+        let level: Adjust = level.parse().map_err(fdo::Error::InvalidArgs)?;
+        let (selected, audio) = (self.select(display)?, self.audio.clone());
+        let (result, warnings) = self
+            .run(move |manager| {
+                let mut warnings = vec![];
+                let levels = [(control, level)];
+                let result = cli::queue(
+                    manager,
+                    &selected,
+                    &levels,
+                    &audio,
+                    Origin::Dbus,
+                    &mut warnings,
+                );
+                (result.map_err(|err| err.to_string()), warnings)
+            })
+            .await?;
+        result.map_err(|err| {
+            let warnings = String::from_utf8_lossy(&warnings)
+                .trim()
+                .replace('\n', ", ");
+            match warnings.is_empty() {
+                true => fdo::Error::Failed(err),
+                false => fdo::Error::Failed(warnings),
+            }
+        })
+    }
+
+    /// Saves the current values of every display as a preset of the caller.
+    async fn save_preset(
+        &self,
+        #[zbus(connection)] bus: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        name: &str,
+    ) -> fdo::Result<()> {
+        self.authorize(bus, &header).await?;
+        let preset = self::preset(name)?;
+        let mut saved = self.presets(bus, &header).await?;
+        let displays = self.displays.lock().unwrap().clone();
+        saved
+            .save(&preset, &displays)
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Writes a preset of the caller, waiting until it is written. Returns how many displays
+    /// had values saved.
+    async fn apply_preset(
+        &self,
+        #[zbus(connection)] bus: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        name: &str,
+    ) -> fdo::Result<u32> {
+        self.authorize(bus, &header).await?;
+        let preset = self::preset(name)?;
+        let saved = self.presets(bus, &header).await?;
+        let job = move |manager: &DisplayManager| {
+            let mut history = Log::default();
+            let queued = saved.queue(&preset, manager, &mut history, Origin::Dbus);
+            let applied = Saved::switch_power(&preset, &queued, &manager.flusher());
+            manager.flush();
+            (applied, manager.pending(), preset)
+        };
+        let (applied, pending, preset) = self.run(job).await?;
+        if applied == 0 {
+            return Err(fdo::Error::Failed(format!(
+                "nothing saved in {name} for these displays"
+            )));
+        }
+        if pending > 0 {
+            return Err(fdo::Error::Failed(format!("not every display took {name}")));
+        }
+        Policy::switch(&preset).map_err(|err| fdo::Error::Failed(err.to_string()))?;
+        Ok(applied as u32)
+    }
+
+    /// A control of a display got a new value, from any source the daemon knows of
+    #[zbus(signal)]
+    async fn value_changed(
+        context: &SignalContext<'_>,
+        id: &str,
+        control: &str,
+        value: u16,
+    ) -> zbus::Result<()>;
+}
+
+/// Takes the name on the session or system bus and serves it while the returned supervisor
+/// lives.
+pub fn start(
+    manager: &DisplayManager,
+    jobs: Jobs,
+    audio: &AudioConfig,
+    config: &DbusConfig,
+) -> Result<Supervisor, Box<dyn Error>> {
+    let displays = manager.shared_displays();
+    let service = Manager {
+        displays: displays.clone(),
+        jobs,
+        audio: audio.clone(),
+        system: config.system,
+    };
+    let builder = match config.system {
+        true => connection::Builder::system()?,
+        false => connection::Builder::session()?,
+    };
+    let bus = builder.name(NAME)?.serve_at(PATH, service)?.build()?;
+
+    let mut supervisor = Supervisor::default();
+    supervisor.spawn("d-bus signals", move |token| {
+        let mut known = HashMap::new();
+        while !token.is_cancelled() {
+            thread::sleep(TICK);
+            let displays = displays.lock().unwrap().clone();
+            for display in &displays {
+                for (control, controller) in &display.controls {
+                    let value = controller.read().unwrap().value;
+                    let key = (display.id.clone(), *control);
+                    // The first value seen isn't a change
+                    if known.insert(key, value).is_some_and(|old| old != value) {
+                        let body = (display.id.as_str(), control.key(), value);
+                        let _ = bus.emit_signal(None::<&str>, PATH, NAME, "ValueChanged", &body);
+                    }
+                }
+            }
+        }
+    });
+    Ok(supervisor)
+}
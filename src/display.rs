@@ -1,153 +1,2178 @@
-use ddc_hi::{Ddc, DdcHost, Display, Handle};
-use std::cmp::{max, min};
-use std::collections::HashMap;
+//! Displays and the queue writing changes to them.
+//!
+//! [`DisplayManager`] and [`MyDisplay`] are `Send + Sync`, so they can be shared with other
+//! threads or an async runtime. Each display has a lock of its own around its handle, so
+//! displays don't wait for each other. Where locks are nested they are taken in this order,
+//! and code taking more than one must stick to it:
+//!
+//! 1. the handle of a display, held while writing a series of changes
+//! 2. a controller
+//! 3. the queued changes, or the previewed ones
+//! 4. the parked changes
+//! 5. the write metrics
+//! 6. the user activity
+//!
+//! The list of displays shared with background threads, the last write error, the known values
+//! and the alias of a display and the sender of failures are only ever locked on their own.
+
+use crate::backend::{self, SendHandle, Session};
+use crate::bulk;
+use crate::capabilities::{Capabilities, FeatureKind};
+use crate::coupling::Coupling;
+use crate::fake::{self, FakeDisplay};
+#[cfg(all(unix, feature = "helper"))]
+use crate::helper;
+use crate::lock::Lock;
+use crate::mapping::Mapping;
+use crate::metrics::{Metrics, Recorder, Written};
+use crate::orientation::{self, Orientation};
+use crate::output::Output;
+use crate::policy::{self, ErrorPolicy, ErrorRule};
+use crate::poll::{Activity, Poller};
+use crate::power::PowerMode;
+use crate::priority::{self, Priority, ThreadId};
+use crate::supervisor::Supervisor;
+use crate::transaction::Transaction;
+use crate::units::{Adjust, Level};
+use crate::values::{self, ValueNames};
+use crate::worker::{self, Change, Worker};
+use crate::Error;
+use ddc_hi::{Backend, Handle};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::mem::{replace, take};
+#[cfg(all(unix, feature = "helper"))]
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::RwLock;
-use std::sync::{mpsc::Sender, Arc, Mutex};
+use std::sync::{
+    mpsc::{Receiver, Sender},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
 use std::{sync::mpsc::channel, thread};
+use tracing::{debug, error, info, warn};
+
+/// How long a display stays dimmed or lit while flashing.
+const FLASH_DELAY: Duration = Duration::from_millis(400);
+
+/// Displays in standby often miss the first write that is meant to wake them.
+const POWER_ON_TRIES: usize = 3;
+const POWER_ON_DELAY: Duration = Duration::from_millis(500);
+
+/// Read-write codes that aren't settings: control messages, and the input and power mode, which
+/// would switch away from or turn off a display restoring them
+const NOT_SETTINGS: [u8; 4] = [0x02, 0x03, 0x60, 0xD6];
+
+/// Time a display takes to restore its defaults before reading them shows the new values.
+const RESET_DELAY: Duration = Duration::from_secs(1);
+
+/// How long a value read from or written to a display is trusted without hearing from it again
+pub const CONFIRMED_FOR: Duration = Duration::from_secs(5 * 60);
+
+/// Quiet time after a display was plugged in or out before it is reported.
+#[cfg(all(any(target_os = "linux", windows), feature = "hotplug"))]
+const HOTPLUG_SETTLE: Duration = Duration::from_secs(1);
 
-#[derive(PartialEq, PartialOrd, Eq, Hash, Clone, Copy)]
+/// How often the hotplug watcher checks for events and cancellation.
+#[cfg(all(any(target_os = "linux", windows), feature = "hotplug"))]
+const HOTPLUG_TICK: Duration = Duration::from_millis(250);
+
+/// Changes noticed by polling kept until taken, older ones are dropped
+const NOTICED_KEPT: usize = 100;
+
+#[derive(PartialEq, PartialOrd, Eq, Ord, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
 pub enum Control {
-    BRIGHTNESS = 0x10,
-    CONTRAST = 0x12,
+    Brightness = 0x10,
+    Contrast = 0x12,
+    ColorPreset = 0x14,
+    RedGain = 0x16,
+    GreenGain = 0x18,
+    BlueGain = 0x1A,
+    InputSource = 0x60,
+    Volume = 0x62,
+    Sharpness = 0x87,
 }
 
 impl Control {
     pub fn get_name(&self) -> &'static str {
         match &self {
-            Control::BRIGHTNESS => "Brightness",
-            Control::CONTRAST => "Contrast",
+            Control::Brightness => "Brightness",
+            Control::Contrast => "Contrast",
+            Control::ColorPreset => "Color preset",
+            Control::RedGain => "Red gain",
+            Control::GreenGain => "Green gain",
+            Control::BlueGain => "Blue gain",
+            Control::InputSource => "Input source",
+            Control::Volume => "Volume",
+            Control::Sharpness => "Sharpness",
+        }
+    }
+
+    /// The name as written in the config and on the command line, e.g. `input_source`.
+    pub fn key(&self) -> String {
+        self.get_name().to_lowercase().replace(' ', "_")
+    }
+
+    /// Whether every display is expected to have the control, others are dropped unless they answer.
+    fn is_core(&self) -> bool {
+        matches!(self, Control::Brightness | Control::Contrast)
+    }
+
+    /// Whether the control takes any value up to its maximum. The others cycle through the
+    /// values the display advertises.
+    pub fn is_continuous(&self) -> bool {
+        !matches!(self, Control::ColorPreset | Control::InputSource)
+    }
+
+    /// The VCP code restoring the factory default of the control, along with the others of its
+    /// group: brightness and contrast, or the colour.
+    pub fn reset_code(&self) -> Option<u8> {
+        match self {
+            Control::Brightness | Control::Contrast => Some(RESET_LUMINANCE),
+            Control::ColorPreset | Control::RedGain | Control::GreenGain | Control::BlueGain => {
+                Some(RESET_COLOR)
+            }
+            _ => None,
+        }
+    }
+
+    /// Drops the high byte non-continuous values don't use.
+    pub fn mask(&self, value: u16) -> u16 {
+        if self.is_continuous() {
+            value
+        } else {
+            value & 0xFF
         }
     }
 }
 
-const ALL_CONTROLS: [Control; 2] = [Control::BRIGHTNESS, Control::CONTRAST];
+/// Every control ddc_bright knows, tried on each display when it is loaded
+pub const ALL_CONTROLS: [Control; 9] = [
+    Control::Brightness,
+    Control::Contrast,
+    Control::ColorPreset,
+    Control::RedGain,
+    Control::GreenGain,
+    Control::BlueGain,
+    Control::InputSource,
+    Control::Volume,
+    Control::Sharpness,
+];
+
+impl FromStr for Control {
+    type Err = String;
 
-#[derive(Clone, Copy)]
+    /// Parses the config name, e.g. `input_source`, or the display name.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim().to_lowercase().replace([' ', '-'], "_");
+        ALL_CONTROLS
+            .into_iter()
+            .find(|control| control.key() == text)
+            .ok_or_else(|| {
+                let keys: Vec<String> = ALL_CONTROLS.iter().map(Control::key).collect();
+                format!("{text:?} is not one of {}", keys.join(", "))
+            })
+    }
+}
+
+impl Control {
+    /// The control of a raw VCP code, `None` for codes not modeled as one.
+    pub fn from_code(code: u8) -> Option<Self> {
+        ALL_CONTROLS
+            .into_iter()
+            .find(|control| *control as u8 == code)
+    }
+}
+
+/// Input select, for code that works with raw VCP codes
+pub const INPUT_SOURCE: u8 = Control::InputSource as u8;
+
+/// Power mode, 1 while on and higher values for standby and off
+pub const POWER_MODE: u8 = 0xD6;
+
+/// Restores every factory default of a display
+pub const RESET_ALL: u8 = 0x04;
+/// Restores the factory brightness and contrast
+pub const RESET_LUMINANCE: u8 = 0x05;
+/// Restores the factory geometry, which no control here covers
+pub const RESET_GEOMETRY: u8 = 0x06;
+/// Restores the factory colour settings
+pub const RESET_COLOR: u8 = 0x08;
+
+#[derive(Clone, Copy, Serialize)]
 pub struct Controller {
     pub value: u16,
+    /// Largest value the display reported, 100 until it answered
+    pub maximum: u16,
+    /// Left out of snapshots, where controls are keyed by it
+    #[serde(skip)]
     pub kind: Control,
+    /// Set while the last write failed and is parked until the display answers again.
+    pub pending: bool,
+    /// Set while the value is only previewed and not written, see [`DisplayManager::start_preview`]
+    pub previewed: bool,
+    /// When the display last answered a read or write of the control, `None` if it never did
+    #[serde(skip)]
+    pub confirmed: Option<Instant>,
+    /// Set while the last read or write of the control failed
+    #[serde(skip)]
+    pub failed: bool,
+}
+
+impl Controller {
+    /// The value as a rounded share of the maximum.
+    pub fn percent(&self) -> u16 {
+        (self.value as u32 * 100 + self.maximum as u32 / 2)
+            .checked_div(self.maximum as u32)
+            .unwrap_or(0) as u16
+    }
+
+    /// The highest value a write may carry: the maximum for continuous controls. Discrete values
+    /// aren't bounded by the maximum the display reports, so they can be anything.
+    pub fn write_bound(&self) -> u16 {
+        match self.kind.is_continuous() {
+            true => self.maximum,
+            false => u16::MAX,
+        }
+    }
+
+    /// Whether the value may be off: the last read or write failed, or the display wasn't heard
+    /// from for longer than [`CONFIRMED_FOR`].
+    pub fn is_unconfirmed(&self) -> bool {
+        self.failed
+            || self
+                .confirmed
+                .is_none_or(|confirmed| confirmed.elapsed() > CONFIRMED_FOR)
+    }
+
+    /// `step` out of 100 in the range of the control, so one going up to 255 moves as far per
+    /// key press as one going up to 100. Moves by at least 1, other controls keep `step`.
+    pub fn scale_step(&self, step: i16) -> i16 {
+        if !self.kind.is_continuous() {
+            return step;
+        }
+        match (step as f32 * self.maximum as f32 / 100.0).round() as i16 {
+            0 => step.signum(),
+            scaled => scaled,
+        }
+    }
+}
+
+/// How writes to a display are paced and checked, measured by the `bench` subcommand
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Tuning {
+    /// Pause after each write, for displays that drop commands sent back to back
+    pub delay_ms: u64,
+    /// Reads each write back, treating a different value like a failed write
+    pub verify: bool,
+    /// Treats the display as slow from the start instead of after its first slow write
+    pub slow: bool,
+}
+
+/// How often a read or write the display didn't answer is tried again, for docks and cables
+/// that drop a command now and then
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    /// Tries after the first one, zero to give up right away
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for each one after it
+    pub backoff: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 2,
+            backoff: Duration::from_millis(40),
+        }
+    }
+}
+
+impl Retry {
+    /// Runs `attempt` until it succeeds or the retries are used up, a disconnected display isn't
+    /// retried.
+    pub(crate) fn run<T>(&self, mut attempt: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        let mut backoff = self.backoff;
+        let mut result = attempt();
+        for retry in 1..=self.attempts {
+            if matches!(result, Ok(_) | Err(Error::Gone)) {
+                break;
+            }
+            info!("retry {retry} of {} in {backoff:?}", self.attempts);
+            thread::sleep(backoff);
+            backoff *= 2;
+            result = attempt();
+        }
+        result
+    }
+}
+
+/// A display that stopped answering even after retrying, see [`DisplayManager::watch_failures`]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Failure {
+    pub id: String,
+    pub name: String,
+    pub error: String,
+}
+
+/// Where displays report their failures, nowhere until someone watches
+type Failures = Arc<Mutex<Option<Sender<Failure>>>>;
+
+/// A value polling read back that differs from the known one, changed at the display's buttons
+/// or by another program, see [`DisplayManager::take_noticed`]
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Noticed {
+    pub display: WrappedDisplay,
+    pub control: Control,
+    /// The value before, as last read or written
+    pub old: u16,
+    pub value: u16,
+}
+
+/// The latest changes noticed by polling, newest last
+#[derive(Clone, Default)]
+pub(crate) struct NoticedLog(Arc<Mutex<VecDeque<Noticed>>>);
+
+impl NoticedLog {
+    pub fn push(&self, noticed: Noticed) {
+        let mut log = self.0.lock().unwrap();
+        if log.len() == NOTICED_KEPT {
+            log.pop_front();
+        }
+        log.push_back(noticed);
+    }
+}
+
+/// Called by background threads once they changed what is shown, see
+/// [`DisplayManager::set_waker`]
+#[derive(Clone, Default)]
+pub(crate) struct Waker(Arc<Mutex<Option<Wake>>>);
+
+type Wake = Box<dyn Fn() + Send>;
+
+impl Waker {
+    pub fn wake(&self) {
+        if let Some(wake) = &*self.0.lock().unwrap() {
+            wake();
+        }
+    }
+}
+
+/// Large changes of continuous controls go through intermediate values instead of jumping
+#[derive(Clone, Copy, Debug)]
+pub struct Fade {
+    /// Time a fade takes, zero to jump right away
+    pub duration: Duration,
+    /// Smallest change faded, in percent of the control's maximum
+    pub threshold: u16,
+}
+
+impl Default for Fade {
+    fn default() -> Self {
+        Self {
+            duration: Duration::ZERO,
+            threshold: 10,
+        }
+    }
+}
+
+impl Fade {
+    /// How long a change from one value to another takes, `None` if it jumps.
+    fn duration(&self, controller: &Controller, from: u16) -> Option<Duration> {
+        let distance = controller.value.abs_diff(from) as u32 * 100;
+        let large = distance >= self.threshold as u32 * controller.maximum.max(1) as u32;
+        (controller.kind.is_continuous() && large && !self.duration.is_zero())
+            .then_some(self.duration)
+    }
 }
 
 pub struct MyDisplay {
-    handle: Arc<Mutex<Handle>>,
+    pub(crate) connection: Box<dyn backend::Backend>,
+    pub id: String,
     pub name: String,
-    pub controls: HashMap<Control, WrappedController>,
+    pub manufacturer: Option<String>,
+    /// Serial number from the EDID, if the display has one
+    pub serial: Option<String>,
+    /// The EDID as the backend read it, decoded by [`crate::edid::Edid`]
+    pub edid: Option<Vec<u8>>,
+    /// Backend the display was found by, e.g. `i2c-dev`, or `helper` through the root helper
+    pub backend: String,
+    pub controls: BTreeMap<Control, WrappedController>,
+    pub tuning: Tuning,
+    pub retry: Retry,
+    /// Controls kept even though the capabilities leave them out, found working by probing
+    pub unadvertised: Vec<Control>,
+    /// Curves between the percent shown and the value written that apply to the display
+    pub mappings: Vec<Mapping>,
+    /// How failed writes to the display are reported
+    pub errors: ErrorPolicy,
+    /// Names the config gives values of non-continuous controls, by control
+    pub value_names: BTreeMap<Control, Vec<(String, u16)>>,
+    /// What may be written to the display, anything if unset
+    pub lock: Option<Lock>,
+    /// Connector and mode the OS reports, as of the last refresh
+    pub output: Option<Output>,
+    /// Capabilities as read on refresh, without the range of continuous features
+    pub advertised: Option<Capabilities>,
+    write_error: Mutex<Option<String>>,
+    /// By code, the value before the latest write and the value the display last answered or took
+    known: Mutex<HashMap<u8, (Option<u16>, u16)>>,
+    /// Name shown instead of the model name, see [`MyDisplay::label`]
+    alias: Mutex<Option<String>>,
+    stale: AtomicBool,
+    powered_off: AtomicBool,
+    unreachable: AtomicBool,
+    failures: Failures,
 }
 
 impl MyDisplay {
-    pub fn new(handle: Handle, name: String) -> Self {
-        let mut controls = HashMap::new();
+    pub fn new(handle: Handle, id: String, name: String, manufacturer: Option<String>) -> Self {
+        Self::from_handle(SendHandle::new(handle), id, name, manufacturer)
+    }
+
+    fn from_handle(
+        handle: SendHandle,
+        id: String,
+        name: String,
+        manufacturer: Option<String>,
+    ) -> Self {
+        Self::with_connection(
+            Box::new(backend::Ddc(Mutex::new(handle))),
+            id,
+            name,
+            manufacturer,
+        )
+    }
+
+    #[cfg(all(unix, feature = "helper"))]
+    pub fn from_helper(client: helper::Client, listed: helper::Listed) -> Self {
+        let connection = backend::Helper {
+            client,
+            id: listed.id.clone(),
+        };
+        let mut display = Self::with_connection(
+            Box::new(connection),
+            listed.id,
+            listed.name,
+            listed.manufacturer,
+        );
+        display.serial = listed.serial;
+        display.edid = listed.edid;
+        display.backend = "helper".to_string();
+        display
+    }
+
+    /// The internal panel, named "Built-in display" with the backlight's name as id.
+    #[cfg(all(target_os = "linux", feature = "sysfs"))]
+    pub(crate) fn from_backlight(backlight: crate::sysfs::Backlight) -> Self {
+        let id = backlight.name.clone();
+        let mut display = Self::with_connection(
+            Box::new(backlight),
+            id,
+            "Built-in display".to_string(),
+            None,
+        );
+        display.backend = "sysfs".to_string();
+        display
+    }
+
+    /// The internal panel on Windows, named "Built-in display" with the WMI instance as id.
+    #[cfg(all(windows, feature = "wmi"))]
+    pub(crate) fn from_wmi(panel: crate::wmi::Panel) -> Self {
+        let id = panel.instance.clone();
+        let mut display =
+            Self::with_connection(Box::new(panel), id, "Built-in display".to_string(), None);
+        display.backend = "wmi".to_string();
+        display
+    }
+
+    /// An output dimmed through its gamma ramps, named after the model in its EDID with the
+    /// output as id.
+    #[cfg(all(target_os = "linux", feature = "gamma"))]
+    pub(crate) fn from_gamma(gamma: crate::gamma::Gamma) -> Self {
+        let id = gamma.output.clone();
+        let name = gamma.model.clone().unwrap_or_else(|| id.clone());
+        let edid = gamma.edid.clone();
+        let mut display = Self::with_connection(Box::new(gamma), id, name, None);
+        display.edid = edid;
+        display.backend = "gamma".to_string();
+        display
+    }
+
+    /// A simulated display, see [`crate::fake`].
+    pub(crate) fn from_fake(fake: FakeDisplay) -> Self {
+        let edid = fake.edid();
+        let mut display = Self::with_connection(
+            Box::new(fake.connection()),
+            fake.id,
+            fake.name,
+            fake.manufacturer,
+        );
+        display.serial = fake.serial;
+        display.edid = Some(edid);
+        display.output = fake.output;
+        display.backend = "fake".to_string();
+        display
+    }
+
+    fn with_connection(
+        connection: Box<dyn backend::Backend>,
+        id: String,
+        name: String,
+        manufacturer: Option<String>,
+    ) -> Self {
+        #[cfg(feature = "trace")]
+        let connection = crate::trace::wrap(connection, &id);
+        let mut controls = BTreeMap::new();
         for control in ALL_CONTROLS {
             controls.insert(
                 control,
                 Arc::new(RwLock::new(Controller {
                     kind: control,
                     value: 0,
+                    maximum: 100,
+                    pending: false,
+                    previewed: false,
+                    confirmed: None,
+                    failed: false,
                 })),
             );
         }
 
         Self {
-            handle: Arc::new(Mutex::new(handle)),
+            connection,
+            id,
             name,
+            manufacturer,
+            serial: None,
+            edid: None,
+            backend: String::new(),
             controls,
+            tuning: Tuning::default(),
+            retry: Retry::default(),
+            unadvertised: vec![],
+            mappings: vec![],
+            errors: ErrorPolicy::default(),
+            value_names: BTreeMap::new(),
+            lock: None,
+            output: None,
+            advertised: None,
+            write_error: Mutex::new(None),
+            known: Mutex::new(HashMap::new()),
+            alias: Mutex::new(None),
+            stale: AtomicBool::new(false),
+            powered_off: AtomicBool::new(false),
+            unreachable: AtomicBool::new(false),
+            failures: Failures::default(),
         }
     }
 
-    fn load(&self) {
+    /// Reads the capabilities and the current values. Controls the display doesn't advertise are
+    /// dropped unless probing found them working, without capabilities every control that
+    /// answers is kept.
+    fn load(&mut self, progress: &Sender<Progress>) {
+        let _ = progress.send(Progress::new(self.label(), Status::Reading));
+        let mut answered = false;
+        let mut silent = vec![];
+        let capabilities = self
+            .connection
+            .capabilities_string()
+            .ok()
+            .and_then(|raw| Capabilities::parse(&raw, |_| None).ok());
         for control in ALL_CONTROLS {
-            let value = self.get(control.clone());
-            let mut controller = self.controls.get(&control).unwrap().write().unwrap();
-            controller.value = value;
+            if capabilities
+                .as_ref()
+                .is_some_and(|capabilities| capabilities.feature(control as u8).is_none())
+                && !self.unadvertised.contains(&control)
+            {
+                self.controls.remove(&control);
+                continue;
+            }
+            match self.get_raw(control as u8) {
+                Ok((value, maximum)) => {
+                    let mut controller = self.controls.get(&control).unwrap().write().unwrap();
+                    controller.value = control.mask(value);
+                    controller.maximum = maximum;
+                    answered = true;
+                }
+                Err(_) if !control.is_core() => {
+                    self.controls.remove(&control);
+                }
+                Err(_) => silent.push(control),
+            }
+        }
+        // A sleeping display enumerates fine but doesn't answer, keep the default then.
+        if capabilities.is_none() && answered {
+            for control in silent {
+                self.controls.remove(&control);
+            }
         }
+        self.advertised = capabilities;
+        // Controls the display doesn't have failing to read say nothing about the connection
+        self.unreachable.store(!answered, Ordering::SeqCst);
+        let status = match answered {
+            true => Status::Ready,
+            false => Status::Failed("no control answered".to_string()),
+        };
+        let _ = progress.send(Progress::new(self.label(), status));
     }
 
-    pub fn get(&self, control: Control) -> u16 {
-        let mut handle = self.handle.lock().unwrap();
-        handle.get_vcp_feature(control as u8).unwrap().value()
+    pub fn get(&self, control: Control) -> Result<u16, Error> {
+        Ok(control.mask(self.get_vcp(control as u8)?))
+    }
+
+    /// Values a non-continuous control can take with their names where known, as advertised.
+    /// Names from the config replace those advertised, and add the values they name.
+    pub fn allowed_values(&self, control: Control) -> Vec<(u16, Option<String>)> {
+        let feature = self
+            .advertised
+            .as_ref()
+            .and_then(|capabilities| capabilities.feature(control as u8));
+        let mut values: Vec<(u16, Option<String>)> = match feature.map(|feature| &feature.kind) {
+            Some(FeatureKind::Discrete { values }) => values
+                .iter()
+                .map(|(value, name)| (*value as u16, name.clone()))
+                .collect(),
+            _ => vec![],
+        };
+        for (name, value) in self.value_names.get(&control).into_iter().flatten() {
+            match values.iter_mut().find(|(allowed, _)| allowed == value) {
+                Some((_, known)) => *known = Some(name.clone()),
+                None => values.push((*value, Some(name.clone()))),
+            }
+        }
+        values
+    }
+
+    /// The curve of a continuous control, an entry naming the display beating one for every
+    /// display.
+    pub fn mapping(&self, control: Control) -> Option<&Mapping> {
+        if !control.is_continuous() {
+            return None;
+        }
+        let mut mappings = self.mappings.iter().filter(|m| m.control == control);
+        mappings
+            .clone()
+            .find(|mapping| mapping.display.is_some())
+            .or_else(|| mappings.next())
+    }
+
+    /// Whether failed writes to the display are only warned about.
+    pub fn is_lenient(&self) -> bool {
+        self.errors == ErrorPolicy::Lenient
+    }
+
+    /// The percent shown for the control, through its curve if it has one.
+    pub fn percent(&self, controller: &Controller) -> u16 {
+        match self.mapping(controller.kind) {
+            Some(mapping) => mapping
+                .percent(controller.value, controller.maximum)
+                .round() as u16,
+            None => controller.percent(),
+        }
+    }
+
+    /// The value a level writes to the control, percentages through its curve if it has one.
+    pub fn resolve(&self, control: Control, level: Level, maximum: u16) -> u16 {
+        match (level, self.mapping(control)) {
+            (Level::Percent(percent), Some(mapping)) => mapping.value(percent, maximum),
+            (level, _) => level.resolve(maximum),
+        }
+    }
+
+    /// The value an adjustment writes to a control at `value`, steps in percent through its
+    /// curve if it has one.
+    pub fn adjust(&self, control: Control, adjust: Adjust, value: u16, maximum: u16) -> u16 {
+        match (adjust, self.mapping(control)) {
+            (Adjust::To(level), _) => self.resolve(control, level, maximum),
+            (Adjust::ByPercent(percent), Some(mapping)) => {
+                mapping.value(mapping.percent(value, maximum) + percent, maximum)
+            }
+            (adjust, None) | (adjust @ Adjust::By(_), _) => adjust.resolve(value, maximum),
+        }
+    }
+
+    /// The value as shown to users: a number, or the name of a non-continuous value.
+    pub fn describe(&self, control: Control, value: u16) -> String {
+        if control.is_continuous() {
+            return value.to_string();
+        }
+        self.allowed_values(control)
+            .into_iter()
+            .find(|(allowed, _)| *allowed == value)
+            .and_then(|(_, name)| name)
+            .unwrap_or_else(|| format!("0x{value:02x}"))
+    }
+
+    /// The value of a non-continuous control by name, like `dp1`, `"HDMI 2"` or `usb-c1` for
+    /// inputs. Names from the config come first, then those the display advertises and those
+    /// of the MCCS spec. Raw values, also as `0x` hex, are taken as they are.
+    pub fn value_named(&self, control: Control, name: &str) -> Option<u16> {
+        let name = name.trim();
+        let raw = match name.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16).ok(),
+            None => name.parse().ok(),
+        };
+        if raw.is_some() {
+            return raw;
+        }
+        // Spelling, spacing and the usual abbreviations don't matter
+        let simplify = |name: &str| {
+            name.to_lowercase()
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+                .replace("displayport", "dp")
+                .replace("analog", "vga")
+                .replace("typec", "usbc")
+        };
+        let wanted = simplify(name);
+        let configured = self.value_names.get(&control).into_iter().flatten();
+        if let Some((_, value)) = configured
+            .clone()
+            .find(|(name, _)| simplify(name) == wanted)
+        {
+            return Some(*value);
+        }
+        let standard = crate::capabilities::standard_values(control as u8)
+            .into_iter()
+            .map(|(value, name)| (value as u16, name));
+        self.allowed_values(control)
+            .into_iter()
+            .chain(standard)
+            .find(|(_, name)| name.as_deref().is_some_and(|name| simplify(name) == wanted))
+            .map(|(value, _)| value)
+    }
+
+    /// Reads any VCP code, including ones not modeled as a `Control`.
+    pub fn get_vcp(&self, code: u8) -> Result<u16, Error> {
+        Ok(self.get_raw(code)?.0)
+    }
+
+    /// Reads the current and maximum value of any VCP code, for poking at codes the display
+    /// doesn't advertise.
+    pub fn get_raw(&self, code: u8) -> Result<(u16, u16), Error> {
+        let result = match self.is_stale() {
+            true => Err(Error::Gone),
+            false => self.retry.run(|| self.read_logged(code)),
+        };
+        self.note_answer(code, result.is_ok());
+        self.note_reachable(result.as_ref().err());
+        result
+    }
+
+    /// [`MyDisplay::get_vcp`] through a session locked for a series of reads and writes.
+    pub(crate) fn get_in(&self, session: &mut dyn Session, code: u8) -> Result<u16, Error> {
+        let result = self.retry.run(|| session.get_vcp(code));
+        self.note_answer(code, result.is_ok());
+        self.note_reachable(result.as_ref().err());
+        result
+    }
+
+    /// Reads a code the display may not have, once. Failing isn't taken for the display no
+    /// longer answering.
+    pub(crate) fn read_raw(&self, code: u8) -> Result<(u16, u16), Error> {
+        match self.is_stale() {
+            true => Err(Error::Gone),
+            false => self.read_logged(code),
+        }
+    }
+
+    fn read_logged(&self, code: u8) -> Result<(u16, u16), Error> {
+        let result = self.connection.read_vcp(code);
+        match &result {
+            Ok((value, maximum)) => {
+                debug!(display = self.id, "read 0x{code:02X}: {value} of {maximum}");
+                let value = Control::from_code(code).map_or(*value, |control| control.mask(*value));
+                let mut known = self.known.lock().unwrap();
+                known.entry(code).or_insert((None, value)).1 = value;
+            }
+            Err(err) => warn!(display = self.id, "reading 0x{code:02X} failed: {err}"),
+        }
+        result
+    }
+
+    /// Writes any VCP code and reads it back, returning the current and maximum value the
+    /// display answers with. The control with the code, if any, takes the value read, masked
+    /// like any read of it.
+    pub fn set_raw(&self, code: u8, value: u16) -> Result<(u16, u16), Error> {
+        self.set_vcp(code, value)?;
+        let (current, maximum) = self.get_raw(code)?;
+        if let Some((control, controller)) = self
+            .controls
+            .iter()
+            .find(|(control, _)| **control as u8 == code)
+        {
+            let mut controller = controller.write().unwrap();
+            controller.value = control.mask(current);
+            controller.maximum = maximum;
+        }
+        Ok((current, maximum))
+    }
+
+    pub fn set(&self, control: Control, value: u16) -> Result<(), Error> {
+        self.set_vcp(control as u8, value)
+    }
+
+    /// Codes holding settings of the display: those it advertises as readable and writable
+    /// values, or the controls it answered for if it has no capabilities.
+    pub fn settings(&self) -> Vec<u8> {
+        let mut codes: Vec<u8> = match &self.advertised {
+            Some(capabilities) => capabilities
+                .features
+                .iter()
+                .filter(|feature| feature.readable && feature.writable)
+                .filter(|feature| !matches!(feature.kind, FeatureKind::Table))
+                .map(|feature| feature.code)
+                .collect(),
+            None => self.controls.keys().map(|control| *control as u8).collect(),
+        };
+        codes.retain(|code| !NOT_SETTINGS.contains(code));
+        codes.sort_unstable();
+        codes.dedup();
+        codes
+    }
+
+    /// Reads every code of [`MyDisplay::settings`], discrete values without the high byte some
+    /// displays fill with noise.
+    pub fn read_settings(&self) -> Vec<(u8, Result<u16, Error>)> {
+        self.settings()
+            .into_iter()
+            .map(|code| {
+                let discrete = match self.advertised.as_ref().and_then(|c| c.feature(code)) {
+                    Some(feature) => matches!(feature.kind, FeatureKind::Discrete { .. }),
+                    None => Control::from_code(code).is_some_and(|c| !c.is_continuous()),
+                };
+                let value = self.get_vcp(code).map(|value| match discrete {
+                    true => value & 0xFF,
+                    false => value,
+                });
+                (code, value)
+            })
+            .collect()
+    }
+
+    /// Writes the values in order, going on past failures, and returns the codes that failed.
+    /// The controls with the codes take the values read back.
+    pub fn write_settings(&self, values: &[(u8, u16)]) -> Vec<(u8, Error)> {
+        values
+            .iter()
+            .filter_map(|&(code, value)| self.set_raw(code, value).err().map(|err| (code, err)))
+            .collect()
+    }
+
+    pub fn set_vcp(&self, code: u8, value: u16) -> Result<(), Error> {
+        self.set_in(&mut *self.session(), code, value)
+    }
+
+    /// [`MyDisplay::set_vcp`] through a session locked for a series of reads and writes.
+    pub(crate) fn set_in(
+        &self,
+        session: &mut dyn Session,
+        code: u8,
+        value: u16,
+    ) -> Result<(), Error> {
+        if let Some(refusal) = self.lock_refusal(code, value) {
+            self.note_write(Some(refusal.clone()));
+            return Err(Error::Locked(refusal));
+        }
+        let result = self.retry.run(|| session.set_vcp(code, value));
+        if result.is_ok() {
+            self.note_written(code, value);
+        }
+        self.note_write(result.as_ref().err().map(ToString::to_string));
+        self.note_answer(code, result.is_ok());
+        self.note_reachable(result.as_ref().err());
+        result
+    }
+
+    /// The lowest and highest value the VCP code may be written with, `None` if the lock
+    /// refuses it.
+    pub fn lock_bounds(&self, code: u8) -> Option<(u16, u16)> {
+        match &self.lock {
+            Some(lock) => lock.bounds(code, self.maximum_of(code)),
+            None => Some((0, u16::MAX)),
+        }
+    }
+
+    fn lock_refusal(&self, code: u8, value: u16) -> Option<String> {
+        let lock = self.lock.as_ref()?;
+        lock.refusal(code, value, self.maximum_of(code))
+    }
+
+    /// The maximum the control of the code reported, or the usual 100.
+    fn maximum_of(&self, code: u8) -> u16 {
+        Control::from_code(code)
+            .and_then(|control| self.controls.get(&control))
+            .map_or(100, |controller| controller.read().unwrap().maximum)
+    }
+
+    /// Whether the display stopped answering reads and writes, even after retrying. Cleared as
+    /// soon as it answers again.
+    pub fn is_unreachable(&self) -> bool {
+        self.unreachable.load(Ordering::SeqCst)
+    }
+
+    /// Notes whether a retried read or write got through, reporting the display once when it
+    /// becomes unreachable.
+    pub(crate) fn note_reachable(&self, error: Option<&Error>) {
+        let error = match error {
+            None => {
+                self.unreachable.store(false, Ordering::SeqCst);
+                return;
+            }
+            // Unplugged displays show as disconnected instead
+            Some(Error::Gone) => return,
+            Some(error) => error,
+        };
+        if self.unreachable.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        error!(display = self.id, "stopped answering: {error}");
+        if let Some(sender) = &*self.failures.lock().unwrap() {
+            let _ = sender.send(Failure {
+                id: self.id.clone(),
+                name: self.label(),
+                error: error.to_string(),
+            });
+        }
+    }
+
+    /// Notes for the control with the code whether the display just answered, for
+    /// [`Controller::is_unconfirmed`].
+    pub(crate) fn note_answer(&self, code: u8, answered: bool) {
+        let Some((_, controller)) = self
+            .controls
+            .iter()
+            .find(|(control, _)| **control as u8 == code)
+        else {
+            return;
+        };
+        let mut controller = controller.write().unwrap();
+        controller.failed = !answered;
+        if answered {
+            controller.confirmed = Some(Instant::now());
+        }
+    }
+
+    /// Why the last write to the display failed, cleared once a write succeeds again.
+    pub fn write_error(&self) -> Option<String> {
+        self.write_error.lock().unwrap().clone()
+    }
+
+    /// Notes that the display took the value, replacing the one it had.
+    pub(crate) fn note_written(&self, code: u8, value: u16) {
+        let mut known = self.known.lock().unwrap();
+        let before = known.get(&code).map(|(_, now)| *now);
+        known.insert(code, (before, value));
+    }
+
+    /// The value a change of the code to `value` replaced, as far as the display told: the one
+    /// before the latest write if `value` was written already, the last one known otherwise.
+    pub fn replaced(&self, code: u8, value: u16) -> Option<u16> {
+        let (before, now) = *self.known.lock().unwrap().get(&code)?;
+        match now == value {
+            true => before,
+            false => Some(now),
+        }
+    }
+
+    pub(crate) fn note_write(&self, error: Option<String>) {
+        *self.write_error.lock().unwrap() = error;
+    }
+
+    /// Locks the connection for a series of writes, none are tried once the display is gone.
+    pub(crate) fn session(&self) -> Box<dyn Session + '_> {
+        match self.is_stale() {
+            true => Box::new(backend::Gone),
+            false => Box::new(backend::Logged {
+                session: self.connection.session(),
+                display: &self.id,
+            }),
+        }
+    }
+
+    /// Whether the display was unplugged. Stale displays fail right away instead of waiting on
+    /// a bus that may never answer, a refresh replaces them if they come back.
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn mark_stale(&self) {
+        self.stale.store(true, Ordering::SeqCst);
+    }
+
+    /// Reads and parses the capabilities string, along with the range of continuous features.
+    pub fn capabilities(&self) -> Result<Capabilities, Error> {
+        if self.is_stale() {
+            return Err(Error::Gone);
+        }
+        let raw = self.connection.capabilities_string()?;
+        let capabilities = Capabilities::parse(&raw, |code| Some(self.get_raw(code).ok()?.1))
+            .map_err(Error::Capabilities)?;
+        Ok(capabilities)
+    }
+
+    /// Whether the display reports being on, a display in standby often doesn't answer at all.
+    pub fn is_awake(&self) -> bool {
+        matches!(self.get_vcp(POWER_MODE), Ok(mode) if mode & 0xFF == 1)
+    }
+
+    /// Whether the display takes a reset code. Displays without capabilities are assumed to.
+    pub fn can_reset(&self, code: u8) -> bool {
+        self.advertised
+            .as_ref()
+            .is_none_or(|capabilities| capabilities.feature(code).is_some())
+    }
+
+    /// Restores factory defaults with a reset code like `RESET_ALL` or a control's
+    /// `reset_code`, then reads back the controls it covers. Returns the values they have now,
+    /// the defaults, leaving out controls that didn't answer.
+    pub fn restore_defaults(&self, code: u8) -> Result<Vec<(Control, u16)>, Error> {
+        self.set_vcp(code, 1)?;
+        thread::sleep(RESET_DELAY);
+        let mut defaults = vec![];
+        for (control, controller) in &self.controls {
+            if code != RESET_ALL && control.reset_code() != Some(code) {
+                continue;
+            }
+            if let Ok(value) = self.get(*control) {
+                controller.write().unwrap().value = value;
+                defaults.push((*control, value));
+            }
+        }
+        Ok(defaults)
+    }
+
+    /// Reads the power mode, `None` for values outside MCCS.
+    pub fn power(&self) -> Result<Option<PowerMode>, Error> {
+        Ok(PowerMode::from_vcp(self.get_vcp(POWER_MODE)?))
+    }
+
+    /// Turns the display on, to standby or off. A display going to sleep may not acknowledge
+    /// the write, that counts as done if it no longer reports being on. Turning it on is tried
+    /// a few times.
+    pub fn set_power(&self, mode: PowerMode) -> Result<(), Error> {
+        let tries = match mode {
+            PowerMode::On => POWER_ON_TRIES,
+            _ => 1,
+        };
+        let mut result = Ok(());
+        for attempt in 0..tries {
+            if attempt > 0 {
+                thread::sleep(POWER_ON_DELAY);
+            }
+            result = self.set_vcp(POWER_MODE, mode.to_vcp());
+            if result.is_ok() {
+                break;
+            }
+            if mode != PowerMode::On && !self.is_awake() {
+                result = Ok(());
+                self.note_write(None);
+                break;
+            }
+        }
+        if result.is_ok() {
+            self.set_powered_off(mode != PowerMode::On);
+        }
+        result
     }
 
-    pub fn set(&self, control: Control, value: u16) -> () {
-        let mut handle = self.handle.lock().unwrap();
-        handle.set_vcp_feature(control as u8, value).unwrap();
+    /// Whether the display was put to standby or off and hasn't been seen on since. These
+    /// displays don't answer, so background reads leave them alone.
+    pub fn is_powered_off(&self) -> bool {
+        self.powered_off.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_powered_off(&self, off: bool) {
+        self.powered_off.store(off, Ordering::SeqCst);
+    }
+
+    /// Reads which way the display is turned, `None` if it doesn't know.
+    pub fn orientation(&self) -> Result<Option<Orientation>, Error> {
+        Ok(Orientation::from_vcp(self.get_vcp(orientation::CODE)?))
+    }
+
+    /// Asks the display to switch orientation, only few accept this instead of a physical pivot.
+    pub fn set_orientation(&self, orientation: Orientation) -> Result<(), Error> {
+        self.set_vcp(orientation::CODE, orientation.to_vcp())
+    }
+
+    /// Blinks the display by dimming it, so it can be told apart from the others. The
+    /// brightness it had is written back even if dimming it failed.
+    pub fn flash(&self, times: usize) -> Result<(), Error> {
+        let brightness = self.get(Control::Brightness)?;
+        for _ in 0..times {
+            let dimmed = self.set(Control::Brightness, brightness / 4);
+            thread::sleep(FLASH_DELAY);
+            // A display may have taken the value even though the write failed
+            self.set(Control::Brightness, brightness)?;
+            dimmed?;
+            thread::sleep(FLASH_DELAY);
+        }
+        Ok(())
+    }
+
+    /// Dims the display for `duration` and brings it back, [`MyDisplay::flash`] without the
+    /// blinking.
+    pub fn dim(&self, duration: Duration) -> Result<(), Error> {
+        let brightness = self.get(Control::Brightness)?;
+        let dimmed = self.set(Control::Brightness, brightness / 4);
+        thread::sleep(duration);
+        self.set(Control::Brightness, brightness)?;
+        dimmed
+    }
+
+    /// Checks whether the display is the one a user referred to by model name, serial or id.
+    pub fn matches(&self, query: &str) -> bool {
+        self.name == query
+            || self.id == query
+            || self.serial.as_deref() == Some(query)
+            || self.alias().as_deref() == Some(query)
+    }
+
+    /// What the display is called wherever it is shown: its alias, or its model name without one.
+    pub fn label(&self) -> String {
+        self.alias().unwrap_or_else(|| self.name.clone())
+    }
+
+    pub fn alias(&self) -> Option<String> {
+        self.alias.lock().unwrap().clone()
+    }
+
+    pub fn set_alias(&self, alias: Option<String>) {
+        *self.alias.lock().unwrap() = alias;
+    }
+
+    /// What the alias is kept by: the serial number, so identical models keep theirs when
+    /// swapping ports, or the id of displays without one.
+    pub fn alias_key(&self) -> &str {
+        self.serial.as_deref().unwrap_or(&self.id)
+    }
+
+    /// The identity and current values of the display.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            alias: self.alias(),
+            manufacturer: self.manufacturer.clone(),
+            serial: self.serial.clone(),
+            backend: self.backend.clone(),
+            output: self.output.clone(),
+            controls: self
+                .controls
+                .iter()
+                .map(|(control, controller)| (*control, *controller.read().unwrap()))
+                .collect(),
+        }
+    }
+
+    /// Identifies the display across reboots and reconnects, unlike `id` which may follow the
+    /// bus or port it is connected to. Displays without a serial number fall back to `id`.
+    pub fn stable_id(&self) -> String {
+        match &self.serial {
+            Some(serial) => format!(
+                "{}:{}:{serial}",
+                self.manufacturer.as_deref().unwrap_or_default(),
+                self.name
+            ),
+            None => self.id.clone(),
+        }
     }
 }
 
-pub type WrappedDisplay = Arc<MyDisplay>;
-pub type WrappedController = Arc<RwLock<Controller>>;
+/// A display as other tools see it, see [`MyDisplay::snapshot`]
+#[derive(Serialize)]
+#[non_exhaustive]
+pub struct Snapshot {
+    pub id: String,
+    pub name: String,
+    pub alias: Option<String>,
+    pub manufacturer: Option<String>,
+    pub serial: Option<String>,
+    pub backend: String,
+    pub output: Option<Output>,
+    pub controls: BTreeMap<Control, Controller>,
+}
 
-struct Change {
-    display: WrappedDisplay,
-    controller: Controller,
+/// A step of a refresh, showing what enumeration is busy with
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Progress {
+    /// The bus or backend being probed, or the name of a display found on it
+    pub source: String,
+    pub status: Status,
+}
+
+impl Progress {
+    pub(crate) fn new(source: impl Into<String>, status: Status) -> Self {
+        Self {
+            source: source.into(),
+            status,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Status {
+    Probing,
+    NoDisplay,
+    Found,
+    Reading,
+    Ready,
+    Failed(String),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Probing => write!(f, "probing"),
+            Status::NoDisplay => write!(f, "no display"),
+            Status::Found => write!(f, "found"),
+            Status::Reading => write!(f, "reading values"),
+            Status::Ready => write!(f, "ready"),
+            Status::Failed(reason) => write!(f, "failed: {reason}"),
+        }
+    }
+}
+
+/// Displays found by a refresh, see [`DisplayManager::start_refresh`]
+pub struct Scan {
+    displays: Result<Vec<WrappedDisplay>, Error>,
+    warnings: Vec<String>,
+}
+
+/// The settings a refresh needs, owned so it can run on another thread
+struct Scanner {
+    timeouts: HashMap<Backend, Duration>,
+    tuning: HashMap<String, Tuning>,
+    unadvertised: HashMap<String, Vec<Control>>,
+    mappings: Vec<Mapping>,
+    errors: Vec<ErrorRule>,
+    value_names: Vec<ValueNames>,
+    lock: Option<Lock>,
+    aliases: HashMap<String, String>,
+    fakes: Vec<FakeDisplay>,
+    retry: Retry,
+    failures: Failures,
+    #[cfg(all(unix, feature = "helper"))]
+    helper_socket: PathBuf,
+}
+
+impl Scanner {
+    fn configure(&self, display: &mut MyDisplay) {
+        display.tuning = self.tuning.get(&display.id).copied().unwrap_or_default();
+        display.retry = self.retry;
+        display.unadvertised = self
+            .unadvertised
+            .get(&display.stable_id())
+            .cloned()
+            .unwrap_or_default();
+        display.mappings = (self.mappings.iter())
+            .filter(|mapping| mapping.applies(display))
+            .cloned()
+            .collect();
+        display.errors = policy::resolve(&self.errors, display);
+        display.value_names = values::resolve(&self.value_names, display);
+        display.lock = self.lock.clone();
+        display.set_alias(self.aliases.get(display.alias_key()).cloned());
+        display.failures = self.failures.clone();
+    }
+
+    /// Loads the displays at the same time, each on a thread of its own, so a display slow to
+    /// answer doesn't hold up the others. Each reports being ready as soon as it is.
+    fn load_all(
+        &self,
+        mut displays: Vec<MyDisplay>,
+        progress: &Sender<Progress>,
+    ) -> Vec<WrappedDisplay> {
+        thread::scope(|scope| {
+            for display in &mut displays {
+                self.configure(display);
+                scope.spawn(move || display.load(progress));
+            }
+        });
+        displays.into_iter().map(Arc::new).collect()
+    }
+
+    fn scan(self, progress: &Sender<Progress>) -> Scan {
+        let mut warnings = vec![];
+        let fakes = match self.fakes.is_empty() {
+            true => fake::from_env(&mut warnings),
+            false => self.fakes.clone(),
+        };
+        // Real displays are left alone, so tests never touch a bus
+        if !fakes.is_empty() {
+            let found = fakes.into_iter().map(MyDisplay::from_fake).collect();
+            return Scan {
+                displays: Ok(self.load_all(found, progress)),
+                warnings,
+            };
+        }
+        let mut found = vec![];
+        for display in crate::enumerate::enumerate(&self.timeouts, &mut warnings, progress) {
+            let serial = display.info.serial_number.clone();
+            let edid = display.info.edid_data.clone();
+            let backend = display.info.backend.to_string();
+            let mut display = MyDisplay::from_handle(
+                display.handle,
+                display.info.id.clone(),
+                display
+                    .info
+                    .model_name
+                    .unwrap_or_else(|| display.info.serial_number.unwrap_or(display.info.id)),
+                display.info.manufacturer_id,
+            );
+            display.serial = serial;
+            display.edid = edid;
+            display.backend = backend;
+            found.push(display);
+        }
+        // Only Linux and Windows add to them or fill in their outputs
+        #[cfg_attr(not(any(target_os = "linux", windows)), allow(unused_mut))]
+        let mut displays = self.load_all(found, progress);
+        #[cfg(all(unix, feature = "helper"))]
+        if displays.is_empty() {
+            if let Some(client) = helper::Client::new(&self.helper_socket) {
+                match client.list() {
+                    Ok(listed) => {
+                        let found = listed
+                            .into_iter()
+                            .map(|listed| MyDisplay::from_helper(client.clone(), listed))
+                            .collect();
+                        displays = self.load_all(found, progress);
+                    }
+                    Err(err) => {
+                        return Scan {
+                            displays: Err(Error::Helper(err)),
+                            warnings,
+                        }
+                    }
+                }
+            }
+        }
+        // Only after the helper, which is asked when no DDC display was found
+        #[cfg(all(target_os = "linux", feature = "sysfs"))]
+        if let Some(backlight) = crate::sysfs::Backlight::find() {
+            let mut display = MyDisplay::from_backlight(backlight);
+            self.configure(&mut display);
+            display.load(progress);
+            displays.push(Arc::new(display));
+        }
+        #[cfg(all(windows, feature = "wmi"))]
+        if let Some(panel) = crate::wmi::Panel::find() {
+            let mut display = MyDisplay::from_wmi(panel);
+            self.configure(&mut display);
+            display.load(progress);
+            displays.push(Arc::new(display));
+        }
+        // Last, for the outputs none of the others reach
+        #[cfg(all(target_os = "linux", feature = "gamma"))]
+        for gamma in crate::gamma::Gamma::find(&displays) {
+            let mut display = MyDisplay::from_gamma(gamma);
+            self.configure(&mut display);
+            display.load(progress);
+            displays.push(Arc::new(display));
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let outputs = crate::output::Outputs::query();
+            // Fake displays come with made up outputs
+            for display in displays
+                .iter_mut()
+                .filter(|display| display.backend != "fake")
+            {
+                // Nothing else holds the displays of a scan yet
+                let display = Arc::get_mut(display).unwrap();
+                display.output = outputs.of(display);
+            }
+        }
+        #[cfg(windows)]
+        {
+            let mut monitors = crate::output::monitors().into_iter();
+            for display in displays
+                .iter_mut()
+                .filter(|display| display.backend == "winapi")
+            {
+                let display = Arc::get_mut(display).unwrap();
+                display.output = monitors.next();
+            }
+        }
+        Scan {
+            displays: Ok(displays),
+            warnings,
+        }
+    }
 }
 
+pub type WrappedDisplay = Arc<MyDisplay>;
+pub type WrappedController = Arc<RwLock<Controller>>;
+
 pub struct DisplayManager {
     pub displays: Vec<WrappedDisplay>,
+    #[doc(hidden)]
+    pub enumeration_timeouts: HashMap<Backend, Duration>,
+    /// Tuning of displays by id, applied on refresh
+    #[doc(hidden)]
+    pub tuning: HashMap<String, Tuning>,
+    /// Controls displays don't advertise but have, by [`MyDisplay::stable_id`], kept on refresh
+    #[doc(hidden)]
+    pub unadvertised: HashMap<String, Vec<Control>>,
+    /// Names shown instead of the model name, by [`MyDisplay::alias_key`], applied on refresh
+    #[doc(hidden)]
+    pub aliases: HashMap<String, String>,
+    /// Simulated displays a refresh finds instead of the real ones, see [`crate::fake`]
+    #[doc(hidden)]
+    pub fakes: Vec<FakeDisplay>,
+    /// Socket of the root helper, used when no display is accessible directly
+    #[cfg(all(unix, feature = "helper"))]
+    #[doc(hidden)]
+    pub helper_socket: PathBuf,
+    /// Problems of the last refresh that didn't stop it
+    pub warnings: Vec<String>,
+    /// How large changes are faded in
+    #[doc(hidden)]
+    pub fade: Fade,
+    /// How reads and writes the displays don't answer are retried, applied on refresh
+    #[doc(hidden)]
+    pub retry: Retry,
+    /// Controls raised as others get low, applied to every queued change
+    #[doc(hidden)]
+    pub couplings: Vec<Coupling>,
+    /// Curves between the percent shown and the value written, applied on refresh
+    #[doc(hidden)]
+    pub mappings: Vec<Mapping>,
+    /// Error policies of displays, applied on refresh
+    #[doc(hidden)]
+    pub errors: Vec<ErrorRule>,
+    /// Names of input and colour preset values, applied on refresh
+    #[doc(hidden)]
+    pub value_names: Vec<ValueNames>,
+    /// What may be written to the displays, applied on refresh
+    #[doc(hidden)]
+    pub lock: Option<Lock>,
+    /// Value each coupled target has without its boost, by display id and target
+    unboosted: Mutex<HashMap<(String, Control), u16>>,
+    failures: Failures,
     changes: Arc<Mutex<Vec<Change>>>,
+    parked: Arc<Mutex<Vec<Change>>>,
+    /// Changes queued or being written, parked ones aren't counted
+    queued: Arc<AtomicUsize>,
+    recorder: Arc<Mutex<Recorder>>,
+    waker: Waker,
+    noticed: NoticedLog,
     tx_queue: Sender<()>,
+    /// Displays background threads work on, kept in sync with `displays` by `refresh`
+    polled: Arc<Mutex<Vec<WrappedDisplay>>>,
+    activity: Arc<Mutex<Activity>>,
+    /// Changes held back while previewing, `None` while changes are written
+    preview: Mutex<Option<Vec<Previewed>>>,
+    supervisor: Supervisor,
+    /// The thread writing changes, for [`DisplayManager::set_worker_priority`]
+    worker_thread: Option<ThreadId>,
+}
+
+/// A change not written yet, as listed by [`DisplayManager::queued`]
+pub struct Queued {
+    pub display: WrappedDisplay,
+    pub control: Control,
+    pub value: u16,
+    /// Waiting for the display to answer again
+    pub parked: bool,
+}
+
+/// Waits for the changes queued on a manager, see [`DisplayManager::flusher`]
+#[derive(Clone)]
+pub struct Flusher(Arc<AtomicUsize>);
+
+impl Flusher {
+    /// Waits for queued changes to be written, parked ones stay parked.
+    pub fn flush(&self) {
+        while self.0.load(Ordering::SeqCst) > 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// A control changed while previewing, with the value to go back to on discard
+struct Previewed {
+    display: WrappedDisplay,
+    controller: WrappedController,
+    original: u16,
+}
+
+impl Default for DisplayManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DisplayManager {
     pub fn new() -> Self {
-        let changes = Arc::new(Mutex::new(vec![]));
-        let changes_clone = changes.clone();
+        let changes: Arc<Mutex<Vec<Change>>> = Arc::new(Mutex::new(vec![]));
+        let parked: Arc<Mutex<Vec<Change>>> = Arc::new(Mutex::new(vec![]));
+        let queued = Arc::new(AtomicUsize::new(0));
+        let recorder = Arc::new(Mutex::new(Recorder::default()));
         let (sender, receiver) = channel::<()>();
+        let waker = Waker::default();
 
-        thread::spawn(move || loop {
-            if receiver.recv().is_err() {
-                return;
-            }
-            let mut changes = changes_clone.lock().unwrap();
-            let change: Change = changes.remove(0);
-            drop(changes);
-
-            change
-                .display
-                .set(change.controller.kind, change.controller.value);
+        let worker = Worker {
+            changes: changes.clone(),
+            parked: parked.clone(),
+            queued: queued.clone(),
+            recorder: recorder.clone(),
+            waker: waker.clone(),
+            receiver,
+            last_retry: Instant::now(),
+        };
+        let mut supervisor = Supervisor::default();
+        let (thread_sender, thread) = channel();
+        supervisor.spawn("change queue", move |token| {
+            let _ = thread_sender.send(priority::current());
+            worker.run(token)
         });
 
         Self {
             displays: vec![],
+            enumeration_timeouts: HashMap::new(),
+            tuning: HashMap::new(),
+            unadvertised: HashMap::new(),
+            aliases: HashMap::new(),
+            fakes: vec![],
+            #[cfg(all(unix, feature = "helper"))]
+            helper_socket: PathBuf::from(helper::DEFAULT_SOCKET),
+            warnings: vec![],
+            fade: Fade::default(),
+            retry: Retry::default(),
+            couplings: vec![],
+            mappings: vec![],
+            errors: vec![],
+            value_names: vec![],
+            lock: None,
+            unboosted: Mutex::new(HashMap::new()),
+            failures: Failures::default(),
             changes,
+            parked,
+            queued,
+            recorder,
+            waker,
+            noticed: NoticedLog::default(),
             tx_queue: sender,
+            polled: Arc::new(Mutex::new(vec![])),
+            activity: Arc::new(Mutex::new(Activity::default())),
+            preview: Mutex::new(None),
+            supervisor,
+            worker_thread: thread.recv().ok(),
         }
     }
 
+    /// Schedules the thread writing changes as `priority` says, e.g. ahead of a compile so
+    /// keypresses still reach the displays quickly. A negative niceness needs root or
+    /// `CAP_SYS_NICE` on Linux.
+    pub fn set_worker_priority(&self, priority: &Priority) -> io::Result<()> {
+        priority::apply(self.worker_thread, priority)
+    }
+
+    /// Receives a [`Failure`] whenever a display stops answering even after retrying. Only the
+    /// receiver of the last call gets them.
+    pub fn watch_failures(&self) -> Receiver<Failure> {
+        let (sender, receiver) = channel();
+        *self.failures.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Calls `wake` from background threads whenever they changed what is shown: a change
+    /// written, polled values that changed, a finished refresh or a display plugged in or out.
+    /// Replaces the one set before.
+    pub fn set_waker(&self, wake: impl Fn() + Send + 'static) {
+        *self.waker.0.lock().unwrap() = Some(Box::new(wake));
+    }
+
+    /// The changes polling noticed since the last call, oldest first. Only the latest 100 are
+    /// kept.
+    pub fn take_noticed(&self) -> Vec<Noticed> {
+        self.noticed.0.lock().unwrap().drain(..).collect()
+    }
+
+    /// The displays as of the last refresh, for threads that outlive a refresh.
+    pub fn shared_displays(&self) -> Arc<Mutex<Vec<WrappedDisplay>>> {
+        self.polled.clone()
+    }
+
+    /// Reads back all controls in the background every `interval`, or every `idle_interval`
+    /// while the user is idle or the app isn't focused.
+    pub fn start_polling(&mut self, interval: Duration, idle_interval: Duration) {
+        let poller = Poller {
+            displays: self.polled.clone(),
+            activity: self.activity.clone(),
+            queued: self.queued.clone(),
+            interval,
+            idle_interval,
+            watched_only: false,
+            waker: self.waker.clone(),
+            noticed: self.noticed.clone(),
+        };
+        self.supervisor
+            .spawn("poller", move |token| poller.run(token));
+    }
+
+    /// Like [`DisplayManager::start_polling`], but only reads the display last passed to
+    /// [`DisplayManager::watch`], so values changed at its buttons or by other tools show up.
+    pub fn start_watching(&mut self, interval: Duration, idle_interval: Duration) {
+        let poller = Poller {
+            displays: self.polled.clone(),
+            activity: self.activity.clone(),
+            queued: self.queued.clone(),
+            interval,
+            idle_interval,
+            watched_only: true,
+            waker: self.waker.clone(),
+            noticed: self.noticed.clone(),
+        };
+        self.supervisor
+            .spawn("watcher", move |token| poller.run(token));
+    }
+
+    /// Sets the display read by [`DisplayManager::start_watching`], `None` for none.
+    pub fn watch(&self, display: Option<&MyDisplay>) {
+        self.activity.lock().unwrap().watched = display.map(|display| display.id.clone());
+    }
+
+    /// Records user input, polling speeds up again after being idle.
+    pub fn note_input(&self) {
+        self.activity.lock().unwrap().last_input = Instant::now();
+    }
+
+    pub fn set_focused(&self, focused: bool) {
+        self.activity.lock().unwrap().focused = focused;
+    }
+
+    /// Whether the terminal of the TUI has focus, true unless told otherwise.
+    pub fn is_focused(&self) -> bool {
+        self.activity.lock().unwrap().focused
+    }
+
+    /// Queues a change relative to the current value. Mapped controls move by as much of the
+    /// percent shown as the step is of their range. Non-continuous controls move to the next or
+    /// previous advertised value instead, whatever the size of the step.
     pub fn queue_change(&self, display: WrappedDisplay, controller: WrappedController, value: i16) {
+        let (kind, current, maximum) = {
+            let controller = controller.read().unwrap();
+            (controller.kind, controller.value, controller.maximum)
+        };
+        let value = if let Some(mapping) = display.mapping(kind) {
+            let step = value as f32 * 100.0 / maximum.max(1) as f32;
+            let mapped = mapping.value(mapping.percent(current, maximum) + step, maximum);
+            // Where the curve is steep, a small step rounds back to the value it started at
+            match mapped == current {
+                true => (current as i32 + value.signum() as i32).clamp(0, maximum as i32) as u16,
+                false => mapped,
+            }
+        } else if kind.is_continuous() {
+            (current as i32 + value as i32).clamp(0, maximum as i32) as u16
+        } else {
+            let values = display.allowed_values(kind);
+            if values.is_empty() {
+                return;
+            }
+            let index = match values.iter().position(|(allowed, _)| *allowed == current) {
+                Some(index) => (index as isize + value.signum() as isize)
+                    .rem_euclid(values.len() as isize) as usize,
+                None => 0,
+            };
+            values[index].0
+        };
+        self.queue_set(display, controller, value);
+    }
+
+    /// Queues the same relative change of a control on every display that has it.
+    pub fn queue_change_linked(&self, control: Control, value: i16) {
+        for display in &self.displays {
+            if let Some(controller) = display.controls.get(&control) {
+                self.queue_change(display.clone(), controller.clone(), value);
+            }
+        }
+    }
+
+    /// Queues writing an absolute value, continuous controls are capped at their maximum.
+    pub fn queue_set(&self, display: WrappedDisplay, controller: WrappedController, value: u16) {
+        let (kind, before) = {
+            let controller = controller.read().unwrap();
+            (controller.kind, controller.percent())
+        };
+        self.queue_value(display.clone(), controller, value);
+        for coupling in &self.couplings {
+            if coupling.applies(&display) {
+                self.couple(&display, coupling, kind, before);
+            }
+        }
+    }
+
+    /// Follows a change of `changed`, which was at `before` percent, with the target of the
+    /// coupling. The target's own changes move the value the boost is added to.
+    fn couple(&self, display: &WrappedDisplay, coupling: &Coupling, changed: Control, before: u16) {
+        let (Some(source), Some(target)) = (
+            display.controls.get(&coupling.control),
+            display.controls.get(&coupling.target),
+        ) else {
+            return;
+        };
+        let (current, maximum) = {
+            let target = target.read().unwrap();
+            (target.value, target.maximum)
+        };
+        let boost = |percent| (coupling.boost(percent) as u32 * maximum as u32 / 100) as u16;
+        let percent = source.read().unwrap().percent();
+        let key = (display.id.clone(), coupling.target);
+        let mut unboosted = self.unboosted.lock().unwrap();
+        if changed == coupling.target {
+            unboosted.insert(key, current.saturating_sub(boost(percent)));
+        } else if changed == coupling.control {
+            // Until the target is seen changing, it is taken to carry the boost it should have
+            let base = *unboosted
+                .entry(key)
+                .or_insert_with(|| current.saturating_sub(boost(before)));
+            drop(unboosted);
+            let value = base.saturating_add(boost(percent)).min(maximum);
+            if value != current {
+                self.queue_value(display.clone(), target.clone(), value);
+            }
+        }
+    }
+
+    fn queue_value(&self, display: WrappedDisplay, controller: WrappedController, value: u16) {
+        let code = controller.read().unwrap().kind as u8;
+        // Values past the range of the lock stop at its ends, locked controls aren't changed
+        let Some((lowest, highest)) = display.lock_bounds(code) else {
+            display.note_write(display.lock_refusal(code, value));
+            return;
+        };
+        let value = value.clamp(lowest, highest);
+        let mut control = controller.write().unwrap();
+        let original = control.value;
+        control.value = if control.kind.is_continuous() {
+            value.min(control.maximum)
+        } else {
+            value
+        };
+
+        if let Some(previewed) = self.preview.lock().unwrap().as_mut() {
+            if !control.previewed {
+                control.previewed = true;
+                previewed.push(Previewed {
+                    display,
+                    controller: controller.clone(),
+                    original,
+                });
+            }
+            return;
+        }
+
+        // Holding the controller while queueing keeps changes to it in order
+        let mut changes = self.changes.lock().unwrap();
+        // A change not taken by the worker yet only needs the newer value
+        let queued = changes.iter_mut().find(|queued| {
+            Arc::ptr_eq(&queued.display, &display) && queued.controller.kind == control.kind
+        });
+        match queued {
+            Some(queued) => {
+                queued.controller = *control;
+                queued.fade = self.fade.duration(&control, queued.from);
+            }
+            None => {
+                self.recorder.lock().unwrap().queue(&display.id);
+                changes.push(Change {
+                    display,
+                    controller: *control,
+                    from: original,
+                    fade: self.fade.duration(&control, original),
+                });
+                self.queued.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        drop(changes);
+        self.activity.lock().unwrap().last_write = Some(Instant::now());
+
         match self.tx_queue.send(()) {
             Ok(()) => (),
             Err(err) => {
                 eprintln!("{err}");
             }
         }
+    }
 
-        let mut control = controller.write().unwrap();
-        control.value = max(min(control.value as i16 + value, 100), 0) as u16;
+    /// Holds back changes queued from now on: controllers take the new values, but nothing is
+    /// written until [`DisplayManager::commit_preview`].
+    pub fn start_preview(&self) {
+        self.preview.lock().unwrap().get_or_insert_with(Vec::new);
+    }
 
-        let mut changes = self.changes.lock().unwrap();
-        changes.push(Change {
-            display: display,
-            controller: control.clone(),
-        });
+    pub fn previewing(&self) -> bool {
+        self.preview.lock().unwrap().is_some()
     }
 
-    pub fn refresh(&mut self) -> Result<(), <Handle as DdcHost>::Error> {
-        self.displays.clear();
-        for display in Display::enumerate() {
-            let display = MyDisplay::new(
-                display.handle,
-                display.info.model_name.unwrap_or_else(|| {
-                    display
-                        .info
-                        .serial_number
-                        .unwrap_or_else(|| display.info.id)
-                }),
-            );
+    /// Number of controls changed since the preview started.
+    pub fn previewed(&self) -> usize {
+        self.preview.lock().unwrap().as_ref().map_or(0, Vec::len)
+    }
 
-            display.load();
+    /// Ends the preview, queueing every previewed value. Returns what was queued.
+    pub fn commit_preview(&self) -> Vec<(WrappedDisplay, Control, u16)> {
+        let previewed = self.preview.lock().unwrap().take().unwrap_or_default();
+        let mut queued = vec![];
+        for previewed in previewed {
+            let (kind, value) = {
+                let mut controller = previewed.controller.write().unwrap();
+                controller.previewed = false;
+                (controller.kind, controller.value)
+            };
+            queued.push((previewed.display.clone(), kind, value));
+            self.queue_set(previewed.display, previewed.controller, value);
+        }
+        queued
+    }
 
-            self.displays.push(Arc::new(display));
+    /// Ends the preview, putting every previewed control back to the value it had before.
+    pub fn discard_preview(&self) {
+        let previewed = self.preview.lock().unwrap().take().unwrap_or_default();
+        for previewed in previewed {
+            let mut controller = previewed.controller.write().unwrap();
+            controller.value = previewed.original;
+            controller.previewed = false;
         }
+    }
+
+    /// Enumerates the displays again, blocking until every backend is done.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        let (progress, _) = channel();
+        let scan = self.scanner().scan(&progress);
+        self.finish_refresh(scan)
+    }
+
+    /// Enumerates the displays on another thread, the current ones stay usable until the result
+    /// is passed to [`DisplayManager::finish_refresh`]. Each step is sent to `progress` on the way.
+    pub fn start_refresh(&self, progress: Sender<Progress>) -> Receiver<Scan> {
+        let scanner = self.scanner();
+        let waker = self.waker.clone();
+        let (sender, receiver) = channel();
+        // Not supervised, a hung bus can't be interrupted anyway
+        thread::spawn(move || {
+            let _ = sender.send(scanner.scan(&progress));
+            waker.wake();
+        });
+        receiver
+    }
+
+    /// Replaces the displays with the ones a refresh found.
+    pub fn finish_refresh(&mut self, scan: Scan) -> Result<(), Error> {
+        self.warnings = scan.warnings;
+        // A failed scan keeps the displays there were
+        let old = replace(&mut self.displays, scan.displays?);
+        // Threads still holding a display that wasn't found again stop talking to it
+        for display in old {
+            if !self.displays.iter().any(|new| new.id == display.id) {
+                display.mark_stale();
+            }
+        }
+        *self.polled.lock().unwrap() = self.displays.clone();
+        self.rebind_parked();
+        self.rebind_preview();
         Ok(())
     }
+
+    /// Watches for displays being plugged in or out until the manager is dropped. Each change is
+    /// sent to the returned receiver once things settled, so the displays can be refreshed.
+    /// Unplugged displays are marked stale right away where the platform tells which one it was.
+    #[cfg(all(any(target_os = "linux", windows), feature = "hotplug"))]
+    pub fn watch_hotplug(&mut self) -> Result<Receiver<()>, Error> {
+        use crate::hotplug::{Event, Watcher};
+
+        let displays = self.polled.clone();
+        let waker = self.waker.clone();
+        let (sender, receiver) = channel();
+        let (ready, started) = channel();
+        // The watcher is created on its thread, neither the udev socket nor the window can move
+        self.supervisor.spawn("hotplug", move |token| {
+            let mut watcher = match Watcher::new() {
+                Ok(watcher) => watcher,
+                Err(err) => {
+                    let _ = ready.send(Err(err));
+                    return;
+                }
+            };
+            let _ = ready.send(Ok(()));
+            let mut changed: Option<Instant> = None;
+            while !token.is_cancelled() {
+                for event in watcher.poll() {
+                    if let Event::Removed(id) = event {
+                        for display in displays.lock().unwrap().iter() {
+                            if display.id == id {
+                                display.mark_stale();
+                            }
+                        }
+                    }
+                    changed = Some(Instant::now());
+                }
+                // A display takes a moment to answer after being plugged in
+                if changed.is_some_and(|changed| changed.elapsed() >= HOTPLUG_SETTLE) {
+                    changed = None;
+                    if sender.send(()).is_err() {
+                        return;
+                    }
+                    waker.wake();
+                }
+                thread::sleep(HOTPLUG_TICK);
+            }
+        });
+        match started.recv() {
+            Ok(Ok(())) => Ok(receiver),
+            Ok(Err(err)) => Err(Error::Hotplug(err)),
+            Err(_) => Err(Error::Hotplug(std::io::ErrorKind::Interrupted.into())),
+        }
+    }
+
+    fn scanner(&self) -> Scanner {
+        Scanner {
+            timeouts: self.enumeration_timeouts.clone(),
+            tuning: self.tuning.clone(),
+            unadvertised: self.unadvertised.clone(),
+            mappings: self.mappings.clone(),
+            errors: self.errors.clone(),
+            value_names: self.value_names.clone(),
+            lock: self.lock.clone(),
+            aliases: self.aliases.clone(),
+            fakes: self.fakes.clone(),
+            retry: self.retry,
+            failures: self.failures.clone(),
+            #[cfg(all(unix, feature = "helper"))]
+            helper_socket: self.helper_socket.clone(),
+        }
+    }
+
+    /// Number of changes not written yet, including parked ones. Those waiting for a display
+    /// that is gone aren't counted, they stay parked until it is found again.
+    pub fn pending(&self) -> usize {
+        let parked = self.parked.lock().unwrap();
+        let waiting = parked.iter().filter(|change| !change.display.is_stale());
+        self.queued.load(Ordering::SeqCst) + waiting.count()
+    }
+
+    /// The latest change the queue got through, and the latest one it failed to write if its
+    /// display wasn't written since.
+    pub fn last_written(&self) -> (Option<Written>, Option<Written>) {
+        self.recorder.lock().unwrap().last_written()
+    }
+
+    /// Queue depth and recent write timings of a display.
+    pub fn metrics(&self, display: &MyDisplay) -> Metrics {
+        let parked = self
+            .parked
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|change| change.display.id == display.id)
+            .count();
+        let mut metrics = self.recorder.lock().unwrap().metrics(&display.id, parked);
+        metrics.slow |= display.tuning.slow;
+        metrics
+    }
+
+    /// Stages writes to the display with that id, written together by
+    /// [`Transaction::commit`] and undone if one of them fails. `None` without such a display.
+    pub fn transaction(&self, display_id: &str) -> Option<Transaction> {
+        let display = self
+            .displays
+            .iter()
+            .find(|display| display.id == display_id)?;
+        Some(Transaction::new(self.flusher(), display.clone()))
+    }
+
+    /// Waits for queued changes to be written, parked ones stay parked.
+    pub fn flush(&self) {
+        self.flusher().flush();
+    }
+
+    /// Waits for the queued changes like [`DisplayManager::flush`], from another thread.
+    pub fn flusher(&self) -> Flusher {
+        Flusher(self.queued.clone())
+    }
+
+    /// The changes not written yet, queued ones before parked ones. Changes being written aren't
+    /// listed.
+    pub fn queued(&self) -> Vec<Queued> {
+        let listed = |change: &Change, parked| Queued {
+            display: change.display.clone(),
+            control: change.controller.kind,
+            value: change.controller.value,
+            parked,
+        };
+        let mut queued: Vec<Queued> = (self.changes.lock().unwrap().iter())
+            .map(|change| listed(change, false))
+            .collect();
+        queued.extend((self.parked.lock().unwrap().iter()).map(|change| listed(change, true)));
+        queued
+    }
+
+    /// Drops the change to a control of a display if it wasn't written yet, showing the value from
+    /// before it again. Returns whether there was one to drop.
+    pub fn discard_change(&self, display_id: &str, control: Control) -> bool {
+        let matches =
+            |change: &Change| change.display.id == display_id && change.controller.kind == control;
+        let mut changes = self.changes.lock().unwrap();
+        let queued = changes
+            .iter()
+            .position(matches)
+            .map(|index| changes.remove(index));
+        drop(changes);
+        let change = match queued {
+            Some(change) => {
+                self.queued.fetch_sub(1, Ordering::SeqCst);
+                self.recorder.lock().unwrap().dequeue(display_id);
+                change
+            }
+            None => {
+                let mut parked = self.parked.lock().unwrap();
+                let Some(index) = parked.iter().position(matches) else {
+                    return false;
+                };
+                parked.remove(index)
+            }
+        };
+        change.mark_pending(false);
+        if let Some(controller) = change.display.controls.get(&control) {
+            controller.write().unwrap().value = change.from;
+        }
+        true
+    }
+
+    /// Writes many values at once, like a preset for every display, without the queue: each
+    /// display one value at a time spaced out as DDC/CI asks, retrying what fails. Changes of the
+    /// same controls still queued are dropped first. See [`bulk::apply`].
+    pub fn apply_bulk(
+        &self,
+        values: &[(WrappedDisplay, Vec<(Control, u16)>)],
+        progress: impl FnMut(&bulk::Applied),
+    ) -> Vec<bulk::Applied> {
+        for (display, values) in values {
+            for (control, _) in values {
+                self.discard_change(&display.id, *control);
+            }
+        }
+        bulk::apply(values, progress)
+    }
+
+    /// Retries the changes parked for unreachable displays now instead of at the next retry.
+    pub fn retry_parked(&self) {
+        let parked = worker::take_retried(&self.parked);
+        self.requeue(parked);
+    }
+
+    /// Drops every change that wasn't written yet.
+    pub fn discard(&self) {
+        let mut changes = self.changes.lock().unwrap();
+        self.queued.fetch_sub(changes.len(), Ordering::SeqCst);
+        changes.clear();
+        self.recorder.lock().unwrap().clear_queued();
+        let parked = take(&mut *self.parked.lock().unwrap());
+        drop(changes);
+        for change in parked {
+            change.mark_pending(false);
+        }
+    }
+
+    /// Carries previewed values over to the reloaded displays, dropping those of displays gone.
+    fn rebind_preview(&self) {
+        // Taken out, controllers come before the preview in the lock order
+        let Some(mut previewed) = self.preview.lock().unwrap().take() else {
+            return;
+        };
+        previewed.retain_mut(|previewed| {
+            let kind = previewed.controller.read().unwrap().kind;
+            let value = previewed.controller.read().unwrap().value;
+            let Some(display) = self.displays.iter().find(|d| d.id == previewed.display.id) else {
+                return false;
+            };
+            let Some(controller) = display.controls.get(&kind) else {
+                return false;
+            };
+            {
+                let mut controller = controller.write().unwrap();
+                controller.value = value;
+                controller.previewed = true;
+            }
+            previewed.display = display.clone();
+            previewed.controller = controller.clone();
+            true
+        });
+        *self.preview.lock().unwrap() = Some(previewed);
+    }
+
+    /// Points parked changes at the reconnected displays and retries them right away. Those of
+    /// displays still missing stay parked.
+    fn rebind_parked(&self) {
+        // Taken out, controllers come before the parked changes in the lock order
+        let mut parked = take(&mut *self.parked.lock().unwrap());
+        for change in &mut parked {
+            if let Some(display) = self.displays.iter().find(|d| d.id == change.display.id) {
+                change.display = display.clone();
+                if let Some(controller) = display.controls.get(&change.controller.kind) {
+                    let mut controller = controller.write().unwrap();
+                    controller.value = change.controller.value;
+                    controller.pending = true;
+                }
+            }
+        }
+        let (waiting, found): (Vec<Change>, Vec<Change>) = parked
+            .into_iter()
+            .partition(|change| change.display.is_stale());
+        self.parked.lock().unwrap().extend(waiting);
+        self.requeue(found);
+    }
+
+    /// Queues parked changes again, except those a change queued since replaces.
+    fn requeue(&self, parked: Vec<Change>) {
+        let mut changes = self.changes.lock().unwrap();
+        for change in parked {
+            let replaced = changes.iter().any(|queued| {
+                Arc::ptr_eq(&queued.display, &change.display)
+                    && queued.controller.kind == change.controller.kind
+            });
+            if replaced {
+                continue;
+            }
+            self.recorder.lock().unwrap().queue(&change.display.id);
+            changes.push(change);
+            self.queued.fetch_add(1, Ordering::SeqCst);
+        }
+        drop(changes);
+        let _ = self.tx_queue.send(());
+    }
+}
+
+// Checked here so a field that isn't thread safe fails to compile instead of surprising embedders,
+// on Windows as well, where the ddc-hi handles only are through `SendHandle`
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<DisplayManager>();
+    assert_send_sync::<MyDisplay>();
+};
+
+impl Drop for DisplayManager {
+    /// Stops the worker once the changes still queued are written.
+    fn drop(&mut self) {
+        self.supervisor.token().cancel();
+        let _ = self.tx_queue.send(());
+        self.supervisor.shutdown();
+    }
 }
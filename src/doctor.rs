@@ -0,0 +1,192 @@
+//! Finding out why displays don't turn up: the checks of `ddc_bright doctor`, also shown when
+//! no display was found
+
+use crate::display::WrappedDisplay;
+
+/// Something wrong and what to do about it
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Problem {
+    pub cause: String,
+    pub fix: String,
+}
+
+/// One thing checked, with what was found if it passed
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Check {
+    /// What was checked, like `i2c-dev module`
+    pub name: &'static str,
+    pub result: Result<String, Problem>,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<String, Problem>) -> Self {
+        Self { name, result }
+    }
+}
+
+fn problem(cause: impl Into<String>, fix: impl Into<String>) -> Problem {
+    Problem {
+        cause: cause.into(),
+        fix: fix.into(),
+    }
+}
+
+/// Runs every check against the system and the displays found, stopping at the first one the
+/// others depend on that failed.
+pub fn run(displays: &[WrappedDisplay]) -> Vec<Check> {
+    let mut checks: Vec<Check> = vec![];
+    // Fake displays need none of the buses
+    #[cfg(all(target_os = "linux", feature = "i2c"))]
+    if !displays.iter().any(|display| display.backend == "fake") {
+        checks = linux::checks(displays);
+    }
+    if checks.iter().all(|check| check.result.is_ok()) {
+        checks.push(Check::new("DDC/CI", ddc(displays)));
+    }
+    checks
+}
+
+/// The problems [`run`] found, for showing along with an empty display list.
+pub fn problems(displays: &[WrappedDisplay]) -> Vec<Problem> {
+    run(displays)
+        .into_iter()
+        .filter_map(|check| check.result.err())
+        .collect()
+}
+
+/// Whether any display answers DDC/CI, rather than only being reached through its backlight or
+/// gamma.
+fn ddc(displays: &[WrappedDisplay]) -> Result<String, Problem> {
+    let answering = displays
+        .iter()
+        .filter(|display| !matches!(display.backend.as_str(), "sysfs" | "wmi" | "gamma"))
+        .count();
+    match answering {
+        0 => Err(problem(
+            "no display answered DDC/CI",
+            "turn on DDC/CI in the on-screen menu of the monitor and connect it directly, many \
+             docks, KVM switches and adapters don't pass DDC/CI through",
+        )),
+        1 => Ok("1 display answers".to_string()),
+        answering => Ok(format!("{answering} displays answer")),
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "i2c"))]
+mod linux {
+    use std::{
+        env,
+        fs::{self, OpenOptions},
+        io,
+        os::unix::fs::MetadataExt,
+    };
+
+    use super::{problem, Check, Problem};
+    use crate::display::WrappedDisplay;
+
+    /// Whether the buses are there and may be opened, the second only once the first passed.
+    pub fn checks(displays: &[WrappedDisplay]) -> Vec<Check> {
+        let buses = crate::enumerate::i2c_buses();
+        let mut checks = vec![Check::new("i2c-dev module", module(&buses))];
+        if !buses.is_empty() {
+            let access = match displays.iter().any(|display| display.backend == "helper") {
+                true => Ok("through the root helper".to_string()),
+                false => access(&buses),
+            };
+            checks.push(Check::new("access to /dev/i2c-*", access));
+        }
+        checks
+    }
+
+    fn module(buses: &[u32]) -> Result<String, Problem> {
+        match buses.len() {
+            0 => Err(problem(
+                "there is no /dev/i2c-*, the i2c-dev kernel module isn't loaded",
+                "load it with `sudo modprobe i2c-dev`, and at every boot with \
+                 `echo i2c-dev | sudo tee /etc/modules-load.d/i2c-dev.conf`",
+            )),
+            1 => Ok("1 bus".to_string()),
+            count => Ok(format!("{count} buses")),
+        }
+    }
+
+    /// Whether every bus opens for reading and writing, and if not why the user may not.
+    fn access(buses: &[u32]) -> Result<String, Problem> {
+        let denied: Vec<String> = buses
+            .iter()
+            .map(|bus| format!("/dev/i2c-{bus}"))
+            .filter(|path| {
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(path)
+                    .is_err_and(|err| err.kind() == io::ErrorKind::PermissionDenied)
+            })
+            .collect();
+        let Some(path) = denied.first() else {
+            return Ok(format!("all {} can be opened", buses.len()));
+        };
+        let helper = "or use the root helper, see Unprivileged use in the README";
+        let gid = fs::metadata(path).map(|meta| meta.gid()).unwrap_or(0);
+        let user = env::var("USER").unwrap_or_else(|_| "$USER".to_string());
+        if gid == 0 {
+            return Err(problem(
+                format!("{path} and the others only belong to root"),
+                format!(
+                    "give them to a group with a udev rule like `KERNEL==\"i2c-[0-9]*\", \
+                     GROUP=\"i2c\", MODE=\"0660\"` and \
+                     `sudo groupadd --system i2c && sudo usermod -aG i2c {user}`, {helper}"
+                ),
+            ));
+        }
+        let (group, members) = group(gid).unwrap_or_else(|| (gid.to_string(), vec![]));
+        if own_groups().contains(&gid) {
+            return Err(problem(
+                format!("{path} can't be opened though you are in its group {group}"),
+                "the group needs to read and write it, look at `ls -l /dev/i2c-*`",
+            ));
+        }
+        if members.contains(&user) {
+            return Err(problem(
+                format!("you were added to the group {group} of {path} after logging in"),
+                format!("log out and in again, or start a shell in it with `newgrp {group}`"),
+            ));
+        }
+        Err(problem(
+            format!("{user} isn't in the group {group} that {path} belongs to"),
+            format!("add them with `sudo usermod -aG {group} {user}` and log in again, {helper}"),
+        ))
+    }
+
+    /// The name and members of a group, from `/etc/group`.
+    fn group(gid: u32) -> Option<(String, Vec<String>)> {
+        let groups = fs::read_to_string("/etc/group").ok()?;
+        groups.lines().find_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            let &[name, _, id, members] = fields.as_slice() else {
+                return None;
+            };
+            (id.parse() == Ok(gid)).then(|| {
+                let members = members.split(',').filter(|member| !member.is_empty());
+                (name.to_string(), members.map(str::to_string).collect())
+            })
+        })
+    }
+
+    /// Groups of this process, which only change on logging in again.
+    fn own_groups() -> Vec<u32> {
+        let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("Groups:"))
+            .map(|groups| {
+                groups
+                    .split_whitespace()
+                    .filter_map(|gid| gid.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
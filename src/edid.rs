@@ -0,0 +1,171 @@
+//! Decoding the base block of an EDID, for `edid` and for naming outputs without DDC/CI
+
+/// Resolutions and refresh rates of the established timings bits, bytes 35 to 37
+const ESTABLISHED: [&str; 17] = [
+    "720x400@70",
+    "720x400@88",
+    "640x480@60",
+    "640x480@67",
+    "640x480@72",
+    "640x480@75",
+    "800x600@56",
+    "800x600@60",
+    "800x600@72",
+    "800x600@75",
+    "832x624@75",
+    "1024x768@87i",
+    "1024x768@60",
+    "1024x768@70",
+    "1024x768@75",
+    "1280x1024@75",
+    "1152x870@75",
+];
+
+const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+/// What the base block of an EDID says about the display
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edid {
+    /// Three letter PNP id, e.g. `DEL`
+    pub manufacturer: String,
+    pub product: u16,
+    /// Serial number field, `None` if zero
+    pub serial: Option<u32>,
+    /// Serial number descriptor, what ddc_bright knows displays by
+    pub serial_text: Option<String>,
+    /// Monitor name descriptor
+    pub name: Option<String>,
+    /// Week of manufacture, `None` if unset or the year is a model year
+    pub week: Option<u8>,
+    pub year: u16,
+    pub version: (u8, u8),
+    pub digital: bool,
+    /// Width and height in centimetres, `None` for projectors and unknown sizes
+    pub size: Option<(u8, u8)>,
+    /// CIE xy of the red, green, blue and white points
+    pub chromaticity: [(f32, f32); 4],
+    /// Established timings like `640x480@60`
+    pub established: Vec<&'static str>,
+    /// Standard timings as width, height and refresh rate
+    pub standard: Vec<(u16, u16, u8)>,
+    /// Detailed timings, the first is the preferred one
+    pub detailed: Vec<Timing>,
+    /// Extension blocks following the base block
+    pub extensions: u8,
+    /// Whether the bytes of the base block add up to zero as they should
+    pub checksum: bool,
+}
+
+/// A detailed timing descriptor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timing {
+    pub width: u16,
+    pub height: u16,
+    /// Pixel clock in kHz
+    pub clock: u32,
+    pub refresh: f32,
+}
+
+impl Edid {
+    /// Decodes the first 128 bytes, extension blocks are only counted.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let block = bytes
+            .get(..128)
+            .ok_or_else(|| format!("{} bytes is too short for an EDID", bytes.len()))?;
+        if block[..8] != HEADER {
+            return Err("no EDID header".to_string());
+        }
+        let packed = u16::from_be_bytes([block[8], block[9]]);
+        let manufacturer = [10, 5, 0]
+            .iter()
+            .map(|shift| (b'@' + ((packed >> shift) & 0x1F) as u8) as char)
+            .collect();
+        let serial = u32::from_le_bytes([block[12], block[13], block[14], block[15]]);
+
+        // Two low bits of each coordinate come first, packed for red and green then blue and white
+        let coordinate = |index: usize| {
+            let low = (block[25 + index / 4] >> (6 - 2 * (index % 4))) & 0x03;
+            ((block[27 + index] as u16) << 2 | low as u16) as f32 / 1024.0
+        };
+        let mut chromaticity = [(0.0, 0.0); 4];
+        for (point, xy) in chromaticity.iter_mut().enumerate() {
+            *xy = (coordinate(2 * point), coordinate(2 * point + 1));
+        }
+
+        let bits = u32::from_be_bytes([block[35], block[36], block[37], 0]);
+        let established = (0..ESTABLISHED.len())
+            .filter(|bit| bits & (1 << (31 - bit)) != 0)
+            .map(|bit| ESTABLISHED[bit])
+            .collect();
+
+        let standard = block[38..54]
+            .chunks(2)
+            .filter(|timing| timing != &[0x01, 0x01] && timing[0] != 0)
+            .map(|timing| {
+                let width = (timing[0] as u16 + 31) * 8;
+                let height = match timing[1] >> 6 {
+                    0 => width * 10 / 16,
+                    1 => width * 3 / 4,
+                    2 => width * 4 / 5,
+                    _ => width * 9 / 16,
+                };
+                (width, height, (timing[1] & 0x3F) + 60)
+            })
+            .collect();
+
+        let mut edid = Edid {
+            manufacturer,
+            product: u16::from_le_bytes([block[10], block[11]]),
+            serial: (serial != 0).then_some(serial),
+            serial_text: None,
+            name: None,
+            week: Some(block[16]).filter(|week| (1..=54).contains(week)),
+            year: 1990 + block[17] as u16,
+            version: (block[18], block[19]),
+            digital: block[20] & 0x80 != 0,
+            size: Some((block[21], block[22])).filter(|&(width, height)| width != 0 && height != 0),
+            chromaticity,
+            established,
+            standard,
+            detailed: vec![],
+            extensions: block[126],
+            checksum: block.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0,
+        };
+        for descriptor in block[54..126].chunks(18) {
+            if descriptor[..2] != [0, 0] {
+                edid.detailed.push(timing(descriptor));
+                continue;
+            }
+            let text = || {
+                let text = String::from_utf8_lossy(&descriptor[5..]);
+                let text = text.split('\n').next().unwrap_or_default().trim();
+                (!text.is_empty()).then(|| text.to_string())
+            };
+            match descriptor[3] {
+                0xFC => edid.name = text(),
+                0xFF => edid.serial_text = text(),
+                _ => {}
+            }
+        }
+        Ok(edid)
+    }
+}
+
+fn timing(descriptor: &[u8]) -> Timing {
+    let clock = u16::from_le_bytes([descriptor[0], descriptor[1]]) as u32 * 10;
+    let twelve = |low: u8, high: u8| low as u16 | (high as u16) << 8;
+    let width = twelve(descriptor[2], descriptor[4] >> 4);
+    let horizontal_blank = twelve(descriptor[3], descriptor[4] & 0x0F);
+    let height = twelve(descriptor[5], descriptor[7] >> 4);
+    let vertical_blank = twelve(descriptor[6], descriptor[7] & 0x0F);
+    let total = (width + horizontal_blank) as f32 * (height + vertical_blank) as f32;
+    Timing {
+        width,
+        height,
+        clock,
+        refresh: match total {
+            0.0 => 0.0,
+            total => clock as f32 * 1000.0 / total,
+        },
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::Duration;
+
+use ddc_hi::{Backend, Display};
+
+use crate::backend::Found;
+use crate::display::{Progress, Status};
+
+/// Time a backend gets to enumerate its displays unless configured otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Enumerates displays of every backend, skipping whatever a backend hasn't
+/// probed once its timeout passed.
+pub fn enumerate(
+    timeouts: &HashMap<Backend, Duration>,
+    warnings: &mut Vec<String>,
+    progress: &Sender<Progress>,
+) -> Vec<Found> {
+    let timeout = |backend| timeouts.get(&backend).copied().unwrap_or(DEFAULT_TIMEOUT);
+
+    #[cfg(all(target_os = "linux", feature = "i2c"))]
+    return enumerate_i2c(timeout(Backend::I2cDevice), warnings, progress);
+
+    #[cfg(not(all(target_os = "linux", feature = "i2c")))]
+    {
+        // Other platforms only expose enumeration as a whole, so bound the slowest backend.
+        let timeout = Backend::values()
+            .iter()
+            .map(|&backend| timeout(backend))
+            .max();
+        let report = |status| {
+            let _ = progress.send(Progress::new("backends", status));
+        };
+        report(Status::Probing);
+        let (sender, receiver) = channel();
+        // Wrapped on the thread that found them, nothing else holds their handles yet
+        thread::spawn(move || {
+            let found: Vec<Found> = Display::enumerate().into_iter().map(Found::from).collect();
+            sender.send(found)
+        });
+        match receiver.recv_timeout(timeout.unwrap_or(DEFAULT_TIMEOUT)) {
+            Ok(displays) => {
+                report(Status::Found);
+                displays
+            }
+            Err(_) => {
+                report(Status::Failed("timed out".to_string()));
+                warnings.push("Enumeration timed out, no displays found".to_string());
+                vec![]
+            }
+        }
+    }
+}
+
+/// Probes every `/dev/i2c-*` bus at the same time, each on a thread of its own, so one slow
+/// bus doesn't hold up the others. Displays keep the order of their buses.
+#[cfg(all(target_os = "linux", feature = "i2c"))]
+fn enumerate_i2c(
+    timeout: Duration,
+    warnings: &mut Vec<String>,
+    progress: &Sender<Progress>,
+) -> Vec<Found> {
+    use std::{sync::mpsc::RecvTimeoutError, time::Instant};
+
+    let buses = i2c_buses();
+    let total = buses.len();
+    let names: Vec<String> = buses.iter().map(|bus| format!("i2c-{bus}")).collect();
+
+    let deadline = Instant::now() + timeout;
+    let (sender, receiver) = channel();
+    // Left running when the deadline passes, a hung bus can't be interrupted.
+    for (index, bus) in buses.into_iter().enumerate() {
+        let _ = progress.send(Progress::new(names[index].clone(), Status::Probing));
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let _ = sender.send((index, probe_i2c(bus)));
+        });
+    }
+    drop(sender);
+
+    // Answers by bus, `None` while it is being probed
+    let mut probes: Vec<Option<Result<Display, Status>>> = (0..total).map(|_| None).collect();
+    let mut probed = 0;
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok((index, display)) => {
+                let status = match &display {
+                    Ok(_) => Status::Found,
+                    Err(status) => status.clone(),
+                };
+                let _ = progress.send(Progress::new(names[index].clone(), status));
+                probed += 1;
+                probes[index] = Some(display);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                for (name, _) in names
+                    .iter()
+                    .zip(&probes)
+                    .filter(|(_, probe)| probe.is_none())
+                {
+                    let _ = progress.send(Progress::new(
+                        name.clone(),
+                        Status::Failed("timed out".to_string()),
+                    ));
+                }
+                warnings.push(format!(
+                    "{}: enumeration took longer than {}s, skipped {} of {total} buses",
+                    Backend::I2cDevice,
+                    timeout.as_secs_f32(),
+                    total - probed
+                ));
+                break;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    probes
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(Found::from)
+        .collect()
+}
+
+/// Numbers of the `/dev/i2c-*` buses, in order.
+#[cfg(all(target_os = "linux", feature = "i2c"))]
+pub(crate) fn i2c_buses() -> Vec<u32> {
+    let mut buses: Vec<u32> = std::fs::read_dir("/dev")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| {
+                    let name = entry.ok()?.file_name();
+                    name.to_str()?.strip_prefix("i2c-")?.parse().ok()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    buses.sort_unstable();
+    buses
+}
+
+/// The display on the bus, or the status telling why there is none.
+#[cfg(all(target_os = "linux", feature = "i2c"))]
+fn probe_i2c(bus: u32) -> Result<Display, Status> {
+    use ddc::Edid;
+    use ddc_hi::{DisplayInfo, Handle};
+    use std::{fs, io, os::unix::fs::MetadataExt};
+
+    // list taken from ddc-i2c, which took it from ddcutil
+    let skip_prefix = ["SMBus", "soc:i2cdsi", "smu", "mac-io", "u4"];
+    let name = fs::read_to_string(format!("/sys/bus/i2c/devices/i2c-{bus}/name"))
+        .map_err(|_| Status::NoDisplay)?;
+    if skip_prefix.iter().any(|prefix| name.starts_with(prefix)) {
+        return Err(Status::NoDisplay);
+    }
+
+    let mut ddc =
+        ddc_i2c::from_i2c_device(format!("/dev/i2c-{bus}")).map_err(|err| match err.kind() {
+            io::ErrorKind::PermissionDenied => Status::Failed("no access".to_string()),
+            _ => Status::NoDisplay,
+        })?;
+    let id = ddc
+        .inner_ref()
+        .inner_ref()
+        .metadata()
+        .map(|meta| meta.rdev())
+        .unwrap_or_default();
+    let mut edid = vec![0u8; 0x100];
+    ddc.read_edid(0, &mut edid).map_err(|_| Status::NoDisplay)?;
+    let info = DisplayInfo::from_edid(Backend::I2cDevice, id.to_string(), edid)
+        .map_err(|_| Status::NoDisplay)?;
+    Ok(Display::new(Handle::I2cDevice(ddc), info))
+}
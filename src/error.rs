@@ -0,0 +1,64 @@
+//! Why talking to a display failed
+
+use std::{error, fmt, io};
+
+/// Errors of reading from and writing to displays. None of them are fatal: a display that is
+/// asleep or refuses a command answers again later.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The display didn't answer or refused a command, e.g. while in standby
+    Ddc(anyhow::Error),
+    /// The root helper couldn't be reached or failed on its side
+    Helper(io::Error),
+    /// The capabilities string the display sent can't be parsed
+    Capabilities(io::Error),
+    /// The display was unplugged, it isn't talked to anymore so nothing hangs on its bus
+    Gone,
+    /// Watching for displays being plugged in or out couldn't be set up
+    Hotplug(io::Error),
+    /// The backlight of an internal panel couldn't be read or written, or doesn't have the
+    /// control
+    Backlight(io::Error),
+    /// The gamma ramps of an output couldn't be read or set, or the control isn't brightness
+    Gamma(io::Error),
+    /// The lock of a shared machine refused the write, with why
+    Locked(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Ddc(err) => write!(f, "{err}"),
+            Error::Helper(err)
+            | Error::Capabilities(err)
+            | Error::Backlight(err)
+            | Error::Gamma(err) => {
+                write!(f, "{err}")
+            }
+            Error::Gone => write!(f, "display was disconnected"),
+            Error::Locked(reason) => write!(f, "{reason}"),
+            Error::Hotplug(err) => write!(f, "watching for displays failed: {err}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Ddc(err) => Some(err.as_ref()),
+            Error::Helper(err)
+            | Error::Capabilities(err)
+            | Error::Hotplug(err)
+            | Error::Backlight(err)
+            | Error::Gamma(err) => Some(err),
+            Error::Gone | Error::Locked(_) => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        Error::Ddc(err)
+    }
+}
@@ -0,0 +1,211 @@
+use std::{error::Error, fmt, fs, io, path::PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    history::{self, Log},
+};
+
+#[cfg(feature = "daemon")]
+use {
+    crate::{logging, webhook},
+    ddc_bright::supervisor::Supervisor,
+    serde_json::{json, Value},
+    std::{
+        sync::mpsc::{channel, Sender},
+        thread,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventsConfig {
+    /// Receives a POST for every value change, (dis)connected display and error in watchdog mode
+    pub webhook: Option<String>,
+    /// Receives a POST whenever a watched display stops or starts responding again
+    pub alert_webhook: Option<String>,
+    /// Attempts after the first failed one, waiting twice as long each time
+    pub retries: u32,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            webhook: None,
+            alert_webhook: None,
+            retries: 3,
+        }
+    }
+}
+
+/// Posts events to a webhook in the background, so retries don't hold up the caller
+#[cfg(feature = "daemon")]
+pub struct Emitter {
+    // Dropped first, letting the thread send what's left before it is joined
+    sender: Option<Sender<Value>>,
+    _supervisor: Supervisor,
+}
+
+#[cfg(feature = "daemon")]
+impl Emitter {
+    /// Makes an emitter dropping every event if there is no webhook.
+    pub fn new(url: Option<String>, retries: u32) -> Self {
+        let mut supervisor = Supervisor::default();
+        let Some(url) = url else {
+            return Self {
+                sender: None,
+                _supervisor: supervisor,
+            };
+        };
+        let (sender, receiver) = channel::<Value>();
+        supervisor.spawn("webhook", move |token| {
+            for body in receiver {
+                let mut delay = Duration::from_secs(1);
+                for attempt in 0..=retries {
+                    match webhook::post(&url, &body) {
+                        Ok(()) => break,
+                        Err(err) if attempt == retries || token.is_cancelled() => {
+                            logging::report(format!("{url}: {err}"));
+                            break;
+                        }
+                        Err(_) => {
+                            thread::sleep(delay);
+                            delay *= 2;
+                        }
+                    }
+                }
+            }
+        });
+        Self {
+            sender: Some(sender),
+            _supervisor: supervisor,
+        }
+    }
+
+    /// Sends an event, `fields` are merged into the body next to its name and unix time.
+    pub fn emit(&self, event: &str, fields: Value) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let mut body = json!({ "event": event, "time": time });
+        if let (Some(body), Value::Object(fields)) = (body.as_object_mut(), fields) {
+            body.extend(fields);
+        }
+        let _ = sender.send(body);
+    }
+}
+
+/// Something that happened besides a value change, kept in `events.log` next to the config
+/// so automation can be looked into after the fact
+pub struct Event {
+    pub time: DateTime<Local>,
+    /// What happened, e.g. `schedule`, `error` or `connected`
+    pub kind: String,
+    pub display: Option<String>,
+    pub detail: String,
+}
+
+impl Event {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("events.log"))
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.time.to_rfc3339(),
+            self.kind,
+            self.display.as_deref().unwrap_or(""),
+            self.detail.replace(['\t', '\n'], " ")
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        Some(Self {
+            time: DateTime::parse_from_rfc3339(fields.next()?)
+                .ok()?
+                .with_timezone(&Local),
+            kind: fields.next()?.to_string(),
+            display: Some(fields.next()?.to_string()).filter(|display| !display.is_empty()),
+            detail: fields.next()?.to_string(),
+        })
+    }
+
+    /// The logged events, oldest first. Lines that can't be read are skipped.
+    fn read() -> Result<Vec<Event>, Box<dyn Error>> {
+        let Some(path) = Self::path() else {
+            return Ok(vec![]);
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Ok(text.lines().filter_map(Event::from_line).collect()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}  {:<13}  ",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.kind
+        )?;
+        if let Some(display) = &self.display {
+            write!(f, "{display}: ")?;
+        }
+        write!(f, "{}", self.detail)
+    }
+}
+
+/// Appends an event to `events.log` right away. Failures are ignored, the log is only an aid
+/// and the TUI has nowhere to print them.
+pub fn record(kind: &str, display: Option<&str>, detail: impl Into<String>) {
+    let event = Event {
+        time: Local::now(),
+        kind: kind.to_string(),
+        display: display.map(str::to_string),
+        detail: detail.into(),
+    };
+    if let Some(path) = Event::path() {
+        let _ = history::append(&path, &(event.to_line() + "\n"));
+    }
+}
+
+/// The logged events together with the value changes of `history.log`, oldest first.
+pub fn export(since: Option<DateTime<Local>>) -> Result<Vec<Event>, Box<dyn Error>> {
+    let changes = Log::read()?.into_iter().map(|entry| Event {
+        time: entry.time,
+        kind: "value_changed".to_string(),
+        detail: format!("{} {} by {}", entry.control(), entry.value, entry.origin),
+        display: Some(entry.display),
+    });
+    let mut events: Vec<Event> = Event::read()?.into_iter().chain(changes).collect();
+    events.retain(|event| since.is_none_or(|since| event.time >= since));
+    events.sort_by_key(|event| event.time);
+    Ok(events)
+}
+
+/// One object per event, for `--json`.
+#[cfg(feature = "json")]
+pub fn to_json(events: &[Event]) -> serde_json::Value {
+    events
+        .iter()
+        .map(|event| {
+            serde_json::json!({
+                "time": event.time.to_rfc3339(),
+                "event": event.kind,
+                "display": event.display,
+                "detail": event.detail,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,259 @@
+//! Simulated displays answering from a table of VCP values instead of a bus, for tests and for
+//! trying the app without hardware
+//!
+//! `DDC_BRIGHT_FAKE=3` in the environment makes every refresh find three of them instead of the
+//! real displays. Tests set [`DisplayManager::fakes`] instead, with tables of their own, and look
+//! at what was written through [`FakeDisplay::state`].
+//!
+//! [`DisplayManager::fakes`]: crate::display::DisplayManager::fakes
+
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+
+use crate::backend::Backend;
+use crate::output::Output;
+use crate::Error;
+
+/// Number of simulated displays to find instead of the real ones
+pub const ENV: &str = "DDC_BRIGHT_FAKE";
+
+/// What the displays of `DDC_BRIGHT_FAKE` advertise, the codes of the default table
+const CAPABILITIES: &str = "(prot(monitor)type(LCD)model(FAKE)cmds(01 02 03 0C E3 F3)\
+    vcp(04 05 08 10 12 14(05 06 08 0B) 16 18 1A 60(0F 11 12) 62 87 D6(01 04 05))mccs_ver(2.1))";
+
+/// Current and maximum value of each code of the default table
+const TABLE: [(u8, u16, u16); 10] = [
+    (0x10, 50, 100),
+    (0x12, 50, 100),
+    (0x14, 0x05, 0x0B),
+    (0x16, 50, 100),
+    (0x18, 50, 100),
+    (0x1A, 50, 100),
+    (0x60, 0x0F, 0x12),
+    (0x62, 30, 100),
+    (0x87, 5, 10),
+    (0xD6, 0x01, 0x05),
+];
+
+/// A simulated display, found by a refresh as often as it is listed
+#[derive(Clone)]
+pub struct FakeDisplay {
+    pub id: String,
+    pub name: String,
+    pub manufacturer: Option<String>,
+    pub serial: Option<String>,
+    /// Sent when asked for capabilities, `None` to have every code that answers count
+    pub capabilities: Option<String>,
+    /// Where the OS shows the display, the ones `DDC_BRIGHT_FAKE` asks for are in rows of three
+    pub output: Option<Output>,
+    state: FakeState,
+}
+
+impl FakeDisplay {
+    /// A display with the default table: the usual controls at half their range, input DP-1
+    /// and powered on.
+    pub fn new(id: impl Into<String>, name: impl Into<String>) -> Self {
+        let id = id.into();
+        let fake = Self {
+            serial: Some(format!("FAKE{id}")),
+            id,
+            name: name.into(),
+            manufacturer: Some("FAK".to_string()),
+            capabilities: Some(CAPABILITIES.to_string()),
+            output: None,
+            state: FakeState::default(),
+        };
+        for (code, value, maximum) in TABLE {
+            fake.state.insert(code, value, maximum);
+        }
+        fake
+    }
+
+    /// A display without any code, to be filled with [`FakeDisplay::with_vcp`].
+    pub fn empty(id: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            capabilities: None,
+            state: FakeState::default(),
+            ..Self::new(id, name)
+        }
+    }
+
+    /// Adds the code or replaces its values.
+    pub fn with_vcp(self, code: u8, value: u16, maximum: u16) -> Self {
+        self.state.insert(code, value, maximum);
+        self
+    }
+
+    /// The values of the display, shared with it once it was found.
+    pub fn state(&self) -> FakeState {
+        self.state.clone()
+    }
+
+    /// An EDID with the name, serial and manufacturer of the display, sRGB primaries and a
+    /// preferred 1920x1080 timing.
+    pub(crate) fn edid(&self) -> Vec<u8> {
+        let mut edid = vec![0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+        let manufacturer = self.manufacturer.as_deref().unwrap_or_default();
+        let packed = (manufacturer.bytes().chain(*b"@@@"))
+            .take(3)
+            .fold(0u16, |packed, letter| {
+                packed << 5 | (letter.to_ascii_uppercase().wrapping_sub(b'@') & 0x1F) as u16
+            });
+        edid.extend(packed.to_be_bytes());
+        edid.extend([0x01, 0x00, 0x01, 0x00, 0x00, 0x00]);
+        // Week 1 of 2024, version 1.4, digital, 60 x 34 cm
+        edid.extend([1, 34, 1, 4, 0x80, 60, 34, 0x78, 0x0A]);
+        edid.extend([0xEE, 0x91, 0xA3, 0x54, 0x4C, 0x99, 0x26, 0x0F, 0x50, 0x54]);
+        edid.extend([0x21, 0x08, 0x00, 0xD1, 0xC0]);
+        edid.extend([0x01; 14]);
+        edid.extend([
+            0x02, 0x3A, 0x80, 0x18, 0x71, 0x38, 0x2D, 0x40, 0x58, 0x2C, 0x45, 0x00, 0x56, 0x50,
+            0x21, 0x00, 0x00, 0x1E,
+        ]);
+        for (kind, text) in [(0xFC, Some(&self.name)), (0xFF, self.serial.as_ref())] {
+            edid.extend([0, 0, 0, kind, 0]);
+            let mut text = text.map_or(vec![], |text| text.bytes().take(13).collect());
+            if text.len() < 13 {
+                text.push(b'\n');
+            }
+            text.resize(13, b' ');
+            edid.extend(text);
+        }
+        edid.extend([0, 0, 0, 0x10, 0]);
+        edid.extend([0; 13]);
+        edid.push(0);
+        let sum = edid.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        edid.push(sum.wrapping_neg());
+        edid
+    }
+
+    pub(crate) fn connection(&self) -> Fake {
+        Fake {
+            capabilities: self.capabilities.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// The VCP table of a simulated display and what was written to it
+#[derive(Clone, Default)]
+pub struct FakeState(Arc<Mutex<Table>>);
+
+#[derive(Default)]
+struct Table {
+    codes: BTreeMap<u8, (u16, u16)>,
+    writes: Vec<(u8, u16)>,
+    unreachable: bool,
+    /// Writes still to be acknowledged without being taken
+    dropping: usize,
+}
+
+impl FakeState {
+    fn insert(&self, code: u8, value: u16, maximum: u16) {
+        self.0.lock().unwrap().codes.insert(code, (value, maximum));
+    }
+
+    pub fn value(&self, code: u8) -> Option<u16> {
+        self.0
+            .lock()
+            .unwrap()
+            .codes
+            .get(&code)
+            .map(|(value, _)| *value)
+    }
+
+    /// Changes a value the way the display's own buttons would, without it counting as written.
+    pub fn set_value(&self, code: u8, value: u16) {
+        if let Some(entry) = self.0.lock().unwrap().codes.get_mut(&code) {
+            entry.0 = value;
+        }
+    }
+
+    /// Every write that got through, oldest first.
+    pub fn writes(&self) -> Vec<(u8, u16)> {
+        self.0.lock().unwrap().writes.clone()
+    }
+
+    /// Makes every read and write fail, like a display in standby or behind a flaky dock.
+    pub fn set_unreachable(&self, unreachable: bool) {
+        self.0.lock().unwrap().unreachable = unreachable;
+    }
+
+    /// Acknowledges the next writes without taking them, like a display busy with the ones
+    /// before.
+    pub fn drop_writes(&self, count: usize) {
+        self.0.lock().unwrap().dropping = count;
+    }
+}
+
+pub(crate) struct Fake {
+    capabilities: Option<String>,
+    state: FakeState,
+}
+
+impl Backend for Fake {
+    fn read_vcp(&self, code: u8) -> Result<(u16, u16), Error> {
+        let table = self.state.0.lock().unwrap();
+        if table.unreachable {
+            return Err(anyhow!("no answer from the fake display").into());
+        }
+        table
+            .codes
+            .get(&code)
+            .copied()
+            .ok_or_else(|| anyhow!("unsupported VCP code 0x{code:02x}").into())
+    }
+
+    fn write_vcp(&self, code: u8, value: u16) -> Result<(), Error> {
+        let mut table = self.state.0.lock().unwrap();
+        if table.unreachable {
+            return Err(anyhow!("no answer from the fake display").into());
+        }
+        if table.dropping > 0 {
+            table.dropping -= 1;
+            return Ok(());
+        }
+        // Displays take the write of a code they don't know and do nothing
+        if let Some(entry) = table.codes.get_mut(&code) {
+            entry.0 = value;
+        }
+        table.writes.push((code, value));
+        Ok(())
+    }
+
+    fn capabilities_string(&self) -> Result<Vec<u8>, Error> {
+        self.capabilities
+            .clone()
+            .map(String::into_bytes)
+            .ok_or_else(|| anyhow!("no capabilities").into())
+    }
+}
+
+/// The displays `DDC_BRIGHT_FAKE` asks for, none if it isn't set.
+pub(crate) fn from_env(warnings: &mut Vec<String>) -> Vec<FakeDisplay> {
+    let Some(count) = env::var_os(ENV) else {
+        return vec![];
+    };
+    match count.to_string_lossy().parse::<usize>() {
+        Ok(count) => (1..=count)
+            .map(|number| {
+                let mut fake = FakeDisplay::new(number.to_string(), format!("FAKE {number}"));
+                let place = number as i32 - 1;
+                fake.output = Some(Output {
+                    connector: Some(format!("FAKE-{number}")),
+                    resolution: Some((1920, 1080)),
+                    refresh: Some(60),
+                    position: Some((place % 3 * 1920, place / 3 * 1080)),
+                });
+                fake
+            })
+            .collect(),
+        Err(_) => {
+            warnings.push(format!("{ENV} must be a number of displays"));
+            vec![]
+        }
+    }
+}
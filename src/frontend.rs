@@ -0,0 +1,129 @@
+//! What the binary runs as when it isn't given a one-off subcommand or flag: the TUI, the
+//! daemon keeping the displays in line headless, the command line reading subcommands from
+//! stdin, or the stub of a GUI. Each is a feature of its own, and all drive a display manager
+//! set up from the config with the same background features started.
+
+use std::error::Error;
+#[cfg(feature = "tui")]
+use std::time::Duration;
+
+use ddc_bright::display::DisplayManager;
+
+use crate::{
+    backlight,
+    cli::{Args, Command},
+    config::Config,
+    wake,
+};
+
+pub enum Frontend {
+    /// Started by `ddc_bright tui` or without arguments
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Started by `ddc_bright daemon` or `--watchdog`
+    #[cfg(feature = "daemon")]
+    Daemon,
+    /// Started by `ddc_bright gui`
+    #[cfg(feature = "gui")]
+    Gui { browser: bool },
+    /// Started by `ddc_bright cli`
+    #[cfg(feature = "cli")]
+    Cli,
+}
+
+impl Frontend {
+    /// The frontend the arguments ask for, taking its subcommand out of them. `None` for the
+    /// other subcommands and the one-off flags, and without any frontend built in.
+    pub fn pick(args: &mut Args) -> Option<Self> {
+        let frontend = match &args.command {
+            #[cfg(feature = "tui")]
+            Some(Command::Tui) => Frontend::Tui,
+            #[cfg(feature = "daemon")]
+            Some(Command::Daemon) => Frontend::Daemon,
+            #[cfg(feature = "gui")]
+            Some(Command::Gui { browser }) => Frontend::Gui { browser: *browser },
+            #[cfg(feature = "cli")]
+            Some(Command::Cli) => Frontend::Cli,
+            Some(_) => return None,
+            None => return Self::by_flags(args),
+        };
+        args.command = None;
+        Some(frontend)
+    }
+
+    fn by_flags(args: &Args) -> Option<Self> {
+        if args.volume.is_some() || args.preset.is_some() {
+            return None;
+        }
+        #[cfg(feature = "daemon")]
+        if args.watchdog {
+            return Some(Frontend::Daemon);
+        }
+        #[cfg(feature = "tui")]
+        return Some(Frontend::Tui);
+        #[cfg(not(feature = "tui"))]
+        None
+    }
+
+    /// Whether it loads the displays itself, showing what it is busy with.
+    pub fn loads_displays(&self) -> bool {
+        match *self {
+            #[cfg(feature = "tui")]
+            Frontend::Tui => true,
+            #[cfg(feature = "daemon")]
+            Frontend::Daemon => false,
+            #[cfg(feature = "gui")]
+            Frontend::Gui { .. } => false,
+            #[cfg(feature = "cli")]
+            Frontend::Cli => false,
+        }
+    }
+
+    /// Runs until quit, or stopped for the daemon.
+    pub fn run(
+        self,
+        config: Config,
+        // Only the GUI leaves it as it is
+        #[cfg_attr(
+            not(any(feature = "tui", feature = "daemon", feature = "cli")),
+            allow(unused_mut)
+        )]
+        mut manager: DisplayManager,
+    ) -> Result<(), Box<dyn Error>> {
+        let _wake = wake::start(&manager, &config.wake, &config.audio, &config.automation);
+        let _backlight = backlight::start(&manager, &config.backlight, &config.automation);
+        match self {
+            #[cfg(feature = "daemon")]
+            Frontend::Daemon => {
+                let stop = ddc_bright::supervisor::CancelToken::default();
+                crate::watchdog::run(&mut manager, &config, &stop)
+            }
+            #[cfg(feature = "tui")]
+            Frontend::Tui => {
+                start_polling(&config, &mut manager);
+                crate::tui::run(config, manager)
+            }
+            #[cfg(feature = "gui")]
+            Frontend::Gui { browser } => crate::gui::run(&manager, browser),
+            #[cfg(feature = "cli")]
+            Frontend::Cli => crate::cli::shell(&config, &mut manager),
+        }
+    }
+}
+
+/// Reads the values back in the background as `[polling]` says, so changes made elsewhere show.
+#[cfg(feature = "tui")]
+fn start_polling(config: &Config, manager: &mut DisplayManager) {
+    if config.polling.enabled {
+        manager.start_polling(
+            Duration::from_secs(config.polling.interval),
+            Duration::from_secs(config.polling.idle_interval),
+        );
+    } else if config.polling.watch > 0 {
+        let watch = Duration::from_secs(config.polling.watch);
+        manager.start_watching(
+            watch,
+            watch.max(Duration::from_secs(config.polling.idle_interval)),
+        );
+    }
+}
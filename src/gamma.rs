@@ -0,0 +1,464 @@
+//! Software brightness for displays without DDC/CI, scaling the gamma ramps of their output
+//! through xrandr on X11 or wlr-gamma-control on Wayland compositors that have it
+//!
+//! Gamma only dims the picture, the backlight stays as bright as before. On Wayland the ramps
+//! last as long as the process setting them, so one-off subcommands only have an effect
+//! through the daemon.
+
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::{self, Seek, Write},
+    os::fd::AsFd,
+    path::Path,
+    process,
+    sync::Mutex,
+};
+
+use wayland_client::{
+    protocol::{wl_output::WlOutput, wl_registry},
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+};
+use wayland_protocols_wlr::gamma_control::v1::client::{
+    zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1,
+    zwlr_gamma_control_v1::{self, ZwlrGammaControlV1},
+};
+
+use crate::backend::{brightness_only, Backend};
+use crate::display::WrappedDisplay;
+use crate::edid::Edid;
+use crate::output::{self, DRM, INTERNAL};
+use crate::Error;
+
+/// What an output advertises, so loading keeps brightness and drops every other control
+pub(crate) const CAPABILITIES: &[u8] = b"(prot(gamma)type(lcd)vcp(10))";
+
+/// Fraction of full brightness left at 0, so the screen stays readable
+const MIN_FACTOR: f64 = 0.1;
+
+/// Wayland connection kept for the life of the process, the compositor drops the ramps along
+/// with it
+static WAYLAND: Mutex<Option<Wayland>> = Mutex::new(None);
+
+pub(crate) struct Gamma {
+    /// Name of the output, e.g. `DP-1`
+    pub output: String,
+    /// Model name from the EDID, if the output has one
+    pub model: Option<String>,
+    pub edid: Option<Vec<u8>>,
+    wayland: bool,
+}
+
+impl Gamma {
+    /// Outputs of the graphical session that no display found already is connected to, every
+    /// output not reached through DDC/CI or a backlight.
+    pub fn find(displays: &[WrappedDisplay]) -> Vec<Self> {
+        let covered = Covered::find(displays);
+        let wayland = env::var_os("WAYLAND_DISPLAY").is_some();
+        let outputs = match (wayland, env::var_os("DISPLAY")) {
+            (true, _) => wayland_outputs(),
+            (false, Some(_)) => xrandr().unwrap_or_default(),
+            (false, None) => vec![],
+        };
+        outputs
+            .into_iter()
+            .filter(|listed| {
+                !covered.names.contains(&listed.output)
+                    && !listed
+                        .edid
+                        .as_ref()
+                        .is_some_and(|edid| covered.edids.contains(edid))
+            })
+            .map(|listed| Self {
+                model: listed
+                    .edid
+                    .as_deref()
+                    .and_then(|edid| Edid::decode(edid).ok()?.name),
+                edid: listed.edid,
+                output: listed.output,
+                wayland,
+            })
+            .collect()
+    }
+
+    /// The current and maximum brightness, 0 to 100.
+    fn read(&self) -> io::Result<(u16, u16)> {
+        let factor = match self.wayland {
+            true => wayland(|wayland| Ok(wayland.output(&self.output)?.factor))?,
+            false => {
+                xrandr()?
+                    .into_iter()
+                    .find(|listed| listed.output == self.output)
+                    .ok_or_else(|| gone(&self.output))?
+                    .factor
+            }
+        };
+        let level = (factor - MIN_FACTOR) / (1.0 - MIN_FACTOR) * 100.0;
+        Ok((level.round().clamp(0.0, 100.0) as u16, 100))
+    }
+
+    fn write(&self, value: u16) -> io::Result<()> {
+        let factor = MIN_FACTOR + (1.0 - MIN_FACTOR) * value.min(100) as f64 / 100.0;
+        if self.wayland {
+            return wayland(|wayland| wayland.set(&self.output, factor));
+        }
+        let status = process::Command::new("xrandr")
+            .args(["--output", &self.output, "--brightness"])
+            .arg(format!("{factor:.3}"))
+            .status()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(io::Error::other(format!("xrandr failed with {status}"))),
+        }
+    }
+}
+
+impl Backend for Gamma {
+    fn read_vcp(&self, code: u8) -> Result<(u16, u16), Error> {
+        brightness_only(code, "gamma").map_err(Error::Gamma)?;
+        self.read().map_err(Error::Gamma)
+    }
+
+    fn write_vcp(&self, code: u8, value: u16) -> Result<(), Error> {
+        brightness_only(code, "gamma").map_err(Error::Gamma)?;
+        self.write(value).map_err(Error::Gamma)
+    }
+
+    fn capabilities_string(&self) -> Result<Vec<u8>, Error> {
+        Ok(CAPABILITIES.to_vec())
+    }
+}
+
+/// An output as the X server or compositor lists it
+struct Listed {
+    output: String,
+    edid: Option<Vec<u8>>,
+    /// Brightness factor of the ramps, 1 at full brightness
+    factor: f64,
+}
+
+fn gone(output: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("output {output} is gone"))
+}
+
+/// DRM connectors reached by another backend, by name like `DP-1` and by EDID
+#[derive(Default)]
+struct Covered {
+    names: HashSet<String>,
+    edids: Vec<Vec<u8>>,
+}
+
+impl Covered {
+    fn find(displays: &[WrappedDisplay]) -> Self {
+        // DDC/CI displays are known by the device number of their i2c bus. Monitors without
+        // DDC/CI are enumerated through their EDID all the same, but never send capabilities.
+        let buses: HashSet<u64> = displays
+            .iter()
+            .filter(|display| display.advertised.is_some())
+            .filter_map(|display| output::i2c_bus(display))
+            .collect();
+        let backlight = displays.iter().any(|display| display.backend == "sysfs");
+
+        let mut covered = Self::default();
+        let Ok(entries) = fs::read_dir(DRM) else {
+            return covered;
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            // Connectors are named after their card, e.g. card0-DP-1
+            let Some((_, connector)) = file_name.split_once('-') else {
+                continue;
+            };
+            let bus = fs::read_link(entry.path().join("ddc"))
+                .ok()
+                .and_then(|link| {
+                    let name = link.file_name()?.to_string_lossy().into_owned();
+                    name.strip_prefix("i2c-")?.parse::<u64>().ok()
+                });
+            let internal = INTERNAL.iter().any(|prefix| connector.starts_with(prefix));
+            if bus.is_some_and(|bus| buses.contains(&bus)) || (internal && backlight) {
+                covered.names.insert(connector.to_string());
+                if let Ok(edid) = fs::read(entry.path().join("edid")) {
+                    covered.edids.extend((!edid.is_empty()).then_some(edid));
+                }
+            }
+        }
+        covered
+    }
+}
+
+/// The EDID of a connector by its name, e.g. `DP-1`.
+fn connector_edid(connector: &str) -> Option<Vec<u8>> {
+    fs::read_dir(DRM).ok()?.flatten().find_map(|entry| {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.split_once('-')?.1 != connector {
+            return None;
+        }
+        fs::read(entry.path().join("edid"))
+            .ok()
+            .filter(|edid| !edid.is_empty())
+    })
+}
+
+/// Connected X11 outputs.
+fn xrandr() -> io::Result<Vec<Listed>> {
+    Ok(output::xrandr()?
+        .into_iter()
+        .map(|output| Listed {
+            output: output.name,
+            edid: output.edid,
+            factor: output.brightness,
+        })
+        .collect())
+}
+
+/// Runs `f` on the Wayland connection, connecting first if there is none yet.
+fn wayland<T>(f: impl FnOnce(&mut Wayland) -> io::Result<T>) -> io::Result<T> {
+    let mut wayland = WAYLAND.lock().unwrap();
+    if wayland.is_none() {
+        *wayland = Some(Wayland::connect()?);
+    }
+    f(wayland.as_mut().unwrap())
+}
+
+/// Outputs the compositor lets us set the gamma of.
+fn wayland_outputs() -> Vec<Listed> {
+    wayland(|wayland| wayland.refresh()).unwrap_or_default()
+}
+
+struct Wayland {
+    queue: EventQueue<State>,
+    state: State,
+}
+
+#[derive(Default)]
+struct State {
+    manager: Option<ZwlrGammaControlManagerV1>,
+    outputs: Vec<Output>,
+}
+
+struct Output {
+    /// Name of the global, the user data of its proxies
+    global: u32,
+    proxy: WlOutput,
+    name: Option<String>,
+    control: Option<ZwlrGammaControlV1>,
+    /// Entries per ramp, 0 until the compositor tells
+    size: u32,
+    factor: f64,
+}
+
+impl Wayland {
+    fn connect() -> io::Result<Self> {
+        let connection = Connection::connect_to_env().map_err(io::Error::other)?;
+        let queue = connection.new_event_queue();
+        connection.display().get_registry(&queue.handle(), ());
+        let mut wayland = Self {
+            queue,
+            state: State::default(),
+        };
+        wayland.roundtrip()?;
+        if wayland.state.manager.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the compositor doesn't support wlr-gamma-control",
+            ));
+        }
+        Ok(wayland)
+    }
+
+    fn roundtrip(&mut self) -> io::Result<()> {
+        self.queue
+            .roundtrip(&mut self.state)
+            .map(drop)
+            .map_err(io::Error::other)
+    }
+
+    /// Picks up outputs plugged in since, taking gamma control of new ones, and returns the
+    /// outputs under control.
+    fn refresh(&mut self) -> io::Result<Vec<Listed>> {
+        // Names of new outputs arrive in the first roundtrip, ramp sizes in the second
+        self.roundtrip()?;
+        let handle = self.queue.handle();
+        if let Some(manager) = &self.state.manager {
+            for output in &mut self.state.outputs {
+                if output.control.is_none() {
+                    output.control =
+                        Some(manager.get_gamma_control(&output.proxy, &handle, output.global));
+                }
+            }
+        }
+        self.roundtrip()?;
+        Ok(self
+            .state
+            .outputs
+            .iter()
+            .filter(|output| output.size > 0)
+            .filter_map(|output| {
+                let name = output.name.clone()?;
+                Some(Listed {
+                    edid: connector_edid(&name),
+                    output: name,
+                    factor: output.factor,
+                })
+            })
+            .collect())
+    }
+
+    fn output(&mut self, name: &str) -> io::Result<&mut Output> {
+        self.queue
+            .dispatch_pending(&mut self.state)
+            .map_err(io::Error::other)?;
+        self.state
+            .outputs
+            .iter_mut()
+            .find(|output| output.name.as_deref() == Some(name))
+            .ok_or_else(|| gone(name))
+    }
+
+    fn set(&mut self, name: &str, factor: f64) -> io::Result<()> {
+        let output = self.output(name)?;
+        let (Some(control), size) = (&output.control, output.size) else {
+            return Err(refused(name));
+        };
+        let ramps = ramps(size, factor)?;
+        control.set_gamma(ramps.as_fd());
+        let global = output.global;
+        // A failure shows up as the control being dropped
+        self.roundtrip()?;
+        let output = self.output(name)?;
+        if output.global != global || output.control.is_none() {
+            return Err(refused(name));
+        }
+        output.factor = factor;
+        Ok(())
+    }
+}
+
+fn refused(output: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("the compositor refused gamma control of {output}, another program may have it"),
+    )
+}
+
+/// A file with the red, green and blue ramps scaled by `factor`, already unlinked.
+fn ramps(size: u32, factor: f64) -> io::Result<fs::File> {
+    let dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, Into::into);
+    let path = Path::new(&dir).join(format!("ddc_bright-gamma-{}", process::id()));
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let _ = fs::remove_file(&path);
+    let mut ramp = Vec::with_capacity(size as usize * 2);
+    for i in 0..size {
+        let position = i as f64 / (size.max(2) - 1) as f64;
+        ramp.extend(((position * factor * 65535.0).round() as u16).to_ne_bytes());
+    }
+    for _ in 0..3 {
+        file.write_all(&ramp)?;
+    }
+    file.rewind()?;
+    Ok(file)
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for State {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        handle: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } => {
+                if interface == WlOutput::interface().name {
+                    // Version 4 sends the connector name
+                    let proxy = registry.bind(name, version.min(4), handle, name);
+                    state.outputs.push(Output {
+                        global: name,
+                        proxy,
+                        name: None,
+                        control: None,
+                        size: 0,
+                        factor: 1.0,
+                    });
+                } else if interface == ZwlrGammaControlManagerV1::interface().name {
+                    state.manager = Some(registry.bind(name, 1, handle, ()));
+                }
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                state.outputs.retain(|output| {
+                    if output.global == name {
+                        if let Some(control) = &output.control {
+                            control.destroy();
+                        }
+                    }
+                    output.global != name
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlOutput, u32> for State {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wayland_client::protocol::wl_output::Event,
+        global: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wayland_client::protocol::wl_output::Event::Name { name } = event {
+            if let Some(output) = state.outputs.iter_mut().find(|o| o.global == *global) {
+                output.name = Some(name);
+            }
+        }
+    }
+}
+
+impl Dispatch<ZwlrGammaControlManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrGammaControlManagerV1,
+        _: <ZwlrGammaControlManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrGammaControlV1, u32> for State {
+    fn event(
+        state: &mut Self,
+        control: &ZwlrGammaControlV1,
+        event: zwlr_gamma_control_v1::Event,
+        global: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.outputs.iter_mut().find(|o| o.global == *global) else {
+            return;
+        };
+        match event {
+            zwlr_gamma_control_v1::Event::GammaSize { size } => output.size = size,
+            // Another client has the output, asked again on the next refresh
+            zwlr_gamma_control_v1::Event::Failed => {
+                control.destroy();
+                output.control = None;
+                output.size = 0;
+            }
+            _ => {}
+        }
+    }
+}
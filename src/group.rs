@@ -0,0 +1,80 @@
+//! Named sets of displays: the targets of hotkeys, and displays adjusted together in the TUI
+
+use std::collections::BTreeMap;
+
+use ddc_bright::display::{Control, MyDisplay};
+use serde::{Deserialize, Serialize};
+
+/// Displays of a `[groups]` entry, written as a list of them or as a table with the settings
+/// of a synced group
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(from = "Written")]
+pub struct Group {
+    /// Model names, ids or serials of the members
+    pub displays: Vec<String>,
+    /// Adjusting a member in the TUI sets the others to the same percent
+    pub sync: bool,
+    /// Percent of brightness a member is kept above the others, by its entry in `displays`
+    pub offsets: BTreeMap<String, i16>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Written {
+    List(Vec<String>),
+    Table {
+        displays: Vec<String>,
+        #[serde(default)]
+        sync: bool,
+        #[serde(default)]
+        offsets: BTreeMap<String, i16>,
+    },
+}
+
+impl From<Written> for Group {
+    fn from(written: Written) -> Self {
+        match written {
+            Written::List(displays) => Group {
+                displays,
+                ..Group::default()
+            },
+            Written::Table {
+                displays,
+                sync,
+                offsets,
+            } => Group {
+                displays,
+                sync,
+                offsets,
+            },
+        }
+    }
+}
+
+impl Group {
+    pub fn contains(&self, display: &MyDisplay) -> bool {
+        self.member(display).is_some()
+    }
+
+    /// The entry of `displays` matching the display.
+    pub fn member(&self, display: &MyDisplay) -> Option<&String> {
+        self.displays.iter().find(|member| display.matches(member))
+    }
+
+    pub fn offset(&self, display: &MyDisplay) -> i16 {
+        self.member(display)
+            .and_then(|member| self.offsets.get(member))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// The percent of `control` the member `to` is set to when `from` was set to `percent`,
+    /// apart by their offsets for brightness.
+    pub fn follow(&self, from: &MyDisplay, to: &MyDisplay, control: Control, percent: f32) -> f32 {
+        let offset = match control {
+            Control::Brightness => self.offset(to) - self.offset(from),
+            _ => 0,
+        };
+        (percent + offset as f32).clamp(0.0, 100.0)
+    }
+}
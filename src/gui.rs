@@ -0,0 +1,214 @@
+//! Stub of a windowed frontend: sliders for the continuous controls of every display. Windows
+//! gets a native window of them, see [`native`]. Elsewhere, where no toolkit speaks to the
+//! desktop without pulling one in, they are on a page served to the browser from localhost,
+//! with just enough HTTP/1.1 for that, one request per connection.
+//!
+//! The page and its requests live under a random path printed on startup, so other users of
+//! the machine and web pages the browser has open can't reach it.
+
+use std::{
+    collections::hash_map::RandomState,
+    error::Error,
+    hash::{BuildHasher, Hasher},
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use ddc_bright::display::{Control, DisplayManager, WrappedDisplay};
+use serde_json::{json, Value};
+
+use crate::history::{Log, Origin};
+
+#[cfg(windows)]
+mod native;
+
+/// How often the listener looks for requests
+const TICK: Duration = Duration::from_millis(50);
+
+/// How long a browser may take to send its request
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Quiet time after which the values dragged to are logged, so a drag is logged once
+const SETTLE: Duration = Duration::from_secs(1);
+
+const PAGE: &str = r#"<!doctype html>
+<meta charset="utf-8">
+<title>ddc_bright</title>
+<style>
+  body { font-family: sans-serif; margin: 2em; }
+  label { display: flex; gap: 1em; margin: 0.5em 0; }
+  label span { width: 8em; }
+  input { width: 20em; }
+</style>
+<div id="displays"></div>
+<script>
+  const list = document.getElementById("displays");
+  const element = (tag, properties) => Object.assign(document.createElement(tag), properties);
+  async function load() {
+    const displays = await (await fetch("state")).json();
+    list.replaceChildren(...displays.map((display, index) => {
+      const section = element("section");
+      section.append(element("h2", { textContent: display.label }));
+      for (const control of display.controls) {
+        const slider = element("input", {
+          type: "range", min: 0, max: control.maximum, value: control.value,
+        });
+        slider.oninput = () => fetch(
+          `set?display=${index + 1}&control=${control.key}&value=${slider.value}`,
+          { method: "POST" },
+        );
+        const label = element("label");
+        label.append(element("span", { textContent: control.name }), slider);
+        section.append(label);
+      }
+      return section;
+    }));
+  }
+  window.onfocus = load;
+  load();
+</script>
+"#;
+
+/// Shows the sliders until the window is closed, or serves them until killed with `browser` or
+/// off Windows, opening them in the browser.
+pub fn run(manager: &DisplayManager, browser: bool) -> Result<(), Box<dyn Error>> {
+    #[cfg(windows)]
+    if !browser {
+        return native::run(manager);
+    }
+    #[cfg(not(windows))]
+    let _ = browser;
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    listener.set_nonblocking(true)?;
+    let url = format!("http://{}/{}/", listener.local_addr()?, token());
+    println!("Sliders for the displays are at {url}");
+    open(&url);
+
+    let prefix = url.trim_start_matches("http://");
+    let prefix = &prefix[prefix.find('/').unwrap_or_default()..];
+    let mut history = Log::default();
+    let mut changed: Option<Instant> = None;
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(err) = serve(stream, prefix, manager, &mut history) {
+                    eprintln!("gui: {err}");
+                }
+                changed = Some(Instant::now());
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if changed.is_some_and(|changed| changed.elapsed() >= SETTLE) {
+                    changed = None;
+                    if let Err(err) = history.flush() {
+                        eprintln!("history: {err}");
+                    }
+                }
+                thread::sleep(TICK);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// 128 random bits in hex, from the keys std seeds its hash maps with.
+fn token() -> String {
+    let half = || RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}", half(), half())
+}
+
+/// Opens the page in the default browser, printing the address is enough where that fails.
+fn open(url: &str) {
+    let mut command = match () {
+        _ if cfg!(windows) => Command::new("explorer"),
+        _ if cfg!(target_os = "macos") => Command::new("open"),
+        _ => Command::new("xdg-open"),
+    };
+    let _ = command.arg(url).spawn();
+}
+
+/// Answers one request under `prefix`.
+fn serve(
+    mut stream: TcpStream,
+    prefix: &str,
+    manager: &DisplayManager,
+    history: &mut Log,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Nothing in the headers matters, they are only read past
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut words = request.split_whitespace();
+    let (method, target) = (
+        words.next().unwrap_or_default(),
+        words.next().unwrap_or_default(),
+    );
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (status, kind, body) = match (method, path.strip_prefix(prefix)) {
+        ("GET", Some("")) => ("200 OK", "text/html; charset=utf-8", PAGE.to_string()),
+        ("GET", Some("state")) => {
+            let state = state(&manager.displays).to_string();
+            ("200 OK", "application/json", state)
+        }
+        ("POST", Some("set")) => match set(manager, history, query) {
+            Ok(()) => ("204 No Content", "text/plain", String::new()),
+            Err(err) => ("400 Bad Request", "text/plain", err),
+        },
+        _ => ("404 Not Found", "text/plain", "not found".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {kind}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// Every display with its continuous controls, in list order.
+fn state(displays: &[WrappedDisplay]) -> Value {
+    let displays: Vec<Value> = (displays.iter())
+        .map(|display| {
+            let controls: Vec<Value> = (display.controls.iter())
+                .filter(|(control, _)| control.is_continuous())
+                .map(|(control, controller)| {
+                    let controller = controller.read().unwrap();
+                    json!({
+                        "key": control.key(),
+                        "name": control.get_name(),
+                        "value": controller.value,
+                        "maximum": controller.maximum,
+                    })
+                })
+                .collect();
+            json!({ "label": display.label(), "controls": controls })
+        })
+        .collect();
+    json!(displays)
+}
+
+/// Queues `display=<number>&control=<key>&value=<value>` from a slider.
+fn set(manager: &DisplayManager, history: &mut Log, query: &str) -> Result<(), String> {
+    let field = |name: &str| {
+        (query.split('&'))
+            .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+            .ok_or_else(|| format!("missing {name}"))
+    };
+    let number: usize = field("display")?.parse().map_err(|_| "invalid display")?;
+    let display = (manager.displays)
+        .get(number.wrapping_sub(1))
+        .ok_or("no such display")?;
+    let control: Control = field("control")?.parse()?;
+    let controller = display.controls.get(&control).ok_or("no such control")?;
+    let value = field("value")?.parse().map_err(|_| "invalid value")?;
+    manager.queue_set(display.clone(), controller.clone(), value);
+    history.record(Origin::Gui, display, control as u8, value);
+    Ok(())
+}
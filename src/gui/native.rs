@@ -0,0 +1,261 @@
+//! The sliders in a window of their own, built from the controls Windows ships: a label for each
+//! display and a trackbar with its name for each of its continuous controls.
+
+use std::{cell::RefCell, error::Error, io, mem, ptr, thread, time::Instant};
+
+use ddc_bright::display::{Control, DisplayManager};
+use windows_sys::core::w;
+use windows_sys::Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+    Graphics::Gdi::{COLOR_WINDOW, HBRUSH},
+    System::LibraryLoader::GetModuleHandleW,
+    UI::{
+        Controls::{
+            InitCommonControlsEx, ICC_BAR_CLASSES, INITCOMMONCONTROLSEX, TBM_SETPOS,
+            TBM_SETRANGEMAX, TBM_SETRANGEMIN, TBS_HORZ, TRACKBAR_CLASSW,
+        },
+        WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DispatchMessageW, LoadCursorW, PeekMessageW,
+            PostQuitMessage, RegisterClassW, SendMessageW, ShowWindow, TranslateMessage,
+            CW_USEDEFAULT, IDC_ARROW, MSG, PM_REMOVE, SW_SHOW, WM_DESTROY, WM_HSCROLL, WM_QUIT,
+            WM_USER, WNDCLASSW, WS_CHILD, WS_OVERLAPPEDWINDOW, WS_VISIBLE,
+        },
+    },
+};
+
+use super::{SETTLE, TICK};
+use crate::history::{Log, Origin};
+
+/// Height of a row of the window, a display's label or a control's slider
+const ROW: i32 = 32;
+const LABEL_WIDTH: i32 = 140;
+const SLIDER_WIDTH: i32 = 320;
+const MARGIN: i32 = 12;
+
+/// Asks a trackbar where it is, missing from the bindings
+const TBM_GETPOS: u32 = WM_USER;
+
+/// A slider and the control it moves
+struct Slider {
+    window: HWND,
+    display: usize,
+    control: Control,
+}
+
+thread_local! {
+    static SLIDERS: RefCell<Vec<Slider>> = const { RefCell::new(vec![]) };
+    /// Sliders moved since the loop last looked, with the value they were moved to
+    static MOVED: RefCell<Vec<(usize, u16)>> = const { RefCell::new(vec![]) };
+}
+
+/// Shows the window until it is closed.
+pub fn run(manager: &DisplayManager) -> Result<(), Box<dyn Error>> {
+    let controls = INITCOMMONCONTROLSEX {
+        dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as u32,
+        dwICC: ICC_BAR_CLASSES,
+    };
+    // SAFETY: loads the trackbar class, the struct lives for the call
+    unsafe { InitCommonControlsEx(&controls) };
+    let class = wide("ddc_bright gui");
+    // SAFETY: the module of the process itself
+    let instance = unsafe { GetModuleHandleW(ptr::null()) };
+    let window_class = WNDCLASSW {
+        style: 0,
+        lpfnWndProc: Some(procedure),
+        cbClsExtra: 0,
+        cbWndExtra: 0,
+        hInstance: instance,
+        hIcon: ptr::null_mut(),
+        // SAFETY: a cursor of the system
+        hCursor: unsafe { LoadCursorW(ptr::null_mut(), IDC_ARROW) },
+        // The system colours are passed as the brush one above them
+        hbrBackground: (COLOR_WINDOW + 1) as HBRUSH,
+        lpszMenuName: ptr::null(),
+        lpszClassName: class.as_ptr(),
+    };
+    // SAFETY: the class outlives every window of it, they are gone when this returns
+    unsafe { RegisterClassW(&window_class) };
+
+    let rows: i32 = (manager.displays.iter())
+        .map(|display| 1 + continuous(display.controls.keys()).count() as i32)
+        .sum();
+    let title = wide("ddc_bright");
+    let width = LABEL_WIDTH + SLIDER_WIDTH + MARGIN * 3;
+    // Room for the title bar and borders, which the size includes
+    let height = rows * ROW + MARGIN * 2 + 48;
+    // SAFETY: the class registered above, no parent, menu or creation data
+    let main = unsafe {
+        CreateWindowExW(
+            0,
+            class.as_ptr(),
+            title.as_ptr(),
+            WS_OVERLAPPEDWINDOW,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            width,
+            height,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            instance,
+            ptr::null(),
+        )
+    };
+    if main.is_null() {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let mut top = MARGIN;
+    for (index, display) in manager.displays.iter().enumerate() {
+        child(
+            main,
+            w!("STATIC"),
+            &display.label(),
+            MARGIN,
+            top,
+            LABEL_WIDTH + SLIDER_WIDTH,
+            0,
+        );
+        top += ROW;
+        for control in continuous(display.controls.keys()) {
+            let (value, maximum) = {
+                let controller = display.controls[&control].read().unwrap();
+                (controller.value, controller.maximum)
+            };
+            let x = MARGIN * 2 + LABEL_WIDTH;
+            child(
+                main,
+                w!("STATIC"),
+                control.get_name(),
+                MARGIN * 2,
+                top,
+                LABEL_WIDTH,
+                0,
+            );
+            let slider = child(main, TRACKBAR_CLASSW, "", x, top, SLIDER_WIDTH, TBS_HORZ);
+            // SAFETY: messages to the trackbar just created
+            unsafe {
+                SendMessageW(slider, TBM_SETRANGEMIN, 0, 0);
+                SendMessageW(slider, TBM_SETRANGEMAX, 0, maximum as LPARAM);
+                SendMessageW(slider, TBM_SETPOS, 1, value as LPARAM);
+            }
+            SLIDERS.with_borrow_mut(|sliders| {
+                sliders.push(Slider {
+                    window: slider,
+                    display: index,
+                    control,
+                })
+            });
+            top += ROW;
+        }
+    }
+    // SAFETY: the window created above
+    unsafe { ShowWindow(main, SW_SHOW) };
+
+    let mut history = Log::default();
+    let mut changed: Option<Instant> = None;
+    // SAFETY: a message is plain data, zeroed until PeekMessageW fills it
+    let mut message: MSG = unsafe { mem::zeroed() };
+    loop {
+        // SAFETY: messages of this thread's windows, handed back to them
+        while unsafe { PeekMessageW(&mut message, ptr::null_mut(), 0, 0, PM_REMOVE) } != 0 {
+            if message.message == WM_QUIT {
+                SLIDERS.take();
+                return history.flush();
+            }
+            unsafe {
+                TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+        }
+        for (index, value) in MOVED.take() {
+            let moved = SLIDERS.with_borrow(|sliders| {
+                let slider = &sliders[index];
+                (slider.display, slider.control)
+            });
+            let display = &manager.displays[moved.0];
+            let Some(controller) = display.controls.get(&moved.1) else {
+                continue;
+            };
+            manager.queue_set(display.clone(), controller.clone(), value);
+            history.record(Origin::Gui, display, moved.1 as u8, value);
+            changed = Some(Instant::now());
+        }
+        // A drag is logged once, where it ended
+        if changed.is_some_and(|changed| changed.elapsed() >= SETTLE) {
+            changed = None;
+            if let Err(err) = history.flush() {
+                eprintln!("history: {err}");
+            }
+        }
+        thread::sleep(TICK);
+    }
+}
+
+/// The controls a slider is shown for.
+fn continuous<'a>(controls: impl Iterator<Item = &'a Control>) -> impl Iterator<Item = Control> {
+    controls.copied().filter(|control| control.is_continuous())
+}
+
+/// A child window of the main one a row high.
+fn child(
+    parent: HWND,
+    class: *const u16,
+    text: &str,
+    x: i32,
+    y: i32,
+    width: i32,
+    style: u32,
+) -> HWND {
+    let text = wide(text);
+    // SAFETY: a class of the system, the text is copied
+    unsafe {
+        CreateWindowExW(
+            0,
+            class,
+            text.as_ptr(),
+            WS_CHILD | WS_VISIBLE | style,
+            x,
+            y,
+            width,
+            ROW - 8,
+            parent,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null(),
+        )
+    }
+}
+
+/// The text NUL-terminated in UTF-16.
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain([0]).collect()
+}
+
+unsafe extern "system" fn procedure(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match message {
+        // Trackbars tell their parent they moved, with themselves as the last parameter
+        WM_HSCROLL if lparam != 0 => {
+            let moved = lparam as HWND;
+            let index = SLIDERS
+                .with_borrow(|sliders| (sliders.iter()).position(|slider| slider.window == moved));
+            if let Some(index) = index {
+                // SAFETY: the trackbar the message came from
+                let value = unsafe { SendMessageW(moved, TBM_GETPOS, 0, 0) };
+                MOVED.with_borrow_mut(|moves| moves.push((index, value as u16)));
+            }
+            0
+        }
+        WM_DESTROY => {
+            // SAFETY: ends the loop of this thread
+            unsafe { PostQuitMessage(0) };
+            0
+        }
+        // SAFETY: the arguments the system handed in
+        _ => unsafe { DefWindowProcW(window, message, wparam, lparam) },
+    }
+}
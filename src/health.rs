@@ -0,0 +1,124 @@
+//! Diagnostic codes of MCCS many displays answer, like usage time and firmware level, read on
+//! demand for the health panel of the TUI
+
+use crate::display::MyDisplay;
+use crate::Error;
+
+/// The diagnostic codes and their names, in the order shown
+pub const CODES: [(u8, &str); 8] = [
+    (0xC0, "Usage time"),
+    (0xC9, "Firmware level"),
+    (0xC8, "Display controller"),
+    (0xDF, "MCCS version"),
+    (0xB6, "Technology"),
+    (0xB2, "Sub-pixel layout"),
+    (0xAC, "Horizontal frequency"),
+    (0xAE, "Vertical frequency"),
+];
+
+/// Makers of display controllers by the low byte of 0xC8
+const CONTROLLERS: [&str; 29] = [
+    "Conexant",
+    "Genesis",
+    "Macronix",
+    "IDT",
+    "Mstar",
+    "Myson",
+    "Philips",
+    "PixelWorks",
+    "RealTek",
+    "Sage",
+    "Silicon Image",
+    "SmartASIC",
+    "STMicroelectronics",
+    "Topro",
+    "Trumpion",
+    "Welltrend",
+    "Samsung",
+    "Novatek",
+    "STK",
+    "Silicon Optics",
+    "Texas Instruments",
+    "Analogix",
+    "Quantum Data",
+    "NXP",
+    "Chrontel",
+    "Parade",
+    "THine",
+    "Trident",
+    "Micros",
+];
+
+const TECHNOLOGIES: [&str; 9] = [
+    "CRT, shadow mask",
+    "CRT, aperture grill",
+    "LCD, active matrix",
+    "LCoS",
+    "Plasma",
+    "OLED",
+    "EL",
+    "Dynamic MEM",
+    "Static MEM",
+];
+
+const LAYOUTS: [&str; 8] = [
+    "RGB vertical stripe",
+    "RGB horizontal stripe",
+    "BGR vertical stripe",
+    "BGR horizontal stripe",
+    "Quad, red top left",
+    "Quad, red bottom left",
+    "Delta",
+    "Mosaic",
+];
+
+/// A diagnostic code and what it read
+pub struct Reading {
+    pub code: u8,
+    pub name: &'static str,
+    /// The decoded value, or why the display didn't answer, most often since it doesn't have
+    /// the code
+    pub value: Result<String, Error>,
+}
+
+/// Reads every diagnostic code once. Most displays lack some, so failing doesn't mark the
+/// display as no longer answering.
+pub fn read(display: &MyDisplay) -> Vec<Reading> {
+    CODES
+        .iter()
+        .map(|&(code, name)| Reading {
+            code,
+            name,
+            value: display
+                .read_raw(code)
+                .map(|(value, maximum)| decode(code, value, maximum)),
+        })
+        .collect()
+}
+
+/// What the current and maximum value of a diagnostic code mean.
+pub fn decode(code: u8, value: u16, maximum: u16) -> String {
+    let [high, low] = value.to_be_bytes();
+    let named = |names: &[&str]| match names.get((low as usize).wrapping_sub(1)) {
+        Some(name) => name.to_string(),
+        None => format!("0x{low:02X}"),
+    };
+    match code {
+        0xC0 => format!("{value} h"),
+        0xC9 | 0xDF => format!("{high}.{low}"),
+        0xC8 => format!("{}, controller 0x{high:02X}", named(&CONTROLLERS)),
+        0xB6 => named(&TECHNOLOGIES),
+        0xB2 => named(&LAYOUTS),
+        // Three bytes in Hz, the highest one in the low byte of the maximum
+        0xAC => match (maximum as u32 & 0xFF) << 16 | value as u32 {
+            0xFF_FFFF => "out of range".to_string(),
+            hertz => format!("{:.2} kHz", hertz as f32 / 1000.0),
+        },
+        // In hundredths of a Hz
+        0xAE => match value {
+            0xFFFF => "out of range".to_string(),
+            value => format!("{:.2} Hz", value as f32 / 100.0),
+        },
+        _ => value.to_string(),
+    }
+}
@@ -0,0 +1,346 @@
+//! A minimal line protocol letting unprivileged users reach DDC through a root helper.
+//!
+//! Every request is one line and answered by one line starting with `ok` or `err`:
+//! `list` (`ok id<TAB>manufacturer<TAB>name...`, entries separated by `;`),
+//! `get <id> <code>` (`ok <value> <maximum>`), `set <id> <code> <value>` and
+//! `caps <id>` (`ok <hex encoded capabilities string>`). Only the codes [`writable`] accepts can
+//! be set, so a user reaching the socket can't reset or power off a display, and writes the
+//! [`Lock`] at [`lock::path`](crate::lock::path) refuses are refused here too.
+//!
+//! Each display is locked on its own, so a slow display only holds up the clients using it, and
+//! `list` scans the buses again at most every few seconds.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ops::RangeInclusive;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc::channel, Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use std::{env, process, thread};
+
+use ddc_hi::Ddc;
+
+use crate::backend::Found;
+use crate::display::ALL_CONTROLS;
+use crate::enumerate::enumerate;
+use crate::lock::Lock;
+use crate::orientation;
+
+pub const DEFAULT_SOCKET: &str = "/run/ddc_bright.sock";
+
+/// How long a client may take to send a request before it is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest request taken, in bytes with its newline, a client sending more is dropped
+const MAX_REQUEST: u64 = 256;
+
+/// Clients served at once, the ones past it are turned away until another hangs up
+const MAX_CLIENTS: usize = 16;
+
+/// How long `list` answers from the last scan instead of scanning the buses again
+const RESCAN: Duration = Duration::from_secs(5);
+
+/// The group the socket belongs to, as the socket unit from `contrib/` sets it
+const GROUP: &str = "ddc_bright";
+
+/// The 6-axis saturation and hue codes
+const SIX_AXIS: [RangeInclusive<u8>; 2] = [0x59..=0x5E, 0x9B..=0xA0];
+
+/// Whether the helper sets `code`: the controls, the orientation and the 6-axis codes.
+pub fn writable(code: u8) -> bool {
+    ALL_CONTROLS.iter().any(|&control| control as u8 == code)
+        || code == orientation::CODE
+        || SIX_AXIS.iter().any(|range| range.contains(&code))
+}
+
+/// A display as listed by the helper
+pub struct Listed {
+    pub id: String,
+    pub manufacturer: Option<String>,
+    pub name: String,
+    pub serial: Option<String>,
+    pub edid: Option<Vec<u8>>,
+}
+
+/// A display found by the helper, locked on its own
+struct Entry {
+    /// Its line in the answer to `list`
+    listed: String,
+    found: Mutex<Found>,
+}
+
+/// The displays of the last scan
+struct Displays {
+    entries: RwLock<HashMap<String, Arc<Entry>>>,
+    /// When the buses were last scanned, held while scanning so scans don't pile up
+    scanned: Mutex<Instant>,
+    lock: Option<Lock>,
+}
+
+/// Serves requests until killed, on the socket passed by systemd if there is one.
+pub fn serve(socket: &Path) -> io::Result<()> {
+    let listener = match activated() {
+        true => {
+            use std::os::unix::io::FromRawFd;
+            // SAFETY: systemd socket activation passes the listening socket as fd 3
+            unsafe { UnixListener::from_raw_fd(3) }
+        }
+        false => bind(socket)?,
+    };
+
+    let displays = Arc::new(Displays {
+        entries: RwLock::new(load()),
+        scanned: Mutex::new(Instant::now()),
+        lock: Lock::load().map_err(io::Error::other)?,
+    });
+    let clients = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        // A client going away before it is accepted says nothing about the others
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("helper: {err}");
+                continue;
+            }
+        };
+        let mut stream = stream;
+        if clients.load(Ordering::SeqCst) >= MAX_CLIENTS {
+            let _ = writeln!(stream, "err too many clients");
+            continue;
+        }
+        clients.fetch_add(1, Ordering::SeqCst);
+        let (displays, clients) = (displays.clone(), clients.clone());
+        thread::spawn(move || {
+            if let Err(err) = client(stream, &displays) {
+                eprintln!("helper: {err}");
+            }
+            clients.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+/// Whether systemd started the helper for its socket, and not some process further up.
+fn activated() -> bool {
+    let pid = env::var("LISTEN_PID").is_ok_and(|pid| pid == process::id().to_string());
+    pid && env::var("LISTEN_FDS").as_deref() == Ok("1")
+}
+
+/// Listens on `socket` like the socket unit does: for root and the `ddc_bright` group only.
+fn bind(socket: &Path) -> io::Result<UnixListener> {
+    let _ = fs::remove_file(socket);
+    // Nobody else may connect in between binding and changing the mode either
+    // SAFETY: umask only swaps the mask of the process, nothing else runs yet
+    let umask = unsafe { libc::umask(0o117) };
+    let listener = UnixListener::bind(socket);
+    unsafe { libc::umask(umask) };
+    let listener = listener?;
+    match group(GROUP) {
+        Some(gid) => std::os::unix::fs::chown(socket, None, Some(gid))?,
+        None => eprintln!(
+            "helper: there is no {GROUP} group, only root can reach {}",
+            socket.display()
+        ),
+    }
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o660))?;
+    Ok(listener)
+}
+
+/// The id of the group called `name`.
+fn group(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    // SAFETY: the entry is copied out before anything else can call getgrnam
+    let group = unsafe { libc::getgrnam(name.as_ptr()) };
+    (!group.is_null()).then(|| unsafe { (*group).gr_gid })
+}
+
+/// Answers the requests of one client until it hangs up, takes too long to send one or sends
+/// one longer than [`MAX_REQUEST`].
+fn client(stream: UnixStream, displays: &Displays) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = (&mut reader).take(MAX_REQUEST).read_line(&mut line)?;
+        if read == 0 {
+            return Ok(());
+        }
+        if read as u64 == MAX_REQUEST && !line.ends_with('\n') {
+            return writeln!(writer, "err request too long");
+        }
+        let reply = match answer(displays, line.trim()) {
+            Ok(reply) => format!("ok {reply}"),
+            Err(err) => format!("err {}", err.to_string().replace('\n', " ")),
+        };
+        writeln!(writer, "{reply}")?;
+    }
+}
+
+fn load() -> HashMap<String, Arc<Entry>> {
+    let (progress, _) = channel();
+    enumerate(&HashMap::new(), &mut vec![], &progress)
+        .into_iter()
+        .map(|display| {
+            let info = &display.info;
+            let name = info.model_name.clone().or(info.serial_number.clone());
+            let edid: String = (info.edid_data.iter().flatten())
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            let listed = format!(
+                "{}\t{}\t{}\t{}\t{edid}",
+                info.id,
+                info.manufacturer_id.as_deref().unwrap_or_default(),
+                name.as_deref().unwrap_or(&info.id),
+                info.serial_number.as_deref().unwrap_or_default()
+            );
+            let id = info.id.clone();
+            let found = Mutex::new(display);
+            (id, Arc::new(Entry { listed, found }))
+        })
+        .collect()
+}
+
+fn answer(displays: &Displays, line: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if let ["list"] = words[..] {
+        {
+            let mut scanned = displays.scanned.lock().unwrap();
+            if scanned.elapsed() >= RESCAN {
+                let entries = load();
+                *displays.entries.write().unwrap() = entries;
+                *scanned = Instant::now();
+            }
+        }
+        let entries = displays.entries.read().unwrap();
+        let listed: Vec<&str> = entries.values().map(|entry| &entry.listed[..]).collect();
+        return Ok(listed.join(";"));
+    }
+
+    if !matches!(words.first(), Some(&("get" | "set" | "caps"))) {
+        return Err("unknown request".into());
+    }
+    let id = words.get(1).ok_or("missing display id")?;
+    let entry = displays.entries.read().unwrap().get(*id).cloned();
+    let entry = entry.ok_or("unknown display")?;
+    let mut display = entry.found.lock().unwrap();
+    let code = || -> Result<u8, Box<dyn std::error::Error>> {
+        Ok(u8::from_str_radix(words.get(2).ok_or("missing code")?, 16)?)
+    };
+    match words[0] {
+        "get" => {
+            let code = code()?;
+            let value = display.handle.with(|handle| handle.get_vcp_feature(code))?;
+            Ok(format!("{} {}", value.value(), value.maximum()))
+        }
+        "set" => {
+            let value = words.get(3).ok_or("missing value")?.parse()?;
+            let code = code()?;
+            if !writable(code) {
+                return Err(format!("code {code:02x} isn't writable through the helper").into());
+            }
+            if let Some(lock) = &displays.lock {
+                let read = display.handle.with(|handle| handle.get_vcp_feature(code))?;
+                if let Some(refusal) = lock.refusal(code, value, read.maximum()) {
+                    return Err(refusal.into());
+                }
+            }
+            display
+                .handle
+                .with(|handle| handle.set_vcp_feature(code, value))?;
+            Ok(String::new())
+        }
+        _ => {
+            let caps = display.handle.with(|handle| handle.capabilities_string())?;
+            Ok(caps.iter().map(|byte| format!("{byte:02x}")).collect())
+        }
+    }
+}
+
+/// Talks to a helper, one connection per request
+#[derive(Clone)]
+pub struct Client {
+    socket: PathBuf,
+}
+
+impl Client {
+    /// Connects to the helper if its socket exists.
+    pub fn new(socket: &Path) -> Option<Self> {
+        socket.exists().then(|| Self {
+            socket: socket.to_path_buf(),
+        })
+    }
+
+    fn request(&self, request: &str) -> io::Result<String> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+        writeln!(stream, "{request}")?;
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        let reply = reply.trim_end_matches('\n');
+        match reply.split_once(' ').unwrap_or((reply, "")) {
+            ("ok", rest) => Ok(rest.to_string()),
+            (_, rest) => Err(io::Error::other(format!("helper: {rest}"))),
+        }
+    }
+
+    pub fn list(&self) -> io::Result<Vec<Listed>> {
+        let reply = self.request("list")?;
+        Ok(reply
+            .split(';')
+            .filter_map(|entry| {
+                let mut fields = entry.split('\t');
+                let id = fields.next()?.to_string();
+                let manufacturer = fields.next().filter(|m| !m.is_empty()).map(String::from);
+                let name = fields.next()?.to_string();
+                // Older helpers don't send the serial
+                let serial = fields.next().filter(|s| !s.is_empty()).map(String::from);
+                // Nor the EDID, in hex
+                let edid = fields.next().and_then(|hex| {
+                    (0..hex.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+                        .collect::<Option<Vec<u8>>>()
+                        .filter(|edid| !edid.is_empty())
+                });
+                Some(Listed {
+                    id,
+                    manufacturer,
+                    name,
+                    serial,
+                    edid,
+                })
+            })
+            .collect())
+    }
+
+    pub fn get(&self, id: &str, code: u8) -> io::Result<(u16, u16)> {
+        let reply = self.request(&format!("get {id} {code:02x}"))?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "helper: invalid reply");
+        let (value, maximum) = reply.split_once(' ').ok_or_else(invalid)?;
+        Ok((
+            value.parse().map_err(|_| invalid())?,
+            maximum.parse().map_err(|_| invalid())?,
+        ))
+    }
+
+    pub fn set(&self, id: &str, code: u8, value: u16) -> io::Result<()> {
+        self.request(&format!("set {id} {code:02x} {value}"))?;
+        Ok(())
+    }
+
+    pub fn capabilities(&self, id: &str) -> io::Result<Vec<u8>> {
+        let reply = self.request(&format!("caps {id}"))?;
+        (0..reply.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(reply.get(i..i + 2).unwrap_or_default(), 16))
+            .collect::<Result<_, _>>()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "helper: invalid reply"))
+    }
+}
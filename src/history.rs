@@ -0,0 +1,285 @@
+//! Every value change with what made it, appended to `history.log` next to the config
+
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Local};
+use ddc_bright::display::{Control, MyDisplay, Noticed};
+
+use crate::{audit, config::Config};
+
+/// The log is cut to its newer half once it grows past this
+const MAX_BYTES: u64 = 1 << 20;
+
+type Watcher = Box<dyn Fn(&Entry) + Send>;
+
+/// Told about every change as it is recorded, see [`watch`]
+static WATCHER: Mutex<Option<Watcher>> = Mutex::new(None);
+
+/// What changed a value
+#[derive(Clone, Copy, PartialEq)]
+pub enum Origin {
+    /// A key press or the palette in the TUI
+    Tui,
+    /// A subcommand or flag
+    Cli,
+    Hotkey,
+    Schedule,
+    /// `calibrate` trying out brightness levels, or adaptive brightness following the sensor
+    Ambient,
+    Watchdog,
+    /// A wake-up rule switching the input
+    Wake,
+    /// Following the internal backlight
+    Backlight,
+    /// A step of a scene file
+    Scene,
+    /// The other machine sharing the displays took over
+    Kvm,
+    /// The displays connected matched a setup rule
+    Setup,
+    /// A request on the control socket of the daemon
+    Socket,
+    /// A method call on the D-Bus service of the daemon
+    Dbus,
+    /// A command Home Assistant sent over MQTT
+    Mqtt,
+    /// A slider of `ddc_bright gui`
+    Gui,
+    /// Changed at the display's buttons or by another program, noticed by polling
+    External,
+}
+
+const ORIGINS: [(Origin, &str); 16] = [
+    (Origin::Tui, "tui"),
+    (Origin::Cli, "cli"),
+    (Origin::Hotkey, "hotkey"),
+    (Origin::Schedule, "schedule"),
+    (Origin::Ambient, "ambient"),
+    (Origin::Watchdog, "watchdog"),
+    (Origin::Wake, "wake"),
+    (Origin::Backlight, "backlight"),
+    (Origin::Scene, "scene"),
+    (Origin::Kvm, "kvm"),
+    (Origin::Setup, "setup"),
+    (Origin::Socket, "socket"),
+    (Origin::Dbus, "dbus"),
+    (Origin::Mqtt, "mqtt"),
+    (Origin::Gui, "gui"),
+    (Origin::External, "external"),
+];
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = ORIGINS.iter().find(|(origin, _)| origin == self).unwrap();
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Origin {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        ORIGINS
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(text.trim()))
+            .map(|(origin, _)| *origin)
+            .ok_or_else(|| {
+                let names: Vec<&str> = ORIGINS.iter().map(|(_, name)| *name).collect();
+                format!("{text:?} is not one of {}", names.join(", "))
+            })
+    }
+}
+
+/// One value written to a display
+pub struct Entry {
+    pub time: DateTime<Local>,
+    pub origin: Origin,
+    pub id: String,
+    pub display: String,
+    pub code: u8,
+    pub value: u16,
+    /// The value the change replaced as far as the display told, only kept in the audit log
+    pub old: Option<u16>,
+}
+
+/// Name of the control with the code, or the code for ones not modeled as a control.
+pub fn control_name(code: u8) -> String {
+    match Control::from_code(code) {
+        Some(control) => control.key(),
+        None => format!("0x{code:02x}"),
+    }
+}
+
+impl Entry {
+    pub fn control(&self) -> String {
+        control_name(self.code)
+    }
+
+    /// Tab separated, display names can't hold tabs but can hold spaces.
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{:02x}\t{}",
+            self.time.to_rfc3339(),
+            self.origin,
+            self.id,
+            self.display,
+            self.code,
+            self.value
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        Some(Self {
+            time: DateTime::parse_from_rfc3339(fields.next()?)
+                .ok()?
+                .with_timezone(&Local),
+            origin: fields.next()?.parse().ok()?,
+            id: fields.next()?.to_string(),
+            display: fields.next()?.to_string(),
+            code: u8::from_str_radix(fields.next()?, 16).ok()?,
+            value: fields.next()?.parse().ok()?,
+            old: None,
+        })
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}  {:<8}  {}  {} {}",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.origin.to_string(),
+            self.display,
+            self.control(),
+            self.value
+        )
+    }
+}
+
+/// Changes not appended to the log yet, written when flushed or dropped
+#[derive(Default)]
+pub struct Log {
+    pending: Vec<Entry>,
+    /// Drops what is recorded, for changes that are only previewed
+    pub paused: bool,
+}
+
+impl Log {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("history.log"))
+    }
+
+    pub fn record(&mut self, origin: Origin, display: &MyDisplay, code: u8, value: u16) {
+        if self.paused {
+            return;
+        }
+        // A held key changes the same control over and over, only the value it ends at counts
+        if let Some(last) = self.pending.last_mut() {
+            if last.origin == origin && last.id == display.id && last.code == code {
+                last.time = Local::now();
+                last.value = value;
+                notify(last);
+                return;
+            }
+        }
+        self.pending.push(Entry {
+            time: Local::now(),
+            origin,
+            id: display.id.clone(),
+            display: display.label(),
+            code,
+            value,
+            old: display.replaced(code, value),
+        });
+        notify(self.pending.last().unwrap());
+    }
+
+    /// Records a change polling noticed, made outside of ddc_bright.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn record_noticed(&mut self, noticed: &Noticed) {
+        self.pending.push(Entry {
+            time: Local::now(),
+            origin: Origin::External,
+            id: noticed.display.id.clone(),
+            display: noticed.display.label(),
+            code: noticed.control as u8,
+            value: noticed.value,
+            old: Some(noticed.old),
+        });
+        notify(self.pending.last().unwrap());
+    }
+
+    /// Appends the pending changes to the log.
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        if let Err(err) = audit::append(&self.pending) {
+            eprintln!("audit: {err}");
+        }
+        let path = Self::path().ok_or("no config directory")?;
+        let text: String = self
+            .pending
+            .drain(..)
+            .map(|entry| entry.to_line() + "\n")
+            .collect();
+        append(&path, &text)
+    }
+
+    /// The logged changes, oldest first. Lines that can't be read are skipped.
+    pub fn read() -> Result<Vec<Entry>, Box<dyn Error>> {
+        let Some(path) = Self::path() else {
+            return Ok(vec![]);
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Ok(text.lines().filter_map(Entry::from_line).collect()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Has `watcher` called with every change recorded from now on, by any log of the process,
+/// including the ones polling noticed. `None` stops calling the one there was.
+#[cfg_attr(not(feature = "daemon"), allow(dead_code))]
+pub fn watch(watcher: Option<Watcher>) {
+    *WATCHER.lock().unwrap() = watcher;
+}
+
+fn notify(entry: &Entry) {
+    if let Some(watcher) = &*WATCHER.lock().unwrap() {
+        watcher(entry);
+    }
+}
+
+/// Appends lines to a log file, cutting it to its newer half once it grows past `MAX_BYTES`.
+pub fn append(path: &Path, text: &str) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(path.parent().ok_or("no config directory")?)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(text.as_bytes())?;
+    if file.metadata()?.len() > MAX_BYTES {
+        let text = fs::read_to_string(path)?;
+        let lines: Vec<&str> = text.lines().collect();
+        let kept = lines[lines.len() / 2..].join("\n") + "\n";
+        fs::write(path, kept)?;
+    }
+    Ok(())
+}
+
+impl Drop for Log {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            eprintln!("history: {err}");
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Hotkeys the daemon takes system-wide, the `global` key combinations of `[[hotkeys]]`.
+//!
+//! Linux reads the keyboards in `/dev/input`, which takes being in the `input` group, so it
+//! works the same on X11, Wayland and the console; the keys still reach the program in front.
+//! Windows registers them with `RegisterHotKey`. Keys are named by their place on a US layout,
+//! with `Ctrl`, `Alt`, `Shift` and `Super` in front, e.g. `Super+Alt+Up`.
+
+use std::{collections::HashMap, error::Error, fmt, str::FromStr, time::Duration};
+
+use ddc_bright::{
+    display::{DisplayManager, WrappedDisplay},
+    supervisor::Supervisor,
+};
+
+use crate::{
+    config::{Config, Hotkey, Target},
+    group::Group,
+    history::{Log, Origin},
+    logging,
+    watchdog::Jobs,
+};
+
+/// How often the keyboards are looked at for presses and cancellation
+const TICK: Duration = Duration::from_millis(100);
+
+/// Letters and digits by their row of a US keyboard, with the Linux code of the first key
+const ROWS: [(&str, u16); 4] = [
+    ("1234567890", 2),
+    ("qwertyuiop", 16),
+    ("asdfghjkl", 30),
+    ("zxcvbnm", 44),
+];
+
+/// The other keys by name, with their Linux code and Windows virtual key, 0 where there is none
+const NAMED: [(&str, u16, u16); 27] = [
+    ("-", 12, 0xBD),
+    ("=", 13, 0xBB),
+    ("[", 26, 0xDB),
+    ("]", 27, 0xDD),
+    (";", 39, 0xBA),
+    ("'", 40, 0xDE),
+    ("`", 41, 0xC0),
+    ("\\", 43, 0xDC),
+    (",", 51, 0xBC),
+    (".", 52, 0xBE),
+    ("/", 53, 0xBF),
+    ("space", 57, 0x20),
+    ("home", 102, 0x24),
+    ("up", 103, 0x26),
+    ("pageup", 104, 0x21),
+    ("left", 105, 0x25),
+    ("right", 106, 0x27),
+    ("end", 107, 0x23),
+    ("down", 108, 0x28),
+    ("pagedown", 109, 0x22),
+    ("insert", 110, 0x2D),
+    ("delete", 111, 0x2E),
+    ("mute", 113, 0xAD),
+    ("volumedown", 114, 0xAE),
+    ("volumeup", 115, 0xAF),
+    // Windows leaves the brightness keys to the firmware
+    ("brightnessdown", 224, 0),
+    ("brightnessup", 225, 0),
+];
+
+/// A key with the modifiers held with it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chord {
+    text: String,
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    super_key: bool,
+    /// Linux key code
+    code: u16,
+    /// Windows virtual key, 0 where there is none
+    virtual_key: u16,
+}
+
+impl Chord {
+    /// Ctrl, Alt, Shift and Super, in that order.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn modifiers(&self) -> [bool; 4] {
+        [self.ctrl, self.alt, self.shift, self.super_key]
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key = (parts.pop())
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| format!("{text:?} has no key"))?;
+        let (code, virtual_key) = key_code(key).ok_or_else(|| format!("{key:?} is not a key"))?;
+        let mut chord = Chord {
+            text: text.to_string(),
+            ctrl: false,
+            alt: false,
+            shift: false,
+            super_key: false,
+            code,
+            virtual_key,
+        };
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "alt" => chord.alt = true,
+                "shift" => chord.shift = true,
+                "super" | "win" | "meta" => chord.super_key = true,
+                _ => return Err(format!("{modifier:?} is not Ctrl, Alt, Shift or Super")),
+            }
+        }
+        Ok(chord)
+    }
+}
+
+/// The Linux code and Windows virtual key of the key called `name`.
+fn key_code(name: &str) -> Option<(u16, u16)> {
+    let name = name.to_lowercase();
+    let function = name
+        .strip_prefix('f')
+        .and_then(|number| number.parse::<u16>().ok());
+    if let Some(number) = function.filter(|number| (1..=12).contains(number)) {
+        // F11 and F12 came later and are numbered after the keypad
+        let code = match number {
+            1..=10 => 58 + number,
+            _ => 76 + number,
+        };
+        return Some((code, 0x6F + number));
+    }
+    let mut chars = name.chars();
+    if let (Some(key), None) = (chars.next(), chars.next()) {
+        for (row, first) in ROWS {
+            if let Some(index) = row.find(key) {
+                return Some((first + index as u16, key.to_ascii_uppercase() as u16));
+            }
+        }
+    }
+    (NAMED.iter())
+        .find(|(known, ..)| *known == name)
+        .map(|&(_, code, virtual_key)| (code, virtual_key))
+}
+
+/// Takes the `global` keys of the hotkeys while the returned supervisor lives, `None` if no
+/// hotkey has one. Presses are handed to the daemon through `jobs`.
+pub fn start(config: &Config, jobs: Jobs) -> Result<Option<Supervisor>, Box<dyn Error>> {
+    let mut bound = vec![];
+    for hotkey in &config.hotkeys {
+        if let Some(global) = &hotkey.global {
+            let chord: Chord = global.parse().map_err(|err| format!("hotkeys: {err}"))?;
+            bound.push((chord, hotkey.clone()));
+        }
+    }
+    if bound.is_empty() {
+        return Ok(None);
+    }
+    let chords = bound.iter().map(|(chord, _)| chord.clone()).collect();
+    let groups = config.groups.clone();
+    let supervisor = platform::listen(chords, move |index| {
+        let (hotkey, groups) = (bound[index].1.clone(), groups.clone());
+        jobs.run(move |manager| press(manager, &hotkey, &groups));
+    })?;
+    Ok(Some(supervisor))
+}
+
+/// Moves the control of the hotkey's displays by its step. The daemon has no focus of its own,
+/// so `focused` is the display written last, the first one until then.
+fn press(manager: &DisplayManager, hotkey: &Hotkey, groups: &HashMap<String, Group>) {
+    let displays = manager.displays.iter();
+    let targets: Vec<&WrappedDisplay> = match &hotkey.target {
+        Target::Focused => {
+            let (written, _) = manager.last_written();
+            let last = written.and_then(|written| {
+                (manager.displays.iter()).find(|display| display.id == written.id)
+            });
+            last.or(manager.displays.first()).into_iter().collect()
+        }
+        Target::All => displays.collect(),
+        Target::Group(name) => match groups.get(name) {
+            Some(group) => displays.filter(|display| group.contains(display)).collect(),
+            None => {
+                logging::report(format!("hotkeys: there is no group {name}"));
+                vec![]
+            }
+        },
+    };
+    let mut history = Log::default();
+    for display in targets {
+        let Some(controller) = display.controls.get(&hotkey.control) else {
+            continue;
+        };
+        manager.queue_change(display.clone(), controller.clone(), hotkey.step);
+        let value = controller.read().unwrap().value;
+        history.record(Origin::Hotkey, display, hotkey.control as u8, value);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::{
+        error::Error,
+        fs::{self, File},
+        io::Read,
+        mem,
+        os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+        ptr,
+    };
+
+    use ddc_bright::supervisor::Supervisor;
+
+    use super::{Chord, TICK};
+
+    /// Codes of the left and right modifier keys, in the order of [`Chord::modifiers`]
+    const MODIFIERS: [[u16; 2]; 4] = [[29, 97], [56, 100], [42, 54], [125, 126]];
+
+    const EV_KEY: u16 = 1;
+
+    pub fn listen(
+        chords: Vec<Chord>,
+        pressed: impl Fn(usize) + Send + 'static,
+    ) -> Result<Supervisor, Box<dyn Error>> {
+        let devices =
+            fs::read_dir("/dev/input").map_err(|err| format!("hotkeys: /dev/input: {err}"))?;
+        let mut keyboards: Vec<File> = (devices.flatten())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("event"))
+            .filter_map(|entry| {
+                let mut options = fs::OpenOptions::new();
+                options.read(true).custom_flags(libc::O_NONBLOCK);
+                options.open(entry.path()).ok()
+            })
+            .collect();
+        if keyboards.is_empty() {
+            return Err("hotkeys: no device in /dev/input can be read, \
+                add the user to the input group"
+                .into());
+        }
+
+        let mut supervisor = Supervisor::default();
+        supervisor.spawn("hotkeys", move |token| {
+            let size = mem::size_of::<libc::input_event>();
+            let mut buffer = vec![0; size * 64];
+            let mut held = [false; 4];
+            while !token.is_cancelled() && !keyboards.is_empty() {
+                let mut polled: Vec<libc::pollfd> = (keyboards.iter())
+                    .map(|keyboard| libc::pollfd {
+                        fd: keyboard.as_raw_fd(),
+                        events: libc::POLLIN,
+                        revents: 0,
+                    })
+                    .collect();
+                // SAFETY: the descriptors belong to the files, which outlive the call
+                let ready = unsafe {
+                    libc::poll(
+                        polled.as_mut_ptr(),
+                        polled.len() as _,
+                        TICK.as_millis() as _,
+                    )
+                };
+                if ready <= 0 {
+                    continue;
+                }
+                for keyboard in &mut keyboards {
+                    while let Ok(read @ 1..) = keyboard.read(&mut buffer) {
+                        for event in buffer[..read].chunks_exact(size) {
+                            // SAFETY: the kernel only hands out whole events
+                            let event: libc::input_event =
+                                unsafe { ptr::read_unaligned(event.as_ptr().cast()) };
+                            if event.type_ != EV_KEY {
+                                continue;
+                            }
+                            // 1 is a press and 2 a repeat of a held key, 0 a release
+                            let down = event.value != 0;
+                            let modifier = MODIFIERS.iter().position(|m| m.contains(&event.code));
+                            if let Some(modifier) = modifier {
+                                held[modifier] = down;
+                                continue;
+                            }
+                            for (index, chord) in chords.iter().enumerate() {
+                                if down && chord.code == event.code && chord.modifiers() == held {
+                                    pressed(index);
+                                }
+                            }
+                        }
+                    }
+                }
+                // Unplugged keyboards only report errors from then on
+                let gone = libc::POLLERR | libc::POLLHUP | libc::POLLNVAL;
+                let mut polled = polled.iter();
+                keyboards.retain(|_| polled.next().is_some_and(|poll| poll.revents & gone == 0));
+            }
+        });
+        Ok(supervisor)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{error::Error, io, mem, ptr, thread};
+
+    use ddc_bright::supervisor::Supervisor;
+    use windows_sys::Win32::UI::{
+        Input::KeyboardAndMouse::{
+            RegisterHotKey, UnregisterHotKey, MOD_ALT, MOD_CONTROL, MOD_SHIFT, MOD_WIN,
+        },
+        WindowsAndMessaging::{PeekMessageW, MSG, PM_REMOVE, WM_HOTKEY},
+    };
+
+    use super::{Chord, TICK};
+    use crate::logging;
+
+    pub fn listen(
+        chords: Vec<Chord>,
+        pressed: impl Fn(usize) + Send + 'static,
+    ) -> Result<Supervisor, Box<dyn Error>> {
+        if let Some(chord) = chords.iter().find(|chord| chord.virtual_key == 0) {
+            return Err(format!("hotkeys: Windows doesn't hand out {chord}").into());
+        }
+        let mut supervisor = Supervisor::default();
+        supervisor.spawn("hotkeys", move |token| {
+            // Hotkeys belong to the thread registering them, their presses arrive in its queue
+            for (index, chord) in chords.iter().enumerate() {
+                let modifiers = [
+                    (chord.ctrl, MOD_CONTROL),
+                    (chord.alt, MOD_ALT),
+                    (chord.shift, MOD_SHIFT),
+                    (chord.super_key, MOD_WIN),
+                ];
+                let modifiers = (modifiers.iter())
+                    .filter(|(held, _)| *held)
+                    .fold(0, |all, (_, modifier)| all | modifier);
+                let key = chord.virtual_key as u32;
+                // SAFETY: no window, the presses go to this thread
+                if unsafe { RegisterHotKey(ptr::null_mut(), index as i32, modifiers, key) } == 0 {
+                    let err = io::Error::last_os_error();
+                    logging::report(format!("hotkeys: {chord}: {err}"));
+                }
+            }
+            // SAFETY: a message is plain data, zeroed until PeekMessageW fills it
+            let mut message: MSG = unsafe { mem::zeroed() };
+            while !token.is_cancelled() {
+                while unsafe { PeekMessageW(&mut message, ptr::null_mut(), 0, 0, PM_REMOVE) } != 0 {
+                    if message.message == WM_HOTKEY {
+                        pressed(message.wParam);
+                    }
+                }
+                thread::sleep(TICK);
+            }
+            for index in 0..chords.len() {
+                unsafe { UnregisterHotKey(ptr::null_mut(), index as i32) };
+            }
+        });
+        Ok(supervisor)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use std::error::Error;
+
+    use ddc_bright::supervisor::Supervisor;
+
+    use super::Chord;
+
+    pub fn listen(
+        _chords: Vec<Chord>,
+        _pressed: impl Fn(usize) + Send + 'static,
+    ) -> Result<Supervisor, Box<dyn Error>> {
+        Err("hotkeys: global keys aren't supported on this platform".into())
+    }
+}
@@ -0,0 +1,155 @@
+//! Notices displays being plugged in or out, so the display list can follow
+
+use std::io;
+
+/// Something that happened to the connected displays
+pub(crate) enum Event {
+    /// A display came or went, which one isn't known
+    Changed,
+    /// The display with this id was unplugged, only udev tells which one it was
+    #[cfg_attr(windows, allow(dead_code))]
+    Removed(String),
+}
+
+/// Listens to udev for connector changes of the graphics cards and i2c buses going away.
+#[cfg(target_os = "linux")]
+pub(crate) struct Watcher {
+    socket: udev::MonitorSocket,
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    pub fn new() -> io::Result<Self> {
+        let context = udev::Context::new()?;
+        let mut builder = udev::MonitorBuilder::new(&context)?;
+        builder.match_subsystem("drm")?;
+        builder.match_subsystem("i2c-dev")?;
+        Ok(Self {
+            socket: builder.listen()?,
+        })
+    }
+
+    /// Events received since the last call, without blocking.
+    pub fn poll(&mut self) -> Vec<Event> {
+        self.socket
+            .by_ref()
+            .map(|event| {
+                let removed = event.event_type() == udev::EventType::Remove
+                    && event
+                        .subsystem()
+                        .is_some_and(|subsystem| subsystem == "i2c-dev");
+                match (removed, event.devnum()) {
+                    // i2c-dev displays are identified by the device number of their bus
+                    (true, Some(devnum)) => Event::Removed(devnum.to_string()),
+                    _ => Event::Changed,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A hidden window receiving the device and display change broadcasts.
+#[cfg(windows)]
+pub(crate) struct Watcher {
+    window: windows_sys::Win32::Foundation::HWND,
+}
+
+#[cfg(windows)]
+mod window {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        DefWindowProcW, DBT_DEVNODES_CHANGED, WM_DEVICECHANGE, WM_DISPLAYCHANGE,
+    };
+
+    /// Set by the window procedure, which can't reach the watcher
+    pub static CHANGED: AtomicBool = AtomicBool::new(false);
+
+    pub unsafe extern "system" fn procedure(
+        window: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if (message == WM_DEVICECHANGE && wparam == DBT_DEVNODES_CHANGED as WPARAM)
+            || message == WM_DISPLAYCHANGE
+        {
+            CHANGED.store(true, Ordering::SeqCst);
+        }
+        DefWindowProcW(window, message, wparam, lparam)
+    }
+}
+
+#[cfg(windows)]
+impl Watcher {
+    pub fn new() -> io::Result<Self> {
+        use std::ptr;
+        use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+        use windows_sys::Win32::UI::WindowsAndMessaging::{
+            CreateWindowExW, RegisterClassW, WNDCLASSW,
+        };
+
+        let class: Vec<u16> = "ddc_bright hotplug\0".encode_utf16().collect();
+        // SAFETY: the class name is NUL terminated and outlives both calls. A top-level window
+        // is needed, message-only windows don't get broadcasts.
+        let window = unsafe {
+            let instance = GetModuleHandleW(ptr::null());
+            let class_info = WNDCLASSW {
+                lpfnWndProc: Some(window::procedure),
+                hInstance: instance,
+                lpszClassName: class.as_ptr(),
+                ..Default::default()
+            };
+            // Fails when registered before, which is fine
+            RegisterClassW(&class_info);
+            CreateWindowExW(
+                0,
+                class.as_ptr(),
+                class.as_ptr(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                instance,
+                ptr::null(),
+            )
+        };
+        if window.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { window })
+    }
+
+    /// Events received since the last call, without blocking.
+    pub fn poll(&mut self) -> Vec<Event> {
+        use std::sync::atomic::Ordering;
+        use windows_sys::Win32::UI::WindowsAndMessaging::{
+            DispatchMessageW, PeekMessageW, TranslateMessage, MSG, PM_REMOVE,
+        };
+
+        let mut message = MSG::default();
+        // SAFETY: the window belongs to this thread, messages are handled by the procedure
+        while unsafe { PeekMessageW(&mut message, self.window, 0, 0, PM_REMOVE) } != 0 {
+            unsafe {
+                TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+        }
+        match window::CHANGED.swap(false, Ordering::SeqCst) {
+            true => vec![Event::Changed],
+            false => vec![],
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // SAFETY: the window was created by this watcher and isn't used after this
+        unsafe { windows_sys::Win32::UI::WindowsAndMessaging::DestroyWindow(self.window) };
+    }
+}
@@ -0,0 +1,529 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use ddc_bright::display::Control;
+use serde::{Deserialize, Serialize};
+
+/// Which keyboard layout navigation keys are looked up by, so they stay where WASD sits on QWERTY
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Qwertz,
+    Azerty,
+    Dvorak,
+    Colemak,
+}
+
+impl KeyboardLayout {
+    /// Keys moving up, left, down and right, where WASD sits on QWERTY.
+    fn block(self) -> [char; 4] {
+        match self {
+            KeyboardLayout::Qwerty | KeyboardLayout::Qwertz => ['w', 'a', 's', 'd'],
+            KeyboardLayout::Azerty => ['z', 'q', 's', 'd'],
+            KeyboardLayout::Dvorak => [',', 'a', 'o', 'e'],
+            KeyboardLayout::Colemak => ['w', 'a', 'r', 's'],
+        }
+    }
+
+    /// The free key in the place the default key `taken` has on QWERTY, for the default keys
+    /// the block takes. Colemak has `p` there for `r`, which the presets have, so refreshing goes
+    /// to `f` next to it.
+    fn moved(self, taken: char) -> Option<char> {
+        match (self, taken) {
+            (KeyboardLayout::Azerty, 'q') => Some('a'),
+            (KeyboardLayout::Dvorak, 'e') => Some('.'),
+            (KeyboardLayout::Colemak, 'r') => Some('f'),
+            _ => None,
+        }
+    }
+}
+
+/// Which keys navigate
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyProfile {
+    /// The WASD block of the layout and the arrow keys
+    #[default]
+    Wasd,
+    /// Only the arrow keys, leaving every letter to its own binding
+    Arrows,
+}
+
+/// What holding an arrow key down does to the highlighted control
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Hold {
+    /// One step per repeated key press
+    Off,
+    /// Moves on a timer, faster the longer the key is held, writing as it goes
+    #[default]
+    Live,
+    /// Moves the same way but only writes once the key is let go
+    WriteBack,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    pub layout: KeyboardLayout,
+    pub profile: KeyProfile,
+    /// Keys highlighting a control of the selected display, wherever the highlight is
+    pub controls: BTreeMap<char, Control>,
+    /// Amount the arrow keys adjust by at startup
+    pub step: i16,
+    /// Step sizes `+` and `-` switch between
+    pub steps: Vec<i16>,
+    /// Smallest step on slow displays, so a change doesn't take dozens of slow writes
+    pub slow_step: i16,
+    /// Fixed raw steps of controls, instead of the step size scaled to their range
+    pub control_steps: BTreeMap<Control, i16>,
+    /// Control the scroll wheel and Shift+arrows adjust from the display list
+    pub primary: Control,
+    pub hold: Hold,
+    /// Keys of actions, replacing the default keys of each action listed
+    pub bindings: BTreeMap<Binding, Vec<Key>>,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        Self {
+            layout: KeyboardLayout::default(),
+            profile: KeyProfile::default(),
+            controls: BTreeMap::new(),
+            step: 1,
+            steps: vec![1, 5, 10],
+            slow_step: 5,
+            control_steps: BTreeMap::new(),
+            primary: Control::Brightness,
+            hold: Hold::default(),
+            bindings: BTreeMap::new(),
+        }
+    }
+}
+
+pub enum Move {
+    Up,
+    Left,
+    Down,
+    Right,
+}
+
+impl KeysConfig {
+    /// The next larger step size after `step`, or the next smaller with a negative `direction`,
+    /// staying at the ends.
+    pub fn next_step(&self, step: i16, direction: isize) -> i16 {
+        let mut steps = self.steps.clone();
+        steps.push(step);
+        steps.sort_unstable();
+        steps.dedup();
+        let index = steps.iter().position(|known| *known == step).unwrap();
+        let index = index.saturating_add_signed(direction).min(steps.len() - 1);
+        steps[index]
+    }
+
+    /// The keys of an action, from `bindings` or the defaults. A default key the navigation
+    /// block took gets company where the layout has a free key in its place, like `a` for `q`
+    /// on AZERTY.
+    pub fn keys(&self, binding: Binding) -> Vec<Key> {
+        if let Some(keys) = self.bindings.get(&binding) {
+            return keys.clone();
+        }
+        let mut keys = binding.default_keys();
+        for key in keys.clone() {
+            match key {
+                Key::Char(char) if self.navigates(char) => {
+                    keys.extend(self.layout.moved(char).map(Key::Char))
+                }
+                _ => {}
+            }
+        }
+        keys
+    }
+
+    /// Keys bound to an action that navigate the lists instead, with the action and its keys
+    /// that still work there: empty if it is only left in the palette.
+    pub fn shadowed(&self) -> Vec<(char, Binding, Vec<Key>)> {
+        let mut shadowed = vec![];
+        for (binding, _) in BINDING_NAMES {
+            let keys = self.keys(binding);
+            let (taken, left): (Vec<Key>, Vec<Key>) = keys
+                .into_iter()
+                .partition(|key| matches!(key, Key::Char(char) if self.navigates(*char)));
+            for key in taken {
+                if let Key::Char(char) = key {
+                    shadowed.push((char, binding, left.clone()));
+                }
+            }
+        }
+        shadowed
+    }
+
+    fn navigates(&self, key: char) -> bool {
+        self.profile != KeyProfile::Arrows && self.layout.block().contains(&key)
+    }
+
+    /// The first of `candidates` bound to the key.
+    pub fn bound(&self, key: Key, candidates: &[Binding]) -> Option<Binding> {
+        candidates
+            .iter()
+            .copied()
+            .find(|binding| self.keys(*binding).contains(&key))
+    }
+
+    /// The move of a key by where it sits on the keyboard, `None` for keys that don't navigate.
+    /// Crossterm drops the base layout key terminals with the kitty protocol report and only
+    /// hands over the character typed, so the place comes from the configured layout.
+    pub fn movement(&self, key: char) -> Option<Move> {
+        if self.profile == KeyProfile::Arrows {
+            return None;
+        }
+        let [up, left, down, right] = self.layout.block();
+        match key {
+            _ if key == up => Some(Move::Up),
+            _ if key == left => Some(Move::Left),
+            _ if key == down => Some(Move::Down),
+            _ if key == right => Some(Move::Right),
+            _ => None,
+        }
+    }
+}
+
+/// A key as written in `[keys.bindings]`: a character, `Ctrl+` and a character, or one of
+/// `Enter`, `Esc`, `Space`, `Tab`, `Backspace`, `Up`, `Down`, `Left`, `Right` and `F1` to `F12`
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Enter,
+    Esc,
+    Tab,
+    Backspace,
+    Up,
+    Down,
+    Left,
+    Right,
+    F(u8),
+}
+
+const KEY_NAMES: [(Key, &str); 9] = [
+    (Key::Char(' '), "Space"),
+    (Key::Enter, "Enter"),
+    (Key::Esc, "Esc"),
+    (Key::Tab, "Tab"),
+    (Key::Backspace, "Backspace"),
+    (Key::Up, "Up"),
+    (Key::Down, "Down"),
+    (Key::Left, "Left"),
+    (Key::Right, "Right"),
+];
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let single = |text: &str| {
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(char), None) => Some(char),
+                _ => None,
+            }
+        };
+        if let Some(char) = single(text) {
+            return Ok(Key::Char(char));
+        }
+        if let Some(char) = text.strip_prefix("Ctrl+").and_then(single) {
+            return Ok(Key::Ctrl(char.to_ascii_lowercase()));
+        }
+        if let Some(number) = text.strip_prefix('F').and_then(|n| n.parse().ok()) {
+            if (1..=12).contains(&number) {
+                return Ok(Key::F(number));
+            }
+        }
+        KEY_NAMES
+            .iter()
+            .find(|(_, name)| *name == text)
+            .map(|(key, _)| *key)
+            .ok_or_else(|| {
+                format!("unknown key {text:?}, e.g. \"x\", \"Ctrl+x\", \"Enter\" or \"F5\"")
+            })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::F(number) => write!(f, "F{number}"),
+            Key::Ctrl(char) => write!(f, "Ctrl+{}", char.to_ascii_uppercase()),
+            Key::Char(char) if *char != ' ' => write!(f, "{char}"),
+            key => {
+                let (_, name) = KEY_NAMES.iter().find(|(known, _)| known == key).unwrap();
+                write!(f, "{name}")
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> Self {
+        key.to_string()
+    }
+}
+
+/// What a key can be bound to in `[keys.bindings]`, by the names in `BINDING_NAMES` or
+/// `display_<n>`
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub enum Binding {
+    Quit,
+    Refresh,
+    /// Selects the display with this number, from 1
+    Display(u8),
+    Identify,
+    Presets,
+    History,
+    Changelog,
+    Preview,
+    Colour,
+    Copy,
+    StepUp,
+    StepDown,
+    Edit,
+    Link,
+    Reset,
+    Power,
+    /// Reads or writes any VCP code of the selected display
+    Vcp,
+    /// Shows or hides what the selected display advertises
+    Capabilities,
+    Palette,
+    Help,
+    /// The previous display in the list or control of the selected display
+    Previous,
+    Next,
+    Decrease,
+    Increase,
+    /// Selects the highlighted display, or applies the highlighted preset
+    Select,
+    Back,
+    SavePreset,
+    /// Gives the highlighted display an alias
+    Rename,
+    /// Writes a note on the selected display
+    Note,
+    /// Narrows the display list, or the controls of the selected display, to those matching
+    Filter,
+    /// Lists the changes not written yet
+    Queue,
+    /// Shows or hides the latest log entries below the lists
+    Log,
+    /// Marks the highlighted display for a new group, or unmarks it
+    Mark,
+    /// Saves the marked displays as a synced group
+    Group,
+    /// Matches the brightness of the two marked displays by eye
+    Equalize,
+    /// Reads the diagnostic codes of the selected display
+    Health,
+    /// Shows the displays where they are on the desktop instead of as a list
+    Layout,
+}
+
+const BINDING_NAMES: [(Binding, &str); 36] = [
+    (Binding::Quit, "quit"),
+    (Binding::Refresh, "refresh"),
+    (Binding::Identify, "identify"),
+    (Binding::Presets, "presets"),
+    (Binding::History, "history"),
+    (Binding::Changelog, "changelog"),
+    (Binding::Preview, "preview"),
+    (Binding::Colour, "colour"),
+    (Binding::Copy, "copy"),
+    (Binding::StepUp, "step_up"),
+    (Binding::StepDown, "step_down"),
+    (Binding::Edit, "edit"),
+    (Binding::Link, "link"),
+    (Binding::Reset, "reset"),
+    (Binding::Power, "power"),
+    (Binding::Vcp, "vcp"),
+    (Binding::Capabilities, "capabilities"),
+    (Binding::Palette, "palette"),
+    (Binding::Help, "help"),
+    (Binding::Previous, "previous"),
+    (Binding::Next, "next"),
+    (Binding::Decrease, "decrease"),
+    (Binding::Increase, "increase"),
+    (Binding::Select, "select"),
+    (Binding::Back, "back"),
+    (Binding::SavePreset, "save_preset"),
+    (Binding::Rename, "rename"),
+    (Binding::Note, "note"),
+    (Binding::Filter, "filter"),
+    (Binding::Queue, "queue"),
+    (Binding::Log, "log"),
+    (Binding::Mark, "mark"),
+    (Binding::Group, "group"),
+    (Binding::Equalize, "equalize"),
+    (Binding::Health, "health"),
+    (Binding::Layout, "layout"),
+];
+
+/// Selecting displays by number, bound to the digits by default
+pub const DISPLAYS: [Binding; 9] = [
+    Binding::Display(1),
+    Binding::Display(2),
+    Binding::Display(3),
+    Binding::Display(4),
+    Binding::Display(5),
+    Binding::Display(6),
+    Binding::Display(7),
+    Binding::Display(8),
+    Binding::Display(9),
+];
+
+impl Binding {
+    fn default_keys(&self) -> Vec<Key> {
+        let char = |char| vec![Key::Char(char)];
+        match self {
+            Binding::Quit => char('q'),
+            Binding::Refresh => char('r'),
+            Binding::Display(number) => char(char::from(b'0' + number % 10)),
+            Binding::Identify => char('i'),
+            Binding::Presets => char('p'),
+            Binding::History => char('h'),
+            Binding::Changelog => char('u'),
+            Binding::Preview => char('P'),
+            Binding::Colour => char('c'),
+            Binding::Copy => char('y'),
+            Binding::StepUp => char('+'),
+            Binding::StepDown => char('-'),
+            Binding::Edit => vec![Key::Char('e'), Key::Char('=')],
+            Binding::Link => char('L'),
+            Binding::Reset => char('R'),
+            Binding::Power => char('O'),
+            Binding::Vcp => char('x'),
+            Binding::Capabilities => char('C'),
+            Binding::Palette => vec![Key::Ctrl('p')],
+            Binding::Help => char('?'),
+            Binding::Previous => vec![Key::Up],
+            Binding::Next => vec![Key::Down],
+            Binding::Decrease => vec![Key::Left],
+            Binding::Increase => vec![Key::Right],
+            Binding::Select => vec![Key::Enter, Key::Char(' ')],
+            Binding::Back => vec![Key::Esc, Key::Char(' '), Key::Char('q')],
+            Binding::SavePreset => char('S'),
+            Binding::Rename => char('n'),
+            Binding::Note => char('N'),
+            Binding::Filter => char('/'),
+            Binding::Queue => char('W'),
+            Binding::Log => char('l'),
+            Binding::Mark => char('m'),
+            Binding::Group => char('g'),
+            Binding::Equalize => char('E'),
+            Binding::Health => char('H'),
+            Binding::Layout => char('v'),
+        }
+    }
+
+    /// What the action does, for the help.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Binding::Quit => "exit",
+            Binding::Refresh => "reload",
+            Binding::Display(_) => "select monitor",
+            Binding::Identify => "flash each monitor as often as its number, over DDC/CI",
+            Binding::Presets => "presets",
+            Binding::History => "history of changes",
+            Binding::Changelog => "what's new in a newer release",
+            Binding::Preview => "preview changes without writing them, again to commit or discard",
+            Binding::Colour => "6-axis colour of the selected monitor",
+            Binding::Copy => "then a number, copy the highlighted control to that monitor",
+            Binding::StepUp => "larger step size",
+            Binding::StepDown => "smaller step size",
+            Binding::Edit => "type the exact value of the highlighted control",
+            Binding::Link => "link controls, adjusting every monitor at once",
+            Binding::Reset => "restore factory defaults of the highlighted control or the monitor",
+            Binding::Power => "turn the selected monitor off, again to turn it on",
+            Binding::Vcp => "read or write any VCP code of the selected monitor",
+            Binding::Capabilities => {
+                "show what the selected monitor advertises, PgUp and PgDn scroll"
+            }
+            Binding::Palette => "command palette",
+            Binding::Help => "this help",
+            Binding::Previous => "previous monitor or control",
+            Binding::Next => "next monitor or control",
+            Binding::Decrease => "decrease the highlighted control",
+            Binding::Increase => "increase the highlighted control",
+            Binding::Select => "select the highlighted monitor or preset",
+            Binding::Back => "back to the monitor list",
+            Binding::SavePreset => "save the highlighted preset in the presets popup",
+            Binding::Rename => "name the highlighted monitor, empty for its model name",
+            Binding::Note => "write a note on the selected monitor, shown in the details",
+            Binding::Filter => "type to narrow the monitors or controls listed, Esc to clear",
+            Binding::Queue => "changes not written yet, to write now or discard",
+            Binding::Log => "show or hide the log of reads, writes, retries and errors",
+            Binding::Mark => "mark the highlighted monitor for a group, again to unmark it",
+            Binding::Group => "name the marked monitors as a group adjusted together",
+            Binding::Equalize => {
+                "match the brightness of two marked monitors by eye, for their group"
+            }
+            Binding::Health => "usage time, firmware and other diagnostics of the selected monitor",
+            Binding::Layout => "switch between the list and the desktop layout of the monitors",
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if let Some(number) = text.strip_prefix("display_").and_then(|n| n.parse().ok()) {
+            if (1..=9).contains(&number) {
+                return Ok(Binding::Display(number));
+            }
+        }
+        BINDING_NAMES
+            .iter()
+            .find(|(_, name)| *name == text)
+            .map(|(binding, _)| *binding)
+            .ok_or_else(|| format!("unknown action {text:?}"))
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Display(number) => write!(f, "display_{number}"),
+            binding => {
+                let (_, name) = BINDING_NAMES
+                    .iter()
+                    .find(|(known, _)| known == binding)
+                    .unwrap();
+                write!(f, "{name}")
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> Self {
+        binding.to_string()
+    }
+}
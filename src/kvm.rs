@@ -0,0 +1,378 @@
+//! Experimental software KVM: two daemons sharing monitors tell each other over UDP how long
+//! their user has been idle, and the input of the shared displays follows the machine used last
+
+use serde::{Deserialize, Serialize};
+
+// The config is understood without the daemon feature, so files stay valid across builds
+#[cfg(feature = "daemon")]
+use {
+    crate::audio::{AudioConfig, Trigger},
+    crate::automation::{Automation, AutomationConfig},
+    crate::cli,
+    crate::digest::{equal, hex, hmac},
+    crate::events::record,
+    crate::history::{Log, Origin},
+    crate::logging,
+    crate::watchdog::Jobs,
+    ddc_bright::{
+        display::{Control, DisplayManager, WrappedDisplay, INPUT_SOURCE},
+        supervisor::Supervisor,
+    },
+    std::{
+        error::Error,
+        io,
+        net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket},
+        sync::Arc,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Longest a read of the socket blocks, so the thread notices being cancelled
+#[cfg(feature = "daemon")]
+const TICK: Duration = Duration::from_millis(100);
+
+/// Heartbeats missed before the other machine counts as gone and nothing is switched
+#[cfg(feature = "daemon")]
+const MISSED: u32 = 3;
+
+/// How far the clock a heartbeat was sent by may be off, older ones are taken as replayed
+#[cfg(feature = "daemon")]
+const SKEW: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct KvmConfig {
+    pub enabled: bool,
+    /// Address heartbeats of the other machine are received on, by default the address facing
+    /// it with the port of `peer`
+    pub listen: Option<String>,
+    /// Address of the other machine's daemon, like `192.168.1.20:7788`
+    pub peer: Option<String>,
+    /// Key every heartbeat is signed with, the same on both machines. Required
+    pub secret: Option<String>,
+    /// Model names or ids of the shared displays, every display if empty
+    pub displays: Vec<String>,
+    /// Input this machine is connected to, like `dp1` or a raw value
+    pub input: Option<String>,
+    /// Input the other machine is connected to
+    pub peer_input: Option<String>,
+    /// Milliseconds between heartbeats
+    pub interval_ms: u64,
+    /// A machine whose user did something within this many milliseconds is the active one
+    pub active_ms: u64,
+}
+
+impl Default for KvmConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: None,
+            peer: None,
+            secret: None,
+            displays: vec![],
+            input: None,
+            peer_input: None,
+            interval_ms: 500,
+            active_ms: 1000,
+        }
+    }
+}
+
+/// Which machine the shared displays should show
+#[cfg(feature = "daemon")]
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Active {
+    Here,
+    Peer,
+}
+
+/// Sends heartbeats and switches the shared displays while the returned supervisor lives.
+#[cfg(feature = "daemon")]
+pub fn start(
+    jobs: Jobs,
+    config: &KvmConfig,
+    audio: &AudioConfig,
+    automation: &AutomationConfig,
+) -> Result<Option<Supervisor>, Box<dyn Error>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let peer = config.peer.clone().ok_or("kvm.peer is not set")?;
+    let secret = (config.secret.clone())
+        .filter(|secret| !secret.is_empty())
+        .ok_or("kvm.secret is not set, anyone on the network could switch the displays")?;
+    let (Some(input), Some(peer_input)) = (config.input.clone(), config.peer_input.clone()) else {
+        return Err("kvm needs both input and peer_input".into());
+    };
+    let peers: Vec<SocketAddr> = peer
+        .to_socket_addrs()
+        .map_err(|err| format!("kvm: {peer}: {err}"))?
+        .collect();
+    let first = *peers
+        .first()
+        .ok_or_else(|| format!("kvm: {peer}: no address"))?;
+    let listen = match &config.listen {
+        Some(listen) => listen.clone(),
+        None => SocketAddr::new(facing(first)?, first.port()).to_string(),
+    };
+    let socket =
+        UdpSocket::bind(&listen).map_err(|err| format!("kvm: listening on {listen}: {err}"))?;
+    socket.set_read_timeout(Some(TICK))?;
+
+    let audio = audio.clone();
+    let automation = automation.clone();
+    let queries = config.displays.clone();
+    let interval = Duration::from_millis(config.interval_ms.max(50));
+    let active_for = Duration::from_millis(config.active_ms);
+
+    let mut supervisor = Supervisor::default();
+    supervisor.spawn("kvm", move |token| {
+        let mut last_sent: Option<Instant> = None;
+        // Idle time of the other machine and when it was heard of
+        let mut peer_idle: Option<(Duration, Instant)> = None;
+        let mut active: Option<Active> = None;
+        // When the last heartbeat taken was sent, anything not newer is a replay
+        let mut last_heard = 0;
+        let mut idle = None;
+        let mut buffer = [0; 512];
+        while !token.is_cancelled() {
+            if last_sent.is_none_or(|sent| sent.elapsed() >= interval) {
+                last_sent = Some(Instant::now());
+                idle = idle_time();
+                // Unknown idle time is sent as never used, so this machine is never followed
+                let millis = idle.map_or(u64::MAX, |idle| idle.as_millis() as u64);
+                let message = sign(&secret, now_micros(), millis);
+                let _ = socket.send_to(message.as_bytes(), first);
+            }
+            match socket.recv_from(&mut buffer) {
+                // Only the peer's heartbeats count, whatever they carry
+                Ok((len, from)) if peers.contains(&from) => {
+                    let message = String::from_utf8_lossy(&buffer[..len]);
+                    if let Some((sent, millis)) = parse(&message, &secret, last_heard) {
+                        last_heard = sent;
+                        peer_idle = Some((Duration::from_millis(millis), Instant::now()));
+                    }
+                }
+                Ok(_) => {}
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(err) => logging::report(format!("kvm: {err}")),
+            }
+
+            let Some((peer, heard)) =
+                peer_idle.filter(|(_, heard)| heard.elapsed() < interval * MISSED)
+            else {
+                // Gone, switching back is up to it once it returns
+                active = None;
+                continue;
+            };
+            let peer = peer.saturating_add(heard.elapsed());
+            let now = match idle {
+                Some(idle) if idle < active_for && idle <= peer => Active::Here,
+                _ if peer < active_for => Active::Peer,
+                // Nobody is using either machine, the displays stay where they are
+                _ => continue,
+            };
+            // Only a change of machine switches, so choosing an input by hand sticks
+            if active.replace(now) == Some(now) || !automation.is_active(Automation::Kvm) {
+                continue;
+            }
+            let target = match now {
+                Active::Here => input.clone(),
+                Active::Peer => peer_input.clone(),
+            };
+            // Written between the daemon's checks like every other change, not past its queue
+            let (queries, audio) = (queries.clone(), audio.clone());
+            let switched = jobs.run(move |manager| {
+                for display in &manager.displays {
+                    if !queries.is_empty() && !queries.iter().any(|query| display.matches(query)) {
+                        continue;
+                    }
+                    switch(manager, display, &target, &audio);
+                }
+            });
+            if switched.is_none() {
+                break;
+            }
+        }
+    });
+    Ok(Some(supervisor))
+}
+
+/// The local address the system sends to `peer` from.
+#[cfg(feature = "daemon")]
+fn facing(peer: SocketAddr) -> io::Result<IpAddr> {
+    let unspecified: IpAddr = match peer {
+        SocketAddr::V4(_) => [0, 0, 0, 0].into(),
+        SocketAddr::V6(_) => [0u16; 8].into(),
+    };
+    let probe = UdpSocket::bind((unspecified, 0))?;
+    // Connecting a UDP socket only picks the route, nothing is sent
+    probe.connect(peer)?;
+    Ok(probe.local_addr()?.ip())
+}
+
+/// Microseconds since the epoch, telling heartbeats apart and in order.
+#[cfg(feature = "daemon")]
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_micros() as u64)
+}
+
+/// A heartbeat sent at `sent` carrying the idle time, signed with the secret.
+#[cfg(feature = "daemon")]
+fn sign(secret: &str, sent: u64, millis: u64) -> String {
+    let message = format!("ddc_bright kvm {sent} {millis}");
+    let mac = hex(&hmac(secret.as_bytes(), message.as_bytes()));
+    format!("{message} {mac}")
+}
+
+/// When a heartbeat was sent and the idle time in it, if it is signed with the secret, was
+/// sent after `last` and isn't older than the clocks can be apart.
+#[cfg(feature = "daemon")]
+fn parse(message: &str, secret: &str, last: u64) -> Option<(u64, u64)> {
+    let (signed, mac) = message.trim().rsplit_once(' ')?;
+    equal(&hex(&hmac(secret.as_bytes(), signed.as_bytes())), mac).then_some(())?;
+    let (sent, millis) = signed.strip_prefix("ddc_bright kvm ")?.split_once(' ')?;
+    let sent: u64 = sent.parse().ok()?;
+    let age = Duration::from_micros(now_micros().abs_diff(sent));
+    (sent > last && age < SKEW).then_some(())?;
+    Some((sent, millis.parse().ok()?))
+}
+
+/// Switches the display to the input unless it already shows it. The inactive machine often
+/// can't reach a display over DDC/CI, so failing is normal there.
+#[cfg(feature = "daemon")]
+fn switch(manager: &DisplayManager, display: &WrappedDisplay, input: &str, audio: &AudioConfig) {
+    let Some(value) = display.value_named(Control::InputSource, input) else {
+        logging::report(format!("{}: kvm: no input named {input}", display.label()));
+        return;
+    };
+    if display
+        .get_vcp(INPUT_SOURCE)
+        .is_ok_and(|current| current & 0xFF == value & 0xFF)
+    {
+        return;
+    }
+    let result = match display.controls.get(&Control::InputSource) {
+        Some(controller) => {
+            manager.queue_set(display.clone(), controller.clone(), value);
+            manager.flush();
+            let parked = (manager.queued().iter()).any(|change| {
+                Arc::ptr_eq(&change.display, display) && change.control == Control::InputSource
+            });
+            match (parked, display.write_error()) {
+                (false, None) => Ok(()),
+                (_, error) => Err(error.unwrap_or_else(|| "waiting for the display".to_string())),
+            }
+        }
+        // Displays leaving the input out of their capabilities still take it
+        None => (display.set_vcp(INPUT_SOURCE, value)).map_err(|err| err.to_string()),
+    };
+    match result {
+        Ok(()) => {
+            println!("{}: kvm switched to {input}", display.label());
+            record(
+                "kvm",
+                Some(&display.label()),
+                format!("switched to {input}"),
+            );
+            Log::default().record(Origin::Kvm, display, INPUT_SOURCE, value);
+            cli::switch_audio(audio, display, Trigger::Input(value));
+        }
+        Err(err) => record(
+            "kvm",
+            Some(&display.label()),
+            format!("switching failed: {err}"),
+        ),
+    }
+}
+
+/// How long ago the user of this machine last did something, `None` where that can't be told.
+#[cfg(all(windows, feature = "daemon"))]
+fn idle_time() -> Option<Duration> {
+    use windows_sys::Win32::{
+        System::SystemInformation::GetTickCount,
+        UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO},
+    };
+
+    let mut info = LASTINPUTINFO {
+        cbSize: size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    // SAFETY: info is a LASTINPUTINFO with its size set
+    if unsafe { GetLastInputInfo(&mut info) } == 0 {
+        return None;
+    }
+    let now = unsafe { GetTickCount() };
+    Some(Duration::from_millis(now.wrapping_sub(info.dwTime) as u64))
+}
+
+/// How long ago the user of this machine last did something, asked from the X server through
+/// `xprintidle`. Spawns it once per heartbeat, every 500ms by default, and only answers in an X11
+/// session, `None` on Wayland, without `DISPLAY` or where it isn't installed.
+#[cfg(all(not(windows), feature = "daemon"))]
+fn idle_time() -> Option<Duration> {
+    std::env::var_os("DISPLAY")?;
+    let output = std::process::Command::new("xprintidle").output().ok()?;
+    let millis = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| Duration::from_millis(millis))
+}
+
+#[cfg(all(test, feature = "daemon"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeats_carry_the_idle_time() {
+        let sent = now_micros();
+        let message = sign("secret", sent, 1500);
+        assert_eq!(parse(&message, "secret", 0), Some((sent, 1500)));
+        assert_eq!(
+            parse(&format!("{message}\n"), "secret", 0),
+            Some((sent, 1500))
+        );
+    }
+
+    #[test]
+    fn heartbeats_need_the_secret() {
+        let message = sign("secret", now_micros(), 1500);
+        assert_eq!(parse(&message, "other", 0), None);
+        // A changed idle time doesn't match the signature any more
+        let forged = message.replacen(" 1500 ", " 0 ", 1);
+        assert_eq!(parse(&forged, "secret", 0), None);
+        assert_eq!(parse("ddc_bright kvm", "secret", 0), None);
+    }
+
+    #[test]
+    fn replayed_heartbeats_are_ignored() {
+        let sent = now_micros();
+        let message = sign("secret", sent, 1500);
+        assert_eq!(parse(&message, "secret", sent), None);
+        assert_eq!(parse(&message, "secret", sent + 1), None);
+    }
+
+    #[test]
+    fn heartbeats_off_by_more_than_the_skew_are_ignored() {
+        let skew = SKEW.as_micros() as u64;
+        let old = sign("secret", now_micros() - skew - 1_000_000, 1500);
+        assert_eq!(parse(&old, "secret", 0), None);
+        let ahead = sign("secret", now_micros() + skew + 1_000_000, 1500);
+        assert_eq!(parse(&ahead, "secret", 0), None);
+        let close = now_micros() - skew / 2;
+        assert_eq!(
+            parse(&sign("secret", close, 1500), "secret", 0),
+            Some((close, 1500))
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Control brightness and other DDC/CI features of displays
+//!
+//! # Stability
+//!
+//! The library follows semantic versioning for the items re-exported here and the modules they
+//! come from: [`DisplayManager`] loading and writing displays, [`MyDisplay`] and its
+//! [`Controller`]s, [`Control`], the scan progress in [`Status`] and [`Progress`], and
+//! [`Error`]. Enums and the structs only handed out by the library are `#[non_exhaustive]`,
+//! so new variants, e.g. another control, and new fields arrive in minor releases. Match them
+//! with a wildcard arm.
+//!
+//! Items are deprecated for at least one minor release before they are removed in the next
+//! major one, the deprecation note names the replacement. The `helper` module is shared with the
+//! binary and not covered, neither are fields and functions marked `#[doc(hidden)]`, like the
+//! settings of [`DisplayManager`] the binary fills in from its config.
+
+mod backend;
+pub mod bulk;
+pub mod capabilities;
+pub mod composite;
+pub mod coupling;
+pub mod display;
+pub mod doctor;
+pub mod edid;
+mod enumerate;
+pub mod error;
+pub mod fake;
+#[cfg(all(target_os = "linux", feature = "gamma"))]
+mod gamma;
+pub mod health;
+#[cfg(all(unix, feature = "helper"))]
+pub mod helper;
+#[cfg(all(any(target_os = "linux", windows), feature = "hotplug"))]
+mod hotplug;
+pub mod lock;
+pub mod mapping;
+pub mod metrics;
+pub mod orientation;
+pub mod output;
+pub mod policy;
+mod poll;
+pub mod power;
+pub mod priority;
+pub mod supervisor;
+#[cfg(all(target_os = "linux", feature = "sysfs"))]
+mod sysfs;
+pub mod temperature;
+#[cfg(feature = "trace")]
+pub mod trace;
+pub mod transaction;
+pub mod units;
+pub mod values;
+#[cfg(all(windows, feature = "wmi"))]
+mod wmi;
+mod worker;
+
+pub use display::{Control, Controller, DisplayManager, MyDisplay, Progress, Status};
+pub use error::Error;
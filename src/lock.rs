@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::display::Control;
+
+/// Where the lock is kept, out of reach of the users it applies to:
+/// `/etc/ddc_bright/lock.toml`, or `%ProgramData%\ddc_bright\lock.toml` on Windows.
+pub fn path() -> PathBuf {
+    match cfg!(windows) {
+        true => env::var_os("ProgramData")
+            .map_or_else(|| PathBuf::from(r"C:\ProgramData"), PathBuf::from)
+            .join(r"ddc_bright\lock.toml"),
+        false => PathBuf::from("/etc/ddc_bright/lock.toml"),
+    }
+}
+
+/// What may be written to the displays of a shared machine, like in a lab or classroom. Every
+/// other write is refused, whichever frontend makes it.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct Lock {
+    /// Controls that can be changed
+    pub allow: Vec<Control>,
+    /// `[lowest, highest]` percent of the maximum continuous controls can be set to
+    pub range: BTreeMap<Control, [u16; 2]>,
+}
+
+impl Lock {
+    /// The lock at [`path`], `None` if there is no file. A file that can't be read or parsed
+    /// is an error rather than no lock.
+    pub fn load() -> Result<Option<Self>, String> {
+        let path = path();
+        let error = |err: &dyn std::fmt::Display| format!("{}: {err}", path.display());
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map(Some).map_err(|err| error(&err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(error(&err)),
+        }
+    }
+
+    /// The lowest and highest value the VCP code can be written with out of `maximum`, `None`
+    /// if it can't be written at all.
+    pub fn bounds(&self, code: u8, maximum: u16) -> Option<(u16, u16)> {
+        let control = Control::from_code(code).filter(|control| self.allow.contains(control))?;
+        let share = |percent: u16| (maximum as u32 * percent.min(100) as u32 / 100) as u16;
+        match self.range.get(&control) {
+            Some([lowest, highest]) if control.is_continuous() => {
+                Some((share(*lowest), share(*highest).max(share(*lowest))))
+            }
+            _ => Some((0, u16::MAX)),
+        }
+    }
+
+    /// Why writing the code with the value is refused, `None` if it is allowed.
+    pub fn refusal(&self, code: u8, value: u16, maximum: u16) -> Option<String> {
+        let name = Control::from_code(code).map_or(format!("0x{code:02X}"), |c| c.key());
+        match self.bounds(code, maximum) {
+            None => Some(format!("{name} is locked")),
+            Some((lowest, highest)) if !(lowest..=highest).contains(&value) => {
+                let [low, high] = self.range[&Control::from_code(code)?];
+                Some(format!("{name} is locked to {low}% - {high}%"))
+            }
+            Some(_) => None,
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! The log of DDC reads and writes, retries and errors: `ddc_bright.log` next to the config, and
+//! the latest entries for the log pane of the TUI. Problems of the features running in the
+//! background are reported here too, so they don't garble the TUI by going to stderr.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span, Event, Level, Metadata, Subscriber,
+};
+
+use crate::config::Config;
+
+/// Entries kept for the log pane
+const RECENT: usize = 200;
+
+/// Size the log grows to before it is moved to `ddc_bright.log.1`, replacing the one before
+const MAX_BYTES: u64 = 1024 * 1024;
+
+static ENTRIES: Mutex<VecDeque<Entry>> = Mutex::new(VecDeque::new());
+
+/// Shows a problem of a background thread somewhere else than on stderr
+type Reporter = Box<dyn Fn(String) + Send>;
+
+/// Where problems of background threads go instead of stderr, see [`report_to`]
+static REPORTER: Mutex<Option<Reporter>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Least severe entries logged
+    pub level: LogLevel,
+    /// Writes the entries to `ddc_bright.log`, otherwise only the TUI shows them
+    pub file: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Debug,
+            file: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Displays that stopped answering
+    Error,
+    /// Reads and writes that failed
+    Warn,
+    /// Retries
+    Info,
+    /// Every read and write
+    Debug,
+    /// Every DDC/CI transaction with its bytes, what `--trace-ddc` logs
+    Trace,
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Level::ERROR,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Trace => Level::TRACE,
+        }
+    }
+}
+
+/// Something logged
+#[derive(Clone)]
+pub struct Entry {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    /// Id of the display it is about
+    pub display: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:<5} ", self.time.format("%H:%M:%S%.3f"), self.level)?;
+        if let Some(display) = &self.display {
+            write!(f, "{display}: ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The latest entries, oldest first.
+pub fn recent() -> Vec<Entry> {
+    ENTRIES.lock().unwrap().iter().cloned().collect()
+}
+
+/// Sends the problems [`report`]ed from now on to `reporter`, or back to stderr for `None`.
+#[cfg(feature = "tui")]
+pub fn report_to(reporter: Option<Reporter>) {
+    *REPORTER.lock().unwrap() = reporter;
+}
+
+/// Reports a problem of a thread working in the background: to the TUI while it runs, on
+/// stderr when headless.
+pub fn report(message: String) {
+    match &*REPORTER.lock().unwrap() {
+        Some(reporter) => reporter(message),
+        None => eprintln!("{message}"),
+    }
+}
+
+/// Collects what ddc_bright logs from now on. Failing to open the file only leaves it out, the
+/// log is an aid.
+pub fn init(config: &LogConfig) {
+    let path = Config::dir().map(|dir| dir.join("ddc_bright.log"));
+    let logger = Logger {
+        level: config.level.into(),
+        file: Mutex::new(None),
+        path: path.filter(|_| config.file),
+    };
+    logger.open();
+    let _ = tracing::subscriber::set_global_default(logger);
+}
+
+struct Logger {
+    level: Level,
+    /// The open log and its size
+    file: Mutex<Option<(File, u64)>>,
+    path: Option<PathBuf>,
+}
+
+impl Logger {
+    fn open(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let _ = path.parent().map(fs::create_dir_all);
+        let file = OpenOptions::new().create(true).append(true).open(path);
+        *self.file.lock().unwrap() = file.ok().map(|file| {
+            let size = file.metadata().map_or(0, |metadata| metadata.len());
+            (file, size)
+        });
+    }
+
+    fn write(&self, line: &str) {
+        let mut file = self.file.lock().unwrap();
+        let Some((open, size)) = file.as_mut() else {
+            return;
+        };
+        if open.write_all(line.as_bytes()).is_err() {
+            return;
+        }
+        *size += line.len() as u64;
+        if *size < MAX_BYTES {
+            return;
+        }
+        *file = None;
+        drop(file);
+        if let Some(path) = &self.path {
+            let _ = fs::rename(path, path.with_extension("log.1"));
+        }
+        self.open();
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.level && metadata.target().starts_with("ddc_bright")
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(LevelFilter::from_level(self.level))
+    }
+
+    fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+        // Nothing uses spans, entries stand on their own
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let entry = Entry {
+            time: Local::now(),
+            level: *event.metadata().level(),
+            display: fields.display,
+            message: fields.message,
+        };
+        self.write(&format!("{} {entry}\n", entry.time.format("%Y-%m-%d")));
+        let mut entries = ENTRIES.lock().unwrap();
+        if entries.len() == RECENT {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+/// The message and display of an event, other fields appended to the message
+#[derive(Default)]
+struct Fields {
+    message: String,
+    display: Option<String>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "display" => self.display = Some(value.to_string()),
+            _ => self.record_debug(field, &value),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => {
+                let _ = write!(self.message, "{value:?}");
+            }
+            name => {
+                let _ = write!(self.message, " {name}={value:?}");
+            }
+        }
+    }
+}
@@ -1,307 +1,236 @@
 use std::{
     error::Error,
-    io,
+    io::{self, IsTerminal},
     process::exit,
-    sync::Arc,
+    time::Duration,
 };
 
-use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+use clap::Parser;
+use cli::{Args, Command};
+use config::Config;
+use ddc_bright::{
+    display::{Control, DisplayManager, Fade, Retry},
+    doctor,
+    priority::Priority,
 };
-use display::{DisplayManager, WrappedController, WrappedDisplay};
-use ratatui::{prelude::*, widgets::*};
+use frontend::Frontend;
+
+mod ambient;
+mod audio;
+mod audit;
+mod automation;
+mod backlight;
+mod bench;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod buttons;
+mod cli;
+mod config;
+#[cfg(feature = "daemon")]
+mod control;
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+mod dbus;
+#[cfg_attr(not(feature = "daemon"), allow(dead_code))]
+mod digest;
+mod events;
+// Empty without the tui, daemon and gui features
+#[cfg_attr(
+    not(any(feature = "tui", feature = "daemon", feature = "gui")),
+    allow(unused)
+)]
+mod frontend;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod group;
+#[cfg(feature = "gui")]
+mod gui;
+mod history;
+#[cfg(feature = "daemon")]
+mod hotkeys;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod keys;
+mod kvm;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod logging;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod notify;
+#[cfg(feature = "overlay")]
+mod overlay;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod presets;
+mod probe;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod quirks;
+mod rotate;
+mod scene;
+#[cfg_attr(not(any(feature = "tui", feature = "daemon")), allow(dead_code))]
+mod schedule;
+#[cfg(all(windows, feature = "service"))]
+mod service;
+#[cfg_attr(not(any(feature = "tui", feature = "daemon")), allow(dead_code))]
+mod setup;
+mod state;
+mod statusline;
+mod sun;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod theme;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "tui")]
+mod update;
+mod wake;
+mod watchdog;
+#[cfg(feature = "daemon")]
+mod webhook;
 
-mod display;
-
-enum InputMode {
-    Select,
-    Help,
-    Selected(WrappedDisplay),
-}
-
-/// App holds the state of the application
-struct App {
-    input_mode: InputMode,
-    manager: DisplayManager,
-    step_size: i16,
-    loading: bool,
-    show_help: bool,
-
-    control_index: usize,
-    control_selected: Option<WrappedController>,
-    control_widget_state: ListState,
-
-    display_index: usize,
-    display_selected: Option<WrappedDisplay>,
-    display_widget_state: ListState,
-}
-
-impl Default for App {
-    fn default() -> App {
-        let manager = DisplayManager::new();
-        App {
-            input_mode: InputMode::Select,
-            step_size: 1,
-            manager,
-            control_index: 0,
-            display_index: 0,
-            control_selected: None,
-            display_selected: None,
-            loading: false,
-            show_help: false,
-            display_widget_state: ListState::default().with_selected(None).with_offset(0),
-            control_widget_state: ListState::default().with_selected(None).with_offset(0),
-        }
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = Args::parse();
+    #[cfg(all(windows, feature = "service"))]
+    if args.service {
+        return service::run();
     }
-}
-
-impl App {
-    fn select_display(&mut self) {
-        if let Some(display) = self.manager.displays.get(self.display_index) {
-            self.display_selected = Some(display.clone());
-            self.select_control(0);
-            self.input_mode =
-                InputMode::Selected(Arc::clone(self.display_selected.as_ref().unwrap()));
-        }
+    #[cfg(feature = "daemon")]
+    if let Some(host) = &args.remote {
+        let command = args.command.ok_or("--remote needs a command, e.g. list")?;
+        return cli::remote(host, command);
     }
-    fn set_display(&mut self, desired: usize) {
-        let desired = if desired >= self.manager.displays.len() {
-            0
-        } else {
-            desired
-        };
-        self.display_index = desired;
-        self.display_widget_state.select(Some(desired));
+    // Running as root, so the helper leaves the config, logs and audit log of root alone
+    #[cfg(all(unix, feature = "helper"))]
+    if let Some(socket) = &args.helper {
+        ddc_bright::helper::serve(socket)?;
+        return Ok(());
     }
-
-    fn select_control(&mut self, mut desired: usize) {
-        let display = self.display_selected.as_mut().unwrap();
-        let length = display.controls.len();
-        if desired >= length {
-            desired = 0;
-        }
-        let control = display.controls.values().nth(desired).unwrap().clone();
-        self.control_index = desired;
-        self.control_selected = Some(control);
-        self.control_widget_state.select(Some(desired));
-    }
-
-    fn next_control(&mut self) {
-        self.select_control(self.control_index.saturating_add(1));
+    let mut config = Config::load()?;
+    #[cfg(feature = "daemon")]
+    if args.relay {
+        return control::relay(&control::endpoint(config.control_socket.as_ref()));
     }
-
-    fn prev_control(&mut self) {
-        self.select_control(self.control_index.saturating_sub(1));
+    if args.no_update_check {
+        config.update_check = false;
     }
-
-    fn add_to_control(&mut self, value: i16) {
-        if let Some(control_mutex) = &self.control_selected {
-            // let mut control = control_mutex.lock().unwrap();
-            // let value = std::cmp::max(std::cmp::min(control.value as i16 + value, 100), 0) as u16;
-            // control.set(value).unwrap()
-            self.manager.queue_change(
-                self.display_selected.as_ref().unwrap().clone(),
-                control_mutex.clone(),
-                value,
-            );
+    if let Some(secs) = args.enumeration_timeout {
+        for backend in ddc_hi::Backend::values() {
+            config.enumeration_timeout.insert(backend.to_string(), secs);
         }
     }
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut app = App::default();
-    println!("Loading monitors..");
-    app.manager.refresh()?;
-
-    if app.manager.displays.len() == 0 {
-        println!("No displays!");
-        exit(1);
+    #[cfg(feature = "trace")]
+    if args.trace_ddc {
+        config.log.level = logging::LogLevel::Trace;
+        ddc_bright::trace::enable();
     }
+    logging::init(&config.log);
+    audit::init(&config.audit);
 
-    // setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    terminal.clear()?;
-
-    // create app and run it
-    let res = run_app(&mut terminal, app);
+    let mut manager = connect(&config)?;
 
-    // restore terminal
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
-
-    if let Err(err) = res {
-        println!("{err:?}");
+    // Started from a launcher or with the output piped, there is no terminal to draw the TUI on
+    if args.command.is_none() && interactive(&args) && !has_terminal() {
+        args.command = Some(Command::List {
+            #[cfg(feature = "json")]
+            json: false,
+        });
+    }
+    let frontend = Frontend::pick(&mut args);
+    if frontend.as_ref().is_some_and(Frontend::loads_displays) && !has_terminal() {
+        return Err("the TUI needs a terminal, run a subcommand like list instead".into());
+    }
+    if let Some(command) = args.command {
+        return cli::run(command, &config, || {
+            refresh(&mut manager)?;
+            Ok(&mut manager)
+        });
     }
 
-    Ok(())
-}
+    if !frontend.as_ref().is_some_and(Frontend::loads_displays) {
+        println!("Loading monitors..");
+        refresh(&mut manager)?;
+    }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
-    loop {
-        terminal.draw(|f| ui(f, &mut app))?;
+    if let Some(level) = args.volume {
+        let levels = [(Control::Volume, level)];
+        return cli::set(&manager.displays, &levels, &config.audio, &mut io::stderr());
+    }
+    if let Some(name) = &args.preset {
+        return presets::apply_named(&config, &manager, name, history::Origin::Cli);
+    }
 
-        if let Event::Key(key) = event::read()? {
-            if let KeyCode::Char(char) = key.code {
-                if let Some(char) = char.to_digit(10) {
-                    app.set_display((char as usize).saturating_sub(1));
-                    app.select_display();
-                }
-            };
-            match app.input_mode {
-                InputMode::Help => match key.code {
-                    KeyCode::Char(_) | KeyCode::Esc => {
-                        app.show_help = false;
-                        app.input_mode = InputMode::Select;
-                    }
-                    _ => (),
-                },
-                InputMode::Select if key.kind == KeyEventKind::Press => match key.code {
-                    KeyCode::Char('q') => {
-                        return Ok(());
-                    }
-                    KeyCode::Char('r') => {
-                        app.manager.refresh().unwrap();
-                    }
-                    KeyCode::Char('?') => {
-                        app.input_mode = InputMode::Help;
-                        app.show_help = true;
-                    }
-                    KeyCode::Char('w') => app.set_display(app.display_index.saturating_sub(1)),
-                    KeyCode::Char('s') => app.set_display(app.display_index.saturating_add(1)),
-                    KeyCode::Enter | KeyCode::Char(' ') => app.select_display(),
-                    _ => {}
-                },
-                InputMode::Selected(_) if key.kind == KeyEventKind::Press => match key.code {
-                    KeyCode::Up | KeyCode::Char('w') => app.prev_control(),
-                    KeyCode::Down | KeyCode::Char('s') => app.next_control(),
-                    KeyCode::Left | KeyCode::Char('a') => app.add_to_control(-app.step_size),
-                    KeyCode::Right | KeyCode::Char('d') => app.add_to_control(app.step_size),
-                    KeyCode::Esc | KeyCode::Char(' ') | KeyCode::Char('q') => {
-                        app.input_mode = InputMode::Select;
-                        app.display_selected = None;
-                    }
-                    _ => {}
-                },
-                _ => {}
+    let Some(frontend) = frontend else {
+        if manager.displays.is_empty() {
+            println!("No displays!");
+            for problem in doctor::problems(&manager.displays) {
+                println!("{}\n  {}", problem.cause, problem.fix);
             }
+            exit(1);
         }
-    }
+        return Err("built without the tui feature, pass --volume or a subcommand".into());
+    };
+    frontend.run(config, manager)
 }
 
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
-    let popup_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints(
-            [
-                Constraint::Percentage((100 - percent_y) / 2),
-                Constraint::Percentage(percent_y),
-                Constraint::Percentage((100 - percent_y) / 2),
-            ]
-            .as_ref(),
-        )
-        .split(r);
-
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints(
-            [
-                Constraint::Percentage((100 - percent_x) / 2),
-                Constraint::Percentage(percent_x),
-                Constraint::Percentage((100 - percent_x) / 2),
-            ]
-            .as_ref(),
-        )
-        .split(popup_layout[1])[1]
+/// Whether the arguments start the TUI.
+fn interactive(args: &Args) -> bool {
+    #[cfg(feature = "daemon")]
+    if args.watchdog {
+        return false;
+    }
+    cfg!(feature = "tui") && args.volume.is_none() && args.preset.is_none()
 }
 
-fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-        .split(f.size());
-
-    // let offset = app.vertical_display_state.offset_mut();
-    // *offset = app.scroll_display;
-
-    let display_widget: Vec<ListItem> = app
-        .manager
-        .displays
-        .iter()
-        .enumerate()
-        .map(|(i, display)| {
-            let content = Line::from(Span::raw(format!("{0}: {1}", i + 1, display.name)));
-            ListItem::new(content)
-        })
-        .collect();
+/// Whether both input and output are a terminal, which the TUI needs.
+fn has_terminal() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
 
-    let mut display_block = Block::default().borders(Borders::ALL).title("Displays");
-    if app.display_selected.is_none() {
-        display_block = display_block.border_style(Style::default().fg(Color::Blue))
+/// A display manager set up from the config, without displays until refreshed.
+fn connect(config: &Config) -> Result<DisplayManager, Box<dyn Error>> {
+    let mut manager = DisplayManager::new();
+    let quirks = quirks::Cache::load()?;
+    manager.tuning = quirks.displays.into_iter().collect();
+    manager.unadvertised = quirks.unadvertised.into_iter().collect();
+    manager.aliases = config.aliases.clone().into_iter().collect();
+    manager.fade = Fade {
+        duration: match config.reduced_motion() {
+            true => Duration::ZERO,
+            false => Duration::from_millis(config.fade.duration_ms),
+        },
+        threshold: config.fade.threshold,
+    };
+    manager.retry = Retry {
+        attempts: config.retry.attempts,
+        backoff: Duration::from_millis(config.retry.backoff_ms),
+    };
+    if config.worker.nice.is_some() || !config.worker.cpus.is_empty() {
+        let priority = Priority {
+            nice: config.worker.nice,
+            cpus: config.worker.cpus.clone(),
+        };
+        manager
+            .set_worker_priority(&priority)
+            .map_err(|err| format!("can't schedule the change queue as the config says: {err}"))?;
     }
-    let display_widget = List::new(display_widget)
-        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-        .highlight_symbol("> ")
-        .block(display_block);
-
-    f.render_stateful_widget(display_widget, chunks[0], &mut app.display_widget_state);
-
-    if let Some(display) = &app.display_selected {
-        let control_widget: Vec<ListItem> = display
-            .controls
-            .iter()
-            .map(|(control, controller)| {
-                let controller = controller.read().unwrap();
-                let content = Line::from(Span::raw(format!(
-                    "{0}: {1}",
-                    control.get_name(),
-                    controller.value
-                )));
-                ListItem::new(content)
-            })
-            .collect();
-        let control_widget = List::new(control_widget)
-            .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-            .highlight_symbol("> ")
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_style(Style::default().fg(Color::Blue))
-                    .title(format!("Controls - {}", display.name)),
-            );
-        f.render_stateful_widget(control_widget, chunks[1], &mut app.control_widget_state);
+    manager.couplings = config.coupling.clone();
+    manager.mappings = config.mapping.clone();
+    manager.errors = config.errors.clone();
+    manager.value_names = config.values.clone();
+    manager.lock = ddc_bright::lock::Lock::load()?;
+    #[cfg(all(unix, feature = "helper"))]
+    if let Some(socket) = &config.helper_socket {
+        manager.helper_socket = socket.clone();
     }
+    for (backend, secs) in &config.enumeration_timeout {
+        let backend = backend
+            .parse()
+            .map_err(|()| format!("unknown backend in enumeration_timeout: {backend}"))?;
+        manager
+            .enumeration_timeouts
+            .insert(backend, Duration::from_secs(*secs));
+    }
+    Ok(manager)
+}
 
-    if app.show_help {
-        let msg = vec![
-            Line::from(vec!["q".bold(), " exit".into()]),
-            Line::from(vec!["r".bold(), " reload".into()]),
-            Line::from(vec!["1-9".bold(), " select monitor".into()]),
-        ];
-        let text = Text::from(msg);
-
-        let area = centered_rect(100, 100, f.size());
-        let paragraph = Paragraph::new(text)
-            .alignment(Alignment::Center)
-            .wrap(Wrap { trim: true });
-
-        let help_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(100)].as_ref())
-            .margin(1)
-            .split(area);
-
-        let block = Block::default().title("Help").borders(Borders::ALL);
-        f.render_widget(Clear, area);
-        f.render_widget(block, area);
-
-        f.render_widget(paragraph, help_chunks[0]);
+fn refresh(manager: &mut DisplayManager) -> Result<(), Box<dyn Error>> {
+    manager.refresh()?;
+    for warning in &manager.warnings {
+        eprintln!("{warning}");
     }
+    Ok(())
 }
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+use crate::display::{Control, MyDisplay};
+
+/// How the percent shown for a control translates to the share of its maximum written, for
+/// displays where half the value doesn't look like half as bright
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Mapping {
+    /// Model name, id or serial of the display, every display if unset
+    pub display: Option<String>,
+    /// Control mapped, only continuous ones are
+    pub control: Control,
+    /// `[shown, written]` pairs in percent, joined by straight lines. 0% and 100% stay as they
+    /// are unless listed. Written values should rise with the shown ones, or reading a value
+    /// back finds only one of the percentages writing it.
+    pub points: Vec<[f32; 2]>,
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Self {
+            display: None,
+            control: Control::Brightness,
+            points: vec![],
+        }
+    }
+}
+
+impl Mapping {
+    pub fn applies(&self, display: &MyDisplay) -> bool {
+        self.display
+            .as_deref()
+            .is_none_or(|query| display.matches(query))
+    }
+
+    /// The points as `(shown, written)`, by shown percent and with the ends added.
+    fn curve(&self) -> Vec<(f32, f32)> {
+        let mut points: Vec<(f32, f32)> = self
+            .points
+            .iter()
+            .map(|[shown, written]| (shown.clamp(0.0, 100.0), written.clamp(0.0, 100.0)))
+            .collect();
+        if !points.iter().any(|(shown, _)| *shown <= 0.0) {
+            points.push((0.0, 0.0));
+        }
+        if !points.iter().any(|(shown, _)| *shown >= 100.0) {
+            points.push((100.0, 100.0));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points
+    }
+
+    /// The value written for `percent` shown, out of `maximum`.
+    pub fn value(&self, percent: f32, maximum: u16) -> u16 {
+        let written = interpolate(&self.curve(), percent.clamp(0.0, 100.0));
+        (maximum as f32 * written / 100.0).round() as u16
+    }
+
+    /// The percent shown for `value` out of `maximum`, the way back from [`Mapping::value`].
+    pub fn percent(&self, value: u16, maximum: u16) -> f32 {
+        if maximum == 0 {
+            return 0.0;
+        }
+        let mut inverse: Vec<(f32, f32)> = self
+            .curve()
+            .into_iter()
+            .map(|(shown, written)| (written, shown))
+            .collect();
+        inverse.sort_by(|a, b| a.0.total_cmp(&b.0));
+        interpolate(&inverse, value as f32 * 100.0 / maximum as f32)
+    }
+}
+
+/// `y` at `x` on the lines between points sorted by `x`, flat past the first and the last one.
+fn interpolate(points: &[(f32, f32)], x: f32) -> f32 {
+    let (Some(&(first_x, first_y)), Some(&(_, last_y))) = (points.first(), points.last()) else {
+        return x;
+    };
+    if x <= first_x {
+        return first_y;
+    }
+    points
+        .windows(2)
+        .find(|pair| x <= pair[1].0)
+        .map(|pair| {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            match x1 - x0 {
+                width if width <= f32::EPSILON => y1,
+                width => y0 + (y1 - y0) * (x - x0) / width,
+            }
+        })
+        .unwrap_or(last_y)
+}
@@ -0,0 +1,132 @@
+use crate::display::Control;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How far back latency and write rate look
+const WINDOW: Duration = Duration::from_secs(5);
+
+/// A display taking this long for a write counts as slow, like many TVs over HDMI
+const SLOW_LATENCY: Duration = Duration::from_millis(700);
+
+/// How the change queue of a display is doing, to tell a slow display from a busy queue
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct Metrics {
+    /// Changes queued or parked and not written yet
+    pub queued: usize,
+    /// Average time a write took, `None` without recent writes
+    pub latency: Option<Duration>,
+    pub writes_per_second: f32,
+    /// A write took long enough to treat the display as slow, until the app restarts
+    pub slow: bool,
+}
+
+/// A change the queue tried to write, see [`DisplayManager::last_written`]
+///
+/// [`DisplayManager::last_written`]: crate::display::DisplayManager::last_written
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Written {
+    pub id: String,
+    pub name: String,
+    pub control: Control,
+    /// Value shown before the change
+    pub from: u16,
+    pub to: u16,
+    /// Why the write failed, `None` if it got through
+    pub error: Option<String>,
+}
+
+/// Write timings and queued changes by display id
+#[derive(Default)]
+pub(crate) struct Recorder {
+    writes: HashMap<String, VecDeque<(Instant, Duration)>>,
+    queued: HashMap<String, usize>,
+    /// Displays that were slow at some point
+    slow: HashSet<String>,
+    /// The latest change that got through
+    written: Option<Written>,
+    /// The latest one that didn't, until its display is written again
+    failed: Option<Written>,
+}
+
+impl Recorder {
+    pub fn record(&mut self, id: &str, latency: Duration) {
+        if latency >= SLOW_LATENCY {
+            self.slow.insert(id.to_string());
+        }
+        let writes = self.writes.entry(id.to_string()).or_default();
+        writes.push_back((Instant::now(), latency));
+        Self::expire(writes);
+    }
+
+    /// Notes a change just tried.
+    pub fn write(&mut self, written: Written) {
+        match written.error {
+            Some(_) => self.failed = Some(written),
+            None => {
+                if self
+                    .failed
+                    .as_ref()
+                    .is_some_and(|failed| failed.id == written.id)
+                {
+                    self.failed = None;
+                }
+                self.written = Some(written);
+            }
+        }
+    }
+
+    /// The latest change that got through and the latest one that failed since.
+    pub fn last_written(&self) -> (Option<Written>, Option<Written>) {
+        (self.written.clone(), self.failed.clone())
+    }
+
+    pub fn queue(&mut self, id: &str) {
+        *self.queued.entry(id.to_string()).or_default() += 1;
+    }
+
+    pub fn dequeue(&mut self, id: &str) {
+        if let Some(queued) = self.queued.get_mut(id) {
+            *queued = queued.saturating_sub(1);
+        }
+    }
+
+    pub fn is_slow(&self, id: &str) -> bool {
+        self.slow.contains(id)
+    }
+
+    pub fn clear_queued(&mut self) {
+        self.queued.clear();
+    }
+
+    /// Metrics of a display, `parked` is added to what is queued.
+    pub fn metrics(&mut self, id: &str, parked: usize) -> Metrics {
+        let queued = self.queued.get(id).copied().unwrap_or(0) + parked;
+        let slow = self.is_slow(id);
+        let Some(writes) = self.writes.get_mut(id) else {
+            return Metrics {
+                queued,
+                slow,
+                ..Metrics::default()
+            };
+        };
+        Self::expire(writes);
+        let total: Duration = writes.iter().map(|(_, latency)| *latency).sum();
+        Metrics {
+            queued,
+            latency: (!writes.is_empty()).then(|| total / writes.len() as u32),
+            writes_per_second: writes.len() as f32 / WINDOW.as_secs_f32(),
+            slow,
+        }
+    }
+
+    fn expire(writes: &mut VecDeque<(Instant, Duration)>) {
+        while writes
+            .front()
+            .is_some_and(|(time, _)| time.elapsed() > WINDOW)
+        {
+            writes.pop_front();
+        }
+    }
+}
@@ -0,0 +1,458 @@
+//! MQTT client of the daemon, publishing every display as a Home Assistant light through MQTT
+//! discovery: brightness is the level of the light, on and off its power mode. Speaks just
+//! enough MQTT 3.1.1 for that, at QoS 0.
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    slice,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ddc_bright::{
+    display::{Control, DisplayManager, WrappedDisplay},
+    power::PowerMode,
+    supervisor::{CancelToken, Supervisor},
+    units::Level,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    audio::AudioConfig, cli, config::MqttConfig, events::record, history::Origin, watchdog::Jobs,
+};
+
+/// How long a read waits for a packet before the values are compared again
+const TICK: Duration = Duration::from_millis(250);
+
+/// Seconds the broker waits without a packet before taking the client for gone
+const KEEP_ALIVE: u16 = 60;
+
+/// Wait before connecting again after the broker went away
+const RECONNECT: Duration = Duration::from_secs(10);
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+const DISCONNECT: u8 = 0xE0;
+
+/// Publishes the displays to the broker and carries out the commands for them while the
+/// returned supervisor lives, connecting again whenever the broker goes away. `None` without a
+/// broker configured.
+pub fn start(
+    manager: &DisplayManager,
+    jobs: Jobs,
+    audio: &AudioConfig,
+    config: &MqttConfig,
+) -> Option<Supervisor> {
+    let broker = config.broker.clone()?;
+    let displays = manager.shared_displays();
+    let (audio, config) = (audio.clone(), config.clone());
+    println!("Publishing the displays to {broker} for Home Assistant");
+
+    let mut supervisor = Supervisor::default();
+    supervisor.spawn("mqtt", move |token| {
+        while !token.is_cancelled() {
+            if let Err(err) = serve(&broker, &config, &displays, &jobs, &audio, &token) {
+                eprintln!("MQTT: {err}");
+                record("error", None, format!("MQTT: {err}"));
+            }
+            let lost = Instant::now();
+            while lost.elapsed() < RECONNECT && !token.is_cancelled() {
+                thread::sleep(TICK);
+            }
+        }
+    });
+    Some(supervisor)
+}
+
+/// One connection to the broker, until it breaks or `token` is cancelled.
+fn serve(
+    broker: &str,
+    config: &MqttConfig,
+    displays: &Arc<Mutex<Vec<WrappedDisplay>>>,
+    jobs: &Jobs,
+    audio: &AudioConfig,
+    token: &CancelToken,
+) -> Result<(), Box<dyn Error>> {
+    let mut client = Client::connect(broker, config)?;
+    let status = format!("{}/status", config.topic);
+    client.publish(&status, "online", true)?;
+    client.subscribe(&format!("{}/+/set", config.topic))?;
+    let mut announced = HashSet::new();
+    let mut published: HashMap<String, Value> = HashMap::new();
+    while !token.is_cancelled() {
+        let displays = displays.lock().unwrap().clone();
+        for display in &displays {
+            let object = object(display);
+            if announced.insert(display.id.clone()) {
+                let topic = format!(
+                    "{}/light/{}/{object}/config",
+                    config.discovery_prefix, config.topic
+                );
+                client.publish(&topic, &discovery(config, display).to_string(), true)?;
+            }
+            let state = state(display);
+            if published.get(&display.id) != Some(&state) {
+                let topic = format!("{}/{object}/state", config.topic);
+                client.publish(&topic, &state.to_string(), true)?;
+                published.insert(display.id.clone(), state);
+            }
+        }
+        client.keep_alive()?;
+        let Some((kind, body)) = client.read()? else {
+            continue;
+        };
+        if kind & 0xF0 != PUBLISH {
+            continue;
+        }
+        if let Some((topic, payload)) = incoming(kind, &body) {
+            command(config, &displays, jobs, audio, topic, payload);
+        }
+    }
+    client.publish(&status, "offline", true)?;
+    client.send(&packet(DISCONNECT, &[]))?;
+    Ok(())
+}
+
+/// The display's id as it appears in topics, only letters, digits, `_` and `-`.
+fn object(display: &WrappedDisplay) -> String {
+    display
+        .id
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+/// The discovery message making the display a light with the JSON schema.
+fn discovery(config: &MqttConfig, display: &WrappedDisplay) -> Value {
+    let (topic, object) = (&config.topic, object(display));
+    let unique = format!("{topic}_{object}");
+    let mut device = json!({ "identifiers": [unique], "name": display.label() });
+    if let Some(serial) = &display.serial {
+        device["serial_number"] = json!(serial);
+    }
+    json!({
+        "name": null,
+        "unique_id": unique,
+        "schema": "json",
+        "state_topic": format!("{topic}/{object}/state"),
+        "command_topic": format!("{topic}/{object}/set"),
+        "availability_topic": format!("{topic}/status"),
+        "brightness": display.controls.contains_key(&Control::Brightness),
+        "brightness_scale": 100,
+        "device": device,
+    })
+}
+
+/// `{"state": "ON", "brightness": 40}`, brightness in percent.
+fn state(display: &WrappedDisplay) -> Value {
+    let power = match display.is_powered_off() {
+        true => "OFF",
+        false => "ON",
+    };
+    let brightness = display
+        .controls
+        .get(&Control::Brightness)
+        .map(|controller| display.percent(&controller.read().unwrap()));
+    json!({ "state": power, "brightness": brightness })
+}
+
+/// Carries out a command Home Assistant sent to `<topic>/<display>/set`, like
+/// `{"state": "ON", "brightness": 40}`.
+fn command(
+    config: &MqttConfig,
+    displays: &[WrappedDisplay],
+    jobs: &Jobs,
+    audio: &AudioConfig,
+    topic: &str,
+    payload: &[u8],
+) {
+    let Some(object) = topic
+        .strip_prefix(&format!("{}/", config.topic))
+        .and_then(|rest| rest.strip_suffix("/set"))
+    else {
+        return;
+    };
+    let Some(display) = displays
+        .iter()
+        .find(|display| self::object(display) == object)
+    else {
+        return;
+    };
+    let result = serde_json::from_slice(payload)
+        .map_err(Into::into)
+        .and_then(|command| apply(display, jobs, audio, &command));
+    if let Err(err) = result {
+        eprintln!("MQTT: {}: {err}", display.label());
+        record("error", Some(&display.id), format!("MQTT command: {err}"));
+    }
+}
+
+fn apply(
+    display: &WrappedDisplay,
+    jobs: &Jobs,
+    audio: &AudioConfig,
+    command: &Value,
+) -> Result<(), Box<dyn Error>> {
+    let power = match command["state"].as_str() {
+        Some("OFF") => Some(PowerMode::Off),
+        Some("ON") if display.is_powered_off() => Some(PowerMode::On),
+        _ => None,
+    };
+    if let Some(mode) = power {
+        let display = display.clone();
+        // After whatever is queued, which a display that is off won't take
+        jobs.run(move |manager| {
+            manager.flush();
+            display.set_power(mode).map_err(|err| err.to_string())
+        })
+        .ok_or("the daemon is stopping")??;
+        if mode == PowerMode::Off {
+            return Ok(());
+        }
+    }
+    if let Some(brightness) = command["brightness"].as_f64() {
+        let level = Level::Percent(brightness.clamp(0.0, 100.0) as f32);
+        let levels = [(Control::Brightness, level.into())];
+        let (display, audio) = (display.clone(), audio.clone());
+        jobs.run(move |manager| {
+            let displays = slice::from_ref(&display);
+            let result = cli::queue(
+                manager,
+                displays,
+                &levels,
+                &audio,
+                Origin::Mqtt,
+                &mut io::sink(),
+            );
+            result.map_err(|err| err.to_string())
+        })
+        .ok_or("the daemon is stopping")??;
+    }
+    Ok(())
+}
+
+/// An MQTT packet: the first byte of the fixed header, the length and the rest.
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    let mut length = body.len();
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        match length {
+            0 => {
+                packet.push(byte);
+                break;
+            }
+            _ => packet.push(byte | 0x80),
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Appends a string prefixed by its length.
+fn string(buffer: &mut Vec<u8>, text: &str) {
+    buffer.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(text.as_bytes());
+}
+
+/// The topic and payload of a PUBLISH body.
+fn incoming(kind: u8, body: &[u8]) -> Option<(&str, &[u8])> {
+    let length = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+    let topic = std::str::from_utf8(body.get(2..2 + length)?).ok()?;
+    // Packets above QoS 0 carry an id before the payload
+    let id = match kind & 0x06 {
+        0 => 0,
+        _ => 2,
+    };
+    Some((topic, body.get(2 + length + id..)?))
+}
+
+struct Client {
+    stream: TcpStream,
+    /// How long a read waits for the start of a packet
+    wait: Duration,
+    sent: Instant,
+}
+
+impl Client {
+    fn connect(broker: &str, config: &MqttConfig) -> Result<Self, Box<dyn Error>> {
+        let address = broker
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format!("{broker} didn't resolve"))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut client = Client {
+            stream,
+            wait: TIMEOUT,
+            sent: Instant::now(),
+        };
+
+        let mut body = vec![];
+        string(&mut body, "MQTT");
+        // Clean session, with a retained will marking the displays unavailable
+        let mut flags = 0x02 | 0x04 | 0x20;
+        if config.username.is_some() {
+            flags |= 0x80;
+        }
+        if config.password.is_some() {
+            flags |= 0x40;
+        }
+        body.extend_from_slice(&[4, flags]);
+        body.extend_from_slice(&KEEP_ALIVE.to_be_bytes());
+        string(&mut body, &config.topic);
+        string(&mut body, &format!("{}/status", config.topic));
+        string(&mut body, "offline");
+        for credential in [&config.username, &config.password].into_iter().flatten() {
+            string(&mut body, credential);
+        }
+        client.send(&packet(CONNECT, &body))?;
+        match client.read()? {
+            Some((CONNACK, reply)) if reply.get(1) == Some(&0) => {}
+            Some((CONNACK, reply)) => {
+                let code = reply.get(1).copied().unwrap_or_default();
+                return Err(format!("{broker} refused the connection with code {code}").into());
+            }
+            _ => return Err(format!("{broker} didn't acknowledge the connection").into()),
+        }
+        client.wait = TICK;
+        Ok(client)
+    }
+
+    /// The next packet, its first byte and its body, `None` if none started in time.
+    fn read(&mut self) -> io::Result<Option<(u8, Vec<u8>)>> {
+        self.stream.set_read_timeout(Some(self.wait))?;
+        let mut kind = [0];
+        match self.stream.read(&mut kind) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => {}
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(None)
+            }
+            Err(err) => return Err(err),
+        }
+        // The rest of a packet that started comes right after
+        self.stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut length = 0;
+        for shift in [0, 7, 14, 21] {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte)?;
+            length |= ((byte[0] & 0x7F) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        self.stream.read_exact(&mut body)?;
+        Ok(Some((kind[0], body)))
+    }
+
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> io::Result<()> {
+        let mut body = vec![];
+        string(&mut body, topic);
+        body.extend_from_slice(payload.as_bytes());
+        self.send(&packet(PUBLISH | retain as u8, &body))
+    }
+
+    fn subscribe(&mut self, filter: &str) -> io::Result<()> {
+        let mut body = 1u16.to_be_bytes().to_vec();
+        string(&mut body, filter);
+        body.push(0);
+        self.send(&packet(SUBSCRIBE, &body))
+    }
+
+    /// Pings the broker when nothing was sent for half the keep-alive.
+    fn keep_alive(&mut self) -> io::Result<()> {
+        match self.sent.elapsed() >= Duration::from_secs(KEEP_ALIVE as u64 / 2) {
+            true => self.send(&packet(PINGREQ, &[])),
+            false => Ok(()),
+        }
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.sent = Instant::now();
+        self.stream.write_all(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn lengths_take_as_many_bytes_as_they_need() {
+        assert_eq!(packet(PINGREQ, &[]), [PINGREQ, 0]);
+        assert_eq!(packet(PUBLISH, &[7; 127])[..2], [PUBLISH, 127]);
+        assert_eq!(packet(PUBLISH, &[7; 128])[..3], [PUBLISH, 0x80, 0x01]);
+        let long = packet(PUBLISH, &[7; 16_384]);
+        assert_eq!(long[..4], [PUBLISH, 0x80, 0x80, 0x01]);
+        assert_eq!(long.len(), 4 + 16_384);
+    }
+
+    #[test]
+    fn strings_are_prefixed_by_their_length() {
+        let mut buffer = vec![];
+        string(&mut buffer, "MQTT");
+        assert_eq!(buffer, b"\x00\x04MQTT");
+    }
+
+    #[test]
+    fn published_topics_and_payloads_are_told_apart() {
+        let mut body = vec![];
+        string(&mut body, "ddc_bright/dell/set");
+        body.extend_from_slice(br#"{"state":"ON"}"#);
+        assert_eq!(
+            incoming(PUBLISH, &body),
+            Some(("ddc_bright/dell/set", &br#"{"state":"ON"}"#[..]))
+        );
+        // At QoS 1 the packet id comes between them
+        let mut body = vec![];
+        string(&mut body, "ddc_bright/dell/set");
+        body.extend_from_slice(&[0, 1]);
+        body.extend_from_slice(b"{}");
+        assert_eq!(
+            incoming(PUBLISH | 0x02, &body),
+            Some(("ddc_bright/dell/set", &b"{}"[..]))
+        );
+        assert_eq!(incoming(PUBLISH, &[0, 9, b'a']), None);
+        assert_eq!(incoming(PUBLISH, &[]), None);
+    }
+
+    #[test]
+    fn packets_read_back_as_sent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut client = Client {
+            stream: listener.accept().unwrap().0,
+            wait: TIMEOUT,
+            sent: Instant::now(),
+        };
+        let body = vec![3; 300];
+        sender.write_all(&packet(PUBLISH, &body)).unwrap();
+        sender.write_all(&packet(PINGREQ, &[])).unwrap();
+        assert_eq!(client.read().unwrap(), Some((PUBLISH, body)));
+        assert_eq!(client.read().unwrap(), Some((PINGREQ, vec![])));
+        drop(sender);
+        assert!(client.read().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Desktop notifications for changes made while nobody looks at the TUI or a terminal: schedule
+//! rules, requests on the control socket and the brightness keys
+
+use std::{
+    error::Error,
+    process::{Command, Stdio},
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// What made a change
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// A schedule rule triggered, in the TUI or the daemon
+    Schedule,
+    /// A `set` request on the control socket of the daemon
+    Socket,
+    /// `ddc_bright brightness-key`, bound to the brightness keys
+    Keys,
+}
+
+impl Source {
+    fn title(&self) -> &'static str {
+        match self {
+            Self::Schedule => "Schedule",
+            Self::Socket => "Control socket",
+            Self::Keys => "Brightness keys",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Sources whose changes show a notification, none by default
+    pub on: Vec<Source>,
+    /// Program showing the notification instead of `notify-send` or `osascript`, with `{title}`
+    /// and `{body}` replaced
+    pub command: Option<String>,
+}
+
+impl NotificationsConfig {
+    /// Shows what changed if notifications are on for the source. Doesn't wait for the
+    /// notification to be shown.
+    pub fn notify(&self, source: Source, body: &str) -> Result<(), Box<dyn Error>> {
+        if !self.on.contains(&source) {
+            return Ok(());
+        }
+        let title = source.title();
+        let mut command = match &self.command {
+            Some(command) => {
+                let mut words = command
+                    .split_whitespace()
+                    .map(|word| word.replace("{title}", title).replace("{body}", body));
+                let program = words.next().ok_or("notifications.command is empty")?;
+                let mut command = Command::new(program);
+                command.args(words);
+                command
+            }
+            None => default_command(title, body)?,
+        };
+        // Nothing may be printed over the TUI
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("showing a notification: {err}"))?;
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn default_command(title: &str, body: &str) -> Result<Command, Box<dyn Error>> {
+    let quoted = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {} with title {}",
+        quoted(body),
+        quoted(title)
+    ));
+    Ok(command)
+}
+
+#[cfg(windows)]
+fn default_command(_title: &str, _body: &str) -> Result<Command, Box<dyn Error>> {
+    Err("notifications need notifications.command on Windows".into())
+}
+
+/// `notify-send`, replacing the previous notification of ddc_bright where the daemon supports it
+#[cfg(not(any(target_os = "macos", windows)))]
+fn default_command(title: &str, body: &str) -> Result<Command, Box<dyn Error>> {
+    let mut command = Command::new("notify-send");
+    command
+        .args(["--app-name", "ddc_bright"])
+        .args([
+            "--hint",
+            "string:x-canonical-private-synchronous:ddc_bright",
+        ])
+        .args([title, body]);
+    Ok(command)
+}
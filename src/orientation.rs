@@ -0,0 +1,76 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// VCP code of the screen orientation, reported by displays that can pivot
+pub const CODE: u8 = 0xAA;
+
+/// Which way a display is turned, clockwise from landscape
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    LandscapeFlipped,
+    PortraitFlipped,
+}
+
+impl Orientation {
+    /// Decodes a 0xAA value, `None` if the display doesn't know its orientation.
+    pub fn from_vcp(value: u16) -> Option<Self> {
+        match value & 0xFF {
+            0x01 => Some(Orientation::Landscape),
+            0x02 => Some(Orientation::Portrait),
+            0x03 => Some(Orientation::LandscapeFlipped),
+            0x04 => Some(Orientation::PortraitFlipped),
+            _ => None,
+        }
+    }
+
+    pub fn to_vcp(self) -> u16 {
+        match self {
+            Orientation::Landscape => 0x01,
+            Orientation::Portrait => 0x02,
+            Orientation::LandscapeFlipped => 0x03,
+            Orientation::PortraitFlipped => 0x04,
+        }
+    }
+
+    /// The xrandr style rotation that keeps the desktop upright on a display turned this way.
+    pub fn rotation(self) -> &'static str {
+        match self {
+            Orientation::Landscape => "normal",
+            Orientation::Portrait => "left",
+            Orientation::LandscapeFlipped => "inverted",
+            Orientation::PortraitFlipped => "right",
+        }
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Orientation::Landscape => "landscape",
+            Orientation::Portrait => "portrait",
+            Orientation::LandscapeFlipped => "landscape-flipped",
+            Orientation::PortraitFlipped => "portrait-flipped",
+        })
+    }
+}
+
+impl FromStr for Orientation {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_ascii_lowercase().as_str() {
+            "landscape" | "0" => Ok(Orientation::Landscape),
+            "portrait" | "90" => Ok(Orientation::Portrait),
+            "landscape-flipped" | "180" => Ok(Orientation::LandscapeFlipped),
+            "portrait-flipped" | "270" => Ok(Orientation::PortraitFlipped),
+            _ => Err(format!(
+                "{text:?} is not landscape, portrait, landscape-flipped or portrait-flipped"
+            )),
+        }
+    }
+}
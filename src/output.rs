@@ -0,0 +1,316 @@
+//! The connector a display is plugged into and the mode it shows, as the OS reports it
+//!
+//! On Linux connectors come from DRM in sysfs and modes from `xrandr`, so the resolution is only
+//! known in an X11 session. On Windows the monitors of the desktop are listed with their place
+//! on it.
+
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::{fs, io, process};
+
+use serde::Serialize;
+
+#[cfg(target_os = "linux")]
+use crate::display::MyDisplay;
+
+#[cfg(target_os = "linux")]
+pub(crate) const DRM: &str = "/sys/class/drm";
+
+/// Connector name prefixes of internal panels, which have a backlight instead
+#[cfg(target_os = "linux")]
+pub(crate) const INTERNAL: [&str; 3] = ["eDP", "LVDS", "DSI"];
+
+/// Where a display is connected and what it shows, telling identical models apart
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct Output {
+    /// Name of the connector, e.g. `DP-2`
+    pub connector: Option<String>,
+    /// Width and height in pixels
+    pub resolution: Option<(u32, u32)>,
+    /// Refresh rate in Hz
+    pub refresh: Option<u32>,
+    /// Top left corner on the desktop in pixels
+    pub position: Option<(i32, i32)>,
+}
+
+impl fmt::Display for Output {
+    /// Like `3840x2160@60 on DP-2`, leaving out what isn't known.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some((width, height)) = self.resolution {
+            let refresh = self.refresh.map(|hz| format!("@{hz}")).unwrap_or_default();
+            parts.push(format!("{width}x{height}{refresh}"));
+        }
+        if let Some(connector) = &self.connector {
+            parts.push(format!("on {connector}"));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Number of the i2c bus a DDC/CI display talks on, from the device number in its id.
+#[cfg(target_os = "linux")]
+pub(crate) fn i2c_bus(display: &MyDisplay) -> Option<u64> {
+    if display.backend != "i2c-dev" && display.backend != "helper" {
+        return None;
+    }
+    let rdev = display.id.parse::<u64>().ok()?;
+    Some((rdev & 0xff) | ((rdev >> 12) & 0xfff00))
+}
+
+/// A connected DRM connector
+#[cfg(target_os = "linux")]
+struct Connector {
+    /// Name without the card, e.g. `DP-2`
+    name: String,
+    /// i2c buses DDC/CI goes over, the `ddc` link and the DP AUX channel
+    buses: Vec<u64>,
+    edid: Option<Vec<u8>>,
+}
+
+/// The connectors and X11 outputs seen at a refresh
+#[cfg(target_os = "linux")]
+pub(crate) struct Outputs {
+    connectors: Vec<Connector>,
+    x11: Vec<XOutput>,
+}
+
+#[cfg(target_os = "linux")]
+impl Outputs {
+    pub fn query() -> Self {
+        let x11 = match std::env::var_os("DISPLAY") {
+            Some(_) => xrandr().unwrap_or_default(),
+            None => vec![],
+        };
+        Self {
+            connectors: connectors(),
+            x11,
+        }
+    }
+
+    /// What is known about the display, `None` if nothing.
+    pub fn of(&self, display: &MyDisplay) -> Option<Output> {
+        let connector = match display.backend.as_str() {
+            "sysfs" => self.connectors.iter().find(|connector| {
+                INTERNAL
+                    .iter()
+                    .any(|prefix| connector.name.starts_with(prefix))
+            }),
+            "gamma" => self
+                .connectors
+                .iter()
+                .find(|connector| connector.name == display.id),
+            _ => i2c_bus(display).and_then(|bus| {
+                self.connectors
+                    .iter()
+                    .find(|connector| connector.buses.contains(&bus))
+            }),
+        };
+        let name = connector.map_or(display.id.as_str(), |connector| &connector.name);
+        // The X server may name outputs differently than the kernel, the EDID is the same
+        let x11 = self.x11.iter().find(|output| {
+            output.name == name
+                || connector
+                    .and_then(|connector| connector.edid.as_ref())
+                    .is_some_and(|edid| output.edid.as_ref() == Some(edid))
+        });
+        let output = Output {
+            connector: connector
+                .map(|connector| connector.name.clone())
+                .or_else(|| x11.map(|output| output.name.clone())),
+            resolution: x11.and_then(|output| output.resolution),
+            refresh: x11.and_then(|output| output.refresh),
+            position: x11.and_then(|output| output.position),
+        };
+        (output != Output::default()).then_some(output)
+    }
+}
+
+/// The outputs of the physical monitors on the desktop, in the order the winapi backend finds
+/// them in. Mirrored monitors share a desktop monitor, and so its name and place.
+#[cfg(windows)]
+pub(crate) fn monitors() -> Vec<Output> {
+    use std::{mem, ptr};
+
+    use windows_sys::core::BOOL;
+    use windows_sys::Win32::{
+        Devices::Display::GetNumberOfPhysicalMonitorsFromHMONITOR,
+        Foundation::{LPARAM, RECT},
+        Graphics::Gdi::{EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFOEXW},
+    };
+
+    unsafe extern "system" fn found(monitor: HMONITOR, _: HDC, _: *mut RECT, data: LPARAM) -> BOOL {
+        // SAFETY: the list handed in below, alive for the whole enumeration
+        unsafe { (*(data as *mut Vec<HMONITOR>)).push(monitor) };
+        1
+    }
+
+    let mut handles: Vec<HMONITOR> = vec![];
+    let data = &mut handles as *mut Vec<HMONITOR> as LPARAM;
+    // SAFETY: every monitor of the desktop, the callback only pushes to the list
+    unsafe { EnumDisplayMonitors(ptr::null_mut(), ptr::null(), Some(found), data) };
+    let mut outputs = vec![];
+    for handle in handles {
+        // SAFETY: plain data, the size tells the call which kind it is
+        let mut info: MONITORINFOEXW = unsafe { mem::zeroed() };
+        info.monitorInfo.cbSize = mem::size_of::<MONITORINFOEXW>() as u32;
+        let mut physical = 0;
+        // SAFETY: handles of this enumeration, written to locals
+        let known = unsafe {
+            GetMonitorInfoW(handle, (&mut info as *mut MONITORINFOEXW).cast()) != 0
+                && GetNumberOfPhysicalMonitorsFromHMONITOR(handle, &mut physical) != 0
+        };
+        if !known {
+            continue;
+        }
+        let device = &info.szDevice;
+        let length = device.iter().position(|&c| c == 0).unwrap_or(device.len());
+        let name = String::from_utf16_lossy(&device[..length]);
+        let rect = info.monitorInfo.rcMonitor;
+        let output = Output {
+            // Named like \\.\DISPLAY1
+            connector: Some(name.trim_start_matches(['\\', '.']).to_string()),
+            resolution: Some((
+                (rect.right - rect.left) as u32,
+                (rect.bottom - rect.top) as u32,
+            )),
+            refresh: None,
+            position: Some((rect.left, rect.top)),
+        };
+        outputs.extend((0..physical).map(|_| output.clone()));
+    }
+    outputs
+}
+
+#[cfg(target_os = "linux")]
+fn connectors() -> Vec<Connector> {
+    let Ok(entries) = fs::read_dir(DRM) else {
+        return vec![];
+    };
+    let bus = |name: &str| name.strip_prefix("i2c-")?.parse::<u64>().ok();
+    let mut connectors = vec![];
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        // Connectors are named after their card, e.g. card0-DP-1
+        let Some((_, name)) = file_name.split_once('-') else {
+            continue;
+        };
+        let path = entry.path();
+        let status = fs::read_to_string(path.join("status")).unwrap_or_default();
+        if status.trim() != "connected" {
+            continue;
+        }
+        let mut buses: Vec<u64> = fs::read_link(path.join("ddc"))
+            .ok()
+            .and_then(|link| bus(&link.file_name()?.to_string_lossy()))
+            .into_iter()
+            .collect();
+        // DisplayPort puts the bus of its AUX channel below the connector
+        if let Ok(children) = fs::read_dir(&path) {
+            buses.extend(
+                children
+                    .flatten()
+                    .filter_map(|child| bus(&child.file_name().to_string_lossy())),
+            );
+        }
+        connectors.push(Connector {
+            name: name.to_string(),
+            buses,
+            edid: fs::read(path.join("edid"))
+                .ok()
+                .filter(|edid| !edid.is_empty()),
+        });
+    }
+    connectors
+}
+
+/// A connected output as `xrandr --verbose` lists it
+#[cfg(target_os = "linux")]
+pub(crate) struct XOutput {
+    pub name: String,
+    pub edid: Option<Vec<u8>>,
+    /// Brightness factor of the gamma ramps, 1 at full brightness
+    pub brightness: f64,
+    pub resolution: Option<(u32, u32)>,
+    pub refresh: Option<u32>,
+    pub position: Option<(i32, i32)>,
+}
+
+/// Connected X11 outputs.
+#[cfg(target_os = "linux")]
+pub(crate) fn xrandr() -> io::Result<Vec<XOutput>> {
+    let output = process::Command::new("xrandr").arg("--verbose").output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "xrandr failed with {}",
+            output.status
+        )));
+    }
+    Ok(parse_xrandr(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[cfg(target_os = "linux")]
+fn parse_xrandr(text: &str) -> Vec<XOutput> {
+    let mut outputs = vec![];
+    let mut current: Option<XOutput> = None;
+    let mut in_edid = false;
+    let mut in_current = false;
+    for line in text.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            outputs.extend(current.take());
+            (in_edid, in_current) = (false, false);
+            let mut words = line.split_whitespace();
+            if let (Some(name), Some("connected")) = (words.next(), words.next()) {
+                // Like `3840x2160+0+0`, missing while the output is off
+                let geometry = words.find_map(|word| {
+                    let mut parts = word.split('+');
+                    let (width, height) = parts.next()?.split_once('x')?;
+                    let (x, y) = (parts.next()?, parts.next()?);
+                    let size = (width.parse().ok()?, height.parse().ok()?);
+                    Some((size, (x.parse().ok()?, y.parse().ok()?)))
+                });
+                current = Some(XOutput {
+                    name: name.to_string(),
+                    edid: None,
+                    brightness: 1.0,
+                    resolution: geometry.map(|(size, _)| size),
+                    refresh: None,
+                    position: geometry.map(|(_, position)| position),
+                });
+            }
+            continue;
+        }
+        let Some(output) = current.as_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if in_edid && line.len() % 2 == 0 && line.chars().all(|c| c.is_ascii_hexdigit()) {
+            output.edid.get_or_insert_with(Vec::new).extend(
+                (0..line.len())
+                    .step_by(2)
+                    .filter_map(|i| u8::from_str_radix(&line[i..i + 2], 16).ok()),
+            );
+            continue;
+        }
+        in_edid = line == "EDID:";
+        if let Some(value) = line.strip_prefix("Brightness:") {
+            output.brightness = value.trim().parse().unwrap_or(1.0);
+        }
+        // The mode in use is marked, its clock follows a few lines down
+        if line.contains("*current") {
+            in_current = true;
+        } else if in_current {
+            if let Some(clock) = line
+                .strip_prefix("v:")
+                .and_then(|v| v.split("clock").nth(1))
+            {
+                let hz = clock.trim().trim_end_matches("Hz").parse::<f64>();
+                output.refresh = hz.ok().map(|hz| hz.round() as u32);
+                in_current = false;
+            }
+        }
+    }
+    outputs.extend(current);
+    outputs
+}
@@ -0,0 +1,779 @@
+//! Numbers drawn on the displays themselves when identifying them, the way the display settings
+//! of the OS do, for mirrored outputs and displays that flash too subtly to tell apart
+//!
+//! Mirrored displays share one badge listing all their numbers. Windows places the badges on
+//! the desktop monitors, Linux on the outputs found at the last refresh: through wlr-layer-shell
+//! on Wayland, falling back to override-redirect windows of the X server, XWayland included,
+//! where the compositor lacks it. Displays with no known output get no badge.
+
+use std::time::Duration;
+
+use ddc_bright::{display::WrappedDisplay, output::Output};
+
+/// How long the badges stay up
+pub const DURATION: Duration = Duration::from_secs(3);
+
+/// Longest wait for window events, so the badges come down on time
+#[cfg_attr(not(any(target_os = "linux", windows)), allow(dead_code))]
+const TICK: Duration = Duration::from_millis(50);
+
+/// Badges are this fraction of the height of the output
+#[cfg_attr(not(any(target_os = "linux", windows)), allow(dead_code))]
+const SCALE: u32 = 4;
+
+/// Height of outputs whose resolution isn't known
+#[cfg_attr(not(any(target_os = "linux", windows)), allow(dead_code))]
+const FALLBACK_HEIGHT: u32 = 1080;
+
+/// Segments of the digits 0 to 9, top, top right, bottom right, bottom, bottom left, top left
+/// and middle from the lowest bit up
+#[cfg_attr(not(any(target_os = "linux", windows)), allow(dead_code))]
+const DIGITS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+    0b1111111, 0b1101111,
+];
+
+/// A filled rectangle, left, top, width and height
+#[cfg_attr(not(any(target_os = "linux", windows)), allow(dead_code))]
+type Rect = (i32, i32, u32, u32);
+
+/// The numbers shown on one output
+#[cfg_attr(not(any(target_os = "linux", windows)), allow(dead_code))]
+struct Badge {
+    text: String,
+    output: Output,
+}
+
+#[cfg_attr(not(any(target_os = "linux", windows)), allow(dead_code))]
+impl Badge {
+    /// Width and height of the badge on its output, and the rectangles of its digits.
+    fn glyphs(&self) -> ((u32, u32), Vec<Rect>) {
+        let height = self
+            .output
+            .resolution
+            .map_or(FALLBACK_HEIGHT, |(_, height)| height);
+        let digit_height = (height / SCALE).max(40);
+        let digit_width = digit_height / 2;
+        let stroke = (digit_height / 8).max(2);
+        let (gap, padding) = (digit_width / 2, digit_height / 4);
+        let half = digit_height / 2;
+        let segments: [Rect; 7] = [
+            (0, 0, digit_width, stroke),
+            ((digit_width - stroke) as i32, 0, stroke, half),
+            (
+                (digit_width - stroke) as i32,
+                half as i32,
+                stroke,
+                digit_height - half,
+            ),
+            (0, (digit_height - stroke) as i32, digit_width, stroke),
+            (0, half as i32, stroke, digit_height - half),
+            (0, 0, stroke, half),
+            (0, ((digit_height - stroke) / 2) as i32, digit_width, stroke),
+        ];
+        let mut rects = vec![];
+        let mut left = padding;
+        for c in self.text.chars() {
+            if let Some(digit) = c.to_digit(10) {
+                for (bit, &(x, y, width, height)) in segments.iter().enumerate() {
+                    if DIGITS[digit as usize] & 1 << bit != 0 {
+                        rects.push((left as i32 + x, padding as i32 + y, width, height));
+                    }
+                }
+            }
+            left += digit_width + gap;
+        }
+        let width = left - gap + padding;
+        ((width, digit_height + padding * 2), rects)
+    }
+
+    /// Top left corner of the badge on the desktop, centred on its output.
+    fn position(&self, (width, height): (u32, u32)) -> Option<(i32, i32)> {
+        let (x, y) = self.output.position?;
+        let (output_width, output_height) = self.output.resolution?;
+        let centre = |start: i32, total: u32, size: u32| start + (total as i32 - size as i32) / 2;
+        Some((
+            centre(x, output_width, width),
+            centre(y, output_height, height),
+        ))
+    }
+}
+
+/// Shows the number of each display on its output for [`DURATION`], blocking until they are
+/// gone. Sessions without a desktop, like the console, show nothing.
+pub fn show(displays: &[WrappedDisplay]) -> Result<(), String> {
+    let mut badges: Vec<Badge> = vec![];
+    for (number, display) in (1..).zip(displays) {
+        let Some(output) = display.output.clone() else {
+            continue;
+        };
+        let same = |badge: &&mut Badge| {
+            badge.output.connector == output.connector && badge.output.position == output.position
+        };
+        match badges.iter_mut().find(same) {
+            Some(badge) => badge.text.push_str(&format!(" {number}")),
+            None => badges.push(Badge {
+                text: number.to_string(),
+                output,
+            }),
+        }
+    }
+    if badges.is_empty() {
+        return Ok(());
+    }
+    platform::draw(&badges)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::env;
+
+    use super::{wayland, x11, Badge};
+
+    pub fn draw(badges: &[Badge]) -> Result<(), String> {
+        let x11 = env::var_os("DISPLAY").is_some();
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            match wayland::draw(badges) {
+                Err(err) if !x11 => return Err(err),
+                Err(_) => {}
+                Ok(()) => return Ok(()),
+            }
+        }
+        match x11 {
+            true => x11::draw(badges),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Layer surfaces of the overlay layer, one on each output with a badge
+#[cfg(target_os = "linux")]
+mod wayland {
+    use std::{
+        env, fs,
+        io::{self, Write},
+        os::fd::{AsFd, AsRawFd},
+        path::Path,
+        process,
+        time::Instant,
+    };
+
+    use wayland_client::{
+        delegate_noop,
+        protocol::{
+            wl_buffer::WlBuffer, wl_compositor::WlCompositor, wl_output, wl_region::WlRegion,
+            wl_registry, wl_shm, wl_shm_pool::WlShmPool, wl_surface::WlSurface,
+        },
+        Connection, Dispatch, Proxy, QueueHandle,
+    };
+    use wayland_protocols_wlr::layer_shell::v1::client::{
+        zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+        zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+    };
+
+    use super::{Badge, Rect, DURATION, TICK};
+
+    #[derive(Default)]
+    struct State {
+        compositor: Option<WlCompositor>,
+        shm: Option<wl_shm::WlShm>,
+        shell: Option<ZwlrLayerShellV1>,
+        outputs: Vec<Output>,
+        surfaces: Vec<Surface>,
+        failed: Option<String>,
+    }
+
+    struct Output {
+        proxy: wl_output::WlOutput,
+        name: Option<String>,
+    }
+
+    struct Surface {
+        surface: WlSurface,
+        layer: ZwlrLayerSurfaceV1,
+        size: (u32, u32),
+        rects: Vec<Rect>,
+        buffer: Option<WlBuffer>,
+    }
+
+    pub fn draw(badges: &[Badge]) -> Result<(), String> {
+        let connection = Connection::connect_to_env().map_err(|err| err.to_string())?;
+        let mut queue = connection.new_event_queue();
+        let handle = queue.handle();
+        connection.display().get_registry(&handle, ());
+        let mut state = State::default();
+        // Globals arrive in the first roundtrip, the names of the outputs in the second
+        for _ in 0..2 {
+            queue.roundtrip(&mut state).map_err(|err| err.to_string())?;
+        }
+        let (Some(compositor), Some(shell)) = (state.compositor.clone(), state.shell.clone())
+        else {
+            return Err("the compositor doesn't support wlr-layer-shell".to_string());
+        };
+        for badge in badges {
+            let output = (state.outputs.iter())
+                .find(|output| output.name.is_some() && output.name == badge.output.connector);
+            let Some(output) = output.map(|output| output.proxy.clone()) else {
+                continue;
+            };
+            let (size, rects) = badge.glyphs();
+            let index = state.surfaces.len();
+            let surface = compositor.create_surface(&handle, ());
+            // An empty input region lets clicks through to what is below
+            let region = compositor.create_region(&handle, ());
+            surface.set_input_region(Some(&region));
+            region.destroy();
+            let layer = shell.get_layer_surface(
+                &surface,
+                Some(&output),
+                Layer::Overlay,
+                "ddc_bright".to_string(),
+                &handle,
+                index,
+            );
+            layer.set_size(size.0, size.1);
+            surface.commit();
+            state.surfaces.push(Surface {
+                surface,
+                layer,
+                size,
+                rects,
+                buffer: None,
+            });
+        }
+        if state.surfaces.is_empty() {
+            return Err("no output of the compositor has a badge".to_string());
+        }
+
+        let deadline = Instant::now() + DURATION;
+        while Instant::now() < deadline && state.failed.is_none() {
+            queue
+                .dispatch_pending(&mut state)
+                .map_err(|err| err.to_string())?;
+            queue.flush().map_err(|err| err.to_string())?;
+            let Some(guard) = queue.prepare_read() else {
+                continue;
+            };
+            let mut poll = libc::pollfd {
+                fd: guard.connection_fd().as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: the descriptor of the connection, which the guard keeps open
+            if unsafe { libc::poll(&mut poll, 1, TICK.as_millis() as _) } > 0 {
+                guard.read().map_err(|err| err.to_string())?;
+            }
+        }
+        for surface in &state.surfaces {
+            surface.layer.destroy();
+            surface.surface.destroy();
+        }
+        let _ = connection.flush();
+        state.failed.map_or(Ok(()), Err)
+    }
+
+    /// A buffer of the badge, white digits on translucent black.
+    fn buffer(
+        shm: &wl_shm::WlShm,
+        surface: &Surface,
+        handle: &QueueHandle<State>,
+    ) -> io::Result<WlBuffer> {
+        let (width, height) = surface.size;
+        let mut pixels = vec![0xCC000000u32; (width * height) as usize];
+        for &(x, y, w, h) in &surface.rects {
+            for row in y as u32..(y as u32 + h).min(height) {
+                let start = (row * width + x as u32) as usize;
+                pixels[start..start + w as usize].fill(0xFFFFFFFF);
+            }
+        }
+        let dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, Into::into);
+        let path = Path::new(&dir).join(format!("ddc_bright-overlay-{}", process::id()));
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let _ = fs::remove_file(&path);
+        let bytes: Vec<u8> = pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_le_bytes())
+            .collect();
+        file.write_all(&bytes)?;
+        let pool = shm.create_pool(file.as_fd(), bytes.len() as i32, handle, ());
+        let buffer = pool.create_buffer(
+            0,
+            width as i32,
+            height as i32,
+            width as i32 * 4,
+            wl_shm::Format::Argb8888,
+            handle,
+            (),
+        );
+        pool.destroy();
+        Ok(buffer)
+    }
+
+    impl Dispatch<wl_registry::WlRegistry, ()> for State {
+        fn event(
+            state: &mut Self,
+            registry: &wl_registry::WlRegistry,
+            event: wl_registry::Event,
+            _: &(),
+            _: &Connection,
+            handle: &QueueHandle<Self>,
+        ) {
+            let wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } = event
+            else {
+                return;
+            };
+            if interface == WlCompositor::interface().name {
+                state.compositor = Some(registry.bind(name, version.min(4), handle, ()));
+            } else if interface == wl_shm::WlShm::interface().name {
+                state.shm = Some(registry.bind(name, 1, handle, ()));
+            } else if interface == ZwlrLayerShellV1::interface().name {
+                state.shell = Some(registry.bind(name, 1, handle, ()));
+            } else if interface == wl_output::WlOutput::interface().name {
+                // Version 4 sends the connector name
+                let proxy = registry.bind(name, version.min(4), handle, ());
+                state.outputs.push(Output { proxy, name: None });
+            }
+        }
+    }
+
+    impl Dispatch<wl_output::WlOutput, ()> for State {
+        fn event(
+            state: &mut Self,
+            proxy: &wl_output::WlOutput,
+            event: wl_output::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+            if let wl_output::Event::Name { name } = event {
+                if let Some(output) = state.outputs.iter_mut().find(|o| &o.proxy == proxy) {
+                    output.name = Some(name);
+                }
+            }
+        }
+    }
+
+    impl Dispatch<ZwlrLayerSurfaceV1, usize> for State {
+        fn event(
+            state: &mut Self,
+            layer: &ZwlrLayerSurfaceV1,
+            event: zwlr_layer_surface_v1::Event,
+            index: &usize,
+            _: &Connection,
+            handle: &QueueHandle<Self>,
+        ) {
+            let zwlr_layer_surface_v1::Event::Configure { serial, .. } = event else {
+                return;
+            };
+            layer.ack_configure(serial);
+            let (Some(shm), Some(surface)) = (&state.shm, state.surfaces.get_mut(*index)) else {
+                return;
+            };
+            if surface.buffer.is_none() {
+                match buffer(shm, surface, handle) {
+                    Ok(buffer) => surface.buffer = Some(buffer),
+                    Err(err) => {
+                        state.failed = Some(err.to_string());
+                        return;
+                    }
+                }
+            }
+            let (width, height) = surface.size;
+            surface.surface.attach(surface.buffer.as_ref(), 0, 0);
+            surface.surface.damage(0, 0, width as i32, height as i32);
+            surface.surface.commit();
+        }
+    }
+
+    delegate_noop!(State: WlCompositor);
+    delegate_noop!(State: WlRegion);
+    delegate_noop!(State: WlShmPool);
+    delegate_noop!(State: ZwlrLayerShellV1);
+    delegate_noop!(State: ignore wl_shm::WlShm);
+    delegate_noop!(State: ignore WlBuffer);
+    delegate_noop!(State: ignore WlSurface);
+}
+
+/// Override-redirect windows, spoken to in the X11 protocol itself over the local socket
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::{
+        env, fs,
+        io::{self, Read, Write},
+        os::unix::net::UnixStream,
+        path::PathBuf,
+        time::Instant,
+    };
+
+    use super::{Badge, Rect, DURATION, TICK};
+
+    const AUTH: &[u8] = b"MIT-MAGIC-COOKIE-1";
+
+    const CREATE_WINDOW: u8 = 1;
+    const MAP_WINDOW: u8 = 8;
+    const CREATE_GC: u8 = 55;
+    const POLY_FILL_RECTANGLE: u8 = 70;
+    const EXPOSE: u8 = 12;
+
+    /// Values of a window: its background, being left alone by the window manager and the
+    /// events it gets
+    const WINDOW_VALUES: u32 = 0x2 | 0x200 | 0x800;
+    const EXPOSURE: u32 = 0x8000;
+    /// Value of a graphics context: the colour it fills with
+    const FOREGROUND: u32 = 0x4;
+
+    /// What the server tells about itself on connecting
+    struct Screen {
+        id_base: u32,
+        root: u32,
+        white: u32,
+        black: u32,
+    }
+
+    pub fn draw(badges: &[Badge]) -> Result<(), String> {
+        draw_badges(badges).map_err(|err| format!("X11: {err}"))
+    }
+
+    fn draw_badges(badges: &[Badge]) -> io::Result<()> {
+        let display = env::var("DISPLAY").unwrap_or_default();
+        let number = (display.strip_prefix(':'))
+            .and_then(|rest| rest.split('.').next())
+            .filter(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or_else(|| io::Error::other(format!("only local displays, not {display:?}")))?;
+        let mut socket = UnixStream::connect(format!("/tmp/.X11-unix/X{number}"))?;
+        let screen = setup(&mut socket, number)?;
+
+        let mut requests = vec![];
+        let gc = screen.id_base;
+        let mut windows: Vec<(u32, Vec<Rect>)> = vec![];
+        for badge in badges {
+            let (size, rects) = badge.glyphs();
+            let Some((x, y)) = badge.position(size) else {
+                continue;
+            };
+            let window = screen.id_base + 1 + windows.len() as u32;
+            let values = [screen.black, 1, EXPOSURE];
+            request(&mut requests, CREATE_WINDOW, 0, |body| {
+                put(body, &window.to_le_bytes());
+                put(body, &screen.root.to_le_bytes());
+                put(body, &(x as i16).to_le_bytes());
+                put(body, &(y as i16).to_le_bytes());
+                put(body, &(size.0 as u16).to_le_bytes());
+                put(body, &(size.1 as u16).to_le_bytes());
+                // No border, input and output, the visual of the root
+                put(body, &[0, 0, 1, 0, 0, 0, 0, 0]);
+                put(body, &WINDOW_VALUES.to_le_bytes());
+                values
+                    .iter()
+                    .for_each(|value| put(body, &value.to_le_bytes()));
+            });
+            request(&mut requests, MAP_WINDOW, 0, |body| {
+                put(body, &window.to_le_bytes())
+            });
+            windows.push((window, rects));
+        }
+        let Some((first, _)) = windows.first() else {
+            return Ok(());
+        };
+        request(&mut requests, CREATE_GC, 0, |body| {
+            put(body, &gc.to_le_bytes());
+            put(body, &first.to_le_bytes());
+            put(body, &FOREGROUND.to_le_bytes());
+            put(body, &screen.white.to_le_bytes());
+        });
+        socket.write_all(&requests)?;
+
+        // Drawn on every exposure, the server keeps nothing of what is drawn
+        let deadline = Instant::now() + DURATION;
+        socket.set_read_timeout(Some(TICK))?;
+        let mut event = [0; 32];
+        while Instant::now() < deadline {
+            match socket.read_exact(&mut event) {
+                Ok(()) => {}
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(err) => return Err(err),
+            }
+            match event[0] & 0x7F {
+                0 => {
+                    let message = format!("request {} failed with error {}", event[10], event[1]);
+                    return Err(io::Error::other(message));
+                }
+                EXPOSE => {
+                    let exposed = u32::from_le_bytes(event[4..8].try_into().unwrap());
+                    let count = u16::from_le_bytes(event[16..18].try_into().unwrap());
+                    let Some((window, rects)) = windows.iter().find(|(id, _)| *id == exposed)
+                    else {
+                        continue;
+                    };
+                    if count == 0 {
+                        let mut requests = vec![];
+                        fill(&mut requests, *window, gc, rects);
+                        socket.write_all(&requests)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Closing the connection takes the windows down
+        Ok(())
+    }
+
+    /// Connects with the cookie of the display, if the authority file has one, and reads the
+    /// first screen.
+    fn setup(socket: &mut UnixStream, number: &str) -> io::Result<Screen> {
+        let cookie = cookie(number).unwrap_or_default();
+        let name: &[u8] = if cookie.is_empty() { &[] } else { AUTH };
+        let mut hello = vec![b'l', 0];
+        put(&mut hello, &11u16.to_le_bytes());
+        put(&mut hello, &0u16.to_le_bytes());
+        put(&mut hello, &(name.len() as u16).to_le_bytes());
+        put(&mut hello, &(cookie.len() as u16).to_le_bytes());
+        put(&mut hello, &[0, 0]);
+        pad(&mut hello, name);
+        pad(&mut hello, &cookie);
+        socket.write_all(&hello)?;
+
+        let mut header = [0; 8];
+        socket.read_exact(&mut header)?;
+        let length = u16::from_le_bytes([header[6], header[7]]) as usize * 4;
+        let mut data = vec![0; length];
+        socket.read_exact(&mut data)?;
+        if header[0] != 1 {
+            let reason = String::from_utf8_lossy(&data[..(header[1] as usize).min(length)]);
+            return Err(io::Error::other(format!("refused: {}", reason.trim())));
+        }
+        let u16_at = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]) as usize;
+        let u32_at = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+        let vendor = u16_at(16).div_ceil(4) * 4;
+        let screen = 32 + vendor + data[21] as usize * 8;
+        if data[20] == 0 || data.len() < screen + 16 {
+            return Err(io::Error::other("the server has no screen"));
+        }
+        Ok(Screen {
+            id_base: u32_at(4),
+            root: u32_at(screen),
+            white: u32_at(screen + 8),
+            black: u32_at(screen + 12),
+        })
+    }
+
+    /// The MIT-MAGIC-COOKIE-1 of display `number` in the X authority file.
+    fn cookie(number: &str) -> Option<Vec<u8>> {
+        let path = env::var_os("XAUTHORITY")
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".Xauthority")))?;
+        let file = fs::read(path).ok()?;
+        let mut rest = &file[..];
+        // Each entry is a family followed by the address, number, name and data
+        loop {
+            rest = rest.get(2..)?;
+            let _address = field(&mut rest)?;
+            let (display, name, data) = (field(&mut rest)?, field(&mut rest)?, field(&mut rest)?);
+            if display == number.as_bytes() && name == AUTH {
+                return Some(data.to_vec());
+            }
+        }
+    }
+
+    /// Takes a field of an authority entry, its length in big endian followed by the bytes.
+    fn field<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let length = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+        let value = rest.get(2..2 + length)?;
+        *rest = &rest[2 + length..];
+        Some(value)
+    }
+
+    fn fill(requests: &mut Vec<u8>, window: u32, gc: u32, rects: &[Rect]) {
+        request(requests, POLY_FILL_RECTANGLE, 0, |body| {
+            put(body, &window.to_le_bytes());
+            put(body, &gc.to_le_bytes());
+            for &(x, y, width, height) in rects {
+                put(body, &(x as i16).to_le_bytes());
+                put(body, &(y as i16).to_le_bytes());
+                put(body, &(width as u16).to_le_bytes());
+                put(body, &(height as u16).to_le_bytes());
+            }
+        });
+    }
+
+    /// Appends a request, its length counted in words including the header.
+    fn request(requests: &mut Vec<u8>, opcode: u8, detail: u8, body: impl FnOnce(&mut Vec<u8>)) {
+        let mut data = vec![];
+        body(&mut data);
+        requests.extend([opcode, detail]);
+        requests.extend((1 + data.len() as u16 / 4).to_le_bytes());
+        requests.extend(data);
+    }
+
+    fn put(to: &mut Vec<u8>, bytes: &[u8]) {
+        to.extend_from_slice(bytes);
+    }
+
+    /// Appends `bytes` padded to whole words.
+    fn pad(to: &mut Vec<u8>, bytes: &[u8]) {
+        to.extend_from_slice(bytes);
+        to.resize(to.len().div_ceil(4) * 4, 0);
+    }
+}
+
+/// Topmost popup windows that don't take the focus or clicks
+#[cfg(windows)]
+mod platform {
+    use std::{cell::RefCell, ptr, thread, time::Instant};
+
+    use windows_sys::Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
+        Graphics::Gdi::{
+            BeginPaint, EndPaint, FillRect, GetStockObject, BLACK_BRUSH, PAINTSTRUCT, WHITE_BRUSH,
+        },
+        System::LibraryLoader::GetModuleHandleW,
+        UI::WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, PeekMessageW,
+            RegisterClassW, SetLayeredWindowAttributes, ShowWindow, LWA_ALPHA, MSG, PM_REMOVE,
+            SW_SHOWNOACTIVATE, WM_PAINT, WNDCLASSW, WS_EX_LAYERED, WS_EX_NOACTIVATE,
+            WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP,
+        },
+    };
+
+    use super::{Badge, Rect, DURATION, TICK};
+
+    thread_local! {
+        /// The digits of each window of this thread, for painting it
+        static WINDOWS: RefCell<Vec<(HWND, Vec<RECT>)>> = const { RefCell::new(vec![]) };
+    }
+
+    pub fn draw(badges: &[Badge]) -> Result<(), String> {
+        let class: Vec<u16> = "ddc_bright overlay\0".encode_utf16().collect();
+        // SAFETY: the module of the process itself
+        let instance = unsafe { GetModuleHandleW(ptr::null()) };
+        let window_class = WNDCLASSW {
+            style: 0,
+            lpfnWndProc: Some(procedure),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hInstance: instance,
+            hIcon: ptr::null_mut(),
+            hCursor: ptr::null_mut(),
+            hbrBackground: ptr::null_mut(),
+            lpszMenuName: ptr::null(),
+            lpszClassName: class.as_ptr(),
+        };
+        // Fails once registered by an earlier call, which leaves it usable
+        unsafe { RegisterClassW(&window_class) };
+        for badge in badges {
+            let (size, rects) = badge.glyphs();
+            let Some((x, y)) = badge.position(size) else {
+                continue;
+            };
+            let style = WS_EX_TOPMOST
+                | WS_EX_TOOLWINDOW
+                | WS_EX_NOACTIVATE
+                | WS_EX_TRANSPARENT
+                | WS_EX_LAYERED;
+            // SAFETY: the class registered above, no parent, menu or creation data
+            let window = unsafe {
+                CreateWindowExW(
+                    style,
+                    class.as_ptr(),
+                    ptr::null(),
+                    WS_POPUP,
+                    x,
+                    y,
+                    size.0 as i32,
+                    size.1 as i32,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    instance,
+                    ptr::null(),
+                )
+            };
+            if window.is_null() {
+                close();
+                return Err(std::io::Error::last_os_error().to_string());
+            }
+            let rects = rects.iter().map(|&rect| rectangle(rect)).collect();
+            WINDOWS.with_borrow_mut(|windows| windows.push((window, rects)));
+            // SAFETY: the window just created
+            unsafe {
+                SetLayeredWindowAttributes(window, 0, 0xCC, LWA_ALPHA);
+                ShowWindow(window, SW_SHOWNOACTIVATE);
+            }
+        }
+        let deadline = Instant::now() + DURATION;
+        // SAFETY: a message is plain data, zeroed until PeekMessageW fills it
+        let mut message: MSG = unsafe { std::mem::zeroed() };
+        while Instant::now() < deadline {
+            while unsafe { PeekMessageW(&mut message, ptr::null_mut(), 0, 0, PM_REMOVE) } != 0 {
+                unsafe { DispatchMessageW(&message) };
+            }
+            thread::sleep(TICK);
+        }
+        close();
+        Ok(())
+    }
+
+    fn rectangle((x, y, width, height): Rect) -> RECT {
+        RECT {
+            left: x,
+            top: y,
+            right: x + width as i32,
+            bottom: y + height as i32,
+        }
+    }
+
+    fn close() {
+        for (window, _) in WINDOWS.take() {
+            // SAFETY: windows of this thread, destroyed once
+            unsafe { DestroyWindow(window) };
+        }
+    }
+
+    unsafe extern "system" fn procedure(
+        window: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if message != WM_PAINT {
+            return unsafe { DefWindowProcW(window, message, wparam, lparam) };
+        }
+        // SAFETY: painting the window this message is for, between BeginPaint and EndPaint
+        unsafe {
+            let mut paint: PAINTSTRUCT = std::mem::zeroed();
+            let context = BeginPaint(window, &mut paint);
+            FillRect(context, &paint.rcPaint, GetStockObject(BLACK_BRUSH));
+            WINDOWS.with_borrow(|windows| {
+                let digits = windows.iter().find(|(known, _)| *known == window);
+                for rect in digits.map_or(&[][..], |(_, rects)| rects) {
+                    FillRect(context, rect, GetStockObject(WHITE_BRUSH));
+                }
+            });
+            EndPaint(window, &paint);
+        }
+        0
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use super::Badge;
+
+    pub fn draw(_badges: &[Badge]) -> Result<(), String> {
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+use crate::display::MyDisplay;
+
+/// How failed writes to a display are reported
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorPolicy {
+    /// Commands exit with an error and the TUI marks the display, for scripts and signage that
+    /// have to know
+    #[default]
+    Strict,
+    /// Only a warning of commands or the status line of the TUI mentions it, commands succeed
+    Lenient,
+}
+
+/// The error policy of the displays it names
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct ErrorRule {
+    /// Model name, id or serial of the display, every display if unset
+    pub display: Option<String>,
+    pub policy: ErrorPolicy,
+}
+
+impl ErrorRule {
+    pub fn applies(&self, display: &MyDisplay) -> bool {
+        self.display
+            .as_deref()
+            .is_none_or(|query| display.matches(query))
+    }
+}
+
+/// The policy of the display: from a rule naming it, otherwise one for every display.
+pub fn resolve(rules: &[ErrorRule], display: &MyDisplay) -> ErrorPolicy {
+    let mut applying = rules.iter().filter(|rule| rule.applies(display));
+    let named = applying.clone().find(|rule| rule.display.is_some());
+    named
+        .or_else(|| applying.next())
+        .map_or_else(ErrorPolicy::default, |rule| rule.policy)
+}
@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::display::{Controller, Noticed, NoticedLog, Waker, WrappedDisplay};
+use crate::supervisor::CancelToken;
+
+/// Without input for this long the user counts as idle.
+const IDLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Delay between one of our writes and reading it back.
+const CONFIRM_DELAY: Duration = Duration::from_millis(500);
+
+/// How often the poller checks whether a poll is due.
+const TICK: Duration = Duration::from_millis(100);
+
+/// What the user and the app did recently, deciding how often to poll
+pub(crate) struct Activity {
+    pub last_input: Instant,
+    pub last_write: Option<Instant>,
+    pub focused: bool,
+    /// Id of the display the TUI shows, the only one read when watching
+    pub watched: Option<String>,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self {
+            last_input: Instant::now(),
+            last_write: None,
+            focused: true,
+            watched: None,
+        }
+    }
+}
+
+pub(crate) struct Poller {
+    pub displays: Arc<Mutex<Vec<WrappedDisplay>>>,
+    pub activity: Arc<Mutex<Activity>>,
+    pub queued: Arc<AtomicUsize>,
+    pub interval: Duration,
+    pub idle_interval: Duration,
+    /// Reads only the watched display instead of all of them
+    pub watched_only: bool,
+    pub waker: Waker,
+    pub noticed: NoticedLog,
+}
+
+impl Poller {
+    /// Reads back every control until cancelled, picking up changes made on the display itself.
+    pub fn run(self, token: CancelToken) {
+        let mut last_poll = Instant::now();
+        while !token.is_cancelled() {
+            thread::sleep(TICK);
+            if !self.due(last_poll) {
+                continue;
+            }
+            // Reading now could show a value that is about to be overwritten
+            if self.queued.load(Ordering::SeqCst) > 0 {
+                continue;
+            }
+            last_poll = Instant::now();
+            let mut displays = self.displays.lock().unwrap().clone();
+            if self.watched_only {
+                let watched = self.activity.lock().unwrap().watched.clone();
+                displays.retain(|display| watched.as_ref() == Some(&display.id));
+            }
+            let mut changed = false;
+            for display in displays {
+                // Only see whether a display turned off was turned back on at its button
+                if display.is_powered_off() {
+                    if !display.is_awake() {
+                        continue;
+                    }
+                    display.set_powered_off(false);
+                    changed = true;
+                }
+                for (control, controller) in &display.controls {
+                    let held = |controller: &Controller| controller.pending || controller.previewed;
+                    if held(&controller.read().unwrap()) {
+                        continue;
+                    }
+                    if let Ok(value) = display.get(*control) {
+                        // Checked again, the value may have been previewed while reading
+                        let mut controller = controller.write().unwrap();
+                        if !held(&controller) && controller.value != value {
+                            self.noticed.push(Noticed {
+                                display: display.clone(),
+                                control: *control,
+                                old: controller.value,
+                                value,
+                            });
+                            controller.value = value;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            if changed {
+                self.waker.wake();
+            }
+        }
+    }
+
+    fn due(&self, last_poll: Instant) -> bool {
+        let activity = self.activity.lock().unwrap();
+        // Confirm our own writes soon after they happened
+        if let Some(write) = activity.last_write {
+            if write > last_poll && write.elapsed() >= CONFIRM_DELAY {
+                return true;
+            }
+        }
+        let idle = !activity.focused || activity.last_input.elapsed() >= IDLE_AFTER;
+        let interval = if idle {
+            self.idle_interval
+        } else {
+            self.interval
+        };
+        last_poll.elapsed() >= interval
+    }
+}
@@ -0,0 +1,58 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// What a display's power mode, VCP 0xD6, says it is doing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum PowerMode {
+    On,
+    Standby,
+    Off,
+}
+
+impl PowerMode {
+    /// Decodes a 0xD6 value, `None` for values outside MCCS.
+    pub fn from_vcp(value: u16) -> Option<Self> {
+        match value & 0xFF {
+            0x01 => Some(PowerMode::On),
+            0x02 | 0x03 => Some(PowerMode::Standby),
+            0x04 | 0x05 => Some(PowerMode::Off),
+            _ => None,
+        }
+    }
+
+    /// Off is the power saving off, 0x04. The hard off behind the power button, 0x05, is never
+    /// written since DDC can't turn the display back on from it.
+    pub fn to_vcp(self) -> u16 {
+        match self {
+            PowerMode::On => 0x01,
+            PowerMode::Standby => 0x02,
+            PowerMode::Off => 0x04,
+        }
+    }
+}
+
+impl fmt::Display for PowerMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PowerMode::On => "on",
+            PowerMode::Standby => "standby",
+            PowerMode::Off => "off",
+        })
+    }
+}
+
+impl FromStr for PowerMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_ascii_lowercase().as_str() {
+            "on" => Ok(PowerMode::On),
+            "standby" => Ok(PowerMode::Standby),
+            "off" => Ok(PowerMode::Off),
+            _ => Err(format!("{text:?} is not on, standby or off")),
+        }
+    }
+}
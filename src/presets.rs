@@ -0,0 +1,412 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs, io,
+    path::PathBuf,
+};
+
+use ddc_bright::{
+    bulk::Applied,
+    display::{Control, DisplayManager, Flusher, WrappedDisplay},
+    power::PowerMode,
+    transaction::{Committed, Transaction},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::automation::{self, Automation, Policy};
+use crate::config::Config;
+use crate::history::{Log, Origin};
+use crate::scene::Amount;
+
+/// A named set of control values, e.g. "night"
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Preset {
+    pub name: String,
+    /// Controls saved into and applied from the preset, every control if unset
+    pub controls: Option<Vec<Control>>,
+    /// Writes right away and restores every display written so far if one fails, instead of
+    /// parking failed writes until the display answers
+    #[serde(default)]
+    pub rollback: bool,
+    /// Values for displays that have nothing saved in the preset, raw (`40`), percent (`"40%"`)
+    /// or dB (`"-20dB"`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<Control, Amount>,
+    /// Power mode every display is switched to after the values
+    pub power: Option<PowerMode>,
+    /// Automations turned on (`true`) or paused (`false`) while the preset is applied
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub automations: BTreeMap<Automation, bool>,
+    /// Shipped with ddc_bright rather than from the config
+    #[serde(skip)]
+    pub builtin: bool,
+}
+
+/// Why applying a preset with `rollback` was undone
+pub struct Aborted {
+    /// Display whose write failed
+    pub display: String,
+    pub error: String,
+    /// Whether every display already written was restored
+    pub restored: bool,
+}
+
+impl Preset {
+    pub fn includes(&self, control: Control) -> bool {
+        self.controls
+            .as_ref()
+            .is_none_or(|controls| controls.contains(&control))
+    }
+
+    /// The `set` values for a display, for the controls it has.
+    fn defaults(&self, display: &WrappedDisplay) -> HashMap<Control, u16> {
+        self.set
+            .iter()
+            .filter_map(|(control, amount)| {
+                let maximum = display.controls.get(control)?.read().unwrap().write_bound();
+                let value = amount.value(display, *control, maximum).ok()?;
+                Some((*control, value))
+            })
+            .collect()
+    }
+}
+
+/// Presets that work without any config, replaced by presets of the same name in it
+fn builtin() -> Vec<Preset> {
+    let preset = |name: &str, brightness: &str, contrast: &str| Preset {
+        name: name.to_string(),
+        controls: Some(vec![Control::Brightness, Control::Contrast]),
+        set: BTreeMap::from([
+            (Control::Brightness, Amount::Text(brightness.to_string())),
+            (Control::Contrast, Amount::Text(contrast.to_string())),
+        ]),
+        builtin: true,
+        ..Preset::default()
+    };
+    vec![
+        preset("reading", "40%", "50%"),
+        preset("movie", "80%", "75%"),
+        preset("presentation", "100%", "80%"),
+        Preset {
+            name: "off-all".to_string(),
+            controls: Some(vec![]),
+            power: Some(PowerMode::Off),
+            builtin: true,
+            ..Preset::default()
+        },
+    ]
+}
+
+/// Adds the built-in presets the config doesn't replace after its own, checking the `set`
+/// values of all of them.
+pub fn with_builtin(presets: &mut Vec<Preset>) -> Result<(), String> {
+    for preset in builtin() {
+        if !presets.iter().any(|other| other.name == preset.name) {
+            presets.push(preset);
+        }
+    }
+    for preset in presets.iter() {
+        for (control, amount) in &preset.set {
+            amount
+                .check(*control)
+                .map_err(|err| format!("preset {}: {err}", preset.name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Saved preset values by preset name and stable display id, kept in `presets.toml`
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Saved {
+    presets: BTreeMap<String, BTreeMap<String, HashMap<Control, u16>>>,
+    /// File the presets were loaded from and are stored to
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+impl Saved {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("presets.toml"))
+    }
+
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        match Self::path() {
+            Some(path) => Self::load_at(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Presets kept in a file of their own, like those of another user.
+    pub fn load_at(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let mut saved: Self = match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|err| format!("{}: {err}", path.display()))?
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => return Err(err.into()),
+        };
+        saved.file = Some(path);
+        Ok(saved)
+    }
+
+    fn store(&self) -> Result<(), Box<dyn Error>> {
+        let path = self.file.as_ref().ok_or("no config directory")?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Saves the current values of the preset's controls, replacing what these displays had saved.
+    pub fn save(
+        &mut self,
+        preset: &Preset,
+        displays: &[WrappedDisplay],
+    ) -> Result<(), Box<dyn Error>> {
+        let saved = self.presets.entry(preset.name.clone()).or_default();
+        for display in displays {
+            let values = display
+                .controls
+                .iter()
+                .filter(|(control, _)| preset.includes(**control))
+                .map(|(control, controller)| (*control, controller.read().unwrap().value))
+                .collect();
+            // Entries from before stable ids are replaced too
+            saved.remove(&display.id);
+            saved.insert(display.stable_id(), values);
+        }
+        self.store()
+    }
+
+    /// Values saved for a display, by its stable id or the plain id older files used, or the
+    /// `set` values of the preset if nothing is saved.
+    pub fn values(
+        &self,
+        preset: &Preset,
+        display: &WrappedDisplay,
+    ) -> Option<HashMap<Control, u16>> {
+        let saved = self.presets.get(&preset.name).and_then(|saved| {
+            saved
+                .get(&display.stable_id())
+                .or_else(|| saved.get(&display.id))
+        });
+        match saved {
+            Some(values) => Some(values.clone()),
+            None => {
+                let defaults = preset.defaults(display);
+                (!defaults.is_empty() || preset.power.is_some()).then_some(defaults)
+            }
+        }
+    }
+
+    /// Switches the display to the power mode of the preset, if it has one. Returns whether it
+    /// took it.
+    fn power(preset: &Preset, display: &WrappedDisplay) -> bool {
+        preset
+            .power
+            .is_none_or(|mode| display.set_power(mode).is_ok())
+    }
+
+    /// Queues the saved values, controls left out of the preset since saving are skipped.
+    /// Returns the displays that had values saved, for [`Saved::switch_power`].
+    pub fn queue(
+        &self,
+        preset: &Preset,
+        manager: &DisplayManager,
+        history: &mut Log,
+        origin: Origin,
+    ) -> Vec<WrappedDisplay> {
+        let mut queued = vec![];
+        for display in &manager.displays {
+            let Some(values) = self.values(preset, display) else {
+                continue;
+            };
+            for (control, value) in values {
+                if let Some(controller) = display.controls.get(&control) {
+                    if preset.includes(control) {
+                        manager.queue_set(display.clone(), controller.clone(), value);
+                        history.record(origin, display, control as u8, value);
+                    }
+                }
+            }
+            queued.push(display.clone());
+        }
+        queued
+    }
+
+    /// Switches the displays [`Saved::queue`] returned to the power mode of the preset, once
+    /// the values are written. Waits on the displays, so it may run on another thread. Returns
+    /// how many took it.
+    pub fn switch_power(preset: &Preset, displays: &[WrappedDisplay], flusher: &Flusher) -> usize {
+        if preset.power.is_some() {
+            // Turning off comes after the values, which a display that is off won't take
+            flusher.flush();
+        }
+        (displays.iter())
+            .filter(|display| Self::power(preset, display))
+            .count()
+    }
+
+    /// Writes the saved values right away through [`DisplayManager::apply_bulk`], spaced out so
+    /// no display drops one, then switches the power mode. `progress` gets each display as it is
+    /// done. Returns how many displays had values saved.
+    pub fn apply_spaced(
+        &self,
+        preset: &Preset,
+        manager: &DisplayManager,
+        history: &mut Log,
+        origin: Origin,
+        progress: impl FnMut(&Applied),
+    ) -> usize {
+        let mut values = vec![];
+        for display in &manager.displays {
+            let Some(saved) = self.values(preset, display) else {
+                continue;
+            };
+            let mut saved: Vec<(Control, u16)> = (saved.into_iter())
+                .filter(|(control, _)| {
+                    preset.includes(*control) && display.controls.contains_key(control)
+                })
+                .collect();
+            saved.sort();
+            // Recorded before writing, while the values they replace are still known
+            for (control, value) in &saved {
+                history.record(origin, display, *control as u8, *value);
+            }
+            values.push((display.clone(), saved));
+        }
+        let mut applied = 0;
+        for done in manager.apply_bulk(&values, progress) {
+            applied += Self::power(preset, &done.display) as usize;
+        }
+        applied
+    }
+
+    /// Writes the saved values directly, restoring the previous values of everything written if
+    /// one write fails. Returns how many displays had values saved.
+    pub fn apply_atomic(
+        &self,
+        preset: &Preset,
+        manager: &DisplayManager,
+        history: &mut Log,
+        origin: Origin,
+    ) -> Result<usize, Aborted> {
+        Self::commit(preset, self.stage(preset, manager), history, origin)
+    }
+
+    /// Stages the saved values in a transaction per display that had values saved, for
+    /// [`Saved::commit`].
+    pub fn stage(&self, preset: &Preset, manager: &DisplayManager) -> Vec<Transaction> {
+        let mut staged = vec![];
+        for display in &manager.displays {
+            let Some(values) = self.values(preset, display) else {
+                continue;
+            };
+            let Some(mut transaction) = manager.transaction(&display.id) else {
+                continue;
+            };
+            for (control, value) in values {
+                if preset.includes(control) {
+                    transaction = transaction.set(control, value);
+                }
+            }
+            staged.push(transaction);
+        }
+        staged
+    }
+
+    /// Commits the transactions [`Saved::stage`]d, each followed by the power mode, and takes
+    /// back every display written if one fails. Waits on the displays, so it may run on another
+    /// thread. Returns how many displays were written.
+    pub fn commit(
+        preset: &Preset,
+        staged: Vec<Transaction>,
+        history: &mut Log,
+        origin: Origin,
+    ) -> Result<usize, Aborted> {
+        let mut committed: Vec<Committed> = vec![];
+        for transaction in staged {
+            let display = transaction.display().clone();
+            match transaction.commit() {
+                Ok(done) => {
+                    for (control, value) in done.written() {
+                        history.record(origin, &display, *control as u8, *value);
+                    }
+                    // Only values are rolled back, a display that missed the power mode keeps
+                    // them
+                    Self::power(preset, &display);
+                    committed.push(done);
+                }
+                Err(aborted) => {
+                    // Earlier displays are taken back too, newest first
+                    let mut restored = aborted.restored;
+                    for done in committed.into_iter().rev() {
+                        let (display, previous) =
+                            (done.display().clone(), done.previous().to_vec());
+                        match done.rollback() {
+                            true => {
+                                for (control, value) in previous {
+                                    history.record(origin, &display, control as u8, value);
+                                }
+                            }
+                            false => restored = false,
+                        }
+                    }
+                    return Err(Aborted {
+                        display: display.label(),
+                        error: aborted.error.to_string(),
+                        restored,
+                    });
+                }
+            }
+        }
+        Ok(committed.len())
+    }
+}
+
+/// Applies the preset of that name from the config right away, waiting until it is written.
+pub fn apply_named(
+    config: &Config,
+    manager: &DisplayManager,
+    name: &str,
+    origin: Origin,
+) -> Result<(), Box<dyn Error>> {
+    let preset = config
+        .presets
+        .iter()
+        .find(|preset| preset.name == name)
+        .ok_or_else(|| format!("no preset named {name} in the config"))?;
+    let saved = Saved::load()?;
+    let mut history = Log::default();
+    let applied = if preset.rollback {
+        saved
+            .apply_atomic(preset, manager, &mut history, origin)
+            .map_err(|aborted| format!("{}: {}", aborted.display, aborted.error))?
+    } else {
+        let mut failed = false;
+        let applied = saved.apply_spaced(preset, manager, &mut history, origin, |done| {
+            let (label, written) = (done.display.label(), done.written.len());
+            let total = written + done.failed.len();
+            if total > 0 {
+                println!("{label}: {written} of {total} values written");
+            }
+            for (control, err) in &done.failed {
+                eprintln!("{label}: {}: {err}", control.get_name());
+            }
+            failed |= !done.failed.is_empty() && !done.display.is_lenient();
+        });
+        if failed {
+            return Err(format!("not every display took {name}").into());
+        }
+        applied
+    };
+    if applied == 0 {
+        return Err(format!("no values in {name} for these displays").into());
+    }
+    Policy::switch(preset)?;
+    match automation::describe(preset) {
+        Some(automations) => println!("Applied {name} to {applied} displays, {automations}"),
+        None => println!("Applied {name} to {applied} displays"),
+    }
+    Ok(())
+}
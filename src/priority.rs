@@ -0,0 +1,150 @@
+//! Scheduling of the thread writing changes, so a busy system doesn't keep the displays waiting
+
+use std::io;
+
+/// How the thread writing changes is scheduled, see [`crate::DisplayManager::set_worker_priority`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Priority {
+    /// Niceness from -20 to 19, lower is scheduled first, `None` to leave it
+    pub nice: Option<i32>,
+    /// Numbers of the CPUs the thread may run on, any if empty
+    pub cpus: Vec<usize>,
+}
+
+/// A thread as the system knows it, so it can be changed from another one
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ThreadId(#[allow(dead_code)] u64);
+
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "thread priorities aren't supported on this platform",
+    )
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::{io, mem};
+
+    use super::{Priority, ThreadId};
+
+    pub fn current() -> ThreadId {
+        ThreadId(unsafe { libc::gettid() } as u64)
+    }
+
+    pub fn apply(thread: ThreadId, priority: &Priority) -> io::Result<()> {
+        let tid = thread.0 as libc::pid_t;
+        if let Some(nice) = priority.nice {
+            // A thread id for PRIO_PROCESS changes just that thread
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS as _, tid as libc::id_t, nice) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if priority.cpus.is_empty() {
+            return Ok(());
+        }
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        for &cpu in &priority.cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("there is no CPU {cpu}"),
+                ));
+            }
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+        match unsafe { libc::sched_setaffinity(tid, mem::size_of_val(&set), &set) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+
+    use windows_sys::Win32::{
+        Foundation::CloseHandle,
+        System::Threading::{
+            GetCurrentThreadId, OpenThread, SetThreadAffinityMask, SetThreadPriority,
+            THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_BELOW_NORMAL, THREAD_PRIORITY_HIGHEST,
+            THREAD_PRIORITY_LOWEST, THREAD_PRIORITY_NORMAL, THREAD_QUERY_INFORMATION,
+            THREAD_SET_INFORMATION,
+        },
+    };
+
+    use super::{Priority, ThreadId};
+
+    pub fn current() -> ThreadId {
+        ThreadId(unsafe { GetCurrentThreadId() } as u64)
+    }
+
+    pub fn apply(thread: ThreadId, priority: &Priority) -> io::Result<()> {
+        let mut mask = 0usize;
+        for &cpu in &priority.cpus {
+            if cpu >= usize::BITS as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("there is no CPU {cpu}"),
+                ));
+            }
+            mask |= 1 << cpu;
+        }
+        let access = THREAD_SET_INFORMATION | THREAD_QUERY_INFORMATION;
+        let handle = unsafe { OpenThread(access, 0, thread.0 as u32) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        // Windows has a few levels instead of niceness, spread over its range
+        let level = priority.nice.map(|nice| match nice {
+            ..=-10 => THREAD_PRIORITY_HIGHEST,
+            -9..=-1 => THREAD_PRIORITY_ABOVE_NORMAL,
+            0 => THREAD_PRIORITY_NORMAL,
+            1..=9 => THREAD_PRIORITY_BELOW_NORMAL,
+            _ => THREAD_PRIORITY_LOWEST,
+        });
+        let mut result = Ok(());
+        if let Some(level) = level {
+            if unsafe { SetThreadPriority(handle, level) } == 0 {
+                result = Err(io::Error::last_os_error());
+            }
+        }
+        if result.is_ok() && mask != 0 && unsafe { SetThreadAffinityMask(handle, mask) } == 0 {
+            result = Err(io::Error::last_os_error());
+        }
+        unsafe { CloseHandle(handle) };
+        result
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use std::io;
+
+    use super::{unsupported, Priority, ThreadId};
+
+    pub fn current() -> ThreadId {
+        ThreadId(0)
+    }
+
+    pub fn apply(_thread: ThreadId, priority: &Priority) -> io::Result<()> {
+        match priority == &Priority::default() {
+            true => Ok(()),
+            false => Err(unsupported()),
+        }
+    }
+}
+
+/// The thread calling it.
+pub(crate) fn current() -> ThreadId {
+    platform::current()
+}
+
+/// Schedules `thread` as `priority` says, leaving what it doesn't set.
+pub(crate) fn apply(thread: Option<ThreadId>, priority: &Priority) -> io::Result<()> {
+    match thread {
+        Some(thread) => platform::apply(thread, priority),
+        None => Err(unsupported()),
+    }
+}
@@ -0,0 +1,90 @@
+use std::error::Error;
+
+use ddc_bright::display::{Control, MyDisplay, WrappedDisplay, ALL_CONTROLS};
+
+use crate::quirks::Cache;
+
+/// What a control the display doesn't advertise did when tried
+enum Found {
+    /// Took a write and read it back, or answered for a control that isn't written
+    Works(String),
+    /// Answered but ignored the write
+    ReadsOnly,
+    NoAnswer,
+}
+
+/// Tries the known controls each display leaves out of its capabilities and lists those that
+/// work, storing them in the quirk cache if `save` is set, so refreshes keep them from then on.
+pub fn run(displays: &[WrappedDisplay], save: bool) -> Result<(), Box<dyn Error>> {
+    let mut cache = Cache::load()?;
+    for display in displays {
+        println!("{}", display.label());
+        let Some(capabilities) = &display.advertised else {
+            println!("  skipped, no capabilities, every control that answers is used already");
+            continue;
+        };
+        let mut working = vec![];
+        for control in ALL_CONTROLS {
+            if capabilities.feature(control as u8).is_some() {
+                continue;
+            }
+            let outcome = match try_control(display, control) {
+                Found::Works(detail) => {
+                    working.push(control);
+                    format!("works, {detail}")
+                }
+                Found::ReadsOnly => "answers but ignores writes".to_string(),
+                Found::NoAnswer => continue,
+            };
+            println!(
+                "  {} (0x{:02X}): {outcome}",
+                control.get_name(),
+                control as u8
+            );
+        }
+        match working.is_empty() {
+            true => {
+                println!("  nothing beyond what is advertised");
+                cache.unadvertised.remove(&display.stable_id());
+            }
+            false => {
+                cache.unadvertised.insert(display.stable_id(), working);
+            }
+        }
+    }
+    if save {
+        let path = cache.store()?;
+        println!("Saved to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Reads the control, and for continuous ones writes a neighbouring value and reads it back
+/// before restoring it. Other controls are only read, writing an input or power mode even
+/// unchanged blanks some displays for a moment.
+fn try_control(display: &MyDisplay, control: Control) -> Found {
+    let Ok((value, maximum)) = display.get_raw(control as u8) else {
+        return Found::NoAnswer;
+    };
+    if !control.is_continuous() {
+        return Found::Works(format!(
+            "reads {}",
+            display.describe(control, control.mask(value))
+        ));
+    }
+    if maximum == 0 {
+        return Found::ReadsOnly;
+    }
+    let other = match value < maximum {
+        true => value + 1,
+        false => value - 1,
+    };
+    let took = display
+        .set_raw(control as u8, other)
+        .is_ok_and(|(read, _)| read == other);
+    let _ = display.set_vcp(control as u8, value);
+    match took {
+        true => Found::Works(format!("{value}/{maximum}")),
+        false => Found::ReadsOnly,
+    }
+}
@@ -0,0 +1,107 @@
+use std::{collections::BTreeMap, error::Error, fs, io, path::PathBuf};
+
+use ddc_bright::display::{Control, MyDisplay, Tuning};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// A read-only VCP code worth showing for matching displays, like usage time or temperature
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Readout {
+    /// EDID manufacturer id (e.g. "DEL"), any manufacturer if unset
+    pub manufacturer: Option<String>,
+    /// Model name, any model if unset
+    pub model: Option<String>,
+    pub code: u8,
+    pub name: String,
+    #[serde(default)]
+    pub unit: String,
+}
+
+impl Readout {
+    fn matches(&self, display: &MyDisplay) -> bool {
+        let manufacturer = self.manufacturer.is_none()
+            || self.manufacturer.as_deref() == display.manufacturer.as_deref();
+        let model = self.model.is_none() || self.model.as_deref() == Some(display.name.as_str());
+        manufacturer && model
+    }
+}
+
+/// Known readouts by EDID manufacturer id, code, name and unit: only the usage time of MCCS, which
+/// every display reports the same way. Vendor codes for temperatures or error counters vary by
+/// model and aren't documented, so they come from the config.
+const KNOWN: [(Option<&str>, u8, &str, &str); 1] = [(None, 0xC0, "Usage time", "h")];
+
+fn builtin() -> impl Iterator<Item = Readout> {
+    KNOWN
+        .iter()
+        .map(|&(manufacturer, code, name, unit)| Readout {
+            manufacturer: manufacturer.map(str::to_string),
+            model: None,
+            code,
+            name: name.to_string(),
+            unit: unit.to_string(),
+        })
+}
+
+/// Readouts known for a display, `configured` ones first so they can shadow built-in codes.
+pub fn readouts(configured: &[Readout], display: &MyDisplay) -> Vec<Readout> {
+    let mut readouts: Vec<Readout> = vec![];
+    for readout in configured.iter().cloned().chain(builtin()) {
+        if readout.matches(display) && !readouts.iter().any(|other| other.code == readout.code) {
+            readouts.push(readout);
+        }
+    }
+    readouts
+}
+
+/// Reads the readouts known for a display, `None` for the codes it doesn't answer. Slow, a
+/// read per code.
+pub fn read(configured: &[Readout], display: &MyDisplay) -> Vec<(Readout, Option<u16>)> {
+    readouts(configured, display)
+        .into_iter()
+        .map(|readout| {
+            let value = display.get_vcp(readout.code).ok();
+            (readout, value)
+        })
+        .collect()
+}
+
+/// What was learned about displays by their id, kept in `quirks.toml`: the tuning written by
+/// `bench --save`, the controls found by `probe --save` and the defaults seen after a factory
+/// reset in the TUI
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Cache {
+    pub displays: BTreeMap<String, Tuning>,
+    /// Controls that work though the capabilities leave them out, by stable id
+    pub unadvertised: BTreeMap<String, Vec<Control>>,
+    /// Factory defaults by display id, as read back after restoring them
+    pub defaults: BTreeMap<String, BTreeMap<Control, u16>>,
+}
+
+impl Cache {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("quirks.toml"))
+    }
+
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|err| format!("{}: {err}", path.display()).into())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn store(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let path = Self::path().ok_or("no config directory")?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, toml::to_string(self)?)?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,111 @@
+use std::{collections::HashMap, error::Error, process};
+
+use ddc_bright::{
+    display::{DisplayManager, MyDisplay},
+    orientation::Orientation,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrientationConfig {
+    /// Rotates the desktop whenever the orientation of a display is read or set
+    pub sync_desktop: bool,
+    /// Run for each display, `{output}` and `{rotation}` (normal, left, inverted or right) are
+    /// filled in
+    pub command: String,
+    /// Output name of the desktop, e.g. "DP-1", by model name or id of the display
+    pub outputs: HashMap<String, String>,
+}
+
+impl Default for OrientationConfig {
+    fn default() -> Self {
+        Self {
+            sync_desktop: false,
+            command: "xrandr --output {output} --rotate {rotation}".to_string(),
+            outputs: HashMap::new(),
+        }
+    }
+}
+
+impl OrientationConfig {
+    fn output(&self, display: &MyDisplay) -> Option<&str> {
+        self.outputs
+            .iter()
+            .find(|(query, _)| display.matches(query))
+            .map(|(_, output)| output.as_str())
+    }
+}
+
+/// Shows or sets the orientation, rotating the desktop along if configured. Without an
+/// orientation, every display (or the given one) is read, so after pivoting a display one call
+/// turns the desktop to match.
+pub fn run(
+    manager: &DisplayManager,
+    config: &OrientationConfig,
+    display: Option<&str>,
+    orientation: Option<Orientation>,
+) -> Result<(), Box<dyn Error>> {
+    let displays: Vec<_> = manager
+        .displays
+        .iter()
+        .filter(|candidate| display.is_none_or(|query| candidate.matches(query)))
+        .collect();
+    if displays.is_empty() {
+        return Err(match display {
+            Some(query) => format!("no display matches {query}").into(),
+            None => "no displays".into(),
+        });
+    }
+
+    for display in displays {
+        let current = match orientation {
+            Some(orientation) => {
+                if let Err(err) = display.set_orientation(orientation) {
+                    eprintln!("{}: display kept its orientation: {err}", display.label());
+                }
+                Some(orientation)
+            }
+            None => match display.orientation() {
+                Ok(current) => current,
+                Err(err) => {
+                    println!("{}: unsupported ({err})", display.label());
+                    continue;
+                }
+            },
+        };
+        let Some(current) = current else {
+            println!("{}: unknown", display.label());
+            continue;
+        };
+        println!("{}: {current}", display.label());
+        if config.sync_desktop {
+            rotate_desktop(config, display, current)?;
+        }
+    }
+    Ok(())
+}
+
+fn rotate_desktop(
+    config: &OrientationConfig,
+    display: &MyDisplay,
+    orientation: Orientation,
+) -> Result<(), Box<dyn Error>> {
+    let Some(output) = config.output(display) else {
+        eprintln!(
+            "{}: no output in [orientation.outputs], desktop not rotated",
+            display.label()
+        );
+        return Ok(());
+    };
+    let mut words = config.command.split_whitespace().map(|word| {
+        word.replace("{output}", output)
+            .replace("{rotation}", orientation.rotation())
+    });
+    let program = words.next().ok_or("orientation.command is empty")?;
+    let status = process::Command::new(&program).args(words).status()?;
+    if !status.success() {
+        return Err(format!("{program} failed with {status}").into());
+    }
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//! Scenes: steps read from a TOML file and run one after another by `ddc_bright run`
+
+use std::{
+    collections::BTreeMap, error::Error, fmt, fs, path::Path, process, thread, time::Duration,
+};
+
+use ddc_bright::{
+    display::{Control, DisplayManager, Fade, MyDisplay},
+    units::Level,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::Trigger,
+    cli,
+    config::Config,
+    history::{Log, Origin},
+    presets,
+};
+
+/// A sequence of steps, `[[step]]` tables in the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    #[serde(rename = "step", default)]
+    pub steps: Vec<Step>,
+}
+
+/// One step of a scene. Whatever it lists is done in the order of the fields: preset, set, run
+/// and then wait.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// Number from `list`, model name or id the values in `set` go to, every display if unset
+    pub display: Option<String>,
+    /// Name of a preset to apply
+    pub preset: Option<String>,
+    /// Values to write, raw (`40`), percent (`"40%"`) or dB (`"-20dB"`)
+    #[serde(default)]
+    pub set: BTreeMap<Control, Amount>,
+    /// Milliseconds the values in `set` fade over, otherwise [fade] applies. Ignored with
+    /// reduced motion
+    pub fade_ms: Option<u64>,
+    /// Command to run, split at whitespace without a shell. The scene stops if it fails
+    pub run: Option<String>,
+    /// Milliseconds to wait before the next step
+    pub wait_ms: Option<u64>,
+}
+
+/// A value in `set`, a number or a string like on the command line
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Amount {
+    Raw(u16),
+    Text(String),
+}
+
+impl Amount {
+    pub fn level(&self) -> Result<Level, String> {
+        match self {
+            Amount::Raw(value) => Ok(Level::Raw(*value)),
+            Amount::Text(text) => text.parse(),
+        }
+    }
+
+    /// Checks the amount is a level, or a name for a control whose values have names.
+    pub fn check(&self, control: Control) -> Result<(), String> {
+        match self.level() {
+            Err(_) if !control.is_continuous() => Ok(()),
+            level => level.map(drop),
+        }
+    }
+
+    /// The value written to the control of the display, names looked up among its values.
+    pub fn value(
+        &self,
+        display: &MyDisplay,
+        control: Control,
+        maximum: u16,
+    ) -> Result<u16, String> {
+        match (self, self.level()) {
+            (_, Ok(level)) => Ok(display.resolve(control, level, maximum)),
+            (Amount::Text(name), Err(_)) if !control.is_continuous() => display
+                .value_named(control, name)
+                .ok_or_else(|| format!("{}: no {} named {name}", display.label(), control.key())),
+            (_, Err(err)) => Err(err),
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Amount::Raw(value) => write!(f, "{value}"),
+            Amount::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let scene: Scene =
+            toml::from_str(&text).map_err(|err| format!("{}: {err}", path.display()))?;
+        for (i, step) in scene.steps.iter().enumerate() {
+            for (control, amount) in &step.set {
+                amount
+                    .check(*control)
+                    .map_err(|err| format!("step {}: {err}", i + 1))?;
+            }
+        }
+        Ok(scene)
+    }
+
+    /// Runs every step, stopping at the first one that fails.
+    pub fn run(&self, config: &Config, manager: &mut DisplayManager) -> Result<(), Box<dyn Error>> {
+        for (i, step) in self.steps.iter().enumerate() {
+            step.run(config, manager)
+                .map_err(|err| format!("step {}: {err}", i + 1))?;
+        }
+        Ok(())
+    }
+}
+
+impl Step {
+    fn run(&self, config: &Config, manager: &mut DisplayManager) -> Result<(), Box<dyn Error>> {
+        if let Some(name) = &self.preset {
+            presets::apply_named(config, manager, name, Origin::Scene)?;
+        }
+        if !self.set.is_empty() {
+            self.set(config, manager)?;
+        }
+        if let Some(command) = &self.run {
+            let mut words = command.split_whitespace();
+            let program = words.next().ok_or("run is empty")?;
+            let status = process::Command::new(program).args(words).status()?;
+            if !status.success() {
+                return Err(format!("{program} failed with {status}").into());
+            }
+        }
+        if let Some(ms) = self.wait_ms {
+            thread::sleep(Duration::from_millis(ms));
+        }
+        Ok(())
+    }
+
+    /// Queues the values and waits until they are written, fading over `fade_ms` if set.
+    fn set(&self, config: &Config, manager: &mut DisplayManager) -> Result<(), Box<dyn Error>> {
+        let displays = cli::select(&manager.displays, self.display.as_deref())?;
+        let configured = manager.fade;
+        if let Some(ms) = self.fade_ms.filter(|_| !config.reduced_motion()) {
+            manager.fade = Fade {
+                duration: Duration::from_millis(ms),
+                threshold: 0,
+            };
+        }
+        let mut history = Log::default();
+        let mut set = vec![];
+        for display in &displays {
+            for (control, amount) in &self.set {
+                let Some(controller) = display.controls.get(control) else {
+                    continue;
+                };
+                let maximum = controller.read().unwrap().write_bound();
+                let value = amount.value(display, *control, maximum)?;
+                manager.queue_set(display.clone(), controller.clone(), value);
+                set.push((display, *control, value));
+            }
+        }
+        manager.flush();
+        manager.fade = configured;
+        if set.is_empty() {
+            return Err("no selected display has any of the controls".into());
+        }
+
+        let mut failed = false;
+        for (display, control, value) in set {
+            if let Some(err) = display.write_error() {
+                eprintln!("{}: {}: {err}", display.label(), control.get_name());
+                failed |= !display.is_lenient();
+                continue;
+            }
+            history.record(Origin::Scene, display, control as u8, value);
+            if control == Control::InputSource {
+                cli::switch_audio(&config.audio, display, Trigger::Input(value));
+            }
+        }
+        if failed {
+            return Err("not every value could be written".into());
+        }
+        Ok(())
+    }
+}
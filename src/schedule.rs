@@ -0,0 +1,485 @@
+use std::{collections::BTreeMap, error::Error, fmt, io::Write};
+
+use chrono::{
+    DateTime, Datelike, Days as DayCount, Duration, Local, NaiveDate, NaiveTime, TimeZone, Weekday,
+};
+use ddc_bright::display::{Control, Controller, DisplayManager, WrappedDisplay};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    cli,
+    config::Config,
+    events,
+    history::{Log, Origin},
+    presets::Saved,
+    scene::Amount,
+    sun::{self, Location},
+};
+
+/// Applies a preset or values at a time of day, on some days of the week
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Rule {
+    /// Every day if empty
+    #[serde(default)]
+    pub days: Vec<Days>,
+    /// Local time, e.g. "08:00", or "sunrise" and "sunset" with an optional offset like
+    /// "sunset-00:30"
+    pub time: TimeOfDay,
+    pub preset: Option<String>,
+    /// Number from `list`, model name or id the values in `set` go to, every display if unset
+    pub display: Option<String>,
+    /// Values to write after the preset, raw (`40`) or percent (`"40%"`)
+    #[serde(default)]
+    pub set: BTreeMap<Control, Amount>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Days {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+    /// Monday to Friday
+    Weekdays,
+    /// Saturday and Sunday
+    Weekends,
+}
+
+impl Days {
+    fn contains(self, day: Weekday) -> bool {
+        let weekend = matches!(day, Weekday::Sat | Weekday::Sun);
+        match self {
+            Days::Weekdays => !weekend,
+            Days::Weekends => weekend,
+            Days::Mon => day == Weekday::Mon,
+            Days::Tue => day == Weekday::Tue,
+            Days::Wed => day == Weekday::Wed,
+            Days::Thu => day == Weekday::Thu,
+            Days::Fri => day == Weekday::Fri,
+            Days::Sat => day == Weekday::Sat,
+            Days::Sun => day == Weekday::Sun,
+        }
+    }
+}
+
+impl fmt::Display for Days {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Days::Mon => "mon",
+            Days::Tue => "tue",
+            Days::Wed => "wed",
+            Days::Thu => "thu",
+            Days::Fri => "fri",
+            Days::Sat => "sat",
+            Days::Sun => "sun",
+            Days::Weekdays => "weekdays",
+            Days::Weekends => "weekends",
+        })
+    }
+}
+
+/// A time of day written as "HH:MM", or relative to the sun like "sunrise+01:00"
+#[derive(Clone, Copy)]
+pub enum TimeOfDay {
+    At(NaiveTime),
+    /// Minutes after sunrise, before it if negative
+    Sunrise(i64),
+    /// Minutes after sunset, before it if negative
+    Sunset(i64),
+}
+
+impl TimeOfDay {
+    /// When on `date` this is, `None` for times skipped by a DST change and sun times without a
+    /// location or on days the sun doesn't rise or set.
+    fn on(self, date: NaiveDate, location: Option<Location>) -> Option<DateTime<Local>> {
+        let (sun, offset) = match self {
+            // A time skipped by a DST change doesn't trigger that day
+            TimeOfDay::At(time) => {
+                return Local.from_local_datetime(&date.and_time(time)).earliest()
+            }
+            TimeOfDay::Sunrise(offset) => (sun::times(date, location?)?.0, offset),
+            TimeOfDay::Sunset(offset) => (sun::times(date, location?)?.1, offset),
+        };
+        Some((sun + Duration::minutes(offset)).with_timezone(&Local))
+    }
+
+    pub fn follows_sun(self) -> bool {
+        !matches!(self, TimeOfDay::At(_))
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (event, offset) = match self {
+            TimeOfDay::At(time) => return write!(f, "{}", time.format("%H:%M")),
+            TimeOfDay::Sunrise(offset) => ("sunrise", *offset),
+            TimeOfDay::Sunset(offset) => ("sunset", *offset),
+        };
+        f.write_str(event)?;
+        if offset != 0 {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let offset = offset.abs();
+            write!(f, "{sign}{:02}:{:02}", offset / 60, offset % 60)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for TimeOfDay {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let invalid = || {
+            de::Error::custom(format!(
+                "{text:?} is not a time like 08:00, sunrise or sunset-00:30"
+            ))
+        };
+        let (event, offset): (fn(i64) -> TimeOfDay, &str) =
+            if let Some(offset) = text.strip_prefix("sunrise") {
+                (TimeOfDay::Sunrise, offset)
+            } else if let Some(offset) = text.strip_prefix("sunset") {
+                (TimeOfDay::Sunset, offset)
+            } else {
+                return NaiveTime::parse_from_str(&text, "%H:%M")
+                    .map(TimeOfDay::At)
+                    .map_err(|_| invalid());
+            };
+        if offset.is_empty() {
+            return Ok(event(0));
+        }
+        let (sign, offset) = match offset.split_at(1) {
+            ("+", offset) => (1, offset),
+            ("-", offset) => (-1, offset),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = offset.split_once(':').ok_or_else(invalid)?;
+        let hours: i64 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i64 = minutes.parse().map_err(|_| invalid())?;
+        if minutes >= 60 {
+            return Err(invalid());
+        }
+        Ok(event(sign * (hours * 60 + minutes)))
+    }
+}
+
+impl Rule {
+    fn applies_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.iter().any(|days| days.contains(day))
+    }
+
+    /// The first time the rule triggers after `after`. Sun times need the location.
+    pub fn next(
+        &self,
+        after: DateTime<Local>,
+        location: Option<Location>,
+    ) -> Option<DateTime<Local>> {
+        (0..=7).find_map(|offset| {
+            let date = after.date_naive().checked_add_days(DayCount::new(offset))?;
+            if !self.applies_on(date.weekday()) {
+                return None;
+            }
+            let at = self.time.on(date, location)?;
+            (at > after).then_some(at)
+        })
+    }
+
+    /// What the rule applies, for listing.
+    pub fn describe(&self) -> String {
+        let mut parts: Vec<String> = self.preset.iter().cloned().collect();
+        for (control, amount) in &self.set {
+            parts.push(format!("{} {amount}", control.key()));
+        }
+        match parts.is_empty() {
+            true => "nothing".to_string(),
+            false => parts.join(", "),
+        }
+    }
+
+    /// What the rule changed, for its notification.
+    pub fn notification(&self) -> String {
+        let display = self.display.as_deref().unwrap_or("Every display");
+        format!("{display}: {}", self.describe())
+    }
+
+    /// Logs that the rule triggered.
+    pub fn record(&self) {
+        let detail = format!("{} triggered: {}", self.time, self.describe());
+        events::record("schedule", self.display.as_deref(), detail);
+    }
+
+    /// Queues the values in `set` on the displays the rule names, returns how many were queued.
+    pub fn queue_values(
+        &self,
+        manager: &DisplayManager,
+        history: &mut Log,
+    ) -> Result<usize, String> {
+        let mut queued = 0;
+        for display in cli::select(&manager.displays, self.display.as_deref())? {
+            for (control, amount) in &self.set {
+                let Some(controller) = display.controls.get(control) else {
+                    continue;
+                };
+                let maximum = controller.read().unwrap().write_bound();
+                let value = amount.value(&display, *control, maximum)?;
+                manager.queue_set(display.clone(), controller.clone(), value);
+                history.record(Origin::Schedule, &display, *control as u8, value);
+                queued += 1;
+            }
+        }
+        Ok(queued)
+    }
+
+    /// Which days the rule applies on, for listing.
+    pub fn describe_days(&self) -> String {
+        if self.days.is_empty() {
+            return "every day".to_string();
+        }
+        let days: Vec<String> = self.days.iter().map(Days::to_string).collect();
+        days.join(", ")
+    }
+}
+
+/// The rule triggering next after `after`, with when it does.
+#[cfg(feature = "tui")]
+pub fn next(config: &Config, after: DateTime<Local>) -> Option<(DateTime<Local>, &Rule)> {
+    config
+        .schedule
+        .iter()
+        .filter_map(|rule| Some((rule.next(after, config.location)?, rule)))
+        .min_by_key(|(at, _)| *at)
+}
+
+/// Rules that triggered after `since` up to and including `now`, in order.
+pub fn due(config: &Config, since: DateTime<Local>, now: DateTime<Local>) -> Vec<&Rule> {
+    let mut due: Vec<(DateTime<Local>, &Rule)> = config
+        .schedule
+        .iter()
+        .filter_map(|rule| Some((rule.next(since, config.location)?, rule)))
+        .filter(|(at, _)| *at <= now)
+        .collect();
+    due.sort_by_key(|(at, _)| *at);
+    due.into_iter().map(|(_, rule)| rule).collect()
+}
+
+/// Prints every rule with the next time it triggers.
+pub fn list(config: &Config) {
+    if config.schedule.is_empty() {
+        println!("No [[schedule]] rules configured");
+        return;
+    }
+    let now = Local::now();
+    for rule in &config.schedule {
+        let next = match rule.next(now, config.location) {
+            Some(at) => at.format("%a %Y-%m-%d %H:%M").to_string(),
+            None if rule.time.follows_sun() && config.location.is_none() => {
+                "never, [location] is not set".to_string()
+            }
+            None => "never".to_string(),
+        };
+        let missing = match &rule.preset {
+            Some(name) if !config.presets.iter().any(|preset| &preset.name == name) => {
+                " (no such preset)"
+            }
+            _ => "",
+        };
+        println!(
+            "{} {}: {}{missing}, next {next}",
+            rule.describe_days(),
+            rule.time,
+            rule.describe()
+        );
+    }
+}
+
+/// Prints what the rules triggering from `from`, now if unset, up to the next `to` would change
+/// on the displays, starting from their current values. Nothing is written.
+pub fn simulate(
+    config: &Config,
+    manager: &DisplayManager,
+    from: Option<NaiveTime>,
+    to: NaiveTime,
+    out: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let now = Local::now();
+    let from = match from {
+        Some(time) => TimeOfDay::At(time)
+            .on(now.date_naive(), None)
+            .ok_or("the start is skipped by a DST change today")?,
+        None => now,
+    };
+    let until = TimeOfDay::At(to);
+    let to = match until.on(from.date_naive(), None) {
+        Some(at) if at >= from => Some(at),
+        _ => from
+            .date_naive()
+            .succ_opt()
+            .and_then(|date| until.on(date, None)),
+    }
+    .ok_or("the end is skipped by a DST change")?;
+
+    // Rules triggering right at the start count too
+    let mut triggers = vec![];
+    for rule in &config.schedule {
+        let mut after = from - Duration::seconds(1);
+        while let Some(at) = rule.next(after, config.location).filter(|at| *at <= to) {
+            triggers.push((at, rule));
+            after = at;
+        }
+    }
+    triggers.sort_by_key(|(at, _)| *at);
+    let period = format!("{} to {}", from.format("%H:%M"), to.format("%H:%M"));
+    if triggers.is_empty() {
+        writeln!(out, "No rules trigger from {period}")?;
+        return Ok(());
+    }
+    writeln!(out, "From {period}, starting at the current values:")?;
+
+    let saved = Saved::load()?;
+    let mut values: Vec<BTreeMap<Control, Controller>> = manager
+        .displays
+        .iter()
+        .map(|display| {
+            display
+                .controls
+                .iter()
+                .map(|(control, controller)| (*control, *controller.read().unwrap()))
+                .collect()
+        })
+        .collect();
+    for (at, rule) in triggers {
+        let mut heading = at.format("%a %H:%M").to_string();
+        if rule.time.follows_sun() {
+            heading += &format!(" ({})", rule.time);
+        }
+        writeln!(out, "{heading}: {}", rule.describe())?;
+        let preset = match &rule.preset {
+            Some(name) => match config.presets.iter().find(|preset| &preset.name == name) {
+                Some(preset) => Some(preset),
+                None => {
+                    writeln!(out, "  no preset named {name} in the config")?;
+                    None
+                }
+            },
+            None => None,
+        };
+        let targets = cli::select(&manager.displays, rule.display.as_deref())?;
+        for (display, values) in manager.displays.iter().zip(&mut values) {
+            // The preset first, then the values of the rule
+            let mut wanted: Vec<(Control, u16)> = preset
+                .and_then(|preset| {
+                    let values = saved.values(preset, display)?;
+                    Some(values.into_iter().filter(|(c, _)| preset.includes(*c)))
+                })
+                .into_iter()
+                .flatten()
+                .collect();
+            wanted.sort_by_key(|(control, _)| *control);
+            if targets.iter().any(|target| target.id == display.id) {
+                for (control, amount) in &rule.set {
+                    let Some(controller) = values.get(control) else {
+                        continue;
+                    };
+                    let maximum = controller.write_bound();
+                    wanted.push((*control, amount.value(display, *control, maximum)?));
+                }
+            }
+
+            let mut changes = vec![];
+            for (control, value) in wanted {
+                let Some(controller) = values.get_mut(&control) else {
+                    continue;
+                };
+                // Kept within the lock like real writes
+                let Some((lowest, highest)) = display.lock_bounds(control as u8) else {
+                    changes.push(format!("{} locked", control.key()));
+                    continue;
+                };
+                let before = shown(display, controller);
+                controller.value = value.clamp(lowest, highest);
+                if control.is_continuous() {
+                    controller.value = controller.value.min(controller.maximum);
+                }
+                let after = shown(display, controller);
+                if after != before {
+                    changes.push(format!("{} {before}→{after}", control.key()));
+                }
+            }
+            if let Some(mode) = preset.and_then(|preset| preset.power) {
+                changes.push(format!("power {mode}"));
+            }
+            if !changes.is_empty() {
+                writeln!(out, "  {}: {}", display.label(), changes.join(", "))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A simulated value as the TUI shows it, percent along the curve or the name of the value.
+fn shown(display: &WrappedDisplay, controller: &Controller) -> String {
+    match controller.kind.is_continuous() {
+        true => format!("{}%", display.percent(controller)),
+        false => display.describe(controller.kind, controller.value),
+    }
+}
+
+/// Applies the rules that triggered since the last run, for headless mode where no TUI does.
+#[cfg(feature = "daemon")]
+pub struct Runner {
+    checked: DateTime<Local>,
+}
+
+#[cfg(feature = "daemon")]
+impl Runner {
+    pub fn new() -> Self {
+        Self {
+            checked: Local::now(),
+        }
+    }
+
+    pub fn run(&mut self, config: &Config, manager: &DisplayManager) {
+        let now = Local::now();
+        let due = due(config, self.checked, now);
+        self.checked = now;
+        // Rules due while paused are skipped rather than caught up on
+        if due.is_empty()
+            || !config
+                .automation
+                .is_active(crate::automation::Automation::Schedule)
+        {
+            return;
+        }
+        let mut history = Log::default();
+        for rule in due {
+            rule.record();
+            if let Some(name) = &rule.preset {
+                if let Err(err) =
+                    crate::presets::apply_named(config, manager, name, Origin::Schedule)
+                {
+                    eprintln!("schedule: {err}");
+                    events::record("error", rule.display.as_deref(), format!("schedule: {err}"));
+                }
+            }
+            match rule.queue_values(manager, &mut history) {
+                Ok(_) => manager.flush(),
+                Err(err) => {
+                    eprintln!("schedule: {err}");
+                    events::record("error", rule.display.as_deref(), format!("schedule: {err}"));
+                }
+            }
+            let source = crate::notify::Source::Schedule;
+            if let Err(err) = config.notifications.notify(source, &rule.notification()) {
+                eprintln!("schedule: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Runs the watchdog as a Windows service, so it keeps working without anyone logged in.
+
+use std::{
+    error::Error,
+    ffi::OsString,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    thread,
+    time::Duration,
+};
+
+use ddc_bright::supervisor::CancelToken;
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+use crate::{config::Config, control, watchdog};
+
+const NAME: &str = "ddc_bright";
+
+/// Control channel of the running service. Only administrators may write to it, the default
+/// for pipes of services running as LocalSystem.
+const PIPE: &str = r"\\.\pipe\ddc_bright";
+
+/// Registers the service to start with Windows, running this executable with `--service`.
+pub fn install() -> Result<(), Box<dyn Error>> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+    let info = ServiceInfo {
+        name: OsString::from(NAME),
+        display_name: OsString::from("ddc_bright watchdog"),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: vec![OsString::from("--service")],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager.create_service(&info, ServiceAccess::CHANGE_CONFIG)?;
+    service.set_description("Keeps displays at the values in the [watchdog] config")?;
+    println!("Installed {NAME}, start it with `sc start {NAME}` or reboot");
+    Ok(())
+}
+
+/// Stops the service if it runs and removes it.
+pub fn uninstall() -> Result<(), Box<dyn Error>> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(
+        NAME,
+        ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+    )?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    service.delete()?;
+    println!("Uninstalled {NAME}");
+    Ok(())
+}
+
+/// Asks the running service how it is doing over its control channel.
+pub fn status() -> Result<(), Box<dyn Error>> {
+    println!("{}", request("status")?);
+    Ok(())
+}
+
+fn request(command: &str) -> Result<String, Box<dyn Error>> {
+    let mut pipe = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(PIPE)
+        .map_err(|err| format!("{NAME} service not reachable: {err}"))?;
+    writeln!(pipe, "{command}")?;
+    let mut reply = String::new();
+    BufReader::new(pipe).read_line(&mut reply)?;
+    Ok(reply.trim_end().to_string())
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Hands the process to the service control manager, returning once the service stopped.
+pub fn run() -> Result<(), Box<dyn Error>> {
+    service_dispatcher::start(NAME, ffi_service_main)?;
+    Ok(())
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(err) = run_service() {
+        eprintln!("{err}");
+    }
+}
+
+fn run_service() -> Result<(), Box<dyn Error>> {
+    let stop = CancelToken::default();
+    let handler_stop = stop.clone();
+    let status = service_control_handler::register(NAME, move |control| match control {
+        ServiceControl::Stop => {
+            handler_stop.cancel();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    let report = |state, controls_accepted, exit_code| {
+        status.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })
+    };
+    report(
+        ServiceState::Running,
+        ServiceControlAccept::STOP,
+        ServiceExitCode::Win32(0),
+    )?;
+
+    let result = watch(&stop);
+    let exit_code = match result {
+        Ok(()) => ServiceExitCode::Win32(0),
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+    report(
+        ServiceState::Stopped,
+        ServiceControlAccept::empty(),
+        exit_code,
+    )?;
+    result
+}
+
+fn watch(stop: &CancelToken) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let mut manager = crate::connect(&config)?;
+    manager.refresh()?;
+    let pipe_stop = stop.clone();
+    // Not supervised, a thread waiting for a client can't be woken up
+    thread::spawn(move || serve(&pipe_stop));
+    watchdog::run(&mut manager, &config, stop)
+}
+
+/// Answers requests on the control channel, one client at a time.
+fn serve(stop: &CancelToken) {
+    while !stop.is_cancelled() {
+        let pipe = match control::accept_pipe(PIPE) {
+            Ok(pipe) => pipe,
+            Err(err) => {
+                eprintln!("{PIPE}: {err}");
+                return;
+            }
+        };
+        let Ok(mut writer) = pipe.try_clone() else {
+            continue;
+        };
+        let mut line = String::new();
+        if BufReader::new(pipe).read_line(&mut line).is_err() {
+            continue;
+        }
+        let reply = match line.trim() {
+            "status" => "running".to_string(),
+            command => format!("err unknown command {command:?}"),
+        };
+        let _ = writeln!(writer, "{reply}");
+    }
+}
@@ -0,0 +1,66 @@
+//! Presets applied when a known set of displays is connected, like the desk monitors or only the
+//! laptop panel, at startup and whenever displays are plugged in or out
+
+use std::collections::BTreeSet;
+
+use ddc_bright::display::WrappedDisplay;
+use serde::{Deserialize, Serialize};
+
+/// Applies a preset once the connected displays are the ones listed
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Setup {
+    /// Model names, serials, aliases or ids, each matched by a connected display of its own
+    pub displays: Vec<String>,
+    /// Whether displays that aren't listed may be connected too
+    #[serde(default)]
+    pub others: bool,
+    pub preset: String,
+}
+
+impl Setup {
+    /// Whether every listed display is connected, and nothing else unless `others` is set.
+    pub fn matches(&self, displays: &[WrappedDisplay]) -> bool {
+        let mut used = vec![false; displays.len()];
+        for query in &self.displays {
+            let found = displays
+                .iter()
+                .zip(&mut used)
+                .find(|(display, used)| !**used && display.matches(query));
+            match found {
+                Some((_, used)) => *used = true,
+                None => return false,
+            }
+        }
+        self.others || used.iter().all(|used| *used)
+    }
+}
+
+/// Tells which rule to apply each time the connected displays change, so refreshing the same
+/// displays again doesn't apply it over values changed since.
+#[derive(Default)]
+pub struct Tracker {
+    connected: Option<BTreeSet<String>>,
+}
+
+impl Tracker {
+    /// The first rule the displays match if they aren't the ones of the last check.
+    pub fn check<'a>(
+        &mut self,
+        rules: &'a [Setup],
+        displays: &[WrappedDisplay],
+    ) -> Option<&'a Setup> {
+        let connected = displays.iter().map(|display| display.id.clone()).collect();
+        if self.connected.as_ref() == Some(&connected) {
+            return None;
+        }
+        self.connected = Some(connected);
+        let rule = rules.iter().find(|rule| rule.matches(displays))?;
+        let detail = format!(
+            "{} connected, applying {}",
+            rule.displays.join(", "),
+            rule.preset
+        );
+        crate::events::record("setup", None, detail);
+        Some(rule)
+    }
+}
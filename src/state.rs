@@ -0,0 +1,171 @@
+//! Every setting of displays as a TOML file, to copy a calibration to identical displays or put
+//! it back after a factory reset
+
+use std::{collections::BTreeMap, error::Error, fs, io::Write, path::Path};
+
+use ddc_bright::{
+    display::{Control, MyDisplay, WrappedDisplay},
+    units,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::history::{Log, Origin};
+
+#[derive(Serialize, Deserialize, Default)]
+struct File {
+    #[serde(default, rename = "display")]
+    displays: Vec<Saved>,
+}
+
+/// The settings of one display and what it is recognised by
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manufacturer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    serial: Option<String>,
+    /// Only matched on for displays without a serial number
+    id: String,
+    /// By control name, or as hex like `0x87` for codes that aren't a control
+    #[serde(default)]
+    values: BTreeMap<String, u16>,
+}
+
+impl Saved {
+    /// Whether this is the same display: by serial number, or by id if it has none.
+    fn is(&self, display: &MyDisplay) -> bool {
+        match (&self.serial, &display.serial) {
+            (Some(serial), Some(other)) => {
+                serial == other
+                    && self.name == display.name
+                    && self.manufacturer == display.manufacturer
+            }
+            (None, None) => self.id == display.id,
+            _ => false,
+        }
+    }
+
+    /// The values as codes, in the order of the file.
+    fn codes(&self) -> Result<Vec<(u8, u16)>, String> {
+        self.values
+            .iter()
+            .map(|(key, value)| {
+                let code = match key.parse::<Control>() {
+                    Ok(control) => control as u8,
+                    Err(_) => units::parse_code(key)
+                        .map_err(|_| format!("{}: {key:?} is no control or VCP code", self.name))?,
+                };
+                Ok((code, *value))
+            })
+            .collect()
+    }
+}
+
+fn key(code: u8) -> String {
+    match Control::from_code(code) {
+        Some(control) => control.key(),
+        None => format!("0x{code:02X}"),
+    }
+}
+
+/// Reads every setting of the displays and writes them to `out` as TOML, leaving out and
+/// reporting codes that don't answer.
+pub fn export(displays: &[WrappedDisplay], out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let mut file = File::default();
+    for display in displays {
+        let mut values = BTreeMap::new();
+        for (code, value) in display.read_settings() {
+            match value {
+                Ok(value) => {
+                    values.insert(key(code), value);
+                }
+                Err(err) => eprintln!("{}: 0x{code:02X} left out, {err}", display.label()),
+            }
+        }
+        file.displays.push(Saved {
+            name: display.name.clone(),
+            manufacturer: display.manufacturer.clone(),
+            serial: display.serial.clone(),
+            id: display.id.clone(),
+            values,
+        });
+    }
+    writeln!(
+        out,
+        "# Written by ddc_bright export, restore with ddc_bright import"
+    )?;
+    write!(out, "{}", toml::to_string(&file)?)?;
+    Ok(())
+}
+
+/// Writes the settings in the file back to the displays they were read from. With `targets`,
+/// they go to those displays instead, from the only display in the file or the one of the same
+/// model.
+pub fn import(
+    displays: &[WrappedDisplay],
+    path: &Path,
+    targets: Option<&[WrappedDisplay]>,
+) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let file: File = toml::from_str(&text).map_err(|err| format!("{}: {err}", path.display()))?;
+    let mut pairs: Vec<(&Saved, WrappedDisplay)> = vec![];
+    match targets {
+        Some(targets) => {
+            for target in targets {
+                let saved = match file.displays.as_slice() {
+                    [saved] => saved,
+                    saved => saved
+                        .iter()
+                        .find(|saved| saved.name == target.name)
+                        .ok_or_else(|| {
+                            format!(
+                                "{} has several displays and none is a {}",
+                                path.display(),
+                                target.name
+                            )
+                        })?,
+                };
+                pairs.push((saved, target.clone()));
+            }
+        }
+        None => {
+            for saved in &file.displays {
+                match displays.iter().find(|display| saved.is(display)) {
+                    Some(display) => pairs.push((saved, display.clone())),
+                    None => println!(
+                        "{} ({}) isn't connected, pass --display to restore it on another",
+                        saved.name,
+                        saved.serial.as_deref().unwrap_or(&saved.id)
+                    ),
+                }
+            }
+        }
+    }
+
+    let mut history = Log::default();
+    let mut failed = false;
+    for (saved, display) in pairs {
+        let values = saved.codes()?;
+        let errors = display.write_settings(&values);
+        for (code, value) in &values {
+            if !errors.iter().any(|(failed, _)| failed == code) {
+                history.record(Origin::Cli, &display, *code, *value);
+            }
+        }
+        println!(
+            "{}: {} of {} values restored",
+            display.label(),
+            values.len() - errors.len(),
+            values.len()
+        );
+        for (code, err) in &errors {
+            println!("  {}: {err}", key(*code));
+        }
+        failed |= !errors.is_empty() && !display.is_lenient();
+    }
+    if failed {
+        return Err("not every value was restored".into());
+    }
+    Ok(())
+}
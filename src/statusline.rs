@@ -0,0 +1,266 @@
+//! Display values on one line for status bars: plain text for i3blocks and polybar, JSON for a
+//! waybar custom module, with scroll handlers stepping the brightness
+
+use std::{
+    env,
+    error::Error,
+    io::{self, Write},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use ddc_bright::{
+    display::{Control, MyDisplay, WrappedDisplay, ALL_CONTROLS},
+    units::Level,
+};
+
+use crate::backlight::Direction;
+use crate::history::{Log, Origin};
+
+/// Line per display unless `--format` is given
+pub const DEFAULT_FORMAT: &str = "{name} {brightness}%";
+
+/// How the line is printed
+pub struct Options<'a> {
+    /// Line per display with placeholders
+    pub format: &'a str,
+    /// Between the lines of the displays
+    pub separator: &'a str,
+    /// waybar's JSON instead of plain text
+    #[cfg(feature = "json")]
+    pub json: bool,
+    /// How often the values are read again, `None` to print once
+    pub watch: Option<Duration>,
+}
+
+/// The scroll direction i3blocks reports in `BLOCK_BUTTON` when running the block for a click.
+pub fn clicked() -> Option<Direction> {
+    match env::var("BLOCK_BUTTON").ok()?.as_str() {
+        "4" => Some(Direction::Up),
+        "5" => Some(Direction::Down),
+        _ => None,
+    }
+}
+
+/// Prints the line of `displays`, numbered by their place in `all` like `list` does, and with
+/// `watch` a new one whenever the values read change.
+pub fn run(
+    all: &[WrappedDisplay],
+    displays: &[WrappedDisplay],
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let controls = used(options.format);
+    let mut last = None;
+    loop {
+        let line = render(all, displays, options);
+        if last.as_ref() != Some(&line) {
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{line}")?;
+            // Bars read line by line from a pipe, which isn't flushed on its own
+            stdout.flush()?;
+            last = Some(line);
+        }
+        let Some(interval) = options.watch else {
+            return Ok(());
+        };
+        thread::sleep(interval);
+        for display in displays {
+            for control in &controls {
+                read(display, *control);
+            }
+        }
+    }
+}
+
+/// Steps the brightness of the displays by `step` percent along their curves.
+pub fn scroll(
+    displays: &[WrappedDisplay],
+    direction: Direction,
+    step: u16,
+) -> Result<(), Box<dyn Error>> {
+    let step = match direction {
+        Direction::Up => step as f32,
+        Direction::Down => -(step as f32),
+    };
+    let mut history = Log::default();
+    let mut failed = false;
+    for display in displays {
+        let Some(controller) = display.controls.get(&Control::Brightness) else {
+            continue;
+        };
+        let (percent, maximum) = {
+            let controller = controller.read().unwrap();
+            (display.percent(&controller) as f32, controller.maximum)
+        };
+        let level = Level::Percent((percent + step).clamp(0.0, 100.0));
+        let value = display.resolve(Control::Brightness, level, maximum);
+        match display.set(Control::Brightness, value) {
+            Ok(()) => {
+                controller.write().unwrap().value = value;
+                history.record(Origin::Cli, display, Control::Brightness as u8, value);
+            }
+            Err(err) => {
+                eprintln!("{}: {err}", display.label());
+                failed |= !display.is_lenient();
+            }
+        }
+    }
+    if failed {
+        return Err("not every display took the change".into());
+    }
+    Ok(())
+}
+
+/// The waybar module running `statusline` with scroll handlers, to paste into its config.
+#[cfg(feature = "json")]
+pub fn module(display: Option<&str>, format: &str) -> Result<(), Box<dyn Error>> {
+    let program = env::current_exe()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "ddc_bright".to_string());
+    let mut args = String::new();
+    if let Some(display) = display {
+        args.push_str(&format!(" --display {}", quote(display)));
+    }
+    let command = |extra: &str| format!("{} statusline{args}{extra}", quote(&program));
+    let mut exec = command(" --json --watch 5s");
+    if format != DEFAULT_FORMAT {
+        exec.push_str(&format!(" --format {}", quote(format)));
+    }
+    let module = serde_json::json!({
+        "custom/ddc_bright": {
+            "exec": exec,
+            "return-type": "json",
+            "format": "{}",
+            "on-scroll-up": command(" --scroll up"),
+            "on-scroll-down": command(" --scroll down"),
+        }
+    });
+    println!("{}", serde_json::to_string_pretty(&module)?);
+    Ok(())
+}
+
+/// Quoted for the shell waybar runs its commands in, if it needs to be.
+#[cfg(feature = "json")]
+fn quote(word: &str) -> String {
+    match word
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "/._-".contains(c))
+    {
+        true => word.to_string(),
+        false => format!("'{}'", word.replace('\'', r"'\''")),
+    }
+}
+
+/// The controls the format has placeholders of.
+fn used(format: &str) -> Vec<Control> {
+    let mut controls: Vec<Control> = ALL_CONTROLS
+        .into_iter()
+        .filter(|control| format.contains(&format!("{{{}}}", control.key())))
+        .collect();
+    // The JSON carries the brightness whatever the format shows
+    if !controls.contains(&Control::Brightness) {
+        controls.push(Control::Brightness);
+    }
+    controls
+}
+
+/// Reads a control again, forgetting its value if the display doesn't answer.
+fn read(display: &MyDisplay, control: Control) {
+    let Some(controller) = display.controls.get(&control) else {
+        return;
+    };
+    let mut controller = controller.write().unwrap();
+    match display.get(control) {
+        Ok(value) => {
+            controller.value = value;
+            controller.failed = false;
+        }
+        Err(_) => controller.failed = true,
+    }
+}
+
+/// A display's line, controls it doesn't have or didn't answer for left as `?`.
+fn line(number: usize, display: &MyDisplay, format: &str) -> String {
+    let mut line = format
+        .replace("{name}", &display.label())
+        .replace("{number}", &number.to_string())
+        .replace("{id}", &display.id);
+    for control in ALL_CONTROLS {
+        let placeholder = format!("{{{}}}", control.key());
+        if !line.contains(&placeholder) {
+            continue;
+        }
+        let value = match display.controls.get(&control) {
+            Some(controller) => {
+                let controller = controller.read().unwrap();
+                match (controller.failed, control.is_continuous()) {
+                    (true, _) => "?".to_string(),
+                    (false, true) => display.percent(&controller).to_string(),
+                    (false, false) => display.describe(control, controller.value),
+                }
+            }
+            None => "?".to_string(),
+        };
+        line = line.replace(&placeholder, &value);
+    }
+    line
+}
+
+fn render(all: &[WrappedDisplay], displays: &[WrappedDisplay], options: &Options) -> String {
+    let text = displays
+        .iter()
+        .map(|display| {
+            let number = all
+                .iter()
+                .position(|other| Arc::ptr_eq(other, display))
+                .map_or(0, |i| i + 1);
+            line(number, display, options.format)
+        })
+        .collect::<Vec<_>>()
+        .join(options.separator);
+    #[cfg(feature = "json")]
+    if options.json {
+        return waybar(displays, text);
+    }
+    text
+}
+
+/// The text with a tooltip, the mean brightness for `format-icons` and the class `unreachable`
+/// while a display doesn't answer, as waybar takes them with `"return-type": "json"`.
+#[cfg(feature = "json")]
+fn waybar(displays: &[WrappedDisplay], text: String) -> String {
+    let brightness: Vec<(String, Option<u16>)> = displays
+        .iter()
+        .map(|display| {
+            let percent = display.controls.get(&Control::Brightness).and_then(|c| {
+                let controller = c.read().unwrap();
+                (!controller.failed).then(|| display.percent(&controller))
+            });
+            (display.label(), percent)
+        })
+        .collect();
+    let tooltip: Vec<String> = brightness
+        .iter()
+        .map(|(label, percent)| match percent {
+            Some(percent) => format!("{label}: brightness {percent}%"),
+            None => format!("{label}: not answering"),
+        })
+        .collect();
+    let known: Vec<u32> = brightness
+        .iter()
+        .filter_map(|(_, percent)| percent.map(u32::from))
+        .collect();
+    let percentage = known.iter().sum::<u32>() / known.len().max(1) as u32;
+    let class = match known.len() == brightness.len() {
+        true => "",
+        false => "unreachable",
+    };
+    serde_json::json!({
+        "text": text,
+        "tooltip": tooltip.join("\n"),
+        "percentage": percentage,
+        "class": class,
+    })
+    .to_string()
+}
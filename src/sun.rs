@@ -0,0 +1,50 @@
+//! Sunrise and sunset, for schedule rules following the daylight
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Where the displays are, in degrees, north and east positive
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Julian day of 2000-01-01 12:00 UTC
+const J2000: f64 = 2451545.0;
+/// Julian day of the Unix epoch
+const UNIX_EPOCH: f64 = 2440587.5;
+
+/// Sunrise and sunset on a date, after the sunrise equation as NOAA approximates it. `None`
+/// during polar day or night, when the sun doesn't cross the horizon.
+pub fn times(date: NaiveDate, location: Location) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let days = date.signed_duration_since(epoch).num_days() as f64;
+    // Mean solar noon at the longitude
+    let noon = days - location.longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * noon)
+        .rem_euclid(360.0)
+        .to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.0200 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic = (anomaly.to_degrees() + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic).sin();
+    let declination = (ecliptic.sin() * 23.4397_f64.to_radians().sin()).asin();
+
+    // The sun's upper edge touches the horizon, refraction included
+    let latitude = location.latitude.to_radians();
+    let hour_angle = ((-0.833_f64).to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&hour_angle) {
+        return None;
+    }
+    let half_day = hour_angle.acos().to_degrees() / 360.0;
+    Some((utc(transit - half_day)?, utc(transit + half_day)?))
+}
+
+fn utc(julian: f64) -> Option<DateTime<Utc>> {
+    let seconds = (julian - UNIX_EPOCH) * 86400.0;
+    DateTime::from_timestamp(seconds.round() as i64, 0)
+}
@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Tells background threads to stop, checked whenever they wake up
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Owns background threads so they are stopped and joined together
+#[derive(Default)]
+pub struct Supervisor {
+    token: CancelToken,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Supervisor {
+    pub fn spawn(&mut self, name: &str, worker: impl FnOnce(CancelToken) + Send + 'static) {
+        let token = self.token.clone();
+        let thread = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || worker(token))
+            .expect("failed to spawn thread");
+        self.threads.push(thread);
+    }
+
+    pub fn token(&self) -> &CancelToken {
+        &self.token
+    }
+
+    /// Cancels and joins every thread, blocked ones have to be woken up by the caller first.
+    pub fn shutdown(&mut self) {
+        self.token.cancel();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
@@ -0,0 +1,116 @@
+//! Internal laptop panels through `/sys/class/backlight`, listed next to the DDC displays with
+//! only a brightness control
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::backend::{brightness_only, Backend};
+use crate::Error;
+
+const SYSFS: &str = "/sys/class/backlight";
+
+/// What the panel advertises, so loading keeps brightness and drops every other control
+pub(crate) const CAPABILITIES: &[u8] = b"(prot(backlight)type(lcd)vcp(10))";
+
+/// Preferred interfaces first, firmware ones know the panel best
+const TYPES: [&str; 3] = ["firmware", "platform", "raw"];
+
+pub(crate) struct Backlight {
+    pub name: String,
+    dir: PathBuf,
+}
+
+impl Backlight {
+    /// The backlight of the internal panel, `None` without one. With several interfaces to the
+    /// same panel, the firmware one is taken before platform and raw ones.
+    pub fn find() -> Option<Self> {
+        let mut found: Vec<(usize, String)> = fs::read_dir(SYSFS)
+            .ok()?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().to_string_lossy().into_owned();
+                let kind = fs::read_to_string(Path::new(SYSFS).join(&name).join("type")).ok()?;
+                let rank = TYPES.iter().position(|known| *known == kind.trim())?;
+                Some((rank, name))
+            })
+            .collect();
+        found.sort();
+        let (_, name) = found.into_iter().next()?;
+        Some(Self {
+            dir: Path::new(SYSFS).join(&name),
+            name,
+        })
+    }
+
+    /// Raw steps per reported step, so ranges past `u16::MAX` still fit.
+    fn scale(maximum: u32) -> u32 {
+        maximum.div_ceil(u16::MAX as u32).max(1)
+    }
+
+    /// The current and maximum brightness.
+    pub fn read(&self) -> io::Result<(u16, u16)> {
+        let maximum = read_number(&self.dir.join("max_brightness"))?;
+        let value = read_number(&self.dir.join("brightness"))?;
+        let scale = Self::scale(maximum);
+        Ok(((value / scale) as u16, (maximum / scale) as u16))
+    }
+
+    /// Writes the sysfs file if allowed, otherwise asks logind, which lets the session user
+    /// change its own backlight.
+    pub fn write(&self, value: u16) -> io::Result<()> {
+        let maximum = read_number(&self.dir.join("max_brightness"))?;
+        let raw = (value as u32 * Self::scale(maximum)).min(maximum);
+        if fs::write(self.dir.join("brightness"), raw.to_string()).is_ok() {
+            return Ok(());
+        }
+        let status = process::Command::new("busctl")
+            .args([
+                "call",
+                "org.freedesktop.login1",
+                "/org/freedesktop/login1/session/auto",
+                "org.freedesktop.login1.Session",
+                "SetBrightness",
+                "ssu",
+                "backlight",
+                &self.name,
+                &raw.to_string(),
+            ])
+            .stdout(process::Stdio::null())
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{}: not writable and logind refused, busctl failed with {status}",
+                self.dir.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Backend for Backlight {
+    fn read_vcp(&self, code: u8) -> Result<(u16, u16), Error> {
+        brightness_only(code, "a backlight").map_err(Error::Backlight)?;
+        self.read().map_err(Error::Backlight)
+    }
+
+    fn write_vcp(&self, code: u8, value: u16) -> Result<(), Error> {
+        brightness_only(code, "a backlight").map_err(Error::Backlight)?;
+        self.write(value).map_err(Error::Backlight)
+    }
+
+    fn capabilities_string(&self) -> Result<Vec<u8>, Error> {
+        Ok(CAPABILITIES.to_vec())
+    }
+}
+
+fn read_number(path: &Path) -> io::Result<u32> {
+    let text = fs::read_to_string(path)?;
+    text.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: not a number: {text:?}", path.display()),
+        )
+    })
+}
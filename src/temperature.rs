@@ -0,0 +1,107 @@
+//! Colour temperature, a virtual control writing the red, green and blue gains together
+
+use serde::{Deserialize, Serialize};
+
+use crate::display::{Control, MyDisplay};
+use crate::units::Level;
+
+/// Warmest temperature in Kelvin, the blue gain is close to off there
+pub const MIN: u16 = 2000;
+/// Temperature of the gains at their maximum, nothing is warmed
+pub const NEUTRAL: u16 = 6500;
+
+const GAINS: [Control; 3] = [Control::RedGain, Control::GreenGain, Control::BlueGain];
+
+/// The colour of a black body at `kelvin` in 0-255 RGB, after Tanner Helland's fit of the
+/// CIE data.
+fn rgb(kelvin: u16) -> [f32; 3] {
+    let t = kelvin as f32 / 100.0;
+    let red = match t <= 66.0 {
+        true => 255.0,
+        false => 329.69873 * (t - 60.0).powf(-0.13320476),
+    };
+    let green = match t <= 66.0 {
+        true => 99.4708 * t.ln() - 161.11957,
+        false => 288.12216 * (t - 60.0).powf(-0.07551485),
+    };
+    let blue = match t {
+        t if t >= 66.0 => 255.0,
+        t if t <= 19.0 => 0.0,
+        t => 138.51773 * (t - 10.0).ln() - 305.0448,
+    };
+    [red, green, blue].map(|channel| channel.clamp(0.0, 255.0))
+}
+
+/// Share of its maximum each gain gets at a temperature, clamped to `MIN..=NEUTRAL`.
+pub fn gains(kelvin: u16) -> [(Control, f32); 3] {
+    let warm = rgb(kelvin.clamp(MIN, NEUTRAL));
+    let neutral = rgb(NEUTRAL);
+    std::array::from_fn(|i| (GAINS[i], (warm[i] / neutral[i]).min(1.0)))
+}
+
+/// The gains for a temperature as percent levels, for writing with the other levels.
+pub fn levels(kelvin: u16) -> Vec<(Control, Level)> {
+    gains(kelvin)
+        .into_iter()
+        .map(|(control, share)| (control, Level::Percent(share * 100.0)))
+        .collect()
+}
+
+/// Whether the display has all three gains.
+pub fn supported(display: &MyDisplay) -> bool {
+    GAINS
+        .iter()
+        .all(|control| display.controls.contains_key(control))
+}
+
+/// Whether displays get the colour temperature control, for displays with their own colour
+/// temperature or a calibration made with the gains
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Setting {
+    /// Model name, id or serial of the display, every display if unset
+    pub display: Option<String>,
+    pub enabled: bool,
+}
+
+impl Default for Setting {
+    fn default() -> Self {
+        Self {
+            display: None,
+            enabled: true,
+        }
+    }
+}
+
+/// Whether the display gets the control: it needs all three gains, and a setting naming it
+/// beats one for every display.
+pub fn offered(display: &MyDisplay, settings: &[Setting]) -> bool {
+    let named = settings.iter().find(|setting| {
+        setting
+            .display
+            .as_deref()
+            .is_some_and(|query| display.matches(query))
+    });
+    let general = settings.iter().find(|setting| setting.display.is_none());
+    supported(display) && named.or(general).is_none_or(|setting| setting.enabled)
+}
+
+/// The temperature, in steps of 100 K, whose gains are closest to the current ones. `None`
+/// without all three gains.
+pub fn estimate(display: &MyDisplay) -> Option<u16> {
+    let mut current = [0.0; 3];
+    for (i, control) in GAINS.iter().enumerate() {
+        let controller = *display.controls.get(control)?.read().unwrap();
+        current[i] = controller.value as f32 / controller.maximum.max(1) as f32;
+    }
+    (MIN..=NEUTRAL).step_by(100).min_by(|a, b| {
+        let distance = |kelvin: u16| {
+            gains(kelvin)
+                .iter()
+                .zip(current)
+                .map(|((_, share), current)| (share - current).powi(2))
+                .sum::<f32>()
+        };
+        distance(*a).total_cmp(&distance(*b))
+    })
+}
@@ -0,0 +1,175 @@
+//! Colours of the TUI: a built-in theme, parts of which the config can give colours of its own
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Names of the 16 terminal colours, in ANSI order
+const NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "gray",
+    "dark_gray",
+    "light_red",
+    "light_green",
+    "light_yellow",
+    "light_blue",
+    "light_magenta",
+    "light_cyan",
+    "white",
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Base {
+    /// Blue borders and bold highlights in the terminal's own colours
+    #[default]
+    Dark,
+    /// Darker colours that stay readable on a light background
+    Light,
+    /// The accents of the Solarized palette
+    Solarized,
+}
+
+/// A terminal colour by name, like `blue` or `dark_gray`, or as `#rrggbb`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Colour {
+    /// One of the 16 terminal colours, by its place in [`NAMES`]
+    Named(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl FromStr for Colour {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim().to_lowercase().replace([' ', '-'], "_");
+        if let Some(index) = NAMES.iter().position(|name| *name == text) {
+            return Ok(Colour::Named(index as u8));
+        }
+        let hex = text
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(rgb) => {
+                let [_, red, green, blue] = rgb.to_be_bytes();
+                Ok(Colour::Rgb(red, green, blue))
+            }
+            None => Err(format!(
+                "unknown colour {text}, expected #rrggbb or one of {}",
+                NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Colour {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Colour> for String {
+    fn from(colour: Colour) -> Self {
+        colour.to_string()
+    }
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Colour::Named(index) => write!(f, "{}", NAMES[*index as usize]),
+            Colour::Rgb(red, green, blue) => write!(f, "#{red:02x}{green:02x}{blue:02x}"),
+        }
+    }
+}
+
+/// Colours of the TUI, unset ones come from the base theme
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub base: Base,
+    /// Border of the focused list, display or colour control
+    pub accent: Option<Colour>,
+    /// Highlighted entry of lists, drawn bold
+    pub selection: Option<Colour>,
+    /// Filled part of the gauges of the colour controls
+    pub gauge: Option<Colour>,
+    /// Border of the help and the keys listed in it, drawn bold
+    pub help: Option<Colour>,
+}
+
+#[cfg(feature = "tui")]
+pub use styles::Theme;
+
+#[cfg(feature = "tui")]
+mod styles {
+    use ratatui::style::{Color, Modifier, Style};
+
+    use super::{Base, Colour, ThemeConfig};
+
+    /// The styles the TUI draws with
+    pub struct Theme {
+        pub accent: Style,
+        pub selection: Style,
+        pub gauge: Style,
+        pub help: Style,
+    }
+
+    impl Theme {
+        pub fn new(config: &ThemeConfig) -> Self {
+            let solarized = |rgb: u32| {
+                let [_, red, green, blue] = rgb.to_be_bytes();
+                Some(Color::Rgb(red, green, blue))
+            };
+            // Accent, selection, gauge and help, `None` for the terminal's own colour
+            let (accent, selection, gauge, help) = match config.base {
+                Base::Dark => (Some(Color::Blue), None, None, None),
+                Base::Light => (
+                    Some(Color::Blue),
+                    Some(Color::Magenta),
+                    Some(Color::Blue),
+                    Some(Color::Magenta),
+                ),
+                Base::Solarized => (
+                    solarized(0x268bd2),
+                    solarized(0xcb4b16),
+                    solarized(0x859900),
+                    solarized(0x2aa198),
+                ),
+            };
+            let pick = |colour: Option<Colour>, base: Option<Color>| {
+                let style = Style::default();
+                match colour.map(Color::from).or(base) {
+                    Some(colour) => style.fg(colour),
+                    None => style,
+                }
+            };
+            let bold = Style::default().add_modifier(Modifier::BOLD);
+            Self {
+                accent: pick(config.accent, accent),
+                selection: pick(config.selection, selection).patch(bold),
+                gauge: pick(config.gauge, gauge),
+                help: pick(config.help, help),
+            }
+        }
+    }
+
+    impl From<Colour> for Color {
+        fn from(colour: Colour) -> Self {
+            match colour {
+                Colour::Named(index) => Color::Indexed(index),
+                Colour::Rgb(red, green, blue) => Color::Rgb(red, green, blue),
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Logging every DDC/CI transaction for `--trace-ddc`: the VCP opcode, the bytes sent and read
+//! back and how long it took, for displays that NAK or garble some commands. The bytes are only
+//! seen through the i2c backend on Linux, other backends are traced by what they hand back.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use tracing::trace;
+
+use crate::backend::Backend;
+use crate::Error;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+const GET_VCP: u8 = 0x01;
+const GET_VCP_REPLY: u8 = 0x02;
+const SET_VCP: u8 = 0x03;
+
+/// Bytes of a Get VCP Feature reply without header and checksum
+const REPLY_LENGTH: usize = 8;
+
+/// Wait between a request and reading its reply, as DDC/CI asks for
+const REPLY_DELAY: Duration = Duration::from_millis(40);
+
+/// Traces the transactions of every display found from now on.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// What went over the wire for one command
+pub(crate) struct Exchange {
+    /// The packet written, from the sub-address to the checksum
+    pub sent: Vec<u8>,
+    /// The bytes read back, empty for commands without a reply
+    pub received: Vec<u8>,
+    /// The reply without header and checksum, if it checked out
+    pub reply: Result<Vec<u8>, Error>,
+}
+
+/// The connection of a display, logging each transaction at the trace level
+struct Traced {
+    connection: Box<dyn Backend>,
+    display: String,
+}
+
+/// The connection itself, or wrapped in one that traces it if tracing is on.
+pub(crate) fn wrap(connection: Box<dyn Backend>, display: &str) -> Box<dyn Backend> {
+    match ENABLED.load(Ordering::Relaxed) {
+        true => Box::new(Traced {
+            connection,
+            display: display.to_string(),
+        }),
+        false => connection,
+    }
+}
+
+impl Backend for Traced {
+    fn read_vcp(&self, code: u8) -> Result<(u16, u16), Error> {
+        let started = Instant::now();
+        let exchange = self
+            .connection
+            .exchange(&[GET_VCP, code], REPLY_LENGTH, REPLY_DELAY);
+        let Some(exchange) = exchange else {
+            let result = self.connection.read_vcp(code);
+            let answer = match &result {
+                Ok((value, maximum)) => format!("{value} of {maximum}"),
+                Err(err) => format!("failed: {err}"),
+            };
+            trace!(
+                display = self.display,
+                "get 0x{code:02X}: {answer} in {}, bytes not seen by this backend",
+                elapsed(started)
+            );
+            return result;
+        };
+        let result = exchange.reply.and_then(|reply| decode(code, &reply));
+        let answer = match &result {
+            Ok((value, maximum)) => format!("{value} of {maximum}"),
+            Err(err) => format!("failed: {err}"),
+        };
+        trace!(
+            display = self.display,
+            "get 0x{code:02X}: {answer} in {}, sent {}, received {}",
+            elapsed(started),
+            hex(&exchange.sent),
+            hex(&exchange.received)
+        );
+        result
+    }
+
+    fn write_vcp(&self, code: u8, value: u16) -> Result<(), Error> {
+        let started = Instant::now();
+        let [high, low] = value.to_be_bytes();
+        let exchange = self
+            .connection
+            .exchange(&[SET_VCP, code, high, low], 0, Duration::ZERO);
+        let (result, sent) = match exchange {
+            Some(exchange) => (exchange.reply.map(drop), Some(exchange.sent)),
+            None => (self.connection.write_vcp(code, value), None),
+        };
+        let answer = match &result {
+            Ok(()) => "done".to_string(),
+            Err(err) => format!("failed: {err}"),
+        };
+        let sent = sent.map_or("bytes not seen by this backend".to_string(), |sent| {
+            format!("sent {}", hex(&sent))
+        });
+        trace!(
+            display = self.display,
+            "set 0x{code:02X} = {value}: {answer} in {}, {sent}",
+            elapsed(started)
+        );
+        result
+    }
+
+    fn capabilities_string(&self) -> Result<Vec<u8>, Error> {
+        let started = Instant::now();
+        let result = self.connection.capabilities_string();
+        let answer = match &result {
+            Ok(string) => format!("{} bytes", string.len()),
+            Err(err) => format!("failed: {err}"),
+        };
+        trace!(
+            display = self.display,
+            "capabilities: {answer} in {}",
+            elapsed(started)
+        );
+        result
+    }
+}
+
+/// The current and maximum value of a Get VCP Feature reply.
+fn decode(code: u8, reply: &[u8]) -> Result<(u16, u16), Error> {
+    let &[opcode, result, replied, _, max_high, max_low, high, low] = reply else {
+        return Err(anyhow!("reply of {} bytes instead of {REPLY_LENGTH}", reply.len()).into());
+    };
+    if opcode != GET_VCP_REPLY || replied != code {
+        return Err(anyhow!("reply to 0x{opcode:02X} for 0x{replied:02X}").into());
+    }
+    match result {
+        0x00 => Ok((
+            u16::from_be_bytes([high, low]),
+            u16::from_be_bytes([max_high, max_low]),
+        )),
+        0x01 => Err(anyhow!("unsupported VCP code 0x{code:02X}").into()),
+        result => Err(anyhow!("result code 0x{result:02X}").into()),
+    }
+}
+
+fn elapsed(started: Instant) -> String {
+    format!("{:.1} ms", started.elapsed().as_secs_f64() * 1000.0)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    match bytes.is_empty() {
+        true => "nothing".to_string(),
+        false => bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Runs the command over the i2c device itself, keeping the bytes, with the delays the ddc
+/// crate keeps between commands.
+#[cfg(all(target_os = "linux", feature = "i2c"))]
+pub(crate) fn exchange_i2c(
+    ddc: &mut ddc_i2c::I2cDeviceDdc,
+    data: &[u8],
+    reply: usize,
+    delay: Duration,
+) -> Exchange {
+    use ddc::{DdcCommandRaw, DdcCommandRawMarker, Delay};
+
+    // The sub-address, the length and the checksum over the destination address and the rest
+    let mut sent = vec![ddc::SUB_ADDRESS_DDC_CI, 0x80 | data.len() as u8];
+    sent.extend_from_slice(data);
+    let checksum = sent
+        .iter()
+        .fold((ddc::I2C_ADDRESS_DDC_CI as u8) << 1, |sum, byte| sum ^ byte);
+    sent.push(checksum);
+
+    // Room for the source address, the length and the checksum around the reply
+    let mut received = vec![0; if reply > 0 { reply + 3 } else { 0 }];
+    let result = ddc
+        .execute_raw(data, &mut received, delay)
+        .map(|reply| reply.to_vec());
+    let wait = match result {
+        Ok(_) => 50,
+        Err(_) => ddc::DELAY_COMMAND_FAILED_MS,
+    };
+    ddc.set_sleep_delay(Delay::new(Duration::from_millis(wait)));
+    Exchange {
+        sent,
+        received,
+        reply: result.map_err(|err| anyhow!(err).into()),
+    }
+}
@@ -0,0 +1,156 @@
+//! Several control writes to one display applied as a unit, see
+//! [`crate::display::DisplayManager::transaction`]
+
+use std::{error, fmt};
+
+use crate::backend::Session;
+use crate::display::{Control, Flusher, WrappedDisplay};
+use crate::Error;
+
+/// Writes staged for a display, nothing is written until [`Transaction::commit`]
+#[must_use = "nothing is written until the transaction is committed"]
+pub struct Transaction {
+    flusher: Flusher,
+    display: WrappedDisplay,
+    writes: Vec<(Control, u16)>,
+}
+
+impl Transaction {
+    pub(crate) fn new(flusher: Flusher, display: WrappedDisplay) -> Self {
+        Self {
+            flusher,
+            display,
+            writes: vec![],
+        }
+    }
+
+    pub fn display(&self) -> &WrappedDisplay {
+        &self.display
+    }
+
+    /// Stages writing a value, replacing a value staged for the control before. Continuous
+    /// controls are capped at their maximum.
+    pub fn set(mut self, control: Control, value: u16) -> Self {
+        self.writes.retain(|(staged, _)| *staged != control);
+        self.writes.push((control, value));
+        self
+    }
+
+    /// Writes the staged values in the order they were staged, once the queued changes are
+    /// written. If one write fails, the values written before it are restored, newest first.
+    /// Controls the display doesn't have are skipped.
+    ///
+    /// The connection stays locked from the first read to the last write, so no other write
+    /// lands in between, and the values to restore are read from the display rather than
+    /// taken from what was last seen.
+    pub fn commit(self) -> Result<Committed, Aborted> {
+        // Queued writes would land after ours otherwise
+        self.flusher.flush();
+        let display = self.display.clone();
+        let mut session = display.session();
+        let mut committed = Committed {
+            display: self.display.clone(),
+            written: vec![],
+            previous: vec![],
+        };
+        for (control, value) in self.writes {
+            let Some(controller) = self.display.controls.get(&control) else {
+                continue;
+            };
+            let previous = display.get_in(&mut *session, control as u8);
+            let written = previous.and_then(|previous| {
+                let value = match control.is_continuous() {
+                    true => value.min(controller.read().unwrap().maximum),
+                    false => value,
+                };
+                display.set_in(&mut *session, control as u8, value)?;
+                Ok((control.mask(previous), value))
+            });
+            let (previous, value) = match written {
+                Ok(written) => written,
+                Err(error) => {
+                    return Err(Aborted {
+                        control,
+                        error,
+                        restored: committed.restore(&mut *session),
+                    })
+                }
+            };
+            controller.write().unwrap().value = value;
+            committed.written.push((control, value));
+            committed.previous.push((control, previous));
+        }
+        Ok(committed)
+    }
+}
+
+/// What a committed transaction wrote, which can still be taken back, e.g. when a transaction
+/// on another display fails
+pub struct Committed {
+    display: WrappedDisplay,
+    written: Vec<(Control, u16)>,
+    previous: Vec<(Control, u16)>,
+}
+
+impl Committed {
+    pub fn display(&self) -> &WrappedDisplay {
+        &self.display
+    }
+
+    /// The values written, in order.
+    pub fn written(&self) -> &[(Control, u16)] {
+        &self.written
+    }
+
+    /// The values the written controls had before, in the same order.
+    pub fn previous(&self) -> &[(Control, u16)] {
+        &self.previous
+    }
+
+    /// Writes the previous values back, newest first. Returns whether every one was restored.
+    pub fn rollback(self) -> bool {
+        self.restore(&mut *self.display.session())
+    }
+
+    /// Keeps going after a failed write, restoring as much as possible.
+    fn restore(&self, session: &mut dyn Session) -> bool {
+        let mut restored = true;
+        for (control, previous) in self.previous.iter().rev() {
+            match self.display.set_in(session, *control as u8, *previous) {
+                Ok(()) => {
+                    if let Some(controller) = self.display.controls.get(control) {
+                        controller.write().unwrap().value = *previous;
+                    }
+                }
+                Err(_) => restored = false,
+            }
+        }
+        restored
+    }
+}
+
+/// Why a transaction stopped, with every earlier write restored unless `restored` is false
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Aborted {
+    /// The control whose write failed
+    pub control: Control,
+    pub error: Error,
+    pub restored: bool,
+}
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "writing {} failed: {}", self.control.key(), self.error)?;
+        if !self.restored {
+            write!(f, ", not every earlier value could be restored")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Aborted {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
@@ -0,0 +1,3844 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    io, panic, process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use crossterm::{
+    event::{
+        self, DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture,
+        Event, KeyCode, KeyEvent, KeyEventKind, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute,
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+use ddc_bright::{
+    capabilities::FeatureKind,
+    composite::Composite,
+    display::{
+        Control, Controller, DisplayManager, Failure, MyDisplay, Progress, Scan, Status,
+        WrappedController, WrappedDisplay, POWER_MODE, RESET_ALL, RESET_COLOR,
+    },
+    doctor::{self, Problem},
+    health,
+    metrics::Written,
+    power::PowerMode,
+    temperature,
+    units::{self, Adjust, Level, VolumeUnit},
+};
+use ratatui::{prelude::*, widgets::*};
+
+use crate::ambient::Adaptive;
+use crate::audio::Trigger;
+use crate::automation::{self, Automation, Policy};
+use crate::buttons::{self, Button};
+use crate::config::{Config, Hotkey, Target};
+use crate::events;
+use crate::history::{Entry, Log, Origin};
+use crate::keys::{Binding, Hold, DISPLAYS};
+use crate::logging;
+use crate::notify::Source;
+#[cfg(feature = "overlay")]
+use crate::overlay;
+use crate::presets::{self, Preset, Saved};
+use crate::quirks::{self, Readout};
+use crate::scene::Amount;
+use crate::schedule::{self, Rule};
+use crate::setup::Tracker;
+use crate::theme::Theme;
+use crate::update::{self, Release};
+use action::Action;
+use color::ColorPanel;
+use equalize::Equalizer;
+use learn::Suggestion;
+use notes::Notes;
+use ramp::Ramp;
+use session::{Row, Session};
+use vcp::VcpForm;
+
+mod action;
+mod color;
+mod equalize;
+mod filter;
+mod learn;
+mod notes;
+mod palette;
+mod ramp;
+mod session;
+mod vcp;
+mod wall;
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(500);
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+/// How long the input thread waits for an event before looking whether the TUI is done
+const INPUT_POLL: Duration = Duration::from_millis(100);
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+/// Time each display stays dimmed when identifying them with reduced motion
+const IDENTIFY_DIM: Duration = Duration::from_millis(1500);
+
+/// Actions listed in the help with their keys, the display line standing for all nine
+const HELP: [Binding; 35] = [
+    Binding::Quit,
+    Binding::Refresh,
+    Binding::Display(1),
+    Binding::Identify,
+    Binding::Presets,
+    Binding::History,
+    Binding::Changelog,
+    Binding::Preview,
+    Binding::Colour,
+    Binding::Copy,
+    Binding::StepUp,
+    Binding::StepDown,
+    Binding::Edit,
+    Binding::Link,
+    Binding::Reset,
+    Binding::Power,
+    Binding::Vcp,
+    Binding::Capabilities,
+    Binding::Rename,
+    Binding::Note,
+    Binding::Filter,
+    Binding::Queue,
+    Binding::Log,
+    Binding::Mark,
+    Binding::Group,
+    Binding::Equalize,
+    Binding::Health,
+    Binding::Layout,
+    Binding::Palette,
+    Binding::Previous,
+    Binding::Next,
+    Binding::Decrease,
+    Binding::Increase,
+    Binding::Select,
+    Binding::Back,
+];
+
+/// Lines the log pane takes, with its borders
+const LOG_HEIGHT: u16 = 8;
+
+/// Smallest terminal the layout stays readable in
+const MIN_WIDTH: u16 = 50;
+const MIN_HEIGHT: u16 = 13;
+/// Lines taken below the prompt on terminals without an alternate screen
+const INLINE_HEIGHT: u16 = 20;
+
+enum InputMode {
+    /// The startup screen, until a refresh found displays
+    Loading,
+    Select,
+    Help,
+    Selected,
+    History,
+    /// The changes not written yet
+    Queue,
+    ConfirmRefresh,
+    /// Asks whether to write or drop the values held back while previewing
+    ConfirmPreview,
+    /// Asks before turning the only display's brightness down to 0
+    ConfirmBlack,
+    /// Offers a synced group keeping two displays as far apart as they are usually set
+    Suggesting,
+    Presets,
+    Palette,
+    /// Typing the exact value of the highlighted control
+    Editing,
+    /// Typing the alias of the highlighted display
+    Renaming,
+    /// Typing the name of the group of the marked displays
+    NamingGroup,
+    /// Asking which of two displays looks brighter until they match
+    Equalizing,
+    /// Typing the note of the selected display
+    EditingNote,
+    /// Typing the filter of the display list, or of the controls of the selected display
+    Filtering,
+    /// Waiting for the number of the display the highlighted control is copied to
+    Copying,
+    /// Asks whether to restore the factory defaults of the highlighted control or the display
+    ConfirmReset,
+    /// What changed in the newer release
+    Changelog,
+    Color,
+    /// Reading and writing any VCP code of the selected display
+    Vcp,
+    /// The diagnostic codes of the selected display
+    Health,
+}
+
+/// App holds the state of the application
+struct App {
+    input_mode: InputMode,
+    config: Config,
+    manager: DisplayManager,
+    step_size: i16,
+    /// Set while displays are enumerated in the background
+    loading: bool,
+    /// What the checks of `doctor` found wrong, after a refresh without displays
+    problems: Vec<Problem>,
+    refresh: Option<(Receiver<Scan>, Receiver<Progress>)>,
+    /// Tells when a display was plugged in or out, `None` if watching for it failed
+    hotplug: Option<Receiver<()>>,
+    /// A display was plugged in or out while a refresh ran, so another one is due
+    hotplugged: bool,
+    /// Displays that stopped answering even after retrying
+    failures: Receiver<Failure>,
+    /// Where the display list was drawn last, for telling what the mouse is over
+    display_area: Rect,
+    /// Where each button was drawn last, for clicks
+    button_areas: Vec<Rect>,
+    /// Latest step of the running or last refresh for each bus and display, in order of appearance
+    progress: Vec<Progress>,
+    /// Frame of the loading spinner, advanced on every draw while loading
+    spinner: usize,
+    show_help: bool,
+
+    control_index: usize,
+    control_selected: Option<WrappedController>,
+    control_widget_state: ListState,
+
+    display_index: usize,
+    display_selected: Option<WrappedDisplay>,
+    display_widget_state: ListState,
+
+    /// Only displays whose alias or model name matches are listed, every display if empty
+    display_filter: String,
+    /// Only controls of the selected display whose name matches are listed
+    control_filter: String,
+
+    /// Diagnostic values of the selected display, `None` if it didn't answer
+    readings: Vec<(Readout, Option<u16>)>,
+    /// Whether the readings of the selected display are still being read
+    reading: bool,
+    /// Names and decoded values of the health popup, `None` for codes the display didn't answer
+    health: Vec<(String, Option<String>)>,
+    /// Composite values by display id and composite name
+    composite_values: HashMap<(String, String), u16>,
+    /// Colour temperatures set here by display id, estimated from the gains otherwise
+    temperatures: HashMap<String, u16>,
+    /// Factory defaults by display id, learned by resetting
+    defaults: BTreeMap<String, BTreeMap<Control, u16>>,
+    /// Receives the newer release once the update check found one
+    update: Option<Receiver<Release>>,
+    release: Option<Release>,
+
+    saved_presets: Saved,
+    preset_index: usize,
+    /// The highlighted change in the queue
+    queue_index: usize,
+    notes: Notes,
+    /// Outcome of the last save or apply, shown in the presets popup
+    preset_status: Option<String>,
+    /// Last failure worth telling, shown in the footer until the next key press
+    status: Option<String>,
+
+    /// Changes made here not in the history file yet, written once keys are let go
+    history: Log,
+    /// The history as read when its popup was opened
+    history_entries: Vec<Entry>,
+
+    palette_query: String,
+    palette_index: usize,
+
+    /// Text typed into the value box
+    edit_input: String,
+    /// Why the typed value was refused, until it is changed
+    edit_error: Option<String>,
+
+    /// Adjusting a control changes it on every display that has it
+    linked: bool,
+
+    /// The colour tuning screen of the selected display while it is open
+    color: Option<ColorPanel>,
+    /// The brightness matching of two marked displays while it runs
+    equalizer: Option<Equalizer>,
+    /// When brightness was last changed by hand, until the history was looked through for it
+    adjusted: Option<Instant>,
+    /// The synced group offered from the history
+    suggestion: Option<Suggestion>,
+    /// The VCP form of the selected display while it is open
+    vcp: Option<VcpForm>,
+    /// The only display and its brightness, waiting for the user to confirm turning it to 0
+    black: Option<(WrappedDisplay, WrappedController)>,
+    /// Ids of the displays showing their capabilities next to the controls
+    capabilities_shown: HashSet<String>,
+    /// First line of the capabilities shown
+    capabilities_scroll: usize,
+    /// Whether the latest log entries show below the lists
+    log_shown: bool,
+    /// Displays are drawn where they are on the desktop instead of as a list
+    wall: bool,
+    theme: Theme,
+    /// Serial numbers, or ids without one, of the displays marked for a new group
+    marked: Vec<String>,
+
+    /// Schedule rules triggering after this are still to be applied
+    schedule_checked: DateTime<Local>,
+    /// Which displays the setup rules were last checked against
+    setups: Tracker,
+    /// Adaptive brightness, if on and the sensor opened
+    ambient: Option<Adaptive>,
+
+    /// Where the last run was left, returned to once the displays are first loaded
+    restore: Option<Session>,
+
+    /// Adjusting the highlighted control while an arrow key is held
+    ramp: Option<Ramp>,
+    /// The last key pressed and when, telling a held key from taps
+    last_press: Option<(KeyCode, Instant)>,
+
+    /// Hands what background threads finished to the main loop, set while it runs
+    sender: Option<Sender<Message>>,
+    /// Set while the displays flash to be identified
+    identifying: bool,
+}
+
+impl App {
+    fn new(config: Config, manager: DisplayManager, saved_presets: Saved, notes: Notes) -> App {
+        let step_size = config.keys.step.max(1);
+        let theme = Theme::new(&config.theme);
+        let failures = manager.watch_failures();
+        let (ambient, status) = match config.ambient.adaptive {
+            true => match Adaptive::new(&config.ambient) {
+                Ok(adaptive) => (Some(adaptive), None),
+                Err(err) => (None, Some(format!("Adaptive brightness: {err}"))),
+            },
+            false => (None, None),
+        };
+        App {
+            input_mode: InputMode::Select,
+            config,
+            step_size,
+            manager,
+            control_index: 0,
+            display_index: 0,
+            control_selected: None,
+            display_selected: None,
+            display_filter: String::new(),
+            control_filter: String::new(),
+            loading: false,
+            problems: vec![],
+            refresh: None,
+            hotplug: None,
+            hotplugged: false,
+            failures,
+            display_area: Rect::default(),
+            button_areas: vec![],
+            progress: vec![],
+            spinner: 0,
+            show_help: false,
+            display_widget_state: ListState::default().with_selected(None).with_offset(0),
+            control_widget_state: ListState::default().with_selected(None).with_offset(0),
+            readings: vec![],
+            reading: false,
+            health: vec![],
+            composite_values: HashMap::new(),
+            temperatures: HashMap::new(),
+            defaults: quirks::Cache::load()
+                .map(|cache| cache.defaults)
+                .unwrap_or_default(),
+            update: None,
+            release: None,
+            saved_presets,
+            notes,
+            preset_index: 0,
+            queue_index: 0,
+            preset_status: None,
+            status,
+            history: Log::default(),
+            history_entries: vec![],
+            palette_query: String::new(),
+            palette_index: 0,
+            edit_input: String::new(),
+            edit_error: None,
+            linked: false,
+            color: None,
+            vcp: None,
+            black: None,
+            capabilities_shown: HashSet::new(),
+            capabilities_scroll: 0,
+            log_shown: false,
+            wall: false,
+            equalizer: None,
+            adjusted: None,
+            suggestion: None,
+            theme,
+            marked: vec![],
+            schedule_checked: Local::now(),
+            setups: Tracker::default(),
+            ambient,
+            restore: Session::load(),
+            ramp: None,
+            last_press: None,
+            sender: None,
+            identifying: false,
+        }
+    }
+
+    /// Runs `work` on a thread of its own and hands what it returns to `done` back on the main
+    /// loop, so slow reads and writes don't hold up the keys.
+    fn background<T: Send + 'static>(
+        &self,
+        work: impl FnOnce() -> T + Send + 'static,
+        done: impl FnOnce(&mut App, T) + Send + 'static,
+    ) {
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let result = work();
+            if let Some(sender) = sender {
+                let _ = sender.send(Message::Done(Box::new(move |app| done(app, result))));
+            }
+        });
+    }
+
+    /// Where the TUI is now, to start there next time.
+    fn session(&self) -> Session {
+        let row = self.display_selected.as_ref().map(|display| {
+            if let Some(controller) = &self.control_selected {
+                return Row::Control(controller.read().unwrap().kind);
+            }
+            match self
+                .config
+                .composites
+                .get(self.control_index - display.controls.len())
+            {
+                Some(composite) => Row::Composite(composite.name.clone()),
+                None => Row::Temperature,
+            }
+        });
+        Session {
+            display: self
+                .manager
+                .displays
+                .get(self.display_index)
+                .map(|display| display.stable_id()),
+            selected: self.display_selected.is_some(),
+            row,
+            step: Some(self.step_size),
+            linked: self.linked,
+            display_offset: self.display_widget_state.offset(),
+            control_offset: self.control_widget_state.offset(),
+            values: Session::values(&self.manager.displays),
+        }
+    }
+
+    /// Goes back to where the last run was left, as far as its display is still there.
+    fn restore_session(&mut self, session: Session) {
+        if let Some(step) = session.step {
+            self.step_size = step.max(1);
+        }
+        self.linked = session.linked;
+        let Some(index) = self
+            .manager
+            .displays
+            .iter()
+            .position(|display| Some(display.stable_id()) == session.display)
+        else {
+            return;
+        };
+        self.set_display(index);
+        *self.display_widget_state.offset_mut() = session.display_offset;
+        if !session.selected {
+            return;
+        }
+        self.select_display();
+        let display = self.display_selected.as_ref().unwrap();
+        let row = match session.row {
+            Some(Row::Control(control)) => {
+                display.controls.keys().position(|known| *known == control)
+            }
+            Some(Row::Composite(name)) => self
+                .config
+                .composites
+                .iter()
+                .position(|composite| composite.name == name)
+                .map(|index| display.controls.len() + index),
+            Some(Row::Temperature) => temperature::offered(display, &self.config.temperature)
+                .then(|| display.controls.len() + self.config.composites.len()),
+            None => None,
+        };
+        if let Some(row) = row {
+            self.select_control(row);
+        }
+        *self.control_widget_state.offset_mut() = session.control_offset;
+    }
+
+    fn select_display(&mut self) {
+        if let Some(display) = self.manager.displays.get(self.display_index) {
+            self.display_selected = Some(display.clone());
+            // A read per code, too slow for the UI thread on some displays
+            self.readings.clear();
+            self.reading = true;
+            let (configured, read) = (self.config.readouts.clone(), display.clone());
+            let shown = display.clone();
+            self.background(
+                move || quirks::read(&configured, &read),
+                move |app, readings| {
+                    if app
+                        .display_selected
+                        .as_ref()
+                        .is_some_and(|selected| Arc::ptr_eq(selected, &shown))
+                    {
+                        app.readings = readings;
+                        app.reading = false;
+                    }
+                },
+            );
+            self.control_filter.clear();
+            self.select_control(0);
+            self.capabilities_scroll = 0;
+            self.input_mode = InputMode::Selected;
+        }
+    }
+    fn set_display(&mut self, desired: usize) {
+        let desired = if desired >= self.manager.displays.len() {
+            0
+        } else {
+            desired
+        };
+        self.display_index = desired;
+        self.display_widget_state.select(Some(desired));
+    }
+
+    fn select_control(&mut self, mut desired: usize) {
+        let display = self.display_selected.as_mut().unwrap();
+        let length = display.controls.len()
+            + self.config.composites.len()
+            + usize::from(temperature::offered(display, &self.config.temperature));
+        if desired >= length {
+            desired = 0;
+        }
+        // Composites and the colour temperature come after the real controls and have no
+        // controller of their own
+        self.control_index = desired;
+        self.control_selected = display.controls.values().nth(desired).cloned();
+        self.control_widget_state.select(Some(desired));
+    }
+
+    fn focus_control(&mut self, control: Control) {
+        if self.display_selected.is_none() {
+            self.select_display();
+        }
+        let Some(display) = &self.display_selected else {
+            return;
+        };
+        if let Some(index) = display.controls.keys().position(|known| *known == control) {
+            self.select_control(index);
+        }
+    }
+
+    fn composite_value(&self, display: &MyDisplay, composite: &Composite) -> u16 {
+        self.composite_values
+            .get(&(display.id.clone(), composite.name.clone()))
+            .copied()
+            .unwrap_or_else(|| composite.estimate(display))
+    }
+
+    fn temperature(&self, display: &MyDisplay) -> u16 {
+        self.temperatures
+            .get(&display.id)
+            .copied()
+            .or_else(|| temperature::estimate(display))
+            .unwrap_or(temperature::NEUTRAL)
+    }
+
+    fn next_control(&mut self) {
+        let shown = self.shown_controls();
+        self.select_control(filter::neighbour(&shown, self.control_index, true));
+    }
+
+    fn prev_control(&mut self) {
+        let shown = self.shown_controls();
+        self.select_control(filter::neighbour(&shown, self.control_index, false));
+    }
+
+    /// Indices of the displays the filter lets through.
+    fn shown_displays(&self) -> Vec<usize> {
+        (0..self.manager.displays.len())
+            .filter(|&i| {
+                let display = &self.manager.displays[i];
+                filter::positions(&display.label(), &self.display_filter).is_some()
+                    || filter::positions(&display.name, &self.display_filter).is_some()
+            })
+            .collect()
+    }
+
+    /// Names of the rows of the controls list: the controls, composites and colour temperature.
+    fn control_names(&self, display: &MyDisplay) -> Vec<String> {
+        let mut names: Vec<String> = display
+            .controls
+            .keys()
+            .map(|control| control.get_name().to_string())
+            .collect();
+        names.extend(self.config.composites.iter().map(|c| c.name.clone()));
+        if temperature::offered(display, &self.config.temperature) {
+            names.push("Colour temperature".to_string());
+        }
+        names
+    }
+
+    /// Indices of the rows of the controls list the filter lets through.
+    fn shown_controls(&self) -> Vec<usize> {
+        let Some(display) = &self.display_selected else {
+            return vec![];
+        };
+        self.control_names(display)
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| filter::positions(name, &self.control_filter).is_some())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Moves the highlight to the first match once the highlighted row no longer matches.
+    fn follow_filter(&mut self) {
+        match self.display_selected {
+            Some(_) => {
+                let shown = self.shown_controls();
+                if !shown.contains(&self.control_index) {
+                    if let Some(&first) = shown.first() {
+                        self.select_control(first);
+                    }
+                }
+            }
+            None => {
+                let shown = self.shown_displays();
+                if !shown.contains(&self.display_index) {
+                    if let Some(&first) = shown.first() {
+                        self.set_display(first);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The filter of the list being typed into.
+    fn filter_mut(&mut self) -> &mut String {
+        match self.display_selected {
+            Some(_) => &mut self.control_filter,
+            None => &mut self.display_filter,
+        }
+    }
+
+    fn add_to_control(&mut self, value: i16) {
+        let display = self.display_selected.clone().unwrap();
+        if let Some(control_mutex) = &self.control_selected {
+            let control = control_mutex.read().unwrap().kind;
+            let mut changed = match self.linked {
+                true => self.manager.displays.clone(),
+                false => vec![display.clone()],
+            };
+            for display in &changed {
+                let Some(controller) = display.controls.get(&control) else {
+                    continue;
+                };
+                let step = self.control_step(&controller.read().unwrap(), value);
+                self.queue_change(display, controller, step);
+            }
+            if !self.linked {
+                changed.extend(self.sync_groups(&display, control));
+            }
+            self.record(Origin::Tui, &changed, control);
+            if control == Control::InputSource {
+                self.switch_audio(&changed);
+            }
+        } else if let Some(composite) = self
+            .config
+            .composites
+            .get(self.control_index - display.controls.len())
+        {
+            let current = self.composite_value(&display, composite);
+            let value = (current as i16 + value).clamp(0, 100) as u16;
+            self.composite_values
+                .insert((display.id.clone(), composite.name.clone()), value);
+            for (control, target) in composite.targets(value) {
+                if let Some(controller) = display.controls.get(&control) {
+                    self.manager
+                        .queue_set(display.clone(), controller.clone(), target);
+                    self.history
+                        .record(Origin::Tui, &display, control as u8, target);
+                }
+            }
+        } else if temperature::offered(&display, &self.config.temperature) {
+            self.add_to_temperature(&display, value);
+        }
+    }
+
+    /// Sets the other members of synced groups the display is in to its percent of the control,
+    /// apart by their offsets, and returns them.
+    fn sync_groups(&self, display: &WrappedDisplay, control: Control) -> Vec<WrappedDisplay> {
+        let Some(controller) = display.controls.get(&control) else {
+            return vec![];
+        };
+        if !control.is_continuous() {
+            return vec![];
+        }
+        let percent = display.percent(&controller.read().unwrap()) as f32;
+        let mut followers: Vec<WrappedDisplay> = vec![];
+        let groups = self.config.groups.values();
+        for group in groups.filter(|group| group.sync && group.contains(display)) {
+            for member in &self.manager.displays {
+                if Arc::ptr_eq(member, display)
+                    || member.is_stale()
+                    || !group.contains(member)
+                    || followers.iter().any(|known| Arc::ptr_eq(known, member))
+                {
+                    continue;
+                }
+                let Some(target) = member.controls.get(&control) else {
+                    continue;
+                };
+                let maximum = target.read().unwrap().maximum;
+                let level = Level::Percent(group.follow(display, member, control, percent));
+                let value = member.resolve(control, level, maximum);
+                self.manager
+                    .queue_set(member.clone(), target.clone(), value);
+                followers.push(member.clone());
+            }
+        }
+        followers
+    }
+
+    /// Queues a relative change, brightness through [`App::interlock`].
+    /// Adds to the primary control of the highlighted display, if it has that control.
+    fn add_to_primary(&mut self, step: i16) {
+        let control = self.config.keys.primary;
+        let Some(display) = self.focused() else {
+            return;
+        };
+        let Some(controller) = display.controls.get(&control).cloned() else {
+            self.status = Some(format!("{} has no {}", display.label(), control.get_name()));
+            return;
+        };
+        let step = self.control_step(&controller.read().unwrap(), step);
+        self.queue_change(&display, &controller, step);
+        self.record(Origin::Tui, std::slice::from_ref(&display), control);
+        if control == Control::InputSource {
+            self.switch_audio(&[display]);
+        }
+    }
+
+    fn queue_change(
+        &mut self,
+        display: &WrappedDisplay,
+        controller: &WrappedController,
+        step: i16,
+    ) {
+        let (kind, current, maximum) = {
+            let controller = controller.read().unwrap();
+            (controller.kind, controller.value, controller.maximum)
+        };
+        if kind != Control::Brightness {
+            self.manager
+                .queue_change(display.clone(), controller.clone(), step);
+            return;
+        }
+        let target = (current as i32 + step as i32).clamp(0, maximum as i32) as u16;
+        if let Some(target) = self.interlock(display, controller, target) {
+            self.manager
+                .queue_set(display.clone(), controller.clone(), target);
+        }
+    }
+
+    /// The brightness to set instead of `value` if the display is the only one connected: at
+    /// least `brightness_floor`, or `None` while asking whether it may go to 0.
+    fn interlock(
+        &mut self,
+        display: &WrappedDisplay,
+        controller: &WrappedController,
+        value: u16,
+    ) -> Option<u16> {
+        let connected = self.manager.displays.iter().filter(|d| !d.is_stale());
+        let (kind, maximum) = {
+            let controller = controller.read().unwrap();
+            (controller.kind, controller.maximum)
+        };
+        if kind != Control::Brightness || connected.count() > 1 {
+            return Some(value);
+        }
+        let floor = (self.config.brightness_floor.min(100) as u32 * maximum as u32).div_ceil(100);
+        match (floor, value) {
+            (0, 0) => {
+                self.black = Some((display.clone(), controller.clone()));
+                self.input_mode = InputMode::ConfirmBlack;
+                None
+            }
+            (floor, value) => Some(value.max(floor as u16)),
+        }
+    }
+
+    /// Starts, keeps up or ends a ramp, returns whether the key was taken by it.
+    fn hold_key(&mut self, key: KeyEvent) -> bool {
+        if let Some(ramp) = &mut self.ramp {
+            if key.code == ramp.code {
+                match key.kind {
+                    KeyEventKind::Release => self.end_ramp(),
+                    _ => ramp.seen(),
+                }
+                return true;
+            }
+            if key.kind != KeyEventKind::Release {
+                self.end_ramp();
+            }
+            return false;
+        }
+        if key.kind == KeyEventKind::Release || self.config.keys.hold == Hold::Off {
+            return false;
+        }
+        let now = Instant::now();
+        let gap = self
+            .last_press
+            .replace((key.code, now))
+            .filter(|(code, _)| *code == key.code)
+            .map(|(_, at)| now - at);
+        if key.kind != KeyEventKind::Repeat && gap.is_none_or(|gap| gap >= ramp::HOLD_GAP) {
+            return false;
+        }
+        let press = KeyEvent {
+            kind: KeyEventKind::Press,
+            ..key
+        };
+        let (Some(Action::Adjust(step)), Some(controller)) =
+            (action::for_key(self, press), &self.control_selected)
+        else {
+            return false;
+        };
+        // Cycling through an input list or colour presets on a timer would be no use
+        if !controller.read().unwrap().kind.is_continuous() {
+            return false;
+        }
+        let write_back = self.config.keys.hold == Hold::WriteBack && !self.manager.previewing();
+        let interval = gap.unwrap_or(ramp::HOLD_GAP);
+        self.ramp = Some(Ramp::new(key.code, step, write_back, interval));
+        true
+    }
+
+    /// Moves the held control by the steps due, ending the ramp once the key was let go.
+    fn run_ramp(&mut self) {
+        let Some(ramp) = &mut self.ramp else {
+            return;
+        };
+        // Also once a confirmation took over the keys
+        if ramp.released() || !matches!(self.input_mode, InputMode::Selected) {
+            self.end_ramp();
+            return;
+        }
+        match (ramp.due(), ramp.write_back) {
+            (0, _) => (),
+            (amount, false) => self.add_to_control(amount),
+            (amount, true) => self.hold_back(amount),
+        }
+    }
+
+    /// Moves the highlighted control like `add_to_control` without writing it, until the ramp
+    /// ends.
+    fn hold_back(&mut self, amount: i16) {
+        let (Some(display), Some(selected)) = (&self.display_selected, &self.control_selected)
+        else {
+            return;
+        };
+        let control = selected.read().unwrap().kind;
+        let changed = match self.linked {
+            true => self.manager.displays.clone(),
+            false => vec![display.clone()],
+        };
+        for display in changed {
+            let Some(controller) = display.controls.get(&control).cloned() else {
+                continue;
+            };
+            let step = self.control_step(&controller.read().unwrap(), amount);
+            let (current, maximum) = {
+                let controller = controller.read().unwrap();
+                (controller.value, controller.maximum)
+            };
+            let target = (current as i32 + step as i32).clamp(0, maximum as i32) as u16;
+            let Some(target) = self.interlock(&display, &controller, target) else {
+                continue;
+            };
+            let ramp = self.ramp.as_mut().unwrap();
+            if !ramp
+                .held
+                .iter()
+                .any(|(_, known, _)| Arc::ptr_eq(known, &controller))
+            {
+                ramp.held
+                    .push((display.clone(), controller.clone(), current));
+            }
+            let mut held = controller.write().unwrap();
+            held.value = target;
+            // Keeps polling from putting the old value back
+            held.previewed = true;
+        }
+    }
+
+    /// Ends the ramp, writing the values it held back.
+    fn end_ramp(&mut self) {
+        let Some(ramp) = self.ramp.take() else {
+            return;
+        };
+        for (display, controller, original) in ramp.held {
+            let (kind, value) = {
+                let mut held = controller.write().unwrap();
+                let value = held.value;
+                held.value = original;
+                held.previewed = false;
+                (held.kind, value)
+            };
+            // Queued from where the ramp started, so fades and couplings start there too
+            self.manager.queue_set(display.clone(), controller, value);
+            self.history
+                .record(Origin::Tui, &display, kind as u8, value);
+        }
+    }
+
+    /// Warms or cools the display by 100 K per step, writing its gains.
+    fn add_to_temperature(&mut self, display: &WrappedDisplay, steps: i16) {
+        let current = self.temperature(display) as i32;
+        let kelvin = (current + steps as i32 * 100)
+            .clamp(temperature::MIN as i32, temperature::NEUTRAL as i32)
+            as u16;
+        self.temperatures.insert(display.id.clone(), kelvin);
+        for (control, level) in temperature::levels(kelvin) {
+            let Some(controller) = display.controls.get(&control) else {
+                continue;
+            };
+            let value = level.resolve(controller.read().unwrap().maximum);
+            self.manager
+                .queue_set(display.clone(), controller.clone(), value);
+            self.history
+                .record(Origin::Tui, display, control as u8, value);
+        }
+    }
+
+    /// Sets the highlighted control of the display at `index` to the value of the selected
+    /// display. Continuous values keep their share of the maximum, others are copied as is.
+    fn copy_control(&mut self, index: usize) {
+        let (Some(source), Some(controller)) = (&self.display_selected, &self.control_selected)
+        else {
+            return;
+        };
+        let source = source.clone();
+        let controller = *controller.read().unwrap();
+        let Some(target) = self.manager.displays.get(index).cloned() else {
+            self.status = Some(format!("No display {}", index + 1));
+            return;
+        };
+        if Arc::ptr_eq(&source, &target) {
+            return;
+        }
+        let Some(target_controller) = target.controls.get(&controller.kind).cloned() else {
+            self.status = Some(format!(
+                "{} has no {}",
+                target.label(),
+                controller.kind.get_name()
+            ));
+            return;
+        };
+        let value = match controller.kind.is_continuous() {
+            true => {
+                let maximum = target_controller.read().unwrap().maximum as u32;
+                let scaled = controller.value as u32 * maximum / controller.maximum.max(1) as u32;
+                scaled as u16
+            }
+            false => controller.value,
+        };
+        self.manager
+            .queue_set(target.clone(), target_controller, value);
+        self.record(Origin::Tui, std::slice::from_ref(&target), controller.kind);
+        if controller.kind == Control::InputSource {
+            self.switch_audio(&[target]);
+        }
+    }
+
+    /// Logs the value a control of displays was just set to.
+    fn record(&mut self, origin: Origin, displays: &[WrappedDisplay], control: Control) {
+        if origin == Origin::Tui && control == Control::Brightness && self.config.learn_offsets {
+            self.adjusted = Some(Instant::now());
+        }
+        for display in displays {
+            if let Some(controller) = display.controls.get(&control) {
+                let value = controller.read().unwrap().value;
+                self.history.record(origin, display, control as u8, value);
+            }
+        }
+    }
+
+    /// Writes the logged changes to the history file, failures go to the footer.
+    fn flush_history(&mut self) {
+        for noticed in self.manager.take_noticed() {
+            self.history.record_noticed(&noticed);
+        }
+        if let Err(err) = self.history.flush() {
+            self.status = Some(format!("Writing the history failed: {err}"));
+        }
+    }
+
+    /// Offers a synced group once brightness changed by hand was left alone for a while, if the
+    /// history shows two displays set the same distance apart every time.
+    fn learn(&mut self) {
+        let settled = self
+            .adjusted
+            .is_some_and(|adjusted| adjusted.elapsed() >= learn::SETTLE);
+        // Waits for popups to close
+        if !settled || !matches!(self.input_mode, InputMode::Select | InputMode::Selected) {
+            return;
+        }
+        self.adjusted = None;
+        let Ok(entries) = Log::read() else {
+            return;
+        };
+        self.suggestion = learn::suggest(&entries, &self.manager.displays, &self.config.groups);
+        if self.suggestion.is_some() {
+            self.input_mode = InputMode::Suggesting;
+        }
+    }
+
+    /// Switches the default audio output following the queued input of displays.
+    fn switch_audio(&mut self, displays: &[WrappedDisplay]) {
+        for display in displays {
+            let Some(controller) = display.controls.get(&Control::InputSource) else {
+                continue;
+            };
+            let input = controller.read().unwrap().value;
+            if let Err(err) = self.config.audio.switch(display, Trigger::Input(input)) {
+                self.status = Some(format!(
+                    "{}: switching audio failed: {err}",
+                    display.label()
+                ));
+            }
+        }
+    }
+
+    /// Turns the selected display off, or back on if it was turned off here. Queued changes
+    /// are written first, the display won't take them once off.
+    fn toggle_power(&mut self) {
+        let Some(display) = self.display_selected.clone() else {
+            return;
+        };
+        let mode = match display.is_powered_off() {
+            true => PowerMode::On,
+            false => PowerMode::Off,
+        };
+        self.manager.flush();
+        if let Err(err) = display.set_power(mode) {
+            self.status = Some(format!("{}: turning {mode} failed: {err}", display.label()));
+            return;
+        }
+        self.history
+            .record(Origin::Tui, &display, POWER_MODE, mode.to_vcp());
+        if mode == PowerMode::Off {
+            if let Err(err) = self.config.audio.switch(&display, Trigger::Off) {
+                self.status = Some(format!(
+                    "{}: switching audio failed: {err}",
+                    display.label()
+                ));
+            }
+        }
+    }
+
+    /// The reset code of the highlighted control, if the selected display takes it.
+    fn reset_code(&self) -> Option<u8> {
+        let display = self.display_selected.as_ref()?;
+        let code = self
+            .control_selected
+            .as_ref()?
+            .read()
+            .unwrap()
+            .kind
+            .reset_code()?;
+        display.can_reset(code).then_some(code)
+    }
+
+    /// Restores the factory defaults of the highlighted control's group, or of the whole
+    /// display, and keeps the values read back as the defaults.
+    fn reset(&mut self, all: bool) {
+        let Some(display) = self.display_selected.clone() else {
+            return;
+        };
+        let code = match all {
+            true => RESET_ALL,
+            false => match self.reset_code() {
+                Some(code) => code,
+                None => return,
+            },
+        };
+        // Queued changes would undo the reset once written
+        self.manager.flush();
+        let defaults = match display.restore_defaults(code) {
+            Ok(defaults) => defaults,
+            Err(err) => {
+                self.status = Some(format!("{}: reset failed: {err}", display.label()));
+                return;
+            }
+        };
+        self.temperatures.remove(&display.id);
+        let known = self.defaults.entry(display.id.clone()).or_default();
+        for (control, value) in defaults {
+            known.insert(control, value);
+            self.history
+                .record(Origin::Tui, &display, control as u8, value);
+        }
+        let saved = quirks::Cache::load().and_then(|mut cache| {
+            cache.defaults = self.defaults.clone();
+            cache.store()
+        });
+        if let Err(err) = saved {
+            self.status = Some(format!("Saving the defaults failed: {err}"));
+        }
+    }
+
+    /// Flashes every display as many times as its number in the list. With reduced motion the
+    /// displays are dimmed once each instead, one after the other in list order. Meanwhile the
+    /// numbers are drawn on the outputs the displays are connected to, one badge with all the
+    /// numbers on mirrored outputs, which flash together.
+    fn identify(&mut self) {
+        if self.identifying {
+            self.status = Some("Still identifying the displays".to_string());
+            return;
+        }
+        self.identifying = true;
+        let displays = self.manager.displays.clone();
+        let dim = self.config.reduced_motion();
+        if dim {
+            self.status = Some("Dimming the displays one at a time, in list order".to_string());
+        }
+        let work = move || -> Vec<(String, Result<(), String>)> {
+            #[cfg(feature = "overlay")]
+            let overlay = {
+                let displays = displays.clone();
+                thread::spawn(move || overlay::show(&displays))
+            };
+            #[cfg_attr(not(feature = "overlay"), allow(unused_mut))]
+            let mut results: Vec<_> = match dim {
+                true => (displays.iter())
+                    .map(|display| {
+                        let dimmed = display.dim(IDENTIFY_DIM);
+                        (display.label(), dimmed.map_err(|err| err.to_string()))
+                    })
+                    .collect(),
+                false => {
+                    let flashing: Vec<_> = (1..)
+                        .zip(displays)
+                        .map(|(times, display)| {
+                            (display.label(), thread::spawn(move || display.flash(times)))
+                        })
+                        .collect();
+                    (flashing.into_iter())
+                        .map(|(label, flash)| {
+                            let flashed = match flash.join() {
+                                Ok(flashed) => flashed.map_err(|err| err.to_string()),
+                                Err(_) => Err("flashing panicked".to_string()),
+                            };
+                            (label, flashed)
+                        })
+                        .collect()
+                }
+            };
+            #[cfg(feature = "overlay")]
+            results.push(match overlay.join() {
+                Ok(shown) => ("overlay".to_string(), shown),
+                Err(_) => ("overlay".to_string(), Err("drawing panicked".to_string())),
+            });
+            results
+        };
+        self.background(work, |app, results| {
+            app.identifying = false;
+            let failed: Vec<String> = (results.into_iter())
+                .filter_map(|(label, result)| result.err().map(|err| format!("{label}: {err}")))
+                .collect();
+            if !failed.is_empty() {
+                app.status = Some(format!("Identifying failed: {}", failed.join(", ")));
+            }
+        });
+    }
+
+    /// The next frame of the loading spinner, which stands still with reduced motion.
+    fn spin(&mut self) -> char {
+        if self.config.reduced_motion() {
+            return '…';
+        }
+        self.spinner = self.spinner.wrapping_add(1);
+        SPINNER[self.spinner % SPINNER.len()]
+    }
+
+    fn save_preset(&mut self) {
+        let Some(preset) = self.config.presets.get(self.preset_index) else {
+            return;
+        };
+        self.preset_status = Some(
+            match self.saved_presets.save(preset, &self.manager.displays) {
+                Ok(()) => format!("Saved {}", preset.name),
+                Err(err) => format!("Saving failed: {err}"),
+            },
+        );
+    }
+
+    /// Applies the preset at `index`, leaving the selection in the presets popup where it is.
+    /// Transactions and power modes wait on the displays, so they are written in the
+    /// background and the outcome shows once they are done.
+    fn apply_preset(&mut self, index: usize, origin: Origin) {
+        let Some(preset) = self.config.presets.get(index).cloned() else {
+            return;
+        };
+        self.preset_status = Some(format!("Applying {}", preset.name));
+        // Writing right away would skip the preview
+        if preset.rollback && !self.manager.previewing() {
+            let staged = self.saved_presets.stage(&preset, &self.manager);
+            self.background(
+                move || {
+                    // The TUI's own log stays on this thread, this one is flushed right away
+                    let mut history = Log::default();
+                    let result = Saved::commit(&preset, staged, &mut history, origin);
+                    let _ = history.flush();
+                    (preset, result)
+                },
+                |app, (preset, result)| app.applied(&preset, result),
+            );
+        } else {
+            let queued =
+                (self.saved_presets).queue(&preset, &self.manager, &mut self.history, origin);
+            let flusher = self.manager.flusher();
+            self.background(
+                move || {
+                    let applied = Saved::switch_power(&preset, &queued, &flusher);
+                    (preset, applied)
+                },
+                |app, (preset, applied)| app.applied(&preset, Ok(applied)),
+            );
+        }
+    }
+
+    /// Writes the next value of the colour screen in the background, one at a time so they
+    /// land in order. The screen is dropped once it is closed and everything is written.
+    fn write_color(&mut self) {
+        let Some(panel) = &mut self.color else {
+            return;
+        };
+        let Some((code, value)) = panel.next_write() else {
+            if !matches!(self.input_mode, InputMode::Color) && !panel.is_busy() {
+                self.color = None;
+            }
+            return;
+        };
+        let display = panel.display.clone();
+        self.background(
+            move || {
+                let result = display.set_vcp(code, value).map_err(|err| err.to_string());
+                (display, result)
+            },
+            move |app, (display, result)| {
+                if result.is_ok() {
+                    app.history.record(Origin::Tui, &display, code, value);
+                }
+                if let Some(panel) = &mut app.color {
+                    panel.written(result);
+                }
+                app.write_color();
+            },
+        );
+    }
+
+    /// Tells how applying the preset went, in the presets popup or the status bar if it's closed.
+    fn applied(&mut self, preset: &Preset, result: Result<usize, presets::Aborted>) {
+        let status = match result {
+            Ok(0) => format!("No values in {} for these displays", preset.name),
+            Ok(applied) => match (Policy::switch(preset), automation::describe(preset)) {
+                (Err(err), _) => format!("Applied {} to {applied} displays, {err}", preset.name),
+                (Ok(()), Some(automations)) => {
+                    format!(
+                        "Applied {} to {applied} displays, {automations}",
+                        preset.name
+                    )
+                }
+                (Ok(()), None) => format!("Applied {} to {applied} displays", preset.name),
+            },
+            Err(aborted) if aborted.restored => format!(
+                "{} failed ({}), rolled back to the previous values",
+                aborted.display, aborted.error
+            ),
+            Err(aborted) => format!(
+                "{} failed ({}), rolling back didn't reach every display either",
+                aborted.display, aborted.error
+            ),
+        };
+        if !matches!(self.input_mode, InputMode::Presets) {
+            self.status = Some(status.clone());
+        }
+        self.preset_status = Some(status);
+    }
+
+    /// Follows the light sensor with the brightness, if adaptive brightness is on.
+    fn run_ambient(&mut self) {
+        let Some(adaptive) = &mut self.ambient else {
+            return;
+        };
+        let config = &self.config;
+        let result = adaptive.run(
+            &config.ambient,
+            &config.automation,
+            &self.manager,
+            &mut self.history,
+        );
+        if let Err(err) = result {
+            self.status = Some(format!("Adaptive brightness: {err}"));
+        }
+    }
+
+    /// Applies the presets and values of schedule rules that triggered since the last check.
+    fn run_schedule(&mut self) {
+        let now = Local::now();
+        let due: Vec<Rule> = schedule::due(&self.config, self.schedule_checked, now)
+            .into_iter()
+            .cloned()
+            .collect();
+        self.schedule_checked = now;
+        if due.is_empty() || !self.config.automation.is_active(Automation::Schedule) {
+            return;
+        }
+        for rule in due {
+            rule.record();
+            if let Some(name) = &rule.preset {
+                match self
+                    .config
+                    .presets
+                    .iter()
+                    .position(|preset| &preset.name == name)
+                {
+                    Some(index) => self.apply_preset(index, Origin::Schedule),
+                    None => self.preset_status = Some(format!("Schedule: no preset named {name}")),
+                }
+            }
+            if let Err(err) = rule.queue_values(&self.manager, &mut self.history) {
+                self.preset_status = Some(format!("Schedule: {err}"));
+            }
+            // Anyone looking at the TUI sees the change already
+            if !self.manager.is_focused() {
+                let notifications = &self.config.notifications;
+                if let Err(err) = notifications.notify(Source::Schedule, &rule.notification()) {
+                    self.status = Some(err.to_string());
+                }
+            }
+        }
+    }
+
+    /// The selected display, or the highlighted one if none is selected.
+    fn focused(&self) -> Option<WrappedDisplay> {
+        self.display_selected
+            .clone()
+            .or_else(|| self.manager.displays.get(self.display_index).cloned())
+    }
+
+    /// What the arrow keys adjust by, at least `slow_step` on a slow focused display.
+    fn step(&self) -> i16 {
+        match self.focused() {
+            Some(display) if self.manager.metrics(&display).slow => {
+                self.step_size.max(self.config.keys.slow_step)
+            }
+            _ => self.step_size,
+        }
+    }
+
+    /// What an adjustment by `step` moves a control by: its entry in `control_steps` if it
+    /// has one, otherwise `step` scaled to its range.
+    fn control_step(&self, controller: &Controller, step: i16) -> i16 {
+        match self.config.keys.control_steps.get(&controller.kind) {
+            Some(fixed) => fixed * step.signum(),
+            None => controller.scale_step(step),
+        }
+    }
+
+    fn request_refresh(&mut self) {
+        if self.manager.pending() > 0 {
+            self.input_mode = InputMode::ConfirmRefresh;
+        } else {
+            self.start_refresh();
+        }
+    }
+
+    fn start_refresh(&mut self) {
+        if self.loading {
+            return;
+        }
+        let (sender, progress) = channel();
+        self.refresh = Some((self.manager.start_refresh(sender), progress));
+        self.progress.clear();
+        self.loading = true;
+    }
+
+    /// Takes over the displays of a finished background refresh.
+    fn poll_refresh(&mut self) {
+        let Some((result, progress)) = &self.refresh else {
+            return;
+        };
+        for step in progress.try_iter() {
+            match self
+                .progress
+                .iter_mut()
+                .find(|known| known.source == step.source)
+            {
+                Some(known) => *known = step,
+                None => self.progress.push(step),
+            }
+        }
+        let Ok(scan) = result.try_recv() else {
+            return;
+        };
+        self.refresh = None;
+        self.loading = false;
+        if let Err(err) = self.manager.finish_refresh(scan) {
+            self.manager.warnings.push(format!("Refresh failed: {err}"));
+            self.status = Some(format!("Refresh failed: {err}"));
+        }
+        self.problems = match self.manager.displays.is_empty() {
+            true => doctor::problems(&self.manager.displays),
+            false => vec![],
+        };
+        // Before the setup rule writes any
+        if let Some(changes) = self
+            .restore
+            .as_ref()
+            .and_then(|session| session.changes(&self.manager.displays))
+        {
+            self.status = Some(changes);
+        }
+        self.apply_setup();
+        // The selected display was replaced, or is gone
+        if let Some(selected) = self.display_selected.take() {
+            match self
+                .manager
+                .displays
+                .iter()
+                .position(|display| display.id == selected.id)
+            {
+                Some(index) if matches!(self.input_mode, InputMode::Selected) => {
+                    self.set_display(index);
+                    self.select_display();
+                }
+                _ => {
+                    self.color = None;
+                    self.vcp = None;
+                    self.input_mode = InputMode::Select;
+                }
+            }
+        }
+        self.set_display(self.display_index);
+        if matches!(self.input_mode, InputMode::Loading) && !self.manager.displays.is_empty() {
+            self.input_mode = InputMode::Select;
+            if let Some(session) = self.restore.take() {
+                self.restore_session(session);
+            }
+        }
+    }
+
+    /// Applies the preset of the setup rule the displays match, once they changed.
+    fn apply_setup(&mut self) {
+        let Some(rule) = self
+            .setups
+            .check(&self.config.setup, &self.manager.displays)
+        else {
+            return;
+        };
+        let name = rule.preset.clone();
+        match self
+            .config
+            .presets
+            .iter()
+            .position(|preset| preset.name == name)
+        {
+            Some(index) => self.apply_preset(index, Origin::Setup),
+            None => self.preset_status = Some(format!("Setup: no preset named {name}")),
+        }
+    }
+
+    /// Shows when a display stopped answering, its list entry says so until it answers again.
+    fn poll_failures(&mut self) {
+        for failure in self.failures.try_iter() {
+            let detail = format!("unreachable: {}", failure.error);
+            events::record("unreachable", Some(&failure.name), &*detail);
+            self.status = Some(format!("{}: {detail}", failure.name));
+        }
+    }
+
+    /// Refreshes once a display was plugged in or out, after the running refresh if there is one.
+    fn poll_hotplug(&mut self) {
+        if let Some(hotplug) = &self.hotplug {
+            if hotplug.try_iter().count() > 0 {
+                self.hotplugged = true;
+            }
+        }
+        if self.hotplugged && !self.loading {
+            self.hotplugged = false;
+            events::record(
+                "hotplug",
+                None,
+                "a display was plugged in or out, refreshing",
+            );
+            self.start_refresh();
+        }
+    }
+
+    fn poll_update(&mut self) {
+        if let Some(release) = self
+            .update
+            .as_ref()
+            .and_then(|update| update.try_recv().ok())
+        {
+            self.release = Some(release);
+            self.update = None;
+        }
+    }
+
+    /// Runs an action from a key press or the command palette, returns whether to quit.
+    fn run_action(&mut self, action: Action) -> bool {
+        match action {
+            Action::FocusDisplay(index) => self.set_display(index),
+            Action::SelectDisplay(index) => {
+                self.set_display(index);
+                self.select_display();
+            }
+            Action::SelectFocused => self.select_display(),
+            Action::Back => {
+                self.show_help = false;
+                self.display_selected = None;
+                self.input_mode = InputMode::Select;
+            }
+            Action::PrevControl => self.prev_control(),
+            Action::NextControl => self.next_control(),
+            Action::FocusControl(control) => self.focus_control(control),
+            Action::Adjust(step) => self.add_to_control(step),
+            Action::AdjustPrimary(step) => self.add_to_primary(step),
+            Action::Set(control, value) => {
+                if let Some(display) = self.focused() {
+                    self.set_value(display, control, value);
+                }
+            }
+            Action::PressButton(index) => self.press_button(index),
+            Action::OpenEdit => {
+                // Composites have no range of their own, they stay on the arrow keys
+                if self.control_selected.is_some() {
+                    self.edit_input.clear();
+                    self.edit_error = None;
+                    self.input_mode = InputMode::Editing;
+                }
+            }
+            Action::EditInput(char) => {
+                self.edit_input.push(char);
+                self.edit_error = None;
+            }
+            Action::EditErase => {
+                self.edit_input.pop();
+                self.edit_error = None;
+            }
+            Action::ApplyEdit => {
+                let (Some(display), Some(controller)) =
+                    (self.display_selected.clone(), self.control_selected.clone())
+                else {
+                    return false;
+                };
+                let controller = *controller.read().unwrap();
+                match edited_value(&display, &controller, &self.edit_input) {
+                    Ok(value) => {
+                        self.input_mode = InputMode::Selected;
+                        return self.run_action(Action::Set(controller.kind, value));
+                    }
+                    Err(err) => self.edit_error = Some(err),
+                }
+            }
+            Action::CloseEdit => self.input_mode = InputMode::Selected,
+            Action::OpenRename => {
+                if let Some(display) = self.focused() {
+                    self.edit_input = display.alias().unwrap_or_default();
+                    self.edit_error = None;
+                    self.input_mode = InputMode::Renaming;
+                }
+            }
+            Action::ApplyRename => {
+                let Some(display) = self.focused() else {
+                    return false;
+                };
+                let alias = Some(self.edit_input.trim()).filter(|alias| !alias.is_empty());
+                match self.config.store_alias(display.alias_key(), alias) {
+                    Ok(()) => {
+                        display.set_alias(alias.map(str::to_string));
+                        self.manager.aliases = self.config.aliases.clone().into_iter().collect();
+                        return self.run_action(Action::CloseRename);
+                    }
+                    Err(err) => self.edit_error = Some(err.to_string()),
+                }
+            }
+            Action::OpenNote => {
+                if let Some(display) = &self.display_selected {
+                    self.edit_input = self.notes.get(display).unwrap_or_default().to_string();
+                    self.edit_error = None;
+                    self.input_mode = InputMode::EditingNote;
+                }
+            }
+            Action::ApplyNote => {
+                let Some(display) = self.display_selected.clone() else {
+                    return false;
+                };
+                match self.notes.set(&display, &self.edit_input) {
+                    Ok(()) => self.input_mode = InputMode::Selected,
+                    Err(err) => self.edit_error = Some(err.to_string()),
+                }
+            }
+            Action::CloseNote => self.input_mode = InputMode::Selected,
+            Action::ToggleMark => {
+                if let Some(display) = self.focused() {
+                    let key = display.alias_key();
+                    match self.marked.iter().position(|marked| marked == key) {
+                        Some(index) => drop(self.marked.remove(index)),
+                        None => self.marked.push(key.to_string()),
+                    }
+                }
+            }
+            Action::OpenGroup => match self.marked.len() {
+                0 | 1 => self.status = Some("Mark at least two displays first".to_string()),
+                _ => {
+                    self.edit_input.clear();
+                    self.edit_error = None;
+                    self.input_mode = InputMode::NamingGroup;
+                }
+            },
+            Action::ApplyGroup => {
+                let name = self.edit_input.trim().to_string();
+                if name.is_empty() {
+                    return self.run_action(Action::CloseGroup);
+                }
+                match self.config.store_group(&name, &self.marked) {
+                    Ok(()) => {
+                        self.status = Some(format!(
+                            "Saved group {name} of {} displays",
+                            self.marked.len()
+                        ));
+                        self.marked.clear();
+                        return self.run_action(Action::CloseGroup);
+                    }
+                    Err(err) => self.edit_error = Some(err.to_string()),
+                }
+            }
+            Action::CloseGroup => self.close_popup(),
+            Action::OpenEqualize => match self.start_equalizing() {
+                Ok(equalizer) => {
+                    self.equalizer = Some(equalizer);
+                    self.show_levels();
+                    self.input_mode = InputMode::Equalizing;
+                }
+                Err(err) => self.status = Some(err),
+            },
+            Action::AnswerEqualize(brighter) => {
+                if let Some(equalizer) = self.equalizer.as_mut().filter(|eq| !eq.settled()) {
+                    equalizer.answer(brighter);
+                    if !equalizer.settled() {
+                        self.show_levels();
+                    }
+                }
+            }
+            Action::SaveEqualize => {
+                let Some(equalizer) = self.equalizer.as_ref().filter(|eq| eq.settled()) else {
+                    return false;
+                };
+                let [first, second] = &equalizer.displays;
+                let group = &equalizer.group;
+                let known = &self.config.groups[group];
+                let member = known.member(second).cloned().unwrap_or_default();
+                let offset = known.offset(first) + equalizer.offset();
+                self.status = Some(match self.config.store_offset(group, &member, offset) {
+                    Ok(()) => format!(
+                        "Saved {} {:+}% brighter than {} in group {group}",
+                        second.label(),
+                        equalizer.offset(),
+                        first.label()
+                    ),
+                    Err(err) => format!("Saving the offset failed: {err}"),
+                });
+                self.marked.clear();
+                return self.run_action(Action::CloseEqualize);
+            }
+            Action::CloseEqualize => {
+                if let Some(equalizer) = self.equalizer.take() {
+                    for (display, value) in equalizer.displays.iter().zip(equalizer.before) {
+                        if let Some(controller) = display.controls.get(&Control::Brightness) {
+                            self.manager
+                                .queue_set(display.clone(), controller.clone(), value);
+                        }
+                    }
+                }
+                self.close_popup();
+            }
+            Action::OpenFilter => self.input_mode = InputMode::Filtering,
+            Action::FilterInput(char) => {
+                self.filter_mut().push(char);
+                self.follow_filter();
+            }
+            Action::FilterErase => {
+                self.filter_mut().pop();
+                self.follow_filter();
+            }
+            Action::ApplyFilter | Action::CloseFilter => {
+                let nothing_shown = match self.display_selected {
+                    Some(_) => self.shown_controls().is_empty(),
+                    None => self.shown_displays().is_empty(),
+                };
+                // A filter matching nothing would leave the highlight on a hidden row
+                if matches!(action, Action::CloseFilter) || nothing_shown {
+                    self.filter_mut().clear();
+                }
+                self.input_mode = match self.display_selected {
+                    Some(_) => InputMode::Selected,
+                    None => InputMode::Select,
+                }
+            }
+            Action::CloseRename => {
+                self.input_mode = match self.display_selected {
+                    Some(_) => InputMode::Selected,
+                    None => InputMode::Select,
+                }
+            }
+            Action::OpenCopy => {
+                if self.control_selected.is_some() {
+                    self.input_mode = InputMode::Copying;
+                }
+            }
+            Action::CopyControl(index) => {
+                self.copy_control(index);
+                self.input_mode = InputMode::Selected;
+            }
+            Action::CloseCopy => self.input_mode = InputMode::Selected,
+            Action::Hotkey(hotkey) => self.apply_hotkey(&hotkey),
+            Action::OpenPresets => {
+                self.preset_status = None;
+                self.input_mode = InputMode::Presets;
+            }
+            Action::FocusPreset(index) => {
+                self.preset_index = index.min(self.config.presets.len().saturating_sub(1))
+            }
+            Action::ApplyPreset(index) | Action::SavePreset(index) => {
+                self.preset_index = index;
+                if let Action::ApplyPreset(_) = action {
+                    self.apply_preset(index, Origin::Tui);
+                } else {
+                    self.save_preset();
+                }
+                self.input_mode = InputMode::Presets;
+            }
+            Action::ChangeStep(direction) => {
+                self.step_size = self.config.keys.next_step(self.step_size, direction).max(1)
+            }
+            Action::ToggleLinked => self.linked = !self.linked,
+            Action::Preview => match self.manager.previewing() {
+                true => self.input_mode = InputMode::ConfirmPreview,
+                false => {
+                    self.manager.start_preview();
+                    self.history.paused = true;
+                }
+            },
+            Action::ConfirmBlack => {
+                if let Some((display, controller)) = self.black.take() {
+                    self.manager.queue_set(display.clone(), controller, 0);
+                    self.record(Origin::Tui, &[display], Control::Brightness);
+                }
+                self.close_popup();
+            }
+            Action::AcceptSuggestion => {
+                if let Some(suggestion) = self.suggestion.take() {
+                    let [first, second] = &suggestion.displays;
+                    let name = suggestion
+                        .group
+                        .unwrap_or_else(|| format!("{} and {}", first.label(), second.label()));
+                    let members = [
+                        first.alias_key().to_string(),
+                        second.alias_key().to_string(),
+                    ];
+                    let saved = self.config.store_group(&name, &members).and_then(|()| {
+                        self.config
+                            .store_offset(&name, &members[1], suggestion.offset)
+                    });
+                    self.status = Some(match saved {
+                        Ok(()) => format!(
+                            "Saved synced group {name}, {} {:+}% brighter than {}",
+                            second.label(),
+                            suggestion.offset,
+                            first.label()
+                        ),
+                        Err(err) => format!("Saving the group failed: {err}"),
+                    });
+                }
+                self.close_popup();
+            }
+            Action::DismissSuggestion => {
+                self.suggestion = None;
+                self.close_popup();
+            }
+            Action::CloseBlack => {
+                self.black = None;
+                self.close_popup();
+            }
+            Action::CommitPreview | Action::DiscardPreview => {
+                self.history.paused = false;
+                match action {
+                    Action::CommitPreview => {
+                        for (display, control, value) in self.manager.commit_preview() {
+                            self.history
+                                .record(Origin::Tui, &display, control as u8, value);
+                        }
+                    }
+                    _ => self.manager.discard_preview(),
+                }
+                self.input_mode = match self.display_selected {
+                    Some(_) => InputMode::Selected,
+                    None => InputMode::Select,
+                };
+            }
+            Action::OpenHistory => {
+                self.flush_history();
+                self.history_entries = match Log::read() {
+                    Ok(entries) => entries,
+                    Err(err) => {
+                        self.status = Some(format!("Reading the history failed: {err}"));
+                        vec![]
+                    }
+                };
+                self.input_mode = InputMode::History;
+            }
+            Action::OpenQueue => {
+                self.queue_index = 0;
+                self.input_mode = InputMode::Queue;
+            }
+            Action::FocusQueued(index) => {
+                self.queue_index = index.min(self.manager.queued().len().saturating_sub(1));
+            }
+            Action::DiscardQueued { all } => {
+                let queued = self.manager.queued();
+                let discarded: Vec<_> = match all {
+                    true => queued.iter().collect(),
+                    false => queued.get(self.queue_index).into_iter().collect(),
+                };
+                for change in discarded {
+                    self.manager
+                        .discard_change(&change.display.id, change.control);
+                }
+                let left = self.manager.queued().len();
+                self.queue_index = self.queue_index.min(left.saturating_sub(1));
+            }
+            Action::RetryQueued => self.manager.retry_parked(),
+            Action::CloseQueue => {
+                self.input_mode = match self.display_selected {
+                    Some(_) => InputMode::Selected,
+                    None => InputMode::Select,
+                }
+            }
+            // The colour screen writes right away, it would leak past the preview
+            Action::OpenColor if self.manager.previewing() => {
+                self.status = Some("Commit or discard the preview first (P)".to_string());
+            }
+            Action::OpenColor if self.color.as_ref().is_some_and(ColorPanel::is_busy) => {
+                self.status = Some("Still writing the colour".to_string());
+            }
+            Action::OpenColor => {
+                if let Some(display) = &self.display_selected {
+                    self.color = Some(ColorPanel::new(display.clone()));
+                    let (read, shown) = (display.clone(), display.clone());
+                    self.background(
+                        move || ColorPanel::read(&read),
+                        move |app, cells| match &mut app.color {
+                            Some(panel) if Arc::ptr_eq(&panel.display, &shown) => {
+                                panel.loaded(cells)
+                            }
+                            _ => {}
+                        },
+                    );
+                    self.input_mode = InputMode::Color;
+                }
+            }
+            Action::MoveColor(rows, columns) => {
+                if let Some(panel) = &mut self.color {
+                    panel.move_by(rows, columns);
+                }
+            }
+            Action::AdjustColor(step) => {
+                if let Some(panel) = &mut self.color {
+                    panel.adjust(step);
+                    self.write_color();
+                }
+            }
+            Action::CloseColor => {
+                // Values still waiting are written all the same
+                if !self.color.as_ref().is_some_and(ColorPanel::is_busy) {
+                    self.color = None;
+                }
+                self.input_mode = InputMode::Selected;
+            }
+            Action::OpenPalette => {
+                self.palette_query.clear();
+                self.palette_index = 0;
+                self.show_help = false;
+                self.input_mode = InputMode::Palette;
+            }
+            Action::PaletteInput(char) => {
+                self.palette_query.push(char);
+                self.palette_index = 0;
+            }
+            Action::PaletteErase => {
+                self.palette_query.pop();
+                self.palette_index = 0;
+            }
+            Action::PaletteMove(delta) => {
+                self.palette_index = self.palette_index.saturating_add_signed(delta)
+            }
+            Action::PaletteRun => {
+                let action = palette::matches(self, &self.palette_query)
+                    .into_iter()
+                    .nth(self.palette_index);
+                if let Some((_, action)) = action {
+                    self.close_popup();
+                    return self.run_action(action);
+                }
+            }
+            Action::ClosePalette => self.close_popup(),
+            Action::Identify => self.identify(),
+            Action::TogglePower => self.toggle_power(),
+            // Previewed values would be written over the defaults on commit
+            Action::OpenReset if self.manager.previewing() => {
+                self.status = Some("Commit or discard the preview first (P)".to_string());
+            }
+            Action::OpenReset => {
+                if self.display_selected.is_some() {
+                    self.input_mode = InputMode::ConfirmReset;
+                }
+            }
+            Action::Reset { all } => {
+                self.reset(all);
+                self.input_mode = InputMode::Selected;
+            }
+            Action::CloseReset => self.input_mode = InputMode::Selected,
+            Action::ToggleCapabilities => {
+                if let Some(display) = &self.display_selected {
+                    if !self.capabilities_shown.remove(&display.id) {
+                        self.capabilities_shown.insert(display.id.clone());
+                    }
+                    self.capabilities_scroll = 0;
+                }
+            }
+            Action::ScrollCapabilities(lines) => {
+                self.capabilities_scroll = self.capabilities_scroll.saturating_add_signed(lines)
+            }
+            Action::ToggleLog => self.log_shown = !self.log_shown,
+            Action::ToggleLayout => self.wall = !self.wall,
+            Action::OpenHealth => {
+                if let Some(display) = &self.display_selected {
+                    self.health.clear();
+                    self.status = Some("Reading the diagnostic codes".to_string());
+                    let (configured, display) = (self.config.readouts.clone(), display.clone());
+                    self.background(
+                        move || {
+                            let mut health: Vec<(String, Option<String>)> = health::read(&display)
+                                .into_iter()
+                                .map(|reading| (reading.name.to_string(), reading.value.ok()))
+                                .collect();
+                            // Vendor codes of the quirk database, after the ones of MCCS
+                            for readout in quirks::readouts(&configured, &display) {
+                                if health::CODES.iter().any(|(code, _)| *code == readout.code) {
+                                    continue;
+                                }
+                                let value = display.get_vcp(readout.code).ok();
+                                let value = value.map(|value| format!("{value}{}", readout.unit));
+                                health.push((readout.name, value));
+                            }
+                            health
+                        },
+                        |app, health| {
+                            app.health = health;
+                            app.status = None;
+                        },
+                    );
+                    self.input_mode = InputMode::Health;
+                }
+            }
+            Action::CloseHealth => self.input_mode = InputMode::Selected,
+            // Raw writes would go around the preview
+            Action::OpenVcp if self.manager.previewing() => {
+                self.status = Some("Commit or discard the preview first (P)".to_string());
+            }
+            Action::OpenVcp => {
+                if self.display_selected.is_some() {
+                    self.vcp = Some(VcpForm::default());
+                    self.input_mode = InputMode::Vcp;
+                }
+            }
+            Action::VcpInput(char) => {
+                if let Some(form) = &mut self.vcp {
+                    form.field().push(char);
+                }
+            }
+            Action::VcpErase => {
+                if let Some(form) = &mut self.vcp {
+                    form.field().pop();
+                }
+            }
+            Action::VcpSwitch => {
+                if let Some(form) = &mut self.vcp {
+                    form.on_value = !form.on_value;
+                }
+            }
+            Action::VcpRun => {
+                let (Some(form), Some(display)) = (&mut self.vcp, &self.display_selected) else {
+                    return false;
+                };
+                if let Some((code, value)) = form.run(display) {
+                    self.history.record(Origin::Tui, display, code, value);
+                }
+            }
+            Action::CloseVcp => {
+                self.vcp = None;
+                self.input_mode = InputMode::Selected;
+            }
+            Action::OpenChangelog => {
+                if self.release.is_some() {
+                    self.input_mode = InputMode::Changelog;
+                }
+            }
+            Action::Refresh => self.request_refresh(),
+            Action::Reload { flush } => {
+                if flush {
+                    self.manager.flush();
+                } else {
+                    self.manager.discard();
+                }
+                self.input_mode = InputMode::Select;
+                self.start_refresh();
+            }
+            Action::Help => {
+                self.input_mode = InputMode::Help;
+                self.show_help = true;
+            }
+            Action::Quit => return true,
+        }
+        false
+    }
+
+    /// Goes back to the controls of the selected display, or the display list.
+    /// Matching of the two marked displays, if both are connected, have a brightness and are in
+    /// a synced group to save the offset to.
+    fn start_equalizing(&self) -> Result<Equalizer, String> {
+        let marked: Vec<WrappedDisplay> = self
+            .marked
+            .iter()
+            .filter_map(|key| {
+                let displays = self.manager.displays.iter();
+                displays.clone().find(|display| display.alias_key() == key)
+            })
+            .cloned()
+            .collect();
+        let Ok([first, second]) = <[WrappedDisplay; 2]>::try_from(marked) else {
+            return Err("Mark the two displays to match first".to_string());
+        };
+        let group = self
+            .config
+            .groups
+            .iter()
+            .filter(|(_, group)| group.sync && group.contains(&first) && group.contains(&second))
+            .map(|(name, _)| name.clone())
+            .min()
+            .ok_or(format!(
+                "Group {} and {} first, the offset is saved to their group",
+                first.label(),
+                second.label()
+            ))?;
+        let mut before = [0; 2];
+        let mut percent = 0.0;
+        for (index, display) in [&first, &second].into_iter().enumerate() {
+            let Some(controller) = display.controls.get(&Control::Brightness) else {
+                return Err(format!("{} has no brightness", display.label()));
+            };
+            let controller = controller.read().unwrap();
+            before[index] = controller.value;
+            if index == 0 {
+                percent = display.percent(&controller) as f32;
+            }
+        }
+        Ok(Equalizer::new([first, second], group, before, percent))
+    }
+
+    /// Sets both displays being matched to the brightness compared now.
+    fn show_levels(&self) {
+        let Some(equalizer) = &self.equalizer else {
+            return;
+        };
+        for (display, percent) in equalizer.displays.iter().zip(equalizer.levels()) {
+            if let Some(controller) = display.controls.get(&Control::Brightness) {
+                let maximum = controller.read().unwrap().maximum;
+                let value = display.resolve(Control::Brightness, Level::Percent(percent), maximum);
+                self.manager
+                    .queue_set(display.clone(), controller.clone(), value);
+            }
+        }
+    }
+
+    fn close_popup(&mut self) {
+        self.input_mode = match self.display_selected {
+            Some(_) => InputMode::Selected,
+            None => InputMode::Select,
+        };
+    }
+
+    /// Queues a value for a control of the display and the synced groups it is in.
+    fn set_value(&mut self, display: WrappedDisplay, control: Control, value: u16) {
+        let Some(controller) = display.controls.get(&control).cloned() else {
+            return;
+        };
+        let Some(value) = self.interlock(&display, &controller, value) else {
+            return;
+        };
+        self.manager.queue_set(display.clone(), controller, value);
+        let mut changed = vec![display.clone()];
+        changed.extend(self.sync_groups(&display, control));
+        self.record(Origin::Tui, &changed, control);
+        if control == Control::InputSource {
+            self.switch_audio(&[display]);
+        }
+    }
+
+    /// Does what the button lists, telling what went wrong in the status bar.
+    fn press_button(&mut self, index: usize) {
+        let Some(button) = self.config.buttons.get(index).cloned() else {
+            return;
+        };
+        let mut failed = vec![];
+        let done = format!("{} done", button.label);
+        if let Some(name) = &button.preset {
+            match self
+                .config
+                .presets
+                .iter()
+                .position(|preset| &preset.name == name)
+            {
+                // How it went shows once it is written
+                Some(index) => self.apply_preset(index, Origin::Tui),
+                None => failed.push(format!("no preset named {name}")),
+            }
+        }
+        let set = button
+            .set
+            .iter()
+            .map(|(control, amount)| (*control, std::slice::from_ref(amount)));
+        let toggle = button
+            .toggle
+            .iter()
+            .map(|(control, amounts)| (*control, amounts.as_slice()));
+        let amounts: Vec<(Control, &[Amount])> = set.chain(toggle).collect();
+        for display in self.targets(&button.target) {
+            for (control, amounts) in &amounts {
+                let Some(controller) = display.controls.get(control) else {
+                    continue;
+                };
+                let value = next_value(&display, &controller.read().unwrap(), *control, amounts);
+                match value {
+                    Ok(value) => self.set_value(display.clone(), *control, value),
+                    Err(err) => failed.push(err),
+                }
+            }
+        }
+        if let Some(command) = &button.run {
+            let mut words = command.split_whitespace();
+            let started = words.next().map(|program| {
+                process::Command::new(program)
+                    .args(words)
+                    .stdin(process::Stdio::null())
+                    .stdout(process::Stdio::null())
+                    .stderr(process::Stdio::null())
+                    .spawn()
+            });
+            match started {
+                // Waited for on the side so it doesn't linger as a zombie
+                Some(Ok(mut child)) => drop(thread::spawn(move || child.wait())),
+                Some(Err(err)) => failed.push(format!("{command}: {err}")),
+                None => failed.push("run is empty".to_string()),
+            }
+        }
+        self.status = Some(match failed.is_empty() {
+            true => done,
+            false => format!("{}: {}", button.label, failed.join(", ")),
+        });
+    }
+
+    /// The displays a hotkey or button goes to.
+    fn targets(&self, target: &Target) -> Vec<WrappedDisplay> {
+        match target {
+            Target::Focused => self.focused().into_iter().collect(),
+            Target::All => self.manager.displays.clone(),
+            Target::Group(group) => self
+                .manager
+                .displays
+                .iter()
+                .filter(|display| self.config.in_group(group, display))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn apply_hotkey(&mut self, hotkey: &Hotkey) {
+        let targets = self.targets(&hotkey.target);
+        for display in &targets {
+            if let Some(controller) = display.controls.get(&hotkey.control) {
+                self.queue_change(display, controller, hotkey.step);
+            }
+        }
+        self.record(Origin::Hotkey, &targets, hotkey.control);
+    }
+}
+
+/// The value of the amounts after the one the control is at, the first if it is at none of them.
+fn next_value(
+    display: &MyDisplay,
+    controller: &Controller,
+    control: Control,
+    amounts: &[Amount],
+) -> Result<u16, String> {
+    let maximum = controller.write_bound();
+    let values = amounts
+        .iter()
+        .map(|amount| amount.value(display, control, maximum))
+        .collect::<Result<Vec<_>, _>>()?;
+    let next = values
+        .iter()
+        .position(|value| *value == controller.value)
+        .map_or(0, |index| (index + 1) % values.len());
+    Ok(values[next])
+}
+
+/// Runs the interactive interface until the user quits, loading the displays on the way.
+pub fn run(config: Config, manager: DisplayManager) -> Result<(), Box<dyn Error>> {
+    let mut app = App::new(config, manager, Saved::load()?, Notes::load()?);
+    app.input_mode = InputMode::Loading;
+    app.start_refresh();
+    #[cfg(all(any(target_os = "linux", windows), feature = "hotplug"))]
+    match app.manager.watch_hotplug() {
+        Ok(hotplug) => app.hotplug = Some(hotplug),
+        Err(err) => app.status = Some(err.to_string()),
+    }
+    if app.config.update_check {
+        app.update = Some(update::check());
+    }
+
+    // A panic would otherwise leave the terminal in raw mode, its message hidden on the
+    // alternate screen
+    let default_hook = panic::take_hook();
+    // Terminals that report repeats and releases tell a held key apart from taps right away
+    let enhanced = supports_keyboard_enhancement().unwrap_or(false);
+    panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        if enhanced {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableFocusChange,
+            DisableMouseCapture
+        );
+        default_hook(info);
+    }));
+
+    // setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnableFocusChange, EnableMouseCapture)?;
+    // Without an alternate screen the TUI is drawn in the lines below the prompt instead
+    let alternate = execute!(stdout, EnterAlternateScreen).is_ok();
+    if !alternate {
+        app.status = Some("The terminal has no alternate screen, drawing inline".to_string());
+    }
+    if enhanced {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )?;
+    }
+    let backend = CrosstermBackend::new(stdout);
+    let viewport = match alternate {
+        true => Viewport::Fullscreen,
+        false => Viewport::Inline(INLINE_HEIGHT),
+    };
+    let mut terminal = Terminal::with_options(backend, TerminalOptions { viewport })?;
+
+    terminal.clear()?;
+
+    // create app and run it
+    let res = run_app(&mut terminal, app);
+
+    // restore terminal
+    disable_raw_mode()?;
+    if enhanced {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
+    match alternate {
+        true => execute!(terminal.backend_mut(), LeaveAlternateScreen)?,
+        // The prompt comes back where the TUI was
+        false => terminal.clear()?,
+    }
+    execute!(
+        terminal.backend_mut(),
+        DisableFocusChange,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+
+    if let Err(err) = res {
+        println!("{err:?}");
+    }
+
+    Ok(())
+}
+
+/// What the main loop waits for
+enum Message {
+    Input(Event),
+    /// A background thread changed something shown
+    Changed,
+    /// Reading the terminal failed
+    Failed(io::Error),
+    /// A background thread finished, see [`App::background`]
+    Done(Box<dyn FnOnce(&mut App) + Send>),
+}
+
+/// Sends the terminal's events to the main loop until `done` is set.
+fn read_input(sender: &Sender<Message>, done: &AtomicBool) {
+    while !done.load(Ordering::SeqCst) {
+        let message = match event::poll(INPUT_POLL) {
+            Ok(false) => continue,
+            Ok(true) => event::read().map_or_else(Message::Failed, Message::Input),
+            Err(err) => Message::Failed(err),
+        };
+        if sender.send(message).is_err() {
+            return;
+        }
+    }
+}
+
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
+    let (sender, messages) = channel();
+    let done = Arc::new(AtomicBool::new(false));
+    let input = {
+        let (sender, done) = (sender.clone(), done.clone());
+        thread::spawn(move || read_input(&sender, &done))
+    };
+    app.sender = Some(sender.clone());
+    // Problems of the background features would garble the screen on stderr
+    {
+        let sender = sender.clone();
+        logging::report_to(Some(Box::new(move |message| {
+            let shown = Box::new(move |app: &mut App| app.status = Some(message));
+            let _ = sender.send(Message::Done(shown));
+        })));
+    }
+    // However many changes come in, only one wake-up waits for the loop at a time
+    let waiting = Arc::new(AtomicBool::new(false));
+    {
+        let waiting = waiting.clone();
+        app.manager.set_waker(move || {
+            if !waiting.swap(true, Ordering::SeqCst) {
+                let _ = sender.send(Message::Changed);
+            }
+        });
+    }
+    let result = event_loop(terminal, &mut app, &messages, &waiting);
+    app.manager.set_waker(|| ());
+    logging::report_to(None);
+    done.store(true, Ordering::SeqCst);
+    let _ = input.join();
+    result
+}
+
+/// Redraws whenever input comes in, a background thread changed something or the tick is due.
+fn event_loop<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    messages: &Receiver<Message>,
+    waiting: &AtomicBool,
+) -> io::Result<()> {
+    loop {
+        app.run_schedule();
+        app.run_ambient();
+        app.poll_refresh();
+        app.poll_hotplug();
+        app.poll_failures();
+        app.poll_update();
+        app.run_ramp();
+        app.manager.watch(app.focused().as_deref());
+        terminal.draw(|f| ui(f, app))?;
+
+        // Redraw now and then so polled values show up, often while the spinner turns or a
+        // held key ramps
+        let interval = if app.ramp.is_some() {
+            ramp::TICK
+        } else if app.loading {
+            SPINNER_INTERVAL
+        } else {
+            REDRAW_INTERVAL
+        };
+        let event = match messages.recv_timeout(interval) {
+            Ok(Message::Input(event)) => event,
+            Ok(Message::Changed) => {
+                waiting.store(false, Ordering::SeqCst);
+                app.flush_history();
+                app.learn();
+                continue;
+            }
+            Ok(Message::Failed(err)) => return Err(err),
+            Ok(Message::Done(done)) => {
+                done(app);
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                app.flush_history();
+                app.learn();
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return Err(io::ErrorKind::BrokenPipe.into()),
+        };
+        match event {
+            Event::FocusGained => app.manager.set_focused(true),
+            Event::FocusLost => app.manager.set_focused(false),
+            // A key let go right after the press would clear what the press put in the footer
+            Event::Key(key) if key.kind != KeyEventKind::Release => {
+                app.manager.note_input();
+                app.status = None;
+            }
+            Event::Mouse(_) => app.manager.note_input(),
+            Event::Resize(_, _) => {
+                terminal.autoresize()?;
+                continue;
+            }
+            _ => (),
+        }
+        let action = match event {
+            Event::Key(key) if app.hold_key(key) => continue,
+            Event::Key(key) => action::for_key(app, key),
+            Event::Mouse(mouse) => action::for_mouse(app, mouse),
+            _ => None,
+        };
+        if let Some(action) = action {
+            if app.run_action(action) {
+                // Not worth keeping the terminal up to tell
+                let _ = app.session().store();
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_y) / 2),
+                Constraint::Percentage(percent_y),
+                Constraint::Percentage((100 - percent_y) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_x) / 2),
+                Constraint::Percentage(percent_x),
+                Constraint::Percentage((100 - percent_x) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(popup_layout[1])[1]
+}
+
+fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let size = f.size();
+    if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+        let msg = vec![
+            Line::from("Terminal too small"),
+            Line::from(format!(
+                "Please enlarge it to at least {MIN_WIDTH}x{MIN_HEIGHT} (now {}x{})",
+                size.width, size.height
+            )),
+        ];
+        let paragraph = Paragraph::new(msg)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+        let area = Rect {
+            y: size.height.saturating_sub(4) / 2,
+            height: size.height.min(4),
+            ..size
+        };
+        f.render_widget(paragraph, area);
+        return;
+    }
+
+    if let InputMode::Loading = app.input_mode {
+        loading_screen(f, app);
+        return;
+    }
+
+    let outer = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Min(0),
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(!app.config.buttons.is_empty() as u16),
+            ]
+            .as_ref(),
+        )
+        .split(size);
+    let (row, areas) = button_row(&app.config.buttons, &app.theme, outer[3]);
+    f.render_widget(row, outer[3]);
+    app.button_areas = areas;
+    let lists = match app.log_shown {
+        true => {
+            let parts = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(LOG_HEIGHT)].as_ref())
+                .split(outer[0]);
+            f.render_widget(log_pane(parts[1].height), parts[1]);
+            parts[0]
+        }
+        false => outer[0],
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(lists);
+    let footer = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(0), Constraint::Length(32)].as_ref())
+        .split(outer[1]);
+    if let Some(status) = &app.status {
+        f.render_widget(Paragraph::new(format!(" {status}")).red(), footer[0]);
+    } else if let Some(display) = app.focused() {
+        f.render_widget(metrics_footer(&app.manager, &display), footer[0]);
+    }
+    f.render_widget(status_bar(&app.manager, app.step()), outer[2]);
+    if let Some((at, rule)) = schedule::next(&app.config, Local::now()) {
+        let next = format!("next: {} {} ", rule.describe(), at.format("%a %H:%M"));
+        f.render_widget(
+            Paragraph::new(next)
+                .alignment(Alignment::Right)
+                .style(Style::default().fg(Color::DarkGray)),
+            footer[1],
+        );
+    }
+
+    // let offset = app.vertical_display_state.offset_mut();
+    // *offset = app.scroll_display;
+
+    let typing = matches!(app.input_mode, InputMode::Filtering);
+    let shown = app.shown_displays();
+    // The alias is typed into the row of the display being renamed
+    let renaming = match app.input_mode {
+        InputMode::Renaming => app.focused(),
+        _ => None,
+    };
+    let renamed = |display: &WrappedDisplay| {
+        renaming
+            .as_ref()
+            .is_some_and(|renamed| Arc::ptr_eq(renamed, display))
+    };
+    let display_widget: Vec<ListItem> = app
+        .manager
+        .displays
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| shown.contains(i))
+        .map(|(i, display)| {
+            let mut content = Line::from(format!("{}: ", i + 1));
+            if renamed(display) {
+                content.spans.push(Span::styled(
+                    app.edit_input.clone(),
+                    Style::default().add_modifier(Modifier::UNDERLINED),
+                ));
+                return ListItem::new(content);
+            }
+            let label = filter::highlight(&display.label(), &app.display_filter);
+            content.spans.extend(label);
+            // Identical models are told apart by where they are connected
+            let ambiguous = app
+                .manager
+                .displays
+                .iter()
+                .filter(|other| other.label() == display.label())
+                .count()
+                > 1;
+            if let Some(output) = display.output.as_ref().filter(|_| ambiguous) {
+                content.spans.push(Span::styled(
+                    format!(" {output}"),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            if app.marked.iter().any(|key| key == display.alias_key()) {
+                content.spans.push(" +".cyan());
+            }
+            let mut synced: Vec<&String> = (app.config.groups.iter())
+                .filter(|(_, group)| group.sync && group.contains(display))
+                .map(|(name, _)| name)
+                .collect();
+            synced.sort();
+            for name in synced {
+                content.spans.push(Span::styled(
+                    format!(" [{name}]"),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            if display.is_stale() {
+                content.spans.push(" (disconnected)".dark_gray());
+            } else if display.is_powered_off() {
+                content.spans.push(" (off)".dark_gray());
+            } else if display.is_lenient() {
+                // Only the status line tells about failures of lenient displays
+            } else if display.is_unreachable() {
+                content.spans.push(" (unreachable)".red());
+            } else if display.write_error().is_some() {
+                content.spans.push(" !".red());
+            }
+            ListItem::new(content)
+        })
+        .collect();
+
+    let title = if let Some(display) = &renaming {
+        match &app.edit_error {
+            Some(err) => format!("Displays - {err}"),
+            None => format!("Displays - alias of {}", display.name),
+        }
+    } else if app.loading {
+        format!("Displays - loading {}", app.spin())
+    } else if app.manager.previewing() {
+        format!(
+            "Displays - preview, {} values held",
+            app.manager.previewed()
+        )
+    } else {
+        match (app.manager.warnings.len(), &app.release) {
+            (0, Some(release)) => format!("Displays - {} is out, u for what's new", release.tag),
+            (0, None) => "Displays".to_string(),
+            (1, _) => format!("Displays - {}", app.manager.warnings[0]),
+            (len, _) => format!("Displays - {len} warnings"),
+        }
+    };
+    let typing_here = typing && app.display_selected.is_none();
+    let title = filter::title(title, &app.display_filter, typing_here);
+    let mut display_block = Block::default().borders(Borders::ALL).title(title);
+    if app.display_selected.is_none() {
+        display_block = display_block.border_style(app.theme.accent)
+    }
+    app.display_area = chunks[0];
+    if app.wall && renaming.is_none() {
+        let area = display_block.inner(chunks[0]);
+        f.render_widget(display_block, chunks[0]);
+        draw_wall(f, app, &shown, area);
+    } else {
+        let display_widget = List::new(display_widget)
+            .highlight_style(app.theme.selection)
+            .highlight_symbol("> ")
+            .block(display_block);
+
+        let offset = if app.display_filter.is_empty() {
+            f.render_stateful_widget(display_widget, chunks[0], &mut app.display_widget_state);
+            app.display_widget_state.offset()
+        } else {
+            // The list only has the matches, the highlight goes by its place among them
+            let mut state = ListState::default()
+                .with_selected(shown.iter().position(|&i| i == app.display_index));
+            f.render_stateful_widget(display_widget, chunks[0], &mut state);
+            state.offset()
+        };
+        let renamed_index = app.manager.displays.iter().position(renamed);
+        if let Some(index) = renamed_index {
+            let row = shown.iter().position(|&i| i == index).unwrap_or(0);
+            // After the border, the highlight symbol and the number
+            let column = 1 + 2 + format!("{}: ", index + 1).len() + app.edit_input.chars().count();
+            let row = row.saturating_sub(offset);
+            f.set_cursor(chunks[0].x + column as u16, chunks[0].y + 1 + row as u16);
+        }
+    }
+
+    if let Some(display) = &app.display_selected {
+        let mut control_widget: Vec<ListItem> = display
+            .controls
+            .iter()
+            .map(|(control, controller)| {
+                let controller = controller.read().unwrap();
+                let mapped = display.mapping(*control).is_some();
+                let value = if app.linked {
+                    linked_values(&app.manager, *control)
+                } else if mapped {
+                    format!("{}%", display.percent(&controller))
+                } else {
+                    display.describe(*control, controller.value)
+                };
+                let mut content =
+                    Line::from(filter::highlight(control.get_name(), &app.control_filter));
+                content.spans.push(": ".into());
+                // Dimmed with a question mark while the display may have moved on
+                match controller.is_unconfirmed() && !controller.previewed && !app.linked {
+                    true => content.spans.extend([Span::from(value).dim(), "?".dim()]),
+                    false => content.spans.push(Span::from(value)),
+                }
+                // What a curve makes of the percent shown
+                if mapped && !app.linked {
+                    content.spans.push(
+                        Span::from(format!(" ({}/{})", controller.value, controller.maximum))
+                            .dark_gray(),
+                    );
+                // Raw values only mean something next to the range the display uses
+                } else if control.is_continuous() && controller.maximum != 100 {
+                    content.spans.push(
+                        Span::from(format!(
+                            "/{} ({}%)",
+                            controller.maximum,
+                            controller.percent()
+                        ))
+                        .dark_gray(),
+                    );
+                }
+                if *control == Control::Volume && app.config.volume_unit == VolumeUnit::Db {
+                    let db = match units::to_db(controller.value, controller.maximum) {
+                        Some(db) => format!(" ({db:.1} dB)"),
+                        None => " (muted)".to_string(),
+                    };
+                    content.spans.push(Span::from(db).dark_gray());
+                }
+                if controller.pending {
+                    content
+                        .spans
+                        .push(" (pending until display reconnects)".dark_gray());
+                }
+                if controller.previewed {
+                    content.spans.push(" (preview)".yellow());
+                }
+                if display.unadvertised.contains(control) {
+                    content.spans.push(" (not advertised)".dark_gray());
+                }
+                let default = app
+                    .defaults
+                    .get(&display.id)
+                    .and_then(|defaults| defaults.get(control));
+                if let Some(default) = default.filter(|default| **default != controller.value) {
+                    let default = display.describe(*control, *default);
+                    content
+                        .spans
+                        .push(Span::from(format!(" (default {default})")).dark_gray());
+                }
+                ListItem::new(content)
+            })
+            .collect();
+        for composite in &app.config.composites {
+            let mut content = Line::from(filter::highlight(&composite.name, &app.control_filter));
+            let value = app.composite_value(display, composite);
+            content.spans.push(format!(": {value}").into());
+            control_widget.push(ListItem::new(content));
+        }
+        if temperature::offered(display, &app.config.temperature) {
+            let mut content =
+                Line::from(filter::highlight("Colour temperature", &app.control_filter));
+            let value = app.temperature(display);
+            content.spans.push(format!(": {value} K").into());
+            control_widget.push(ListItem::new(content));
+        }
+        let shown = app.shown_controls();
+        let control_widget: Vec<ListItem> = control_widget
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| shown.contains(i))
+            .map(|(_, item)| item)
+            .collect();
+        let control_widget = List::new(control_widget)
+            .highlight_style(app.theme.selection)
+            .highlight_symbol("> ")
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(app.theme.accent)
+                    .title(filter::title(
+                        match (app.linked, app.manager.previewing()) {
+                            (true, _) => {
+                                format!("Controls - {} (linked to all displays)", display.label())
+                            }
+                            (false, true) => {
+                                format!("Controls - {} (preview, not written)", display.label())
+                            }
+                            (false, false) => format!("Controls - {}", display.label()),
+                        },
+                        &app.control_filter,
+                        typing,
+                    )),
+            );
+        let capabilities_shown = app.capabilities_shown.contains(&display.id);
+        let note = app.notes.get(display).map(str::to_string);
+        // The note wraps, a line of the panel fits its width less the borders
+        let note_lines = note.as_ref().map_or(0, |note| {
+            let width = chunks[1].width.saturating_sub(2).max(1) as usize;
+            (note.chars().count() + 6).div_ceil(width)
+        });
+        let details_lines = app.readings.len() + app.reading as usize + note_lines;
+        let control_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Min(0),
+                    Constraint::Percentage(match capabilities_shown {
+                        true => 50,
+                        false => 0,
+                    }),
+                    Constraint::Length(match details_lines {
+                        0 => 0,
+                        len => len as u16 + 2,
+                    }),
+                ]
+                .as_ref(),
+            )
+            .split(chunks[1]);
+        if app.control_filter.is_empty() {
+            f.render_stateful_widget(
+                control_widget,
+                control_chunks[0],
+                &mut app.control_widget_state,
+            );
+        } else {
+            let mut state = ListState::default()
+                .with_selected(shown.iter().position(|&i| i == app.control_index));
+            f.render_stateful_widget(control_widget, control_chunks[0], &mut state);
+        }
+
+        if details_lines > 0 {
+            let mut details: Vec<Line> = app
+                .readings
+                .iter()
+                .map(|(readout, value)| {
+                    let value = match value {
+                        Some(value) => format!("{value}{0}", readout.unit),
+                        None => "unsupported".to_string(),
+                    };
+                    Line::from(vec![format!("{0}: ", readout.name).into(), value.into()])
+                })
+                .collect();
+            if app.reading {
+                details.push(Line::from("Reading the diagnostic codes..."));
+            }
+            if let Some(note) = note {
+                details.push(Line::from(vec!["Note: ".into(), note.into()]));
+            }
+            let details = Paragraph::new(details)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title("Details"));
+            f.render_widget(details, control_chunks[2]);
+        }
+
+        if capabilities_shown {
+            let lines = capability_lines(display);
+            // Scrolling past the end stops at the last line
+            app.capabilities_scroll = app.capabilities_scroll.min(lines.len().saturating_sub(1));
+            let capabilities = Paragraph::new(lines)
+                .scroll((app.capabilities_scroll as u16, 0))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("Capabilities - PgUp/PgDn to scroll"),
+                );
+            f.render_widget(capabilities, control_chunks[1]);
+        }
+    }
+
+    if app.show_help {
+        let keys = &app.config.keys;
+        let key = app.theme.help.add_modifier(Modifier::BOLD);
+        let mut msg = vec![];
+        for binding in HELP {
+            let bound = match binding {
+                Binding::Display(_) if !DISPLAYS.iter().any(|d| keys.bindings.contains_key(d)) => {
+                    "1-9".to_string()
+                }
+                Binding::Display(_) => DISPLAYS
+                    .iter()
+                    .flat_map(|display| keys.keys(*display))
+                    .map(|key| key.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                binding => keys
+                    .keys(binding)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            let mut description = binding.describe().to_string();
+            if let Binding::StepUp | Binding::StepDown = binding {
+                description += &format!(", now {}", app.step_size);
+            }
+            msg.push(Line::from(vec![
+                Span::styled(bound, key),
+                format!(" {description}").into(),
+            ]));
+        }
+        msg.push(Line::from(vec![
+            Span::styled("Shift+arrows, scroll", key),
+            format!(
+                " adjust {} of the highlighted display",
+                keys.primary.get_name()
+            )
+            .into(),
+        ]));
+        // Where the navigation block sits on letters of the layout, they don't do what they say
+        for (char, binding, left) in keys.shadowed() {
+            let left = match left.is_empty() {
+                true => "the palette".to_string(),
+                false => (left.iter().map(ToString::to_string))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            msg.push(Line::from(vec![
+                Span::styled(char.to_string(), key),
+                format!(
+                    " navigates in the lists, there {} is on {left}",
+                    binding.describe()
+                )
+                .into(),
+            ]));
+        }
+        for (shortcut, control) in &app.config.keys.controls {
+            msg.push(Line::from(vec![
+                Span::styled(shortcut.to_string(), key),
+                format!(" highlight {}", control.get_name()).into(),
+            ]));
+        }
+        for hotkey in &app.config.hotkeys {
+            let Some(name) = hotkey.key else {
+                continue;
+            };
+            let target = match &hotkey.target {
+                Target::Focused => "focused display".to_string(),
+                Target::All => "all displays".to_string(),
+                Target::Group(group) => format!("group {group}"),
+            };
+            msg.push(Line::from(vec![
+                Span::styled(name.to_string(), key),
+                format!(
+                    " {} {:+} on {target}",
+                    hotkey.control.get_name(),
+                    hotkey.step
+                )
+                .into(),
+            ]));
+        }
+        styled_popup(
+            f,
+            centered_rect(100, 100, f.size()),
+            "Help",
+            Text::from(msg),
+            app.theme.help,
+        );
+    }
+
+    if let (InputMode::Copying, Some(controller)) = (&app.input_mode, &app.control_selected) {
+        let mut msg = vec![
+            Line::from(format!(
+                "Copy {} to",
+                controller.read().unwrap().kind.get_name()
+            )),
+            Line::from(""),
+        ];
+        for (i, display) in app.manager.displays.iter().enumerate() {
+            msg.push(Line::from(vec![
+                Span::from((i + 1).to_string()).bold(),
+                format!(" {}", display.label()).into(),
+            ]));
+        }
+        msg.push(Line::from(vec!["Esc".bold(), " cancel".into()]));
+        popup(f, centered_rect(50, 40, f.size()), "Copy", Text::from(msg));
+    }
+
+    if let InputMode::ConfirmReset = app.input_mode {
+        let mut msg = vec![Line::from("Restore factory defaults of"), Line::from("")];
+        if let Some(code) = app.reset_code() {
+            let group = match code {
+                RESET_COLOR => "the colour settings",
+                _ => "brightness and contrast",
+            };
+            msg.push(Line::from(vec!["c".bold(), format!(" {group}").into()]));
+        }
+        msg.push(Line::from(vec![
+            "a".bold(),
+            " every setting of the display".into(),
+        ]));
+        msg.push(Line::from(vec!["Esc".bold(), " cancel".into()]));
+        popup(f, centered_rect(50, 40, f.size()), "Reset", Text::from(msg));
+    }
+
+    if let InputMode::ConfirmPreview = app.input_mode {
+        let msg = vec![
+            Line::from(format!(
+                "{} previewed values are not written",
+                app.manager.previewed()
+            )),
+            Line::from(""),
+            Line::from(vec!["c".bold(), " commit, writing them".into()]),
+            Line::from(vec!["d".bold(), " discard, back to the real values".into()]),
+            Line::from(vec!["Esc".bold(), " keep previewing".into()]),
+        ];
+        popup(
+            f,
+            centered_rect(50, 40, f.size()),
+            "Preview",
+            Text::from(msg),
+        );
+    }
+
+    if let (InputMode::ConfirmBlack, Some((display, _))) = (&app.input_mode, &app.black) {
+        let msg = vec![
+            Line::from(format!("{} is the only display.", display.label())),
+            Line::from("At brightness 0 it may turn black, with no other screen to undo it on."),
+            Line::from(""),
+            Line::from(vec!["y".bold(), " turn it to 0 anyway".into()]),
+            Line::from(vec!["Esc".bold(), " keep the brightness".into()]),
+        ];
+        popup(
+            f,
+            centered_rect(50, 40, f.size()),
+            "Brightness 0",
+            Text::from(msg),
+        );
+    }
+
+    if let (InputMode::Suggesting, Some(suggestion)) = (&app.input_mode, &app.suggestion) {
+        let [first, second] = &suggestion.displays;
+        let saved = match &suggestion.group {
+            Some(group) => format!("Make group {group} a synced one keeping them that far apart?"),
+            None => "Save a synced group keeping them that far apart?".to_string(),
+        };
+        let msg = vec![
+            Line::from(format!(
+                "The last {} times {} was set {:+}% brighter than {}.",
+                learn::SAMPLES,
+                second.label(),
+                suggestion.offset,
+                first.label()
+            )),
+            Line::from(saved),
+            Line::from(""),
+            Line::from(vec!["y".bold(), " save the group".into()]),
+            Line::from(vec!["Esc".bold(), " not now".into()]),
+        ];
+        popup(
+            f,
+            centered_rect(50, 40, f.size()),
+            "Synced group",
+            Text::from(msg),
+        );
+    }
+
+    if let InputMode::ConfirmRefresh = app.input_mode {
+        let msg = vec![
+            Line::from(format!(
+                "{} changes are not written yet",
+                app.manager.pending()
+            )),
+            Line::from(""),
+            Line::from(vec!["f".bold(), " flush and reload".into()]),
+            Line::from(vec!["d".bold(), " discard and reload".into()]),
+            Line::from(vec!["Esc".bold(), " cancel".into()]),
+        ];
+        popup(
+            f,
+            centered_rect(50, 40, f.size()),
+            "Reload",
+            Text::from(msg),
+        );
+    }
+
+    if let InputMode::Presets = app.input_mode {
+        presets_popup(f, app);
+    }
+
+    if let (InputMode::Changelog, Some(release)) = (&app.input_mode, &app.release) {
+        changelog_popup(f, release);
+    }
+
+    if let InputMode::History = app.input_mode {
+        history_popup(f, app);
+    }
+
+    if let InputMode::Queue = app.input_mode {
+        queue_popup(f, app);
+    }
+
+    if let InputMode::Health = app.input_mode {
+        health_popup(f, app);
+    }
+
+    if let InputMode::Palette = app.input_mode {
+        palette_popup(f, app);
+    }
+
+    if let InputMode::Editing = app.input_mode {
+        edit_popup(f, app);
+    }
+
+    if let InputMode::NamingGroup = app.input_mode {
+        let title = format!("Name of the group of {} displays", app.marked.len());
+        text_popup(f, app, &title);
+    }
+
+    if let (InputMode::EditingNote, Some(display)) = (&app.input_mode, &app.display_selected) {
+        let title = format!("Note on {}, empty to remove", display.label());
+        text_popup(f, app, &title);
+    }
+
+    if let (InputMode::Equalizing, Some(equalizer)) = (&app.input_mode, &app.equalizer) {
+        equalize_popup(f, equalizer);
+    }
+
+    if let (InputMode::Color, Some(panel)) = (&app.input_mode, &app.color) {
+        color_popup(f, panel, &app.theme);
+    }
+
+    if let (InputMode::Vcp, Some(form), Some(display)) =
+        (&app.input_mode, &app.vcp, &app.display_selected)
+    {
+        vcp_popup(f, form, display);
+    }
+}
+
+/// The value typed for a control, refused if the control can't take it. Continuous controls
+/// take a raw value up to their maximum, a percentage or dB, others one of the values their
+/// display advertises, in decimal, `0x` hex or by name.
+fn edited_value(display: &MyDisplay, controller: &Controller, input: &str) -> Result<u16, String> {
+    let input = input.trim();
+    if controller.kind.is_continuous() {
+        let adjust: Adjust = input.parse()?;
+        return match adjust {
+            Adjust::To(Level::Raw(value)) if value > controller.maximum => Err(format!(
+                "{value} is above the maximum of {}",
+                controller.maximum
+            )),
+            Adjust::To(Level::Percent(percent)) if !(0.0..=100.0).contains(&percent) => {
+                Err(format!("{percent}% is not between 0% and 100%"))
+            }
+            // Steps from the current value stop at the ends of the range
+            adjust => Ok(display.adjust(
+                controller.kind,
+                adjust,
+                controller.value,
+                controller.maximum,
+            )),
+        };
+    }
+    let value = display
+        .value_named(controller.kind, input)
+        .ok_or_else(|| format!("{input:?} is no value or name of a value"))?;
+    let allowed = display.allowed_values(controller.kind);
+    if !allowed.is_empty() && !allowed.iter().any(|(known, _)| *known == value) {
+        let known: Vec<String> = allowed
+            .iter()
+            .map(|(known, _)| display.describe(controller.kind, *known))
+            .collect();
+        return Err(format!("not advertised, try {}", known.join(", ")));
+    }
+    Ok(value)
+}
+
+/// What the display advertised when it was loaded: MCCS version, type and model first, then
+/// every VCP code with its access and range, allowed values one per line.
+fn capability_lines(display: &MyDisplay) -> Vec<Line<'static>> {
+    let Some(capabilities) = &display.advertised else {
+        return vec![Line::from(
+            "No capabilities, the display didn't send any".dark_gray(),
+        )];
+    };
+    let mut summary = vec![];
+    if let Some((major, minor)) = capabilities.mccs_version {
+        summary.push(format!("MCCS {major}.{minor}"));
+    }
+    if let Some(display_type) = &capabilities.display_type {
+        summary.push(display_type.clone());
+    }
+    if let Some(model) = &capabilities.model {
+        summary.push(format!("model {model}"));
+    }
+    let gray = Style::default().fg(Color::DarkGray);
+    let mut lines = vec![Line::from(Span::styled(
+        summary.join(", "),
+        Style::default().add_modifier(Modifier::BOLD),
+    ))];
+    for feature in &capabilities.features {
+        let mut line = Line::from(format!(
+            "0x{:02x} {} {}",
+            feature.code,
+            feature.access(),
+            feature.name.as_deref().unwrap_or("unnamed")
+        ));
+        match &feature.kind {
+            FeatureKind::Discrete { values } => {
+                lines.push(line);
+                for (value, name) in values {
+                    let name = name.as_deref().unwrap_or_default();
+                    lines.push(Line::from(Span::styled(
+                        format!("       0x{value:02x} {name}"),
+                        gray,
+                    )));
+                }
+            }
+            kind => {
+                line.spans.push(Span::styled(format!(" {kind}"), gray));
+                lines.push(line);
+            }
+        }
+    }
+    lines
+}
+
+/// What every bus and display is busy with while the first refresh runs.
+fn loading_screen<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let mut msg: Vec<Line> = app
+        .progress
+        .iter()
+        .map(|step| {
+            let status = Span::from(step.status.to_string());
+            let status = match step.status {
+                Status::Ready | Status::Found => status.green(),
+                Status::Failed(_) => status.red(),
+                Status::NoDisplay => status.dark_gray(),
+                _ => status,
+            };
+            Line::from(vec![format!("{}: ", step.source).into(), status])
+        })
+        .collect();
+    let title = if app.loading {
+        format!("Loading displays {}", app.spin())
+    } else {
+        msg.push(Line::from(""));
+        msg.push(Line::from("No displays found"));
+        msg.extend(
+            app.manager
+                .warnings
+                .iter()
+                .map(|warning| Line::from(warning.as_str())),
+        );
+        for problem in &app.problems {
+            msg.push(Line::from(problem.cause.as_str().yellow()));
+            msg.push(Line::from(format!("  {}", problem.fix)));
+        }
+        msg.push(Line::from(vec![
+            "r".bold(),
+            " retry  ".into(),
+            "q".bold(),
+            " exit".into(),
+        ]));
+        "Loading displays".to_string()
+    };
+    popup(f, f.size(), &title, Text::from(msg));
+}
+
+/// The buttons side by side with their keys, and the area of each.
+fn button_row<'a>(buttons: &'a [Button], theme: &Theme, area: Rect) -> (Paragraph<'a>, Vec<Rect>) {
+    let mut spans = vec![];
+    let mut areas = vec![];
+    let mut x = area.x;
+    for (index, button) in buttons.iter().enumerate() {
+        let key = match index < buttons::KEYS {
+            true => Span::styled(format!(" F{} ", index + 1), theme.accent.reversed()),
+            false => Span::raw(" "),
+        };
+        let label = Span::styled(format!(" {} ", button.label), theme.selection);
+        let width = (key.width() + label.width()) as u16;
+        areas.push(Rect::new(x, area.y, width, area.height).intersection(area));
+        x = x.saturating_add(width + 1);
+        spans.extend([key, label, Span::raw(" ")]);
+    }
+    (Paragraph::new(Line::from(spans)), areas)
+}
+
+fn equalize_popup<B: Backend>(f: &mut Frame<B>, equalizer: &Equalizer) {
+    let [first, second] = &equalizer.displays;
+    let answered = format!("{} answered, Esc to stop", equalizer.answers());
+    let msg = match equalizer.settled() {
+        false => vec![
+            Line::from("Which looks brighter?"),
+            Line::from(""),
+            Line::from(vec![
+                "←/1".bold(),
+                format!(" {}   ", first.label()).into(),
+                "→/2".bold(),
+                format!(" {}", second.label()).into(),
+            ]),
+            Line::from(""),
+            Line::from(answered.dark_gray()),
+        ],
+        true => vec![
+            Line::from(format!(
+                "{} looks like {} at {:+}% brightness",
+                second.label(),
+                first.label(),
+                equalizer.offset()
+            )),
+            Line::from(""),
+            Line::from(vec![
+                "Enter".bold(),
+                format!(" save to group {}  ", equalizer.group).into(),
+                "Esc".bold(),
+                " discard".into(),
+            ]),
+        ],
+    };
+    let area = centered_rect(60, 40, f.size());
+    popup(f, area, "Match brightness", Text::from(msg));
+}
+
+fn color_popup<B: Backend>(f: &mut Frame<B>, panel: &ColorPanel, theme: &Theme) {
+    let area = centered_rect(100, 100, f.size());
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Block::default()
+            .title("6-axis colour")
+            .borders(Borders::ALL),
+        area,
+    );
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+
+    for (row, (label, _)) in color::ROWS.iter().enumerate() {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, color::AXES.len() as u32); 6].as_ref())
+            .split(chunks[row]);
+        for (column, axis) in color::AXES.iter().enumerate() {
+            let mut block = Block::default()
+                .borders(Borders::ALL)
+                .title(format!("{axis} {label}"));
+            if (row, column) == (panel.row, panel.column) {
+                block = block.border_style(theme.accent);
+            }
+            match &panel.cells[row][column] {
+                Some(cell) => {
+                    let ratio = cell.value as f64 / cell.maximum.max(1) as f64;
+                    let gauge = Gauge::default()
+                        .block(block)
+                        .gauge_style(theme.gauge)
+                        .ratio(ratio.min(1.0))
+                        .label(cell.value.to_string());
+                    f.render_widget(gauge, columns[column]);
+                }
+                None => f.render_widget(
+                    Paragraph::new("n/a".dark_gray())
+                        .alignment(Alignment::Center)
+                        .block(block),
+                    columns[column],
+                ),
+            }
+        }
+    }
+
+    let mut msg = vec![Line::from(vec![
+        "hjkl".bold(),
+        " move  ".into(),
+        "+/-".bold(),
+        " adjust  ".into(),
+        "Esc".bold(),
+        " close".into(),
+    ])];
+    if let Some(error) = &panel.error {
+        msg.insert(0, Line::from(error.as_str()));
+    } else if panel.loading {
+        msg.insert(0, Line::from("Reading the 6-axis codes..."));
+    }
+    f.render_widget(
+        Paragraph::new(msg)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true }),
+        chunks[2],
+    );
+}
+
+/// The value of a control on every display having it, once if they agree.
+fn linked_values(manager: &DisplayManager, control: Control) -> String {
+    let mut values: Vec<String> = manager
+        .displays
+        .iter()
+        .filter_map(|display| {
+            let value = display.controls.get(&control)?.read().unwrap().value;
+            Some(display.describe(control, value))
+        })
+        .collect();
+    values.dedup();
+    values.join(" | ")
+}
+
+/// The latest log entries that fit, warnings and errors in colour.
+fn log_pane(height: u16) -> Paragraph<'static> {
+    let entries = logging::recent();
+    let shown = entries
+        .len()
+        .saturating_sub(height.saturating_sub(2) as usize);
+    let lines: Vec<Line> = entries[shown..]
+        .iter()
+        .map(|entry| {
+            let text = Span::from(entry.to_string());
+            match entry.level {
+                tracing::Level::ERROR => Line::from(text.red()),
+                tracing::Level::WARN => Line::from(text.yellow()),
+                tracing::Level::DEBUG => Line::from(text.dark_gray()),
+                _ => Line::from(text),
+            }
+        })
+        .collect();
+    let block = Block::default().borders(Borders::ALL).title("Log");
+    match lines.is_empty() {
+        true => Paragraph::new("Nothing logged yet".dark_gray()).block(block),
+        false => Paragraph::new(lines).block(block),
+    }
+}
+
+/// The shown displays as boxes where they are on the desktop, with their brightness.
+fn draw_wall<B: Backend>(f: &mut Frame<B>, app: &App, shown: &[usize], area: Rect) {
+    for (index, rect) in wall::place(&app.manager.displays, shown, area) {
+        let display = &app.manager.displays[index];
+        let focused = index == app.display_index;
+        let state = if display.is_stale() {
+            "(disconnected)".dark_gray()
+        } else if display.is_powered_off() {
+            "(off)".dark_gray()
+        } else if display.is_unreachable() && !display.is_lenient() {
+            "(unreachable)".red()
+        } else {
+            match display.controls.get(&Control::Brightness) {
+                Some(controller) => {
+                    Span::from(format!("{}%", display.percent(&controller.read().unwrap())))
+                }
+                None => "no brightness".dark_gray(),
+            }
+        };
+        let mut label = Span::from(display.label());
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{}", index + 1));
+        if focused {
+            label = label.bold();
+            block = block.border_style(app.theme.accent);
+        }
+        let text = Paragraph::new(vec![Line::from(label), Line::from(state)])
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .block(block);
+        f.render_widget(text, rect);
+    }
+}
+
+/// Queue depth and write timings of a display, to tell a slow display from a busy queue, and
+/// why the last write failed if it did.
+fn metrics_footer(manager: &DisplayManager, display: &MyDisplay) -> Paragraph<'static> {
+    let metrics = manager.metrics(display);
+    let latency = match metrics.latency {
+        Some(latency) => format!("{} ms", latency.as_millis()),
+        None => "-".to_string(),
+    };
+    let output = display
+        .output
+        .as_ref()
+        .map(|output| format!(" {output}"))
+        .unwrap_or_default();
+    let mut line = Line::from(vec![
+        Span::from(format!(" {}", display.label())).bold(),
+        output.into(),
+        format!(
+            "  queued {}  latency {latency}  {:.1} writes/s",
+            metrics.queued, metrics.writes_per_second
+        )
+        .into(),
+    ]);
+    if metrics.slow {
+        line.spans
+            .push(Span::from("  slow, writes merged").yellow());
+    }
+    if let Some(error) = display.write_error().filter(|_| !display.is_lenient()) {
+        line.spans
+            .push(Span::from(format!("  last write failed: {error}")).red());
+    }
+    Paragraph::new(line).style(Style::default().fg(Color::DarkGray))
+}
+
+/// What the change queue did last, so changes written in the background don't go unnoticed.
+fn status_bar(manager: &DisplayManager, step: i16) -> Paragraph<'static> {
+    let describe = |written: &Written| {
+        format!(
+            "{} {} {}\u{2192}{}",
+            written.name,
+            written.control.get_name().to_lowercase(),
+            written.from,
+            written.to
+        )
+    };
+    let (written, failed) = manager.last_written();
+    let last = written
+        .as_ref()
+        .map_or("nothing written yet".to_string(), describe);
+    let mut line = Line::from(format!(
+        " {last}  pending {}  step {step}",
+        manager.pending()
+    ));
+    if let Some(failed) = &failed {
+        line.spans.push(Span::styled(
+            format!(
+                "  {} failed: {}",
+                describe(failed),
+                failed.error.as_deref().unwrap_or_default()
+            ),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    Paragraph::new(line).style(Style::default().fg(Color::DarkGray))
+}
+
+/// The latest changes first, as many as fit.
+fn changelog_popup<B: Backend>(f: &mut Frame<B>, release: &Release) {
+    let area = centered_rect(100, 100, f.size());
+    let fits = area.height.saturating_sub(5) as usize;
+    let mut msg: Vec<Line> = release
+        .body
+        .as_deref()
+        .unwrap_or("No changelog")
+        .lines()
+        .take(fits)
+        .map(|line| Line::from(line.to_string()))
+        .collect();
+    msg.push(Line::from(""));
+    msg.push(Line::from(release.html_url.as_str()));
+    msg.push(Line::from(vec!["Esc".bold(), " close".into()]));
+    let title = format!("{} - running {}", release.tag, env!("CARGO_PKG_VERSION"));
+    popup(f, area, &title, Text::from(msg));
+}
+
+/// The diagnostic codes read when the popup opened, those the display doesn't have dimmed.
+fn health_popup<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let area = centered_rect(60, 60, f.size());
+    let mut msg: Vec<Line> = app
+        .health
+        .iter()
+        .map(|(name, value)| match value {
+            Some(value) => Line::from(vec![format!("{name}: ").into(), value.as_str().bold()]),
+            None => Line::from(Span::styled(
+                format!("{name}: not supported"),
+                Style::default().fg(Color::DarkGray),
+            )),
+        })
+        .collect();
+    if msg.is_empty() {
+        msg.push(Line::from("Reading the diagnostic codes..."));
+    }
+    msg.push(Line::from(""));
+    msg.push(Line::from(vec!["r".bold(), " read again".into()]));
+    msg.push(Line::from(vec!["Esc".bold(), " close".into()]));
+    let title = match &app.display_selected {
+        Some(display) => format!("Health of {}", display.label()),
+        None => "Health".to_string(),
+    };
+    popup(f, area, &title, Text::from(msg));
+}
+
+fn history_popup<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let area = centered_rect(100, 100, f.size());
+    let fits = area.height.saturating_sub(4) as usize;
+    let mut msg: Vec<Line> = app
+        .history_entries
+        .iter()
+        .rev()
+        .take(fits)
+        .map(|entry| Line::from(entry.to_string()))
+        .collect();
+    if msg.is_empty() {
+        msg.push(Line::from("No changes logged yet"));
+    }
+    msg.push(Line::from(""));
+    msg.push(Line::from(vec!["Esc".bold(), " close".into()]));
+    popup(f, area, "History", Text::from(msg));
+}
+
+fn presets_popup<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let mut msg: Vec<Line> = app
+        .config
+        .presets
+        .iter()
+        .enumerate()
+        .map(|(i, preset)| {
+            let controls = match &preset.controls {
+                Some(controls) => controls
+                    .iter()
+                    .map(|control| control.get_name())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                None => "all controls".to_string(),
+            };
+            let mut line = match preset.power {
+                Some(mode) if controls.is_empty() => format!("{}: turn {mode}", preset.name),
+                Some(mode) => format!("{}: {controls}, then turn {mode}", preset.name),
+                None => format!("{}: {controls}", preset.name),
+            };
+            if preset.builtin {
+                line.push_str(" (built-in)");
+            }
+            if i == app.preset_index {
+                Line::from(Span::from(format!("> {line}")).bold())
+            } else {
+                Line::from(line)
+            }
+        })
+        .collect();
+    if msg.is_empty() {
+        msg.push(Line::from("No presets in the config"));
+    }
+    msg.push(Line::from(""));
+    if let Some(status) = &app.preset_status {
+        msg.push(Line::from(status.as_str()));
+        msg.push(Line::from(""));
+    }
+    msg.push(Line::from(vec!["Enter".bold(), " apply".into()]));
+    msg.push(Line::from(vec![
+        "S".bold(),
+        " save current values of all displays".into(),
+    ]));
+    msg.push(Line::from(vec!["Esc".bold(), " close".into()]));
+    popup(
+        f,
+        centered_rect(60, 60, f.size()),
+        "Presets",
+        Text::from(msg),
+    );
+}
+
+/// The changes not written yet, with the highlighted one marked.
+fn queue_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let queued = app.manager.queued();
+    app.queue_index = app.queue_index.min(queued.len().saturating_sub(1));
+    let area = centered_rect(70, 70, f.size());
+    let fits = area.height.saturating_sub(9) as usize;
+    let first = (app.queue_index + 1).saturating_sub(fits);
+    let mut msg: Vec<Line> = queued
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(fits)
+        .map(|(i, change)| {
+            let mut line = format!(
+                "{}  {} {}",
+                change.display.label(),
+                change.control.get_name(),
+                change.display.describe(change.control, change.value)
+            );
+            if change.parked {
+                line.push_str(" (waiting for the display to answer)");
+            }
+            match i == app.queue_index {
+                true => Line::from(Span::from(format!("> {line}")).bold()),
+                false => Line::from(format!("  {line}")),
+            }
+        })
+        .collect();
+    if msg.is_empty() {
+        msg.push(Line::from("Nothing waiting to be written"));
+    }
+    // Changes the worker took are past discarding
+    let writing = app.manager.pending().saturating_sub(queued.len());
+    if writing > 0 {
+        msg.push(Line::from(format!("{writing} being written")));
+    }
+    msg.push(Line::from(""));
+    msg.push(Line::from(vec!["d".bold(), " discard highlighted".into()]));
+    msg.push(Line::from(vec!["c".bold(), " discard all".into()]));
+    msg.push(Line::from(vec![
+        "f".bold(),
+        " write now, retrying displays that stopped answering".into(),
+    ]));
+    msg.push(Line::from(vec!["Esc".bold(), " close".into()]));
+    popup(f, area, "Queue", Text::from(msg));
+}
+
+fn palette_popup<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let matches = palette::matches(app, &app.palette_query);
+    app.palette_index = app.palette_index.min(matches.len().saturating_sub(1));
+    let area = centered_rect(60, 60, f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .split(area);
+
+    let input = Paragraph::new(format!("> {}", app.palette_query))
+        .block(Block::default().borders(Borders::ALL).title("Command"));
+    let items: Vec<ListItem> = matches
+        .into_iter()
+        .map(|(label, _)| ListItem::new(label))
+        .collect();
+    let list = List::new(items)
+        .highlight_style(app.theme.selection)
+        .highlight_symbol("> ")
+        .block(Block::default().borders(Borders::ALL));
+    let mut state = ListState::default().with_selected(Some(app.palette_index));
+
+    f.render_widget(Clear, area);
+    f.render_widget(input, chunks[0]);
+    f.render_stateful_widget(list, chunks[1], &mut state);
+    f.set_cursor(
+        chunks[0].x + 3 + app.palette_query.chars().count() as u16,
+        chunks[0].y + 1,
+    );
+}
+
+fn edit_popup<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let Some(controller) = &app.control_selected else {
+        return;
+    };
+    let controller = controller.read().unwrap();
+    let title = match controller.kind.is_continuous() {
+        true => format!(
+            "Set {} (0-{}, % or dB)",
+            controller.kind.get_name(),
+            controller.maximum
+        ),
+        false => format!("Set {}", controller.kind.get_name()),
+    };
+    let area = centered_rect(50, 20, f.size());
+    let area = Rect {
+        height: area.height.max(4),
+        ..area
+    };
+    let mut lines = vec![Line::from(format!("> {}", app.edit_input))];
+    if let Some(err) = &app.edit_error {
+        lines.push(Line::from(err.as_str().red()));
+    }
+    let input = Paragraph::new(lines)
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title(title));
+
+    f.render_widget(Clear, area);
+    f.render_widget(input, area);
+    f.set_cursor(
+        area.x + 3 + app.edit_input.chars().count() as u16,
+        area.y + 1,
+    );
+}
+
+/// A box with the typed text and why it was refused, if it was.
+fn text_popup<B: Backend>(f: &mut Frame<B>, app: &App, title: &str) {
+    let area = centered_rect(50, 20, f.size());
+    let area = Rect {
+        height: area.height.max(4),
+        ..area
+    };
+    let mut lines = vec![Line::from(format!("> {}", app.edit_input))];
+    if let Some(err) = &app.edit_error {
+        lines.push(Line::from(err.as_str().red()));
+    }
+    let input = Paragraph::new(lines)
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title(title));
+
+    f.render_widget(Clear, area);
+    f.render_widget(input, area);
+    f.set_cursor(
+        area.x + 3 + app.edit_input.chars().count() as u16,
+        area.y + 1,
+    );
+}
+
+fn vcp_popup<B: Backend>(f: &mut Frame<B>, form: &VcpForm, display: &MyDisplay) {
+    let area = centered_rect(60, 60, f.size());
+    let field = |label: &str, text: &str, active: bool| {
+        let style = match active {
+            true => Style::default().add_modifier(Modifier::BOLD),
+            false => Style::default(),
+        };
+        Line::from(vec![
+            Span::styled(format!("{label:<6}> "), style),
+            text.to_string().into(),
+        ])
+    };
+    let mut lines = vec![
+        field("Code", &form.code, !form.on_value),
+        field("Value", &form.value, form.on_value),
+        Line::from(""),
+    ];
+    for answer in &form.answers {
+        lines.push(match answer {
+            Ok(answer) => Line::from(answer.as_str()),
+            Err(err) => Line::from(err.as_str().red()),
+        });
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        "Tab".bold(),
+        " switch field  ".into(),
+        "Enter".bold(),
+        " read, or write the value  ".into(),
+        "Esc".bold(),
+        " close".into(),
+    ]));
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("VCP codes of {} (code in hex)", display.label())),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+    let (row, typed) = match form.on_value {
+        true => (2, &form.value),
+        false => (1, &form.code),
+    };
+    f.set_cursor(area.x + 9 + typed.chars().count() as u16, area.y + row);
+}
+
+fn popup<B: Backend>(f: &mut Frame<B>, area: Rect, title: &str, text: Text) {
+    styled_popup(f, area, title, text, Style::default());
+}
+
+/// A popup with its border in the style.
+fn styled_popup<B: Backend>(f: &mut Frame<B>, area: Rect, title: &str, text: Text, border: Style) {
+    let paragraph = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(100)].as_ref())
+        .margin(1)
+        .split(area);
+
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(border);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+
+    f.render_widget(paragraph, chunks[0]);
+}
@@ -0,0 +1,594 @@
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use ddc_bright::display::Control;
+use ratatui::layout::Rect;
+
+use super::{equalize::Brighter, filter, wall, App, InputMode};
+use crate::buttons;
+use crate::config::Hotkey;
+use crate::keys::{Binding, Key, Move, DISPLAYS};
+
+const SELECT: [Binding; 19] = [
+    Binding::Quit,
+    Binding::Refresh,
+    Binding::Identify,
+    Binding::Presets,
+    Binding::History,
+    Binding::Changelog,
+    Binding::Preview,
+    Binding::Help,
+    Binding::Previous,
+    Binding::Next,
+    Binding::Select,
+    Binding::Rename,
+    Binding::Filter,
+    Binding::Queue,
+    Binding::Log,
+    Binding::Mark,
+    Binding::Group,
+    Binding::Equalize,
+    Binding::Layout,
+];
+
+const SELECTED: [Binding; 23] = [
+    Binding::Previous,
+    Binding::Next,
+    Binding::Decrease,
+    Binding::Increase,
+    Binding::Colour,
+    Binding::Edit,
+    Binding::Copy,
+    Binding::StepUp,
+    Binding::StepDown,
+    Binding::Link,
+    Binding::Power,
+    Binding::Vcp,
+    Binding::Capabilities,
+    Binding::Reset,
+    Binding::History,
+    Binding::Preview,
+    Binding::Rename,
+    Binding::Note,
+    Binding::Filter,
+    Binding::Queue,
+    Binding::Log,
+    Binding::Health,
+    Binding::Back,
+];
+
+const PRESETS: [Binding; 5] = [
+    Binding::Previous,
+    Binding::Next,
+    Binding::Select,
+    Binding::SavePreset,
+    Binding::Back,
+];
+
+/// Something the user can do, from a key press or the command palette
+#[derive(Clone)]
+pub enum Action {
+    /// Highlights a display in the list without selecting it
+    FocusDisplay(usize),
+    SelectDisplay(usize),
+    SelectFocused,
+    /// Goes back to the display list from any popup or the selected display
+    Back,
+    PrevControl,
+    NextControl,
+    /// Highlights a control of the selected display, selecting the focused one if none is
+    FocusControl(Control),
+    /// Adds to the highlighted control of the selected display, or of every display if linked
+    Adjust(i16),
+    /// Adds to the primary control of the highlighted display, from the display list
+    AdjustPrimary(i16),
+    /// Opens a box to type the exact value of the highlighted control into
+    OpenEdit,
+    EditInput(char),
+    EditErase,
+    /// Sets the highlighted control to the typed value if the control takes it
+    ApplyEdit,
+    CloseEdit,
+    /// Opens a box to type the alias of the highlighted display into
+    OpenRename,
+    /// Gives the highlighted display the typed alias, or its model name back if empty
+    ApplyRename,
+    CloseRename,
+    /// Opens a box to type the note on the selected display into
+    OpenNote,
+    /// Keeps the typed note, or removes the note if empty
+    ApplyNote,
+    CloseNote,
+    /// Marks the highlighted display for a new group, or unmarks it
+    ToggleMark,
+    /// Opens a box to type the name of the group of the marked displays into
+    OpenGroup,
+    /// Saves the marked displays as a synced group of the typed name
+    ApplyGroup,
+    CloseGroup,
+    /// Starts matching the brightness of the two marked displays
+    OpenEqualize,
+    /// Answers which display looks brighter
+    AnswerEqualize(Brighter),
+    /// Saves the offset found to the group of both
+    SaveEqualize,
+    CloseEqualize,
+    /// Starts typing a filter for the display list, or the controls of the selected display
+    OpenFilter,
+    FilterInput(char),
+    FilterErase,
+    /// Keeps the filter and goes back to the list
+    ApplyFilter,
+    /// Clears the filter
+    CloseFilter,
+    /// Asks which display the highlighted control is copied to
+    OpenCopy,
+    /// Copies the highlighted control to the display at this index, scaled to its maximum
+    CopyControl(usize),
+    CloseCopy,
+    /// Switches to the next larger step size, or the next smaller one if negative
+    ChangeStep(isize),
+    ToggleLinked,
+    /// Starts holding back changes, or asks what to do with the held ones while previewing
+    Preview,
+    CommitPreview,
+    DiscardPreview,
+    /// Turns the only display's brightness to 0 after all
+    ConfirmBlack,
+    CloseBlack,
+    /// Saves the synced group the history suggested
+    AcceptSuggestion,
+    DismissSuggestion,
+    Set(Control, u16),
+    /// Does what the button at this index lists
+    PressButton(usize),
+    Hotkey(Hotkey),
+    OpenPresets,
+    FocusPreset(usize),
+    ApplyPreset(usize),
+    SavePreset(usize),
+    OpenColor,
+    MoveColor(isize, isize),
+    AdjustColor(i16),
+    CloseColor,
+    /// Shows the latest changes and what made them
+    OpenHistory,
+    /// Lists the changes not written yet
+    OpenQueue,
+    FocusQueued(usize),
+    /// Drops the highlighted change of the queue, or every change if `all`
+    DiscardQueued {
+        all: bool,
+    },
+    /// Retries the changes waiting for displays that stopped answering right away
+    RetryQueued,
+    CloseQueue,
+    OpenPalette,
+    PaletteInput(char),
+    PaletteErase,
+    PaletteMove(isize),
+    /// Runs the highlighted palette entry
+    PaletteRun,
+    ClosePalette,
+    Identify,
+    /// Turns the selected display off, or on again if it was turned off
+    TogglePower,
+    /// Opens the form reading and writing any VCP code of the selected display
+    OpenVcp,
+    VcpInput(char),
+    VcpErase,
+    /// Moves between the code and value fields
+    VcpSwitch,
+    /// Writes the typed value to the typed code, or only reads the code without a value
+    VcpRun,
+    CloseVcp,
+    /// Shows or hides the capabilities of the selected display, remembered per display
+    ToggleCapabilities,
+    /// Scrolls the capabilities by this many lines
+    ScrollCapabilities(isize),
+    /// Shows or hides the log pane
+    ToggleLog,
+    ToggleLayout,
+    /// Reads the diagnostic codes of the selected display and shows them
+    OpenHealth,
+    CloseHealth,
+    /// Asks what to restore the factory defaults of
+    OpenReset,
+    /// Restores the defaults of the highlighted control's group, or of every setting
+    Reset {
+        all: bool,
+    },
+    CloseReset,
+    /// Shows the changelog of the newer release the update check found
+    OpenChangelog,
+    Refresh,
+    /// Reloads the displays after flushing or discarding the queued changes
+    Reload {
+        flush: bool,
+    },
+    Help,
+    Quit,
+}
+
+/// What a key press does in the current input mode. Shortcuts only apply in the modes listed
+/// here, so text typed into a prompt never triggers them.
+pub fn for_key(app: &App, key: KeyEvent) -> Option<Action> {
+    // Repeats count as presses, as they do in terminals that don't tell them apart
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    // The palette takes every key as text
+    if let InputMode::Palette = app.input_mode {
+        return match key.code {
+            KeyCode::Esc => Some(Action::ClosePalette),
+            KeyCode::Enter => Some(Action::PaletteRun),
+            KeyCode::Up => Some(Action::PaletteMove(-1)),
+            KeyCode::Down => Some(Action::PaletteMove(1)),
+            KeyCode::Backspace => Some(Action::PaletteErase),
+            KeyCode::Char(char) => Some(Action::PaletteInput(char)),
+            _ => None,
+        };
+    }
+    // So do the value box and the VCP form
+    if let InputMode::Editing = app.input_mode {
+        return match key.code {
+            KeyCode::Esc => Some(Action::CloseEdit),
+            KeyCode::Enter => Some(Action::ApplyEdit),
+            KeyCode::Backspace => Some(Action::EditErase),
+            KeyCode::Char(char) => Some(Action::EditInput(char)),
+            _ => None,
+        };
+    }
+    if let InputMode::Renaming = app.input_mode {
+        return match key.code {
+            KeyCode::Esc => Some(Action::CloseRename),
+            KeyCode::Enter => Some(Action::ApplyRename),
+            KeyCode::Backspace => Some(Action::EditErase),
+            KeyCode::Char(char) => Some(Action::EditInput(char)),
+            _ => None,
+        };
+    }
+    if let InputMode::NamingGroup = app.input_mode {
+        return match key.code {
+            KeyCode::Esc => Some(Action::CloseGroup),
+            KeyCode::Enter => Some(Action::ApplyGroup),
+            KeyCode::Backspace => Some(Action::EditErase),
+            KeyCode::Char(char) => Some(Action::EditInput(char)),
+            _ => None,
+        };
+    }
+    if let InputMode::EditingNote = app.input_mode {
+        return match key.code {
+            KeyCode::Esc => Some(Action::CloseNote),
+            KeyCode::Enter => Some(Action::ApplyNote),
+            KeyCode::Backspace => Some(Action::EditErase),
+            KeyCode::Char(char) => Some(Action::EditInput(char)),
+            _ => None,
+        };
+    }
+    if let InputMode::Filtering = app.input_mode {
+        return match key.code {
+            KeyCode::Esc => Some(Action::CloseFilter),
+            KeyCode::Enter => Some(Action::ApplyFilter),
+            // The highlight moves among the matches while typing
+            KeyCode::Up | KeyCode::Down => {
+                let forward = key.code == KeyCode::Down;
+                Some(match (&app.display_selected, forward) {
+                    (Some(_), false) => Action::PrevControl,
+                    (Some(_), true) => Action::NextControl,
+                    (None, _) => Action::FocusDisplay(filter::neighbour(
+                        &app.shown_displays(),
+                        app.display_index,
+                        forward,
+                    )),
+                })
+            }
+            KeyCode::Backspace => Some(Action::FilterErase),
+            KeyCode::Char(char) => Some(Action::FilterInput(char)),
+            _ => None,
+        };
+    }
+    if let InputMode::Vcp = app.input_mode {
+        return match key.code {
+            KeyCode::Esc => Some(Action::CloseVcp),
+            KeyCode::Enter => Some(Action::VcpRun),
+            KeyCode::Tab | KeyCode::Up | KeyCode::Down => Some(Action::VcpSwitch),
+            KeyCode::Backspace => Some(Action::VcpErase),
+            KeyCode::Char(char) => Some(Action::VcpInput(char)),
+            _ => None,
+        };
+    }
+    let keys = &app.config.keys;
+    if bound(app, key.code, key.modifiers, &[Binding::Palette]).is_some() {
+        return Some(Action::OpenPalette);
+    }
+    if let (InputMode::Select | InputMode::Selected, KeyCode::Char(char)) =
+        (&app.input_mode, key.code)
+    {
+        if let Some(hotkey) = app.config.hotkey(char) {
+            return Some(Action::Hotkey(hotkey.clone()));
+        }
+        if let Some(control) = keys.controls.get(&char) {
+            return Some(Action::FocusControl(*control));
+        }
+    }
+    // Navigation keys go by where they sit, other letters by what they are
+    let code = match key.code {
+        KeyCode::Char(char)
+            if matches!(
+                app.input_mode,
+                InputMode::Select | InputMode::Selected | InputMode::Presets
+            ) =>
+        {
+            match keys.movement(char) {
+                Some(Move::Up) => KeyCode::Up,
+                Some(Move::Left) => KeyCode::Left,
+                Some(Move::Down) => KeyCode::Down,
+                Some(Move::Right) => KeyCode::Right,
+                None => key.code,
+            }
+        }
+        code => code,
+    };
+    let modifiers = key.modifiers;
+    match app.input_mode {
+        InputMode::Select | InputMode::Selected if matches!(code, KeyCode::F(number) if number as usize <= app.config.buttons.len().min(buttons::KEYS)) =>
+        {
+            let KeyCode::F(number) = code else {
+                return None;
+            };
+            Some(Action::PressButton(number as usize - 1))
+        }
+        InputMode::Loading if key.code == KeyCode::Esc => Some(Action::Quit),
+        InputMode::Loading => {
+            match bound(app, key.code, modifiers, &[Binding::Quit, Binding::Refresh])? {
+                Binding::Quit => Some(Action::Quit),
+                _ => Some(Action::Refresh),
+            }
+        }
+        InputMode::History | InputMode::Changelog => match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('h') => Some(Action::Back),
+            _ => None,
+        },
+        InputMode::Queue => match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                Some(Action::FocusQueued(app.queue_index.saturating_sub(1)))
+            }
+            KeyCode::Down | KeyCode::Char('j') => Some(Action::FocusQueued(app.queue_index + 1)),
+            KeyCode::Char('d') | KeyCode::Delete => Some(Action::DiscardQueued { all: false }),
+            KeyCode::Char('c') => Some(Action::DiscardQueued { all: true }),
+            KeyCode::Char('f') => Some(Action::RetryQueued),
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('W') => Some(Action::CloseQueue),
+            _ => None,
+        },
+        InputMode::Help => match key.code {
+            KeyCode::Char(_) | KeyCode::Esc => Some(Action::Back),
+            _ => None,
+        },
+        InputMode::Select
+            if modifiers.contains(KeyModifiers::SHIFT)
+                && matches!(
+                    key.code,
+                    KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right
+                ) =>
+        {
+            match key.code {
+                KeyCode::Up | KeyCode::Right => Some(Action::AdjustPrimary(app.step())),
+                _ => Some(Action::AdjustPrimary(-app.step())),
+            }
+        }
+        // The arrows go by where the boxes are in the layout view
+        InputMode::Select
+            if app.wall
+                && modifiers.is_empty()
+                && matches!(
+                    code,
+                    KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right
+                ) =>
+        {
+            let direction = match code {
+                KeyCode::Up => Move::Up,
+                KeyCode::Down => Move::Down,
+                KeyCode::Left => Move::Left,
+                _ => Move::Right,
+            };
+            Some(Action::FocusDisplay(wall::neighbour(
+                &app.manager.displays,
+                &app.shown_displays(),
+                app.display_index,
+                direction,
+            )))
+        }
+        InputMode::Select => match bound(app, code, modifiers, &SELECT)
+            .or_else(|| bound(app, code, modifiers, &DISPLAYS))?
+        {
+            Binding::Quit => Some(Action::Quit),
+            Binding::Refresh => Some(Action::Refresh),
+            Binding::Identify => Some(Action::Identify),
+            Binding::Presets => Some(Action::OpenPresets),
+            Binding::History => Some(Action::OpenHistory),
+            Binding::Changelog => Some(Action::OpenChangelog),
+            Binding::Preview => Some(Action::Preview),
+            Binding::Help => Some(Action::Help),
+            Binding::Previous => Some(Action::FocusDisplay(filter::neighbour(
+                &app.shown_displays(),
+                app.display_index,
+                false,
+            ))),
+            Binding::Next => Some(Action::FocusDisplay(filter::neighbour(
+                &app.shown_displays(),
+                app.display_index,
+                true,
+            ))),
+            Binding::Select => Some(Action::SelectFocused),
+            Binding::Rename => Some(Action::OpenRename),
+            Binding::Filter => Some(Action::OpenFilter),
+            Binding::Queue => Some(Action::OpenQueue),
+            Binding::Log => Some(Action::ToggleLog),
+            Binding::Mark => Some(Action::ToggleMark),
+            Binding::Group => Some(Action::OpenGroup),
+            Binding::Equalize => Some(Action::OpenEqualize),
+            Binding::Layout => Some(Action::ToggleLayout),
+            Binding::Display(number) => Some(Action::SelectDisplay(number as usize - 1)),
+            _ => None,
+        },
+        InputMode::Selected if key.code == KeyCode::PageUp => Some(Action::ScrollCapabilities(-5)),
+        InputMode::Selected if key.code == KeyCode::PageDown => Some(Action::ScrollCapabilities(5)),
+        InputMode::Selected => match bound(app, code, modifiers, &SELECTED)
+            .or_else(|| bound(app, code, modifiers, &DISPLAYS))?
+        {
+            Binding::Previous => Some(Action::PrevControl),
+            Binding::Next => Some(Action::NextControl),
+            Binding::Decrease => Some(Action::Adjust(-app.step())),
+            Binding::Increase => Some(Action::Adjust(app.step())),
+            Binding::Colour => Some(Action::OpenColor),
+            Binding::Edit => Some(Action::OpenEdit),
+            Binding::Copy => Some(Action::OpenCopy),
+            Binding::StepUp => Some(Action::ChangeStep(1)),
+            Binding::StepDown => Some(Action::ChangeStep(-1)),
+            Binding::Link => Some(Action::ToggleLinked),
+            Binding::Power => Some(Action::TogglePower),
+            Binding::Vcp => Some(Action::OpenVcp),
+            Binding::Capabilities => Some(Action::ToggleCapabilities),
+            Binding::Reset => Some(Action::OpenReset),
+            Binding::History => Some(Action::OpenHistory),
+            Binding::Preview => Some(Action::Preview),
+            Binding::Rename => Some(Action::OpenRename),
+            Binding::Note => Some(Action::OpenNote),
+            Binding::Filter => Some(Action::OpenFilter),
+            Binding::Queue => Some(Action::OpenQueue),
+            Binding::Log => Some(Action::ToggleLog),
+            Binding::Health => Some(Action::OpenHealth),
+            Binding::Back => Some(Action::Back),
+            Binding::Display(number) => Some(Action::SelectDisplay(number as usize - 1)),
+            _ => None,
+        },
+        InputMode::Health => match key.code {
+            KeyCode::Char('r') => Some(Action::OpenHealth),
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('H') => Some(Action::CloseHealth),
+            _ => None,
+        },
+        InputMode::ConfirmRefresh => match key.code {
+            KeyCode::Char('f') => Some(Action::Reload { flush: true }),
+            KeyCode::Char('d') => Some(Action::Reload { flush: false }),
+            KeyCode::Esc | KeyCode::Char('q') => Some(Action::Back),
+            _ => None,
+        },
+        InputMode::Copying => match key.code {
+            KeyCode::Char(char) if char.is_ascii_digit() => {
+                let number = char.to_digit(10).unwrap() as usize;
+                Some(Action::CopyControl(number.saturating_sub(1)))
+            }
+            KeyCode::Esc | KeyCode::Char('q') => Some(Action::CloseCopy),
+            _ => None,
+        },
+        InputMode::ConfirmReset => match key.code {
+            KeyCode::Char('c') if app.reset_code().is_some() => Some(Action::Reset { all: false }),
+            KeyCode::Char('a') => Some(Action::Reset { all: true }),
+            KeyCode::Esc | KeyCode::Char('q') => Some(Action::CloseReset),
+            _ => None,
+        },
+        InputMode::ConfirmBlack => match key.code {
+            KeyCode::Char('y') => Some(Action::ConfirmBlack),
+            KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('q') => Some(Action::CloseBlack),
+            _ => None,
+        },
+        InputMode::Suggesting => match key.code {
+            KeyCode::Char('y') => Some(Action::AcceptSuggestion),
+            KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('q') => {
+                Some(Action::DismissSuggestion)
+            }
+            _ => None,
+        },
+        InputMode::ConfirmPreview => match key.code {
+            KeyCode::Char('c') => Some(Action::CommitPreview),
+            KeyCode::Char('d') => Some(Action::DiscardPreview),
+            KeyCode::Esc | KeyCode::Char('q') => Some(Action::Back),
+            _ => None,
+        },
+        InputMode::Presets => match bound(app, code, modifiers, &PRESETS)? {
+            Binding::Previous => Some(Action::FocusPreset(app.preset_index.saturating_sub(1))),
+            Binding::Next => Some(Action::FocusPreset(app.preset_index + 1)),
+            Binding::Select => Some(Action::ApplyPreset(app.preset_index)),
+            Binding::SavePreset => Some(Action::SavePreset(app.preset_index)),
+            Binding::Back => Some(Action::Back),
+            _ => None,
+        },
+        InputMode::Color => match key.code {
+            KeyCode::Left | KeyCode::Char('h') => Some(Action::MoveColor(0, -1)),
+            KeyCode::Right | KeyCode::Char('l') => Some(Action::MoveColor(0, 1)),
+            KeyCode::Up | KeyCode::Char('k') => Some(Action::MoveColor(-1, 0)),
+            KeyCode::Down | KeyCode::Char('j') => Some(Action::MoveColor(1, 0)),
+            KeyCode::Char('+') | KeyCode::Char('=') => Some(Action::AdjustColor(app.step_size)),
+            KeyCode::Char('-') => Some(Action::AdjustColor(-app.step_size)),
+            KeyCode::Esc | KeyCode::Char('q') => Some(Action::CloseColor),
+            _ => None,
+        },
+        InputMode::Equalizing => match key.code {
+            KeyCode::Left | KeyCode::Char('1') => Some(Action::AnswerEqualize(Brighter::First)),
+            KeyCode::Right | KeyCode::Char('2') => Some(Action::AnswerEqualize(Brighter::Second)),
+            KeyCode::Enter => Some(Action::SaveEqualize),
+            KeyCode::Esc | KeyCode::Char('q') => Some(Action::CloseEqualize),
+            _ => None,
+        },
+        InputMode::Palette
+        | InputMode::Editing
+        | InputMode::Renaming
+        | InputMode::NamingGroup
+        | InputMode::EditingNote
+        | InputMode::Filtering
+        | InputMode::Vcp => None,
+    }
+}
+
+/// What scrolling does: over the display list it adjusts the primary control of the
+/// highlighted display. Clicks press the buttons.
+pub fn for_mouse(app: &App, mouse: MouseEvent) -> Option<Action> {
+    let at = Rect::new(mouse.column, mouse.row, 1, 1);
+    if let (MouseEventKind::Down(MouseButton::Left), InputMode::Select | InputMode::Selected) =
+        (mouse.kind, &app.input_mode)
+    {
+        return app
+            .button_areas
+            .iter()
+            .position(|area| area.intersects(at))
+            .map(Action::PressButton);
+    }
+    if !matches!(app.input_mode, InputMode::Select) {
+        return None;
+    }
+    let over = app
+        .display_area
+        .intersects(Rect::new(mouse.column, mouse.row, 1, 1));
+    match mouse.kind {
+        MouseEventKind::ScrollUp if over => Some(Action::AdjustPrimary(app.step())),
+        MouseEventKind::ScrollDown if over => Some(Action::AdjustPrimary(-app.step())),
+        _ => None,
+    }
+}
+
+/// The first of `candidates` the key is bound to.
+fn bound(
+    app: &App,
+    code: KeyCode,
+    modifiers: KeyModifiers,
+    candidates: &[Binding],
+) -> Option<Binding> {
+    let key = match code {
+        KeyCode::Char(char) if modifiers.contains(KeyModifiers::CONTROL) => {
+            Key::Ctrl(char.to_ascii_lowercase())
+        }
+        KeyCode::Char(char) => Key::Char(char),
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::F(number) => Key::F(number),
+        _ => return None,
+    };
+    app.config.keys.bound(key, candidates)
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use ddc_bright::{
+    capabilities::FeatureKind,
+    display::{MyDisplay, WrappedDisplay},
+};
+
+/// The six axes in the order their VCP codes follow
+pub const AXES: [&str; 6] = ["Red", "Yellow", "Green", "Cyan", "Blue", "Magenta"];
+
+/// First VCP code of each row, the other axes follow it
+pub const ROWS: [(&str, u8); 2] = [("Hue", 0x9B), ("Saturation", 0x59)];
+
+/// A 6-axis code the display advertises
+pub struct Cell {
+    pub code: u8,
+    pub value: u16,
+    pub maximum: u16,
+}
+
+/// Rows as in `ROWS`, columns as in `AXES`, `None` where the display doesn't advertise the code
+pub type Cells = [[Option<Cell>; 6]; 2];
+
+/// State of the colour tuning screen for one display. The codes are read and written on
+/// background threads, the display may take its time with each.
+pub struct ColorPanel {
+    pub display: WrappedDisplay,
+    pub cells: Cells,
+    pub row: usize,
+    pub column: usize,
+    /// Whether the codes are still being read
+    pub loading: bool,
+    /// Why the codes couldn't be read, or the last write failed
+    pub error: Option<String>,
+    /// Values waiting to be written by code, only the newest of each counts
+    pending: BTreeMap<u8, u16>,
+    /// Whether a write is under way, they go one at a time
+    writing: bool,
+}
+
+impl ColorPanel {
+    /// A panel for the display, loading until [`ColorPanel::loaded`] gets its codes.
+    pub fn new(display: WrappedDisplay) -> Self {
+        ColorPanel {
+            display,
+            cells: Default::default(),
+            row: 0,
+            column: 0,
+            loading: true,
+            error: None,
+            pending: BTreeMap::new(),
+            writing: false,
+        }
+    }
+
+    /// Reads which 6-axis codes the display advertises and their current values.
+    pub fn read(display: &MyDisplay) -> Result<Cells, String> {
+        let capabilities = display
+            .capabilities()
+            .map_err(|err| format!("Reading capabilities failed: {err}"))?;
+        let mut cells: Cells = Default::default();
+        for (row, (_, first)) in cells.iter_mut().zip(ROWS) {
+            for (column, cell) in row.iter_mut().enumerate() {
+                let code = first + column as u8;
+                let Some(feature) = capabilities.feature(code) else {
+                    continue;
+                };
+                let maximum = match feature.kind {
+                    FeatureKind::Continuous { maximum } => maximum.unwrap_or(100),
+                    _ => continue,
+                };
+                if let Ok(value) = display.get_vcp(code) {
+                    *cell = Some(Cell {
+                        code,
+                        value,
+                        maximum,
+                    });
+                }
+            }
+        }
+        match cells.iter().flatten().all(Option::is_none) {
+            true => Err("The display doesn't advertise 6-axis colour controls".to_string()),
+            false => Ok(cells),
+        }
+    }
+
+    /// Takes the codes [`ColorPanel::read`] found.
+    pub fn loaded(&mut self, cells: Result<Cells, String>) {
+        self.loading = false;
+        match cells {
+            Ok(cells) => self.cells = cells,
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    pub fn move_by(&mut self, rows: isize, columns: isize) {
+        self.row = self.row.saturating_add_signed(rows).min(ROWS.len() - 1);
+        self.column = self
+            .column
+            .saturating_add_signed(columns)
+            .min(AXES.len() - 1);
+    }
+
+    /// Changes the highlighted code, leaving the value to [`ColorPanel::next_write`].
+    pub fn adjust(&mut self, step: i16) {
+        let Some(cell) = self.cells[self.row][self.column].as_mut() else {
+            return;
+        };
+        cell.value = (cell.value as i32 + step as i32).clamp(0, cell.maximum as i32) as u16;
+        self.pending.insert(cell.code, cell.value);
+    }
+
+    /// The code and value to write next, `None` while a write is under way or nothing waits.
+    pub fn next_write(&mut self) -> Option<(u8, u16)> {
+        if self.writing {
+            return None;
+        }
+        let next = self.pending.pop_first();
+        self.writing = next.is_some();
+        next
+    }
+
+    /// Notes how the write [`ColorPanel::next_write`] handed out went.
+    pub fn written(&mut self, result: Result<(), String>) {
+        self.writing = false;
+        self.error = result.err().map(|err| format!("Writing failed: {err}"));
+    }
+
+    /// Whether values are still waiting to be written or being written.
+    pub fn is_busy(&self) -> bool {
+        self.writing || !self.pending.is_empty()
+    }
+}
@@ -0,0 +1,158 @@
+//! Matching the brightness of two displays by eye: two staircases, one stepping the second
+//! display against the first and one the first against the second, take turns asking which
+//! looks brighter. Each halves its step whenever the answer turns around, and the offset is
+//! the average of where they turned once they settled.
+
+use ddc_bright::display::WrappedDisplay;
+
+/// Percent the stepped display starts above the one held still
+const START: f32 = 16.0;
+const FIRST_STEP: f32 = 8.0;
+const LAST_STEP: f32 = 1.0;
+/// Turns a staircase makes before it counts as settled
+const REVERSALS: usize = 6;
+/// First turns left out of the offset, made while the steps are still coarse
+const SKIPPED: usize = 2;
+/// Answers after which a staircase counts as settled anyway
+const MAX_ANSWERS: usize = 30;
+/// Brightness the display held still is kept within, leaving room to step the other around it
+const REFERENCE: (f32, f32) = (20.0, 80.0);
+
+/// Which of the two displays looks brighter
+#[derive(Clone, Copy)]
+pub enum Brighter {
+    First,
+    Second,
+}
+
+struct Staircase {
+    /// Percent the stepped display is above the one held still
+    offset: f32,
+    step: f32,
+    /// Whether the last answer stepped down
+    falling: Option<bool>,
+    /// Offsets at which the answers turned around
+    reversals: Vec<f32>,
+    answers: usize,
+}
+
+impl Staircase {
+    fn new() -> Self {
+        Self {
+            offset: START,
+            step: FIRST_STEP,
+            falling: None,
+            reversals: vec![],
+            answers: 0,
+        }
+    }
+
+    /// Steps down if the stepped display looked brighter, up otherwise.
+    fn answer(&mut self, stepped_brighter: bool) {
+        self.answers += 1;
+        if self
+            .falling
+            .is_some_and(|falling| falling != stepped_brighter)
+        {
+            self.reversals.push(self.offset);
+            self.step = (self.step / 2.0).max(LAST_STEP);
+        }
+        self.falling = Some(stepped_brighter);
+        self.offset += match stepped_brighter {
+            true => -self.step,
+            false => self.step,
+        };
+    }
+
+    fn settled(&self) -> bool {
+        self.reversals.len() >= REVERSALS || self.answers >= MAX_ANSWERS
+    }
+
+    /// The offset at which both look alike, so far.
+    fn estimate(&self) -> f32 {
+        let settled = match self.reversals.get(SKIPPED..) {
+            Some(settled) if !settled.is_empty() => settled,
+            _ if self.reversals.is_empty() => return self.offset,
+            _ => &self.reversals,
+        };
+        settled.iter().sum::<f32>() / settled.len() as f32
+    }
+}
+
+/// The matching of two displays while it runs
+pub struct Equalizer {
+    pub displays: [WrappedDisplay; 2],
+    /// The synced group of both the offset is saved to
+    pub group: String,
+    /// Brightness of each before, written back when done
+    pub before: [u16; 2],
+    /// Percent of brightness the display held still is at
+    reference: f32,
+    staircases: [Staircase; 2],
+    /// The staircase the next answer goes to
+    turn: usize,
+}
+
+impl Equalizer {
+    /// Starts matching, with the display held still at `percent` or near it.
+    pub fn new(
+        displays: [WrappedDisplay; 2],
+        group: String,
+        before: [u16; 2],
+        percent: f32,
+    ) -> Self {
+        Self {
+            displays,
+            group,
+            before,
+            reference: percent.clamp(REFERENCE.0, REFERENCE.1),
+            staircases: [Staircase::new(), Staircase::new()],
+            turn: 0,
+        }
+    }
+
+    /// Percent of brightness of the first and the second display to be compared now.
+    pub fn levels(&self) -> [f32; 2] {
+        let stepped = (self.reference + self.staircases[self.turn].offset).clamp(0.0, 100.0);
+        match self.turn {
+            0 => [self.reference, stepped],
+            _ => [stepped, self.reference],
+        }
+    }
+
+    pub fn answer(&mut self, brighter: Brighter) {
+        let stepped_brighter = matches!(
+            (self.turn, brighter),
+            (0, Brighter::Second) | (1, Brighter::First)
+        );
+        let staircase = &mut self.staircases[self.turn];
+        staircase.answer(stepped_brighter);
+        // Past the ends the display can't go further, so neither does the staircase
+        staircase.offset = staircase
+            .offset
+            .clamp(-self.reference, 100.0 - self.reference);
+        let other = 1 - self.turn;
+        if !self.staircases[other].settled() {
+            self.turn = other;
+        }
+    }
+
+    pub fn settled(&self) -> bool {
+        self.staircases.iter().all(Staircase::settled)
+    }
+
+    /// Answers given so far.
+    pub fn answers(&self) -> usize {
+        self.staircases
+            .iter()
+            .map(|staircase| staircase.answers)
+            .sum()
+    }
+
+    /// Percent of brightness the second display needs above the first to look alike.
+    pub fn offset(&self) -> i16 {
+        // Stepping the first display finds the offset the other way around
+        let [over, under] = [&self.staircases[0], &self.staircases[1]].map(Staircase::estimate);
+        ((over - under) / 2.0).round() as i16
+    }
+}
@@ -0,0 +1,56 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::Span,
+};
+
+/// Positions of the characters of the label the query matched, as a case insensitive
+/// subsequence, `None` if it doesn't match.
+pub fn positions(label: &str, query: &str) -> Option<Vec<usize>> {
+    let label: Vec<char> = label.to_lowercase().chars().collect();
+    let mut positions = vec![];
+    let mut position = 0;
+    for wanted in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = label[position..].iter().position(|&c| c == wanted)? + position;
+        positions.push(found);
+        position = found + 1;
+    }
+    Some(positions)
+}
+
+/// The label with the characters the query matched highlighted.
+pub fn highlight(label: &str, query: &str) -> Vec<Span<'static>> {
+    let matched = positions(label, query).unwrap_or_default();
+    if matched.is_empty() {
+        return vec![Span::raw(label.to_string())];
+    }
+    let style = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::UNDERLINED);
+    label
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match matched.contains(&i) {
+            true => Span::styled(c.to_string(), style),
+            false => Span::raw(c.to_string()),
+        })
+        .collect()
+}
+
+/// The title of a list with its filter, and a cursor while it is typed.
+pub fn title(title: String, filter: &str, typing: bool) -> String {
+    match (filter, typing) {
+        (filter, true) => format!("{title} /{filter}_"),
+        ("", false) => title,
+        (filter, false) => format!("{title} /{filter}"),
+    }
+}
+
+/// The shown index after `current`, or before it if not `forward`. Past the last one is the first
+/// one again, before the first one stays there.
+pub fn neighbour(shown: &[usize], current: usize, forward: bool) -> usize {
+    let found = match forward {
+        true => shown.iter().find(|&&index| index > current),
+        false => shown.iter().rev().find(|&&index| index < current),
+    };
+    found.or(shown.first()).copied().unwrap_or(current)
+}
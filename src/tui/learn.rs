@@ -0,0 +1,127 @@
+//! Learning how far apart in brightness two displays are set by hand, from the history: each
+//! time both were adjusted in the TUI and then left alone, their difference counts once. Once
+//! the last few agree, the TUI offers a synced group keeping them that far apart.
+
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{DateTime, Local};
+use ddc_bright::display::{Control, WrappedDisplay};
+
+use crate::{
+    group::Group,
+    history::{Entry, Origin},
+};
+
+/// Time both displays are left alone after adjusting them before their difference counts
+pub const SETTLE: Duration = Duration::from_secs(20);
+/// Differences in a row that have to agree
+pub const SAMPLES: usize = 3;
+/// Percent the agreeing differences may be apart
+const SPREAD: f32 = 5.0;
+
+/// A synced group to offer
+pub struct Suggestion {
+    pub displays: [WrappedDisplay; 2],
+    /// Percent of brightness the second is kept above the first
+    pub offset: i16,
+    /// Group of just these two turned into the synced one, a new group if `None`
+    pub group: Option<String>,
+}
+
+/// Two displays of those adjusted last that were set about the same distance apart the last
+/// [`SAMPLES`] times, unless a synced group already keeps them together.
+pub fn suggest(
+    entries: &[Entry],
+    displays: &[WrappedDisplay],
+    groups: &HashMap<String, Group>,
+) -> Option<Suggestion> {
+    let find = |id: &str| displays.iter().find(|display| display.id == id);
+    let percent = |id: &str, value: u16| {
+        let display = find(id)?;
+        let mut controller = *display.controls.get(&Control::Brightness)?.read().unwrap();
+        controller.value = value;
+        Some(display.percent(&controller) as f32)
+    };
+    // Latest brightness of each display, whatever set it
+    let mut levels: HashMap<&str, f32> = HashMap::new();
+    // Displays adjusted by hand since the last pause, in order
+    let mut touched: Vec<&str> = vec![];
+    let mut samples: HashMap<(&str, &str), Vec<f32>> = HashMap::new();
+    let mut last: Option<DateTime<Local>> = None;
+    let brightness = entries
+        .iter()
+        .filter(|entry| entry.code == Control::Brightness as u8);
+    for entry in brightness {
+        let Some(level) = percent(&entry.id, entry.value) else {
+            continue;
+        };
+        let paused = last.is_some_and(|last| {
+            (entry.time - last)
+                .to_std()
+                .is_ok_and(|pause| pause >= SETTLE)
+        });
+        if paused {
+            sample(&touched, &levels, &mut samples);
+            touched.clear();
+            last = None;
+        }
+        if entry.origin == Origin::Tui {
+            if !touched.contains(&entry.id.as_str()) {
+                touched.push(&entry.id);
+            }
+            last = Some(entry.time);
+        }
+        levels.insert(&entry.id, level);
+    }
+    // Called once the last adjustment settled, so that counts too
+    sample(&touched, &levels, &mut samples);
+
+    pairs(&touched).into_iter().find_map(|(first, second)| {
+        let differences = samples.get(&(first, second))?;
+        let recent = differences.get(differences.len().checked_sub(SAMPLES)?..)?;
+        let low = recent.iter().copied().fold(f32::INFINITY, f32::min);
+        let high = recent.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        if high - low > SPREAD {
+            return None;
+        }
+        let displays = [find(first)?.clone(), find(second)?.clone()];
+        let together = |group: &Group| displays.iter().all(|display| group.contains(display));
+        if groups.values().any(|group| group.sync && together(group)) {
+            return None;
+        }
+        let group = groups
+            .iter()
+            .find(|(_, group)| group.displays.len() == 2 && together(group))
+            .map(|(name, _)| name.clone());
+        let offset = recent.iter().sum::<f32>() / recent.len() as f32;
+        Some(Suggestion {
+            displays,
+            offset: offset.round() as i16,
+            group,
+        })
+    })
+}
+
+/// Every pair of the displays, the ids of each in order.
+fn pairs<'a>(ids: &[&'a str]) -> Vec<(&'a str, &'a str)> {
+    let mut pairs = vec![];
+    for (index, &first) in ids.iter().enumerate() {
+        for &second in &ids[index + 1..] {
+            pairs.push((first.min(second), first.max(second)));
+        }
+    }
+    pairs
+}
+
+/// Counts the difference of every pair of the displays adjusted together.
+fn sample<'a>(
+    touched: &[&'a str],
+    levels: &HashMap<&'a str, f32>,
+    samples: &mut HashMap<(&'a str, &'a str), Vec<f32>>,
+) {
+    for (first, second) in pairs(touched) {
+        if let (Some(low), Some(high)) = (levels.get(first), levels.get(second)) {
+            samples.entry((first, second)).or_default().push(high - low);
+        }
+    }
+}
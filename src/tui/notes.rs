@@ -0,0 +1,51 @@
+//! Freeform notes on displays, kept in `notes.toml` next to the config by the stable id of each
+//! display, so they stay with the display rather than the port
+
+use std::{collections::BTreeMap, error::Error, fs, io, path::PathBuf};
+
+use ddc_bright::display::MyDisplay;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Notes {
+    notes: BTreeMap<String, String>,
+}
+
+impl Notes {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("notes.toml"))
+    }
+
+    /// The stored notes, none if there is no file yet. A file that can't be read is an error, so
+    /// it isn't overwritten by the next note.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                Ok(toml::from_str(&text).map_err(|err| format!("{}: {err}", path.display()))?)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn get(&self, display: &MyDisplay) -> Option<&str> {
+        self.notes.get(&display.stable_id()).map(String::as_str)
+    }
+
+    /// Replaces the note of the display, removing it if empty, and stores the notes.
+    pub fn set(&mut self, display: &MyDisplay, note: &str) -> Result<(), Box<dyn Error>> {
+        match note.trim() {
+            "" => self.notes.remove(&display.stable_id()),
+            note => self.notes.insert(display.stable_id(), note.to_string()),
+        };
+        let path = Self::path().ok_or("no config directory")?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use super::action::Action;
+use super::filter;
+use super::App;
+
+/// Actions matching the query, best first. A trailing number in the query offers setting the
+/// controls of the focused display to it.
+pub fn matches(app: &App, query: &str) -> Vec<(String, Action)> {
+    let (query, value) = match query.trim_end().rsplit_once(' ') {
+        Some((rest, number)) => match number.parse::<u16>() {
+            Ok(value) if value <= 100 => (rest, Some(value)),
+            _ => (query, None),
+        },
+        None => match query.trim().parse::<u16>() {
+            Ok(value) if value <= 100 => ("", Some(value)),
+            _ => (query, None),
+        },
+    };
+
+    let mut actions = vec![];
+    if let Some(value) = value {
+        if let Some(display) = app.focused() {
+            for control in display.controls.keys() {
+                actions.push((
+                    format!("Set {} to {value}", control.get_name()),
+                    Action::Set(*control, value),
+                ));
+            }
+        }
+    }
+    for (i, display) in app.manager.displays.iter().enumerate() {
+        actions.push((
+            format!("Select display: {}", display.label()),
+            Action::SelectDisplay(i),
+        ));
+    }
+    for (i, preset) in app.config.presets.iter().enumerate() {
+        actions.push((
+            format!("Apply preset: {}", preset.name),
+            Action::ApplyPreset(i),
+        ));
+        actions.push((
+            format!("Save preset: {}", preset.name),
+            Action::SavePreset(i),
+        ));
+    }
+    if app.control_selected.is_some() {
+        actions.push(("Type a value".to_string(), Action::OpenEdit));
+    }
+    if let Some(display) = app.focused() {
+        actions.push(("Rename display".to_string(), Action::OpenRename));
+        let name = match app.marked.iter().any(|key| key == display.alias_key()) {
+            true => "Unmark display for a group",
+            false => "Mark display for a group",
+        };
+        actions.push((name.to_string(), Action::ToggleMark));
+    }
+    if !app.marked.is_empty() {
+        actions.push(("Group the marked displays".to_string(), Action::OpenGroup));
+    }
+    if app.marked.len() == 2 {
+        let name = "Match the brightness of the marked displays";
+        actions.push((name.to_string(), Action::OpenEqualize));
+    }
+    actions.push(("Filter the list".to_string(), Action::OpenFilter));
+    if let Some(display) = &app.display_selected {
+        let name = match display.is_powered_off() {
+            true => "Turn display on",
+            false => "Turn display off",
+        };
+        actions.push((name.to_string(), Action::TogglePower));
+        actions.push(("Restore factory defaults".to_string(), Action::OpenReset));
+        actions.push(("Read or write a VCP code".to_string(), Action::OpenVcp));
+        actions.push(("Show monitor health".to_string(), Action::OpenHealth));
+        actions.push(("Edit note".to_string(), Action::OpenNote));
+        let name = match app.capabilities_shown.contains(&display.id) {
+            true => "Hide capabilities",
+            false => "Show capabilities",
+        };
+        actions.push((name.to_string(), Action::ToggleCapabilities));
+    }
+    actions.push(("Identify displays".to_string(), Action::Identify));
+    actions.push(("Show history".to_string(), Action::OpenHistory));
+    actions.push(("Show the change queue".to_string(), Action::OpenQueue));
+    let name = match app.log_shown {
+        true => "Hide the log",
+        false => "Show the log",
+    };
+    actions.push((name.to_string(), Action::ToggleLog));
+    let name = match app.wall {
+        true => "List the displays",
+        false => "Show the displays as laid out on the desktop",
+    };
+    actions.push((name.to_string(), Action::ToggleLayout));
+    if app.manager.previewing() {
+        actions.push(("Commit preview".to_string(), Action::CommitPreview));
+        actions.push(("Discard preview".to_string(), Action::DiscardPreview));
+    } else {
+        actions.push(("Start preview".to_string(), Action::Preview));
+    }
+    actions.push(("Reload displays".to_string(), Action::Refresh));
+    actions.push(("Help".to_string(), Action::Help));
+    actions.push(("Quit".to_string(), Action::Quit));
+
+    let mut scored: Vec<(usize, (String, Action))> = actions
+        .into_iter()
+        .filter_map(|action| Some((score(&action.0, query)?, action)))
+        .collect();
+    scored.sort_by_key(|(score, _)| *score);
+    scored.into_iter().map(|(_, action)| action).collect()
+}
+
+/// Matches the query as a case insensitive subsequence, lower scores are tighter matches.
+fn score(label: &str, query: &str) -> Option<usize> {
+    let positions = filter::positions(label, query)?;
+    Some(match (positions.first(), positions.last()) {
+        (Some(first), Some(last)) => last + 1 - first,
+        _ => 0,
+    })
+}
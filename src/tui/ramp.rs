@@ -0,0 +1,79 @@
+//! Adjusting on a timer while an arrow key is held, instead of once per repeated key press
+
+use std::time::{Duration, Instant};
+
+use crossterm::event::KeyCode;
+use ddc_bright::display::{WrappedController, WrappedDisplay};
+
+/// Presses of the same key closer together than this come from the key repeating
+pub const HOLD_GAP: Duration = Duration::from_millis(120);
+
+/// The key counts as let go once it stopped repeating for this long, for terminals that don't
+/// report releases
+const RELEASE_GAP: Duration = Duration::from_millis(200);
+
+/// Time between steps of a ramp
+pub const TICK: Duration = Duration::from_millis(50);
+
+/// Steps grow by one key press worth every second held, up to this many
+const MAX_SPEED: i16 = 4;
+
+pub struct Ramp {
+    pub code: KeyCode,
+    /// Step of a single key press, negative when decreasing
+    step: i16,
+    /// Values are only shown until the key is let go, then written once
+    pub write_back: bool,
+    /// Controls held back with the value they had before the ramp
+    pub held: Vec<(WrappedDisplay, WrappedController, u16)>,
+    started: Instant,
+    /// When the key last repeated and how long it took to
+    seen: Instant,
+    interval: Duration,
+    next_tick: Instant,
+}
+
+impl Ramp {
+    pub fn new(code: KeyCode, step: i16, write_back: bool, interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            code,
+            step,
+            write_back,
+            held: vec![],
+            started: now,
+            seen: now,
+            interval,
+            next_tick: now,
+        }
+    }
+
+    /// Notes the key repeating.
+    pub fn seen(&mut self) {
+        let now = Instant::now();
+        self.interval = (now - self.seen).min(RELEASE_GAP);
+        self.seen = now;
+    }
+
+    pub fn released(&self) -> bool {
+        self.seen.elapsed() >= RELEASE_GAP
+    }
+
+    /// Sum of the steps due since the last call, larger the longer the key is held.
+    pub fn due(&mut self) -> i16 {
+        let now = Instant::now();
+        // A key let go only shows by not repeating any more, so the ramp doesn't run ahead of it
+        let until = now.min(self.seen + self.interval + TICK);
+        let mut amount = 0;
+        while self.next_tick <= until {
+            let speed = (1 + (self.next_tick - self.started).as_secs() as i16).min(MAX_SPEED);
+            amount += self.step * speed;
+            self.next_tick += TICK;
+        }
+        // Time waited for the key to repeat isn't made up for
+        if until < now {
+            self.next_tick = self.next_tick.max(now);
+        }
+        amount
+    }
+}
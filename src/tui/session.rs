@@ -0,0 +1,104 @@
+//! Where the TUI was left, kept in `session.toml` next to the config so the next run starts there
+
+use std::{collections::BTreeMap, error::Error, fs, path::PathBuf};
+
+use ddc_bright::display::{Control, WrappedDisplay};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// A row of the control list, named so it is found again after controls come and go
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Row {
+    Control(Control),
+    Composite(String),
+    Temperature,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Session {
+    /// Stable id of the highlighted display
+    pub display: Option<String>,
+    /// Whether the display was selected rather than only highlighted
+    pub selected: bool,
+    pub row: Option<Row>,
+    pub step: Option<i16>,
+    pub linked: bool,
+    /// First visible rows of the display and control lists
+    pub display_offset: usize,
+    pub control_offset: usize,
+    /// Values of the controls of each display by stable id, as they were left
+    pub values: BTreeMap<String, BTreeMap<Control, u16>>,
+}
+
+impl Session {
+    fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("session.toml"))
+    }
+
+    /// The last session, or none if it was never stored or can't be read any more.
+    pub fn load() -> Option<Self> {
+        let text = fs::read_to_string(Self::path()?).ok()?;
+        toml::from_str(&text).ok()
+    }
+
+    /// The values of the displays now, for comparing them to on the next run. Left out are
+    /// previewed values and those the display never answered with.
+    pub fn values(displays: &[WrappedDisplay]) -> BTreeMap<String, BTreeMap<Control, u16>> {
+        displays
+            .iter()
+            .map(|display| {
+                let values = display
+                    .controls
+                    .iter()
+                    .map(|(control, controller)| (*control, controller.read().unwrap()))
+                    .filter(|(_, controller)| {
+                        !controller.previewed && controller.confirmed.is_some()
+                    })
+                    .map(|(control, controller)| (control, controller.value))
+                    .collect();
+                (display.stable_id(), values)
+            })
+            .collect()
+    }
+
+    /// One line naming the values that changed since the session was stored, like a display
+    /// resetting itself or another program writing to it. Displays and controls that didn't
+    /// answer are left out, they only show their defaults.
+    pub fn changes(&self, displays: &[WrappedDisplay]) -> Option<String> {
+        let mut changes = vec![];
+        for display in displays {
+            let Some(before) = self.values.get(&display.stable_id()) else {
+                continue;
+            };
+            if display.is_unreachable() {
+                continue;
+            }
+            for (control, controller) in &display.controls {
+                let controller = controller.read().unwrap();
+                if controller.confirmed.is_none() || controller.failed {
+                    continue;
+                }
+                let after = controller.value;
+                match before.get(control) {
+                    Some(&before) if before != after => changes.push(format!(
+                        "{} {} {before}→{after}",
+                        display.label(),
+                        control.key()
+                    )),
+                    _ => {}
+                }
+            }
+        }
+        (!changes.is_empty()).then(|| format!("Changed since the last run: {}", changes.join(", ")))
+    }
+
+    pub fn store(&self) -> Result<(), Box<dyn Error>> {
+        let path = Self::path().ok_or("no config directory")?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use ddc_bright::{
+    display::{Control, MyDisplay},
+    units,
+};
+
+/// Answers kept in the form, older ones scroll out
+const KEPT: usize = 8;
+
+/// State of the form reading and writing any VCP code of one display
+#[derive(Default)]
+pub struct VcpForm {
+    pub code: String,
+    pub value: String,
+    /// Typing into the value field rather than the code field
+    pub on_value: bool,
+    /// What the display answered so far, newest last, or why it couldn't be asked
+    pub answers: Vec<Result<String, String>>,
+}
+
+impl VcpForm {
+    pub fn field(&mut self) -> &mut String {
+        match self.on_value {
+            true => &mut self.value,
+            false => &mut self.code,
+        }
+    }
+
+    /// Reads the typed code, writing the typed value to it first if there is one. Returns the
+    /// code and the value read back after a write.
+    pub fn run(&mut self, display: &MyDisplay) -> Option<(u8, u16)> {
+        let code = match units::parse_code(&self.code) {
+            Ok(code) => code,
+            Err(err) => return self.answer(Err(err)),
+        };
+        let value = match self.value.trim() {
+            "" => None,
+            value => match units::parse_raw(value) {
+                Ok(value) => Some(value),
+                Err(err) => return self.answer(Err(err)),
+            },
+        };
+        let name = Control::from_code(code)
+            .map(|control| format!(" {}", control.get_name()))
+            .unwrap_or_default();
+        let result = match value {
+            Some(value) => display.set_raw(code, value),
+            None => display.get_raw(code),
+        };
+        let (current, maximum) = match result {
+            Ok(answer) => answer,
+            Err(err) => return self.answer(Err(format!("0x{code:02x}{name}: {err}"))),
+        };
+        let read = format!("{current} (0x{current:02x}) of {maximum}");
+        match value {
+            Some(value) => {
+                self.answer(Ok(format!(
+                    "0x{code:02x}{name} set to {value}, reads {read}"
+                )));
+                Some((code, current))
+            }
+            None => self.answer(Ok(format!("0x{code:02x}{name} is {read}"))),
+        }
+    }
+
+    fn answer(&mut self, answer: Result<String, String>) -> Option<(u8, u16)> {
+        self.answers.push(answer);
+        let excess = self.answers.len().saturating_sub(KEPT);
+        self.answers.drain(..excess);
+        None
+    }
+}
@@ -0,0 +1,109 @@
+//! The layout view of the display list: every display as a box where the OS puts it on the
+//! desktop, moved between with the arrow keys by where the boxes are
+
+use ddc_bright::display::WrappedDisplay;
+use ratatui::layout::Rect;
+
+use crate::keys::Move;
+
+/// Size taken for displays the OS doesn't tell the resolution of
+const UNKNOWN: (u32, u32) = (1920, 1080);
+
+/// Left, top, right and bottom edge on the desktop in pixels
+type Edges = (i64, i64, i64, i64);
+
+/// Where the shown displays are on the desktop. Those without a position, and mirrored ones at
+/// the place of another, go in a row below the rest.
+fn desktop(displays: &[WrappedDisplay], shown: &[usize]) -> Vec<(usize, Edges)> {
+    let mut placed: Vec<(usize, Edges)> = vec![];
+    let mut unplaced = vec![];
+    for &index in shown {
+        let output = displays[index].output.as_ref();
+        let (width, height) = output
+            .and_then(|output| output.resolution)
+            .unwrap_or(UNKNOWN);
+        match output.and_then(|output| output.position) {
+            Some((x, y))
+                if !placed
+                    .iter()
+                    .any(|(_, edges)| (edges.0, edges.1) == (x as i64, y as i64)) =>
+            {
+                let (x, y) = (x as i64, y as i64);
+                placed.push((index, (x, y, x + width as i64, y + height as i64)));
+            }
+            _ => unplaced.push((index, (width as i64, height as i64))),
+        }
+    }
+    let left = placed.iter().map(|(_, edges)| edges.0).min().unwrap_or(0);
+    let top = placed.iter().map(|(_, edges)| edges.3).max().unwrap_or(0);
+    let mut x = left;
+    for (index, (width, height)) in unplaced {
+        placed.push((index, (x, top, x + width, top + height)));
+        x += width;
+    }
+    placed
+}
+
+/// The box of each shown display within `area`, scaled to fit and centred. Cells are about
+/// twice as tall as wide, so heights are halved to keep the proportions.
+pub fn place(displays: &[WrappedDisplay], shown: &[usize], area: Rect) -> Vec<(usize, Rect)> {
+    let placed = desktop(displays, shown);
+    let (Some(left), Some(top)) = (
+        placed.iter().map(|(_, edges)| edges.0).min(),
+        placed.iter().map(|(_, edges)| edges.1).min(),
+    ) else {
+        return vec![];
+    };
+    let right = placed
+        .iter()
+        .map(|(_, edges)| edges.2)
+        .max()
+        .unwrap_or(left);
+    let bottom = placed.iter().map(|(_, edges)| edges.3).max().unwrap_or(top);
+    let (width, height) = ((right - left).max(1) as f64, (bottom - top).max(1) as f64);
+    let scale = (area.width as f64 / width).min(2.0 * area.height as f64 / height);
+    let margin_x = (area.width as f64 - width * scale) / 2.0;
+    let margin_y = (area.height as f64 - height * scale / 2.0) / 2.0;
+    // Both edges are rounded so neighbouring boxes meet without gaps
+    let column = |x: i64| area.x + (margin_x + (x - left) as f64 * scale).round() as u16;
+    let row = |y: i64| area.y + (margin_y + (y - top) as f64 * scale / 2.0).round() as u16;
+    placed
+        .into_iter()
+        .map(|(index, (x0, y0, x1, y1))| {
+            let (x, y) = (column(x0), row(y0));
+            let rect = Rect::new(x, y, column(x1) - x, row(y1) - y);
+            (index, rect.intersection(area))
+        })
+        .collect()
+}
+
+/// The shown display nearest to `from` in the direction, `from` itself if there is none.
+pub fn neighbour(
+    displays: &[WrappedDisplay],
+    shown: &[usize],
+    from: usize,
+    direction: Move,
+) -> usize {
+    let placed = desktop(displays, shown);
+    let centre = |(x0, y0, x1, y1): Edges| ((x0 + x1) / 2, (y0 + y1) / 2);
+    let Some(&(_, start)) = placed.iter().find(|(index, _)| *index == from) else {
+        return shown.first().copied().unwrap_or(from);
+    };
+    let (x, y) = centre(start);
+    placed
+        .iter()
+        .filter_map(|&(index, edges)| {
+            let (dx, dy) = (centre(edges).0 - x, centre(edges).1 - y);
+            // Distance along the direction, and off to the side of it
+            let (along, aside) = match direction {
+                Move::Up => (-dy, dx),
+                Move::Down => (dy, dx),
+                Move::Left => (-dx, dy),
+                Move::Right => (dx, dy),
+            };
+            // Displays off to the side count as further away than those straight ahead
+            (along > 0).then_some((along + 2 * aside.abs(), index))
+        })
+        .min()
+        .map_or(from, |(_, index)| index)
+}
@@ -0,0 +1,231 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// How volume is shown
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum VolumeUnit {
+    #[default]
+    Percent,
+    /// Approximate decibels relative to the loudest value
+    Db,
+}
+
+/// Approximate attenuation of a raw value, treating the range as linear amplitude.
+/// `None` means muted.
+pub fn to_db(value: u16, maximum: u16) -> Option<f32> {
+    if value == 0 || maximum == 0 {
+        return None;
+    }
+    Some(20.0 * (value as f32 / maximum as f32).log10())
+}
+
+pub fn from_db(db: f32, maximum: u16) -> u16 {
+    let value = maximum as f32 * 10f32.powf(db.min(0.0) / 20.0);
+    value.round() as u16
+}
+
+/// A value given on the command line: raw (`40`), percent of the maximum (`40%`) or decibels
+/// (`-20dB`)
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum Level {
+    Raw(u16),
+    Percent(f32),
+    Db(f32),
+}
+
+impl Level {
+    /// The raw value to write for a control reaching up to `maximum`.
+    pub fn resolve(self, maximum: u16) -> u16 {
+        match self {
+            Level::Raw(value) => value.min(maximum),
+            Level::Percent(percent) => {
+                (maximum as f32 * percent.clamp(0.0, 100.0) / 100.0).round() as u16
+            }
+            Level::Db(db) => from_db(db, maximum),
+        }
+    }
+}
+
+/// Written the way it is parsed, e.g. `40%`.
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Raw(value) => write!(f, "{value}"),
+            Level::Percent(percent) => write!(f, "{percent}%"),
+            Level::Db(db) => write!(f, "{db}dB"),
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let invalid = || format!("{text:?} is not a value like 40, 40% or -20dB");
+        let lower = text.to_ascii_lowercase();
+        if let Some(db) = lower.strip_suffix("db") {
+            return Ok(Level::Db(db.trim().parse().map_err(|_| invalid())?));
+        }
+        if let Some(percent) = lower.strip_suffix('%') {
+            return Ok(Level::Percent(
+                percent.trim().parse().map_err(|_| invalid())?,
+            ));
+        }
+        Ok(Level::Raw(text.parse().map_err(|_| invalid())?))
+    }
+}
+
+/// A value to set: a level, or a step from the current value if it starts with a sign (`+10`,
+/// `-5%`). Decibels are always a level, since most of them are negative.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum Adjust {
+    To(Level),
+    /// Raw steps added to the current value
+    By(i32),
+    /// Percent of the range added to the current percentage
+    ByPercent(f32),
+}
+
+impl Adjust {
+    /// The raw value to write for a control at `value` reaching up to `maximum`.
+    pub fn resolve(self, value: u16, maximum: u16) -> u16 {
+        match self {
+            Adjust::To(level) => level.resolve(maximum),
+            Adjust::By(step) => (value as i32 + step).clamp(0, maximum as i32) as u16,
+            Adjust::ByPercent(percent) => {
+                let current = match maximum {
+                    0 => 0.0,
+                    maximum => value as f32 * 100.0 / maximum as f32,
+                };
+                Level::Percent(current + percent).resolve(maximum)
+            }
+        }
+    }
+
+    pub fn is_relative(self) -> bool {
+        !matches!(self, Adjust::To(_))
+    }
+}
+
+impl From<Level> for Adjust {
+    fn from(level: Level) -> Self {
+        Adjust::To(level)
+    }
+}
+
+/// Written the way it is parsed, e.g. `+10` or `40%`.
+impl fmt::Display for Adjust {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Adjust::To(level) => write!(f, "{level}"),
+            Adjust::By(step) => write!(f, "{step:+}"),
+            Adjust::ByPercent(percent) => write!(f, "{percent:+}%"),
+        }
+    }
+}
+
+impl FromStr for Adjust {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let lower = text.to_ascii_lowercase();
+        if !text.starts_with(['+', '-']) || lower.ends_with("db") {
+            return Ok(Adjust::To(text.parse()?));
+        }
+        let invalid = || format!("{text:?} is not a value like 40, 40%, -20dB, +10 or -5%");
+        match lower.strip_suffix('%') {
+            Some(percent) => Ok(Adjust::ByPercent(
+                percent.trim().parse().map_err(|_| invalid())?,
+            )),
+            None => Ok(Adjust::By(text.parse().map_err(|_| invalid())?)),
+        }
+    }
+}
+
+/// A VCP code the way MCCS lists them, in hex with or without `0x`.
+pub fn parse_code(text: &str) -> Result<u8, String> {
+    let text = text.trim();
+    let hex = text.strip_prefix("0x").unwrap_or(text);
+    u8::from_str_radix(hex, 16).map_err(|_| format!("{text:?} is not a VCP code like 10 or 0x10"))
+}
+
+/// A raw value in decimal, or in hex like `0x11`.
+pub fn parse_raw(text: &str) -> Result<u16, String> {
+    let text = text.trim();
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| format!("{text:?} is not a number like 17 or 0x11"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_parse_by_their_suffix() {
+        assert!(matches!("40".parse(), Ok(Level::Raw(40))));
+        assert!(matches!(" 40 % ".parse(), Ok(Level::Percent(40.0))));
+        assert!(matches!("-20dB".parse(), Ok(Level::Db(-20.0))));
+        assert!(matches!("-6 DB".parse(), Ok(Level::Db(-6.0))));
+        assert!("40x".parse::<Level>().is_err());
+        assert!("-4".parse::<Level>().is_err());
+    }
+
+    #[test]
+    fn levels_resolve_within_the_maximum() {
+        assert_eq!(Level::Raw(120).resolve(100), 100);
+        assert_eq!(Level::Percent(50.0).resolve(80), 40);
+        assert_eq!(Level::Percent(150.0).resolve(80), 80);
+        assert_eq!(Level::Db(0.0).resolve(100), 100);
+        assert_eq!(Level::Db(-20.0).resolve(100), 10);
+        // Gains above 0dB can't be had
+        assert_eq!(Level::Db(6.0).resolve(100), 100);
+    }
+
+    #[test]
+    fn decibels_round_trip() {
+        assert_eq!(to_db(0, 100), None);
+        assert_eq!(to_db(100, 100), Some(0.0));
+        let db = to_db(10, 100).unwrap();
+        assert!((db + 20.0).abs() < 1e-4);
+        assert_eq!(from_db(db, 100), 10);
+    }
+
+    #[test]
+    fn signs_make_adjustments_relative() {
+        assert!(matches!("+10".parse(), Ok(Adjust::By(10))));
+        assert!(matches!("-5%".parse(), Ok(Adjust::ByPercent(-5.0))));
+        assert!(matches!(
+            "40%".parse(),
+            Ok(Adjust::To(Level::Percent(40.0)))
+        ));
+        // Decibels are a level even though they are negative
+        assert!(matches!("-20dB".parse(), Ok(Adjust::To(Level::Db(-20.0)))));
+        assert!("+ten".parse::<Adjust>().is_err());
+    }
+
+    #[test]
+    fn adjustments_resolve_from_the_current_value() {
+        assert_eq!(Adjust::By(10).resolve(95, 100), 100);
+        assert_eq!(Adjust::By(-10).resolve(5, 100), 0);
+        assert_eq!(Adjust::ByPercent(25.0).resolve(20, 80), 40);
+        assert_eq!(Adjust::ByPercent(10.0).resolve(0, 0), 0);
+        assert_eq!(Adjust::To(Level::Raw(30)).resolve(90, 100), 30);
+    }
+
+    #[test]
+    fn values_are_written_the_way_they_are_parsed() {
+        for text in ["40", "40%", "-20dB", "+10", "-5%"] {
+            assert_eq!(text.parse::<Adjust>().unwrap().to_string(), text);
+        }
+    }
+}
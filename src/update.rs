@@ -0,0 +1,70 @@
+//! Looking for a newer release on GitHub, in the background so the TUI doesn't wait for it
+
+use std::{
+    error::Error,
+    sync::mpsc::{channel, Receiver},
+    thread,
+};
+
+use serde::Deserialize;
+
+/// Latest release of the repository, through the GitHub API
+#[cfg(feature = "update")]
+const LATEST: &str = "https://api.github.com/repos/thegamerx1/ddc_bright/releases/latest";
+
+#[derive(Deserialize, Clone)]
+pub struct Release {
+    /// Version tag, e.g. `v0.3.0`
+    #[serde(rename = "tag_name")]
+    pub tag: String,
+    /// Changelog in Markdown
+    #[serde(default)]
+    pub body: Option<String>,
+    pub html_url: String,
+}
+
+/// Sends the latest release once it is known, if it is newer than this build. Nothing is sent
+/// when offline or the request fails, the check isn't worth an error.
+pub fn check() -> Receiver<Release> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        if let Ok(release) = latest() {
+            if newer(&release.tag, env!("CARGO_PKG_VERSION")) {
+                let _ = sender.send(release);
+            }
+        }
+    });
+    receiver
+}
+
+/// Asks curl, which ships with Windows, macOS and most Linux installs, so no TLS stack has to
+/// be built in.
+#[cfg(feature = "update")]
+fn latest() -> Result<Release, Box<dyn Error>> {
+    let output = std::process::Command::new("curl")
+        .args(["--silent", "--fail", "--location", "--max-time", "10"])
+        .args(["--header", "Accept: application/vnd.github+json", LATEST])
+        .output()?;
+    if !output.status.success() {
+        return Err(format!("curl failed with {}", output.status).into());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+#[cfg(not(feature = "update"))]
+fn latest() -> Result<Release, Box<dyn Error>> {
+    Err("built without the update feature".into())
+}
+
+/// Whether the version of `tag`, e.g. `v0.3.0`, is newer than `current`, ignoring suffixes
+/// like `-rc1`.
+fn newer(tag: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Option<Vec<u64>> {
+        let version = version.trim_start_matches('v').split('-').next()?;
+        version.split('.').map(|part| part.parse().ok()).collect()
+    };
+    match (parse(tag), parse(current)) {
+        (Some(tag), Some(current)) => tag > current,
+        _ => false,
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::display::{Control, MyDisplay};
+
+/// Names for values of the non-continuous controls, for displays with codes of their own or to
+/// call inputs by what is plugged into them
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct ValueNames {
+    /// Model name, id or serial of the display, every display if unset
+    pub display: Option<String>,
+    /// Input source values by name, like `desktop = 15`
+    pub inputs: BTreeMap<String, u16>,
+    /// Colour preset values by name
+    pub color_presets: BTreeMap<String, u16>,
+}
+
+impl ValueNames {
+    pub fn applies(&self, display: &MyDisplay) -> bool {
+        self.display
+            .as_deref()
+            .is_none_or(|query| display.matches(query))
+    }
+
+    fn names(&self, control: Control) -> Option<&BTreeMap<String, u16>> {
+        match control {
+            Control::InputSource => Some(&self.inputs),
+            Control::ColorPreset => Some(&self.color_presets),
+            _ => None,
+        }
+    }
+}
+
+/// The value names of the display by control, names of entries naming it replacing those of
+/// entries for every display.
+pub fn resolve(
+    entries: &[ValueNames],
+    display: &MyDisplay,
+) -> BTreeMap<Control, Vec<(String, u16)>> {
+    let applying: Vec<&ValueNames> = entries.iter().filter(|e| e.applies(display)).collect();
+    let generic = applying.iter().filter(|entry| entry.display.is_none());
+    let named = applying.iter().filter(|entry| entry.display.is_some());
+    let mut resolved: BTreeMap<Control, Vec<(String, u16)>> = BTreeMap::new();
+    for entry in generic.chain(named) {
+        for control in [Control::InputSource, Control::ColorPreset] {
+            let names = resolved.entry(control).or_default();
+            for (name, value) in entry.names(control).into_iter().flatten() {
+                names.retain(|(other, _)| other != name);
+                names.push((name.clone(), *value));
+            }
+        }
+    }
+    resolved.retain(|_, names| !names.is_empty());
+    resolved
+}
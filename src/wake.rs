@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    thread,
+    time::{Duration, Instant},
+};
+
+use ddc_bright::{
+    display::{DisplayManager, MyDisplay, INPUT_SOURCE},
+    supervisor::Supervisor,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{AudioConfig, Trigger};
+use crate::automation::{Automation, AutomationConfig};
+use crate::history::{Log, Origin};
+use crate::logging;
+
+/// How often the background thread wakes up to see whether it was cancelled
+const TICK: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct WakeConfig {
+    /// Seconds between reading the power mode of the displays with rules, including the audio
+    /// rules applying on power off
+    pub interval: u64,
+    pub rules: Vec<WakeRule>,
+}
+
+impl Default for WakeConfig {
+    fn default() -> Self {
+        Self {
+            interval: 2,
+            rules: vec![],
+        }
+    }
+}
+
+/// Switches the input of a display that just woke up from standby
+#[derive(Serialize, Deserialize)]
+pub struct WakeRule {
+    /// Model name or id
+    pub display: String,
+    /// Raw input select value the display woke up on, any input if unset
+    pub input: Option<u16>,
+    /// Raw input select value to switch to
+    pub switch_to: u16,
+}
+
+/// Watches the power mode of the displays with rules while the returned supervisor lives.
+pub fn start(
+    manager: &DisplayManager,
+    config: &WakeConfig,
+    audio: &AudioConfig,
+    automation: &AutomationConfig,
+) -> Option<Supervisor> {
+    if config.rules.is_empty() && !audio.rules.iter().any(|rule| rule.off) {
+        return None;
+    }
+    let audio = audio.clone();
+    let automation = automation.clone();
+    let displays = manager.shared_displays();
+    let rules: Vec<(String, Option<u16>, u16)> = config
+        .rules
+        .iter()
+        .map(|rule| (rule.display.clone(), rule.input, rule.switch_to))
+        .collect();
+    let interval = Duration::from_secs(config.interval);
+
+    let mut supervisor = Supervisor::default();
+    supervisor.spawn("wake rules", move |token| {
+        // Displays count as awake until seen asleep, so starting up switches nothing
+        let mut awake: HashMap<String, bool> = HashMap::new();
+        let mut last_check = Instant::now();
+        while !token.is_cancelled() {
+            thread::sleep(TICK);
+            if last_check.elapsed() < interval {
+                continue;
+            }
+            last_check = Instant::now();
+            let displays = displays.lock().unwrap().clone();
+            for display in displays {
+                let matching: Vec<_> = rules
+                    .iter()
+                    .filter(|(query, _, _)| display.matches(query))
+                    .collect();
+                if matching.is_empty() && !audio.watches_power(&display) {
+                    continue;
+                }
+                let now = display.is_awake();
+                let was = awake.insert(display.id.clone(), now).unwrap_or(true);
+                if was && !now {
+                    switch_audio(&audio, &display, Trigger::Off);
+                }
+                if was || !now {
+                    continue;
+                }
+                let Ok(input) = display.get_vcp(INPUT_SOURCE) else {
+                    continue;
+                };
+                let rule = matching
+                    .iter()
+                    .find(|(_, from, _)| from.is_none_or(|from| from == input & 0xFF));
+                let rule = rule.filter(|_| automation.is_active(Automation::Wake));
+                if let Some((_, _, switch_to)) = rule {
+                    match display.set_vcp(INPUT_SOURCE, *switch_to) {
+                        Ok(()) => {
+                            Log::default().record(Origin::Wake, &display, INPUT_SOURCE, *switch_to);
+                            switch_audio(&audio, &display, Trigger::Input(*switch_to));
+                        }
+                        Err(err) => logging::report(format!(
+                            "{}: switching input failed: {err}",
+                            display.label()
+                        )),
+                    }
+                }
+            }
+        }
+    });
+    Some(supervisor)
+}
+
+fn switch_audio(audio: &AudioConfig, display: &MyDisplay, trigger: Trigger) {
+    if let Err(err) = audio.switch(display, trigger) {
+        logging::report(format!(
+            "{}: switching audio output failed: {err}",
+            display.label()
+        ));
+    }
+}
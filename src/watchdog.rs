@@ -0,0 +1,426 @@
+use serde::{Deserialize, Serialize};
+
+// The config is understood without the daemon feature, so files stay valid across builds
+#[cfg(feature = "daemon")]
+use {
+    crate::ambient::Adaptive,
+    crate::automation::Automation,
+    crate::config::Config,
+    crate::control,
+    crate::events::{record, Emitter},
+    crate::history::{self, Log, Origin},
+    crate::schedule::Runner,
+    crate::setup::Tracker,
+    ddc_bright::{
+        display::{Control, DisplayManager, MyDisplay, INPUT_SOURCE},
+        supervisor::{CancelToken, Supervisor},
+    },
+    serde_json::json,
+    std::{
+        collections::{HashMap, HashSet},
+        error::Error,
+        sync::{
+            mpsc::{channel, RecvTimeoutError, Sender},
+            Arc, Mutex,
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchdogConfig {
+    /// Seconds between checks
+    pub interval: u64,
+    pub displays: Vec<Expected>,
+    /// Displays read now and then so they keep answering
+    pub keepalive: Vec<KeepAlive>,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            interval: 60,
+            displays: vec![],
+            keepalive: vec![],
+        }
+    }
+}
+
+/// A display that stops answering DDC after a long idle time until it gets a read
+#[derive(Serialize, Deserialize)]
+pub struct KeepAlive {
+    /// Model name or id
+    pub display: String,
+    /// Seconds between reads
+    #[serde(default = "default_keepalive_interval")]
+    pub interval: u64,
+}
+
+fn default_keepalive_interval() -> u64 {
+    300
+}
+
+/// The state a display should be kept in
+#[derive(Serialize, Deserialize)]
+pub struct Expected {
+    /// Model name or id
+    pub display: String,
+    pub brightness: Option<u16>,
+    pub contrast: Option<u16>,
+    /// Raw input select value
+    pub input: Option<u16>,
+}
+
+/// The values of the watched controls written last by display id and code, other than by the
+/// watchdog itself
+#[cfg(feature = "daemon")]
+type Effective = Arc<Mutex<HashMap<(String, u8), u16>>>;
+
+/// Work handed to the thread owning the display manager of the daemon
+#[cfg(feature = "daemon")]
+type Job = Box<dyn FnOnce(&DisplayManager) + Send>;
+
+/// Lets the control socket, D-Bus and MQTT write through the manager of the daemon, so their
+/// changes are queued, coupled and retried like every other
+#[cfg(feature = "daemon")]
+#[derive(Clone)]
+pub struct Jobs(Sender<Job>);
+
+#[cfg(feature = "daemon")]
+impl Jobs {
+    /// Runs `job` with the manager between the checks of the daemon and waits for what it
+    /// returns. `None` once the daemon stopped.
+    pub fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&DisplayManager) -> T + Send + 'static,
+    ) -> Option<T> {
+        let (reply, result) = channel();
+        let job = move |manager: &DisplayManager| {
+            let _ = reply.send(job(manager));
+        };
+        self.0.send(Box::new(job)).ok()?;
+        result.recv().ok()
+    }
+}
+
+#[cfg(feature = "daemon")]
+impl Expected {
+    /// The values to keep `display` at: the configured ones, or what the schedule, a preset or
+    /// another writer set since.
+    fn values(&self, display: &MyDisplay, effective: &Effective) -> Vec<(u8, &'static str, u16)> {
+        let effective = effective.lock().unwrap();
+        [
+            (Control::Brightness as u8, "brightness", self.brightness),
+            (Control::Contrast as u8, "contrast", self.contrast),
+            (INPUT_SOURCE, "input", self.input),
+        ]
+        .into_iter()
+        .filter_map(|(code, name, value)| {
+            let written = effective.get(&(display.id.clone(), code)).copied();
+            Some((code, name, written.or(value)?))
+        })
+        .collect()
+    }
+}
+
+/// How often a sleeping watchdog checks whether it was stopped
+#[cfg(feature = "daemon")]
+const TICK: Duration = Duration::from_millis(500);
+
+#[cfg(feature = "daemon")]
+/// Keeps the configured displays in their expected state and applies the schedule until
+/// `stop` is cancelled. A value the schedule, a preset or a request writes is kept from then on
+/// instead of the configured one.
+pub fn run(
+    manager: &mut DisplayManager,
+    config: &Config,
+    stop: &CancelToken,
+) -> Result<(), Box<dyn Error>> {
+    let effective = Effective::default();
+    let written = effective.clone();
+    let events = Arc::new(Emitter::new(
+        config.events.webhook.clone(),
+        config.events.retries,
+    ));
+    let emitter = events.clone();
+    // Every change recorded in the process, whatever made it
+    history::watch(Some(Box::new(move |entry| {
+        if !matches!(entry.origin, Origin::Watchdog | Origin::External) {
+            let key = (entry.id.clone(), entry.code);
+            written.lock().unwrap().insert(key, entry.value);
+        }
+        let fields = json!({
+            "display": entry.id,
+            "control": entry.control(),
+            "from": entry.old,
+            "to": entry.value,
+            "origin": entry.origin.to_string(),
+        });
+        emitter.emit("value_changed", fields);
+    })));
+    let result = keep(manager, config, stop, &effective, &events);
+    history::watch(None);
+    result
+}
+
+#[cfg(feature = "daemon")]
+fn keep(
+    manager: &mut DisplayManager,
+    config: &Config,
+    stop: &CancelToken,
+    effective: &Effective,
+    events: &Emitter,
+) -> Result<(), Box<dyn Error>> {
+    let watchdog = &config.watchdog;
+    let (jobs, received) = channel::<Job>();
+    let jobs = Jobs(jobs);
+    let endpoint = control::endpoint(config.control_socket.as_ref());
+    let _control = control::start(
+        manager,
+        jobs.clone(),
+        endpoint.clone(),
+        control::Settings {
+            audio: config.audio.clone(),
+            notifications: config.notifications.clone(),
+            readouts: config.readouts.clone(),
+        },
+    )
+    .map_err(|err| format!("{}: {err}", endpoint.display()))?;
+    println!("Taking commands on {}", endpoint.display());
+    // Headless machines often have no session bus, the daemon is useful without it
+    #[cfg(all(target_os = "linux", feature = "dbus"))]
+    let _dbus = match crate::dbus::start(manager, jobs.clone(), &config.audio, &config.dbus) {
+        Ok(supervisor) => {
+            let bus = match config.dbus.system {
+                true => "system",
+                false => "session",
+            };
+            println!("Serving {} on the {bus} bus", crate::dbus::NAME);
+            Some(supervisor)
+        }
+        Err(err) => {
+            eprintln!("D-Bus: {err}");
+            None
+        }
+    };
+    #[cfg(feature = "mqtt")]
+    let _mqtt = crate::mqtt::start(manager, jobs.clone(), &config.audio, &config.mqtt);
+    let _keepalive = keep_alive(manager, &watchdog.keepalive);
+    let _kvm = crate::kvm::start(jobs.clone(), &config.kvm, &config.audio, &config.automation)?;
+    let _hotkeys = crate::hotkeys::start(config, jobs.clone())?;
+    // Declared after the listeners so it goes first, failing the jobs they still wait for
+    // instead of leaving them waiting while they are stopped
+    let received = received;
+    drop(jobs);
+    let mut schedule = Runner::new();
+    let mut ambient = match config.ambient.adaptive {
+        true => Some(Adaptive::new(&config.ambient).map_err(|err| format!("ambient: {err}"))?),
+        false => None,
+    };
+    let alerts = Emitter::new(config.events.alert_webhook.clone(), config.events.retries);
+    let mut connected = ids(manager);
+    let mut unresponsive = HashSet::new();
+    let mut history = Log::default();
+    let failures = manager.watch_failures();
+    let mut setups = Tracker::default();
+    apply_setup(&mut setups, config, manager);
+    #[cfg(all(any(target_os = "linux", windows), feature = "hotplug"))]
+    let hotplug = manager
+        .watch_hotplug()
+        .map_err(|err| eprintln!("{err}"))
+        .ok();
+    // A refresh that failed is tried again after the next check
+    let mut retry = false;
+    while !stop.is_cancelled() {
+        let mut lost = retry;
+        let correcting = config.automation.is_active(Automation::Watchdog);
+        for expected in watchdog.displays.iter().filter(|_| correcting) {
+            let display = manager
+                .displays
+                .iter()
+                .find(|display| display.matches(&expected.display));
+            let result = match display {
+                Some(display) => correct(display, expected, effective, &mut history),
+                None => Err("not connected".into()),
+            };
+
+            match result {
+                Ok(()) => {
+                    if unresponsive.remove(&expected.display) {
+                        println!("{}: responding again", expected.display);
+                        record("recovered", Some(&expected.display), "responding again");
+                        alerts.emit("recovered", json!({ "display": expected.display }));
+                    }
+                }
+                Err(err) => {
+                    lost = true;
+                    if unresponsive.insert(expected.display.clone()) {
+                        eprintln!("{}: stopped responding: {err}", expected.display);
+                        let detail = format!("stopped responding: {err}");
+                        record("unresponsive", Some(&expected.display), detail);
+                        let fields =
+                            json!({ "display": expected.display, "error": err.to_string() });
+                        alerts.emit("unresponsive", fields.clone());
+                        events.emit("error", fields);
+                    }
+                }
+            }
+        }
+
+        for failure in failures.try_iter() {
+            eprintln!("{}: unreachable: {}", failure.name, failure.error);
+            record("unreachable", Some(&failure.name), &*failure.error);
+            events.emit(
+                "error",
+                json!({ "display": failure.name, "error": failure.error }),
+            );
+        }
+
+        if let Err(err) = history.flush() {
+            eprintln!("history: {err}");
+        }
+
+        let checked = Instant::now();
+        while checked.elapsed() < Duration::from_secs(watchdog.interval) && !stop.is_cancelled() {
+            schedule.run(config, manager);
+            if let Some(adaptive) = &mut ambient {
+                let automation = &config.automation;
+                if let Err(err) = adaptive.run(&config.ambient, automation, manager, &mut history) {
+                    eprintln!("ambient: {err}");
+                    record("error", None, format!("adaptive brightness: {err}"));
+                }
+            }
+            #[cfg(all(any(target_os = "linux", windows), feature = "hotplug"))]
+            if hotplug
+                .as_ref()
+                .is_some_and(|hotplug| hotplug.try_iter().count() > 0)
+            {
+                record(
+                    "hotplug",
+                    None,
+                    "a display was plugged in or out, refreshing",
+                );
+                lost = true;
+                break;
+            }
+            match received.recv_timeout(TICK) {
+                Ok(job) => job(manager),
+                Err(RecvTimeoutError::Timeout) => {}
+                // Every listener is gone
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(TICK),
+            }
+        }
+        if lost && !stop.is_cancelled() {
+            // The displays there were stay until the next try
+            if let Err(err) = manager.refresh() {
+                eprintln!("refreshing failed: {err}");
+                record("error", None, format!("refreshing failed: {err}"));
+                events.emit("error", json!({ "error": err.to_string() }));
+                retry = true;
+                continue;
+            }
+            retry = false;
+            let now = ids(manager);
+            for id in connected.difference(&now) {
+                record("disconnected", Some(id), "gone after a refresh");
+                events.emit("disconnected", json!({ "display": id }));
+            }
+            for id in now.difference(&connected) {
+                record("connected", Some(id), "found by a refresh");
+                events.emit("connected", json!({ "display": id }));
+            }
+            connected = now;
+            apply_setup(&mut setups, config, manager);
+        }
+    }
+    Ok(())
+}
+
+/// Applies the preset of the setup rule the displays match, once they changed.
+#[cfg(feature = "daemon")]
+fn apply_setup(setups: &mut Tracker, config: &Config, manager: &DisplayManager) {
+    if let Some(rule) = setups.check(&config.setup, &manager.displays) {
+        if let Err(err) = crate::presets::apply_named(config, manager, &rule.preset, Origin::Setup)
+        {
+            eprintln!("setup: {err}");
+            record("error", None, format!("setup: {err}"));
+        }
+    }
+}
+
+/// Reads the brightness of the displays with keep-alive rules at their interval while the
+/// returned supervisor lives.
+#[cfg(feature = "daemon")]
+fn keep_alive(manager: &DisplayManager, rules: &[KeepAlive]) -> Option<Supervisor> {
+    if rules.is_empty() {
+        return None;
+    }
+    let displays = manager.shared_displays();
+    let rules: Vec<(String, Duration)> = rules
+        .iter()
+        .map(|rule| (rule.display.clone(), Duration::from_secs(rule.interval)))
+        .collect();
+
+    let mut supervisor = Supervisor::default();
+    supervisor.spawn("keep-alive", move |token| {
+        let mut last_read: HashMap<String, Instant> = HashMap::new();
+        while !token.is_cancelled() {
+            thread::sleep(TICK);
+            let displays = displays.lock().unwrap().clone();
+            for display in displays {
+                let Some((_, interval)) = rules.iter().find(|(query, _)| display.matches(query))
+                else {
+                    continue;
+                };
+                if display.is_powered_off() {
+                    continue;
+                }
+                if last_read
+                    .get(&display.id)
+                    .is_some_and(|read| read.elapsed() < *interval)
+                {
+                    continue;
+                }
+                last_read.insert(display.id.clone(), Instant::now());
+                // Only the read matters, a display that doesn't answer is the watchdog's to report
+                let _ = display.get_vcp(Control::Brightness as u8);
+            }
+        }
+    });
+    Some(supervisor)
+}
+
+#[cfg(feature = "daemon")]
+fn ids(manager: &DisplayManager) -> HashSet<String> {
+    manager
+        .displays
+        .iter()
+        .map(|display| display.id.clone())
+        .collect()
+}
+
+#[cfg(feature = "daemon")]
+/// Writes back every value that drifted from the expected one.
+fn correct(
+    display: &MyDisplay,
+    expected: &Expected,
+    effective: &Effective,
+    history: &mut Log,
+) -> Result<(), Box<dyn Error>> {
+    for (code, name, value) in expected.values(display, effective) {
+        // The input source leaves the high byte to noise on some displays
+        let current = display.get_vcp(code)?;
+        let current = Control::from_code(code).map_or(current, |control| control.mask(current));
+        if current != value {
+            display.set_vcp(code, value)?;
+            history.record(Origin::Watchdog, display, code, value);
+            println!(
+                "{}: corrected {name} {current} -> {value}",
+                expected.display
+            );
+        }
+    }
+    Ok(())
+}
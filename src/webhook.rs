@@ -0,0 +1,77 @@
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Posts a JSON body to an `http://` URL, or an `https://` one through curl.
+pub fn post(url: &str, body: &serde_json::Value) -> Result<(), Box<dyn Error>> {
+    if url.starts_with("https://") {
+        return post_tls(url, body);
+    }
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or("webhooks need an http:// or https:// URL")?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let has_port = authority
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok());
+    let address = if has_port {
+        authority.to_string()
+    } else {
+        format!("{authority}:80")
+    };
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| format!("{authority} didn't resolve"))?;
+
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let body = body.to_string();
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\nHost: {authority}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    match status.split_whitespace().nth(1).map(str::parse::<u16>) {
+        Some(Ok(200..=299)) => Ok(()),
+        _ => Err(format!("webhook answered {:?}", status.trim()).into()),
+    }
+}
+
+/// Asks curl, like the update check does, so no TLS stack has to be built in.
+fn post_tls(url: &str, body: &serde_json::Value) -> Result<(), Box<dyn Error>> {
+    let mut curl = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time"])
+        .arg(TIMEOUT.as_secs().to_string())
+        .args(["--header", "Content-Type: application/json"])
+        .args(["--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("running curl: {err}"))?;
+    if let Some(mut stdin) = curl.stdin.take() {
+        stdin.write_all(body.to_string().as_bytes())?;
+    }
+    let output = curl.wait_with_output()?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_string()
+            .into()),
+    }
+}
@@ -0,0 +1,110 @@
+//! Internal laptop panels on Windows through WMI, `WmiMonitorBrightness` and its methods, since
+//! they don't speak DDC/CI. Listed next to the DDC displays with only a brightness control.
+//!
+//! WMI is asked through PowerShell, which comes with every Windows, so no COM bindings are
+//! needed. Each read and write starts it anew and takes a few hundred milliseconds.
+
+use std::{
+    io,
+    process::{Command, Stdio},
+};
+
+use crate::backend::{brightness_only, Backend};
+use crate::Error;
+
+/// What the panel advertises, so loading keeps brightness and drops every other control
+pub(crate) const CAPABILITIES: &[u8] = b"(prot(backlight)type(lcd)vcp(10))";
+
+const NAMESPACE: &str = "root/WMI";
+
+pub(crate) struct Panel {
+    /// WMI instance name, e.g. `DISPLAY\BOE0868\4&1a2b3c4d&0&UID8388688_0`
+    pub instance: String,
+}
+
+impl Panel {
+    /// The first active panel WMI controls the brightness of, `None` without one, as on
+    /// desktops.
+    pub fn find() -> Option<Self> {
+        let listed = powershell(&format!(
+            "Get-CimInstance -Namespace {NAMESPACE} -ClassName WmiMonitorBrightness \
+             -ErrorAction Stop | Where-Object Active | ForEach-Object InstanceName"
+        ))
+        .ok()?;
+        let instance = listed
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())?;
+        Some(Self {
+            instance: instance.to_string(),
+        })
+    }
+
+    /// The instance of `class` this panel is, for a pipeline.
+    fn query(&self, class: &str) -> String {
+        format!(
+            "Get-CimInstance -Namespace {NAMESPACE} -ClassName {class} -ErrorAction Stop | \
+             Where-Object InstanceName -eq '{}'",
+            self.instance.replace('\'', "''")
+        )
+    }
+
+    /// The current brightness out of 100.
+    pub fn read(&self) -> io::Result<(u16, u16)> {
+        let query = self.query("WmiMonitorBrightness");
+        let answer = powershell(&format!("({query}).CurrentBrightness"))?;
+        let value = answer.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("WMI brightness is not a number: {answer:?}"),
+            )
+        })?;
+        Ok((value, 100))
+    }
+
+    pub fn write(&self, value: u16) -> io::Result<()> {
+        let query = self.query("WmiMonitorBrightnessMethods");
+        powershell(&format!(
+            "{query} | Invoke-CimMethod -MethodName WmiSetBrightness -ErrorAction Stop \
+             -Arguments @{{ Timeout = 0; Brightness = [byte]{} }} | Out-Null",
+            value.min(100)
+        ))?;
+        Ok(())
+    }
+}
+
+impl Backend for Panel {
+    fn read_vcp(&self, code: u8) -> Result<(u16, u16), Error> {
+        brightness_only(code, "a backlight").map_err(Error::Backlight)?;
+        self.read().map_err(Error::Backlight)
+    }
+
+    fn write_vcp(&self, code: u8, value: u16) -> Result<(), Error> {
+        brightness_only(code, "a backlight").map_err(Error::Backlight)?;
+        self.write(value).map_err(Error::Backlight)
+    }
+
+    fn capabilities_string(&self) -> Result<Vec<u8>, Error> {
+        Ok(CAPABILITIES.to_vec())
+    }
+}
+
+/// Runs a PowerShell command without a window of its own and returns what it printed.
+fn powershell(command: &str) -> io::Result<String> {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", command])
+        .stdin(Stdio::null())
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "WMI: {}",
+            error.lines().next().unwrap_or("PowerShell failed").trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
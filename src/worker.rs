@@ -0,0 +1,289 @@
+//! Writes queued changes to the displays in the background
+
+use crate::backend::Session;
+use crate::display::{Controller, Waker, WrappedDisplay};
+use crate::metrics::{Recorder, Written};
+use crate::supervisor::CancelToken;
+use std::collections::VecDeque;
+use std::mem::take;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often changes parked for an unreachable display are retried.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a display being adjusted keeps its handle locked after the last change.
+const HOT_PERIOD: Duration = Duration::from_millis(500);
+
+/// Time between the intermediate writes of a fade
+const FADE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Quiet time waited for before writing, so changes in quick succession become one write
+const DEBOUNCE: Duration = Duration::from_millis(40);
+
+/// Longest a write is put off while changes keep coming, so a held key still shows progress
+const MAX_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// The same for slow displays, where every write that isn't the last one costs seconds
+const SLOW_DEBOUNCE: Duration = Duration::from_millis(400);
+const SLOW_MAX_DEBOUNCE: Duration = Duration::from_millis(1500);
+
+pub(crate) struct Change {
+    pub display: WrappedDisplay,
+    pub controller: Controller,
+    /// Value shown before the change, where a fade starts
+    pub from: u16,
+    /// Time to fade from `from` over, `None` to write the value right away
+    pub fade: Option<Duration>,
+}
+
+impl Change {
+    /// Writes the change, retrying as configured and parking it if the display still doesn't
+    /// answer. Returns whether it got through.
+    fn apply(self, session: &mut dyn Session, parked: &Mutex<Vec<Change>>) -> bool {
+        let code = self.controller.kind as u8;
+        let value = self.controller.value;
+        let tuning = self.display.tuning;
+        if let Some(duration) = self.fade {
+            self.fade(session, duration);
+        }
+        let result = self.display.retry.run(|| {
+            session.set_vcp(code, value)?;
+            if tuning.verify {
+                session.get_vcp(code)
+            } else {
+                Ok(value)
+            }
+        });
+        self.display.note_reachable(result.as_ref().err());
+        let error = match result {
+            Ok(read) if read == value => None,
+            Ok(read) => Some(format!("wrote {value} but read back {read}")),
+            Err(err) => Some(err.to_string()),
+        };
+        if tuning.delay_ms > 0 {
+            thread::sleep(Duration::from_millis(tuning.delay_ms));
+        }
+        let written = error.is_none();
+        if written {
+            self.display.note_written(code, value);
+        }
+        self.display.note_write(error);
+        self.display.note_answer(code, written);
+        self.mark_pending(!written);
+        if !written {
+            park(&mut parked.lock().unwrap(), self);
+        }
+        written
+    }
+
+    /// Writes evenly spaced values between `from` and the target, leaving the last one to
+    /// `apply`. A failed write ends the fade, the target is still tried.
+    fn fade(&self, session: &mut dyn Session, duration: Duration) {
+        let (from, to) = (self.from as i32, self.controller.value as i32);
+        let steps = (duration.as_millis() / FADE_INTERVAL.as_millis()).max(1) as i32;
+        let steps = steps.min((to - from).abs()).max(1);
+        for step in 1..steps {
+            let value = from + (to - from) * step / steps;
+            if session
+                .set_vcp(self.controller.kind as u8, value as u16)
+                .is_err()
+            {
+                return;
+            }
+            thread::sleep(duration / steps as u32);
+        }
+    }
+
+    pub fn mark_pending(&self, pending: bool) {
+        if let Some(controller) = self.display.controls.get(&self.controller.kind) {
+            controller.write().unwrap().pending = pending;
+        }
+    }
+}
+
+/// Takes the parked changes out to be written again, leaving those of displays that are gone
+/// parked until a refresh finds them again.
+pub(crate) fn take_retried(parked: &Mutex<Vec<Change>>) -> Vec<Change> {
+    let mut parked = parked.lock().unwrap();
+    let (waiting, retried) = take(&mut *parked)
+        .into_iter()
+        .partition(|change| change.display.is_stale());
+    *parked = waiting;
+    retried
+}
+
+/// Parks a change, only keeping the latest value per display and control.
+fn park(parked: &mut Vec<Change>, change: Change) {
+    parked.retain(|other| {
+        !Arc::ptr_eq(&other.display, &change.display)
+            || other.controller.kind != change.controller.kind
+    });
+    change.mark_pending(true);
+    // By the time the display answers again, where it faded from is long gone
+    let mut change = change;
+    change.fade = None;
+    parked.push(change);
+}
+
+/// Writes queued changes in the background and retries parked ones
+pub(crate) struct Worker {
+    pub changes: Arc<Mutex<Vec<Change>>>,
+    pub parked: Arc<Mutex<Vec<Change>>>,
+    pub queued: Arc<AtomicUsize>,
+    pub recorder: Arc<Mutex<Recorder>>,
+    pub waker: Waker,
+    pub receiver: Receiver<()>,
+    pub last_retry: Instant,
+}
+
+impl Worker {
+    pub fn run(mut self, token: CancelToken) {
+        loop {
+            match self.receiver.recv_timeout(RETRY_INTERVAL) {
+                Ok(()) => self.debounce(),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            // Queued changes are counted until written, retried ones were counted when parked.
+            let mut pending: VecDeque<(Change, bool)> = VecDeque::new();
+            if self.last_retry.elapsed() >= RETRY_INTERVAL {
+                self.last_retry = Instant::now();
+                let retry = take_retried(&self.parked);
+                pending.extend(retry.into_iter().map(|change| (change, false)));
+            }
+
+            while let Some(display) = self.next_display(&pending) {
+                self.take_queued(&display, &mut pending);
+                self.write_display(&display, &mut pending, &token);
+            }
+            // Queued changes were written above, parked ones are given up on.
+            if token.is_cancelled() {
+                return;
+            }
+        }
+    }
+
+    /// Waits until nothing was queued for `DEBOUNCE`, at most `MAX_DEBOUNCE`. Changes to the
+    /// same control queued meanwhile are merged, only their last value gets written.
+    fn debounce(&self) {
+        self.wait_quiet(DEBOUNCE, MAX_DEBOUNCE);
+    }
+
+    fn wait_quiet(&self, quiet: Duration, max: Duration) {
+        let start = Instant::now();
+        while let Some(left) = max.checked_sub(start.elapsed()) {
+            if self.receiver.recv_timeout(quiet.min(left)).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Whether the display has a change waiting for it to answer again.
+    fn is_parked(&self, display: &WrappedDisplay) -> bool {
+        self.parked
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|other| Arc::ptr_eq(&other.display, display))
+    }
+
+    /// The display written next, that of the oldest retried or queued change.
+    fn next_display(&self, pending: &VecDeque<(Change, bool)>) -> Option<WrappedDisplay> {
+        if let Some((change, _)) = pending.front() {
+            return Some(change.display.clone());
+        }
+        let changes = self.changes.lock().unwrap();
+        changes.first().map(|change| change.display.clone())
+    }
+
+    /// Takes the queued changes of the display about to be written. Those of other displays
+    /// stay queued, where they can still be listed, merged and discarded.
+    fn take_queued(&self, display: &WrappedDisplay, pending: &mut VecDeque<(Change, bool)>) {
+        let mut changes = self.changes.lock().unwrap();
+        let (taken, rest) = take(&mut *changes)
+            .into_iter()
+            .partition::<Vec<_>, _>(|change| Arc::ptr_eq(&change.display, display));
+        *changes = rest;
+        pending.extend(taken.into_iter().map(|change| (change, true)));
+    }
+
+    /// Writes the pending changes of a display while holding its handle, then keeps holding it
+    /// for changes that follow shortly, like while a key is held.
+    fn write_display(
+        &self,
+        display: &WrappedDisplay,
+        pending: &mut VecDeque<(Change, bool)>,
+        token: &CancelToken,
+    ) {
+        let mut session = None;
+        let slow = display.tuning.slow || self.recorder.lock().unwrap().is_slow(&display.id);
+        loop {
+            // Values only the last of which counts are cheap to wait for, expensive to write
+            if slow {
+                self.wait_quiet(SLOW_DEBOUNCE, SLOW_MAX_DEBOUNCE);
+                self.take_queued(display, pending);
+            }
+            let (changes, rest): (VecDeque<_>, _) = take(pending)
+                .into_iter()
+                .partition(|(change, _)| Arc::ptr_eq(&change.display, display));
+            *pending = rest;
+            let superseded: Vec<bool> = (0..changes.len())
+                .map(|i| {
+                    changes
+                        .iter()
+                        .skip(i + 1)
+                        .any(|(later, _)| later.controller.kind == changes[i].0.controller.kind)
+                })
+                .collect();
+            for ((change, counted), superseded) in changes.into_iter().zip(superseded) {
+                // Don't block on a display that just failed, wait for the next retry instead.
+                if self.is_parked(display) {
+                    park(&mut self.parked.lock().unwrap(), change);
+                } else if !superseded {
+                    let session = session.get_or_insert_with(|| display.session());
+                    let start = Instant::now();
+                    let (control, from, to) =
+                        (change.controller.kind, change.from, change.controller.value);
+                    let error = match change.apply(&mut **session, &self.parked) {
+                        true => None,
+                        false => Some(display.write_error().unwrap_or_default()),
+                    };
+                    let mut recorder = self.recorder.lock().unwrap();
+                    recorder.record(&display.id, start.elapsed());
+                    recorder.write(Written {
+                        id: display.id.clone(),
+                        name: display.label(),
+                        control,
+                        from,
+                        to,
+                        error,
+                    });
+                }
+                if counted {
+                    self.queued.fetch_sub(1, Ordering::SeqCst);
+                    self.recorder.lock().unwrap().dequeue(&display.id);
+                }
+            }
+            self.waker.wake();
+            let others_waiting = !pending.is_empty() || !self.changes.lock().unwrap().is_empty();
+            if session.is_none()
+                || self.is_parked(display)
+                || token.is_cancelled()
+                || others_waiting
+            {
+                return;
+            }
+            match self.receiver.recv_timeout(HOT_PERIOD) {
+                Ok(()) => {
+                    self.debounce();
+                    self.take_queued(display, pending);
+                }
+                Err(_) => return,
+            }
+        }
+    }
+}
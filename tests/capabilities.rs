@@ -0,0 +1,198 @@
+use std::{fs, io, path::PathBuf};
+
+use ddc_bright::capabilities::{Capabilities, FeatureKind};
+
+fn corpus(name: &str) -> Vec<u8> {
+    fs::read(
+        PathBuf::from("tests/corpus")
+            .join(name)
+            .with_extension("txt"),
+    )
+    .unwrap()
+}
+
+fn parse(name: &str) -> Capabilities {
+    Capabilities::parse(&corpus(name), |_| Some(100)).unwrap()
+}
+
+fn discrete(capabilities: &Capabilities, code: u8) -> Vec<u8> {
+    match &capabilities.feature(code).unwrap().kind {
+        FeatureKind::Discrete { values } => values.iter().map(|(value, _)| *value).collect(),
+        kind => panic!("{code:02X} is {kind:?}"),
+    }
+}
+
+#[test]
+fn corpus_finds_brightness() {
+    for entry in fs::read_dir("tests/corpus").unwrap() {
+        let path = entry.unwrap().path();
+        if path.file_stem().unwrap() == "dell_p2419h_truncated" {
+            continue;
+        }
+        let capabilities = Capabilities::parse(&fs::read(&path).unwrap(), |_| Some(100))
+            .unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+        let brightness = capabilities
+            .feature(0x10)
+            .unwrap_or_else(|| panic!("{}: no brightness", path.display()));
+        assert!(brightness.readable && brightness.writable);
+        assert!(matches!(
+            brightness.kind,
+            FeatureKind::Continuous { maximum: Some(100) }
+        ));
+    }
+}
+
+#[test]
+fn dell() {
+    let capabilities = parse("dell_p2317h");
+    assert_eq!(capabilities.mccs_version, Some((2, 1)));
+    assert_eq!(capabilities.model.as_deref(), Some("p2317h"));
+    assert_eq!(capabilities.display_type.as_deref(), Some("lcd"));
+    assert_eq!(discrete(&capabilities, 0x60), [0x01, 0x0F, 0x11]);
+    assert!(capabilities.feature(0x62).is_none());
+}
+
+#[test]
+fn write_only_features() {
+    let capabilities = parse("dell_p2317h");
+    let reset = capabilities.feature(0x04).unwrap();
+    assert!(!reset.readable && reset.writable);
+}
+
+#[test]
+fn codes_without_spaces() {
+    let capabilities = parse("lg_25um65_no_spaces");
+    assert_eq!(capabilities.features.len(), 40);
+    assert_eq!(discrete(&capabilities, 0xED), [0x00, 0x10, 0x20, 0x40]);
+}
+
+#[test]
+fn model_without_parentheses() {
+    let capabilities = parse("lg_34uc88_bare_model");
+    assert_eq!(capabilities.model, None);
+    assert!(capabilities.feature(0x62).is_some());
+}
+
+#[test]
+fn nested_value_lists() {
+    let capabilities = parse("hp_x24c_nested");
+    assert_eq!(capabilities.model.as_deref(), Some("HP X24c"));
+    assert_eq!(discrete(&capabilities, 0xDC), [0x00, 0x01, 0x02, 0x03]);
+    assert_eq!(
+        discrete(&capabilities, 0xE1),
+        [0x02, 0x03, 0x04, 0x0F, 0x10, 0x27]
+    );
+    assert!(capabilities.feature(0xFF).is_some());
+}
+
+#[test]
+fn doubled_outer_parentheses() {
+    let capabilities = parse("asus_vg278h_double_parens");
+    assert_eq!(capabilities.mccs_version, Some((2, 1)));
+    assert!(capabilities.feature(0x62).is_some());
+}
+
+#[test]
+fn missing_outer_parentheses() {
+    let capabilities = parse("viewsonic_vx4380_no_parens");
+    assert_eq!(capabilities.model.as_deref(), Some("VX4380"));
+    assert_eq!(discrete(&capabilities, 0x60), [0x0F, 0x10, 0x11, 0x12]);
+}
+
+#[test]
+fn vcp_before_other_tags() {
+    let capabilities = parse("acer_sa240y_vcp_first");
+    assert_eq!(capabilities.model.as_deref(), Some("SA240Y bid"));
+    assert!(capabilities.feature(0xDF).is_some());
+}
+
+#[test]
+fn trailing_nul_bytes() {
+    let plain = parse("benq_xl2411t");
+    let padded = parse("benq_xl2411t_trailing_nul");
+    let codes = |capabilities: &Capabilities| {
+        capabilities
+            .features
+            .iter()
+            .map(|feature| feature.code)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(codes(&plain), codes(&padded));
+    assert_eq!(padded.raw, plain.raw);
+}
+
+#[test]
+fn packed_version() {
+    let capabilities = parse("sony_sdm_s205_packed");
+    assert_eq!(capabilities.mccs_version, Some((2, 1)));
+}
+
+#[test]
+fn missing_version() {
+    let capabilities = parse("no_mccs_version");
+    assert_eq!(capabilities.mccs_version, None);
+    assert_eq!(capabilities.features.len(), 3);
+}
+
+#[test]
+fn vcpname() {
+    let capabilities = parse("mccs_spec_vcpname");
+    let brightness = capabilities.feature(0x10).unwrap();
+    assert_eq!(brightness.name.as_deref(), Some("Brightness"));
+}
+
+#[test]
+fn truncated() {
+    let err = Capabilities::parse(&corpus("dell_p2419h_truncated"), |_| Some(100)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "malformed capabilities string");
+}
+
+#[test]
+fn maximum_only_asked_for_readable_continuous_features() {
+    let mut asked = vec![];
+    let capabilities = Capabilities::parse(&corpus("dell_p2317h"), |code| {
+        asked.push(code);
+        (code == 0x10).then_some(400)
+    })
+    .unwrap();
+    assert!(asked.contains(&0x10) && asked.contains(&0x12));
+    assert!(!asked.contains(&0x60) && !asked.contains(&0x04));
+    assert!(matches!(
+        capabilities.feature(0x10).unwrap().kind,
+        FeatureKind::Continuous { maximum: Some(400) }
+    ));
+    assert!(matches!(
+        capabilities.feature(0x12).unwrap().kind,
+        FeatureKind::Continuous { maximum: None }
+    ));
+}
+
+/// A cheap stand-in for the fuzz target so mangled strings are covered by `cargo test` too.
+#[test]
+fn mangled_strings_dont_panic() {
+    let mut state: u64 = 0x2545F4914F6CDD1D;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as usize
+    };
+    let samples: Vec<Vec<u8>> = fs::read_dir("tests/corpus")
+        .unwrap()
+        .map(|entry| fs::read(entry.unwrap().path()).unwrap())
+        .collect();
+    for i in 0..5_000 {
+        let mut raw = samples[i % samples.len()].clone();
+        for _ in 0..random() % 4 + 1 {
+            let index = random() % (raw.len() + 1);
+            match random() % 4 {
+                0 if index < raw.len() => raw[index] = b"() 0aF\0\xff"[random() % 8],
+                1 if index < raw.len() => drop(raw.remove(index)),
+                2 => raw.insert(index, b"() 0aF"[random() % 6]),
+                _ => raw.truncate(index),
+            }
+        }
+        let _ = Capabilities::parse(&raw, |_| Some(100));
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Runs the binary against two fake displays, with a config directory of its own.
+fn run(name: &str, args: &[&str]) -> String {
+    run_with(name, "", args)
+}
+
+/// Runs the binary like [`run`] with `config` as its config file.
+fn run_with(name: &str, config: &str, args: &[&str]) -> String {
+    let dir = config_dir(name, config);
+    let output = run_in(&dir, args);
+    let _ = fs::remove_dir_all(&dir);
+    output.unwrap()
+}
+
+/// A fresh config directory with `config` as the config file.
+fn config_dir(name: &str, config: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ddc_bright-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("ddc_bright")).unwrap();
+    fs::write(dir.join("ddc_bright/config.toml"), config).unwrap();
+    dir
+}
+
+/// Runs the binary against two fake displays with the config directory, what it printed or
+/// what it failed with.
+fn run_in(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(PathBuf::from(env!("CARGO_BIN_EXE_ddc_bright")))
+        .args(args)
+        .env("DDC_BRIGHT_FAKE", "2")
+        .env("XDG_CONFIG_HOME", dir)
+        .env("XDG_RUNTIME_DIR", dir)
+        .env("USER", "tester")
+        .output()
+        .unwrap();
+    match output.status.success() {
+        true => Ok(String::from_utf8(output.stdout).unwrap()),
+        false => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+    }
+}
+
+#[test]
+fn list_shows_the_fake_displays() {
+    assert_eq!(
+        run("list", &["list"]),
+        "1: FAKE 1 (1) 1920x1080@60 on FAKE-1\n2: FAKE 2 (2) 1920x1080@60 on FAKE-2\n"
+    );
+}
+
+#[test]
+fn get_reads_the_fake_table() {
+    let output = run("get", &["get", "--display", "2", "brightness"]);
+    assert_eq!(output, "50\n");
+}
+
+#[test]
+fn statusline_prints_the_formatted_values() {
+    assert_eq!(
+        run("statusline", &["statusline"]),
+        "FAKE 1 50%  FAKE 2 50%\n"
+    );
+    let output = run(
+        "statusline-scroll",
+        &[
+            "statusline",
+            "--display",
+            "2",
+            "--scroll",
+            "down",
+            "--format",
+            "{number}:{brightness}",
+        ],
+    );
+    assert_eq!(output, "2:45\n");
+}
+
+#[test]
+fn export_writes_settings_import_restores() {
+    let exported = run("export", &["export", "--display", "1"]);
+    assert!(exported.contains("serial = \"FAKE1\""));
+    assert!(exported.contains("brightness = 50\n"));
+    assert!(!exported.contains("input_source"));
+
+    let file = env::temp_dir().join(format!("ddc_bright-import-{}.toml", std::process::id()));
+    fs::write(&file, exported).unwrap();
+    let imported = run("import", &["import", file.to_str().unwrap()]);
+    let other = run(
+        "import-other",
+        &["import", file.to_str().unwrap(), "--display", "2"],
+    );
+    let _ = fs::remove_file(&file);
+    assert_eq!(imported, "FAKE 1: 8 of 8 values restored\n");
+    assert_eq!(other, "FAKE 2: 8 of 8 values restored\n");
+}
+
+#[test]
+fn edid_decodes_the_blob_of_the_backend() {
+    let decoded = run("edid", &["edid", "--display", "2", "--decoded"]);
+    assert!(decoded.contains("name: FAKE 2\n"));
+    assert!(decoded.contains("manufacturer: FAK, product 0x0001, serial FAKE2\n"));
+    assert!(decoded.contains("white 0.312, 0.329"));
+    assert!(decoded.contains("preferred: 1920x1080 @ 60.00 Hz, 148.50 MHz\n"));
+    assert!(!decoded.contains("00 ff ff ff"));
+    let dumped = run("edid-hex", &["edid", "--display", "2"]);
+    assert!(dumped.contains("  00 ff ff ff ff ff ff 00 18 2b 01 00 01 00 00 00\n"));
+}
+
+#[test]
+fn schedule_simulate_prints_what_rules_would_change() {
+    let config = r#"
+version = 3
+
+[[schedule]]
+time = "08:00"
+preset = "reading"
+
+[[schedule]]
+time = "21:00"
+display = "1"
+set = { brightness = "30%" }
+
+[[schedule]]
+time = "12:00"
+set = { contrast = 0 }
+"#;
+    let args = ["schedule", "simulate", "--from", "18:00", "--to", "09:00"];
+    let output = run_with("simulate", config, &args);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines[0],
+        "From 18:00 to 09:00, starting at the current values:"
+    );
+    assert!(lines[1].ends_with(" 21:00: brightness 30%"));
+    assert_eq!(lines[2], "  FAKE 1: brightness 50%→30%");
+    assert!(lines[3].ends_with(" 08:00: reading"));
+    assert_eq!(lines[4], "  FAKE 1: brightness 30%→40%");
+    assert_eq!(lines[5], "  FAKE 2: brightness 50%→40%");
+    assert_eq!(lines.len(), 6);
+
+    let args = ["schedule", "simulate", "--from", "19:00", "--to", "20:00"];
+    let output = run_with("simulate-none", config, &args);
+    assert_eq!(output, "No rules trigger from 19:00 to 20:00\n");
+}
+
+#[test]
+fn audit_lists_writes_and_notices_changed_lines() {
+    let dir = config_dir("audit", "version = 3\n\n[audit]\nenabled = true\n");
+    let key = run_in(&dir, &["audit", "--init"]).unwrap();
+    let key = key.trim();
+    run_in(&dir, &["set", "--display", "2", "--brightness", "30"]).unwrap();
+    run_in(&dir, &["vcp", "--display", "1", "12", "40"]).unwrap();
+    let audited = run_in(&dir, &["audit", "--since", "yesterday"]).unwrap();
+    let lines: Vec<&str> = audited.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("  tester      cli       FAKE 2  brightness 50→30"));
+    assert!(lines[1].ends_with("FAKE 1  contrast 50→40"));
+    let only = run_in(&dir, &["audit", "--display", "1"]).unwrap();
+    assert_eq!(only.lines().count(), 1);
+    let verified = run_in(&dir, &["audit", "--verify", "--key", key]).unwrap();
+    assert_eq!(verified, "2 audited writes, the chain is intact\n");
+    // The key on the machine has moved on, it can't sign the lines again
+    let unkept = run_in(&dir, &["audit", "--verify"]).unwrap_err();
+    assert!(unkept.contains("no first key"));
+
+    let log = dir.join("ddc_bright/audit.log");
+    let text = fs::read_to_string(&log).unwrap();
+    fs::write(&log, text.lines().next().unwrap().to_string() + "\n").unwrap();
+    let cut = run_in(&dir, &["audit", "--verify", "--key", key]).unwrap_err();
+    assert!(cut.contains("the log ends after line 1 but audit.key was moved on to 2"));
+    fs::write(&log, text.replacen("\t30\t", "\t100\t", 1)).unwrap();
+    let broken = run_in(&dir, &["audit", "--verify", "--key", key]).unwrap_err();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(broken.contains("audit.log line 1: the chain breaks here"));
+}
+
+#[test]
+fn set_steps_from_the_current_value_within_the_range() {
+    let dir = config_dir("steps", "version = 3\n\n[audit]\nenabled = true\n");
+    let pairs = [
+        "set",
+        "--display",
+        "2",
+        "brightness",
+        "+10",
+        "contrast",
+        "-5%",
+    ];
+    run_in(&dir, &pairs).unwrap();
+    run_in(&dir, &["set", "--display", "1", "--brightness", "+80"]).unwrap();
+    run_in(&dir, &["set", "--display", "1", "--contrast", "180"]).unwrap();
+    let stepped = run_in(&dir, &["set", "--display", "1", "input_source", "+1"]).unwrap_err();
+    let audited = run_in(&dir, &["audit"]).unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    let lines: Vec<&str> = audited.lines().collect();
+    assert!(lines[0].ends_with("FAKE 2  brightness 50→60"));
+    assert!(lines[1].ends_with("FAKE 2  contrast 50→45"));
+    assert!(lines[2].ends_with("FAKE 1  brightness 50→100"));
+    assert!(lines[3].ends_with("FAKE 1  contrast 50→100"));
+    assert!(stepped.contains("input_source takes no steps like +1, only values"));
+}
+
+#[test]
+fn doctor_passes_once_displays_answer() {
+    assert_eq!(
+        run("doctor", &["doctor"]),
+        "DDC/CI: ok, 2 displays answer\n"
+    );
+}
+
+#[test]
+fn theme_colours_are_names_or_hex() {
+    let config = "version = 3\n\n[theme]\nbase = \"solarized\"\naccent = \"#d33682\"\n";
+    let dir = config_dir("theme", config);
+    let listed = run_in(&dir, &["list"]);
+    fs::write(
+        dir.join("ddc_bright/config.toml"),
+        config.replace("#d33682", "pink"),
+    )
+    .unwrap();
+    let failed = run_in(&dir, &["list"]).unwrap_err();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(listed.is_ok());
+    assert!(failed.contains("unknown colour pink"));
+}
+
+#[test]
+fn buttons_are_checked_when_loading() {
+    let config = "version = 3\n\n[[buttons]]\nlabel = \"Dim\"\ntoggle = { brightness = [\"20%\", \"dim\"] }\n";
+    let dir = config_dir("buttons", config);
+    let unknown = run_in(&dir, &["list"]).unwrap_err();
+    let config = "version = 3\n\n[[buttons]]\nlabel = \"Input\"\ntoggle = { input_source = [] }\n";
+    fs::write(dir.join("ddc_bright/config.toml"), config).unwrap();
+    let empty = run_in(&dir, &["list"]).unwrap_err();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(unknown.contains("button Dim: "));
+    assert!(empty.contains("button Input: no values to toggle input_source through"));
+}
+
+#[test]
+fn without_a_terminal_the_tui_lists_instead() {
+    assert_eq!(
+        run("no-tty", &[]),
+        "1: FAKE 1 (1) 1920x1080@60 on FAKE-1\n2: FAKE 2 (2) 1920x1080@60 on FAKE-2\n"
+    );
+}
+
+#[test]
+fn presets_report_each_display_as_it_is_written() {
+    let output = run("preset", &["--preset", "reading"]);
+    assert!(output.contains("FAKE 1: 2 of 2 values written\n"));
+    assert!(output.contains("FAKE 2: 2 of 2 values written\n"));
+    assert!(output.ends_with("Applied reading to 2 displays\n"));
+}
+
+#[test]
+fn tui_subcommand_needs_a_terminal() {
+    let dir = config_dir("tui-terminal", "version = 3\n");
+    let err = run_in(&dir, &["tui"]).unwrap_err();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(err.contains("the TUI needs a terminal"), "{err}");
+}
+
+#[test]
+fn cli_runs_the_lines_against_displays_loaded_once() {
+    let dir = config_dir("shell", "");
+    let mut child = Command::new(PathBuf::from(env!("CARGO_BIN_EXE_ddc_bright")))
+        .arg("cli")
+        .env("DDC_BRIGHT_FAKE", "2")
+        .env("XDG_CONFIG_HOME", &dir)
+        .env("XDG_RUNTIME_DIR", &dir)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let script = "# fake displays start at 50\nset --display \"FAKE 2\" --brightness 30\n\
+        get --display 2 brightness\nbogus\n";
+    std::io::Write::write_all(child.stdin.as_mut().unwrap(), script.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Loading monitors..\n30\n"
+    );
+    let errors = String::from_utf8_lossy(&output.stderr);
+    assert!(errors.contains("bogus: error: unrecognized subcommand 'bogus'"));
+    assert!(errors.contains("1 of 3 commands failed"));
+}
@@ -0,0 +1,589 @@
+use std::time::Duration;
+
+use ddc_bright::{
+    bulk,
+    display::{Control, DisplayManager, WrappedController, WrappedDisplay},
+    fake::{FakeDisplay, FakeState},
+    health,
+    lock::Lock,
+    mapping::Mapping,
+    policy::{ErrorPolicy, ErrorRule},
+    units::Level,
+    values::ValueNames,
+};
+
+const BRIGHTNESS: u8 = Control::Brightness as u8;
+
+/// A manager that found the fake displays, with their shared state in the same order.
+fn manager(fakes: Vec<FakeDisplay>) -> (DisplayManager, Vec<FakeState>) {
+    let states = fakes.iter().map(FakeDisplay::state).collect();
+    let mut manager = DisplayManager::new();
+    manager.fakes = fakes;
+    manager.refresh().unwrap();
+    (manager, states)
+}
+
+fn control(display: &WrappedDisplay, control: Control) -> WrappedController {
+    display.controls.get(&control).unwrap().clone()
+}
+
+#[test]
+fn refresh_reads_the_table() {
+    let (manager, _) = manager(vec![
+        FakeDisplay::new("1", "FAKE 1").with_vcp(BRIGHTNESS, 70, 255),
+        FakeDisplay::new("2", "FAKE 2"),
+    ]);
+    assert_eq!(manager.displays.len(), 2);
+    let display = &manager.displays[0];
+    assert_eq!(display.backend, "fake");
+    assert_eq!(display.serial.as_deref(), Some("FAKE1"));
+    let brightness = *control(display, Control::Brightness).read().unwrap();
+    assert_eq!((brightness.value, brightness.maximum), (70, 255));
+    assert_eq!(
+        display
+            .allowed_values(Control::InputSource)
+            .iter()
+            .map(|(value, _)| *value)
+            .collect::<Vec<_>>(),
+        [0x0F, 0x11, 0x12]
+    );
+}
+
+#[test]
+fn refresh_drops_codes_that_dont_answer() {
+    let (manager, _) = manager(vec![FakeDisplay::empty("1", "FAKE").with_vcp(0x62, 10, 100)]);
+    let display = &manager.displays[0];
+    assert!(display.controls.contains_key(&Control::Volume));
+    assert!(!display.controls.contains_key(&Control::Sharpness));
+    assert!(!display.controls.contains_key(&Control::Brightness));
+}
+
+#[test]
+fn refresh_keeps_core_controls_of_a_display_that_doesnt_answer() {
+    let (manager, _) = manager(vec![FakeDisplay::empty("1", "FAKE")]);
+    // It may only be asleep
+    assert!(manager.displays[0]
+        .controls
+        .contains_key(&Control::Brightness));
+}
+
+#[test]
+fn queued_changes_are_written() {
+    let (manager, states) = manager(vec![FakeDisplay::new("1", "FAKE")]);
+    let display = manager.displays[0].clone();
+    manager.queue_change(display.clone(), control(&display, Control::Brightness), 5);
+    manager.flush();
+    assert_eq!(states[0].value(BRIGHTNESS), Some(55));
+    assert_eq!(manager.pending(), 0);
+
+    let (written, failed) = manager.last_written();
+    let written = written.unwrap();
+    assert_eq!(
+        (written.control, written.from, written.to),
+        (Control::Brightness, 50, 55)
+    );
+    assert!(failed.is_none());
+}
+
+#[test]
+fn the_waker_hears_of_writes_and_polled_changes() {
+    let (mut manager, states) = manager(vec![FakeDisplay::new("1", "FAKE")]);
+    let (sender, woken) = std::sync::mpsc::channel();
+    manager.set_waker(move || {
+        let _ = sender.send(());
+    });
+    let display = manager.displays[0].clone();
+    manager.queue_change(display.clone(), control(&display, Control::Brightness), 5);
+    assert!(woken.recv_timeout(Duration::from_secs(5)).is_ok());
+    manager.flush();
+    while woken.try_recv().is_ok() {}
+
+    manager.start_polling(Duration::ZERO, Duration::ZERO);
+    states[0].set_value(BRIGHTNESS, 20);
+    assert!(woken.recv_timeout(Duration::from_secs(5)).is_ok());
+    assert_eq!(
+        control(&display, Control::Brightness).read().unwrap().value,
+        20
+    );
+}
+
+#[test]
+fn polling_notices_changes_made_elsewhere_with_the_old_value() {
+    let (mut manager, states) = manager(vec![FakeDisplay::new("1", "FAKE")]);
+    let (sender, woken) = std::sync::mpsc::channel();
+    manager.set_waker(move || {
+        let _ = sender.send(());
+    });
+    manager.start_polling(Duration::ZERO, Duration::ZERO);
+    states[0].set_value(BRIGHTNESS, 20);
+    assert!(woken.recv_timeout(Duration::from_secs(5)).is_ok());
+    let noticed = manager.take_noticed();
+    assert_eq!(noticed.len(), 1);
+    assert_eq!(noticed[0].control, Control::Brightness);
+    assert_eq!((noticed[0].old, noticed[0].value), (50, 20));
+    assert!(manager.take_noticed().is_empty());
+}
+
+#[test]
+fn changes_are_clamped_to_the_maximum() {
+    let (manager, states) = manager(vec![
+        FakeDisplay::new("1", "FAKE").with_vcp(BRIGHTNESS, 95, 100)
+    ]);
+    let display = manager.displays[0].clone();
+    let brightness = control(&display, Control::Brightness);
+    manager.queue_change(display.clone(), brightness.clone(), 10);
+    manager.flush();
+    assert_eq!(states[0].value(BRIGHTNESS), Some(100));
+
+    manager.queue_set(display.clone(), brightness.clone(), 400);
+    manager.flush();
+    assert_eq!(states[0].value(BRIGHTNESS), Some(100));
+
+    manager.queue_change(display, brightness, -150);
+    manager.flush();
+    assert_eq!(states[0].value(BRIGHTNESS), Some(0));
+}
+
+#[test]
+fn changes_in_quick_succession_are_merged() {
+    let (manager, states) = manager(vec![FakeDisplay::new("1", "FAKE")]);
+    let display = manager.displays[0].clone();
+    let brightness = control(&display, Control::Brightness);
+    for _ in 0..10 {
+        manager.queue_change(display.clone(), brightness.clone(), 1);
+    }
+    manager.flush();
+    assert_eq!(states[0].value(BRIGHTNESS), Some(60));
+    assert!(states[0].writes().len() < 10);
+}
+
+#[test]
+fn displays_that_dont_answer_keep_their_changes() {
+    let (manager, states) = manager(vec![
+        FakeDisplay::new("1", "FAKE"),
+        FakeDisplay::new("2", "FAKE"),
+    ]);
+    let failures = manager.watch_failures();
+    states[0].set_unreachable(true);
+    for display in &manager.displays {
+        manager.queue_set(display.clone(), control(display, Control::Brightness), 80);
+    }
+    manager.flush();
+
+    // The other display doesn't wait for the one that failed
+    assert_eq!(states[1].value(BRIGHTNESS), Some(80));
+    assert_eq!(states[0].value(BRIGHTNESS), Some(50));
+    assert_eq!(manager.pending(), 1);
+    assert!(manager.displays[0].write_error().is_some());
+    let failure = failures.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(failure.id, "1");
+    assert!(manager.last_written().1.is_some());
+
+    manager.discard();
+    assert_eq!(manager.pending(), 0);
+}
+
+#[test]
+fn parked_changes_are_listed_retried_and_discarded() {
+    let (manager, states) = manager(vec![FakeDisplay::new("1", "FAKE")]);
+    let display = &manager.displays[0];
+    states[0].set_unreachable(true);
+    manager.queue_set(display.clone(), control(display, Control::Brightness), 80);
+    manager.queue_set(display.clone(), control(display, Control::Contrast), 70);
+    manager.flush();
+
+    let queued = manager.queued();
+    assert_eq!(queued.len(), 2);
+    assert!(queued.iter().all(|change| change.parked));
+    assert!(manager.discard_change("1", Control::Contrast));
+    assert_eq!(
+        control(display, Control::Contrast).read().unwrap().value,
+        50
+    );
+
+    states[0].set_unreachable(false);
+    manager.retry_parked();
+    manager.flush();
+    assert_eq!(states[0].value(BRIGHTNESS), Some(80));
+    assert_eq!(states[0].value(Control::Contrast as u8), Some(50));
+    assert!(manager.queued().is_empty());
+}
+
+#[test]
+fn parked_changes_wait_for_their_display_to_come_back() {
+    let first = FakeDisplay::new("1", "FAKE");
+    let (mut manager, states) = manager(vec![first.clone()]);
+    let display = manager.displays[0].clone();
+    states[0].set_unreachable(true);
+    manager.queue_set(display.clone(), control(&display, Control::Brightness), 80);
+    manager.flush();
+    assert_eq!(manager.pending(), 1);
+
+    // Unplugged, the change waits without being retried against the old display
+    manager.fakes = vec![FakeDisplay::new("2", "OTHER")];
+    manager.refresh().unwrap();
+    manager.retry_parked();
+    manager.flush();
+    assert_eq!(manager.pending(), 0);
+    assert!(manager.queued().iter().all(|change| change.parked));
+    assert_eq!(manager.queued().len(), 1);
+
+    states[0].set_unreachable(false);
+    manager.fakes = vec![first];
+    manager.refresh().unwrap();
+    manager.flush();
+    assert_eq!(states[0].value(BRIGHTNESS), Some(80));
+    assert!(manager.queued().is_empty());
+}
+
+#[test]
+fn mapped_brightness_shows_and_steps_along_the_curve() {
+    let mut manager = DisplayManager::new();
+    manager.fakes = vec![FakeDisplay::new("1", "FAKE")];
+    manager.mappings = vec![Mapping {
+        points: vec![[50.0, 20.0]],
+        ..Mapping::default()
+    }];
+    manager.refresh().unwrap();
+    let display = &manager.displays[0];
+    let brightness = control(display, Control::Brightness);
+
+    // 50 written is between 20 at 50% and 100 at 100%
+    assert_eq!(display.percent(&brightness.read().unwrap()), 69);
+    let half = display.resolve(Control::Brightness, Level::Percent(50.0), 100);
+    assert_eq!(half, 20);
+    manager.queue_change(display.clone(), brightness.clone(), 10);
+    assert_eq!(brightness.read().unwrap().value, 66);
+    assert_eq!(display.percent(&brightness.read().unwrap()), 79);
+}
+
+#[test]
+fn values_changed_on_the_display_are_read_back() {
+    let (manager, states) = manager(vec![FakeDisplay::new("1", "FAKE")]);
+    states[0].set_value(BRIGHTNESS, 20);
+    assert_eq!(manager.displays[0].get(Control::Brightness).unwrap(), 20);
+    assert!(states[0].writes().is_empty());
+}
+
+#[test]
+fn watching_mirrors_only_the_watched_display() {
+    let (mut manager, states) = manager(vec![
+        FakeDisplay::new("1", "FAKE 1"),
+        FakeDisplay::new("2", "FAKE 2"),
+    ]);
+    manager.watch(Some(&manager.displays[1]));
+    manager.start_watching(Duration::ZERO, Duration::ZERO);
+    states[0].set_value(BRIGHTNESS, 20);
+    states[1].set_value(BRIGHTNESS, 30);
+    let watched = control(&manager.displays[1], Control::Brightness);
+    for _ in 0..50 {
+        if watched.read().unwrap().value == 30 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(watched.read().unwrap().value, 30);
+    let other = control(&manager.displays[0], Control::Brightness);
+    assert_eq!(other.read().unwrap().value, 50);
+}
+
+#[test]
+fn settings_are_read_and_written_in_bulk() {
+    let (manager, states) = manager(vec![FakeDisplay::new("1", "FAKE")]);
+    let display = &manager.displays[0];
+    // Input and power are left out, restoring them would switch the display away or off
+    assert_eq!(
+        display.settings(),
+        [0x10, 0x12, 0x14, 0x16, 0x18, 0x1A, 0x62, 0x87]
+    );
+    let read: Vec<(u8, u16)> = display
+        .read_settings()
+        .into_iter()
+        .map(|(code, value)| (code, value.unwrap()))
+        .collect();
+    assert_eq!(read[0], (0x10, 50));
+
+    states[0].set_value(BRIGHTNESS, 20);
+    assert!(display
+        .write_settings(&[(BRIGHTNESS, 70), (0x87, 8)])
+        .is_empty());
+    assert_eq!(states[0].value(BRIGHTNESS), Some(70));
+    assert_eq!(states[0].value(0x87), Some(8));
+    assert_eq!(
+        control(display, Control::Brightness).read().unwrap().value,
+        70
+    );
+}
+
+#[test]
+fn error_policies_apply_by_display() {
+    let mut manager = DisplayManager::new();
+    manager.fakes = vec![
+        FakeDisplay::new("1", "FAKE 1"),
+        FakeDisplay::new("2", "FAKE 2"),
+    ];
+    manager.errors = vec![
+        ErrorRule {
+            display: None,
+            policy: ErrorPolicy::Lenient,
+        },
+        ErrorRule {
+            display: Some("FAKE 2".to_string()),
+            policy: ErrorPolicy::Strict,
+        },
+    ];
+    manager.refresh().unwrap();
+    assert!(manager.displays[0].is_lenient());
+    assert!(!manager.displays[1].is_lenient());
+}
+
+#[test]
+fn reread_reads_a_few_displays_at_a_time() {
+    let fakes = (1..=5)
+        .map(|i| FakeDisplay::new(i.to_string(), format!("FAKE {i}")))
+        .collect();
+    let (manager, states) = manager(fakes);
+    for (i, state) in states.iter().enumerate() {
+        state.set_value(BRIGHTNESS, 10 + i as u16);
+    }
+    states[4].set_unreachable(true);
+    let mut done = 0;
+    let rereads = bulk::reread(&manager.displays, 2, |_| done += 1);
+    assert_eq!(done, 5);
+    for (i, display) in manager.displays.iter().enumerate().take(4) {
+        let controller = display.controls[&Control::Brightness].read().unwrap();
+        assert_eq!(controller.value, 10 + i as u16);
+    }
+    let failed = rereads.iter().find(|r| r.display.id == "5").unwrap();
+    assert_eq!(failed.read, 0);
+    assert!(!failed.failed.is_empty());
+}
+
+#[test]
+fn bulk_writes_are_checked_and_written_again() {
+    let (manager, states) = manager(vec![
+        FakeDisplay::new("1", "FAKE 1"),
+        FakeDisplay::new("2", "FAKE 2"),
+    ]);
+    states[0].drop_writes(1);
+    states[1].set_unreachable(true);
+    let values: Vec<_> = (manager.displays.iter())
+        .map(|display| {
+            let values = vec![(Control::Brightness, 30), (Control::Contrast, 60)];
+            (display.clone(), values)
+        })
+        .collect();
+    let mut done = 0;
+    let applied = manager.apply_bulk(&values, |_| done += 1);
+    assert_eq!(done, 2);
+
+    // The dropped write is noticed reading back and taken the second time
+    let first = applied.iter().find(|a| a.display.id == "1").unwrap();
+    assert!(first.failed.is_empty());
+    assert_eq!(states[0].value(BRIGHTNESS), Some(30));
+    assert_eq!(states[0].value(Control::Contrast as u8), Some(60));
+    assert_eq!(
+        control(&manager.displays[0], Control::Brightness)
+            .read()
+            .unwrap()
+            .value,
+        30
+    );
+    let second = applied.iter().find(|a| a.display.id == "2").unwrap();
+    assert!(second.written.is_empty());
+    assert_eq!(second.failed.len(), 2);
+}
+
+#[test]
+fn value_names_resolve_by_display() {
+    let mut manager = DisplayManager::new();
+    manager.fakes = vec![
+        FakeDisplay::new("1", "FAKE 1"),
+        FakeDisplay::new("2", "FAKE 2"),
+    ];
+    manager.value_names = vec![
+        ValueNames {
+            inputs: [("desktop".to_string(), 0x0F), ("laptop".to_string(), 0x11)].into(),
+            ..ValueNames::default()
+        },
+        ValueNames {
+            display: Some("FAKE 2".to_string()),
+            inputs: [("desktop".to_string(), 0x1B)].into(),
+            ..ValueNames::default()
+        },
+    ];
+    manager.refresh().unwrap();
+    let (first, second) = (&manager.displays[0], &manager.displays[1]);
+    assert_eq!(
+        first.value_named(Control::InputSource, "Desktop"),
+        Some(0x0F)
+    );
+    assert_eq!(first.describe(Control::InputSource, 0x11), "laptop");
+    assert_eq!(first.value_named(Control::InputSource, "hdmi1"), Some(0x11));
+    assert_eq!(
+        second.value_named(Control::InputSource, "desktop"),
+        Some(0x1B)
+    );
+    assert_eq!(
+        second.value_named(Control::InputSource, "laptop"),
+        Some(0x11)
+    );
+    assert!(second
+        .allowed_values(Control::InputSource)
+        .contains(&(0x1B, Some("desktop".to_string()))));
+}
+
+#[test]
+fn locked_writes_are_refused_or_kept_in_range() {
+    let mut manager = DisplayManager::new();
+    manager.fakes = vec![FakeDisplay::new("1", "FAKE")];
+    manager.lock = Some(Lock {
+        allow: vec![Control::Brightness],
+        range: [(Control::Brightness, [20, 80])].into(),
+    });
+    manager.refresh().unwrap();
+    let display = manager.displays[0].clone();
+    let brightness = control(&display, Control::Brightness);
+    manager.queue_set(display.clone(), brightness.clone(), 95);
+    assert_eq!(brightness.read().unwrap().value, 80);
+    assert!(display.set(Control::Brightness, 50).is_ok());
+    let refused = display.set(Control::Brightness, 10).unwrap_err();
+    assert_eq!(refused.to_string(), "brightness is locked to 20% - 80%");
+    assert!(display.set(Control::Contrast, 50).is_err());
+    let contrast = control(&display, Control::Contrast);
+    let before = contrast.read().unwrap().value;
+    manager.queue_set(display.clone(), contrast.clone(), before + 10);
+    assert_eq!(contrast.read().unwrap().value, before);
+}
+
+#[test]
+fn health_decodes_the_codes_a_display_has() {
+    let (manager, _) = manager(vec![FakeDisplay::new("1", "FAKE")
+        .with_vcp(0xC0, 1234, 0xFFFF)
+        .with_vcp(0xC9, 0x0102, 0xFFFF)
+        .with_vcp(0xC8, 0x1205, 0xFFFF)
+        .with_vcp(0xAE, 6000, 0xFFFF)]);
+    let display = &manager.displays[0];
+    let readings = health::read(display);
+    let value = |code: u8| {
+        let reading = readings
+            .iter()
+            .find(|reading| reading.code == code)
+            .unwrap();
+        reading.value.as_ref().ok().cloned()
+    };
+    assert_eq!(value(0xC0).as_deref(), Some("1234 h"));
+    assert_eq!(value(0xC9).as_deref(), Some("1.2"));
+    assert_eq!(value(0xC8).as_deref(), Some("Mstar, controller 0x12"));
+    assert_eq!(value(0xAE).as_deref(), Some("60.00 Hz"));
+    assert_eq!(value(0xB6), None);
+    assert!(!display.is_unreachable());
+}
+
+#[test]
+fn probed_controls_are_kept_though_not_advertised() {
+    let mut fake = FakeDisplay::new("1", "FAKE").with_vcp(0x87, 5, 10);
+    fake.capabilities = Some("(type(LCD)vcp(10 12))".to_string());
+    let mut manager = DisplayManager::new();
+    manager.fakes = vec![fake.clone()];
+    manager.refresh().unwrap();
+    assert!(!manager.displays[0]
+        .controls
+        .contains_key(&Control::Sharpness));
+
+    let stable_id = manager.displays[0].stable_id();
+    manager
+        .unadvertised
+        .insert(stable_id, vec![Control::Sharpness]);
+    manager.refresh().unwrap();
+    let display = &manager.displays[0];
+    assert!(display.controls.contains_key(&Control::Sharpness));
+    assert!(!display.controls.contains_key(&Control::Volume));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn worker_priority_changes_only_the_change_queue() {
+    use ddc_bright::priority::Priority;
+
+    // The affinity of this process, which containers may keep off CPU 0
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    let allowed = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+        .unwrap();
+    let cpu = allowed
+        .trim()
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let manager = DisplayManager::new();
+    let priority = Priority {
+        nice: Some(7),
+        cpus: vec![cpu],
+    };
+    manager.set_worker_priority(&priority).unwrap();
+    let niced: Vec<String> = std::fs::read_dir("/proc/self/task")
+        .unwrap()
+        .map(|task| task.unwrap().path())
+        .filter(|task| {
+            let stat = std::fs::read_to_string(task.join("stat")).unwrap_or_default();
+            // Fields after the name, which is in parentheses, start with the state
+            let fields: Vec<&str> = stat
+                .rsplit(')')
+                .next()
+                .unwrap()
+                .split_whitespace()
+                .collect();
+            fields.get(16) == Some(&"7")
+        })
+        .map(|task| std::fs::read_to_string(task.join("comm")).unwrap())
+        .collect();
+    assert_eq!(niced, ["change queue\n"]);
+
+    let missing = Priority {
+        nice: None,
+        cpus: vec![100_000],
+    };
+    assert!(manager.set_worker_priority(&missing).is_err());
+}
+
+#[cfg(all(unix, feature = "helper"))]
+#[test]
+fn helper_refuses_resets_and_power() {
+    use ddc_bright::{display::POWER_MODE, display::RESET_ALL, helper::writable};
+
+    assert!(writable(BRIGHTNESS));
+    assert!(writable(0x9B));
+    assert!(!writable(RESET_ALL));
+    assert!(!writable(POWER_MODE));
+}
+
+#[test]
+fn transactions_restore_the_values_read_from_the_display() {
+    let (manager, states) = manager(vec![FakeDisplay::new("1", "FAKE")]);
+    // Changed at the display's buttons, the cached value is out of date
+    states[0].set_value(BRIGHTNESS, 20);
+    let committed = (manager.transaction("1").unwrap())
+        .set(Control::Brightness, 80)
+        .commit()
+        .unwrap();
+    assert_eq!(committed.previous(), [(Control::Brightness, 20)]);
+    assert!(committed.rollback());
+    assert_eq!(states[0].value(BRIGHTNESS), Some(20));
+}
+
+#[test]
+fn raw_writes_leave_the_control_masked() {
+    let (manager, _) = manager(vec![FakeDisplay::new("1", "FAKE")]);
+    let display = &manager.displays[0];
+    // Some displays answer with the high byte set, which isn't part of the input
+    let (current, _) = display.set_raw(0x60, 0x0211).unwrap();
+    assert_eq!(current, 0x0211);
+    assert_eq!(
+        control(display, Control::InputSource).read().unwrap().value,
+        0x11
+    );
+}